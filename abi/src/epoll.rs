@@ -0,0 +1,45 @@
+//! The event queue of Linux, epoll.
+
+use bitflags::bitflags;
+
+/// The operations of epoll_ctl.
+pub const EPOLL_CTL_ADD: usize = 1;
+pub const EPOLL_CTL_DEL: usize = 2;
+pub const EPOLL_CTL_MOD: usize = 3;
+
+bitflags! {
+    /// The events of `EpollEvent`, those of poll and how they are reported
+    pub struct EpollFlags: u32 {
+        const EPOLLIN = 0x001;
+        const EPOLLOUT = 0x004;
+        const EPOLLERR = 0x008;
+        const EPOLLHUP = 0x010;
+        /// reported once, then disabled until `EPOLL_CTL_MOD`
+        const EPOLLONESHOT = 1 << 30;
+        /// edge triggered, reported only when the file may have changed
+        const EPOLLET = 1 << 31;
+    }
+}
+
+/// Same layout as the epoll_event of Linux on RISC-V, which is not packed
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct EpollEvent {
+    pub events: u32,
+    __pad: u32,
+    /// given back with the events, usually the fd
+    pub data: u64,
+}
+
+impl EpollEvent {
+    pub fn new(events: EpollFlags, data: u64) -> Self {
+        Self {
+            events: events.bits(),
+            __pad: 0,
+            data,
+        }
+    }
+    pub fn flags(&self) -> EpollFlags {
+        EpollFlags::from_bits_truncate(self.events)
+    }
+}
@@ -0,0 +1,202 @@
+/// Why a syscall of the native ABI failed, returned as the negative code of
+/// the variant. The codes are the negated errnos of Linux, but for EPERM,
+/// ENOENT and ESRCH, whose codes -1, -2 and -3 the ABI of rCore gave to
+/// `Failed`, `NotReady` and `OwnerDead` first, so theirs are offset by
+/// 0x100.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(isize)]
+pub enum SysError {
+    /// any other failure, one the variants below do not tell
+    Failed = -1,
+    /// not done without waiting longer: the timeout passed first, a
+    /// non-blocking call would wait, or the child waited for is running
+    NotReady = -2,
+    /// a mutex is locked, but its previous owner exited without unlocking it
+    OwnerDead = -3,
+    /// a signal with a handler came first, EINTR of Linux
+    Interrupted = -4,
+    /// the file is not a program the kernel can load, ENOEXEC of Linux
+    NotExecutable = -8,
+    /// the fd is not open, or not for reading or writing as asked, EBADF of
+    /// Linux
+    BadFd = -9,
+    /// the process has no child to wait for, ECHILD of Linux
+    NoChild = -10,
+    /// it would wait, or the word of a futex is not the value expected,
+    /// EAGAIN of Linux
+    WouldBlock = -11,
+    /// no memory or frame is left for it, ENOMEM of Linux
+    NoMemory = -12,
+    /// the mode of the file or the mount refuses it, EACCES of Linux
+    Denied = -13,
+    /// a pointer given is not to the user memory it has to read or write,
+    /// EFAULT of Linux
+    Fault = -14,
+    /// the file, mount or lock is in use, EBUSY of Linux
+    Busy = -16,
+    /// the file to create is there already, EEXIST of Linux
+    Exists = -17,
+    /// there is no device, or no file system, of the name, ENODEV of Linux
+    NoDevice = -19,
+    /// a component of the path is no directory, ENOTDIR of Linux
+    NotDir = -20,
+    /// the file is a directory, EISDIR of Linux
+    IsDir = -21,
+    /// an argument, flag or command is not valid, EINVAL of Linux
+    Invalid = -22,
+    /// the fd table of the process is full, EMFILE of Linux
+    TooManyFiles = -24,
+    /// the file does not take the ioctl, ENOTTY of Linux
+    NotTty = -25,
+    /// the device is full, ENOSPC of Linux
+    NoSpace = -28,
+    /// the file system is mounted read-only, EROFS of Linux
+    ReadOnly = -30,
+    /// no reader of the pipe written is left, EPIPE of Linux
+    BrokenPipe = -32,
+    /// what is asked for does not fit in the buffer given, ERANGE of Linux
+    Range = -34,
+    /// the syscall is not implemented, ENOSYS of Linux
+    NoSys = -38,
+    /// the directory to remove is not empty, ENOTEMPTY of Linux
+    NotEmpty = -39,
+    /// only root or the owner may do it, EPERM of Linux
+    NotPermitted = -0x101,
+    /// the file or directory is not there, ENOENT of Linux
+    NoEntry = -0x102,
+    /// there is no process or thread of the id, ESRCH of Linux
+    NoProcess = -0x103,
+    /// interrupted before doing anything, the kernel restarts it or returns
+    /// `Interrupted` instead, so it is never returned to the user,
+    /// ERESTARTSYS of Linux
+    Restart = -512,
+    /// waiting would deadlock, same as the value in rCore labs
+    Deadlock = -0xdead,
+}
+
+/// The result of a syscall, the value returned if it is not negative.
+pub type SysResult<T = usize> = Result<T, SysError>;
+
+impl SysError {
+    /// What the syscall returns for it.
+    pub const fn code(self) -> isize {
+        self as isize
+    }
+
+    /// The error of a negative return value, `Failed` for the codes unknown.
+    /// None if it is no error.
+    pub fn from_code(code: isize) -> Option<Self> {
+        Some(match code {
+            0.. => return None,
+            -2 => Self::NotReady,
+            -3 => Self::OwnerDead,
+            -4 => Self::Interrupted,
+            -8 => Self::NotExecutable,
+            -9 => Self::BadFd,
+            -10 => Self::NoChild,
+            -11 => Self::WouldBlock,
+            -12 => Self::NoMemory,
+            -13 => Self::Denied,
+            -14 => Self::Fault,
+            -16 => Self::Busy,
+            -17 => Self::Exists,
+            -19 => Self::NoDevice,
+            -20 => Self::NotDir,
+            -21 => Self::IsDir,
+            -22 => Self::Invalid,
+            -24 => Self::TooManyFiles,
+            -25 => Self::NotTty,
+            -28 => Self::NoSpace,
+            -30 => Self::ReadOnly,
+            -32 => Self::BrokenPipe,
+            -34 => Self::Range,
+            -38 => Self::NoSys,
+            -39 => Self::NotEmpty,
+            -0x101 => Self::NotPermitted,
+            -0x102 => Self::NoEntry,
+            -0x103 => Self::NoProcess,
+            -512 => Self::Restart,
+            -0xdead => Self::Deadlock,
+            _ => Self::Failed,
+        })
+    }
+
+    /// The result of a syscall returning `ret`.
+    pub fn check(ret: isize) -> SysResult {
+        match Self::from_code(ret) {
+            Some(error) => Err(error),
+            None => Ok(ret as usize),
+        }
+    }
+
+    /// What a syscall returns for `result`, the other way of `check`.
+    pub fn encode(result: SysResult) -> isize {
+        match result {
+            Ok(value) => value as isize,
+            Err(error) => error.code(),
+        }
+    }
+
+    /// The errno of Linux for it. `Failed` is EINVAL and `NotReady`, a
+    /// timeout mostly, ETIMEDOUT; the callers knowing better map them first.
+    pub const fn linux_errno(self) -> isize {
+        match self {
+            Self::Failed => 22,
+            Self::NotReady => 110,
+            Self::OwnerDead => 130,
+            Self::Deadlock => 35,
+            Self::NotPermitted | Self::NoEntry | Self::NoProcess => -self.code() - 0x100,
+            _ => -self.code(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_round_trip() {
+        for error in [
+            SysError::Failed,
+            SysError::NotReady,
+            SysError::OwnerDead,
+            SysError::Interrupted,
+            SysError::NotExecutable,
+            SysError::BadFd,
+            SysError::NoChild,
+            SysError::WouldBlock,
+            SysError::NoMemory,
+            SysError::Denied,
+            SysError::Fault,
+            SysError::Busy,
+            SysError::Exists,
+            SysError::NoDevice,
+            SysError::NotDir,
+            SysError::IsDir,
+            SysError::Invalid,
+            SysError::TooManyFiles,
+            SysError::NotTty,
+            SysError::NoSpace,
+            SysError::ReadOnly,
+            SysError::BrokenPipe,
+            SysError::Range,
+            SysError::NoSys,
+            SysError::NotEmpty,
+            SysError::NotPermitted,
+            SysError::NoEntry,
+            SysError::NoProcess,
+            SysError::Restart,
+            SysError::Deadlock,
+        ] {
+            assert_eq!(SysError::from_code(error.code()), Some(error));
+            assert_eq!(SysError::encode(Err(error)), error.code());
+            assert!(error.linux_errno() > 0);
+        }
+        assert_eq!(SysError::NoEntry.linux_errno(), 2);
+        assert_eq!(SysError::BadFd.linux_errno(), 9);
+        assert_eq!(SysError::check(3), Ok(3));
+        assert_eq!(SysError::check(-5), Err(SysError::Failed));
+        assert_eq!(SysError::from_code(0), None);
+    }
+}
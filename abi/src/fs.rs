@@ -0,0 +1,71 @@
+//! The layouts of what the file syscalls of Linux take and fill in.
+
+/// The bytes of `linux_dirent64` before the name: the inode number, the
+/// offset of the next entry, the length of the entry and the type.
+pub const DIRENT64_HEAD: usize = 19;
+/// The types of the entries of `linux_dirent64`.
+pub const DT_DIR: u8 = 4;
+pub const DT_REG: u8 = 8;
+
+/// The stat of the generic syscall ABI, used by RISC-V
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Stat {
+    pub dev: u64,
+    pub ino: u64,
+    pub mode: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub rdev: u64,
+    pub __pad1: u64,
+    pub size: i64,
+    pub blksize: i32,
+    pub __pad2: i32,
+    pub blocks: i64,
+    pub atime: [i64; 2],
+    pub mtime: [i64; 2],
+    pub ctime: [i64; 2],
+    pub __unused: [u32; 2],
+}
+
+/// The fds an fd_set holds at most.
+pub const FD_SETSIZE: usize = 1024;
+
+/// The fd_set of Linux for pselect, a bit for each fd.
+#[repr(C)]
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct FdSet {
+    bits: [u64; FD_SETSIZE / 64],
+}
+
+impl FdSet {
+    pub fn insert(&mut self, fd: usize) {
+        self.bits[fd / 64] |= 1 << (fd % 64);
+    }
+    pub fn remove(&mut self, fd: usize) {
+        self.bits[fd / 64] &= !(1 << (fd % 64));
+    }
+    /// False for the fds beyond `FD_SETSIZE` too.
+    pub fn contains(&self, fd: usize) -> bool {
+        fd < FD_SETSIZE && self.bits[fd / 64] & (1 << (fd % 64)) != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fd_set_bits() {
+        let mut set = FdSet::default();
+        set.insert(0);
+        set.insert(65);
+        set.insert(FD_SETSIZE - 1);
+        assert!(set.contains(0) && set.contains(65) && set.contains(FD_SETSIZE - 1));
+        assert!(!set.contains(1) && !set.contains(64) && !set.contains(FD_SETSIZE));
+        set.remove(65);
+        assert!(!set.contains(65));
+        assert_eq!(core::mem::size_of::<FdSet>(), 128);
+    }
+}
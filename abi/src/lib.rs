@@ -0,0 +1,19 @@
+//! What the kernel and the user programs agree on, shared so that the two
+//! sides cannot drift apart: the errors of the syscalls and the layouts of
+//! what they take and fill in.
+
+#![no_std]
+
+mod epoll;
+mod error;
+mod fs;
+mod signal;
+mod termios;
+mod time;
+
+pub use epoll::*;
+pub use error::{SysError, SysResult};
+pub use fs::*;
+pub use signal::*;
+pub use termios::*;
+pub use time::*;
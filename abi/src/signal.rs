@@ -0,0 +1,156 @@
+//! The signals, numbered as on Linux, and what a process does on them.
+
+use bitflags::bitflags;
+
+pub const MAX_SIG: usize = 31;
+/// The handlers of a `SignalAction` which are not functions.
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+bitflags! {
+    /// A set of signals, the one numbered `n` as in Linux is the bit `1 << n`.
+    pub struct SignalFlags: u32 {
+        const SIGHUP    = 1 << 1;
+        const SIGINT    = 1 << 2;
+        const SIGQUIT   = 1 << 3;
+        const SIGILL    = 1 << 4;
+        const SIGTRAP   = 1 << 5;
+        const SIGABRT   = 1 << 6;
+        const SIGBUS    = 1 << 7;
+        const SIGFPE    = 1 << 8;
+        const SIGKILL   = 1 << 9;
+        const SIGUSR1   = 1 << 10;
+        const SIGSEGV   = 1 << 11;
+        const SIGUSR2   = 1 << 12;
+        const SIGPIPE   = 1 << 13;
+        const SIGALRM   = 1 << 14;
+        const SIGTERM   = 1 << 15;
+        const SIGSTKFLT = 1 << 16;
+        const SIGCHLD   = 1 << 17;
+        const SIGCONT   = 1 << 18;
+        const SIGSTOP   = 1 << 19;
+        const SIGTSTP   = 1 << 20;
+        const SIGTTIN   = 1 << 21;
+        const SIGTTOU   = 1 << 22;
+        const SIGURG    = 1 << 23;
+        const SIGXCPU   = 1 << 24;
+        const SIGXFSZ   = 1 << 25;
+        const SIGVTALRM = 1 << 26;
+        const SIGPROF   = 1 << 27;
+        const SIGWINCH  = 1 << 28;
+        const SIGIO     = 1 << 29;
+        const SIGPWR    = 1 << 30;
+        const SIGSYS    = 1 << 31;
+    }
+}
+
+impl SignalFlags {
+    /// The signal numbered `signum`, None unless it is 1 to `MAX_SIG`.
+    pub fn from_signum(signum: usize) -> Option<Self> {
+        match signum {
+            1..=MAX_SIG => Self::from_bits(1 << signum),
+            _ => None,
+        }
+    }
+
+    /// The lowest number of the signals in the set.
+    pub fn first(&self) -> Option<usize> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.bits.trailing_zeros() as usize)
+        }
+    }
+
+    /// The signals which can be neither caught, ignored nor blocked.
+    pub fn uncatchable() -> Self {
+        Self::SIGKILL | Self::SIGSTOP
+    }
+
+    /// Whether the default action of all the signals in the set is to
+    /// terminate the process.
+    ///
+    /// NOTICE: stopping is not supported, the signals stopping or continuing
+    /// a process are ignored like SIGCHLD.
+    pub fn terminates(&self) -> bool {
+        let ignored = Self::SIGCHLD
+            | Self::SIGCONT
+            | Self::SIGSTOP
+            | Self::SIGTSTP
+            | Self::SIGTTIN
+            | Self::SIGTTOU
+            | Self::SIGURG
+            | Self::SIGWINCH;
+        !self.is_empty() && !self.intersects(ignored)
+    }
+
+    /// The exit code and the description of a process terminated by the
+    /// lowest signal in the set.
+    pub fn check_error(&self) -> Option<(i32, &'static str)> {
+        let signum = self.first()?;
+        let description = match signum {
+            1 => "Hangup, SIGHUP=1",
+            2 => "Killed, SIGINT=2",
+            3 => "Quit, SIGQUIT=3",
+            4 => "Illegal Instruction, SIGILL=4",
+            5 => "Trace/Breakpoint Trap, SIGTRAP=5",
+            6 => "Aborted, SIGABRT=6",
+            7 => "Bus Error, SIGBUS=7",
+            8 => "Erroneous Arithmetic Operation, SIGFPE=8",
+            9 => "Killed, SIGKILL=9",
+            11 => "Segmentation Fault, SIGSEGV=11",
+            13 => "Broken Pipe, SIGPIPE=13",
+            14 => "Alarm Clock, SIGALRM=14",
+            15 => "Terminated, SIGTERM=15",
+            _ => "Terminated by a signal",
+        };
+        Some((-(signum as i32), description))
+    }
+}
+
+bitflags! {
+    /// The flags of a `SignalAction`, the others are ignored
+    pub struct SignalActionFlags: u32 {
+        /// a syscall interrupted before it has done anything is restarted
+        /// once the handler returns, instead of returning EINTR
+        const SA_RESTART = 0x1000_0000;
+    }
+}
+
+/// What a process does on a signal. A handler is called with the number of
+/// the signal and the signals of `mask` and the signal itself blocked, and
+/// returns to `restorer`, which calls sigreturn so that the thread goes on
+/// where it was.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SignalAction {
+    /// `SIG_DFL`, `SIG_IGN` or an `extern "C" fn(signum: i32)`
+    pub handler: usize,
+    /// blocked while the handler runs, as well as the signal itself
+    pub mask: SignalFlags,
+    pub flags: SignalActionFlags,
+    /// set by `sigaction` of the user library
+    pub restorer: usize,
+}
+
+impl SignalAction {
+    pub fn new(handler: extern "C" fn(i32), mask: SignalFlags) -> Self {
+        Self {
+            handler: handler as usize,
+            mask,
+            flags: SignalActionFlags::empty(),
+            restorer: 0,
+        }
+    }
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: SIG_DFL,
+            mask: SignalFlags::empty(),
+            flags: SignalActionFlags::empty(),
+            restorer: 0,
+        }
+    }
+}
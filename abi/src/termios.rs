@@ -0,0 +1,60 @@
+//! The terminal interface of Linux, taken by ioctl on the console.
+
+use bitflags::bitflags;
+
+pub const TCGETS: usize = 0x5401;
+pub const TCSETS: usize = 0x5402;
+pub const TIOCGPGRP: usize = 0x540f;
+pub const TIOCSPGRP: usize = 0x5410;
+pub const TIOCGWINSZ: usize = 0x5413;
+
+bitflags! {
+    /// Input modes, the others are ignored
+    pub struct InputFlags: u32 {
+        /// a carriage return is received as a newline
+        const ICRNL = 0o400;
+    }
+}
+
+bitflags! {
+    /// Local modes, the others are ignored
+    pub struct LocalFlags: u32 {
+        /// the interrupt and suspend characters send SIGINT and SIGTSTP
+        const ISIG = 0o1;
+        const ICANON = 0o2;
+        const ECHO = 0o10;
+        /// the erase character erases the last one on the screen
+        const ECHOE = 0o20;
+    }
+}
+
+/// The indices of the special characters in `Termios::cc`.
+pub const VINTR: usize = 0;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VEOF: usize = 4;
+pub const VTIME: usize = 5;
+pub const VMIN: usize = 6;
+pub const VSUSP: usize = 10;
+
+/// Same layout as the termios of Linux
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Termios {
+    pub iflag: u32,
+    pub oflag: u32,
+    pub cflag: u32,
+    pub lflag: u32,
+    pub line: u8,
+    pub cc: [u8; 19],
+}
+
+/// Same layout as the winsize of Linux
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Winsize {
+    pub row: u16,
+    pub col: u16,
+    pub xpixel: u16,
+    pub ypixel: u16,
+}
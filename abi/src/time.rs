@@ -0,0 +1,29 @@
+//! The clocks and the times passed to and from the syscalls.
+
+/// the wall clock time, from boot on a board without a real time clock
+pub const CLOCK_REALTIME: usize = 0;
+/// the time since boot, which never goes back
+pub const CLOCK_MONOTONIC: usize = 1;
+
+const NSEC_PER_SEC: usize = 1_000_000_000;
+
+/// Same layout as the timespec of Linux
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TimeSpec {
+    pub sec: usize,
+    pub nsec: usize,
+}
+
+impl TimeSpec {
+    pub fn from_ns(ns: usize) -> Self {
+        Self {
+            sec: ns / NSEC_PER_SEC,
+            nsec: ns % NSEC_PER_SEC,
+        }
+    }
+
+    pub fn as_ns(&self) -> usize {
+        self.sec * NSEC_PER_SEC + self.nsec
+    }
+}
@@ -6,6 +6,10 @@ use std::sync::Arc;
 use std::sync::Mutex;
 
 const BLOCK_SZ: usize = 512;
+/// 32MiB, at most 4095 files
+const FS_BLOCKS: usize = 32 * 2048;
+/// Reserved after the file system for the kernel to save a crash dump.
+const CRASH_DUMP_BLOCKS: usize = 256;
 
 struct BlockFile(Mutex<File>);
 
@@ -24,6 +28,11 @@ impl BlockDevice for BlockFile {
         assert_eq!(file.write(buf).unwrap(), BLOCK_SZ, "Not a complete block!");
     }
 
+    fn num_blocks(&self) -> usize {
+        let file = self.0.lock().unwrap();
+        file.metadata().unwrap().len() as usize / BLOCK_SZ
+    }
+
     fn handle_irq(&self) {
         unimplemented!();
     }
@@ -59,15 +68,14 @@ fn easy_fs_pack() -> std::io::Result<()> {
             .write(true)
             .create(true)
             .open(format!("{}{}", target_path, "fs.img"))?;
-        f.set_len(32 * 2048 * 512).unwrap();
+        f.set_len(((FS_BLOCKS + CRASH_DUMP_BLOCKS) * BLOCK_SZ) as u64)
+            .unwrap();
         f
     })));
-    // 32MiB, at most 4095 files
-    let efs = EasyFileSystem::create(block_file, 32 * 2048, 1);
+    let efs = EasyFileSystem::create(block_file, FS_BLOCKS as u32, 1);
     let root_inode = Arc::new(EasyFileSystem::root_inode(&efs));
     let apps: Vec<_> = read_dir(src_path)
         .unwrap()
-        .into_iter()
         .map(|dir_entry| {
             let mut name_with_ext = dir_entry.unwrap().file_name().into_string().unwrap();
             name_with_ext.drain(name_with_ext.find('.').unwrap()..name_with_ext.len());
@@ -125,7 +133,7 @@ fn efs_test() -> std::io::Result<()> {
         use rand;
         // random digit
         for _ in 0..len {
-            str.push(char::from('0' as u8 + rand::random::<u8>() % 10));
+            str.push(char::from(b'0' + rand::random::<u8>() % 10));
         }
         filea.write_at(0, str.as_bytes());
         let mut read_buffer = [0u8; 127];
@@ -151,5 +159,360 @@ fn efs_test() -> std::io::Result<()> {
     random_str_test(1000 * BLOCK_SZ);
     random_str_test(2000 * BLOCK_SZ);
 
+    let truncate_test = |len: usize, new_len: usize| {
+        filea.clear();
+        let data: Vec<u8> = (0..len).map(|_| b'a' + rand::random::<u8>() % 26).collect();
+        filea.write_at(0, &data);
+        filea.truncate(new_len);
+        assert_eq!(filea.size(), new_len);
+        let mut read_data = vec![0u8; new_len];
+        assert_eq!(filea.read_at(0, &mut read_data), new_len);
+        // bytes past the old end must read as zeros
+        let kept = new_len.min(len);
+        assert_eq!(&read_data[..kept], &data[..kept]);
+        assert!(read_data[kept..].iter().all(|&b| b == 0));
+        // a shrunk tail must not come back after growing again
+        filea.truncate(len);
+        let mut read_data = vec![0u8; len];
+        assert_eq!(filea.read_at(0, &mut read_data), len);
+        assert_eq!(&read_data[..kept], &data[..kept]);
+        assert!(read_data[kept..].iter().all(|&b| b == 0));
+    };
+
+    truncate_test(4 * BLOCK_SZ, 4 * BLOCK_SZ + 100);
+    truncate_test(8 * BLOCK_SZ + BLOCK_SZ / 2, BLOCK_SZ / 3);
+    truncate_test(100 * BLOCK_SZ, 20 * BLOCK_SZ + 7);
+    truncate_test((12 + 128) * BLOCK_SZ, 28 * BLOCK_SZ);
+    truncate_test(2000 * BLOCK_SZ, 300 * BLOCK_SZ + 1);
+    truncate_test(2000 * BLOCK_SZ, 0);
+
+    // files growing at once are interleaved into many extents, more than
+    // the inode holds
+    let frag1 = root_inode.create("frag1").unwrap();
+    let frag2 = root_inode.create("frag2").unwrap();
+    let block = |i: usize| [b'a' + (i % 26) as u8; BLOCK_SZ];
+    for i in 0..100 {
+        frag1.write_at(i * BLOCK_SZ, &block(i));
+        frag2.write_at(i * BLOCK_SZ, &block(i + 1));
+    }
+    let mut read_block = [0u8; BLOCK_SZ];
+    for i in 0..100 {
+        assert_eq!(frag1.read_at(i * BLOCK_SZ, &mut read_block), BLOCK_SZ);
+        assert_eq!(read_block, block(i));
+        assert_eq!(frag2.read_at(i * BLOCK_SZ, &mut read_block), BLOCK_SZ);
+        assert_eq!(read_block, block(i + 1));
+    }
+    frag2.truncate(30 * BLOCK_SZ);
+    assert_eq!(frag2.read_at(29 * BLOCK_SZ, &mut read_block), BLOCK_SZ);
+    assert_eq!(read_block, block(30));
+    frag2.clear();
+
+    // direct I/O bypasses the cache, but sees what is changed in it
+    let data: Vec<u8> = (0..3 * BLOCK_SZ).map(|i| (i % 251) as u8).collect();
+    let (head, tail) = data.split_at(BLOCK_SZ);
+    assert_eq!(frag2.write_direct(BLOCK_SZ, &[head, tail]), 3 * BLOCK_SZ);
+    let mut read_data = vec![0u8; 4 * BLOCK_SZ];
+    assert_eq!(frag2.read_at(0, &mut read_data), 4 * BLOCK_SZ);
+    assert!(read_data[..BLOCK_SZ].iter().all(|&b| b == 0));
+    assert_eq!(&read_data[BLOCK_SZ..], &data[..]);
+    frag2.write_at(2 * BLOCK_SZ, b"cached");
+    frag2.truncate(3 * BLOCK_SZ + 100);
+    let mut read_data = vec![0u8; 5 * BLOCK_SZ];
+    let (head, tail) = read_data.split_at_mut(BLOCK_SZ);
+    assert_eq!(
+        frag2.read_direct(2 * BLOCK_SZ, &mut [head, tail]),
+        BLOCK_SZ + 100
+    );
+    assert_eq!(&read_data[..6], b"cached");
+    assert_eq!(
+        &read_data[6..BLOCK_SZ + 100],
+        &data[BLOCK_SZ + 6..2 * BLOCK_SZ + 100]
+    );
+    assert!(read_data[BLOCK_SZ + 100..].iter().all(|&b| b == 0));
+    frag2.clear();
+
+    // the inode in use is found again, under its new name once renamed
+    assert!(Arc::ptr_eq(&root_inode.find("filea").unwrap(), &filea));
+    assert!(root_inode.rename("filea", "filec"));
+    assert!(root_inode.find("filea").is_none());
+    assert!(Arc::ptr_eq(&root_inode.find("filec").unwrap(), &filea));
+    assert!(!root_inode.rename("filec", "fileb"));
+    assert!(!root_inode.rename("filea", "filed"));
+
+    let dir = root_inode.create_dir("dir").unwrap();
+    assert!(dir.is_dir());
+    assert!(!root_inode.find("filec").unwrap().is_dir());
+    assert!(root_inode.create_dir("dir").is_none());
+    let filed = dir.create("filed").unwrap();
+    filed.write_at(0, greet_str.as_bytes());
+    assert_eq!(dir.ls(), vec!["filed"]);
+    assert!(root_inode.find("filed").is_none());
+    let len = dir.find("filed").unwrap().read_at(0, &mut buffer);
+    assert_eq!(&buffer[..len], greet_str.as_bytes());
+
+    // reads update the access time unless mounted read-only or noatime
+    efs.lock().clock = || 42;
+    filed.read_at(0, &mut buffer);
+    assert_eq!(filed.times().0, 42);
+    efs.lock().clock = || 43;
+    efs.lock().remount(easy_fs::MountFlags::RDONLY);
+    filed.read_at(0, &mut buffer);
+    assert_eq!(filed.times(), (42, 0));
+    assert_eq!(filed.write_at(0, b"x"), 0);
+    assert!(dir.create("filee").is_none());
+    assert!(!dir.rename("filed", "filee"));
+    filed.clear();
+    assert_eq!(filed.size(), greet_str.len());
+    efs.lock().remount(easy_fs::MountFlags::NOATIME);
+    filed.read_at(0, &mut buffer);
+    assert_eq!(filed.times().0, 42);
+    assert_eq!(filed.write_at(0, b"h"), 1);
+    assert_eq!(filed.times(), (42, 43));
+
+    // a user is limited to what it owns
+    efs.lock().current_uid = || 7;
+    efs.lock().set_quota_limits(7, 4, 2);
+    let q1 = root_inode.create("q1").unwrap();
+    assert_eq!(q1.write_at(0, &[1u8; 4 * BLOCK_SZ]), 4 * BLOCK_SZ);
+    assert_eq!(q1.write_at(4 * BLOCK_SZ, b"x"), 0);
+    assert!(!q1.truncate(5 * BLOCK_SZ));
+    assert_eq!(q1.size(), 4 * BLOCK_SZ);
+    assert!(root_inode.create("q2").is_some());
+    assert!(root_inode.create("q3").is_none());
+    let quota = easy_fs::Quota {
+        blocks: 4,
+        inodes: 2,
+        block_limit: 4,
+        inode_limit: 2,
+    };
+    assert_eq!(efs.lock().quota(7), quota);
+    assert!(q1.truncate(BLOCK_SZ));
+    assert_eq!(q1.write_at(BLOCK_SZ, &[1u8; 3 * BLOCK_SZ]), 3 * BLOCK_SZ);
+    q1.clear();
+    efs.lock().current_uid = || 0;
+    // the usage is counted again when opened, but not the limits
+    let quota = EasyFileSystem::open(block_file.clone()).lock().quota(7);
+    assert_eq!((quota.blocks, quota.inodes, quota.block_limit), (0, 2, 0));
+
+    // removed with their blocks unless in use, or a directory not empty
+    let usage = efs.lock().quota(0);
+    let gone = root_inode.create("gone").unwrap();
+    gone.write_at(0, &[7u8; 20 * BLOCK_SZ]);
+    assert!(!root_inode.unlink("gone", false));
+    drop(gone);
+    assert!(root_inode.create_dir("empty").is_some());
+    assert!(!root_inode.unlink("gone", true));
+    assert!(!root_inode.unlink("empty", false));
+    assert!(!root_inode.unlink("dir", true));
+    assert!(root_inode.unlink("gone", false));
+    assert!(root_inode.unlink("empty", true));
+    assert!(!root_inode.unlink("gone", false));
+    assert!(root_inode.find("gone").is_none());
+    assert!(root_inode
+        .ls()
+        .iter()
+        .all(|name| name != "gone" && name != "empty"));
+    assert_eq!(efs.lock().quota(0), usage);
+    assert_eq!(root_inode.find("q2").unwrap().size(), 0);
+
+    let report = EasyFileSystem::check(block_file.clone());
+    assert!(report.is_clean(), "{:?}", report.errors);
+    // filec grew back to 2000 blocks, frag1 has 100 blocks in as many
+    // extents, 87 of them in 2 extent blocks
+    assert_eq!((report.dirs, report.files, report.blocks), (2, 7, 2105));
+    // a block marked used by no inode
+    let leaked = efs.lock().alloc_data();
+    let report = EasyFileSystem::check(block_file.clone());
+    assert_eq!(report.error_count, 1);
+    assert_eq!(report.errors[0], format!("block {} is not used", leaked));
+
+    Ok(())
+}
+
+#[test]
+fn fat_test() -> std::io::Result<()> {
+    use fat_fs::FatFileSystem;
+    let block_file: Arc<dyn BlockDevice> = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open("target/fat.img")?;
+        f.set_len(8192 * 512).unwrap();
+        f
+    })));
+    FatFileSystem::format(block_file.clone(), 8192);
+    assert!(FatFileSystem::probe(&block_file));
+    assert!(!EasyFileSystem::probe(&block_file));
+    let fs = FatFileSystem::open(block_file.clone());
+    let root_inode = Arc::new(FatFileSystem::root_inode(&fs));
+    assert!(root_inode.is_dir());
+
+    // 8.3 names take a short entry only, others long entries before it
+    let names = [
+        "README.TXT",
+        "notes.md",
+        "A long file name.txt",
+        "a name long enough to take several long entries of 13 characters.data",
+        "中文名",
+    ];
+    for name in names {
+        assert!(root_inode.create(name).is_some(), "{}", name);
+    }
+    assert_eq!(root_inode.ls(), names);
+    assert!(root_inode.create("readme.txt").is_none());
+    assert!(root_inode.create("a:b").is_none());
+    assert!(root_inode.create("dot.").is_none());
+    // found ignoring the case, the same inode while in use
+    let readme = root_inode.find("ReadMe.txt").unwrap();
+    assert!(Arc::ptr_eq(
+        &root_inode.find("README.TXT").unwrap(),
+        &readme
+    ));
+    let greet_str = "Hello, world!";
+    assert_eq!(readme.write_at(0, greet_str.as_bytes()), greet_str.len());
+    let mut buffer = [0u8; 233];
+    let len = readme.read_at(0, &mut buffer);
+    assert_eq!(&buffer[..len], greet_str.as_bytes());
+
+    // a file of many clusters, read in pieces across them
+    let big = root_inode.find("A long file name.txt").unwrap();
+    let free = fs.lock().free_clusters();
+    let data: Vec<u8> = (0..300 * BLOCK_SZ + 17)
+        .map(|_| rand::random::<u8>())
+        .collect();
+    assert_eq!(big.write_at(0, &data), data.len());
+    assert_eq!(big.size(), data.len());
+    let mut read_data = Vec::new();
+    let mut read_buffer = [0u8; 127];
+    loop {
+        let len = big.read_at(read_data.len(), &mut read_buffer);
+        if len == 0 {
+            break;
+        }
+        read_data.extend_from_slice(&read_buffer[..len]);
+    }
+    assert_eq!(read_data, data);
+    assert_eq!(fs.lock().free_clusters(), free - 301);
+
+    // holes and the tails of shrunk files read as zeros
+    assert!(big.truncate(10));
+    assert_eq!(fs.lock().free_clusters(), free - 1);
+    assert_eq!(big.write_at(3 * BLOCK_SZ, b"end"), 3);
+    let mut read_data = vec![0xffu8; 3 * BLOCK_SZ + 3];
+    assert_eq!(big.read_at(0, &mut read_data), 3 * BLOCK_SZ + 3);
+    assert_eq!(&read_data[..10], &data[..10]);
+    assert!(read_data[10..3 * BLOCK_SZ].iter().all(|&b| b == 0));
+    assert_eq!(&read_data[3 * BLOCK_SZ..], b"end");
+    assert!(big.truncate(0));
+    assert_eq!(fs.lock().free_clusters(), free);
+    assert!(big.truncate(BLOCK_SZ + 1));
+    assert_eq!(big.read_at(0, &mut buffer), buffer.len());
+    assert!(buffer.iter().all(|&b| b == 0));
+
+    // renamed in place to a shorter name, or moved to a longer one
+    assert!(root_inode.rename("A long file name.txt", "short.txt"));
+    assert!(root_inode.find("A long file name.txt").is_none());
+    assert!(Arc::ptr_eq(&root_inode.find("SHORT.TXT").unwrap(), &big));
+    assert!(root_inode.rename("short.txt", "a much longer name than it was before"));
+    assert!(Arc::ptr_eq(
+        &root_inode
+            .find("a much longer name than it was before")
+            .unwrap(),
+        &big
+    ));
+    assert_eq!(big.write_at(0, b"moved"), 5);
+    assert!(!root_inode.rename("notes.md", "readme.TXT"));
+    assert!(root_inode.rename("readme.txt", "ReadMe.txt"));
+
+    let dir = root_inode.create_dir("sub dir").unwrap();
+    assert!(dir.is_dir());
+    assert!(dir.ls().is_empty());
+    let inner = dir.create("inner").unwrap();
+    assert_eq!(inner.write_at(0, greet_str.as_bytes()), greet_str.len());
+    assert!(root_inode.find("inner").is_none());
+    assert_eq!(dir.write_at(0, b"x"), 0);
+    assert!(!dir.truncate(0));
+    // directories grow by clusters for more entries
+    for i in 0..40 {
+        let name = format!("file with a long name {}", i);
+        assert!(dir.create(name.as_str()).is_some());
+    }
+    assert_eq!(dir.ls().len(), 41);
+    assert!(dir.size() > BLOCK_SZ);
+
+    // removed with their clusters unless in use, or a directory not empty
+    let free = fs.lock().free_clusters();
+    let scratch = root_inode.create("a scratch file").unwrap();
+    assert_eq!(scratch.write_at(0, &[7u8; 5 * BLOCK_SZ]), 5 * BLOCK_SZ);
+    assert!(!root_inode.unlink("a scratch file", false));
+    drop(scratch);
+    assert!(root_inode.create_dir("empty").is_some());
+    assert!(!root_inode.unlink("A SCRATCH FILE", true));
+    assert!(!root_inode.unlink("empty", false));
+    assert!(!root_inode.unlink("sub dir", true));
+    assert!(!dir.unlink("inner", false));
+    assert!(root_inode.unlink("A SCRATCH FILE", false));
+    assert!(root_inode.unlink("Empty", true));
+    assert!(root_inode.find("a scratch file").is_none());
+    assert!(root_inode.find("empty").is_none());
+    assert_eq!(fs.lock().free_clusters(), free);
+
+    // the times are of the clock, the access time a date only
+    fs.lock().clock = || 1_700_000_123;
+    inner.read_at(0, &mut buffer);
+    assert_eq!(inner.times().0, 1_700_000_123 / 86400 * 86400);
+    assert_eq!(inner.write_at(0, b"h"), 1);
+    assert_eq!(inner.times().1, 1_700_000_122);
+    fs.lock().remount(easy_fs::MountFlags::RDONLY);
+    assert_eq!(inner.write_at(0, b"x"), 0);
+    assert!(root_inode.create("new").is_none());
+    assert!(!root_inode.rename("notes.md", "notes.txt"));
+    fs.lock().remount(easy_fs::MountFlags::SYNC);
+
+    // all is on the disk when opened again, the free clusters in FSInfo
+    easy_fs::block_cache_sync_all();
+    let free = fs.lock().free_clusters();
+    let fs = FatFileSystem::open(block_file.clone());
+    assert_eq!(fs.lock().free_clusters(), free);
+    let root_inode = FatFileSystem::root_inode(&fs);
+    assert_eq!(
+        root_inode.ls(),
+        // ReadMe.txt takes a long entry now, moved to the entries of the
+        // long name renamed before
+        [
+            "notes.md",
+            "ReadMe.txt",
+            "a name long enough to take several long entries of 13 characters.data",
+            "中文名",
+            "a much longer name than it was before",
+            "sub dir",
+        ]
+    );
+    let len = root_inode
+        .find("sub dir")
+        .unwrap()
+        .find("inner")
+        .unwrap()
+        .read_at(0, &mut buffer);
+    assert_eq!(&buffer[..len], b"hello, world!");
+    let moved = root_inode
+        .find("A MUCH LONGER NAME THAN IT WAS BEFORE")
+        .unwrap();
+    assert_eq!(moved.size(), BLOCK_SZ + 1);
+    assert_eq!(moved.read_at(0, &mut buffer[..5]), 5);
+    assert_eq!(&buffer[..5], b"moved");
+
+    // laid out as the specification says
+    let mut sector = [0u8; BLOCK_SZ];
+    block_file.read_block(0, &mut sector);
+    assert_eq!(&sector[82..90], b"FAT32   ");
+    assert_eq!(&sector[510..], &[0x55, 0xAA]);
+    let mut backup = [0u8; BLOCK_SZ];
+    block_file.read_block(6, &mut backup);
+    assert_eq!(sector, backup);
+    block_file.read_block(1, &mut sector);
+    assert_eq!(&sector[..4], b"RRaA");
+    assert_eq!(sector[488..492], free.to_le_bytes());
     Ok(())
 }
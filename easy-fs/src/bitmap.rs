@@ -53,6 +53,20 @@ impl Bitmap {
         None
     }
 
+    /// Allocate `bit` if it is free, return false otherwise.
+    pub fn alloc_at(&self, block_device: &Arc<dyn BlockDevice>, bit: usize) -> bool {
+        let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
+        get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))
+            .lock()
+            .modify(0, |bitmap_block: &mut BitmapBlock| {
+                if bitmap_block[bits64_pos] & (1u64 << inner_pos) != 0 {
+                    return false;
+                }
+                bitmap_block[bits64_pos] |= 1u64 << inner_pos;
+                true
+            })
+    }
+
     pub fn dealloc(&self, block_device: &Arc<dyn BlockDevice>, bit: usize) {
         let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
         get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))
@@ -63,6 +77,15 @@ impl Bitmap {
             });
     }
 
+    pub fn is_set(&self, block_device: &Arc<dyn BlockDevice>, bit: usize) -> bool {
+        let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
+        get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))
+            .lock()
+            .read(0, |bitmap_block: &BitmapBlock| {
+                bitmap_block[bits64_pos] & (1u64 << inner_pos) != 0
+            })
+    }
+
     pub fn maximum(&self) -> usize {
         self.blocks * BLOCK_BITS
     }
@@ -1,5 +1,5 @@
 use super::{BlockDevice, BLOCK_SZ};
-use alloc::collections::VecDeque;
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use alloc::vec;
 use alloc::vec::Vec;
@@ -74,16 +74,106 @@ impl Drop for BlockCache {
     }
 }
 
-const BLOCK_CACHE_SIZE: usize = 16;
+/// The blocks cached at most by default, small for the tools with little
+/// heap, see `set_block_cache_capacity`.
+const DEFAULT_CAPACITY: usize = 16;
+/// At most this many blocks are read ahead of a sequential read.
+const READ_AHEAD_MAX: usize = 4;
 
+/// A block by the address of its device and the block id, so that the
+/// devices mounted at once do not share the cached blocks.
+type BlockKey = (usize, usize);
+
+fn block_key(block_id: usize, block_device: &Arc<dyn BlockDevice>) -> BlockKey {
+    (Arc::as_ptr(block_device) as *const u8 as usize, block_id)
+}
+
+struct CachedBlock {
+    cache: Arc<Mutex<BlockCache>>,
+    /// when it was used last by the clock of the manager
+    last_used: u64,
+}
+
+impl CachedBlock {
+    /// Not used but by the cache, so it may be dropped.
+    fn idle(&self) -> bool {
+        Arc::strong_count(&self.cache) == 1
+    }
+}
+
+/// The blocks cached, the least recently used of them replaced first, a
+/// clean one before a modified one so that a miss seldom waits for a write.
+/// The modified blocks are written back when replaced or synced, see
+/// `block_cache_sync_all` and `block_cache_write_back_oldest`.
 pub struct BlockCacheManager {
-    queue: VecDeque<(usize, Arc<Mutex<BlockCache>>)>,
+    blocks: BTreeMap<BlockKey, CachedBlock>,
+    capacity: usize,
+    /// counts the uses of the blocks
+    clock: u64,
+    /// the block missed last
+    last_miss: Option<usize>,
+    /// the misses in a row each of the block after the last one
+    sequential: usize,
+    /// the blocks before it are read ahead already in this run
+    ahead_end: usize,
 }
 
 impl BlockCacheManager {
     pub fn new() -> Self {
         Self {
-            queue: VecDeque::new(),
+            blocks: BTreeMap::new(),
+            capacity: DEFAULT_CAPACITY,
+            clock: 0,
+            last_miss: None,
+            sequential: 0,
+            ahead_end: 0,
+        }
+    }
+
+    /// On a miss of `block_id`, read the blocks after it ahead if it follows
+    /// the last miss, more of them the longer the run is. Those cached
+    /// already are not read again.
+    fn read_ahead(&mut self, block_id: usize, block_device: &Arc<dyn BlockDevice>) {
+        let sequential = block_id > 0 && self.last_miss == Some(block_id - 1);
+        self.last_miss = Some(block_id);
+        if !sequential {
+            self.sequential = 0;
+            self.ahead_end = 0;
+            return;
+        }
+        self.sequential += 1;
+        let window = (self.sequential * 2).min(READ_AHEAD_MAX);
+        let start = (block_id + 1).max(self.ahead_end);
+        let end = (block_id + 1 + window).min(block_device.num_blocks());
+        if start >= end {
+            return;
+        }
+        let mut run_start = start;
+        for id in start..=end {
+            if id == end || self.blocks.contains_key(&block_key(id, block_device)) {
+                if run_start < id {
+                    block_device.read_ahead(run_start, id - run_start);
+                }
+                run_start = id + 1;
+            }
+        }
+        self.ahead_end = end;
+    }
+
+    /// Drop the least recently used of the idle blocks, a clean one if any.
+    /// A modified one is written back as it is dropped.
+    fn evict(&mut self) {
+        let victim = self
+            .blocks
+            .iter()
+            .filter(|(_, block)| block.idle())
+            .min_by_key(|(_, block)| (block.cache.lock().modified, block.last_used))
+            .map(|(key, _)| *key);
+        match victim {
+            Some(key) => {
+                self.blocks.remove(&key);
+            }
+            None => panic!("Run out of BlockCache!"),
         }
     }
 
@@ -92,31 +182,29 @@ impl BlockCacheManager {
         block_id: usize,
         block_device: Arc<dyn BlockDevice>,
     ) -> Arc<Mutex<BlockCache>> {
-        if let Some(pair) = self.queue.iter().find(|pair| pair.0 == block_id) {
-            Arc::clone(&pair.1)
-        } else {
-            // substitute
-            if self.queue.len() == BLOCK_CACHE_SIZE {
-                // from front to tail
-                if let Some((idx, _)) = self
-                    .queue
-                    .iter()
-                    .enumerate()
-                    .find(|(_, pair)| Arc::strong_count(&pair.1) == 1)
-                {
-                    self.queue.drain(idx..=idx);
-                } else {
-                    panic!("Run out of BlockCache!");
-                }
-            }
-            // load block into mem and push back
-            let block_cache = Arc::new(Mutex::new(BlockCache::new(
-                block_id,
-                Arc::clone(&block_device),
-            )));
-            self.queue.push_back((block_id, Arc::clone(&block_cache)));
-            block_cache
+        let key = block_key(block_id, &block_device);
+        self.clock += 1;
+        if let Some(block) = self.blocks.get_mut(&key) {
+            block.last_used = self.clock;
+            return Arc::clone(&block.cache);
+        }
+        while self.blocks.len() >= self.capacity {
+            self.evict();
         }
+        self.read_ahead(block_id, &block_device);
+        // load block into mem
+        let cache = Arc::new(Mutex::new(BlockCache::new(
+            block_id,
+            Arc::clone(&block_device),
+        )));
+        self.blocks.insert(
+            key,
+            CachedBlock {
+                cache: Arc::clone(&cache),
+                last_used: self.clock,
+            },
+        );
+        cache
     }
 }
 
@@ -134,9 +222,123 @@ pub fn get_block_cache(
         .get_block_cache(block_id, block_device)
 }
 
+/// Cache up to `capacity` blocks from now on, the idle ones over it are
+/// dropped at once.
+pub fn set_block_cache_capacity(capacity: usize) {
+    let mut manager = BLOCK_CACHE_MANAGER.lock();
+    manager.capacity = capacity.max(1);
+    while manager.blocks.len() > manager.capacity
+        && manager.blocks.values().any(|block| block.idle())
+    {
+        manager.evict();
+    }
+}
+
+/// Write back the blocks, then flush the devices written to, so that the
+/// changes are persistent when it returns.
+fn write_back(caches: Vec<Arc<Mutex<BlockCache>>>) {
+    let mut devices: Vec<Arc<dyn BlockDevice>> = Vec::new();
+    for cache in caches {
+        let mut cache = cache.lock();
+        if !cache.modified {
+            continue;
+        }
+        cache.sync();
+        let ptr = Arc::as_ptr(&cache.block_device) as *const u8;
+        if !devices
+            .iter()
+            .any(|device| Arc::as_ptr(device) as *const u8 == ptr)
+        {
+            devices.push(Arc::clone(&cache.block_device));
+        }
+    }
+    for device in devices {
+        device.flush();
+    }
+}
+
+/// Write back every modified block, then flush the devices written to, so
+/// that the changes are persistent when it returns.
 pub fn block_cache_sync_all() {
+    let caches = BLOCK_CACHE_MANAGER
+        .lock()
+        .blocks
+        .values()
+        .map(|block| Arc::clone(&block.cache))
+        .collect();
+    write_back(caches);
+}
+
+/// Write back the least recently used of the modified blocks until `keep` of
+/// them are left, for the background writeback of the kernel. The cache is
+/// not held while they are written.
+pub fn block_cache_write_back_oldest(keep: usize) {
     let manager = BLOCK_CACHE_MANAGER.lock();
-    for (_, cache) in manager.queue.iter() {
-        cache.lock().sync();
+    let mut dirty: Vec<(u64, Arc<Mutex<BlockCache>>)> = manager
+        .blocks
+        .values()
+        .filter(|block| block.cache.lock().modified)
+        .map(|block| (block.last_used, Arc::clone(&block.cache)))
+        .collect();
+    drop(manager);
+    if dirty.len() <= keep {
+        return;
     }
+    dirty.sort_unstable_by_key(|(last_used, _)| *last_used);
+    let count = dirty.len() - keep;
+    write_back(
+        dirty
+            .into_iter()
+            .take(count)
+            .map(|(_, cache)| cache)
+            .collect(),
+    );
+}
+
+/// How many blocks are cached.
+pub fn block_cache_count() -> usize {
+    BLOCK_CACHE_MANAGER.lock().blocks.len()
+}
+
+/// How many cached blocks are modified and not written back yet.
+pub fn block_cache_dirty_count() -> usize {
+    let manager = BLOCK_CACHE_MANAGER.lock();
+    manager
+        .blocks
+        .values()
+        .filter(|block| block.cache.lock().modified)
+        .count()
+}
+
+/// Write back the cached copy of a block if it is modified, before the block
+/// is read from the device bypassing the cache.
+pub fn block_cache_write_back(block_id: usize, block_device: &Arc<dyn BlockDevice>) {
+    let key = block_key(block_id, block_device);
+    let manager = BLOCK_CACHE_MANAGER.lock();
+    if let Some(block) = manager.blocks.get(&key) {
+        block.cache.lock().sync();
+    }
+}
+
+/// Drop the cached copy of a block without writing it back, before the whole
+/// block is written to the device bypassing the cache.
+pub fn block_cache_discard(block_id: usize, block_device: &Arc<dyn BlockDevice>) {
+    let key = block_key(block_id, block_device);
+    let mut manager = BLOCK_CACHE_MANAGER.lock();
+    if let Some(block) = manager.blocks.remove(&key) {
+        // stale once written, so not written back when dropped
+        block.cache.lock().modified = false;
+    }
+}
+
+/// Write back the cached blocks of a device and drop them, when the file
+/// system on it is unmounted. The blocks in use are kept.
+pub fn block_cache_release(block_device: &Arc<dyn BlockDevice>) {
+    let device = block_key(0, block_device).0;
+    let mut manager = BLOCK_CACHE_MANAGER.lock();
+    manager
+        .blocks
+        .retain(|key, block| key.0 != device || !block.idle());
+    drop(manager);
+    block_device.flush();
 }
@@ -1,7 +1,37 @@
+use super::BLOCK_SZ;
 use core::any::Any;
 
 pub trait BlockDevice: Send + Sync + Any {
     fn read_block(&self, block_id: usize, buf: &mut [u8]);
     fn write_block(&self, block_id: usize, buf: &[u8]);
+    /// The capacity of the device in blocks.
+    fn num_blocks(&self) -> usize;
     fn handle_irq(&self);
+    /// Start reading `count` blocks from `block_id` without waiting for
+    /// them, so that `read_block` of them later waits less. Nothing to do by
+    /// default.
+    fn read_ahead(&self, _block_id: usize, _count: usize) {}
+    /// Make the blocks written so far persistent, a barrier for those
+    /// written after it. Nothing to do for a device without a volatile
+    /// write cache.
+    fn flush(&self) {}
+    /// Read the blocks from `block_id` into the buffers in turn, each of
+    /// whole blocks. A device may transfer them straight into the buffers
+    /// by one request, it reads them one by one by default.
+    fn read_blocks(&self, block_id: usize, bufs: &mut [&mut [u8]]) {
+        let blocks = bufs
+            .iter_mut()
+            .flat_map(|buf| buf.chunks_exact_mut(BLOCK_SZ));
+        for (block_id, block) in (block_id..).zip(blocks) {
+            self.read_block(block_id, block);
+        }
+    }
+    /// Write the buffers in turn to the blocks from `block_id`, like
+    /// `read_blocks`.
+    fn write_blocks(&self, block_id: usize, bufs: &[&[u8]]) {
+        let blocks = bufs.iter().flat_map(|buf| buf.chunks_exact(BLOCK_SZ));
+        for (block_id, block) in (block_id..).zip(blocks) {
+            self.write_block(block_id, block);
+        }
+    }
 }
@@ -3,17 +3,78 @@ use super::{
     SuperBlock,
 };
 use crate::BLOCK_SZ;
-use alloc::sync::Arc;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use bitflags::*;
 use spin::Mutex;
 
+bitflags! {
+    pub struct MountFlags: u32 {
+        /// nothing is written, changing operations fail
+        const RDONLY = 1 << 0;
+        /// the access times of inodes are not updated by reads
+        const NOATIME = 1 << 1;
+        /// changes are written to the device before operations return,
+        /// otherwise when evicted from the block cache or synced
+        const SYNC = 1 << 2;
+    }
+}
+
+/// The blocks and inodes taken by a user, and the limits of them, which are
+/// none if 0. Blocks are the data blocks of the files, not the extent
+/// blocks mapping them.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Quota {
+    pub blocks: u32,
+    pub inodes: u32,
+    pub block_limit: u32,
+    pub inode_limit: u32,
+}
+
+impl Quota {
+    fn blocks_left(&self) -> u32 {
+        match self.block_limit {
+            0 => u32::MAX,
+            limit => limit.saturating_sub(self.blocks),
+        }
+    }
+    fn inodes_left(&self) -> u32 {
+        match self.inode_limit {
+            0 => u32::MAX,
+            limit => limit.saturating_sub(self.inodes),
+        }
+    }
+}
+
 pub struct EasyFileSystem {
     pub block_device: Arc<dyn BlockDevice>,
     pub inode_bitmap: Bitmap,
     pub data_bitmap: Bitmap,
     inode_area_start_block: u32,
     data_area_start_block: u32,
+    data_area_blocks: u32,
+    pub flags: MountFlags,
+    /// the time in seconds for the access and modification times of inodes
+    pub clock: fn() -> u32,
+    /// the user creating inodes, who owns them
+    pub current_uid: fn() -> u16,
+    /// the usage of each user counted when opened, and the limits set since,
+    /// which are not on the disk
+    quotas: BTreeMap<u16, Quota>,
+    /// the inodes in memory by inode number, one for each file in use
+    pub(crate) inodes: BTreeMap<u32, Weak<Inode>>,
+    /// the inode numbers of the names found in directories, by the inode
+    /// number of the directory and the name
+    pub(crate) dentries: BTreeMap<(u32, String), u32>,
 }
 
+/// The inodes in memory are pruned of the ones not in use beyond this.
+const INODE_CACHE_SIZE: usize = 64;
+/// The names found in directories cached at most.
+const DENTRY_CACHE_SIZE: usize = 256;
+
 type DataBlock = [u8; BLOCK_SZ];
 
 impl EasyFileSystem {
@@ -41,6 +102,13 @@ impl EasyFileSystem {
             data_bitmap,
             inode_area_start_block: 1 + inode_bitmap_blocks,
             data_area_start_block: 1 + inode_total_blocks + data_bitmap_blocks,
+            data_area_blocks,
+            flags: MountFlags::SYNC,
+            clock: || 0,
+            current_uid: || 0,
+            quotas: BTreeMap::new(),
+            inodes: BTreeMap::new(),
+            dentries: BTreeMap::new(),
         };
         // clear all blocks
         for i in 0..total_blocks {
@@ -75,18 +143,27 @@ impl EasyFileSystem {
                 disk_inode.initialize(DiskInodeType::Directory);
             });
         block_cache_sync_all();
+        efs.count_usage();
         Arc::new(Mutex::new(efs))
     }
 
+    /// Whether the device has an easy-fs, which `open` asserts.
+    pub fn probe(block_device: &Arc<dyn BlockDevice>) -> bool {
+        block_device.num_blocks() > 0
+            && get_block_cache(0, Arc::clone(block_device))
+                .lock()
+                .read(0, |super_block: &SuperBlock| super_block.is_valid())
+    }
+
     pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
         // read SuperBlock
-        get_block_cache(0, Arc::clone(&block_device))
-            .lock()
-            .read(0, |super_block: &SuperBlock| {
+        let mut efs = get_block_cache(0, Arc::clone(&block_device)).lock().read(
+            0,
+            |super_block: &SuperBlock| {
                 assert!(super_block.is_valid(), "Error loading EFS!");
                 let inode_total_blocks =
                     super_block.inode_bitmap_blocks + super_block.inode_area_blocks;
-                let efs = Self {
+                Self {
                     block_device,
                     inode_bitmap: Bitmap::new(1, super_block.inode_bitmap_blocks as usize),
                     data_bitmap: Bitmap::new(
@@ -95,9 +172,73 @@ impl EasyFileSystem {
                     ),
                     inode_area_start_block: 1 + super_block.inode_bitmap_blocks,
                     data_area_start_block: 1 + inode_total_blocks + super_block.data_bitmap_blocks,
-                };
-                Arc::new(Mutex::new(efs))
-            })
+                    data_area_blocks: super_block.data_area_blocks,
+                    flags: MountFlags::SYNC,
+                    clock: || 0,
+                    current_uid: || 0,
+                    quotas: BTreeMap::new(),
+                    inodes: BTreeMap::new(),
+                    dentries: BTreeMap::new(),
+                }
+            },
+        );
+        efs.count_usage();
+        Arc::new(Mutex::new(efs))
+    }
+
+    /// Count the blocks and inodes of each user from the inodes in use.
+    fn count_usage(&mut self) {
+        for inode_id in 0..self.inode_bitmap.maximum() {
+            if !self.inode_bitmap.is_set(&self.block_device, inode_id) {
+                continue;
+            }
+            let (block_id, offset) = self.get_disk_inode_pos(inode_id as u32);
+            let (uid, blocks) = get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+                .lock()
+                .read(offset, |disk_inode: &DiskInode| {
+                    (disk_inode.uid, disk_inode.data_blocks())
+                });
+            let quota = self.quotas.entry(uid).or_default();
+            quota.inodes += 1;
+            quota.blocks += blocks;
+        }
+    }
+
+    /// The usage and the limits of `uid`.
+    pub fn quota(&self, uid: u16) -> Quota {
+        self.quotas.get(&uid).copied().unwrap_or_default()
+    }
+
+    /// Set the limits of `uid`, 0 for none. It keeps what it has over them,
+    /// but gets nothing more.
+    pub fn set_quota_limits(&mut self, uid: u16, block_limit: u32, inode_limit: u32) {
+        let quota = self.quotas.entry(uid).or_default();
+        quota.block_limit = block_limit;
+        quota.inode_limit = inode_limit;
+    }
+
+    /// How many more data blocks `uid` may take.
+    pub(crate) fn blocks_left(&self, uid: u16) -> u32 {
+        self.quota(uid).blocks_left()
+    }
+
+    pub(crate) fn inodes_left(&self, uid: u16) -> u32 {
+        self.quota(uid).inodes_left()
+    }
+
+    /// Count `blocks` data blocks and `inodes` inodes more for `uid`.
+    pub(crate) fn charge(&mut self, uid: u16, blocks: u32, inodes: u32) {
+        let quota = self.quotas.entry(uid).or_default();
+        quota.blocks += blocks;
+        quota.inodes += inodes;
+    }
+
+    /// Count `blocks` data blocks and `inodes` inodes less for `uid`, which
+    /// are freed.
+    pub(crate) fn refund(&mut self, uid: u16, blocks: u32, inodes: u32) {
+        let quota = self.quotas.entry(uid).or_default();
+        quota.blocks = quota.blocks.saturating_sub(blocks);
+        quota.inodes = quota.inodes.saturating_sub(inodes);
     }
 
     pub fn root_inode(efs: &Arc<Mutex<Self>>) -> Inode {
@@ -105,7 +246,56 @@ impl EasyFileSystem {
         // acquire efs lock temporarily
         let (block_id, block_offset) = efs.lock().get_disk_inode_pos(0);
         // release efs lock
-        Inode::new(block_id, block_offset, Arc::clone(efs), block_device)
+        Inode::new(0, block_id, block_offset, Arc::clone(efs), block_device)
+    }
+
+    /// The number of blocks of the file system on a device, or None if there
+    /// is none. It is read from the device rather than the block cache, so
+    /// it works even when the cache is locked. The blocks of the device after
+    /// the file system are left for other uses, like kernel crash dumps.
+    pub fn total_blocks(block_device: &dyn BlockDevice) -> Option<usize> {
+        let mut block: DataBlock = [0; BLOCK_SZ];
+        block_device.read_block(0, &mut block);
+        let super_block = unsafe { (block.as_ptr() as *const SuperBlock).read_unaligned() };
+        if super_block.is_valid() {
+            Some(super_block.total_blocks as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Change how it is mounted, changes are written back first.
+    pub fn remount(&mut self, flags: MountFlags) {
+        block_cache_sync_all();
+        self.flags = flags;
+    }
+
+    /// Write back changes if mounted with `MountFlags::SYNC`.
+    pub fn sync_if_needed(&self) {
+        if self.flags.contains(MountFlags::SYNC) {
+            block_cache_sync_all();
+        }
+    }
+
+    /// Remember that `name` in the directory `dir` is the inode `inode_id`,
+    /// dropping an arbitrary name when there are too many.
+    pub(crate) fn cache_dentry(&mut self, dir: u32, name: &str, inode_id: u32) {
+        if self.dentries.len() >= DENTRY_CACHE_SIZE {
+            self.dentries.pop_first();
+        }
+        self.dentries.insert((dir, String::from(name)), inode_id);
+    }
+
+    /// The inode in memory of `inode_id` if it is in use.
+    pub(crate) fn cached_inode(&self, inode_id: u32) -> Option<Arc<Inode>> {
+        self.inodes.get(&inode_id).and_then(Weak::upgrade)
+    }
+
+    pub(crate) fn cache_inode(&mut self, inode_id: u32, inode: &Arc<Inode>) {
+        if self.inodes.len() >= INODE_CACHE_SIZE {
+            self.inodes.retain(|_, inode| inode.strong_count() > 0);
+        }
+        self.inodes.insert(inode_id, Arc::downgrade(inode));
     }
 
     pub fn get_disk_inode_pos(&self, inode_id: u32) -> (u32, usize) {
@@ -126,11 +316,29 @@ impl EasyFileSystem {
         self.inode_bitmap.alloc(&self.block_device).unwrap() as u32
     }
 
+    pub fn dealloc_inode(&mut self, inode_id: u32) {
+        self.inode_bitmap
+            .dealloc(&self.block_device, inode_id as usize)
+    }
+
     /// Return a block ID not ID in the data area.
     pub fn alloc_data(&mut self) -> u32 {
         self.data_bitmap.alloc(&self.block_device).unwrap() as u32 + self.data_area_start_block
     }
 
+    /// Like `alloc_data`, but take the block `goal` if it is free, so a file
+    /// grows into the blocks after it.
+    pub fn alloc_data_near(&mut self, goal: u32) -> u32 {
+        if let Some(bit) = goal.checked_sub(self.data_area_start_block) {
+            if bit < self.data_area_blocks
+                && self.data_bitmap.alloc_at(&self.block_device, bit as usize)
+            {
+                return goal;
+            }
+        }
+        self.alloc_data()
+    }
+
     pub fn dealloc_data(&mut self, block_id: u32) {
         get_block_cache(block_id as usize, Arc::clone(&self.block_device))
             .lock()
@@ -0,0 +1,213 @@
+use super::{
+    get_block_cache, Bitmap, BlockDevice, DirEntry, DiskInode, EasyFileSystem, SuperBlock,
+    BLOCK_SZ, DIRENT_SZ,
+};
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Errors beyond it are only counted, a broken disk may have many.
+const MAX_ERRORS: usize = 16;
+
+type DataBlock = [u8; BLOCK_SZ];
+
+/// What is found by `EasyFileSystem::check`.
+#[derive(Default)]
+pub struct FsckReport {
+    pub files: usize,
+    pub dirs: usize,
+    /// used data blocks, including the index blocks of inodes
+    pub blocks: usize,
+    pub error_count: usize,
+    /// the first `MAX_ERRORS` errors
+    pub errors: Vec<String>,
+}
+
+impl FsckReport {
+    pub fn is_clean(&self) -> bool {
+        self.error_count == 0
+    }
+
+    fn error(&mut self, error: String) {
+        if self.errors.len() < MAX_ERRORS {
+            self.errors.push(error);
+        }
+        self.error_count += 1;
+    }
+}
+
+/// A set of numbers below a bound.
+struct BitSet(Vec<u64>);
+
+impl BitSet {
+    fn new(bound: usize) -> Self {
+        Self(vec![0; (bound + 63) / 64])
+    }
+
+    /// Return false if it is already in the set.
+    fn insert(&mut self, n: usize) -> bool {
+        let inserted = self.0[n / 64] & (1 << (n % 64)) == 0;
+        self.0[n / 64] |= 1 << (n % 64);
+        inserted
+    }
+
+    fn contains(&self, n: usize) -> bool {
+        self.0[n / 64] & (1 << (n % 64)) != 0
+    }
+}
+
+impl EasyFileSystem {
+    /// Check a file system on a device which is not mounted: the layout
+    /// in the super block, that each inode linked from the root is in one
+    /// directory only, that each block is used by one inode only, and that
+    /// the bitmaps mark exactly the inodes and blocks in use.
+    pub fn check(block_device: Arc<dyn BlockDevice>) -> FsckReport {
+        let mut report = FsckReport::default();
+        let super_block = get_block_cache(0, Arc::clone(&block_device))
+            .lock()
+            .read(0, |super_block: &SuperBlock| *super_block);
+        if !super_block.is_valid() {
+            report.error(String::from("no easy-fs super block"));
+            return report;
+        }
+        let total_blocks = super_block.total_blocks as usize;
+        let inode_bitmap_blocks = super_block.inode_bitmap_blocks as usize;
+        let inode_area_blocks = super_block.inode_area_blocks as usize;
+        let data_bitmap_blocks = super_block.data_bitmap_blocks as usize;
+        let data_area_blocks = super_block.data_area_blocks as usize;
+        // the same layout as `EasyFileSystem::create` makes
+        let inode_num = inode_bitmap_blocks * BLOCK_SZ * 8;
+        let data_total_blocks =
+            total_blocks.saturating_sub(1 + inode_bitmap_blocks + inode_area_blocks);
+        if inode_bitmap_blocks == 0
+            || inode_area_blocks
+                != (inode_num * core::mem::size_of::<DiskInode>() + BLOCK_SZ - 1) / BLOCK_SZ
+            || data_bitmap_blocks != (data_total_blocks + 4096) / 4097
+            || data_area_blocks + data_bitmap_blocks != data_total_blocks
+        {
+            report.error(String::from("bad layout in the super block"));
+            return report;
+        }
+        if total_blocks > block_device.num_blocks() {
+            report.error(format!(
+                "{} blocks in the super block but {} on the device",
+                total_blocks,
+                block_device.num_blocks()
+            ));
+            return report;
+        }
+        let inode_area_start = 1 + inode_bitmap_blocks;
+        let data_area_start = inode_area_start + inode_area_blocks + data_bitmap_blocks;
+        let inode_bitmap = Bitmap::new(1, inode_bitmap_blocks);
+        let data_bitmap = Bitmap::new(inode_area_start + inode_area_blocks, data_bitmap_blocks);
+        let in_data_area =
+            |block_id: u32| (data_area_start..total_blocks).contains(&(block_id as usize));
+
+        // walk the tree from the root
+        let mut linked = BitSet::new(inode_num);
+        let mut used = BitSet::new(data_area_blocks);
+        let mut pending = vec![0u32];
+        linked.insert(0);
+        while let Some(inode_id) = pending.pop() {
+            if !inode_bitmap.is_set(&block_device, inode_id as usize) {
+                report.error(format!("inode {} is linked but free", inode_id));
+            }
+            let inodes_per_block = BLOCK_SZ / core::mem::size_of::<DiskInode>();
+            let block_id = inode_area_start + inode_id as usize / inodes_per_block;
+            let offset = inode_id as usize % inodes_per_block * core::mem::size_of::<DiskInode>();
+            let inode_cache = get_block_cache(block_id, Arc::clone(&block_device));
+            if !inode_cache
+                .lock()
+                .read(0, |block: &DataBlock| DiskInode::type_valid(block, offset))
+            {
+                report.error(format!("inode {} has a bad type", inode_id));
+                continue;
+            }
+            let inode_cache = inode_cache.lock();
+            let disk_inode: &DiskInode = inode_cache.get_ref(offset);
+            if inode_id == 0 && !disk_inode.is_dir() {
+                report.error(String::from("the root is not a directory"));
+                continue;
+            }
+            let mut blocks_valid = true;
+            let walked = disk_inode.for_each_block(&block_device, in_data_area, |block_id| {
+                if !in_data_area(block_id) {
+                    report.error(format!(
+                        "inode {} has block {} out of the data area",
+                        inode_id, block_id
+                    ));
+                    blocks_valid = false;
+                    return;
+                }
+                let bit = block_id as usize - data_area_start;
+                if !used.insert(bit) {
+                    report.error(format!("block {} is used more than once", block_id));
+                } else if !data_bitmap.is_set(&block_device, bit) {
+                    report.error(format!("block {} is used but free", block_id));
+                }
+                report.blocks += 1;
+            });
+            match walked {
+                Ok(mapped) if mapped == disk_inode.data_blocks() as usize => {}
+                Ok(_) => {
+                    report.error(format!("inode {} has fewer blocks than its size", inode_id));
+                    continue;
+                }
+                Err(block_id) => {
+                    report.error(format!(
+                        "inode {} has extent block {} out of the data area",
+                        inode_id, block_id
+                    ));
+                    continue;
+                }
+            }
+            if !disk_inode.is_dir() {
+                report.files += 1;
+                continue;
+            }
+            report.dirs += 1;
+            if !blocks_valid || disk_inode.size as usize % DIRENT_SZ != 0 {
+                report.error(format!("directory {} has bad entries", inode_id));
+                continue;
+            }
+            for i in 0..disk_inode.size as usize / DIRENT_SZ {
+                let mut dirent = DirEntry::empty();
+                disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &block_device);
+                let name = match dirent.checked_name() {
+                    Some(name) if !name.is_empty() => name,
+                    _ => {
+                        report.error(format!("directory {} has a bad name", inode_id));
+                        continue;
+                    }
+                };
+                let child = dirent.inode_number();
+                if child as usize >= inode_num {
+                    report.error(format!("{} links to no inode {}", name, child));
+                } else if !linked.insert(child as usize) {
+                    report.error(format!("inode {} is linked more than once", child));
+                } else {
+                    pending.push(child);
+                }
+            }
+        }
+
+        // and what is marked but not found
+        for inode_id in 0..inode_num {
+            if !linked.contains(inode_id) && inode_bitmap.is_set(&block_device, inode_id) {
+                report.error(format!("inode {} is not linked", inode_id));
+            }
+        }
+        for bit in 0..data_bitmap.maximum() {
+            if bit >= data_area_blocks {
+                if data_bitmap.is_set(&block_device, bit) {
+                    report.error(format!("bit {} beyond the data area is set", bit));
+                }
+            } else if !used.contains(bit) && data_bitmap.is_set(&block_device, bit) {
+                report.error(format!("block {} is not used", data_area_start + bit));
+            }
+        }
+        report
+    }
+}
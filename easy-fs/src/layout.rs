@@ -3,17 +3,16 @@ use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::fmt::{Debug, Formatter, Result};
 
-const EFS_MAGIC: u32 = 0x3b800001;
-const INODE_DIRECT_COUNT: usize = 28;
-const NAME_LENGTH_LIMIT: usize = 27;
-const INODE_INDIRECT1_COUNT: usize = BLOCK_SZ / 4;
-const INODE_INDIRECT2_COUNT: usize = INODE_INDIRECT1_COUNT * INODE_INDIRECT1_COUNT;
-const DIRECT_BOUND: usize = INODE_DIRECT_COUNT;
-const INDIRECT1_BOUND: usize = DIRECT_BOUND + INODE_INDIRECT1_COUNT;
-#[allow(unused)]
-const INDIRECT2_BOUND: usize = INDIRECT1_BOUND + INODE_INDIRECT2_COUNT;
+/// changed with the layout of inodes: 0x3b800002 added the access and
+/// change times, this one maps data blocks by extents
+const EFS_MAGIC: u32 = 0x3b800003;
+/// as many as keep an inode 128 bytes
+const INODE_EXTENT_COUNT: usize = 13;
+const BLOCK_EXTENT_COUNT: usize = BLOCK_SZ / core::mem::size_of::<Extent>() - 1;
+pub const NAME_LENGTH_LIMIT: usize = 27;
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct SuperBlock {
     magic: u32,
     pub total_blocks: u32,
@@ -59,31 +58,71 @@ impl SuperBlock {
 }
 
 #[derive(PartialEq)]
+#[repr(u8)]
 pub enum DiskInodeType {
     File,
     Directory,
 }
 
-type IndirectBlock = [u32; BLOCK_SZ / 4];
 type DataBlock = [u8; BLOCK_SZ];
 
+/// A run of data blocks next to each other on the disk.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct Extent {
+    start: u32,
+    len: u32,
+}
+
+/// The extents of an inode after those in the inode, in a list of blocks.
+#[repr(C)]
+struct ExtentBlock {
+    extents: [Extent; BLOCK_EXTENT_COUNT],
+    /// the next block of the list
+    next: u32,
+    _reserved: u32,
+}
+
+/// The data blocks of an inode are mapped by extents, so a file written at
+/// once takes a few of them whatever its size, and is read without reading
+/// index blocks. The first extents are in the inode, the others in a list
+/// of extent blocks.
 #[repr(C)]
 pub struct DiskInode {
     pub size: u32,
-    pub direct: [u32; INODE_DIRECT_COUNT],
-    pub indirect1: u32,
-    pub indirect2: u32,
+    /// the extents in use, in the order of the data they map
+    extent_count: u32,
+    extents: [Extent; INODE_EXTENT_COUNT],
+    /// the first extent block, allocated once the inode is full
+    extent_block: u32,
+    /// the time of the last read in seconds of the clock of the file system
+    pub atime: u32,
+    /// the time of the last change
+    pub mtime: u32,
     type_: DiskInodeType,
+    /// the user who created it, charged for its blocks, 0 in file systems
+    /// made before owners were recorded
+    pub uid: u16,
 }
 
 impl DiskInode {
-    /// indirect1 and indirect2 block are allocated only when they are needed.
+    /// Whether the type of the inode at `offset` in a block of inodes is
+    /// valid, to be checked before reading an inode from a broken disk.
+    pub fn type_valid(block: &DataBlock, offset: usize) -> bool {
+        // the type is the last field but the owner
+        let type_offset = offset + core::mem::size_of::<u32>() * (5 + 2 * INODE_EXTENT_COUNT);
+        block[type_offset] <= DiskInodeType::Directory as u8
+    }
+    /// Extent blocks are allocated only when they are needed.
     pub fn initialize(&mut self, type_: DiskInodeType) {
         self.size = 0;
-        self.direct.iter_mut().for_each(|v| *v = 0);
-        self.indirect1 = 0;
-        self.indirect2 = 0;
+        self.extent_count = 0;
+        self.extents = [Extent::default(); INODE_EXTENT_COUNT];
+        self.extent_block = 0;
+        self.atime = 0;
+        self.mtime = 0;
         self.type_ = type_;
+        self.uid = 0;
     }
     pub fn is_dir(&self) -> bool {
         self.type_ == DiskInodeType::Directory
@@ -99,198 +138,282 @@ impl DiskInode {
     fn _data_blocks(size: u32) -> u32 {
         (size + BLOCK_SZ as u32 - 1) / BLOCK_SZ as u32
     }
-    /// Return number of blocks needed include indirect1/2.
-    pub fn total_blocks(size: u32) -> u32 {
-        let data_blocks = Self::_data_blocks(size) as usize;
-        let mut total = data_blocks as usize;
-        // indirect1
-        if data_blocks > INODE_DIRECT_COUNT {
-            total += 1;
+
+    /// Call `f` with the extents in order, and the extent block of each or
+    /// 0 for those in the inode, until it returns false. Return the first
+    /// extent block for which `valid` is false, which is not read.
+    fn walk_extents(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        valid: impl Fn(u32) -> bool,
+        mut f: impl FnMut(u32, Extent) -> bool,
+    ) -> core::result::Result<(), u32> {
+        let count = self.extent_count as usize;
+        for extent in self.extents.iter().take(count) {
+            if !f(0, *extent) {
+                return Ok(());
+            }
         }
-        // indirect2
-        if data_blocks > INDIRECT1_BOUND {
-            total += 1;
-            // sub indirect1
-            total +=
-                (data_blocks - INDIRECT1_BOUND + INODE_INDIRECT1_COUNT - 1) / INODE_INDIRECT1_COUNT;
+        let mut rest = count.saturating_sub(INODE_EXTENT_COUNT);
+        let mut block_id = self.extent_block;
+        while rest > 0 {
+            if !valid(block_id) {
+                return Err(block_id);
+            }
+            let (extents, next) = get_block_cache(block_id as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |extent_block: &ExtentBlock| {
+                    (extent_block.extents, extent_block.next)
+                });
+            for extent in extents.iter().take(rest) {
+                if !f(block_id, *extent) {
+                    return Ok(());
+                }
+            }
+            rest = rest.saturating_sub(BLOCK_EXTENT_COUNT);
+            block_id = next;
         }
-        total as u32
-    }
-    pub fn blocks_num_needed(&self, new_size: u32) -> u32 {
-        assert!(new_size >= self.size);
-        Self::total_blocks(new_size) - Self::total_blocks(self.size)
+        Ok(())
     }
-    pub fn get_block_id(&self, inner_id: u32, block_device: &Arc<dyn BlockDevice>) -> u32 {
-        let inner_id = inner_id as usize;
-        if inner_id < INODE_DIRECT_COUNT {
-            self.direct[inner_id]
-        } else if inner_id < INDIRECT1_BOUND {
-            get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
+    /// The extent block holding the extent `index`, which is not in the
+    /// inode.
+    fn extent_block_of(&self, index: usize, block_device: &Arc<dyn BlockDevice>) -> u32 {
+        let mut block_id = self.extent_block;
+        for _ in 0..(index - INODE_EXTENT_COUNT) / BLOCK_EXTENT_COUNT {
+            block_id = get_block_cache(block_id as usize, Arc::clone(block_device))
                 .lock()
-                .read(0, |indirect_block: &IndirectBlock| {
-                    indirect_block[inner_id - INODE_DIRECT_COUNT]
-                })
-        } else {
-            let last = inner_id - INDIRECT1_BOUND;
-            let indirect1 = get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+                .read(0, |extent_block: &ExtentBlock| extent_block.next);
+        }
+        block_id
+    }
+    fn extent(&self, index: usize, block_device: &Arc<dyn BlockDevice>) -> Extent {
+        if index < INODE_EXTENT_COUNT {
+            return self.extents[index];
+        }
+        get_block_cache(
+            self.extent_block_of(index, block_device) as usize,
+            Arc::clone(block_device),
+        )
+        .lock()
+        .read(0, |extent_block: &ExtentBlock| {
+            extent_block.extents[(index - INODE_EXTENT_COUNT) % BLOCK_EXTENT_COUNT]
+        })
+    }
+    fn set_extent(&mut self, index: usize, extent: Extent, block_device: &Arc<dyn BlockDevice>) {
+        if index < INODE_EXTENT_COUNT {
+            self.extents[index] = extent;
+            return;
+        }
+        get_block_cache(
+            self.extent_block_of(index, block_device) as usize,
+            Arc::clone(block_device),
+        )
+        .lock()
+        .modify(0, |extent_block: &mut ExtentBlock| {
+            extent_block.extents[(index - INODE_EXTENT_COUNT) % BLOCK_EXTENT_COUNT] = extent;
+        });
+    }
+    /// Append an extent, with a new extent block from `alloc` if the last
+    /// one is full.
+    fn push_extent(
+        &mut self,
+        extent: Extent,
+        alloc: &mut impl FnMut(u32) -> u32,
+        block_device: &Arc<dyn BlockDevice>,
+    ) {
+        let index = self.extent_count as usize;
+        if index >= INODE_EXTENT_COUNT && (index - INODE_EXTENT_COUNT) % BLOCK_EXTENT_COUNT == 0 {
+            let block_id = alloc(0);
+            if index == INODE_EXTENT_COUNT {
+                self.extent_block = block_id;
+            } else {
+                get_block_cache(
+                    self.extent_block_of(index - 1, block_device) as usize,
+                    Arc::clone(block_device),
+                )
                 .lock()
-                .read(0, |indirect2: &IndirectBlock| {
-                    indirect2[last / INODE_INDIRECT1_COUNT]
+                .modify(0, |extent_block: &mut ExtentBlock| {
+                    extent_block.next = block_id;
                 });
-            get_block_cache(indirect1 as usize, Arc::clone(block_device))
+            }
+        }
+        self.extent_count += 1;
+        self.set_extent(index, extent, block_device);
+    }
+    /// Remove the last extent, push its extent block to `freed` if it is
+    /// left empty.
+    fn pop_extent(&mut self, freed: &mut Vec<u32>, block_device: &Arc<dyn BlockDevice>) {
+        let index = self.extent_count as usize - 1;
+        if index >= INODE_EXTENT_COUNT && (index - INODE_EXTENT_COUNT) % BLOCK_EXTENT_COUNT == 0 {
+            freed.push(self.extent_block_of(index, block_device));
+            if index == INODE_EXTENT_COUNT {
+                self.extent_block = 0;
+            } else {
+                get_block_cache(
+                    self.extent_block_of(index - 1, block_device) as usize,
+                    Arc::clone(block_device),
+                )
                 .lock()
-                .read(0, |indirect1: &IndirectBlock| {
-                    indirect1[last % INODE_INDIRECT1_COUNT]
-                })
+                .modify(0, |extent_block: &mut ExtentBlock| {
+                    extent_block.next = 0;
+                });
+            }
+        } else {
+            self.set_extent(index, Extent::default(), block_device);
         }
+        self.extent_count -= 1;
+    }
+
+    /// The block of the `inner_id`th data block, and how many data blocks
+    /// from it are next to each other.
+    fn map(&self, inner_id: u32, block_device: &Arc<dyn BlockDevice>) -> (u32, u32) {
+        let mut skipped = 0;
+        let mut found = None;
+        self.walk_extents(
+            block_device,
+            |_| true,
+            |_, extent| {
+                if inner_id - skipped < extent.len {
+                    let offset = inner_id - skipped;
+                    found = Some((extent.start + offset, extent.len - offset));
+                    return false;
+                }
+                skipped += extent.len;
+                true
+            },
+        )
+        .unwrap();
+        found.expect("data block out of the extents")
     }
+    /// The runs of blocks next to each other of `count` data blocks from
+    /// the `inner_id`th one, each as the first block and the length.
+    pub fn block_runs(
+        &self,
+        mut inner_id: u32,
+        mut count: u32,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> Vec<(u32, u32)> {
+        let mut runs = Vec::new();
+        while count > 0 {
+            let (start, len) = self.map(inner_id, block_device);
+            let len = len.min(count);
+            runs.push((start, len));
+            inner_id += len;
+            count -= len;
+        }
+        runs
+    }
+    /// Grow to `new_size` with the blocks from `alloc`, which is given the
+    /// block wanted and returns it if it is free or another one, 0 is for
+    /// any. The block after the last one is wanted, so the last extent
+    /// grows as long as the blocks after it are free.
     pub fn increase_size(
         &mut self,
         new_size: u32,
-        new_blocks: Vec<u32>,
+        mut alloc: impl FnMut(u32) -> u32,
         block_device: &Arc<dyn BlockDevice>,
     ) {
-        let mut current_blocks = self.data_blocks();
+        let old_blocks = self.data_blocks();
         self.size = new_size;
-        let mut total_blocks = self.data_blocks();
-        let mut new_blocks = new_blocks.into_iter();
-        // fill direct
-        while current_blocks < total_blocks.min(INODE_DIRECT_COUNT as u32) {
-            self.direct[current_blocks as usize] = new_blocks.next().unwrap();
-            current_blocks += 1;
-        }
-        // alloc indirect1
-        if total_blocks > INODE_DIRECT_COUNT as u32 {
-            if current_blocks == INODE_DIRECT_COUNT as u32 {
-                self.indirect1 = new_blocks.next().unwrap();
-            }
-            current_blocks -= INODE_DIRECT_COUNT as u32;
-            total_blocks -= INODE_DIRECT_COUNT as u32;
-        } else {
+        let new_blocks = self.data_blocks();
+        if new_blocks <= old_blocks {
             return;
         }
-        // fill indirect1
-        get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
-            .lock()
-            .modify(0, |indirect1: &mut IndirectBlock| {
-                while current_blocks < total_blocks.min(INODE_INDIRECT1_COUNT as u32) {
-                    indirect1[current_blocks as usize] = new_blocks.next().unwrap();
-                    current_blocks += 1;
+        // the last extent is kept here while it grows
+        let mut last = match self.extent_count {
+            0 => None,
+            count => Some((
+                count as usize - 1,
+                self.extent(count as usize - 1, block_device),
+            )),
+        };
+        for _ in old_blocks..new_blocks {
+            let goal = last.map_or(0, |(_, extent)| extent.start + extent.len);
+            let block_id = alloc(goal);
+            match last.as_mut() {
+                Some((_, extent)) if block_id == goal && extent.len < u32::MAX => {
+                    extent.len += 1;
                 }
-            });
-        // alloc indirect2
-        if total_blocks > INODE_INDIRECT1_COUNT as u32 {
-            if current_blocks == INODE_INDIRECT1_COUNT as u32 {
-                self.indirect2 = new_blocks.next().unwrap();
-            }
-            current_blocks -= INODE_INDIRECT1_COUNT as u32;
-            total_blocks -= INODE_INDIRECT1_COUNT as u32;
-        } else {
-            return;
-        }
-        // fill indirect2 from (a0, b0) -> (a1, b1)
-        let mut a0 = current_blocks as usize / INODE_INDIRECT1_COUNT;
-        let mut b0 = current_blocks as usize % INODE_INDIRECT1_COUNT;
-        let a1 = total_blocks as usize / INODE_INDIRECT1_COUNT;
-        let b1 = total_blocks as usize % INODE_INDIRECT1_COUNT;
-        // alloc low-level indirect1
-        get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
-            .lock()
-            .modify(0, |indirect2: &mut IndirectBlock| {
-                while (a0 < a1) || (a0 == a1 && b0 < b1) {
-                    if b0 == 0 {
-                        indirect2[a0] = new_blocks.next().unwrap();
-                    }
-                    // fill current
-                    get_block_cache(indirect2[a0] as usize, Arc::clone(block_device))
-                        .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
-                            indirect1[b0] = new_blocks.next().unwrap();
-                        });
-                    // move to next
-                    b0 += 1;
-                    if b0 == INODE_INDIRECT1_COUNT {
-                        b0 = 0;
-                        a0 += 1;
+                _ => {
+                    if let Some((index, extent)) = last {
+                        self.set_extent(index, extent, block_device);
                     }
+                    let extent = Extent {
+                        start: block_id,
+                        len: 1,
+                    };
+                    self.push_extent(extent, &mut alloc, block_device);
+                    last = Some((self.extent_count as usize - 1, extent));
                 }
-            });
+            }
+        }
+        if let Some((index, extent)) = last {
+            self.set_extent(index, extent, block_device);
+        }
     }
 
-    /// Clear size to zero and return blocks that should be deallocated.
+    /// Shrink size to `new_size` and return blocks that should be deallocated,
+    /// including extent blocks which are no longer needed.
     ///
     /// We will clear the block contents to zero later.
-    pub fn clear_size(&mut self, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
+    pub fn decrease_size(
+        &mut self,
+        new_size: u32,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> Vec<u32> {
+        assert!(new_size <= self.size);
+        let mut excess = self.data_blocks() - Self::_data_blocks(new_size);
+        self.size = new_size;
         let mut v: Vec<u32> = Vec::new();
-        let mut data_blocks = self.data_blocks() as usize;
-        self.size = 0;
-        let mut current_blocks = 0usize;
-        // direct
-        while current_blocks < data_blocks.min(INODE_DIRECT_COUNT) {
-            v.push(self.direct[current_blocks]);
-            self.direct[current_blocks] = 0;
-            current_blocks += 1;
-        }
-        // indirect1 block
-        if data_blocks > INODE_DIRECT_COUNT {
-            v.push(self.indirect1);
-            data_blocks -= INODE_DIRECT_COUNT;
-            current_blocks = 0;
-        } else {
-            return v;
-        }
-        // indirect1
-        get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
-            .lock()
-            .modify(0, |indirect1: &mut IndirectBlock| {
-                while current_blocks < data_blocks.min(INODE_INDIRECT1_COUNT) {
-                    v.push(indirect1[current_blocks]);
-                    //indirect1[current_blocks] = 0;
-                    current_blocks += 1;
-                }
-            });
-        self.indirect1 = 0;
-        // indirect2 block
-        if data_blocks > INODE_INDIRECT1_COUNT {
-            v.push(self.indirect2);
-            data_blocks -= INODE_INDIRECT1_COUNT;
-        } else {
-            return v;
+        while excess > 0 {
+            let index = self.extent_count as usize - 1;
+            let mut extent = self.extent(index, block_device);
+            let cut = excess.min(extent.len);
+            extent.len -= cut;
+            excess -= cut;
+            v.extend(extent.start + extent.len..extent.start + extent.len + cut);
+            if extent.len > 0 {
+                self.set_extent(index, extent, block_device);
+            } else {
+                self.pop_extent(&mut v, block_device);
+            }
         }
-        // indirect2
-        assert!(data_blocks <= INODE_INDIRECT2_COUNT);
-        let a1 = data_blocks / INODE_INDIRECT1_COUNT;
-        let b1 = data_blocks % INODE_INDIRECT1_COUNT;
-        get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
-            .lock()
-            .modify(0, |indirect2: &mut IndirectBlock| {
-                // full indirect1 blocks
-                for entry in indirect2.iter_mut().take(a1) {
-                    v.push(*entry);
-                    get_block_cache(*entry as usize, Arc::clone(block_device))
-                        .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
-                            for entry in indirect1.iter() {
-                                v.push(*entry);
-                            }
-                        });
-                }
-                // last indirect1 block
-                if b1 > 0 {
-                    v.push(indirect2[a1]);
-                    get_block_cache(indirect2[a1] as usize, Arc::clone(block_device))
-                        .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
-                            for entry in indirect1.iter().take(b1) {
-                                v.push(*entry);
-                            }
-                        });
-                    //indirect2[a1] = 0;
-                }
-            });
-        self.indirect2 = 0;
         v
     }
+
+    /// Clear size to zero and return blocks that should be deallocated.
+    ///
+    /// We will clear the block contents to zero later.
+    pub fn clear_size(&mut self, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
+        self.decrease_size(0, block_device)
+    }
+
+    /// Call `f` with each data block and extent block of the inode, at most
+    /// as many data blocks as the size needs. Return the number of data
+    /// blocks mapped by the extents, or the first extent block for which
+    /// `valid` is false, which is not read.
+    pub fn for_each_block(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        valid: impl Fn(u32) -> bool,
+        mut f: impl FnMut(u32),
+    ) -> core::result::Result<usize, u32> {
+        let data_blocks = self.data_blocks() as usize;
+        let mut walked = 0;
+        let mut extent_block = 0;
+        self.walk_extents(block_device, valid, |block_id, extent| {
+            if block_id != extent_block {
+                extent_block = block_id;
+                f(block_id);
+            }
+            let len = (extent.len as usize).min(data_blocks - walked);
+            (0..len as u32).for_each(|i| f(extent.start.wrapping_add(i)));
+            walked += len;
+            // more extents than the size needs are still walked
+            true
+        })?;
+        Ok(walked)
+    }
     pub fn read_at(
         &self,
         offset: usize,
@@ -304,22 +427,24 @@ impl DiskInode {
         }
         let mut start_block = start / BLOCK_SZ;
         let mut read_size = 0usize;
+        // the blocks of the extent from the current one, mapped once
+        let mut run = (0, 0);
         loop {
+            if run.1 == 0 {
+                run = self.map(start_block as u32, block_device);
+            }
             // calculate end of current block
             let mut end_current_block = (start / BLOCK_SZ + 1) * BLOCK_SZ;
             end_current_block = end_current_block.min(end);
             // read and update read size
             let block_read_size = end_current_block - start;
             let dst = &mut buf[read_size..read_size + block_read_size];
-            get_block_cache(
-                self.get_block_id(start_block as u32, block_device) as usize,
-                Arc::clone(block_device),
-            )
-            .lock()
-            .read(0, |data_block: &DataBlock| {
-                let src = &data_block[start % BLOCK_SZ..start % BLOCK_SZ + block_read_size];
-                dst.copy_from_slice(src);
-            });
+            get_block_cache(run.0 as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |data_block: &DataBlock| {
+                    let src = &data_block[start % BLOCK_SZ..start % BLOCK_SZ + block_read_size];
+                    dst.copy_from_slice(src);
+                });
             read_size += block_read_size;
             // move to next block
             if end_current_block == end {
@@ -327,6 +452,7 @@ impl DiskInode {
             }
             start_block += 1;
             start = end_current_block;
+            run = (run.0 + 1, run.1 - 1);
         }
         read_size
     }
@@ -340,24 +466,29 @@ impl DiskInode {
         let mut start = offset;
         let end = (offset + buf.len()).min(self.size as usize);
         assert!(start <= end);
+        if start == end {
+            return 0;
+        }
         let mut start_block = start / BLOCK_SZ;
         let mut write_size = 0usize;
+        let mut run = (0, 0);
         loop {
+            if run.1 == 0 {
+                run = self.map(start_block as u32, block_device);
+            }
             // calculate end of current block
             let mut end_current_block = (start / BLOCK_SZ + 1) * BLOCK_SZ;
             end_current_block = end_current_block.min(end);
             // write and update write size
             let block_write_size = end_current_block - start;
-            get_block_cache(
-                self.get_block_id(start_block as u32, block_device) as usize,
-                Arc::clone(block_device),
-            )
-            .lock()
-            .modify(0, |data_block: &mut DataBlock| {
-                let src = &buf[write_size..write_size + block_write_size];
-                let dst = &mut data_block[start % BLOCK_SZ..start % BLOCK_SZ + block_write_size];
-                dst.copy_from_slice(src);
-            });
+            get_block_cache(run.0 as usize, Arc::clone(block_device))
+                .lock()
+                .modify(0, |data_block: &mut DataBlock| {
+                    let src = &buf[write_size..write_size + block_write_size];
+                    let dst =
+                        &mut data_block[start % BLOCK_SZ..start % BLOCK_SZ + block_write_size];
+                    dst.copy_from_slice(src);
+                });
             write_size += block_write_size;
             // move to next block
             if end_current_block == end {
@@ -365,6 +496,7 @@ impl DiskInode {
             }
             start_block += 1;
             start = end_current_block;
+            run = (run.0 + 1, run.1 - 1);
         }
         write_size
     }
@@ -403,6 +535,11 @@ impl DirEntry {
         let len = (0usize..).find(|i| self.name[*i] == 0).unwrap();
         core::str::from_utf8(&self.name[..len]).unwrap()
     }
+    /// Return None if the name is not terminated or not UTF-8.
+    pub fn checked_name(&self) -> Option<&str> {
+        let len = self.name.iter().position(|byte| *byte == 0)?;
+        core::str::from_utf8(&self.name[..len]).ok()
+    }
     pub fn inode_number(&self) -> u32 {
         self.inode_number
     }
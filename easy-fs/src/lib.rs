@@ -6,13 +6,19 @@ mod bitmap;
 mod block_cache;
 mod block_dev;
 mod efs;
+mod fsck;
 mod layout;
 mod vfs;
 
 pub const BLOCK_SZ: usize = 512;
 use bitmap::Bitmap;
-use block_cache::{block_cache_sync_all, get_block_cache};
+pub use block_cache::{
+    block_cache_count, block_cache_dirty_count, block_cache_release, block_cache_sync_all,
+    block_cache_write_back_oldest, get_block_cache, set_block_cache_capacity, BlockCache,
+};
+use block_cache::{block_cache_discard, block_cache_write_back};
 pub use block_dev::BlockDevice;
-pub use efs::EasyFileSystem;
+pub use efs::{EasyFileSystem, MountFlags, Quota};
+pub use fsck::FsckReport;
 use layout::*;
 pub use vfs::Inode;
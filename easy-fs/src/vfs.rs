@@ -1,13 +1,15 @@
 use super::{
-    block_cache_sync_all, get_block_cache, BlockDevice, DirEntry, DiskInode, DiskInodeType,
-    EasyFileSystem, DIRENT_SZ,
+    block_cache_discard, block_cache_write_back, get_block_cache, BlockDevice, DirEntry, DiskInode,
+    DiskInodeType, EasyFileSystem, MountFlags, BLOCK_SZ, DIRENT_SZ, NAME_LENGTH_LIMIT,
 };
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use spin::{Mutex, MutexGuard};
 
+/// An inode in memory, the same one for a file as long as it is in use.
 pub struct Inode {
+    inode_id: u32,
     block_id: usize,
     block_offset: usize,
     fs: Arc<Mutex<EasyFileSystem>>,
@@ -17,12 +19,14 @@ pub struct Inode {
 impl Inode {
     /// We should not acquire efs lock here.
     pub fn new(
+        inode_id: u32,
         block_id: u32,
         block_offset: usize,
         fs: Arc<Mutex<EasyFileSystem>>,
         block_device: Arc<dyn BlockDevice>,
     ) -> Self {
         Self {
+            inode_id,
             block_id: block_id as usize,
             block_offset,
             fs,
@@ -30,6 +34,17 @@ impl Inode {
         }
     }
 
+    /// A number of the inode unique in the file system, made of where it is
+    /// on the disk.
+    pub fn id(&self) -> usize {
+        self.block_id * BLOCK_SZ + self.block_offset
+    }
+
+    /// The file system of the inode, to change how it is mounted.
+    pub fn fs(&self) -> &Arc<Mutex<EasyFileSystem>> {
+        &self.fs
+    }
+
     fn read_disk_inode<V>(&self, f: impl FnOnce(&DiskInode) -> V) -> V {
         get_block_cache(self.block_id, Arc::clone(&self.block_device))
             .lock()
@@ -59,40 +74,94 @@ impl Inode {
         None
     }
 
+    /// Find a name in this directory, without reading the directory again
+    /// if it is found before.
     pub fn find(&self, name: &str) -> Option<Arc<Inode>> {
-        let fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| {
-            self.find_inode_id(name, disk_inode).map(|inode_id| {
-                let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
-                Arc::new(Self::new(
-                    block_id,
-                    block_offset,
-                    self.fs.clone(),
-                    self.block_device.clone(),
-                ))
-            })
-        })
+        let mut fs = self.fs.lock();
+        let cached = fs
+            .dentries
+            .get(&(self.inode_id, String::from(name)))
+            .copied();
+        let inode_id = match cached {
+            Some(inode_id) => inode_id,
+            None => {
+                let inode_id =
+                    self.read_disk_inode(|disk_inode| self.find_inode_id(name, disk_inode))?;
+                fs.cache_dentry(self.inode_id, name, inode_id);
+                inode_id
+            }
+        };
+        Some(self.get_inode(inode_id, &mut fs))
+    }
+
+    /// The inode in memory of `inode_id`, created if it is not in use.
+    fn get_inode(&self, inode_id: u32, fs: &mut MutexGuard<EasyFileSystem>) -> Arc<Inode> {
+        if let Some(inode) = fs.cached_inode(inode_id) {
+            return inode;
+        }
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        let inode = Arc::new(Self::new(
+            inode_id,
+            block_id,
+            block_offset,
+            self.fs.clone(),
+            self.block_device.clone(),
+        ));
+        fs.cache_inode(inode_id, &inode);
+        inode
     }
 
+    /// Grow to `new_size` unless the owner would go over its block limit,
+    /// return false in that case.
     fn increase_size(
         &self,
         new_size: u32,
         disk_inode: &mut DiskInode,
         fs: &mut MutexGuard<EasyFileSystem>,
-    ) {
+    ) -> bool {
         if new_size < disk_inode.size {
-            return;
+            return true;
         }
-        let blocks_needed = disk_inode.blocks_num_needed(new_size);
-        let mut v: Vec<u32> = Vec::new();
-        for _ in 0..blocks_needed {
-            v.push(fs.alloc_data());
+        let old_blocks = disk_inode.data_blocks();
+        let new_blocks = (new_size + BLOCK_SZ as u32 - 1) / BLOCK_SZ as u32;
+        if new_blocks - old_blocks > fs.blocks_left(disk_inode.uid) {
+            return false;
         }
-        disk_inode.increase_size(new_size, v, &self.block_device);
+        disk_inode.increase_size(
+            new_size,
+            |goal| fs.alloc_data_near(goal),
+            &self.block_device,
+        );
+        fs.charge(disk_inode.uid, new_blocks - old_blocks, 0);
+        true
     }
 
+    /// Create a file in this directory.
     pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::File)
+    }
+
+    /// Create a sub-directory in this directory.
+    pub fn create_dir(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::Directory)
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.read_disk_inode(|disk_inode| disk_inode.is_dir())
+    }
+
+    /// Fail if the current user would go over its inode limit, or the owner
+    /// of the directory over its block limit.
+    fn create_inode(&self, name: &str, type_: DiskInodeType) -> Option<Arc<Inode>> {
         let mut fs = self.fs.lock();
+        if fs.flags.contains(MountFlags::RDONLY) {
+            return None;
+        }
+        let uid = (fs.current_uid)();
+        if fs.inodes_left(uid) == 0 {
+            return None;
+        }
+        let now = (fs.clock)();
         let op = |root_inode: &mut DiskInode| {
             // assert it is a directory
             assert!(root_inode.is_dir());
@@ -102,40 +171,44 @@ impl Inode {
         if self.modify_disk_inode(op).is_some() {
             return None;
         }
+        // make room for the dirent first, nothing is allocated if it fails
+        let (file_count, grown) = self.modify_disk_inode(|root_inode| {
+            let file_count = (root_inode.size as usize) / DIRENT_SZ;
+            let new_size = (file_count + 1) * DIRENT_SZ;
+            let grown = self.increase_size(new_size as u32, root_inode, &mut fs);
+            (file_count, grown)
+        });
+        if !grown {
+            return None;
+        }
         // create a new file
-        // alloc a inode with an indirect block
         let new_inode_id = fs.alloc_inode();
         // initialize inode
         let (new_inode_block_id, new_inode_block_offset) = fs.get_disk_inode_pos(new_inode_id);
         get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
             .lock()
             .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
-                new_inode.initialize(DiskInodeType::File);
+                new_inode.initialize(type_);
+                new_inode.atime = now;
+                new_inode.mtime = now;
+                new_inode.uid = uid;
             });
+        fs.charge(uid, 0, 1);
         self.modify_disk_inode(|root_inode| {
             // append file in the dirent
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let new_size = (file_count + 1) * DIRENT_SZ;
-            // increase size
-            self.increase_size(new_size as u32, root_inode, &mut fs);
-            // write dirent
             let dirent = DirEntry::new(name, new_inode_id);
             root_inode.write_at(
                 file_count * DIRENT_SZ,
                 dirent.as_bytes(),
                 &self.block_device,
             );
+            root_inode.mtime = now;
         });
 
-        let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
-        block_cache_sync_all();
+        fs.cache_dentry(self.inode_id, name, new_inode_id);
+        fs.sync_if_needed();
         // return inode
-        Some(Arc::new(Self::new(
-            block_id,
-            block_offset,
-            self.fs.clone(),
-            self.block_device.clone(),
-        )))
+        Some(self.get_inode(new_inode_id, &mut fs))
         // release efs lock automatically by compiler
     }
 
@@ -156,31 +229,321 @@ impl Inode {
         })
     }
 
-    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+    /// Rename a file in this directory, fail if `old_name` does not exist
+    /// or `new_name` is already taken.
+    pub fn rename(&self, old_name: &str, new_name: &str) -> bool {
+        if new_name.len() > NAME_LENGTH_LIMIT {
+            return false;
+        }
+        let mut fs = self.fs.lock();
+        if fs.flags.contains(MountFlags::RDONLY) {
+            return false;
+        }
+        let now = (fs.clock)();
+        let renamed = self.modify_disk_inode(|root_inode| {
+            // assert it is a directory
+            assert!(root_inode.is_dir());
+            if self.find_inode_id(new_name, root_inode).is_some() {
+                return None;
+            }
+            let file_count = (root_inode.size as usize) / DIRENT_SZ;
+            let mut dirent = DirEntry::empty();
+            for i in 0..file_count {
+                assert_eq!(
+                    root_inode.read_at(DIRENT_SZ * i, dirent.as_bytes_mut(), &self.block_device,),
+                    DIRENT_SZ,
+                );
+                if dirent.name() == old_name {
+                    let inode_id = dirent.inode_number();
+                    let dirent = DirEntry::new(new_name, inode_id);
+                    root_inode.write_at(DIRENT_SZ * i, dirent.as_bytes(), &self.block_device);
+                    root_inode.mtime = now;
+                    return Some(inode_id);
+                }
+            }
+            None
+        });
+        if let Some(inode_id) = renamed {
+            fs.dentries.remove(&(self.inode_id, String::from(old_name)));
+            fs.cache_dentry(self.inode_id, new_name, inode_id);
+        }
+        fs.sync_if_needed();
+        renamed.is_some()
+    }
+
+    /// Remove a file, or an empty directory if `dir`, from this directory,
+    /// and free its inode and blocks. Fail if the file system is read-only,
+    /// `name` does not exist or is not of the kind asked, the directory is
+    /// not empty, or the file is in use, which is left whole then. The last
+    /// entry is moved to where it was.
+    pub fn unlink(&self, name: &str, dir: bool) -> bool {
+        let mut fs = self.fs.lock();
+        if fs.flags.contains(MountFlags::RDONLY) {
+            return false;
+        }
+        let found = self.read_disk_inode(|root_inode| {
+            // assert it is a directory
+            assert!(root_inode.is_dir());
+            let file_count = (root_inode.size as usize) / DIRENT_SZ;
+            let mut dirent = DirEntry::empty();
+            (0..file_count).find_map(|i| {
+                assert_eq!(
+                    root_inode.read_at(DIRENT_SZ * i, dirent.as_bytes_mut(), &self.block_device,),
+                    DIRENT_SZ,
+                );
+                (dirent.name() == name).then(|| (i, dirent.inode_number()))
+            })
+        });
+        let (slot, inode_id) = match found {
+            Some(found) => found,
+            None => return false,
+        };
+        if fs.cached_inode(inode_id).is_some() {
+            return false;
+        }
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        let inode_block = get_block_cache(block_id as usize, Arc::clone(&self.block_device));
+        let removable = inode_block
+            .lock()
+            .read(block_offset, |disk_inode: &DiskInode| {
+                disk_inode.is_dir() == dir && (!dir || disk_inode.size == 0)
+            });
+        if !removable {
+            return false;
+        }
+        let now = (fs.clock)();
+        self.modify_disk_inode(|root_inode| {
+            let last = (root_inode.size as usize) / DIRENT_SZ - 1;
+            if slot != last {
+                let mut dirent = DirEntry::empty();
+                root_inode.read_at(DIRENT_SZ * last, dirent.as_bytes_mut(), &self.block_device);
+                root_inode.write_at(DIRENT_SZ * slot, dirent.as_bytes(), &self.block_device);
+            }
+            let old_blocks = root_inode.data_blocks();
+            let data_blocks_dealloc =
+                root_inode.decrease_size((DIRENT_SZ * last) as u32, &self.block_device);
+            fs.refund(root_inode.uid, old_blocks - root_inode.data_blocks(), 0);
+            for data_block in data_blocks_dealloc.into_iter() {
+                fs.dealloc_data(data_block);
+            }
+            root_inode.mtime = now;
+        });
+        inode_block
+            .lock()
+            .modify(block_offset, |disk_inode: &mut DiskInode| {
+                fs.refund(disk_inode.uid, disk_inode.data_blocks(), 1);
+                let data_blocks_dealloc = disk_inode.clear_size(&self.block_device);
+                for data_block in data_blocks_dealloc.into_iter() {
+                    fs.dealloc_data(data_block);
+                }
+            });
+        fs.dealloc_inode(inode_id);
+        fs.dentries.remove(&(self.inode_id, String::from(name)));
+        fs.sync_if_needed();
+        true
+    }
+
+    pub fn size(&self) -> usize {
         let _fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| disk_inode.read_at(offset, buf, &self.block_device))
+        self.read_disk_inode(|disk_inode| disk_inode.size as usize)
     }
 
+    /// Return the access and modification times.
+    pub fn times(&self) -> (u32, u32) {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| (disk_inode.atime, disk_inode.mtime))
+    }
+
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let fs = self.fs.lock();
+        let (size, atime) = self.read_disk_inode(|disk_inode| {
+            (
+                disk_inode.read_at(offset, buf, &self.block_device),
+                disk_inode.atime,
+            )
+        });
+        // the inode is only dirtied once a second, and not written back
+        // until the next change even with `MountFlags::SYNC`
+        let now = (fs.clock)();
+        if !fs
+            .flags
+            .intersects(MountFlags::RDONLY | MountFlags::NOATIME)
+            && atime != now
+        {
+            self.modify_disk_inode(|disk_inode| disk_inode.atime = now);
+        }
+        size
+    }
+
+    /// Return 0 if the file system is read-only, or the owner would go over
+    /// its block limit.
     pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
         let mut fs = self.fs.lock();
+        if fs.flags.contains(MountFlags::RDONLY) {
+            return 0;
+        }
+        let now = (fs.clock)();
         let size = self.modify_disk_inode(|disk_inode| {
-            self.increase_size((offset + buf.len()) as u32, disk_inode, &mut fs);
+            if !self.increase_size((offset + buf.len()) as u32, disk_inode, &mut fs) {
+                return 0;
+            }
+            disk_inode.mtime = now;
             disk_inode.write_at(offset, buf, &self.block_device)
         });
-        block_cache_sync_all();
+        fs.sync_if_needed();
+        size
+    }
+
+    /// Read from `offset` straight from the device into the buffers, without
+    /// the block cache. The offset and the buffers must be of whole blocks,
+    /// which are read whole, but only the bytes in the file are counted.
+    pub fn read_direct(&self, offset: usize, bufs: &mut [&mut [u8]]) -> usize {
+        assert!(offset % BLOCK_SZ == 0 && bufs.iter().all(|buf| buf.len() % BLOCK_SZ == 0));
+        let fs = self.fs.lock();
+        let len: usize = bufs.iter().map(|buf| buf.len()).sum();
+        let (size, atime, runs) = self.read_disk_inode(|disk_inode| {
+            let end = (offset + len).min(disk_inode.size as usize);
+            if offset >= end {
+                return (0, disk_inode.atime, Vec::new());
+            }
+            let count = (end - offset + BLOCK_SZ - 1) / BLOCK_SZ;
+            let runs =
+                disk_inode.block_runs((offset / BLOCK_SZ) as u32, count as u32, &self.block_device);
+            (end - offset, disk_inode.atime, runs)
+        });
+        let mut bufs = bufs.iter_mut().map(|buf| &mut **buf);
+        let mut rest: &mut [u8] = &mut [];
+        let mut pieces: Vec<&mut [u8]> = Vec::new();
+        for (start, len) in runs {
+            // the device has to see what is changed in the cache
+            (start..start + len)
+                .for_each(|block_id| block_cache_write_back(block_id as usize, &self.block_device));
+            let mut left = len as usize * BLOCK_SZ;
+            while left > 0 {
+                while rest.is_empty() {
+                    rest = bufs.next().unwrap();
+                }
+                let mid = left.min(rest.len());
+                let (piece, tail) = core::mem::take(&mut rest).split_at_mut(mid);
+                left -= piece.len();
+                pieces.push(piece);
+                rest = tail;
+            }
+            self.block_device.read_blocks(start as usize, &mut pieces);
+            pieces.clear();
+        }
+        let now = (fs.clock)();
+        if size > 0
+            && !fs
+                .flags
+                .intersects(MountFlags::RDONLY | MountFlags::NOATIME)
+            && atime != now
+        {
+            self.modify_disk_inode(|disk_inode| disk_inode.atime = now);
+        }
         size
     }
 
+    /// Write the buffers from `offset` straight to the device, without the
+    /// block cache, growing the file like `write_at`. The offset and the
+    /// buffers must be of whole blocks.
+    pub fn write_direct(&self, offset: usize, bufs: &[&[u8]]) -> usize {
+        assert!(offset % BLOCK_SZ == 0 && bufs.iter().all(|buf| buf.len() % BLOCK_SZ == 0));
+        let mut fs = self.fs.lock();
+        let len: usize = bufs.iter().map(|buf| buf.len()).sum();
+        if fs.flags.contains(MountFlags::RDONLY) || len == 0 {
+            return 0;
+        }
+        let now = (fs.clock)();
+        let runs = self.modify_disk_inode(|disk_inode| {
+            if !self.increase_size((offset + len) as u32, disk_inode, &mut fs) {
+                return None;
+            }
+            disk_inode.mtime = now;
+            Some(disk_inode.block_runs(
+                (offset / BLOCK_SZ) as u32,
+                (len / BLOCK_SZ) as u32,
+                &self.block_device,
+            ))
+        });
+        let runs = match runs {
+            Some(runs) => runs,
+            None => return 0,
+        };
+        let mut bufs = bufs.iter().copied();
+        let mut rest: &[u8] = &[];
+        let mut pieces: Vec<&[u8]> = Vec::new();
+        for (start, len) in runs {
+            // the cached copies would be stale, and the new blocks zeroed
+            // in the cache would be written back over the data
+            (start..start + len)
+                .for_each(|block_id| block_cache_discard(block_id as usize, &self.block_device));
+            let mut left = len as usize * BLOCK_SZ;
+            while left > 0 {
+                while rest.is_empty() {
+                    rest = bufs.next().unwrap();
+                }
+                let (piece, tail) = rest.split_at(left.min(rest.len()));
+                left -= piece.len();
+                pieces.push(piece);
+                rest = tail;
+            }
+            self.block_device.write_blocks(start as usize, &pieces);
+            pieces.clear();
+        }
+        fs.sync_if_needed();
+        len
+    }
+
+    /// Set file size to `new_size`, filling with zeros when growing. Return
+    /// false if the file system is read-only, or the owner would go over its
+    /// block limit.
+    pub fn truncate(&self, new_size: usize) -> bool {
+        let mut fs = self.fs.lock();
+        if fs.flags.contains(MountFlags::RDONLY) {
+            return false;
+        }
+        let now = (fs.clock)();
+        let done = self.modify_disk_inode(|disk_inode| {
+            let size = disk_inode.size as usize;
+            if new_size >= size {
+                let grown = self.increase_size(new_size as u32, disk_inode, &mut fs);
+                if grown {
+                    disk_inode.mtime = now;
+                }
+                return grown;
+            }
+            disk_inode.mtime = now;
+            // clear the tail of the last block so that it reads as zeros if the file grows again
+            let tail_end = size.min((new_size + BLOCK_SZ - 1) / BLOCK_SZ * BLOCK_SZ);
+            let zeros = [0u8; BLOCK_SZ];
+            disk_inode.write_at(new_size, &zeros[..tail_end - new_size], &self.block_device);
+            let old_blocks = disk_inode.data_blocks();
+            let data_blocks_dealloc = disk_inode.decrease_size(new_size as u32, &self.block_device);
+            fs.refund(disk_inode.uid, old_blocks - disk_inode.data_blocks(), 0);
+            for data_block in data_blocks_dealloc.into_iter() {
+                fs.dealloc_data(data_block);
+            }
+            true
+        });
+        fs.sync_if_needed();
+        done
+    }
+
     pub fn clear(&self) {
         let mut fs = self.fs.lock();
+        if fs.flags.contains(MountFlags::RDONLY) {
+            return;
+        }
+        let now = (fs.clock)();
         self.modify_disk_inode(|disk_inode| {
-            let size = disk_inode.size;
+            disk_inode.mtime = now;
+            fs.refund(disk_inode.uid, disk_inode.data_blocks(), 0);
             let data_blocks_dealloc = disk_inode.clear_size(&self.block_device);
-            assert!(data_blocks_dealloc.len() == DiskInode::total_blocks(size) as usize);
             for data_block in data_blocks_dealloc.into_iter() {
                 fs.dealloc_data(data_block);
             }
         });
-        block_cache_sync_all();
+        fs.sync_if_needed();
     }
 }
@@ -0,0 +1,362 @@
+use super::{
+    get_block_cache, parse_fs_info, write_fs_info, BlockDevice, BootSector, DirEntry, Inode,
+    MountFlags, Sector, BLOCK_SZ, FAT_BAD, FAT_EOC, FAT_MASK, FS_INFO_UNKNOWN,
+};
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use easy_fs::block_cache_sync_all;
+use spin::Mutex;
+
+/// The sectors reserved before the FATs by `format`, for the boot sector,
+/// the FSInfo and their copies.
+const RESERVED_SECTORS: u16 = 32;
+const FS_INFO_SECTOR: u16 = 1;
+const BACKUP_BOOT_SECTOR: u16 = 6;
+const NUM_FATS: u8 = 2;
+
+pub struct FatFileSystem {
+    pub block_device: Arc<dyn BlockDevice>,
+    boot: BootSector,
+    fat_start: u32,
+    data_start: u32,
+    cluster_count: u32,
+    /// the FAT read and the only one written if they are not mirrored
+    active_fat: Option<u32>,
+    free_count: u32,
+    /// where to look for a free cluster first
+    next_free: u32,
+    pub flags: MountFlags,
+    /// the time in seconds of the times of files
+    pub clock: fn() -> u32,
+    /// the inodes in memory by where their entries are, one for each file
+    /// in use
+    pub(crate) inodes: BTreeMap<usize, Weak<Inode>>,
+}
+
+/// The inodes in memory are pruned of the ones not in use beyond this.
+const INODE_CACHE_SIZE: usize = 64;
+
+impl FatFileSystem {
+    /// Whether the device has FAT32 on it, which `open` asserts.
+    pub fn probe(block_device: &Arc<dyn BlockDevice>) -> bool {
+        block_device.num_blocks() > 0
+            && get_block_cache(0, Arc::clone(block_device))
+                .lock()
+                .read(0, |sector: &Sector| {
+                    BootSector::parse(sector).map_or(false, |boot| {
+                        boot.total_sectors as usize <= block_device.num_blocks()
+                    })
+                })
+    }
+
+    pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
+        let boot = get_block_cache(0, Arc::clone(&block_device))
+            .lock()
+            .read(0, |sector: &Sector| BootSector::parse(sector))
+            .expect("Error loading FAT32!");
+        let mut fs = Self::new(block_device, boot);
+        let fs_info = match fs.fs_info_sector() {
+            Some(sector) => get_block_cache(sector, Arc::clone(&fs.block_device))
+                .lock()
+                .read(0, parse_fs_info),
+            None => None,
+        };
+        match fs_info {
+            Some((free_count, next_free)) if free_count <= fs.cluster_count => {
+                fs.free_count = free_count;
+                if (2..fs.cluster_count + 2).contains(&next_free) {
+                    fs.next_free = next_free;
+                }
+            }
+            // not kept by the system writing last, counted in the FAT
+            _ => {
+                fs.free_count = (2..fs.cluster_count + 2)
+                    .filter(|cluster| fs.fat_entry(*cluster) == 0)
+                    .count() as u32;
+                fs.write_fs_info();
+            }
+        }
+        Arc::new(Mutex::new(fs))
+    }
+
+    fn new(block_device: Arc<dyn BlockDevice>, boot: BootSector) -> Self {
+        Self {
+            block_device,
+            boot,
+            fat_start: boot.reserved_sectors as u32,
+            data_start: boot.data_start(),
+            cluster_count: boot.cluster_count(),
+            active_fat: match boot.ext_flags & 0x80 {
+                0 => None,
+                _ => Some((boot.ext_flags & 0xF) as u32),
+            },
+            free_count: 0,
+            next_free: 2,
+            flags: MountFlags::SYNC,
+            clock: || 0,
+            inodes: BTreeMap::new(),
+        }
+    }
+
+    /// Make FAT32 of `total_sectors` on the device, laid out as Microsoft
+    /// specifies with two FATs and a copy of the boot sector.
+    pub fn format(block_device: Arc<dyn BlockDevice>, total_sectors: u32) -> Arc<Mutex<Self>> {
+        // the sectors of a cluster by the size of the disk, as in the
+        // table of the specification
+        let sectors_per_cluster: u8 = match total_sectors {
+            0..=532_480 => 1,
+            532_481..=16_777_216 => 8,
+            16_777_217..=33_554_432 => 16,
+            33_554_433..=67_108_864 => 32,
+            _ => 64,
+        };
+        let per_fat_sector = (256 * sectors_per_cluster as u32 + NUM_FATS as u32) / 2;
+        let fat_size =
+            (total_sectors - RESERVED_SECTORS as u32 + per_fat_sector - 1) / per_fat_sector;
+        let boot = BootSector {
+            bytes_per_sector: BLOCK_SZ as u16,
+            sectors_per_cluster,
+            reserved_sectors: RESERVED_SECTORS,
+            num_fats: NUM_FATS,
+            total_sectors,
+            fat_size,
+            ext_flags: 0,
+            root_cluster: 2,
+            fs_info: FS_INFO_SECTOR,
+            backup_boot: BACKUP_BOOT_SECTOR,
+            volume_id: 0x2023_1009,
+        };
+        // clear the reserved sectors and the FATs
+        for sector in 0..boot.data_start() {
+            get_block_cache(sector as usize, Arc::clone(&block_device))
+                .lock()
+                .modify(0, |sector: &mut Sector| sector.fill(0));
+        }
+        for sector in [0, BACKUP_BOOT_SECTOR] {
+            get_block_cache(sector as usize, Arc::clone(&block_device))
+                .lock()
+                .modify(0, |sector: &mut Sector| boot.write(sector));
+        }
+        let mut fs = Self::new(block_device, boot);
+        fs.free_count = fs.cluster_count;
+        for sector in [FS_INFO_SECTOR, BACKUP_BOOT_SECTOR + 1] {
+            get_block_cache(sector as usize, Arc::clone(&fs.block_device))
+                .lock()
+                .modify(0, |sector: &mut Sector| {
+                    write_fs_info(sector, FS_INFO_UNKNOWN, FS_INFO_UNKNOWN, true)
+                });
+        }
+        // the media type and the end of chains in the first two entries
+        fs.set_fat_entry(0, 0x0FFF_FFF8);
+        fs.set_fat_entry(1, FAT_MASK);
+        assert_eq!(fs.alloc_cluster(None), Some(boot.root_cluster));
+        block_cache_sync_all();
+        Arc::new(Mutex::new(fs))
+    }
+
+    pub fn root_inode(fs: &Arc<Mutex<Self>>) -> Inode {
+        let block_device = Arc::clone(&fs.lock().block_device);
+        Inode::new(0, Arc::clone(fs), block_device)
+    }
+
+    /// Change how it is mounted, changes are written back first.
+    pub fn remount(&mut self, flags: MountFlags) {
+        block_cache_sync_all();
+        self.flags = flags;
+    }
+
+    /// Write back changes if mounted with `MountFlags::SYNC`.
+    pub fn sync_if_needed(&self) {
+        if self.flags.contains(MountFlags::SYNC) {
+            block_cache_sync_all();
+        }
+    }
+
+    /// The bytes of a cluster.
+    pub fn cluster_size(&self) -> usize {
+        self.boot.sectors_per_cluster as usize * BLOCK_SZ
+    }
+
+    pub fn root_cluster(&self) -> u32 {
+        self.boot.root_cluster
+    }
+
+    /// The first sector of a cluster.
+    pub fn cluster_sector(&self, cluster: u32) -> usize {
+        (self.data_start + (cluster - 2) * self.boot.sectors_per_cluster as u32) as usize
+    }
+
+    /// Whether the cluster is one of data, which a chain may go on to.
+    fn is_data(&self, cluster: u32) -> bool {
+        (2..self.cluster_count + 2).contains(&cluster)
+    }
+
+    fn fs_info_sector(&self) -> Option<usize> {
+        match self.boot.fs_info {
+            0 | 0xFFFF => None,
+            sector if sector < self.boot.reserved_sectors => Some(sector as usize),
+            _ => None,
+        }
+    }
+
+    fn fat_pos(&self, fat: u32, cluster: u32) -> (usize, usize) {
+        let offset = cluster as usize * 4;
+        (
+            (self.fat_start + fat * self.boot.fat_size) as usize + offset / BLOCK_SZ,
+            offset % BLOCK_SZ,
+        )
+    }
+
+    fn fat_entry(&self, cluster: u32) -> u32 {
+        let (sector, offset) = self.fat_pos(self.active_fat.unwrap_or(0), cluster);
+        get_block_cache(sector, Arc::clone(&self.block_device))
+            .lock()
+            .read(offset, |entry: &[u8; 4]| {
+                u32::from_le_bytes(*entry) & FAT_MASK
+            })
+    }
+
+    /// Set the entry in every FAT, or in the active one, keeping the high 4
+    /// bits.
+    fn set_fat_entry(&mut self, cluster: u32, value: u32) {
+        let fats = match self.active_fat {
+            Some(fat) => fat..fat + 1,
+            None => 0..self.boot.num_fats as u32,
+        };
+        for fat in fats {
+            let (sector, offset) = self.fat_pos(fat, cluster);
+            get_block_cache(sector, Arc::clone(&self.block_device))
+                .lock()
+                .modify(offset, |entry: &mut [u8; 4]| {
+                    let old = u32::from_le_bytes(*entry);
+                    *entry = (old & !FAT_MASK | value & FAT_MASK).to_le_bytes();
+                });
+        }
+    }
+
+    /// The cluster after `cluster` in its chain, None at the end. A chain
+    /// going out of the clusters of data ends there.
+    pub fn next_cluster(&self, cluster: u32) -> Option<u32> {
+        let next = self.fat_entry(cluster);
+        if next >= FAT_EOC || !self.is_data(next) {
+            None
+        } else {
+            Some(next)
+        }
+    }
+
+    /// The clusters of the chain from `first`, none if it is 0. It stops at
+    /// a loop of the chain, as long as all the clusters.
+    pub fn chain(&self, first: u32) -> Vec<u32> {
+        let mut clusters = Vec::new();
+        let mut cluster = Some(first).filter(|cluster| self.is_data(*cluster));
+        while let Some(current) = cluster {
+            if clusters.len() >= self.cluster_count as usize {
+                break;
+            }
+            clusters.push(current);
+            cluster = self.next_cluster(current);
+        }
+        clusters
+    }
+
+    /// Allocate a cluster filled with zeros at the end of the chain ending
+    /// at `last`, or as a new chain. None if the disk is full.
+    pub fn alloc_cluster(&mut self, last: Option<u32>) -> Option<u32> {
+        if self.free_count == 0 {
+            return None;
+        }
+        let start = self.next_free;
+        let cluster = (start..self.cluster_count + 2)
+            .chain(2..start)
+            .find(|cluster| self.fat_entry(*cluster) == 0)?;
+        self.set_fat_entry(cluster, FAT_MASK);
+        if let Some(last) = last {
+            self.set_fat_entry(last, cluster);
+        }
+        let sector = self.cluster_sector(cluster);
+        for sector in sector..sector + self.boot.sectors_per_cluster as usize {
+            get_block_cache(sector, Arc::clone(&self.block_device))
+                .lock()
+                .modify(0, |sector: &mut Sector| sector.fill(0));
+        }
+        self.free_count -= 1;
+        self.next_free = if cluster + 1 < self.cluster_count + 2 {
+            cluster + 1
+        } else {
+            2
+        };
+        self.write_fs_info();
+        Some(cluster)
+    }
+
+    /// Free the clusters of a chain from `first`.
+    pub fn free_chain(&mut self, first: u32) {
+        for cluster in self.chain(first) {
+            if self.fat_entry(cluster) != FAT_BAD {
+                self.set_fat_entry(cluster, 0);
+                self.free_count += 1;
+            }
+        }
+        self.write_fs_info();
+    }
+
+    /// End the chain at `last`, whose clusters after it are freed.
+    pub fn cut_chain(&mut self, last: u32) {
+        if let Some(next) = self.next_cluster(last) {
+            self.set_fat_entry(last, FAT_MASK);
+            self.free_chain(next);
+        }
+    }
+
+    fn write_fs_info(&self) {
+        let (free_count, next_free) = (self.free_count, self.next_free);
+        if let Some(sector) = self.fs_info_sector() {
+            get_block_cache(sector, Arc::clone(&self.block_device))
+                .lock()
+                .modify(0, |sector: &mut Sector| {
+                    if parse_fs_info(sector).is_some() {
+                        write_fs_info(sector, free_count, next_free, false);
+                    }
+                });
+        }
+    }
+
+    /// The clusters not in use.
+    pub fn free_clusters(&self) -> u32 {
+        self.free_count
+    }
+
+    /// The inode in memory whose short entry is at `pos`, if it is in use.
+    pub(crate) fn cached_inode(&self, pos: usize) -> Option<Arc<Inode>> {
+        self.inodes.get(&pos).and_then(Weak::upgrade)
+    }
+
+    pub(crate) fn cache_inode(&mut self, pos: usize, inode: &Arc<Inode>) {
+        if self.inodes.len() >= INODE_CACHE_SIZE {
+            self.inodes.retain(|_, inode| inode.strong_count() > 0);
+        }
+        self.inodes.insert(pos, Arc::downgrade(inode));
+    }
+
+    /// The short entry of a file in use is moved from `old_pos` to `pos`.
+    pub(crate) fn move_inode(&mut self, old_pos: usize, pos: usize) {
+        if let Some(inode) = self.inodes.remove(&old_pos) {
+            self.inodes.insert(pos, inode);
+        }
+    }
+
+    /// Read the short entry at `pos`.
+    pub(crate) fn read_dirent(&self, pos: usize) -> DirEntry {
+        get_block_cache(pos / BLOCK_SZ, Arc::clone(&self.block_device))
+            .lock()
+            .read(pos % BLOCK_SZ, |dirent: &DirEntry| *dirent)
+    }
+
+    pub(crate) fn write_dirent(&self, pos: usize, dirent: &DirEntry) {
+        get_block_cache(pos / BLOCK_SZ, Arc::clone(&self.block_device))
+            .lock()
+            .modify(pos % BLOCK_SZ, |old: &mut DirEntry| *old = *dirent);
+    }
+}
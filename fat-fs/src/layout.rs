@@ -0,0 +1,390 @@
+use super::BLOCK_SZ;
+
+/// The size of an entry in directories, short or long.
+pub const DIRENT_SZ: usize = 32;
+/// The entries of a directory at most.
+pub const DIR_ENTRIES_LIMIT: usize = 65536;
+/// The end of a cluster chain, values from it up mark the end too.
+pub const FAT_EOC: u32 = 0x0FFF_FFF8;
+/// A bad cluster, never allocated.
+pub const FAT_BAD: u32 = 0x0FFF_FFF7;
+/// The low 28 bits of the entries of FAT32 are used, the rest are kept.
+pub const FAT_MASK: u32 = 0x0FFF_FFFF;
+/// The count of free clusters and the next free one are not known.
+pub const FS_INFO_UNKNOWN: u32 = 0xFFFF_FFFF;
+
+pub const ATTR_READ_ONLY: u8 = 0x01;
+pub const ATTR_HIDDEN: u8 = 0x02;
+pub const ATTR_SYSTEM: u8 = 0x04;
+pub const ATTR_VOLUME_ID: u8 = 0x08;
+pub const ATTR_DIRECTORY: u8 = 0x10;
+pub const ATTR_ARCHIVE: u8 = 0x20;
+pub const ATTR_LONG_NAME: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME_ID;
+
+/// The first byte of the name of an entry deleted.
+pub const DIRENT_FREE: u8 = 0xE5;
+/// The first byte of the name when it is 0xE5 indeed.
+pub const DIRENT_KANJI: u8 = 0x05;
+/// The bit of the order of the last long entry, which is the first one on
+/// the disk.
+pub const LONG_LAST: u8 = 0x40;
+/// The UCS-2 characters of a long name in an entry.
+pub const LONG_CHARS: usize = 13;
+/// The characters of a long name at most.
+pub const LONG_NAME_LIMIT: usize = 255;
+/// The bits of `nt_res` for a short name in lowercase, in the base and the
+/// extension.
+pub const NT_LOWER_BASE: u8 = 0x08;
+pub const NT_LOWER_EXT: u8 = 0x10;
+
+const LEAD_SIG: u32 = 0x4161_5252;
+const STRUC_SIG: u32 = 0x6141_7272;
+const TRAIL_SIG: u32 = 0xAA55_0000;
+
+pub type Sector = [u8; BLOCK_SZ];
+
+fn le16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn le32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+fn put16(bytes: &mut [u8], offset: usize, value: u16) {
+    bytes[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put32(bytes: &mut [u8], offset: usize, value: u32) {
+    bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// The BIOS parameter block of FAT32 in the boot sector, the fields in use.
+#[derive(Clone, Copy, Debug)]
+pub struct BootSector {
+    pub bytes_per_sector: u16,
+    pub sectors_per_cluster: u8,
+    pub reserved_sectors: u16,
+    pub num_fats: u8,
+    pub total_sectors: u32,
+    /// the sectors of each FAT
+    pub fat_size: u32,
+    /// whether the FATs are mirrored, and the one in use if not
+    pub ext_flags: u16,
+    pub root_cluster: u32,
+    /// the sector of the FSInfo, 0 or 0xFFFF if none
+    pub fs_info: u16,
+    /// the sector of the copy of the boot sector, 0 if none
+    pub backup_boot: u16,
+    pub volume_id: u32,
+}
+
+impl BootSector {
+    /// Read a boot sector of FAT32, None if it is not one or has sectors
+    /// other than blocks of the cache. FAT32 is told from FAT12 and FAT16
+    /// by the size of the FAT in its own field, as by Linux, rather than by
+    /// the count of clusters, so that small disks may have it.
+    pub fn parse(sector: &Sector) -> Option<Self> {
+        if sector[510] != 0x55 || sector[511] != 0xAA {
+            return None;
+        }
+        let total_sectors = match le16(sector, 19) {
+            0 => le32(sector, 32),
+            total_sectors => total_sectors as u32,
+        };
+        let boot = Self {
+            bytes_per_sector: le16(sector, 11),
+            sectors_per_cluster: sector[13],
+            reserved_sectors: le16(sector, 14),
+            num_fats: sector[16],
+            total_sectors,
+            fat_size: le32(sector, 36),
+            ext_flags: le16(sector, 40),
+            root_cluster: le32(sector, 44),
+            fs_info: le16(sector, 48),
+            backup_boot: le16(sector, 50),
+            volume_id: le32(sector, 67),
+        };
+        let valid = boot.bytes_per_sector as usize == BLOCK_SZ
+            && boot.sectors_per_cluster.is_power_of_two()
+            && boot.reserved_sectors > 0
+            && boot.num_fats > 0
+            // no fixed root directory and no 16-bit FAT size
+            && le16(sector, 17) == 0
+            && le16(sector, 22) == 0
+            && boot.fat_size > 0
+            && boot.root_cluster >= 2
+            && boot.data_start() < boot.total_sectors
+            && boot.cluster_count() > 0
+            // the FAT has an entry for every cluster
+            && (boot.cluster_count() as usize + 2) * 4 <= boot.fat_size as usize * BLOCK_SZ;
+        if valid {
+            Some(boot)
+        } else {
+            None
+        }
+    }
+
+    pub fn data_start(&self) -> u32 {
+        self.reserved_sectors as u32 + self.num_fats as u32 * self.fat_size
+    }
+
+    /// The clusters of data, numbered from 2.
+    pub fn cluster_count(&self) -> u32 {
+        self.total_sectors.saturating_sub(self.data_start()) / self.sectors_per_cluster as u32
+    }
+
+    /// Write it as a whole boot sector, with the fields not in use as
+    /// `mkfs.fat` writes them.
+    pub fn write(&self, sector: &mut Sector) {
+        sector.fill(0);
+        sector[..3].copy_from_slice(&[0xEB, 0x58, 0x90]);
+        sector[3..11].copy_from_slice(b"rCore   ");
+        put16(sector, 11, self.bytes_per_sector);
+        sector[13] = self.sectors_per_cluster;
+        put16(sector, 14, self.reserved_sectors);
+        sector[16] = self.num_fats;
+        // the media type of fixed disks
+        sector[21] = 0xF8;
+        put32(sector, 32, self.total_sectors);
+        put32(sector, 36, self.fat_size);
+        put16(sector, 40, self.ext_flags);
+        put32(sector, 44, self.root_cluster);
+        put16(sector, 48, self.fs_info);
+        put16(sector, 50, self.backup_boot);
+        // the drive number and the signature of the extended fields
+        sector[64] = 0x80;
+        sector[66] = 0x29;
+        put32(sector, 67, self.volume_id);
+        sector[71..82].copy_from_slice(b"NO NAME    ");
+        sector[82..90].copy_from_slice(b"FAT32   ");
+        sector[510] = 0x55;
+        sector[511] = 0xAA;
+    }
+}
+
+/// The free clusters counted and the hint where to look for one, None if
+/// the sector is not an FSInfo.
+pub fn parse_fs_info(sector: &Sector) -> Option<(u32, u32)> {
+    if le32(sector, 0) != LEAD_SIG || le32(sector, 484) != STRUC_SIG {
+        return None;
+    }
+    Some((le32(sector, 488), le32(sector, 492)))
+}
+
+/// Write an FSInfo with the free clusters and the hint, keeping nothing of
+/// the sector when `initialize`.
+pub fn write_fs_info(sector: &mut Sector, free_count: u32, next_free: u32, initialize: bool) {
+    if initialize {
+        sector.fill(0);
+        put32(sector, 0, LEAD_SIG);
+        put32(sector, 484, STRUC_SIG);
+        put32(sector, 508, TRAIL_SIG);
+    }
+    put32(sector, 488, free_count);
+    put32(sector, 492, next_free);
+}
+
+/// An entry of a directory as on the disk, a short one of a file or a long
+/// one holding a part of the name of the short one after it.
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+pub struct DirEntry(pub [u8; DIRENT_SZ]);
+
+impl DirEntry {
+    pub fn empty() -> Self {
+        Self([0; DIRENT_SZ])
+    }
+    /// A short entry of a new file or directory, with all the times `now`.
+    pub fn new(short_name: &[u8; 11], attr: u8, nt_res: u8, now: u32) -> Self {
+        let mut dirent = Self::empty();
+        dirent.0[..11].copy_from_slice(short_name);
+        dirent.0[11] = attr;
+        dirent.0[12] = nt_res;
+        let (date, time) = to_fat_time(now);
+        put16(&mut dirent.0, 14, time);
+        put16(&mut dirent.0, 16, date);
+        put16(&mut dirent.0, 18, date);
+        put16(&mut dirent.0, 22, time);
+        put16(&mut dirent.0, 24, date);
+        dirent
+    }
+    /// The long entry `order` from 1 of the name with the checksum of the
+    /// short name, holding the characters from `13 * (order - 1)`.
+    pub fn new_long(name: &[u16], order: usize, checksum: u8) -> Self {
+        let mut dirent = Self::empty();
+        let start = (order - 1) * LONG_CHARS;
+        dirent.0[0] = order as u8;
+        if start + LONG_CHARS >= name.len() {
+            dirent.0[0] |= LONG_LAST;
+        }
+        dirent.0[11] = ATTR_LONG_NAME;
+        dirent.0[13] = checksum;
+        for (i, offset) in LONG_OFFSETS.iter().enumerate() {
+            // terminated by a null, and padded with 0xFFFF after it
+            let ch = match name.get(start + i) {
+                Some(ch) => *ch,
+                None if start + i == name.len() => 0,
+                None => 0xFFFF,
+            };
+            put16(&mut dirent.0, *offset, ch);
+        }
+        dirent
+    }
+    /// No entries are in use from this one on.
+    pub fn is_end(&self) -> bool {
+        self.0[0] == 0
+    }
+    pub fn is_free(&self) -> bool {
+        self.0[0] == DIRENT_FREE || self.is_end()
+    }
+    pub fn mark_free(&mut self) {
+        self.0[0] = DIRENT_FREE;
+    }
+    pub fn attr(&self) -> u8 {
+        self.0[11]
+    }
+    pub fn is_long(&self) -> bool {
+        self.attr() & 0x3F == ATTR_LONG_NAME
+    }
+    pub fn is_volume_id(&self) -> bool {
+        self.attr() & ATTR_VOLUME_ID != 0
+    }
+    pub fn is_dir(&self) -> bool {
+        self.attr() & ATTR_DIRECTORY != 0
+    }
+    /// `.` or `..` of a directory.
+    pub fn is_dot(&self) -> bool {
+        self.0[0] == b'.'
+    }
+    pub fn short_name(&self) -> [u8; 11] {
+        let mut name = [0; 11];
+        name.copy_from_slice(&self.0[..11]);
+        if name[0] == DIRENT_KANJI {
+            name[0] = DIRENT_FREE;
+        }
+        name
+    }
+    pub fn set_short_name(&mut self, short_name: &[u8; 11], nt_res: u8) {
+        self.0[..11].copy_from_slice(short_name);
+        self.0[12] = nt_res;
+    }
+    pub fn nt_res(&self) -> u8 {
+        self.0[12]
+    }
+    pub fn first_cluster(&self) -> u32 {
+        (le16(&self.0, 20) as u32) << 16 | le16(&self.0, 26) as u32
+    }
+    pub fn set_first_cluster(&mut self, cluster: u32) {
+        put16(&mut self.0, 20, (cluster >> 16) as u16);
+        put16(&mut self.0, 26, cluster as u16);
+    }
+    pub fn size(&self) -> u32 {
+        le32(&self.0, 28)
+    }
+    pub fn set_size(&mut self, size: u32) {
+        put32(&mut self.0, 28, size);
+    }
+    /// The access time is a date only, so it is the midnight of the day.
+    pub fn atime(&self) -> u32 {
+        from_fat_time(le16(&self.0, 18), 0)
+    }
+    pub fn set_atime(&mut self, now: u32) {
+        put16(&mut self.0, 18, to_fat_time(now).0);
+    }
+    pub fn mtime(&self) -> u32 {
+        from_fat_time(le16(&self.0, 24), le16(&self.0, 22))
+    }
+    /// Set the modification time, and mark it to be archived.
+    pub fn set_mtime(&mut self, now: u32) {
+        let (date, time) = to_fat_time(now);
+        put16(&mut self.0, 22, time);
+        put16(&mut self.0, 24, date);
+        self.0[11] |= ATTR_ARCHIVE;
+    }
+    /// The order from 1 of a long entry, and whether it is the last one.
+    pub fn long_order(&self) -> (usize, bool) {
+        (
+            (self.0[0] & !LONG_LAST) as usize,
+            self.0[0] & LONG_LAST != 0,
+        )
+    }
+    pub fn long_checksum(&self) -> u8 {
+        self.0[13]
+    }
+    /// The characters of a long entry, up to the null.
+    pub fn long_chars(&self) -> impl Iterator<Item = u16> + '_ {
+        LONG_OFFSETS
+            .iter()
+            .map(move |offset| le16(&self.0, *offset))
+            .take_while(|ch| *ch != 0)
+    }
+}
+
+/// Where the characters of a long entry are.
+const LONG_OFFSETS: [usize; LONG_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+/// Days from 1970-01-01 to 1980-01-01, where FAT dates start.
+const FAT_EPOCH_DAYS: u32 = 3652;
+const SECS_PER_DAY: u32 = 86400;
+
+/// The days since 1970-01-01 of a date of the Gregorian calendar.
+fn days_from_civil(year: u32, month: u32, day: u32) -> u32 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let month = (month + 9) % 12;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The year, the month and the day of the days since 1970-01-01.
+fn civil_from_days(days: u32) -> (u32, u32, u32) {
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// The date and the time of FAT of the seconds since 1970, in UTC as there
+/// are no time zones, and from 1980 at the earliest.
+pub fn to_fat_time(secs: u32) -> (u16, u16) {
+    let secs = secs.max(FAT_EPOCH_DAYS * SECS_PER_DAY);
+    let (year, month, day) = civil_from_days(secs / SECS_PER_DAY);
+    let secs = secs % SECS_PER_DAY;
+    let date = ((year - 1980).min(127) << 9 | month << 5 | day) as u16;
+    let time = ((secs / 3600) << 11 | (secs % 3600 / 60) << 5 | (secs % 60 / 2)) as u16;
+    (date, time)
+}
+
+/// The seconds since 1970 of a date and a time of FAT, 0 if the date is
+/// not set.
+pub fn from_fat_time(date: u16, time: u16) -> u32 {
+    let (year, month, day) = (
+        1980 + (date >> 9) as u32,
+        ((date >> 5) & 0xF) as u32,
+        (date & 0x1F) as u32,
+    );
+    if !(1..=12).contains(&month) || day == 0 {
+        return 0;
+    }
+    let (hours, minutes, secs) = (
+        (time >> 11) as u32,
+        ((time >> 5) & 0x3F) as u32,
+        (time & 0x1F) as u32 * 2,
+    );
+    days_from_civil(year, month, day) * SECS_PER_DAY + hours * 3600 + minutes * 60 + secs
+}
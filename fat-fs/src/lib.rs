@@ -0,0 +1,16 @@
+//! FAT32 on the block devices and the block cache of easy-fs, to mount the
+//! disks and the SD cards formatted by other systems.
+
+#![no_std]
+
+extern crate alloc;
+
+mod fs;
+mod layout;
+mod name;
+mod vfs;
+
+use easy_fs::{get_block_cache, BlockDevice, MountFlags, BLOCK_SZ};
+pub use fs::FatFileSystem;
+use layout::*;
+pub use vfs::Inode;
@@ -0,0 +1,169 @@
+//! Short names of 8.3 characters in uppercase, and long names in UCS-2 of
+//! the long entries before them.
+
+use super::{LONG_NAME_LIMIT, NT_LOWER_BASE, NT_LOWER_EXT};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// The characters not allowed in long names, besides the controls.
+const INVALID_LONG: &str = "\"*/:<>?\\|";
+/// The characters not allowed in short names, besides those above.
+const INVALID_SHORT: &str = "+,;=[] .";
+
+/// The name in UCS-2 for the long entries, None if it cannot be a name.
+/// Names ending with dots or spaces are not taken, which Windows would
+/// strip.
+pub fn long_name(name: &str) -> Option<Vec<u16>> {
+    let invalid = name.is_empty()
+        || name.ends_with('.')
+        || name.ends_with(' ')
+        || name
+            .chars()
+            .any(|ch| (ch as u32) < 0x20 || INVALID_LONG.contains(ch));
+    if invalid {
+        return None;
+    }
+    let name: Vec<u16> = name.encode_utf16().collect();
+    if name.len() > LONG_NAME_LIMIT {
+        return None;
+    }
+    Some(name)
+}
+
+fn valid_short_char(ch: char) -> bool {
+    ch.is_ascii() && ch as u32 >= 0x20 && !INVALID_LONG.contains(ch) && !INVALID_SHORT.contains(ch)
+}
+
+/// Whether a part of a short name is all in one case, and in lowercase.
+fn one_case(part: &str) -> Option<bool> {
+    let lower = part.chars().any(|ch| ch.is_ascii_lowercase());
+    let upper = part.chars().any(|ch| ch.is_ascii_uppercase());
+    if lower && upper {
+        None
+    } else {
+        Some(lower)
+    }
+}
+
+/// The short name of a name which is 8.3 already, with the bits of
+/// `nt_res` for the parts in lowercase as Windows NT does, so that no long
+/// entries are needed. None if it is not.
+pub fn exact_short_name(name: &str) -> Option<([u8; 11], u8)> {
+    let (base, ext) = match name.split_once('.') {
+        Some((base, ext)) => (base, ext),
+        None => (name, ""),
+    };
+    if !(1..=8).contains(&base.len())
+        || ext.len() > 3
+        || name.ends_with('.')
+        || !base.chars().chain(ext.chars()).all(valid_short_char)
+    {
+        return None;
+    }
+    let mut nt_res = 0;
+    if one_case(base)? {
+        nt_res |= NT_LOWER_BASE;
+    }
+    if one_case(ext)? {
+        nt_res |= NT_LOWER_EXT;
+    }
+    let mut short_name = [b' '; 11];
+    short_name[..base.len()].copy_from_slice(base.as_bytes());
+    short_name[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+    short_name.make_ascii_uppercase();
+    Some((short_name, nt_res))
+}
+
+/// The short name a long name is based on, which is made unique with a
+/// numeric tail by `with_tail`.
+pub fn basis_name(name: &str) -> [u8; 11] {
+    let name = name.trim_start_matches('.');
+    let (base, ext) = match name.rfind('.') {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => (name, ""),
+    };
+    let short = |part: &str, len: usize| {
+        part.chars()
+            .filter(|ch| *ch != ' ' && *ch != '.')
+            .map(|ch| {
+                if valid_short_char(ch) {
+                    ch.to_ascii_uppercase() as u8
+                } else {
+                    b'_'
+                }
+            })
+            .take(len)
+            .collect::<Vec<u8>>()
+    };
+    let mut basis = [b' '; 11];
+    let base = short(base, 8);
+    if base.is_empty() {
+        basis[0] = b'_';
+    }
+    basis[..base.len()].copy_from_slice(&base);
+    let ext = short(ext, 3);
+    basis[8..8 + ext.len()].copy_from_slice(&ext);
+    basis
+}
+
+/// The basis name with the tail `~n` at the end of the base, cut short to
+/// make room for it.
+pub fn with_tail(basis: &[u8; 11], n: usize) -> [u8; 11] {
+    let mut tail = [0u8; 8];
+    let mut len = 0;
+    let mut n = n;
+    while n > 0 {
+        tail[7 - len] = b'0' + (n % 10) as u8;
+        n /= 10;
+        len += 1;
+    }
+    len += 1;
+    tail[8 - len] = b'~';
+    let base_len = basis[..8]
+        .iter()
+        .position(|byte| *byte == b' ')
+        .unwrap_or(8)
+        .min(8 - len);
+    let mut short_name = *basis;
+    short_name[base_len..base_len + len].copy_from_slice(&tail[8 - len..]);
+    short_name[base_len + len..8].fill(b' ');
+    short_name
+}
+
+/// The checksum of a short name in its long entries, which are not of it if
+/// they do not match.
+pub fn checksum(short_name: &[u8; 11]) -> u8 {
+    short_name
+        .iter()
+        .fold(0u8, |sum, byte| sum.rotate_right(1).wrapping_add(*byte))
+}
+
+/// The name of a file with no long entries, the parts lowercased as marked
+/// in `nt_res`. The bytes beyond ASCII are in a code page which is not
+/// known, they are taken as Latin-1.
+pub fn short_display_name(short_name: &[u8; 11], nt_res: u8) -> String {
+    let part = |bytes: &[u8], lower: bool| {
+        let len = bytes
+            .iter()
+            .rposition(|byte| *byte != b' ')
+            .map_or(0, |last| last + 1);
+        bytes[..len]
+            .iter()
+            .map(|byte| {
+                let ch = *byte as char;
+                if lower {
+                    ch.to_ascii_lowercase()
+                } else {
+                    ch
+                }
+            })
+            .collect::<String>()
+    };
+    let mut name = part(&short_name[..8], nt_res & NT_LOWER_BASE != 0);
+    let ext = part(&short_name[8..], nt_res & NT_LOWER_EXT != 0);
+    if !ext.is_empty() {
+        name.push('.');
+        name.push_str(ext.as_str());
+    }
+    name
+}
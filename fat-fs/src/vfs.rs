@@ -0,0 +1,650 @@
+use super::name::{
+    basis_name, checksum, exact_short_name, long_name, short_display_name, with_tail,
+};
+use super::{
+    get_block_cache, to_fat_time, BlockDevice, DirEntry, FatFileSystem, MountFlags, Sector,
+    ATTR_DIRECTORY, BLOCK_SZ, DIRENT_FREE, DIRENT_SZ, DIR_ENTRIES_LIMIT, LONG_CHARS,
+};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::{Mutex, MutexGuard};
+
+/// A file or a directory in memory, the same one for a file as long as it
+/// is in use. FAT has no inodes, a file is its short entry in the directory,
+/// which is read and written for its first cluster, its size and its times.
+pub struct Inode {
+    /// the number of it, where its short entry was when it was found
+    id: usize,
+    /// where its short entry is as a byte offset on the disk, which changes
+    /// when it is renamed. 0 for the root directory which has none.
+    pos: AtomicUsize,
+    fs: Arc<Mutex<FatFileSystem>>,
+    block_device: Arc<dyn BlockDevice>,
+}
+
+/// A file in a directory by its entries.
+struct DirItem {
+    name: String,
+    /// the index of the first entry of it, long or short
+    first_slot: usize,
+    /// the index of its short entry
+    slot: usize,
+    dirent: DirEntry,
+}
+
+/// The long name being read from the long entries before a short one.
+struct LongName {
+    first_slot: usize,
+    checksum: u8,
+    /// the order of the last long entry read, which is 1 when it is whole
+    order: usize,
+    /// the parts of the name from the end
+    parts: Vec<Vec<u16>>,
+}
+
+impl Inode {
+    /// We should not acquire the fs lock here.
+    pub fn new(
+        pos: usize,
+        fs: Arc<Mutex<FatFileSystem>>,
+        block_device: Arc<dyn BlockDevice>,
+    ) -> Self {
+        Self {
+            id: pos,
+            pos: AtomicUsize::new(pos),
+            fs,
+            block_device,
+        }
+    }
+
+    /// A number of the file unique in the file system while it is in use.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// The file system of the inode, to change how it is mounted.
+    pub fn fs(&self) -> &Arc<Mutex<FatFileSystem>> {
+        &self.fs
+    }
+
+    fn pos(&self) -> usize {
+        self.pos.load(Ordering::Relaxed)
+    }
+
+    fn is_root(&self) -> bool {
+        self.pos() == 0
+    }
+
+    /// The short entry of it, None for the root directory.
+    fn dirent(&self, fs: &FatFileSystem) -> Option<DirEntry> {
+        match self.pos() {
+            0 => None,
+            pos => Some(fs.read_dirent(pos)),
+        }
+    }
+
+    fn first_cluster(&self, fs: &FatFileSystem) -> u32 {
+        self.dirent(fs)
+            .map_or(fs.root_cluster(), |dirent| dirent.first_cluster())
+    }
+
+    fn is_dir_locked(&self, fs: &FatFileSystem) -> bool {
+        self.dirent(fs).map_or(true, |dirent| dirent.is_dir())
+    }
+
+    /// The size of a file, or of the clusters of a directory.
+    fn size_locked(&self, fs: &FatFileSystem) -> usize {
+        match self.dirent(fs) {
+            Some(dirent) if !dirent.is_dir() => dirent.size() as usize,
+            _ => fs.chain(self.first_cluster(fs)).len() * fs.cluster_size(),
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        let fs = self.fs.lock();
+        self.is_dir_locked(&fs)
+    }
+
+    pub fn size(&self) -> usize {
+        let fs = self.fs.lock();
+        self.size_locked(&fs)
+    }
+
+    /// Return the access and modification times, the access time of the
+    /// day only. The root directory has none.
+    pub fn times(&self) -> (u32, u32) {
+        let fs = self.fs.lock();
+        self.dirent(&fs)
+            .map_or((0, 0), |dirent| (dirent.atime(), dirent.mtime()))
+    }
+
+    /// The parts of sectors of the bytes from `offset` in the chain from
+    /// `first`, as far as it goes.
+    fn spans(
+        &self,
+        fs: &FatFileSystem,
+        first: u32,
+        offset: usize,
+        len: usize,
+    ) -> Vec<(usize, usize, usize)> {
+        let cluster_size = fs.cluster_size();
+        let mut spans = Vec::new();
+        let mut cluster = Some(first);
+        for _ in 0..offset / cluster_size {
+            cluster = cluster.and_then(|cluster| fs.next_cluster(cluster));
+        }
+        let (mut start, end) = (offset, offset + len);
+        while let Some(current) = cluster.filter(|_| start < end) {
+            let cluster_end = (start / cluster_size + 1) * cluster_size;
+            while start < end.min(cluster_end) {
+                let sector = fs.cluster_sector(current) + start % cluster_size / BLOCK_SZ;
+                let sector_offset = start % BLOCK_SZ;
+                let len = (BLOCK_SZ - sector_offset).min(end - start);
+                spans.push((sector, sector_offset, len));
+                start += len;
+            }
+            cluster = fs.next_cluster(current);
+        }
+        spans
+    }
+
+    fn read_data(&self, fs: &FatFileSystem, first: u32, offset: usize, buf: &mut [u8]) -> usize {
+        let mut read_size = 0;
+        for (sector, sector_offset, len) in self.spans(fs, first, offset, buf.len()) {
+            get_block_cache(sector, Arc::clone(&self.block_device))
+                .lock()
+                .read(0, |data: &Sector| {
+                    buf[read_size..read_size + len]
+                        .copy_from_slice(&data[sector_offset..sector_offset + len]);
+                });
+            read_size += len;
+        }
+        read_size
+    }
+
+    /// Write the bytes, or zeros if None, in the clusters allocated.
+    fn write_data(
+        &self,
+        fs: &FatFileSystem,
+        first: u32,
+        offset: usize,
+        len: usize,
+        buf: Option<&[u8]>,
+    ) {
+        let mut written = 0;
+        for (sector, sector_offset, len) in self.spans(fs, first, offset, len) {
+            get_block_cache(sector, Arc::clone(&self.block_device))
+                .lock()
+                .modify(0, |data: &mut Sector| {
+                    let data = &mut data[sector_offset..sector_offset + len];
+                    match buf {
+                        Some(buf) => data.copy_from_slice(&buf[written..written + len]),
+                        None => data.fill(0),
+                    }
+                });
+            written += len;
+        }
+    }
+
+    /// Grow the chain from `first`, 0 if none, to `clusters`, return the
+    /// first cluster. Nothing is allocated if the disk is too full.
+    fn grow_chain(&self, fs: &mut FatFileSystem, first: u32, clusters: usize) -> Option<u32> {
+        let chain = fs.chain(first);
+        if chain.len() >= clusters {
+            return Some(first);
+        }
+        if ((clusters - chain.len()) as u32) > fs.free_clusters() {
+            return None;
+        }
+        let mut last = chain.last().copied();
+        let mut first = first;
+        for _ in chain.len()..clusters {
+            let cluster = fs.alloc_cluster(last)?;
+            if last.is_none() {
+                first = cluster;
+            }
+            last = Some(cluster);
+        }
+        Some(first)
+    }
+
+    /// Fill with zeros the rest of the last cluster of a file after its
+    /// size, which may have been written before it was shrunk, as it grows.
+    fn zero_tail(&self, fs: &FatFileSystem, first: u32, old_size: usize, new_size: usize) {
+        let cluster_size = fs.cluster_size();
+        let end = ((old_size + cluster_size - 1) / cluster_size * cluster_size).min(new_size);
+        if old_size < end {
+            self.write_data(fs, first, old_size, end - old_size, None);
+        }
+    }
+
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let fs = self.fs.lock();
+        let size = self.size_locked(&fs);
+        if offset >= size {
+            return 0;
+        }
+        let len = buf.len().min(size - offset);
+        let read_size = self.read_data(&fs, self.first_cluster(&fs), offset, &mut buf[..len]);
+        // only the date is kept, so it is written once a day at most
+        if let Some(mut dirent) = self.dirent(&fs) {
+            let now = (fs.clock)();
+            if !fs
+                .flags
+                .intersects(MountFlags::RDONLY | MountFlags::NOATIME)
+                && to_fat_time(dirent.atime()).0 != to_fat_time(now).0
+            {
+                dirent.set_atime(now);
+                fs.write_dirent(self.pos(), &dirent);
+            }
+        }
+        read_size
+    }
+
+    /// Return 0 if the file system is read-only, it is a directory, or the
+    /// disk is too full. Files are 4GiB at most.
+    pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        let mut fs = self.fs.lock();
+        let mut dirent = match self.dirent(&fs) {
+            Some(dirent) if !dirent.is_dir() => dirent,
+            _ => return 0,
+        };
+        let end = offset + buf.len();
+        if fs.flags.contains(MountFlags::RDONLY) || buf.is_empty() || end > u32::MAX as usize {
+            return 0;
+        }
+        let old_size = dirent.size() as usize;
+        let cluster_size = fs.cluster_size();
+        let clusters = (end + cluster_size - 1) / cluster_size;
+        let first = match self.grow_chain(&mut fs, dirent.first_cluster(), clusters) {
+            Some(first) => first,
+            None => return 0,
+        };
+        self.zero_tail(&fs, first, old_size, offset);
+        self.write_data(&fs, first, offset, buf.len(), Some(buf));
+        dirent.set_first_cluster(first);
+        dirent.set_size(old_size.max(end) as u32);
+        dirent.set_mtime((fs.clock)());
+        fs.write_dirent(self.pos(), &dirent);
+        fs.sync_if_needed();
+        buf.len()
+    }
+
+    /// Set the size of a file, filling with zeros when growing, return false
+    /// if the file system is read-only, it is a directory, or the disk is too
+    /// full.
+    pub fn truncate(&self, new_size: usize) -> bool {
+        let mut fs = self.fs.lock();
+        let mut dirent = match self.dirent(&fs) {
+            Some(dirent) if !dirent.is_dir() => dirent,
+            _ => return false,
+        };
+        if fs.flags.contains(MountFlags::RDONLY) || new_size > u32::MAX as usize {
+            return false;
+        }
+        let old_size = dirent.size() as usize;
+        let cluster_size = fs.cluster_size();
+        let clusters = (new_size + cluster_size - 1) / cluster_size;
+        let mut first = dirent.first_cluster();
+        if new_size > old_size {
+            first = match self.grow_chain(&mut fs, first, clusters) {
+                Some(first) => first,
+                None => return false,
+            };
+            self.zero_tail(&fs, first, old_size, new_size);
+        } else if clusters == 0 {
+            fs.free_chain(first);
+            first = 0;
+        } else if let Some(last) = fs.chain(first).get(clusters - 1) {
+            fs.cut_chain(*last);
+        }
+        dirent.set_first_cluster(first);
+        dirent.set_size(new_size as u32);
+        dirent.set_mtime((fs.clock)());
+        fs.write_dirent(self.pos(), &dirent);
+        fs.sync_if_needed();
+        true
+    }
+
+    /// Where the entry `slot` of the directory is, as a byte offset on the
+    /// disk.
+    fn slot_pos(fs: &FatFileSystem, chain: &[u32], slot: usize) -> usize {
+        let offset = slot * DIRENT_SZ;
+        let cluster_size = fs.cluster_size();
+        fs.cluster_sector(chain[offset / cluster_size]) * BLOCK_SZ + offset % cluster_size
+    }
+
+    /// The files in this directory, with the clusters of it.
+    fn read_dir(&self, fs: &FatFileSystem) -> (Vec<u32>, Vec<DirItem>) {
+        let chain = fs.chain(self.first_cluster(fs));
+        let slots = chain.len() * fs.cluster_size() / DIRENT_SZ;
+        let mut items = Vec::new();
+        let mut long: Option<LongName> = None;
+        for slot in 0..slots {
+            let dirent = fs.read_dirent(Self::slot_pos(fs, &chain, slot));
+            if dirent.is_end() {
+                break;
+            }
+            if dirent.0[0] == DIRENT_FREE {
+                long = None;
+                continue;
+            }
+            if dirent.is_long() {
+                let (order, last) = dirent.long_order();
+                let chars = dirent.long_chars().collect();
+                long = match long.take() {
+                    _ if last => Some(LongName {
+                        first_slot: slot,
+                        checksum: dirent.long_checksum(),
+                        order,
+                        parts: Vec::from([chars]),
+                    }),
+                    Some(mut long)
+                        if order + 1 == long.order && dirent.long_checksum() == long.checksum =>
+                    {
+                        long.order = order;
+                        long.parts.push(chars);
+                        Some(long)
+                    }
+                    // an orphan of a long name, skipped
+                    _ => None,
+                };
+                continue;
+            }
+            let long = long.take();
+            if dirent.is_volume_id() || dirent.is_dot() {
+                continue;
+            }
+            let short_name = dirent.short_name();
+            let (name, first_slot) = match long {
+                Some(long) if long.order == 1 && long.checksum == checksum(&short_name) => {
+                    let name: Vec<u16> = long.parts.into_iter().rev().flatten().collect();
+                    (String::from_utf16_lossy(&name), long.first_slot)
+                }
+                _ => (short_display_name(&short_name, dirent.nt_res()), slot),
+            };
+            items.push(DirItem {
+                name,
+                first_slot,
+                slot,
+                dirent,
+            });
+        }
+        (chain, items)
+    }
+
+    /// Find `count` free entries in a row in this directory, growing it if
+    /// there are none. Return the clusters of it and the first entry.
+    fn alloc_slots(&self, fs: &mut FatFileSystem, count: usize) -> Option<(Vec<u32>, usize)> {
+        let mut chain = fs.chain(self.first_cluster(fs));
+        let slots_per_cluster = fs.cluster_size() / DIRENT_SZ;
+        let mut run = 0;
+        let mut end = false;
+        for slot in 0..chain.len() * slots_per_cluster {
+            let dirent = fs.read_dirent(Self::slot_pos(fs, &chain, slot));
+            end = end || dirent.is_end();
+            if end || dirent.is_free() {
+                run += 1;
+                if run == count {
+                    return Some((chain, slot + 1 - count));
+                }
+            } else {
+                run = 0;
+            }
+        }
+        // the clusters added are zeros, ending the directory
+        let start = chain.len() * slots_per_cluster - run;
+        if start + count > DIR_ENTRIES_LIMIT {
+            return None;
+        }
+        while chain.len() * slots_per_cluster < start + count {
+            let cluster = fs.alloc_cluster(chain.last().copied())?;
+            chain.push(cluster);
+        }
+        Some((chain, start))
+    }
+
+    /// A short name for `name` unlike the ones of `items` other than the
+    /// entry `except`, and whether long entries are needed with it.
+    fn short_name_for(
+        name: &str,
+        items: &[DirItem],
+        except: Option<usize>,
+    ) -> Option<([u8; 11], u8, bool)> {
+        let taken = |short_name: &[u8; 11]| {
+            items
+                .iter()
+                .any(|item| Some(item.slot) != except && item.dirent.short_name() == *short_name)
+        };
+        if let Some((short_name, nt_res)) = exact_short_name(name) {
+            if !taken(&short_name) {
+                return Some((short_name, nt_res, false));
+            }
+        }
+        let basis = basis_name(name);
+        (1..1_000_000)
+            .map(|n| with_tail(&basis, n))
+            .find(|short_name| !taken(short_name))
+            .map(|short_name| (short_name, 0, true))
+    }
+
+    /// The entries of a file named `name` with the short entry `dirent`, the
+    /// long ones first if needed.
+    fn name_entries(name: &[u16], dirent: DirEntry, with_long: bool) -> Vec<DirEntry> {
+        let mut entries = Vec::new();
+        if with_long {
+            let sum = checksum(&dirent.short_name());
+            let count = (name.len() + LONG_CHARS - 1) / LONG_CHARS;
+            for order in (1..=count).rev() {
+                entries.push(DirEntry::new_long(name, order, sum));
+            }
+        }
+        entries.push(dirent);
+        entries
+    }
+
+    /// The inode in memory of the short entry at `pos`, created if it is
+    /// not in use.
+    fn get_inode(&self, pos: usize, fs: &mut MutexGuard<FatFileSystem>) -> Arc<Inode> {
+        if let Some(inode) = fs.cached_inode(pos) {
+            return inode;
+        }
+        let inode = Arc::new(Self::new(pos, self.fs.clone(), self.block_device.clone()));
+        fs.cache_inode(pos, &inode);
+        inode
+    }
+
+    /// Find a name in this directory, ignoring the case as FAT does.
+    pub fn find(&self, name: &str) -> Option<Arc<Inode>> {
+        let mut fs = self.fs.lock();
+        if !self.is_dir_locked(&fs) {
+            return None;
+        }
+        let (chain, items) = self.read_dir(&fs);
+        let item = items
+            .iter()
+            .find(|item| item.name.eq_ignore_ascii_case(name))?;
+        let pos = Self::slot_pos(&fs, &chain, item.slot);
+        Some(self.get_inode(pos, &mut fs))
+    }
+
+    pub fn ls(&self) -> Vec<String> {
+        let fs = self.fs.lock();
+        let (_, items) = self.read_dir(&fs);
+        items.into_iter().map(|item| item.name).collect()
+    }
+
+    /// Set the modification time of this directory, which the root has not.
+    fn touch(&self, fs: &FatFileSystem, now: u32) {
+        if let Some(mut dirent) = self.dirent(fs) {
+            dirent.set_mtime(now);
+            fs.write_dirent(self.pos(), &dirent);
+        }
+    }
+
+    /// Create a file in this directory.
+    pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, false)
+    }
+
+    /// Create a sub-directory in this directory.
+    pub fn create_dir(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, true)
+    }
+
+    /// Fail if the name is taken ignoring the case, it cannot be a name of
+    /// FAT, or the disk is too full.
+    fn create_inode(&self, name: &str, dir: bool) -> Option<Arc<Inode>> {
+        let mut fs = self.fs.lock();
+        if fs.flags.contains(MountFlags::RDONLY) || !self.is_dir_locked(&fs) {
+            return None;
+        }
+        let long = long_name(name)?;
+        let (_, items) = self.read_dir(&fs);
+        if items
+            .iter()
+            .any(|item| item.name.eq_ignore_ascii_case(name))
+        {
+            return None;
+        }
+        let (short_name, nt_res, with_long) = Self::short_name_for(name, &items, None)?;
+        let now = (fs.clock)();
+        let attr = if dir { ATTR_DIRECTORY } else { 0 };
+        let mut dirent = DirEntry::new(&short_name, attr, nt_res, now);
+        let entries = Self::name_entries(&long, dirent, with_long);
+        let (chain, start) = self.alloc_slots(&mut fs, entries.len())?;
+        if dir {
+            let cluster = match fs.alloc_cluster(None) {
+                Some(cluster) => cluster,
+                None => return None,
+            };
+            let pos = fs.cluster_sector(cluster) * BLOCK_SZ;
+            let mut dot = DirEntry::new(b".          ", ATTR_DIRECTORY, 0, now);
+            dot.set_first_cluster(cluster);
+            fs.write_dirent(pos, &dot);
+            // the root is cluster 0 as the parent
+            let mut dotdot = DirEntry::new(b"..         ", ATTR_DIRECTORY, 0, now);
+            if !self.is_root() {
+                dotdot.set_first_cluster(self.first_cluster(&fs));
+            }
+            fs.write_dirent(pos + DIRENT_SZ, &dotdot);
+            dirent.set_first_cluster(cluster);
+        }
+        let entries = Self::name_entries(&long, dirent, with_long);
+        for (i, entry) in entries.iter().enumerate() {
+            fs.write_dirent(Self::slot_pos(&fs, &chain, start + i), entry);
+        }
+        self.touch(&fs, now);
+        fs.sync_if_needed();
+        let pos = Self::slot_pos(&fs, &chain, start + entries.len() - 1);
+        Some(self.get_inode(pos, &mut fs))
+    }
+
+    /// Remove a file, or an empty directory if `dir`, from this directory
+    /// and free its clusters. Fail if the file system is read-only, `name`
+    /// does not exist or is not of the kind asked, the directory is not
+    /// empty, or the file is in use, which is left whole then.
+    pub fn unlink(&self, name: &str, dir: bool) -> bool {
+        let mut fs = self.fs.lock();
+        if fs.flags.contains(MountFlags::RDONLY) || !self.is_dir_locked(&fs) {
+            return false;
+        }
+        let (chain, items) = self.read_dir(&fs);
+        let item = match items
+            .iter()
+            .find(|item| item.name.eq_ignore_ascii_case(name))
+        {
+            Some(item) => item,
+            None => return false,
+        };
+        let pos = Self::slot_pos(&fs, &chain, item.slot);
+        if item.dirent.is_dir() != dir || fs.cached_inode(pos).is_some() {
+            return false;
+        }
+        if dir {
+            // not registered, it is gone once it is checked
+            let inode = Self::new(pos, self.fs.clone(), self.block_device.clone());
+            if !inode.read_dir(&fs).1.is_empty() {
+                return false;
+            }
+        }
+        for slot in item.first_slot..item.slot + 1 {
+            let pos = Self::slot_pos(&fs, &chain, slot);
+            let mut dirent = fs.read_dirent(pos);
+            dirent.mark_free();
+            fs.write_dirent(pos, &dirent);
+        }
+        fs.free_chain(item.dirent.first_cluster());
+        self.touch(&fs, (fs.clock)());
+        fs.sync_if_needed();
+        true
+    }
+
+    /// Rename a file in this directory, fail if `old_name` does not exist,
+    /// `new_name` is taken by another file or cannot be a name of FAT. The
+    /// entries are rewritten in place if the new name takes no more of
+    /// them, or moved elsewhere in the directory.
+    pub fn rename(&self, old_name: &str, new_name: &str) -> bool {
+        let mut fs = self.fs.lock();
+        if fs.flags.contains(MountFlags::RDONLY) || !self.is_dir_locked(&fs) {
+            return false;
+        }
+        let long = match long_name(new_name) {
+            Some(long) => long,
+            None => return false,
+        };
+        let (chain, items) = self.read_dir(&fs);
+        let item = match items
+            .iter()
+            .find(|item| item.name.eq_ignore_ascii_case(old_name))
+        {
+            Some(item) => item,
+            None => return false,
+        };
+        if items
+            .iter()
+            .any(|other| other.slot != item.slot && other.name.eq_ignore_ascii_case(new_name))
+        {
+            return false;
+        }
+        let (short_name, nt_res, with_long) =
+            match Self::short_name_for(new_name, &items, Some(item.slot)) {
+                Some(short_name) => short_name,
+                None => return false,
+            };
+        let mut dirent = item.dirent;
+        dirent.set_short_name(&short_name, nt_res);
+        let entries = Self::name_entries(&long, dirent, with_long);
+        let old_pos = Self::slot_pos(&fs, &chain, item.slot);
+        let old_slots = item.first_slot..item.slot + 1;
+        let (chain, start) = if entries.len() <= old_slots.len() {
+            (chain, item.slot + 1 - entries.len())
+        } else {
+            match self.alloc_slots(&mut fs, entries.len()) {
+                Some(slots) => slots,
+                None => return false,
+            }
+        };
+        for slot in old_slots {
+            let pos = Self::slot_pos(&fs, &chain, slot);
+            let mut dirent = fs.read_dirent(pos);
+            dirent.mark_free();
+            fs.write_dirent(pos, &dirent);
+        }
+        for (i, entry) in entries.iter().enumerate() {
+            fs.write_dirent(Self::slot_pos(&fs, &chain, start + i), entry);
+        }
+        let pos = Self::slot_pos(&fs, &chain, start + entries.len() - 1);
+        if pos != old_pos {
+            fs.move_inode(old_pos, pos);
+            if let Some(inode) = fs.cached_inode(pos) {
+                inode.pos.store(pos, Ordering::Relaxed);
+            }
+        }
+        self.touch(&fs, (fs.clock)());
+        fs.sync_if_needed();
+        true
+    }
+}
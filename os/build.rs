@@ -1,6 +1,69 @@
+use rustc_demangle::demangle;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
 static TARGET_PATH: &str = "../user/target/riscv64gc-unknown-none-elf/release/";
 
 fn main() {
     println!("cargo:rerun-if-changed=../user/src/");
     println!("cargo:rerun-if-changed={}", TARGET_PATH);
+    // the console level of src/klog.rs
+    println!("cargo:rerun-if-env-changed=LOG");
+    gen_ksyms();
+}
+
+/// Generate the symbol table of src/ksyms.rs from `rust-nm -n` of the kernel
+/// linked before, whose path is in `KSYMS`. It is empty without one.
+fn gen_ksyms() {
+    println!("cargo:rerun-if-env-changed=KSYMS");
+    let mut symbols: Vec<(usize, String)> = Vec::new();
+    if let Ok(path) = env::var("KSYMS") {
+        println!("cargo:rerun-if-changed={}", path);
+        for line in fs::read_to_string(&path).unwrap_or_default().lines() {
+            let mut fields = line.split(' ');
+            let (addr, ty, name) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(addr), Some(ty), Some(name)) => (addr, ty, name),
+                _ => continue,
+            };
+            // functions only, without local labels of the assembler
+            if !matches!(ty, "t" | "T" | "w" | "W") || name.starts_with(".L") {
+                continue;
+            }
+            if let Ok(addr) = usize::from_str_radix(addr, 16) {
+                // the alternate format leaves out the hash
+                symbols.push((addr, format!("{:#}", demangle(name))));
+            }
+        }
+    }
+    symbols.sort_by_key(|(addr, _)| *addr);
+
+    let mut asm = String::new();
+    asm.push_str("    .section .rodata.ksyms, \"a\"\n    .p2align 3\n");
+    writeln!(
+        asm,
+        "    .globl ksyms_num\nksyms_num:\n    .quad {}",
+        symbols.len()
+    )
+    .unwrap();
+    asm.push_str("    .globl ksyms_addrs\nksyms_addrs:\n");
+    for (addr, _) in symbols.iter() {
+        writeln!(asm, "    .quad {:#x}", addr).unwrap();
+    }
+    // one more offset for the end of the last name
+    asm.push_str("    .globl ksyms_offsets\nksyms_offsets:\n");
+    let mut offset = 0;
+    for (_, name) in symbols.iter() {
+        writeln!(asm, "    .word {}", offset).unwrap();
+        offset += name.len();
+    }
+    writeln!(asm, "    .word {}", offset).unwrap();
+    asm.push_str("    .globl ksyms_names\nksyms_names:\n");
+    for (_, name) in symbols.iter() {
+        let escaped = name.replace('\\', "\\\\").replace('"', "\\\"");
+        writeln!(asm, "    .ascii \"{}\"", escaped).unwrap();
+    }
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("ksyms.S");
+    fs::write(out, asm).unwrap();
 }
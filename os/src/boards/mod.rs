@@ -0,0 +1,73 @@
+//! Everything specific to a board: the memory map, the devices and how their
+//! interrupts are routed.
+//!
+//! Each board implements [`Board`] in its own module, and the one being built
+//! for is re-exported here as `BoardImpl`. The rest of the kernel only uses
+//! the items below, so adding a board does not touch any other module.
+
+#[cfg(not(feature = "board_sifive_u"))]
+mod qemu;
+#[cfg(feature = "board_sifive_u")]
+mod sifive_u;
+
+use crate::drivers::block::AsyncBlockDevice;
+use crate::drivers::chardev::CharDevice;
+
+#[cfg(not(feature = "board_sifive_u"))]
+pub use qemu::QemuVirt as BoardImpl;
+#[cfg(feature = "board_sifive_u")]
+pub use sifive_u::SifiveU as BoardImpl;
+
+pub trait Board {
+    /// The frequency of the `time` CSR.
+    const CLOCK_FREQ: usize;
+    /// The end of the physical memory, which starts at the kernel image.
+    const MEMORY_END: usize;
+    /// The device ranges identically mapped into the kernel space.
+    const MMIO: &'static [(usize, usize)];
+    /// The hart running the kernel.
+    const BOOT_HART: usize;
+    /// The CLINT programmed by the kernel for timers and IPIs, None to go
+    /// through the SBI. The firmware has to allow it, see `drivers::clint`.
+    const CLINT: Option<usize>;
+    /// The Goldfish RTC for the wall clock time, None if there is no real
+    /// time clock and the time starts from the Unix epoch at boot.
+    const RTC: Option<usize>;
+
+    type BlockDevice: AsyncBlockDevice;
+    type CharDevice: CharDevice + Send + Sync;
+
+    fn block_device() -> Self::BlockDevice;
+    fn char_device() -> Self::CharDevice;
+    /// Probe the devices and route their interrupts to the supervisor mode.
+    fn device_init();
+    /// Handle an external interrupt.
+    fn irq_handler();
+}
+
+pub const CLOCK_FREQ: usize = <BoardImpl as Board>::CLOCK_FREQ;
+pub const MEMORY_END: usize = <BoardImpl as Board>::MEMORY_END;
+pub const MMIO: &[(usize, usize)] = <BoardImpl as Board>::MMIO;
+pub const BOOT_HART: usize = <BoardImpl as Board>::BOOT_HART;
+pub const CLINT: Option<usize> = <BoardImpl as Board>::CLINT;
+pub const RTC: Option<usize> = <BoardImpl as Board>::RTC;
+
+pub type BlockDeviceImpl = <BoardImpl as Board>::BlockDevice;
+pub type CharDeviceImpl = <BoardImpl as Board>::CharDevice;
+
+pub fn block_device() -> BlockDeviceImpl {
+    BoardImpl::block_device()
+}
+
+pub fn char_device() -> CharDeviceImpl {
+    BoardImpl::char_device()
+}
+
+pub fn device_init() {
+    BoardImpl::device_init();
+}
+
+pub fn irq_handler() {
+    crate::random::add_interrupt_randomness();
+    BoardImpl::irq_handler();
+}
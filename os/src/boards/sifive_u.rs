@@ -0,0 +1,77 @@
+//! The `sifive_u` machine of QEMU, which models the FU540 of HiFive Unleashed.
+//!
+//! Hart 0 is an E51 monitor core without the supervisor mode, so the kernel
+//! runs on hart 1 with `-smp 2`. There is no virtio device, the file system
+//! image is loaded into the memory by QEMU and used as a RAM disk.
+
+use super::Board;
+use crate::drivers::block::RamDisk;
+use crate::drivers::chardev::{CharDevice, SifiveUart, UART};
+use crate::drivers::plic::{IntrTargetPriority, PLIC};
+
+pub const SIFIVE_CLINT: usize = 0x200_0000;
+pub const SIFIVE_PLIC: usize = 0xC00_0000;
+pub const SIFIVE_UART0: usize = 0x1001_0000;
+/// where `-device loader` puts `fs.img`, out of the memory of the kernel
+pub const RAM_DISK_BASE: usize = 0x9000_0000;
+pub const RAM_DISK_SIZE: usize = 32 * 2048 * 512;
+
+const UART0_IRQ: usize = 4;
+
+pub struct SifiveU;
+
+impl Board for SifiveU {
+    const CLOCK_FREQ: usize = 1000000;
+    const MEMORY_END: usize = 0x88000000;
+    const MMIO: &'static [(usize, usize)] = &[
+        (0x2000000, 0x10000),           // core local interrupter (CLINT)
+        (0xc000000, 0x4000000),         // PLIC
+        (0x10010000, 0x1000),           // UART0
+        (RAM_DISK_BASE, RAM_DISK_SIZE), // the RAM disk, not a device
+    ];
+    /// the first U54, started by the SBI
+    const BOOT_HART: usize = 1;
+    const CLINT: Option<usize> = if cfg!(feature = "clint-timer") {
+        Some(SIFIVE_CLINT)
+    } else {
+        None
+    };
+    const RTC: Option<usize> = None;
+
+    type BlockDevice = RamDisk<RAM_DISK_BASE, RAM_DISK_SIZE>;
+    type CharDevice = SifiveUart<SIFIVE_UART0>;
+
+    fn block_device() -> Self::BlockDevice {
+        RamDisk::new()
+    }
+
+    fn char_device() -> Self::CharDevice {
+        SifiveUart::new()
+    }
+
+    fn device_init() {
+        use riscv::register::sie;
+        let mut plic = unsafe { PLIC::with_monitor_core(SIFIVE_PLIC) };
+        let supervisor = IntrTargetPriority::Supervisor;
+        let machine = IntrTargetPriority::Machine;
+        plic.set_threshold(Self::BOOT_HART, supervisor, 0);
+        plic.set_threshold(Self::BOOT_HART, machine, 1);
+        plic.enable(Self::BOOT_HART, supervisor, UART0_IRQ);
+        plic.set_priority(UART0_IRQ, 1);
+        unsafe {
+            sie::set_sext();
+        }
+    }
+
+    fn irq_handler() {
+        let mut plic = unsafe { PLIC::with_monitor_core(SIFIVE_PLIC) };
+        let intr_src_id = plic.claim(Self::BOOT_HART, IntrTargetPriority::Supervisor);
+        match intr_src_id as usize {
+            UART0_IRQ => UART.handle_irq(),
+            // claimed by another context already
+            0 => return,
+            _ => log_ratelimited!("[kernel] unsupported IRQ {}", intr_src_id),
+        }
+        plic.complete(Self::BOOT_HART, IntrTargetPriority::Supervisor, intr_src_id);
+    }
+}
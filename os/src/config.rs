@@ -1,13 +1,34 @@
 #[allow(unused)]
 
 pub const USER_STACK_SIZE: usize = 4096 * 2;
+/// The stack of the main thread grows on demand up to this size.
+pub const MAIN_STACK_MAX_SIZE: usize = 4096 * 64;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
 pub const KERNEL_HEAP_SIZE: usize = 0x100_0000;
-pub const MEMORY_END: usize = 0x88000000;
+/// The blocks of the file systems cached at most, 256 KiB.
+pub const BLOCK_CACHE_SIZE: usize = 512;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
 
+/// Typed in a row on the console, it makes the next key a magic SysRq
+/// command like a break does, Ctrl-O twice by default.
+pub const SYSRQ_SEQUENCE: &[u8] = b"\x0f\x0f";
+
+/// The end of the user space of SV39.
+pub const USER_SPACE_END: usize = 1 << 38;
+/// The longest string a syscall takes from the user, the `\0` included, like
+/// PATH_MAX of Linux. It holds for the paths, the arguments and the names.
+pub const USER_STRING_MAX: usize = 4096;
+/// The most arguments exec and spawn take from the user, so are the actions
+/// of spawn.
+pub const USER_ARGS_MAX: usize = 256;
+/// The user stacks of the threads are below this, from the end of the
+/// program on.
+pub const USER_STACKS_END: usize = 0x10_0000_0000;
+/// `sys_mmap` places the mappings without an address from here on.
+pub const MMAP_BASE: usize = 0x30_0000_0000;
+
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 pub const TRAP_CONTEXT_BASE: usize = TRAMPOLINE - PAGE_SIZE;
 
-pub use crate::board::{CLOCK_FREQ, MMIO};
+pub use crate::boards::{BOOT_HART, CLINT, CLOCK_FREQ, MEMORY_END, MMIO, RTC};
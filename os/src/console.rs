@@ -1,20 +1,280 @@
+//! The kernel console on the UART. A print is put in the print ring under
+//! the console lock, a line at a time, so the prints of the harts and tasks
+//! do not interleave. The console thread hands the ring to the UART in
+//! chunks, which the UART sends from its interrupt handler, so a task
+//! printing much is not held up by the baud rate. Before the thread runs,
+//! or when the ring is full, the print writes to the UART itself. Once the
+//! kernel panics, the prints go to the SBI without any lock, which the
+//! panicked code may hold.
+//!
+//! The diagnostics of interrupts go through `log_ratelimited!`, so a device
+//! flooding the kernel with interrupts cannot keep the console busy.
+//!
+//! The messages with a level go through `error!`, `warn!`, `info!` and
+//! `debug!`, which print them up to the console level of `klog` and keep
+//! them in the log anyway.
+
 use crate::drivers::chardev::CharDevice;
 use crate::drivers::chardev::UART;
+use crate::klog::{self, Level};
+use crate::sbi::console_putchar;
+use crate::sync::{block_on_yielding, UPIntrFreeCell, UPIntrRefMut};
+use crate::task::spawn_kernel_thread;
+use crate::timer::get_time_ms;
+use alloc::collections::VecDeque;
 use core::fmt::{self, Write};
+use core::future::poll_fn;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::task::{Context, Poll, Waker};
+use lazy_static::*;
+use riscv::register::sstatus;
 
-struct Stdout;
+/// The bytes buffered before they are put in the print ring.
+const LINE_LEN: usize = 128;
+/// The bytes in the print ring at most.
+const PRINT_RING_SIZE: usize = 16 * 1024;
+/// The console thread hands at most this many bytes to the UART at a time.
+const CHUNK_LEN: usize = 1024;
+/// At most `RATELIMIT_BURST` messages of a `log_ratelimited!` are printed
+/// in `RATELIMIT_INTERVAL_MS`, like the default of Linux.
+const RATELIMIT_INTERVAL_MS: usize = 5000;
+const RATELIMIT_BURST: usize = 10;
 
-impl Write for Stdout {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            UART.write(c as u8);
+struct Console {
+    line: [u8; LINE_LEN],
+    len: usize,
+    /// the lines printed and not handed to the UART yet
+    ring: VecDeque<u8>,
+    /// the console thread waiting for lines
+    waker: Option<Waker>,
+}
+
+impl Console {
+    fn push(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.line[self.len] = byte;
+            self.len += 1;
+            if byte == b'\n' || self.len == LINE_LEN {
+                self.flush();
+            }
         }
+    }
+
+    fn flush(&mut self) {
+        let line = &self.line[..self.len];
+        if CONSOLED_RUNNING.load(Ordering::Acquire)
+            && self.ring.len() + line.len() <= PRINT_RING_SIZE
+        {
+            self.ring.extend(line);
+        } else {
+            // the lines in the ring go first
+            for byte in self.ring.drain(..).chain(line.iter().copied()) {
+                UART.write(byte);
+            }
+        }
+        self.len = 0;
+    }
+
+    /// Hand a chunk of the ring to the UART, or wait until there are lines
+    /// and the UART has room for them.
+    fn poll_hand_over(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let (chunk, _) = self.ring.as_slices();
+        if chunk.is_empty() {
+            self.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let len = chunk.len().min(CHUNK_LEN);
+        let queued = core::task::ready!(UART.poll_write(&chunk[..len], cx));
+        self.ring.drain(..queued);
+        Poll::Ready(())
+    }
+}
+
+lazy_static! {
+    static ref CONSOLE: UPIntrFreeCell<Console> = unsafe {
+        UPIntrFreeCell::new(Console {
+            line: [0; LINE_LEN],
+            len: 0,
+            ring: VecDeque::new(),
+            waker: None,
+        })
+    };
+}
+
+/// Release the console, and wake the console thread up for the lines left
+/// in the ring. It is woken up out of the lock, which it takes then.
+fn release(mut console: UPIntrRefMut<'_, Console>) {
+    let waker = match console.ring.is_empty() {
+        true => None,
+        false => console.waker.take(),
+    };
+    drop(console);
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
+
+static PANICKING: AtomicBool = AtomicBool::new(false);
+/// Whether the console thread runs, the prints go through the ring then.
+static CONSOLED_RUNNING: AtomicBool = AtomicBool::new(false);
+
+struct Stdout<'a>(&'a mut Console);
+
+impl Write for Stdout<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.push(s.as_bytes());
+        crate::klog::record(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Writes to the kernel log only.
+struct KlogStdout;
+
+impl Write for KlogStdout {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        klog::record(s.as_bytes());
+        Ok(())
+    }
+}
+
+struct EmergencyStdout;
+
+impl Write for EmergencyStdout {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        s.bytes().for_each(console_putchar);
         Ok(())
     }
 }
 
+/// Print the output of a process, which is not kept in the kernel log.
+pub fn print_user(s: &str) {
+    if PANICKING.load(Ordering::Acquire) {
+        return;
+    }
+    let mut console = CONSOLE.exclusive_access();
+    console.push(s.as_bytes());
+    console.flush();
+    release(console);
+}
+
+/// Echo the input of the console after what is printed before, which may
+/// not be UTF-8.
+pub fn echo(bytes: &[u8]) {
+    if PANICKING.load(Ordering::Acquire) {
+        return;
+    }
+    let mut console = CONSOLE.exclusive_access();
+    console.push(bytes);
+    console.flush();
+    release(console);
+}
+
 pub fn print(args: fmt::Arguments) {
-    Stdout.write_fmt(args).unwrap();
+    if PANICKING.load(Ordering::Acquire) {
+        EmergencyStdout.write_fmt(args).unwrap();
+        return;
+    }
+    let mut console = CONSOLE.exclusive_access();
+    Stdout(&mut console).write_fmt(args).unwrap();
+    // a print without a newline, like a prompt
+    console.flush();
+    release(console);
+}
+
+/// Keep a message of `level` in the log, print it if the console level
+/// allows.
+pub fn log(level: Level, args: fmt::Arguments) {
+    if PANICKING.load(Ordering::Acquire) {
+        if klog::printed(level) {
+            EmergencyStdout.write_fmt(args).unwrap();
+        }
+        return;
+    }
+    // the prefix and the message stay together in the log
+    let mut console = CONSOLE.exclusive_access();
+    klog::record(level.prefix().as_bytes());
+    if klog::printed(level) {
+        Stdout(&mut console).write_fmt(args).unwrap();
+        console.flush();
+    } else {
+        KlogStdout.write_fmt(args).unwrap();
+    }
+    release(console);
+}
+
+/// The kernel panics, print with the SBI from now on. What is printed
+/// before is sent first, unless the console or the UART is held, which the
+/// panicked code may do.
+pub fn enter_emergency() {
+    PANICKING.store(true, Ordering::Release);
+    UART.emergency_flush();
+    if let Some(mut console) = CONSOLE.try_exclusive_access() {
+        console.ring.drain(..).for_each(console_putchar);
+    }
+}
+
+fn consoled_main() -> ! {
+    // it waits for the UART with interrupts on
+    unsafe {
+        sstatus::set_sie();
+    }
+    // room for the whole ring, so that a print never allocates
+    CONSOLE
+        .exclusive_access()
+        .ring
+        .reserve_exact(PRINT_RING_SIZE);
+    CONSOLED_RUNNING.store(true, Ordering::Release);
+    loop {
+        block_on_yielding(poll_fn(|cx| {
+            CONSOLE.exclusive_session(|console| console.poll_hand_over(cx))
+        }));
+    }
+}
+
+/// Start the console thread, once there are tasks to schedule.
+pub fn spawn_consoled() {
+    spawn_kernel_thread(consoled_main);
+}
+
+/// The state of a `log_ratelimited!`, which only approximates the limit when
+/// it is hit by the harts at the same time.
+pub struct RateLimit {
+    /// the start of the interval, 0 before the first message
+    begin_ms: AtomicUsize,
+    printed: AtomicUsize,
+    missed: AtomicUsize,
+}
+
+impl RateLimit {
+    pub const fn new() -> Self {
+        Self {
+            begin_ms: AtomicUsize::new(0),
+            printed: AtomicUsize::new(0),
+            missed: AtomicUsize::new(0),
+        }
+    }
+
+    /// Return the messages suppressed since the last one printed if this one
+    /// is to be printed, or None.
+    pub fn check(&self) -> Option<usize> {
+        let now = get_time_ms().max(1);
+        let begin = self.begin_ms.load(Ordering::Relaxed);
+        if (begin == 0 || now - begin >= RATELIMIT_INTERVAL_MS)
+            && self
+                .begin_ms
+                .compare_exchange(begin, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            self.printed.store(0, Ordering::Relaxed);
+        }
+        if self.printed.fetch_add(1, Ordering::Relaxed) < RATELIMIT_BURST {
+            Some(self.missed.swap(0, Ordering::Relaxed))
+        } else {
+            self.missed.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
 }
 
 #[macro_export]
@@ -30,3 +290,54 @@ macro_rules! println {
         $crate::console::print(format_args!(concat!($fmt, "\n") $(, $($arg)+)?))
     }
 }
+
+/// Print a line of a level like `println!`, see `log`.
+#[macro_export]
+macro_rules! log {
+    ($level: expr, $fmt: literal $(, $($arg: tt)+)?) => {
+        $crate::console::log($level, format_args!(concat!($fmt, "\n") $(, $($arg)+)?))
+    }
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg: tt)+) => {
+        $crate::log!($crate::klog::Level::Error, $($arg)+)
+    }
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg: tt)+) => {
+        $crate::log!($crate::klog::Level::Warn, $($arg)+)
+    }
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg: tt)+) => {
+        $crate::log!($crate::klog::Level::Info, $($arg)+)
+    }
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg: tt)+) => {
+        $crate::log!($crate::klog::Level::Debug, $($arg)+)
+    }
+}
+
+/// Print like `println!`, but at most `RATELIMIT_BURST` times in a while at
+/// each place it is used, for the diagnostics of interrupts.
+#[macro_export]
+macro_rules! log_ratelimited {
+    ($fmt: literal $(, $($arg: tt)+)?) => {{
+        static RATELIMIT: $crate::console::RateLimit = $crate::console::RateLimit::new();
+        if let Some(missed) = RATELIMIT.check() {
+            if missed > 0 {
+                println!("[kernel] {} messages suppressed", missed);
+            }
+            println!($fmt $(, $($arg)+)?);
+        }
+    }};
+}
@@ -0,0 +1,155 @@
+//! Crash dumps: on panic, the panic message, some registers, a memory summary
+//! and the kernel log are saved to the root disk, in the blocks after the
+//! file system reserved by `easy-fs-fuse`. The `crashdump` tool reads them
+//! after reboot. Nothing is saved if there are no blocks reserved.
+//!
+//! The first block has a `DumpHeader` and the text follows from the next
+//! one. The header is written last, so a dump cut short is not taken as a
+//! valid one.
+
+use crate::drivers::BLOCK_DEVICE;
+use crate::klog;
+use crate::mm::{frame_stats, heap_stats};
+use crate::timer::get_time_ms;
+use crate::DEV_NON_BLOCKING_ACCESS;
+use core::arch::asm;
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+use easy_fs::{BlockDevice, EasyFileSystem, BLOCK_SZ};
+
+const DUMP_MAGIC: [u8; 8] = *b"rCoreDMP";
+
+/// The same layout as the one of the `crashdump` tool.
+#[repr(C)]
+struct DumpHeader {
+    magic: [u8; 8],
+    /// the length of the text
+    len: u64,
+    /// when it crashed, in milliseconds since boot
+    time_ms: u64,
+}
+
+static DUMPING: AtomicBool = AtomicBool::new(false);
+
+/// Write text to the blocks in [block_id, end), the rest is dropped.
+struct DumpWriter<'a> {
+    device: &'a dyn BlockDevice,
+    block: [u8; BLOCK_SZ],
+    /// the length in `block`
+    pos: usize,
+    block_id: usize,
+    end: usize,
+    len: usize,
+}
+
+impl DumpWriter<'_> {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if self.block_id >= self.end {
+                return;
+            }
+            self.block[self.pos] = byte;
+            self.pos += 1;
+            self.len += 1;
+            if self.pos == BLOCK_SZ {
+                self.flush();
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.pos == 0 {
+            return;
+        }
+        self.block[self.pos..].fill(0);
+        self.device.write_block(self.block_id, &self.block);
+        self.block_id += 1;
+        self.pos = 0;
+    }
+}
+
+impl Write for DumpWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// The registers of the panicked hart, mostly the trap CSRs.
+fn write_registers(writer: &mut DumpWriter) -> fmt::Result {
+    let (ra, sp, gp, tp, fp): (usize, usize, usize, usize, usize);
+    let (sstatus, sepc, scause, stval, satp): (usize, usize, usize, usize, usize);
+    unsafe {
+        asm!("mv {}, ra", out(reg) ra);
+        asm!("mv {}, sp", out(reg) sp);
+        asm!("mv {}, gp", out(reg) gp);
+        asm!("mv {}, tp", out(reg) tp);
+        asm!("mv {}, s0", out(reg) fp);
+        asm!("csrr {}, sstatus", out(reg) sstatus);
+        asm!("csrr {}, sepc", out(reg) sepc);
+        asm!("csrr {}, scause", out(reg) scause);
+        asm!("csrr {}, stval", out(reg) stval);
+        asm!("csrr {}, satp", out(reg) satp);
+    }
+    writeln!(
+        writer,
+        "ra={:#x} sp={:#x} gp={:#x} tp={:#x} fp={:#x}",
+        ra, sp, gp, tp, fp
+    )?;
+    writeln!(
+        writer,
+        "sstatus={:#x} sepc={:#x} scause={:#x} stval={:#x} satp={:#x}",
+        sstatus, sepc, scause, stval, satp
+    )
+}
+
+/// Save a crash dump for a panic if the root disk has blocks reserved.
+pub fn save(info: &PanicInfo) {
+    if DUMPING.swap(true, Ordering::Relaxed) {
+        println!("[kernel] Panicked again when saving the crash dump");
+        return;
+    }
+    // wait for the disk by polling, nothing is scheduled any more
+    *DEV_NON_BLOCKING_ACCESS.exclusive_access() = false;
+    let device = BLOCK_DEVICE.clone();
+    let start = match EasyFileSystem::total_blocks(device.as_ref()) {
+        Some(start) if start + 1 < device.num_blocks() => start,
+        _ => return,
+    };
+    let mut writer = DumpWriter {
+        device: device.as_ref(),
+        block: [0; BLOCK_SZ],
+        pos: 0,
+        block_id: start + 1,
+        end: device.num_blocks(),
+        len: 0,
+    };
+    let time_ms = get_time_ms();
+    let _ = writeln!(writer, "{}", info);
+    let _ = write_registers(&mut writer);
+    let (free, total) = frame_stats();
+    let _ = writeln!(writer, "frames: {} free of {}", free, total);
+    let _ = match heap_stats() {
+        Some((used, total)) => writeln!(writer, "heap: {} of {} bytes used", used, total),
+        None => writeln!(writer, "heap: locked"),
+    };
+    let _ = writeln!(writer, "---START KERNEL LOG---");
+    klog::for_each_text(|text| writer.write_bytes(text));
+    let _ = writeln!(writer, "---END   KERNEL LOG---");
+    writer.flush();
+    let header = DumpHeader {
+        magic: DUMP_MAGIC,
+        len: writer.len as u64,
+        time_ms: time_ms as u64,
+    };
+    let mut block = [0u8; BLOCK_SZ];
+    unsafe {
+        (block.as_mut_ptr() as *mut DumpHeader).write_unaligned(header);
+    }
+    device.write_block(start, &block);
+    println!(
+        "[kernel] Crash dump of {} bytes saved after block {}",
+        writer.len, start
+    );
+}
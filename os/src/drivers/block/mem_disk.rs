@@ -0,0 +1,42 @@
+use super::BlockDevice;
+use crate::config::PAGE_SIZE;
+use crate::mm::{frame_alloc_more, FrameTracker};
+use alloc::vec::Vec;
+
+const BLOCK_SZ: usize = 512;
+const BLOCKS_PER_FRAME: usize = PAGE_SIZE / BLOCK_SZ;
+
+/// A disk in frames allocated by the kernel, empty when created and lost
+/// when the kernel stops.
+pub struct MemDisk {
+    frames: Vec<FrameTracker>,
+}
+
+impl MemDisk {
+    pub fn new(blocks: usize) -> Self {
+        let frames = frame_alloc_more((blocks + BLOCKS_PER_FRAME - 1) / BLOCKS_PER_FRAME)
+            .expect("no frames for the memory disk");
+        Self { frames }
+    }
+
+    fn block(&self, block_id: usize) -> &'static mut [u8] {
+        let frame = &self.frames[block_id / BLOCKS_PER_FRAME];
+        let offset = block_id % BLOCKS_PER_FRAME * BLOCK_SZ;
+        &mut frame.ppn.get_bytes_array()[offset..offset + BLOCK_SZ]
+    }
+}
+
+impl BlockDevice for MemDisk {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        buf.copy_from_slice(self.block(block_id));
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.block(block_id).copy_from_slice(buf);
+    }
+    fn num_blocks(&self) -> usize {
+        self.frames.len() * BLOCKS_PER_FRAME
+    }
+    fn handle_irq(&self) {
+        unreachable!("memory disk has no interrupts");
+    }
+}
@@ -1,14 +1,45 @@
+mod mem_disk;
+mod partition;
+#[cfg(feature = "board_sifive_u")]
+mod ram_disk;
+#[cfg(not(feature = "board_sifive_u"))]
 mod virtio_blk;
 
+pub use mem_disk::MemDisk;
+pub use partition::Partition;
+#[cfg(feature = "board_sifive_u")]
+pub use ram_disk::RamDisk;
+#[cfg(not(feature = "board_sifive_u"))]
 pub use virtio_blk::VirtIOBlock;
 
-use crate::board::BlockDeviceImpl;
+use crate::boards::{block_device, BlockDeviceImpl};
+use alloc::boxed::Box;
 use alloc::sync::Arc;
+use core::future::Future;
+use core::pin::Pin;
 use easy_fs::BlockDevice;
 use lazy_static::*;
 
+/// 2MiB, enough for a small easy-fs
+const MEM_DISK_BLOCKS: usize = 4096;
+
+pub type BlockFuture<'a> = Pin<Box<dyn Future<Output = ()> + 'a>>;
+
+/// A block device whose reads and writes are futures woken up by the
+/// interrupt of their completion, rather than blocking in the driver, like
+/// `AsyncCharReader` of the UART. The buffers are borrowed until the future
+/// is done or dropped.
+pub trait AsyncBlockDevice: BlockDevice {
+    fn read_block_async<'a>(&'a self, block_id: usize, buf: &'a mut [u8]) -> BlockFuture<'a>;
+    fn write_block_async<'a>(&'a self, block_id: usize, buf: &'a [u8]) -> BlockFuture<'a>;
+}
+
 lazy_static! {
-    pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = Arc::new(BlockDeviceImpl::new());
+    /// The root disk, whose requests can also be issued without blocking.
+    pub static ref ASYNC_BLOCK_DEVICE: Arc<BlockDeviceImpl> = Arc::new(block_device());
+    pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = ASYNC_BLOCK_DEVICE.clone();
+    /// A spare disk to make file systems on, allocated when first used.
+    pub static ref MEM_DISK: Arc<dyn BlockDevice> = Arc::new(MemDisk::new(MEM_DISK_BLOCKS));
 }
 
 #[allow(unused)]
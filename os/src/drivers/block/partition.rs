@@ -0,0 +1,94 @@
+use super::BlockDevice;
+use alloc::sync::Arc;
+
+const BLOCK_SZ: usize = 512;
+/// where the four primary entries of the MBR are
+const MBR_ENTRIES: usize = 446;
+const MBR_ENTRY_SZ: usize = 16;
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+
+/// A range of blocks of a disk, used as a disk itself.
+pub struct Partition {
+    disk: Arc<dyn BlockDevice>,
+    start: usize,
+    blocks: usize,
+}
+
+impl Partition {
+    /// Find a primary partition of the MBR of a disk by its number from 1,
+    /// return None if there is no such table or the entry is empty.
+    pub fn find(disk: &Arc<dyn BlockDevice>, number: usize) -> Option<Self> {
+        if !(1..=4).contains(&number) {
+            return None;
+        }
+        let mut mbr = [0u8; BLOCK_SZ];
+        disk.read_block(0, &mut mbr);
+        if mbr[BLOCK_SZ - 2..] != MBR_SIGNATURE {
+            return None;
+        }
+        let entry = &mbr[MBR_ENTRIES + (number - 1) * MBR_ENTRY_SZ..][..MBR_ENTRY_SZ];
+        let field = |offset: usize| {
+            u32::from_le_bytes(entry[offset..offset + 4].try_into().unwrap()) as usize
+        };
+        // the type, then the first block and the number of blocks
+        let (type_, start, blocks) = (entry[4], field(8), field(12));
+        if type_ == 0 || start == 0 || blocks == 0 || start + blocks > disk.num_blocks() {
+            return None;
+        }
+        Some(Self {
+            disk: disk.clone(),
+            start,
+            blocks,
+        })
+    }
+}
+
+impl BlockDevice for Partition {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        assert!(
+            block_id < self.blocks,
+            "block {} is out of the partition",
+            block_id
+        );
+        self.disk.read_block(self.start + block_id, buf);
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        assert!(
+            block_id < self.blocks,
+            "block {} is out of the partition",
+            block_id
+        );
+        self.disk.write_block(self.start + block_id, buf);
+    }
+    fn num_blocks(&self) -> usize {
+        self.blocks
+    }
+    fn handle_irq(&self) {
+        unreachable!("interrupts are handled by the disk");
+    }
+    fn read_ahead(&self, block_id: usize, count: usize) {
+        let count = count.min(self.blocks.saturating_sub(block_id));
+        self.disk.read_ahead(self.start + block_id, count);
+    }
+    fn flush(&self) {
+        self.disk.flush();
+    }
+    fn read_blocks(&self, block_id: usize, bufs: &mut [&mut [u8]]) {
+        let blocks = bufs.iter().map(|buf| buf.len()).sum::<usize>() / BLOCK_SZ;
+        assert!(
+            block_id + blocks <= self.blocks,
+            "block {} is out of the partition",
+            block_id + blocks - 1
+        );
+        self.disk.read_blocks(self.start + block_id, bufs);
+    }
+    fn write_blocks(&self, block_id: usize, bufs: &[&[u8]]) {
+        let blocks = bufs.iter().map(|buf| buf.len()).sum::<usize>() / BLOCK_SZ;
+        assert!(
+            block_id + blocks <= self.blocks,
+            "block {} is out of the partition",
+            block_id + blocks - 1
+        );
+        self.disk.write_blocks(self.start + block_id, bufs);
+    }
+}
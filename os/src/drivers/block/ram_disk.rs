@@ -0,0 +1,54 @@
+use super::{AsyncBlockDevice, BlockDevice, BlockFuture};
+use alloc::boxed::Box;
+
+const BLOCK_SZ: usize = 512;
+
+/// A disk image loaded into the memory by the bootloader or QEMU at
+/// `BASE_ADDR`, which should be identically mapped.
+pub struct RamDisk<const BASE_ADDR: usize, const SIZE: usize>;
+
+impl<const BASE_ADDR: usize, const SIZE: usize> RamDisk<BASE_ADDR, SIZE> {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn block(&self, block_id: usize) -> *mut u8 {
+        assert!(
+            (block_id + 1) * BLOCK_SZ <= SIZE,
+            "block {} is out of the ram disk",
+            block_id
+        );
+        (BASE_ADDR + block_id * BLOCK_SZ) as *mut u8
+    }
+}
+
+impl<const BASE_ADDR: usize, const SIZE: usize> BlockDevice for RamDisk<BASE_ADDR, SIZE> {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        assert_eq!(buf.len(), BLOCK_SZ);
+        unsafe {
+            core::ptr::copy_nonoverlapping(self.block(block_id), buf.as_mut_ptr(), BLOCK_SZ);
+        }
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        assert_eq!(buf.len(), BLOCK_SZ);
+        unsafe {
+            core::ptr::copy_nonoverlapping(buf.as_ptr(), self.block(block_id), BLOCK_SZ);
+        }
+    }
+    fn num_blocks(&self) -> usize {
+        SIZE / BLOCK_SZ
+    }
+    fn handle_irq(&self) {
+        unreachable!("ram disk has no interrupts");
+    }
+}
+
+/// Copied at once, the futures are ready when first polled.
+impl<const BASE_ADDR: usize, const SIZE: usize> AsyncBlockDevice for RamDisk<BASE_ADDR, SIZE> {
+    fn read_block_async<'a>(&'a self, block_id: usize, buf: &'a mut [u8]) -> BlockFuture<'a> {
+        Box::pin(async move { self.read_block(block_id, buf) })
+    }
+    fn write_block_async<'a>(&'a self, block_id: usize, buf: &'a [u8]) -> BlockFuture<'a> {
+        Box::pin(async move { self.write_block(block_id, buf) })
+    }
+}
@@ -1,87 +1,390 @@
-use super::BlockDevice;
-use crate::drivers::bus::virtio::VirtioHal;
-use crate::sync::{Condvar, UPIntrFreeCell};
-use crate::task::schedule;
+use super::{AsyncBlockDevice, BlockDevice, BlockFuture};
+use crate::config::PAGE_SIZE;
+use crate::drivers::bus::virtio::{as_bytes, as_bytes_mut, DeviceType, MmioTransport, VirtQueue};
+use crate::hart::{hart_id, MAX_HARTS};
+use crate::sync::{block_on_yielding, poll_once, UPIntrFreeCell};
 use crate::DEV_NON_BLOCKING_ACCESS;
-use alloc::collections::BTreeMap;
-use virtio_drivers::{BlkResp, RespStatus, VirtIOBlk, VirtIOHeader};
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::future::{poll_fn, Future};
+use core::pin::Pin;
+use core::task::{ready, Context, Poll};
 
-#[allow(unused)]
 const VIRTIO0: usize = 0x10008000;
 
+const QUEUE_SIZE: u16 = 16;
+const BLK_SIZE: usize = 512;
+/// At most this many blocks are read ahead and not read yet. The requests
+/// in flight are also limited by the free descriptors of the queues.
+const MAX_READ_AHEAD: usize = 8;
+/// At most this many pages of buffers in a request, with a descriptor for
+/// each, so that it fits in a queue with the header and the status.
+const MAX_SEGMENTS: usize = QUEUE_SIZE as usize - 2;
+
+const BLK_T_IN: u32 = 0;
+const BLK_T_OUT: u32 = 1;
+const BLK_T_FLUSH: u32 = 4;
+const BLK_S_OK: u8 = 0;
+/// the device has a volatile write cache, flushed by `BLK_T_FLUSH`
+const BLK_F_FLUSH: u64 = 1 << 9;
+/// the device has more than one request queue
+const BLK_F_MQ: u64 = 1 << 12;
+/// offset of the capacity in sectors in the configuration
+const BLK_CONFIG_CAPACITY: usize = 0;
+/// offset of the number of request queues, valid with `BLK_F_MQ`
+const BLK_CONFIG_NUM_QUEUES: usize = 34;
+
+#[repr(C)]
+struct BlkReq {
+    type_: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+/// The header and the status of a request, on the heap so they stay where
+/// the device sees them when the future moves.
+struct BlkReqState {
+    req: BlkReq,
+    status: u8,
+}
+
+enum BlkBuf<'a> {
+    Read(&'a mut [u8]),
+    Write(&'a [u8]),
+    /// the blocks in turn in the buffers, each of whole blocks, which are
+    /// transferred straight to or from them
+    ReadBlocks(Vec<&'a mut [u8]>),
+    WriteBlocks(Vec<&'a [u8]>),
+    /// read into a buffer of its own, see `VirtIOBlock::read_ahead`
+    ReadAhead(Box<[u8; BLK_SIZE]>),
+    /// no buffer, the writes completed before are made persistent
+    Flush,
+}
+
+/// A read or write of a block, or a flush, submitted when first polled.
+struct BlkRequest<'a> {
+    queue: Arc<VirtQueue>,
+    state: Box<BlkReqState>,
+    buf: BlkBuf<'a>,
+    /// the token once submitted, None again when done
+    token: Option<u16>,
+}
+
+impl<'a> BlkRequest<'a> {
+    fn new(queue: Arc<VirtQueue>, block_id: usize, buf: BlkBuf<'a>) -> Self {
+        let type_ = match buf {
+            BlkBuf::Read(ref buf) => {
+                assert_eq!(buf.len(), BLK_SIZE);
+                BLK_T_IN
+            }
+            BlkBuf::Write(buf) => {
+                assert_eq!(buf.len(), BLK_SIZE);
+                BLK_T_OUT
+            }
+            BlkBuf::ReadBlocks(ref bufs) => {
+                assert!(bufs.iter().all(|buf| buf.len() % BLK_SIZE == 0));
+                BLK_T_IN
+            }
+            BlkBuf::WriteBlocks(ref bufs) => {
+                assert!(bufs.iter().all(|buf| buf.len() % BLK_SIZE == 0));
+                BLK_T_OUT
+            }
+            BlkBuf::ReadAhead(_) => BLK_T_IN,
+            BlkBuf::Flush => BLK_T_FLUSH,
+        };
+        let req = BlkReq {
+            type_,
+            reserved: 0,
+            sector: block_id as u64,
+        };
+        Self {
+            queue,
+            state: Box::new(BlkReqState {
+                req,
+                status: u8::MAX,
+            }),
+            buf,
+            token: None,
+        }
+    }
+}
+
+impl Future for BlkRequest<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        let token = match this.token {
+            Some(token) => token,
+            None => {
+                let header = as_bytes(&this.state.req);
+                let status = as_bytes_mut(&mut this.state.status);
+                // the buffers are kept until done, see drop
+                let token = ready!(unsafe {
+                    match &mut this.buf {
+                        BlkBuf::Read(buf) => {
+                            this.queue
+                                .poll_submit(&[header], &mut [&mut **buf, status], cx)
+                        }
+                        BlkBuf::ReadAhead(buf) => {
+                            this.queue
+                                .poll_submit(&[header], &mut [&mut buf[..], status], cx)
+                        }
+                        BlkBuf::Write(buf) => {
+                            this.queue.poll_submit(&[header, *buf], &mut [status], cx)
+                        }
+                        BlkBuf::ReadBlocks(bufs) => {
+                            let mut outputs: Vec<&mut [u8]> =
+                                bufs.iter_mut().map(|buf| &mut **buf).collect();
+                            outputs.push(status);
+                            this.queue.poll_submit(&[header], &mut outputs, cx)
+                        }
+                        BlkBuf::WriteBlocks(bufs) => {
+                            let mut inputs = Vec::from([header]);
+                            inputs.extend(bufs.iter().copied());
+                            this.queue.poll_submit(&inputs, &mut [status], cx)
+                        }
+                        BlkBuf::Flush => this.queue.poll_submit(&[header], &mut [status], cx),
+                    }
+                });
+                this.token = Some(token);
+                token
+            }
+        };
+        ready!(this.queue.poll_request(token, cx));
+        this.token = None;
+        assert_eq!(
+            this.state.status, BLK_S_OK,
+            "Error when accessing VirtIOBlk"
+        );
+        Poll::Ready(())
+    }
+}
+
+impl Drop for BlkRequest<'_> {
+    fn drop(&mut self) {
+        // the device may still access the buffers
+        if let Some(token) = self.token.take() {
+            block_on_yielding(poll_fn(|cx| self.queue.poll_request(token, cx)));
+        }
+    }
+}
+
+/// A virtio-blk device with a request queue for each hart if it has enough
+/// of them, so the harts submit requests without contending for the lock of
+/// a single queue.
+///
+/// NOTICE: a virtio-mmio device has a single interrupt for all its queues,
+/// so there is no affinity of the queues to the harts for it. The hart
+/// taking the interrupt completes the requests of every queue.
 pub struct VirtIOBlock {
-    virtio_blk: UPIntrFreeCell<VirtIOBlk<'static, VirtioHal>>,
-    condvars: BTreeMap<u16, Condvar>,
+    transport: MmioTransport,
+    queues: Vec<Arc<VirtQueue>>,
+    /// whether the device caches writes, which have to be flushed
+    write_cache: bool,
+    /// the reads ahead by block, submitted or done already, oldest first
+    read_ahead: UPIntrFreeCell<VecDeque<(usize, BlkRequest<'static>)>>,
+}
+
+impl AsyncBlockDevice for VirtIOBlock {
+    fn read_block_async<'a>(&'a self, block_id: usize, buf: &'a mut [u8]) -> BlockFuture<'a> {
+        Box::pin(BlkRequest::new(self.queue(), block_id, BlkBuf::Read(buf)))
+    }
+    fn write_block_async<'a>(&'a self, block_id: usize, buf: &'a [u8]) -> BlockFuture<'a> {
+        drop(self.take_read_ahead(block_id));
+        Box::pin(BlkRequest::new(self.queue(), block_id, BlkBuf::Write(buf)))
+    }
 }
 
 impl BlockDevice for VirtIOBlock {
     fn read_block(&self, block_id: usize, buf: &mut [u8]) {
-        let nb = *DEV_NON_BLOCKING_ACCESS.exclusive_access();
-        if nb {
-            let mut resp = BlkResp::default();
-            let task_cx_ptr = self.virtio_blk.exclusive_session(|blk| {
-                let token = unsafe { blk.read_block_nb(block_id, buf, &mut resp).unwrap() };
-                self.condvars.get(&token).unwrap().wait_no_sched()
-            });
-            schedule(task_cx_ptr);
-            assert_eq!(
-                resp.status(),
-                RespStatus::Ok,
-                "Error when reading VirtIOBlk"
-            );
-        } else {
-            self.virtio_blk
-                .exclusive_access()
-                .read_block(block_id, buf)
-                .expect("Error when reading VirtIOBlk");
+        match self.take_read_ahead(block_id) {
+            Some(mut req) => {
+                // not submitted again once done, as the token is None then
+                if req.token.is_some() {
+                    block_on_yielding(&mut req);
+                }
+                if let BlkBuf::ReadAhead(data) = &req.buf {
+                    buf.copy_from_slice(&data[..]);
+                }
+            }
+            None => block_on_yielding(self.read_block_async(block_id, buf)),
         }
     }
     fn write_block(&self, block_id: usize, buf: &[u8]) {
-        let nb = *DEV_NON_BLOCKING_ACCESS.exclusive_access();
-        if nb {
-            let mut resp = BlkResp::default();
-            let task_cx_ptr = self.virtio_blk.exclusive_session(|blk| {
-                let token = unsafe { blk.write_block_nb(block_id, buf, &mut resp).unwrap() };
-                self.condvars.get(&token).unwrap().wait_no_sched()
-            });
-            schedule(task_cx_ptr);
-            assert_eq!(
-                resp.status(),
-                RespStatus::Ok,
-                "Error when writing VirtIOBlk"
-            );
-        } else {
-            self.virtio_blk
+        drop(self.take_read_ahead(block_id));
+        block_on_yielding(self.write_block_async(block_id, buf));
+    }
+    fn num_blocks(&self) -> usize {
+        // 64-bit fields of the configuration are read as two 32-bit halves
+        let low: u32 = self.transport.config_read(BLK_CONFIG_CAPACITY);
+        let high: u32 = self.transport.config_read(BLK_CONFIG_CAPACITY + 4);
+        ((high as usize) << 32) | low as usize
+    }
+    fn handle_irq(&self) {
+        self.transport.ack_interrupt();
+        for queue in self.queues.iter() {
+            queue.handle_irq(|_, _| {});
+        }
+    }
+    /// The reads are submitted without waiting for them and kept until
+    /// `read_block` takes them. None is submitted while the requests are
+    /// still polled to completion or once a queue is full.
+    fn read_ahead(&self, block_id: usize, count: usize) {
+        if !*DEV_NON_BLOCKING_ACCESS.exclusive_access() {
+            return;
+        }
+        for block_id in block_id..block_id + count {
+            if self
+                .read_ahead
                 .exclusive_access()
-                .write_block(block_id, buf)
-                .expect("Error when writing VirtIOBlk");
+                .iter()
+                .any(|(id, _)| *id == block_id)
+            {
+                continue;
+            }
+            let buf = BlkBuf::ReadAhead(Box::new([0; BLK_SIZE]));
+            let mut req = BlkRequest::new(self.queue(), block_id, buf);
+            if poll_once(&mut req).is_pending() && req.token.is_none() {
+                break;
+            }
+            let mut read_ahead = self.read_ahead.exclusive_access();
+            read_ahead.push_back((block_id, req));
+            let evicted = if read_ahead.len() > MAX_READ_AHEAD {
+                read_ahead.pop_front()
+            } else {
+                None
+            };
+            // dropping a request in flight waits for it
+            drop(read_ahead);
+            drop(evicted);
         }
     }
-    fn handle_irq(&self) {
-        self.virtio_blk.exclusive_session(|blk| {
-            while let Ok(token) = blk.pop_used() {
-                self.condvars.get(&token).unwrap().signal();
+    /// The buffers are transferred by as few requests as they fit in, each
+    /// with a descriptor for every page of them rather than a copy.
+    fn read_blocks(&self, mut block_id: usize, bufs: &mut [&mut [u8]]) {
+        let groups = Self::group(bufs.iter().map(|buf| (buf.as_ptr() as usize, buf.len())));
+        let mut rest = bufs;
+        for count in groups {
+            let (group, tail) = core::mem::take(&mut rest).split_at_mut(count);
+            rest = tail;
+            let len: usize = group.iter().map(|buf| buf.len()).sum();
+            if len == 0 {
+                continue;
+            }
+            let bufs = group.iter_mut().map(|buf| &mut **buf).collect();
+            block_on_yielding(BlkRequest::new(
+                self.queue(),
+                block_id,
+                BlkBuf::ReadBlocks(bufs),
+            ));
+            block_id += len / BLK_SIZE;
+        }
+    }
+    fn write_blocks(&self, mut block_id: usize, bufs: &[&[u8]]) {
+        let groups = Self::group(bufs.iter().map(|buf| (buf.as_ptr() as usize, buf.len())));
+        let mut rest = bufs;
+        for count in groups {
+            let (group, tail) = rest.split_at(count);
+            rest = tail;
+            let len: usize = group.iter().map(|buf| buf.len()).sum();
+            if len == 0 {
+                continue;
+            }
+            for block_id in block_id..block_id + len / BLK_SIZE {
+                drop(self.take_read_ahead(block_id));
             }
-        });
+            block_on_yielding(BlkRequest::new(
+                self.queue(),
+                block_id,
+                BlkBuf::WriteBlocks(Vec::from(group)),
+            ));
+            block_id += len / BLK_SIZE;
+        }
+    }
+    /// The writes completed before the flush is submitted are persistent once
+    /// it completes, whichever queue they went through. `write_block` waits
+    /// for its write, so every one returned from is covered.
+    fn flush(&self) {
+        if self.write_cache {
+            block_on_yielding(BlkRequest::new(self.queue(), 0, BlkBuf::Flush));
+        }
     }
 }
 
 impl VirtIOBlock {
     pub fn new() -> Self {
-        let virtio_blk = unsafe {
-            UPIntrFreeCell::new(
-                VirtIOBlk::<VirtioHal>::new(&mut *(VIRTIO0 as *mut VirtIOHeader)).unwrap(),
-            )
+        let transport =
+            MmioTransport::new(VIRTIO0, DeviceType::Block).expect("can't find virtio block device");
+        let features = transport.begin_init(BLK_F_MQ | BLK_F_FLUSH);
+        let num_queues = if features & BLK_F_MQ != 0 {
+            let num_queues: u16 = transport.config_read(BLK_CONFIG_NUM_QUEUES);
+            (num_queues as usize).clamp(1, MAX_HARTS)
+        } else {
+            1
         };
-        let mut condvars = BTreeMap::new();
-        let channels = virtio_blk.exclusive_access().virt_queue_size();
-        for i in 0..channels {
-            let condvar = Condvar::new();
-            condvars.insert(i, condvar);
-        }
+        let queues = (0..num_queues)
+            .map(|index| Arc::new(VirtQueue::new(&transport, index as u16, QUEUE_SIZE)))
+            .collect();
+        transport.finish_init();
+        let write_cache = features & BLK_F_FLUSH != 0;
+        info!(
+            "KERN: virtio-blk with {} request queues{}",
+            num_queues,
+            if write_cache { ", write cache" } else { "" }
+        );
         Self {
-            virtio_blk,
-            condvars,
+            transport,
+            queues,
+            write_cache,
+            read_ahead: unsafe { UPIntrFreeCell::new(VecDeque::new()) },
         }
     }
+
+    /// The queue of the current hart, shared by the harts if there are more
+    /// harts than queues. A request stays in its queue even if the task
+    /// moves to another hart.
+    fn queue(&self) -> Arc<VirtQueue> {
+        Arc::clone(&self.queues[hart_id() % self.queues.len()])
+    }
+
+    /// Group the buffers, given by their addresses and lengths, into the
+    /// requests of `read_blocks` and `write_blocks`, each with at most
+    /// `MAX_SEGMENTS` pages of them. Return how many buffers are in each.
+    fn group(bufs: impl Iterator<Item = (usize, usize)>) -> Vec<usize> {
+        let mut groups = Vec::new();
+        let (mut count, mut pages) = (0, 0);
+        for (addr, len) in bufs {
+            let buf_pages = match len {
+                0 => 0,
+                _ => (addr + len - 1) / PAGE_SIZE - addr / PAGE_SIZE + 1,
+            };
+            assert!(
+                buf_pages <= MAX_SEGMENTS,
+                "buffer of {} bytes is too large",
+                len
+            );
+            if pages + buf_pages > MAX_SEGMENTS {
+                groups.push(count);
+                count = 0;
+                pages = 0;
+            }
+            count += 1;
+            pages += buf_pages;
+        }
+        if count > 0 {
+            groups.push(count);
+        }
+        groups
+    }
+
+    /// Take the read ahead of a block, which is stale once it is written.
+    /// Drop it outside the lock, as dropping a request in flight waits.
+    fn take_read_ahead(&self, block_id: usize) -> Option<BlkRequest<'static>> {
+        let mut read_ahead = self.read_ahead.exclusive_access();
+        let index = read_ahead.iter().position(|(id, _)| *id == block_id)?;
+        read_ahead.remove(index).map(|(_, req)| req)
+    }
 }
@@ -0,0 +1,20 @@
+//! The virtio transport and virtqueues shared by the virtio drivers.
+
+mod queue;
+mod transport;
+
+pub use queue::VirtQueue;
+pub use transport::{DeviceType, MmioTransport};
+
+/// View a request or response of the device as bytes to be queued.
+pub fn as_bytes<T>(value: &T) -> &[u8] {
+    unsafe {
+        core::slice::from_raw_parts(value as *const T as *const u8, core::mem::size_of::<T>())
+    }
+}
+
+pub fn as_bytes_mut<T>(value: &mut T) -> &mut [u8] {
+    unsafe {
+        core::slice::from_raw_parts_mut(value as *mut T as *mut u8, core::mem::size_of::<T>())
+    }
+}
@@ -0,0 +1,451 @@
+use super::transport::MmioTransport;
+use crate::config::PAGE_SIZE;
+use crate::mm::{kernel_token, DmaBuffer, PageTable, VirtAddr};
+use crate::sync::{Condvar, UPIntrFreeCell};
+use crate::task::schedule;
+use crate::DEV_NON_BLOCKING_ACCESS;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::hint::spin_loop;
+use core::mem::size_of;
+use core::sync::atomic::{fence, Ordering};
+use core::task::{Context, Poll, Waker};
+
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+/// set by the device in the used ring while it needs no notifications
+const USED_F_NO_NOTIFY: u16 = 1;
+
+#[repr(C, align(16))]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+/// A physically contiguous part of a buffer: the address, the length and
+/// whether the device writes it.
+type Segment = (usize, usize, bool);
+
+fn align_up(size: usize) -> usize {
+    (size + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE
+}
+
+/// The memory of a split virtqueue shared with the device.
+///
+/// Ref: virtio v1.1 2.6 Split Virtqueues
+struct Ring {
+    dma: DmaBuffer,
+    size: u16,
+    avail_offset: usize,
+    used_offset: usize,
+    free_head: u16,
+    num_free: u16,
+    avail_idx: u16,
+    last_used_idx: u16,
+}
+
+impl Ring {
+    fn new(size: u16) -> Self {
+        let n = size as usize;
+        let avail_offset = size_of::<Descriptor>() * n;
+        // the used ring is page aligned for the legacy interface
+        let used_offset = align_up(avail_offset + size_of::<u16>() * (3 + n));
+        let used_size = size_of::<u16>() * 3 + size_of::<u32>() * 2 * n;
+        let dma = DmaBuffer::new((used_offset + align_up(used_size)) / PAGE_SIZE).unwrap();
+        let mut ring = Self {
+            dma,
+            size,
+            avail_offset,
+            used_offset,
+            free_head: 0,
+            num_free: size,
+            avail_idx: 0,
+            last_used_idx: 0,
+        };
+        for i in 0..size - 1 {
+            ring.desc(i).next = i + 1;
+        }
+        ring
+    }
+
+    fn desc(&mut self, index: u16) -> &mut Descriptor {
+        assert!(index < self.size);
+        unsafe { &mut *(self.dma.vaddr() as *mut Descriptor).add(index as usize) }
+    }
+
+    /// flags, idx, then the ring
+    fn avail(&self, index: usize) -> *mut u16 {
+        unsafe { ((self.dma.vaddr() + self.avail_offset) as *mut u16).add(index) }
+    }
+
+    /// flags and idx in u16, then the ring of id and len in u32
+    fn used(&self) -> usize {
+        self.dma.vaddr() + self.used_offset
+    }
+
+    /// Chain descriptors of segments and make it available, return the head.
+    fn add(&mut self, segments: &[Segment]) -> u16 {
+        assert!(!segments.is_empty() && segments.len() <= self.num_free as usize);
+        let head = self.free_head;
+        let mut last = head;
+        for &(paddr, len, write) in segments {
+            last = self.free_head;
+            let desc = self.desc(last);
+            desc.addr = paddr as u64;
+            desc.len = len as u32;
+            desc.flags = if write {
+                DESC_F_NEXT | DESC_F_WRITE
+            } else {
+                DESC_F_NEXT
+            };
+            self.free_head = desc.next;
+        }
+        self.desc(last).flags &= !DESC_F_NEXT;
+        self.num_free -= segments.len() as u16;
+        let slot = self.avail_idx & (self.size - 1);
+        unsafe {
+            self.avail(2 + slot as usize).write_volatile(head);
+        }
+        // the device must see the descriptors before the index
+        fence(Ordering::SeqCst);
+        self.avail_idx = self.avail_idx.wrapping_add(1);
+        unsafe {
+            self.avail(1).write_volatile(self.avail_idx);
+        }
+        fence(Ordering::SeqCst);
+        head
+    }
+
+    /// Whether the device wants to be notified of the chains made available,
+    /// it may not while it is working through the available ring.
+    fn needs_notify(&self) -> bool {
+        fence(Ordering::SeqCst);
+        let flags = unsafe { (self.used() as *const u16).read_volatile() };
+        flags & USED_F_NO_NOTIFY == 0
+    }
+
+    /// Pop a chain used by the device, return its head and the length
+    /// written. The descriptors are not recycled yet.
+    fn pop_used(&mut self) -> Option<(u16, u32)> {
+        fence(Ordering::SeqCst);
+        let used_idx = unsafe { ((self.used() + 2) as *const u16).read_volatile() };
+        if used_idx == self.last_used_idx {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+        let slot = (self.last_used_idx & (self.size - 1)) as usize;
+        let elem = self.used() + 4 + 8 * slot;
+        let (id, len) = unsafe {
+            (
+                (elem as *const u32).read_volatile(),
+                ((elem + 4) as *const u32).read_volatile(),
+            )
+        };
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+        Some((id as u16, len))
+    }
+
+    /// Put the descriptors of a chain back to the free list.
+    fn recycle(&mut self, head: u16) {
+        let free_head = self.free_head;
+        let mut index = head;
+        loop {
+            self.num_free += 1;
+            let desc = self.desc(index);
+            if desc.flags & DESC_F_NEXT == 0 {
+                desc.next = free_head;
+                break;
+            }
+            index = desc.next;
+        }
+        self.free_head = head;
+    }
+}
+
+struct VirtQueueInner {
+    ring: Ring,
+    /// whether a task is waiting for the chain of a head
+    waiting: Vec<bool>,
+    /// the length written by the device to the chain of a head
+    done: Vec<Option<u32>>,
+    /// the wakers of the requests submitted without waiting
+    wakers: Vec<Option<Waker>>,
+    /// the wakers of the requests waiting for free descriptors
+    free_wakers: Vec<Waker>,
+}
+
+impl VirtQueueInner {
+    fn recycle(&mut self, head: u16) {
+        self.ring.recycle(head);
+        for waker in self.free_wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// A virtqueue whose used buffers are collected by the interrupt handler.
+///
+/// A request sleeps until the interrupt of its completion, or polls the used
+/// ring before tasks are scheduled. A request can also be submitted without
+/// waiting, and polled as a future woken up by the interrupt. Buffers like
+/// received events are posted without waiting, and passed to a callback of
+/// `handle_irq` when used.
+pub struct VirtQueue {
+    transport: MmioTransport,
+    index: u16,
+    inner: UPIntrFreeCell<VirtQueueInner>,
+    /// one for each descriptor, for the request headed by it
+    condvars: Vec<Condvar>,
+    /// for tasks waiting for free descriptors
+    free: Condvar,
+}
+
+impl VirtQueue {
+    /// Set up the queue `index` of the device with at most `size`
+    /// descriptors, which should be a power of 2.
+    pub fn new(transport: &MmioTransport, index: u16, size: u16) -> Self {
+        let size = size.min(transport.max_queue_size(index));
+        assert!(size.is_power_of_two(), "bad size of virtqueue {}", size);
+        let ring = Ring::new(size);
+        let paddr = ring.dma.paddr();
+        transport.setup_queue(
+            index,
+            size,
+            paddr,
+            paddr + ring.avail_offset,
+            paddr + ring.used_offset,
+        );
+        Self {
+            transport: *transport,
+            index,
+            inner: unsafe {
+                UPIntrFreeCell::new(VirtQueueInner {
+                    ring,
+                    waiting: vec![false; size as usize],
+                    done: vec![None; size as usize],
+                    wakers: vec![None; size as usize],
+                    free_wakers: Vec::new(),
+                })
+            },
+            condvars: (0..size).map(|_| Condvar::new()).collect(),
+            free: Condvar::new(),
+        }
+    }
+
+    pub fn size(&self) -> u16 {
+        self.inner.exclusive_access().ring.size
+    }
+
+    /// Notify the device of the chains just made available unless it is
+    /// working already, so a batch of requests submitted meanwhile costs no
+    /// more notifications.
+    fn notify(&self, ring: &Ring) {
+        if ring.needs_notify() {
+            self.transport.notify(self.index);
+        }
+    }
+
+    /// Split buffers into physically contiguous segments. Segments of
+    /// different buffers are never merged since legacy devices care about
+    /// the boundaries.
+    fn segments(inputs: &[&[u8]], outputs: &mut [&mut [u8]]) -> Vec<Segment> {
+        let page_table = PageTable::from_token(kernel_token());
+        let mut segments: Vec<Segment> = Vec::new();
+        let buffers = inputs
+            .iter()
+            .map(|buf| (buf.as_ptr() as usize, buf.len(), false))
+            .chain(
+                outputs
+                    .iter_mut()
+                    .map(|buf| (buf.as_mut_ptr() as usize, buf.len(), true)),
+            );
+        for (mut start, len, write) in buffers {
+            let end = start + len;
+            let first = segments.len();
+            while start < end {
+                let page_end = ((start / PAGE_SIZE + 1) * PAGE_SIZE).min(end);
+                let paddr = page_table.translate_va(VirtAddr::from(start)).unwrap().0;
+                let merged = segments.len() > first;
+                match segments.last_mut() {
+                    Some(last) if merged && last.0 + last.1 == paddr => {
+                        last.1 += page_end - start;
+                    }
+                    _ => segments.push((paddr, page_end - start, write)),
+                }
+                start = page_end;
+            }
+        }
+        segments
+    }
+
+    /// Send a request made of buffers read by the device and ones written by
+    /// it, return the length written when it completes. Return None if the
+    /// buffers take more descriptors than the queue has, which would never be
+    /// free.
+    pub fn request(&self, inputs: &[&[u8]], outputs: &mut [&mut [u8]]) -> Option<u32> {
+        let segments = Self::segments(inputs, outputs);
+        let nb = *DEV_NON_BLOCKING_ACCESS.exclusive_access();
+        let mut inner = self.inner.exclusive_access();
+        if segments.len() > inner.ring.size as usize {
+            return None;
+        }
+        while (inner.ring.num_free as usize) < segments.len() {
+            assert!(nb, "virtqueue {} is full", self.index);
+            let task_cx_ptr = self.free.wait_no_sched();
+            drop(inner);
+            schedule(task_cx_ptr);
+            inner = self.inner.exclusive_access();
+        }
+        let token = inner.ring.add(&segments);
+        self.notify(&inner.ring);
+        if !nb {
+            // no other requests before tasks are scheduled
+            let len = loop {
+                match inner.ring.pop_used() {
+                    Some((head, len)) => {
+                        assert_eq!(head, token);
+                        break len;
+                    }
+                    None => spin_loop(),
+                }
+            };
+            inner.ring.recycle(token);
+            return Some(len);
+        }
+        inner.waiting[token as usize] = true;
+        // the descriptors are kept until it is done so the token is not reused
+        let len = loop {
+            if let Some(len) = inner.done[token as usize].take() {
+                break len;
+            }
+            // woken up for another reason otherwise
+            let task_cx_ptr = self.condvars[token as usize].wait_no_sched();
+            drop(inner);
+            schedule(task_cx_ptr);
+            inner = self.inner.exclusive_access();
+        };
+        inner.waiting[token as usize] = false;
+        inner.recycle(token);
+        drop(inner);
+        self.free.signal();
+        Some(len)
+    }
+
+    /// Submit a request like `request` without waiting for it, return the
+    /// token to poll it with `poll_request`. It is pending if there are not
+    /// enough free descriptors, until some are freed.
+    ///
+    /// # Safety
+    ///
+    /// The buffers must be alive until the request is done.
+    pub unsafe fn poll_submit(
+        &self,
+        inputs: &[&[u8]],
+        outputs: &mut [&mut [u8]],
+        cx: &mut Context<'_>,
+    ) -> Poll<u16> {
+        let segments = Self::segments(inputs, outputs);
+        let nb = *DEV_NON_BLOCKING_ACCESS.exclusive_access();
+        let mut inner = self.inner.exclusive_access();
+        // or it would be pending forever
+        assert!(
+            segments.len() <= inner.ring.size as usize,
+            "too many buffers for virtqueue {}",
+            self.index
+        );
+        if (inner.ring.num_free as usize) < segments.len() {
+            assert!(nb, "virtqueue {} is full", self.index);
+            inner.free_wakers.push(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let token = inner.ring.add(&segments);
+        inner.waiting[token as usize] = true;
+        inner.wakers[token as usize] = Some(cx.waker().clone());
+        self.notify(&inner.ring);
+        Poll::Ready(token)
+    }
+
+    /// Poll a request submitted by `poll_submit`, return the length written
+    /// once it completes, when the token is no longer valid. Before tasks
+    /// are scheduled, it polls the used ring until then.
+    pub fn poll_request(&self, token: u16, cx: &mut Context<'_>) -> Poll<u32> {
+        let nb = *DEV_NON_BLOCKING_ACCESS.exclusive_access();
+        let mut inner = self.inner.exclusive_access();
+        while !nb && inner.done[token as usize].is_none() {
+            match inner.ring.pop_used() {
+                Some((head, len)) => inner.done[head as usize] = Some(len),
+                None => spin_loop(),
+            }
+        }
+        match inner.done[token as usize].take() {
+            Some(len) => {
+                inner.waiting[token as usize] = false;
+                inner.wakers[token as usize] = None;
+                inner.recycle(token);
+                drop(inner);
+                self.free.signal();
+                Poll::Ready(len)
+            }
+            None => {
+                inner.wakers[token as usize] = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Post buffers written by the device at any time, return the token
+    /// passed to the callback of `handle_irq` when they are used.
+    ///
+    /// # Safety
+    ///
+    /// The buffers must be alive until they are used.
+    pub unsafe fn post(&self, outputs: &mut [&mut [u8]]) -> u16 {
+        let segments = Self::segments(&[], outputs);
+        let mut inner = self.inner.exclusive_access();
+        assert!(
+            segments.len() <= inner.ring.num_free as usize,
+            "virtqueue {} is full",
+            self.index
+        );
+        let token = inner.ring.add(&segments);
+        self.notify(&inner.ring);
+        token
+    }
+
+    /// Collect used buffers on an interrupt. The waiting requests are woken
+    /// up, and `used` is called with the token of each posted one and the
+    /// length written.
+    pub fn handle_irq(&self, mut used: impl FnMut(u16, u32)) {
+        let mut completed = Vec::new();
+        let mut woken = Vec::new();
+        let mut posted = Vec::new();
+        self.inner.exclusive_session(|inner| {
+            while let Some((token, len)) = inner.ring.pop_used() {
+                if inner.waiting[token as usize] {
+                    inner.done[token as usize] = Some(len);
+                    match inner.wakers[token as usize].take() {
+                        Some(waker) => woken.push(waker),
+                        None => completed.push(token),
+                    }
+                } else {
+                    inner.recycle(token);
+                    posted.push((token, len));
+                }
+            }
+        });
+        for token in completed {
+            self.condvars[token as usize].signal();
+        }
+        for waker in woken {
+            waker.wake();
+        }
+        if !posted.is_empty() {
+            self.free.signal();
+        }
+        for (token, len) in posted {
+            used(token, len);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Ref: virtio v1.1 4.2 Virtio Over MMIO
+
+use crate::config::PAGE_SIZE;
+use crate::drivers::mmio::{mmio_read, mmio_write};
+
+const MAGIC_VALUE: u32 = 0x7472_6976;
+const LEGACY_VERSION: u32 = 1;
+const MODERN_VERSION: u32 = 2;
+
+const MAGIC: usize = 0x000;
+const VERSION: usize = 0x004;
+const DEVICE_ID: usize = 0x008;
+const DEVICE_FEATURES: usize = 0x010;
+const DEVICE_FEATURES_SEL: usize = 0x014;
+const DRIVER_FEATURES: usize = 0x020;
+const DRIVER_FEATURES_SEL: usize = 0x024;
+const LEGACY_GUEST_PAGE_SIZE: usize = 0x028;
+const QUEUE_SEL: usize = 0x030;
+const QUEUE_NUM_MAX: usize = 0x034;
+const QUEUE_NUM: usize = 0x038;
+const LEGACY_QUEUE_ALIGN: usize = 0x03c;
+const LEGACY_QUEUE_PFN: usize = 0x040;
+const QUEUE_READY: usize = 0x044;
+const QUEUE_NOTIFY: usize = 0x050;
+const INTERRUPT_STATUS: usize = 0x060;
+const INTERRUPT_ACK: usize = 0x064;
+const STATUS: usize = 0x070;
+const QUEUE_DESC_LOW: usize = 0x080;
+const QUEUE_DESC_HIGH: usize = 0x084;
+const QUEUE_DRIVER_LOW: usize = 0x090;
+const QUEUE_DRIVER_HIGH: usize = 0x094;
+const QUEUE_DEVICE_LOW: usize = 0x0a0;
+const QUEUE_DEVICE_HIGH: usize = 0x0a4;
+const CONFIG: usize = 0x100;
+
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+const STATUS_FEATURES_OK: u32 = 8;
+
+/// Set by devices which are not legacy ones.
+const FEATURE_VERSION_1: u64 = 1 << 32;
+
+#[allow(unused)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u32)]
+pub enum DeviceType {
+    Network = 1,
+    Block = 2,
+    Gpu = 16,
+    Input = 18,
+}
+
+/// The registers of a virtio device over MMIO, both the legacy interface and
+/// the modern one are supported.
+#[derive(Copy, Clone)]
+pub struct MmioTransport {
+    base: usize,
+    version: u32,
+}
+
+impl MmioTransport {
+    /// Probe the device at `base`, return None if it is not a virtio device
+    /// of `device_type`.
+    pub fn new(base: usize, device_type: DeviceType) -> Option<Self> {
+        let mut transport = Self { base, version: 0 };
+        if transport.read(MAGIC) != MAGIC_VALUE || transport.read(DEVICE_ID) != device_type as u32 {
+            return None;
+        }
+        transport.version = transport.read(VERSION);
+        match transport.version {
+            LEGACY_VERSION | MODERN_VERSION => Some(transport),
+            _ => None,
+        }
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        self.version == LEGACY_VERSION
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        mmio_read(self.base + offset)
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        mmio_write(self.base + offset, value);
+    }
+
+    /// Reset the device and negotiate features, only the ones both in
+    /// `features` and offered by the device are accepted and returned.
+    ///
+    /// Ref: virtio v1.1 3.1.1 Driver Requirements: Device Initialization
+    pub fn begin_init(&self, features: u64) -> u64 {
+        self.write(STATUS, 0);
+        self.write(STATUS, STATUS_ACKNOWLEDGE);
+        self.write(STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+        self.write(DEVICE_FEATURES_SEL, 0);
+        let mut device_features = self.read(DEVICE_FEATURES) as u64;
+        self.write(DEVICE_FEATURES_SEL, 1);
+        device_features |= (self.read(DEVICE_FEATURES) as u64) << 32;
+        let mut driver_features = device_features & features;
+        if self.version == MODERN_VERSION {
+            driver_features |= device_features & FEATURE_VERSION_1;
+        }
+        self.write(DRIVER_FEATURES_SEL, 0);
+        self.write(DRIVER_FEATURES, driver_features as u32);
+        self.write(DRIVER_FEATURES_SEL, 1);
+        self.write(DRIVER_FEATURES, (driver_features >> 32) as u32);
+        if self.version == MODERN_VERSION {
+            let status = STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK;
+            self.write(STATUS, status);
+            assert!(
+                self.read(STATUS) & STATUS_FEATURES_OK != 0,
+                "virtio device at {:#x} rejects the features",
+                self.base
+            );
+        } else {
+            self.write(LEGACY_GUEST_PAGE_SIZE, PAGE_SIZE as u32);
+        }
+        driver_features
+    }
+
+    /// Let the device work after the queues are set up.
+    pub fn finish_init(&self) {
+        self.write(STATUS, self.read(STATUS) | STATUS_DRIVER_OK);
+    }
+
+    pub fn max_queue_size(&self, queue: u16) -> u16 {
+        self.write(QUEUE_SEL, queue as u32);
+        self.read(QUEUE_NUM_MAX).min(u16::MAX as u32) as u16
+    }
+
+    /// Tell the device where the descriptor table, the available ring and
+    /// the used ring of a queue are. The legacy interface needs them to be
+    /// laid out contiguously from a page, with the used ring page aligned.
+    pub fn setup_queue(&self, queue: u16, size: u16, desc: usize, avail: usize, used: usize) {
+        self.write(QUEUE_SEL, queue as u32);
+        self.write(QUEUE_NUM, size as u32);
+        if self.version == LEGACY_VERSION {
+            assert_eq!(desc % PAGE_SIZE, 0);
+            self.write(LEGACY_QUEUE_ALIGN, PAGE_SIZE as u32);
+            self.write(LEGACY_QUEUE_PFN, (desc / PAGE_SIZE) as u32);
+        } else {
+            self.write(QUEUE_DESC_LOW, desc as u32);
+            self.write(QUEUE_DESC_HIGH, (desc >> 32) as u32);
+            self.write(QUEUE_DRIVER_LOW, avail as u32);
+            self.write(QUEUE_DRIVER_HIGH, (avail >> 32) as u32);
+            self.write(QUEUE_DEVICE_LOW, used as u32);
+            self.write(QUEUE_DEVICE_HIGH, (used >> 32) as u32);
+            self.write(QUEUE_READY, 1);
+        }
+    }
+
+    pub fn notify(&self, queue: u16) {
+        self.write(QUEUE_NOTIFY, queue as u32);
+    }
+
+    /// Acknowledge the interrupt, return whether there is one.
+    pub fn ack_interrupt(&self) -> bool {
+        let status = self.read(INTERRUPT_STATUS);
+        if status != 0 {
+            self.write(INTERRUPT_ACK, status);
+        }
+        status != 0
+    }
+
+    /// Read the device-specific configuration at `offset`.
+    #[allow(unused)]
+    pub fn config_read<T: Copy + Into<u64>>(&self, offset: usize) -> T {
+        mmio_read(self.base + CONFIG + offset)
+    }
+
+    #[allow(unused)]
+    pub fn config_write<T: Copy + Into<u64>>(&self, offset: usize, value: T) {
+        mmio_write(self.base + CONFIG + offset, value);
+    }
+}
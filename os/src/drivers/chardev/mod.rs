@@ -1,17 +1,88 @@
+#[cfg(not(feature = "board_sifive_u"))]
 mod ns16550a;
+mod read_buffer;
+#[cfg(feature = "board_sifive_u")]
+mod sifive_uart;
+mod write_buffer;
 
-use crate::board::CharDeviceImpl;
+use crate::boards::{char_device, CharDeviceImpl};
+use crate::sync::block_on_cancellable;
+use crate::timer::with_timeout;
 use alloc::sync::Arc;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
 use lazy_static::*;
+#[cfg(not(feature = "board_sifive_u"))]
 pub use ns16550a::NS16550a;
+use read_buffer::ReadBuffer;
+#[cfg(feature = "board_sifive_u")]
+pub use sifive_uart::SifiveUart;
+use write_buffer::WriteBuffer;
 
 pub trait CharDevice {
     fn init(&self);
-    fn read(&self) -> u8;
+    /// Poll for a received byte, see `ReadBuffer::poll_read`.
+    fn poll_read(&self, ticket: &mut Option<usize>, cx: &mut Context<'_>) -> Poll<u8>;
+    /// Stop waiting for a byte with `ticket`.
+    fn cancel_read(&self, ticket: usize);
+    /// Queue bytes to send as the transmitter has room, see
+    /// `WriteBuffer::poll_push`.
+    fn poll_write(&self, bytes: &[u8], cx: &mut Context<'_>) -> Poll<usize>;
+    /// Send a byte at once, after the bytes queued.
     fn write(&self, ch: u8);
+    /// Send the bytes queued at once, unless the device is held, which the
+    /// panicked code may do.
+    fn emergency_flush(&self);
     fn handle_irq(&self);
+    fn read_buffer_is_empty(&self) -> bool;
+
+    /// Wait for a byte, the current task is blocked meanwhile. None if it is
+    /// exiting or signalled before a byte comes, which is left for the other
+    /// readers.
+    fn read(&self) -> Option<u8> {
+        block_on_cancellable(AsyncCharReader::new(self))
+    }
+
+    /// Like `read`, but also give up once the time reaches `expire_ns`.
+    fn read_until(&self, expire_ns: usize) -> Option<u8> {
+        block_on_cancellable(with_timeout(AsyncCharReader::new(self), expire_ns)).flatten()
+    }
+}
+
+/// A byte to read from a `CharDevice`, the readers get the bytes in the
+/// order they start waiting.
+pub struct AsyncCharReader<'a, D: CharDevice + ?Sized> {
+    device: &'a D,
+    ticket: Option<usize>,
+}
+
+impl<'a, D: CharDevice + ?Sized> AsyncCharReader<'a, D> {
+    pub fn new(device: &'a D) -> Self {
+        Self {
+            device,
+            ticket: None,
+        }
+    }
+}
+
+impl<D: CharDevice + ?Sized> Future for AsyncCharReader<'_, D> {
+    type Output = u8;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u8> {
+        let this = self.get_mut();
+        this.device.poll_read(&mut this.ticket, cx)
+    }
+}
+
+impl<D: CharDevice + ?Sized> Drop for AsyncCharReader<'_, D> {
+    fn drop(&mut self) {
+        if let Some(ticket) = self.ticket.take() {
+            self.device.cancel_read(ticket);
+        }
+    }
 }
 
 lazy_static! {
-    pub static ref UART: Arc<CharDeviceImpl> = Arc::new(CharDeviceImpl::new());
+    pub static ref UART: Arc<CharDeviceImpl> = Arc::new(char_device());
 }
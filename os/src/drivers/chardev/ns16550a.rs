@@ -1,12 +1,13 @@
 ///! Ref: https://www.lammertbies.nl/comm/info/serial-uart
 ///! Ref: ns16550a datasheet: https://datasheetspdf.com/pdf-file/605590/NationalSemiconductor/NS16550A/1
 ///! Ref: ns16450 datasheet: https://datasheetspdf.com/pdf-file/1311818/NationalSemiconductor/NS16450/1
-use super::CharDevice;
-use crate::sync::{Condvar, UPIntrFreeCell};
-use crate::task::schedule;
-use alloc::collections::VecDeque;
+use super::{CharDevice, ReadBuffer, WriteBuffer};
+use crate::drivers::mmio::{mmio_read, mmio_write};
+use crate::fs::CONSOLE_TTY;
+use crate::sync::SpinLockIrqSave;
+use crate::sysrq;
 use bitflags::*;
-use volatile::{ReadOnly, Volatile, WriteOnly};
+use core::task::{Context, Poll};
 
 bitflags! {
     /// InterruptEnableRegister
@@ -18,6 +19,7 @@ bitflags! {
     /// LineStatusRegister
     pub struct LSR: u8 {
         const DATA_AVAILABLE = 1 << 0;
+        const BREAK_INTERRUPT = 1 << 4;
         const THR_EMPTY = 1 << 5;
     }
 
@@ -30,44 +32,20 @@ bitflags! {
     }
 }
 
-#[repr(C)]
-#[allow(dead_code)]
-struct ReadWithoutDLAB {
-    /// receiver buffer register
-    pub rbr: ReadOnly<u8>,
-    /// interrupt enable register
-    pub ier: Volatile<IER>,
-    /// interrupt identification register
-    pub iir: ReadOnly<u8>,
-    /// line control register
-    pub lcr: Volatile<u8>,
-    /// model control register
-    pub mcr: Volatile<MCR>,
-    /// line status register
-    pub lsr: ReadOnly<LSR>,
-    /// ignore MSR
-    _padding1: ReadOnly<u8>,
-    /// ignore SCR
-    _padding2: ReadOnly<u8>,
-}
-
-#[repr(C)]
-#[allow(dead_code)]
-struct WriteWithoutDLAB {
-    /// transmitter holding register
-    pub thr: WriteOnly<u8>,
-    /// interrupt enable register
-    pub ier: Volatile<IER>,
-    /// ignore FCR
-    _padding0: ReadOnly<u8>,
-    /// line control register
-    pub lcr: Volatile<u8>,
-    /// modem control register
-    pub mcr: Volatile<MCR>,
-    /// line status register
-    pub lsr: ReadOnly<LSR>,
-    /// ignore other registers
-    _padding1: ReadOnly<u16>,
+/// receiver buffer register when read, transmitter holding register when
+/// written, all offsets are without DLAB
+const RBR_THR_OFFSET: usize = 0;
+/// interrupt enable register
+const IER_OFFSET: usize = 1;
+/// modem control register
+const MCR_OFFSET: usize = 4;
+/// line status register
+const LSR_OFFSET: usize = 5;
+
+pub enum Received {
+    Byte(u8),
+    /// the line is held low, the zero byte received with it is dropped
+    Break,
 }
 
 pub struct NS16550aRaw {
@@ -75,12 +53,16 @@ pub struct NS16550aRaw {
 }
 
 impl NS16550aRaw {
-    fn read_end(&mut self) -> &mut ReadWithoutDLAB {
-        unsafe { &mut *(self.base_addr as *mut ReadWithoutDLAB) }
+    fn read_reg(&self, offset: usize) -> u8 {
+        mmio_read(self.base_addr + offset)
+    }
+
+    fn write_reg(&mut self, offset: usize, value: u8) {
+        mmio_write(self.base_addr + offset, value);
     }
 
-    fn write_end(&mut self) -> &mut WriteWithoutDLAB {
-        unsafe { &mut *(self.base_addr as *mut WriteWithoutDLAB) }
+    fn lsr(&self) -> LSR {
+        LSR::from_bits_truncate(self.read_reg(LSR_OFFSET))
     }
 
     pub fn new(base_addr: usize) -> Self {
@@ -88,99 +70,138 @@ impl NS16550aRaw {
     }
 
     pub fn init(&mut self) {
-        let read_end = self.read_end();
         let mut mcr = MCR::empty();
         mcr |= MCR::DATA_TERMINAL_READY;
         mcr |= MCR::REQUEST_TO_SEND;
         mcr |= MCR::AUX_OUTPUT2;
-        read_end.mcr.write(mcr);
+        self.write_reg(MCR_OFFSET, mcr.bits());
         let ier = IER::RX_AVAILABLE;
-        read_end.ier.write(ier);
+        self.write_reg(IER_OFFSET, ier.bits());
     }
 
-    pub fn read(&mut self) -> Option<u8> {
-        let read_end = self.read_end();
-        let lsr = read_end.lsr.read();
-        if lsr.contains(LSR::DATA_AVAILABLE) {
-            Some(read_end.rbr.read())
+    pub fn read(&mut self) -> Option<Received> {
+        // the break bit is cleared once the LSR is read
+        let lsr = self.lsr();
+        if !lsr.contains(LSR::DATA_AVAILABLE) {
+            return None;
+        }
+        let ch = self.read_reg(RBR_THR_OFFSET);
+        if lsr.contains(LSR::BREAK_INTERRUPT) {
+            Some(Received::Break)
         } else {
-            None
+            Some(Received::Byte(ch))
         }
     }
 
     pub fn write(&mut self, ch: u8) {
-        let write_end = self.write_end();
         loop {
-            if write_end.lsr.read().contains(LSR::THR_EMPTY) {
-                write_end.thr.write(ch);
+            if self.lsr().contains(LSR::THR_EMPTY) {
+                self.write_reg(RBR_THR_OFFSET, ch);
                 break;
             }
         }
     }
+
+    /// Send the bytes queued while the transmitter is empty, and be
+    /// interrupted once it is empty again if any is left.
+    pub fn transmit(&mut self, write_buffer: &mut WriteBuffer) {
+        while self.lsr().contains(LSR::THR_EMPTY) {
+            match write_buffer.pop() {
+                Some(ch) => self.write_reg(RBR_THR_OFFSET, ch),
+                None => break,
+            }
+        }
+        let mut ier = IER::RX_AVAILABLE;
+        ier.set(IER::TX_EMPTY, !write_buffer.is_empty());
+        self.write_reg(IER_OFFSET, ier.bits());
+    }
 }
 
 struct NS16550aInner {
     ns16550a: NS16550aRaw,
-    read_buffer: VecDeque<u8>,
+    read_buffer: ReadBuffer,
+    write_buffer: WriteBuffer,
 }
 
 pub struct NS16550a<const BASE_ADDR: usize> {
-    inner: UPIntrFreeCell<NS16550aInner>,
-    condvar: Condvar,
+    inner: SpinLockIrqSave<NS16550aInner>,
 }
 
 impl<const BASE_ADDR: usize> NS16550a<BASE_ADDR> {
     pub fn new() -> Self {
         let inner = NS16550aInner {
             ns16550a: NS16550aRaw::new(BASE_ADDR),
-            read_buffer: VecDeque::new(),
+            read_buffer: ReadBuffer::new(),
+            write_buffer: WriteBuffer::new(),
         };
         //inner.ns16550a.init();
         Self {
-            inner: unsafe { UPIntrFreeCell::new(inner) },
-            condvar: Condvar::new(),
+            inner: SpinLockIrqSave::new(inner),
         }
     }
-
-    pub fn read_buffer_is_empty(&self) -> bool {
-        self.inner
-            .exclusive_session(|inner| inner.read_buffer.is_empty())
-    }
 }
 
 impl<const BASE_ADDR: usize> CharDevice for NS16550a<BASE_ADDR> {
     fn init(&self) {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
         inner.ns16550a.init();
-        drop(inner);
+        // what is queued before a suspend
+        inner.ns16550a.transmit(&mut inner.write_buffer);
     }
 
-    fn read(&self) -> u8 {
-        loop {
-            let mut inner = self.inner.exclusive_access();
-            if let Some(ch) = inner.read_buffer.pop_front() {
-                return ch;
-            } else {
-                let task_cx_ptr = self.condvar.wait_no_sched();
-                drop(inner);
-                schedule(task_cx_ptr);
-            }
-        }
+    fn poll_read(&self, ticket: &mut Option<usize>, cx: &mut Context<'_>) -> Poll<u8> {
+        self.inner.lock().read_buffer.poll_read(ticket, cx)
+    }
+    fn cancel_read(&self, ticket: usize) {
+        self.inner.lock().read_buffer.cancel(ticket);
+    }
+    fn poll_write(&self, bytes: &[u8], cx: &mut Context<'_>) -> Poll<usize> {
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+        let queued = inner.write_buffer.poll_push(bytes, cx);
+        inner.ns16550a.transmit(&mut inner.write_buffer);
+        queued
     }
     fn write(&self, ch: u8) {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner.lock();
+        while let Some(queued) = inner.write_buffer.pop() {
+            inner.ns16550a.write(queued);
+        }
         inner.ns16550a.write(ch);
     }
+    fn emergency_flush(&self) {
+        if let Some(mut inner) = self.inner.try_lock() {
+            for ch in inner.write_buffer.take_all() {
+                inner.ns16550a.write(ch);
+            }
+        }
+    }
     fn handle_irq(&self) {
-        let mut count = 0;
-        self.inner.exclusive_session(|inner| {
-            while let Some(ch) = inner.ns16550a.read() {
-                count += 1;
-                inner.read_buffer.push_back(ch);
+        {
+            let mut inner = self.inner.lock();
+            let inner = &mut *inner;
+            inner.ns16550a.transmit(&mut inner.write_buffer);
+        }
+        // SysRq commands and the echo of Ctrl-C print to the UART, so it is
+        // not held for them
+        loop {
+            // not held while the loop body runs
+            let received = self.inner.lock().ns16550a.read();
+            let Some(received) = received else {
+                break;
+            };
+            match received {
+                Received::Byte(ch) if sysrq::handle_input(ch) && CONSOLE_TTY.handle_input(ch) => {
+                    self.inner.lock().read_buffer.push(ch);
+                    CONSOLE_TTY.notify_received();
+                }
+                Received::Byte(_) => {}
+                Received::Break => sysrq::handle_break(),
             }
-        });
-        if count > 0 {
-            self.condvar.signal();
         }
     }
+    fn read_buffer_is_empty(&self) -> bool {
+        self.inner.lock().read_buffer.is_empty()
+    }
 }
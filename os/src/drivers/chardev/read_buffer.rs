@@ -0,0 +1,93 @@
+use crate::latency::UART_WAKEUP_LATENCY;
+use crate::timer::get_time_us;
+use alloc::collections::{BTreeMap, VecDeque};
+use core::task::{Context, Poll, Waker};
+
+/// Bytes received by a UART, handed out to the readers in the order they
+/// started waiting, so a reader coming later cannot take the bytes for the
+/// ones woken up before.
+pub struct ReadBuffer {
+    /// the bytes for no reader
+    bytes: VecDeque<u8>,
+    /// the readers waiting by their tickets, the longest waiting first
+    read_waker_list: VecDeque<(usize, Waker)>,
+    /// the bytes handed over to the readers woken up, by their tickets, with
+    /// the time they are received in us
+    handed_over: BTreeMap<usize, (u8, usize)>,
+    next_ticket: usize,
+}
+
+impl ReadBuffer {
+    pub fn new() -> Self {
+        Self {
+            bytes: VecDeque::new(),
+            read_waker_list: VecDeque::new(),
+            handed_over: BTreeMap::new(),
+            next_ticket: 0,
+        }
+    }
+
+    /// Hand a received byte to the reader waiting the longest, if any.
+    pub fn push(&mut self, ch: u8) {
+        match self.read_waker_list.pop_front() {
+            Some((ticket, waker)) => {
+                self.handed_over.insert(ticket, (ch, get_time_us()));
+                waker.wake();
+            }
+            None => self.bytes.push_back(ch),
+        }
+    }
+
+    /// Poll for a byte. A new reader without a ticket takes one if there is
+    /// any, or gets a ticket to wait in line.
+    pub fn poll_read(&mut self, ticket: &mut Option<usize>, cx: &mut Context<'_>) -> Poll<u8> {
+        let waiting = match *ticket {
+            Some(waiting) => waiting,
+            None => {
+                if let Some(ch) = self.bytes.pop_front() {
+                    return Poll::Ready(ch);
+                }
+                let waiting = self.next_ticket;
+                self.next_ticket += 1;
+                *ticket = Some(waiting);
+                self.read_waker_list
+                    .push_back((waiting, cx.waker().clone()));
+                return Poll::Pending;
+            }
+        };
+        if let Some((ch, received_us)) = self.handed_over.remove(&waiting) {
+            *ticket = None;
+            UART_WAKEUP_LATENCY.record(get_time_us() - received_us);
+            return Poll::Ready(ch);
+        }
+        // polled again before a byte is handed over, keep the place in line
+        if let Some((_, waker)) = self
+            .read_waker_list
+            .iter_mut()
+            .find(|(other, _)| *other == waiting)
+        {
+            *waker = cx.waker().clone();
+        }
+        Poll::Pending
+    }
+
+    /// A reader with `ticket` stops waiting, the byte handed over to it, if
+    /// any, goes to the next one in line.
+    pub fn cancel(&mut self, ticket: usize) {
+        self.read_waker_list.retain(|(other, _)| *other != ticket);
+        if let Some(handed_over) = self.handed_over.remove(&ticket) {
+            match self.read_waker_list.pop_front() {
+                Some((next, waker)) => {
+                    self.handed_over.insert(next, handed_over);
+                    waker.wake();
+                }
+                None => self.bytes.push_front(handed_over.0),
+            }
+        }
+    }
+
+    /// Whether there is no byte for a new reader.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
@@ -0,0 +1,168 @@
+//! Ref: SiFive FU540-C000 Manual, Chapter 13 Universal Asynchronous Receiver/Transmitter
+
+use super::{CharDevice, ReadBuffer, WriteBuffer};
+use crate::drivers::mmio::{mmio_read, mmio_write};
+use crate::fs::CONSOLE_TTY;
+use crate::sync::SpinLockIrqSave;
+use crate::sysrq;
+use core::task::{Context, Poll};
+
+/// transmit data register, bit 31 is set when the FIFO is full
+const TXDATA_OFFSET: usize = 0x00;
+/// receive data register, bit 31 is set when the FIFO is empty
+const RXDATA_OFFSET: usize = 0x04;
+/// transmit control register
+const TXCTRL_OFFSET: usize = 0x08;
+/// receive control register
+const RXCTRL_OFFSET: usize = 0x0c;
+/// interrupt enable register
+const IE_OFFSET: usize = 0x10;
+
+const FIFO_FLAG: u32 = 1 << 31;
+const TXCTRL_TXEN: u32 = 1 << 0;
+/// the transmit watermark is 1, so it is pending once the FIFO is empty
+const TXCTRL_TXCNT_1: u32 = 1 << 16;
+/// the receive watermark is 0, so it is pending whenever data is available
+const RXCTRL_RXEN: u32 = 1 << 0;
+const IE_TXWM: u32 = 1 << 0;
+const IE_RXWM: u32 = 1 << 1;
+
+pub struct SifiveUartRaw {
+    base_addr: usize,
+}
+
+impl SifiveUartRaw {
+    fn read_reg(&self, offset: usize) -> u32 {
+        mmio_read(self.base_addr + offset)
+    }
+
+    fn write_reg(&mut self, offset: usize, value: u32) {
+        mmio_write(self.base_addr + offset, value);
+    }
+
+    pub fn new(base_addr: usize) -> Self {
+        Self { base_addr }
+    }
+
+    pub fn init(&mut self) {
+        self.write_reg(TXCTRL_OFFSET, TXCTRL_TXEN | TXCTRL_TXCNT_1);
+        self.write_reg(RXCTRL_OFFSET, RXCTRL_RXEN);
+        self.write_reg(IE_OFFSET, IE_RXWM);
+    }
+
+    pub fn read(&mut self) -> Option<u8> {
+        let rxdata = self.read_reg(RXDATA_OFFSET);
+        if rxdata & FIFO_FLAG == 0 {
+            Some(rxdata as u8)
+        } else {
+            None
+        }
+    }
+
+    pub fn write(&mut self, ch: u8) {
+        while self.read_reg(TXDATA_OFFSET) & FIFO_FLAG != 0 {}
+        self.write_reg(TXDATA_OFFSET, ch as u32);
+    }
+
+    /// Send the bytes queued while the FIFO has room, and be interrupted
+    /// once it is empty if any is left.
+    pub fn transmit(&mut self, write_buffer: &mut WriteBuffer) {
+        while self.read_reg(TXDATA_OFFSET) & FIFO_FLAG == 0 {
+            match write_buffer.pop() {
+                Some(ch) => self.write_reg(TXDATA_OFFSET, ch as u32),
+                None => break,
+            }
+        }
+        let ie = if write_buffer.is_empty() {
+            IE_RXWM
+        } else {
+            IE_RXWM | IE_TXWM
+        };
+        self.write_reg(IE_OFFSET, ie);
+    }
+}
+
+struct SifiveUartInner {
+    uart: SifiveUartRaw,
+    read_buffer: ReadBuffer,
+    write_buffer: WriteBuffer,
+}
+
+pub struct SifiveUart<const BASE_ADDR: usize> {
+    inner: SpinLockIrqSave<SifiveUartInner>,
+}
+
+impl<const BASE_ADDR: usize> SifiveUart<BASE_ADDR> {
+    pub fn new() -> Self {
+        let inner = SifiveUartInner {
+            uart: SifiveUartRaw::new(BASE_ADDR),
+            read_buffer: ReadBuffer::new(),
+            write_buffer: WriteBuffer::new(),
+        };
+        Self {
+            inner: SpinLockIrqSave::new(inner),
+        }
+    }
+}
+
+impl<const BASE_ADDR: usize> CharDevice for SifiveUart<BASE_ADDR> {
+    fn init(&self) {
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+        inner.uart.init();
+        // what is queued before a suspend
+        inner.uart.transmit(&mut inner.write_buffer);
+    }
+
+    fn poll_read(&self, ticket: &mut Option<usize>, cx: &mut Context<'_>) -> Poll<u8> {
+        self.inner.lock().read_buffer.poll_read(ticket, cx)
+    }
+    fn cancel_read(&self, ticket: usize) {
+        self.inner.lock().read_buffer.cancel(ticket);
+    }
+    fn poll_write(&self, bytes: &[u8], cx: &mut Context<'_>) -> Poll<usize> {
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+        let queued = inner.write_buffer.poll_push(bytes, cx);
+        inner.uart.transmit(&mut inner.write_buffer);
+        queued
+    }
+    fn write(&self, ch: u8) {
+        let mut inner = self.inner.lock();
+        while let Some(queued) = inner.write_buffer.pop() {
+            inner.uart.write(queued);
+        }
+        inner.uart.write(ch);
+    }
+    fn emergency_flush(&self) {
+        if let Some(mut inner) = self.inner.try_lock() {
+            for ch in inner.write_buffer.take_all() {
+                inner.uart.write(ch);
+            }
+        }
+    }
+    fn handle_irq(&self) {
+        {
+            let mut inner = self.inner.lock();
+            let inner = &mut *inner;
+            inner.uart.transmit(&mut inner.write_buffer);
+        }
+        // SysRq commands and the echo of Ctrl-C print to the UART, so it is
+        // not held for them, and only the escape sequence works as breaks are
+        // not reported
+        loop {
+            // not held while the loop body runs
+            let received = self.inner.lock().uart.read();
+            let Some(ch) = received else {
+                break;
+            };
+            if sysrq::handle_input(ch) && CONSOLE_TTY.handle_input(ch) {
+                self.inner.lock().read_buffer.push(ch);
+                CONSOLE_TTY.notify_received();
+            }
+        }
+    }
+    fn read_buffer_is_empty(&self) -> bool {
+        self.inner.lock().read_buffer.is_empty()
+    }
+}
@@ -0,0 +1,55 @@
+use alloc::collections::VecDeque;
+use core::task::{Context, Poll, Waker};
+
+/// The bytes queued at most.
+const WRITE_BUFFER_SIZE: usize = 4096;
+
+/// Bytes to transmit by a UART, sent by the interrupt handler whenever the
+/// transmitter has room, so the writer queues many of them at once instead
+/// of waiting for each.
+pub struct WriteBuffer {
+    bytes: VecDeque<u8>,
+    /// the writer waiting for room, woken up once half of it is free
+    waker: Option<Waker>,
+}
+
+impl WriteBuffer {
+    pub fn new() -> Self {
+        Self {
+            bytes: VecDeque::new(),
+            waker: None,
+        }
+    }
+
+    /// Queue as many of `bytes` as there is room for and return how many, or
+    /// wait for room if there is none.
+    pub fn poll_push(&mut self, bytes: &[u8], cx: &mut Context<'_>) -> Poll<usize> {
+        let len = bytes.len().min(WRITE_BUFFER_SIZE - self.bytes.len());
+        if len == 0 && !bytes.is_empty() {
+            self.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        self.bytes.extend(&bytes[..len]);
+        Poll::Ready(len)
+    }
+
+    /// Take the next byte to send.
+    pub fn pop(&mut self) -> Option<u8> {
+        let ch = self.bytes.pop_front()?;
+        if self.bytes.len() <= WRITE_BUFFER_SIZE / 2 {
+            if let Some(waker) = self.waker.take() {
+                waker.wake();
+            }
+        }
+        Some(ch)
+    }
+
+    /// Take all the bytes queued without waking the writer up, for a panic.
+    pub fn take_all(&mut self) -> VecDeque<u8> {
+        core::mem::take(&mut self.bytes)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
@@ -0,0 +1,43 @@
+//! Core Local Interruptor, compatible with the MTIMER and MSWI of ACLINT.
+//!
+//! The registers are for the machine mode, so the firmware must grant the
+//! kernel access to them with PMP. Also, the machine timer and software
+//! interrupts cannot be delegated, the firmware has to pass them on as
+//! supervisor ones.
+//!
+//! Ref: SiFive FU540-C000 Manual, Chapter 9 Core Local Interruptor
+
+use super::mmio::{mmio_read, mmio_write};
+
+const MSIP_OFFSET: usize = 0x0000;
+const MTIMECMP_OFFSET: usize = 0x4000;
+const MTIME_OFFSET: usize = 0xbff8;
+
+pub struct Clint {
+    base_addr: usize,
+}
+
+impl Clint {
+    pub fn new(base_addr: usize) -> Self {
+        Self { base_addr }
+    }
+
+    pub fn mtime(&self) -> u64 {
+        mmio_read(self.base_addr + MTIME_OFFSET)
+    }
+
+    /// Raise the timer interrupt of `hart_id` when `mtime` reaches `time`,
+    /// a pending one is cleared if it is in the future.
+    pub fn set_timer(&self, hart_id: usize, time: u64) {
+        mmio_write(self.base_addr + MTIMECMP_OFFSET + 8 * hart_id, time);
+    }
+
+    /// Raise the software interrupt of `hart_id`.
+    pub fn send_soft(&self, hart_id: usize) {
+        mmio_write(self.base_addr + MSIP_OFFSET + 4 * hart_id, 1u32);
+    }
+
+    pub fn clear_soft(&self, hart_id: usize) {
+        mmio_write(self.base_addr + MSIP_OFFSET + 4 * hart_id, 0u32);
+    }
+}
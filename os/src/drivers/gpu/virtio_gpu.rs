@@ -0,0 +1,310 @@
+use super::GpuDevice;
+use crate::config::PAGE_SIZE;
+use crate::drivers::bus::virtio::{as_bytes, as_bytes_mut, DeviceType, MmioTransport, VirtQueue};
+use crate::mm::DmaBuffer;
+use alloc::vec::Vec;
+use embedded_graphics::pixelcolor::Rgb888;
+use tinybmp::Bmp;
+
+const VIRTIO7: usize = 0x10007000;
+
+const QUEUE_SIZE: u16 = 2;
+const QUEUE_CONTROL: u16 = 0;
+const QUEUE_CURSOR: u16 = 1;
+
+const CMD_GET_DISPLAY_INFO: u32 = 0x100;
+const CMD_RESOURCE_CREATE_2D: u32 = 0x101;
+const CMD_SET_SCANOUT: u32 = 0x103;
+const CMD_RESOURCE_FLUSH: u32 = 0x104;
+const CMD_TRANSFER_TO_HOST_2D: u32 = 0x105;
+const CMD_RESOURCE_ATTACH_BACKING: u32 = 0x106;
+const CMD_UPDATE_CURSOR: u32 = 0x300;
+const RESP_OK_NODATA: u32 = 0x1100;
+const RESP_OK_DISPLAY_INFO: u32 = 0x1101;
+
+const FORMAT_B8G8R8A8_UNORM: u32 = 1;
+const MAX_SCANOUTS: usize = 16;
+const SCANOUT_ID: u32 = 0;
+const RESOURCE_ID_FB: u32 = 0xbabe;
+const RESOURCE_ID_CURSOR: u32 = 0xdade;
+const CURSOR_RECT: Rect = Rect {
+    x: 0,
+    y: 0,
+    width: 64,
+    height: 64,
+};
+
+#[repr(C)]
+#[derive(Default)]
+struct CtrlHeader {
+    hdr_type: u32,
+    flags: u32,
+    fence_id: u64,
+    ctx_id: u32,
+    _padding: u32,
+}
+
+impl CtrlHeader {
+    fn with_type(hdr_type: u32) -> Self {
+        Self {
+            hdr_type,
+            ..Default::default()
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+struct Rect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+struct DisplayOne {
+    rect: Rect,
+    enabled: u32,
+    flags: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct RespDisplayInfo {
+    header: CtrlHeader,
+    pmodes: [DisplayOne; MAX_SCANOUTS],
+}
+
+#[repr(C)]
+struct ResourceCreate2D {
+    header: CtrlHeader,
+    resource_id: u32,
+    format: u32,
+    width: u32,
+    height: u32,
+}
+
+#[repr(C)]
+struct ResourceAttachBacking {
+    header: CtrlHeader,
+    resource_id: u32,
+    nr_entries: u32,
+    addr: u64,
+    length: u32,
+    _padding: u32,
+}
+
+#[repr(C)]
+struct SetScanout {
+    header: CtrlHeader,
+    rect: Rect,
+    scanout_id: u32,
+    resource_id: u32,
+}
+
+#[repr(C)]
+struct TransferToHost2D {
+    header: CtrlHeader,
+    rect: Rect,
+    offset: u64,
+    resource_id: u32,
+    _padding: u32,
+}
+
+#[repr(C)]
+struct ResourceFlush {
+    header: CtrlHeader,
+    rect: Rect,
+    resource_id: u32,
+    _padding: u32,
+}
+
+#[repr(C)]
+struct CursorPos {
+    scanout_id: u32,
+    x: u32,
+    y: u32,
+    _padding: u32,
+}
+
+#[repr(C)]
+struct UpdateCursor {
+    header: CtrlHeader,
+    pos: CursorPos,
+    resource_id: u32,
+    hot_x: u32,
+    hot_y: u32,
+    _padding: u32,
+}
+
+pub struct VirtIOGpuWrapper {
+    transport: MmioTransport,
+    control_queue: VirtQueue,
+    cursor_queue: VirtQueue,
+    rect: Rect,
+    fb: DmaBuffer,
+    /// the backing of the cursor is kept as long as the device
+    _cursor: DmaBuffer,
+}
+
+static BMP_DATA: &[u8] = include_bytes!("../../assert/mouse.bmp");
+
+fn pages(size: usize) -> usize {
+    (size + PAGE_SIZE - 1) / PAGE_SIZE
+}
+
+/// Send a control command which responds with no data.
+fn control_ok<Req>(queue: &VirtQueue, req: &Req) {
+    let mut resp = CtrlHeader::default();
+    queue.request(&[as_bytes(req)], &mut [as_bytes_mut(&mut resp)]);
+    assert_eq!(resp.hdr_type, RESP_OK_NODATA, "virtio gpu command fails");
+}
+
+fn resource_create_2d(queue: &VirtQueue, resource_id: u32, width: u32, height: u32) {
+    control_ok(
+        queue,
+        &ResourceCreate2D {
+            header: CtrlHeader::with_type(CMD_RESOURCE_CREATE_2D),
+            resource_id,
+            format: FORMAT_B8G8R8A8_UNORM,
+            width,
+            height,
+        },
+    );
+}
+
+fn resource_attach_backing(queue: &VirtQueue, resource_id: u32, backing: &DmaBuffer, length: u32) {
+    control_ok(
+        queue,
+        &ResourceAttachBacking {
+            header: CtrlHeader::with_type(CMD_RESOURCE_ATTACH_BACKING),
+            resource_id,
+            nr_entries: 1,
+            addr: backing.paddr() as u64,
+            length,
+            _padding: 0,
+        },
+    );
+}
+
+fn transfer_to_host_2d(queue: &VirtQueue, resource_id: u32, rect: Rect) {
+    control_ok(
+        queue,
+        &TransferToHost2D {
+            header: CtrlHeader::with_type(CMD_TRANSFER_TO_HOST_2D),
+            rect,
+            offset: 0,
+            resource_id,
+            _padding: 0,
+        },
+    );
+}
+
+impl VirtIOGpuWrapper {
+    pub fn new() -> Self {
+        let transport =
+            MmioTransport::new(VIRTIO7, DeviceType::Gpu).expect("can't find virtio gpu device");
+        transport.begin_init(0);
+        let control_queue = VirtQueue::new(&transport, QUEUE_CONTROL, QUEUE_SIZE);
+        let cursor_queue = VirtQueue::new(&transport, QUEUE_CURSOR, QUEUE_SIZE);
+        transport.finish_init();
+
+        // set up the framebuffer
+        let mut info = RespDisplayInfo::default();
+        control_queue.request(
+            &[as_bytes(&CtrlHeader::with_type(CMD_GET_DISPLAY_INFO))],
+            &mut [as_bytes_mut(&mut info)],
+        );
+        assert_eq!(info.header.hdr_type, RESP_OK_DISPLAY_INFO);
+        let rect = info.pmodes[SCANOUT_ID as usize].rect;
+        let size = rect.width * rect.height * 4;
+        resource_create_2d(&control_queue, RESOURCE_ID_FB, rect.width, rect.height);
+        let fb = DmaBuffer::new(pages(size as usize)).expect("can't allocate the framebuffer");
+        resource_attach_backing(&control_queue, RESOURCE_ID_FB, &fb, size);
+        control_ok(
+            &control_queue,
+            &SetScanout {
+                header: CtrlHeader::with_type(CMD_SET_SCANOUT),
+                rect,
+                scanout_id: SCANOUT_ID,
+                resource_id: RESOURCE_ID_FB,
+            },
+        );
+
+        // set up the cursor, white pixels are transparent
+        let bmp = Bmp::<Rgb888>::from_slice(BMP_DATA).unwrap();
+        let raw = bmp.as_raw();
+        let mut b = Vec::new();
+        for i in raw.image_data().chunks(3) {
+            let mut v = i.to_vec();
+            b.append(&mut v);
+            if i == [255, 255, 255] {
+                b.push(0x0)
+            } else {
+                b.push(0xff)
+            }
+        }
+        let size = CURSOR_RECT.width * CURSOR_RECT.height * 4;
+        assert_eq!(b.len(), size as usize);
+        let mut cursor = DmaBuffer::new(pages(size as usize)).unwrap();
+        cursor.as_mut_slice()[..b.len()].copy_from_slice(&b);
+        resource_create_2d(
+            &control_queue,
+            RESOURCE_ID_CURSOR,
+            CURSOR_RECT.width,
+            CURSOR_RECT.height,
+        );
+        resource_attach_backing(&control_queue, RESOURCE_ID_CURSOR, &cursor, size);
+        transfer_to_host_2d(&control_queue, RESOURCE_ID_CURSOR, CURSOR_RECT);
+        let update = UpdateCursor {
+            header: CtrlHeader::with_type(CMD_UPDATE_CURSOR),
+            pos: CursorPos {
+                scanout_id: SCANOUT_ID,
+                x: 50,
+                y: 50,
+                _padding: 0,
+            },
+            resource_id: RESOURCE_ID_CURSOR,
+            hot_x: 50,
+            hot_y: 50,
+            _padding: 0,
+        };
+        cursor_queue.request(&[as_bytes(&update)], &mut []);
+
+        Self {
+            transport,
+            control_queue,
+            cursor_queue,
+            rect,
+            fb,
+            _cursor: cursor,
+        }
+    }
+}
+
+impl GpuDevice for VirtIOGpuWrapper {
+    fn flush(&self) {
+        transfer_to_host_2d(&self.control_queue, RESOURCE_ID_FB, self.rect);
+        control_ok(
+            &self.control_queue,
+            &ResourceFlush {
+                header: CtrlHeader::with_type(CMD_RESOURCE_FLUSH),
+                rect: self.rect,
+                resource_id: RESOURCE_ID_FB,
+                _padding: 0,
+            },
+        );
+    }
+    fn get_framebuffer(&self) -> &mut [u8] {
+        let len = (self.rect.width * self.rect.height * 4) as usize;
+        unsafe { core::slice::from_raw_parts_mut(self.fb.vaddr() as *mut u8, len) }
+    }
+    fn update_cursor(&self) {}
+    fn handle_irq(&self) {
+        self.transport.ack_interrupt();
+        self.control_queue.handle_irq(|_, _| {});
+        self.cursor_queue.handle_irq(|_, _| {});
+    }
+}
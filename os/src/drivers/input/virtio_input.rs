@@ -0,0 +1,115 @@
+use super::InputDevice;
+use crate::drivers::bus::virtio::{DeviceType, MmioTransport, VirtQueue};
+use crate::mm::DmaBuffer;
+use crate::sync::{Condvar, UPIntrFreeCell};
+use crate::task::schedule;
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+const QUEUE_SIZE: u16 = 32;
+const QUEUE_EVENT: u16 = 0;
+const QUEUE_STATUS: u16 = 1;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct InputEvent {
+    event_type: u16,
+    code: u16,
+    value: u32,
+}
+
+struct VirtIOInputInner {
+    events: VecDeque<u64>,
+    /// the event buffers posted to the device, in a page so none of them
+    /// crosses pages
+    buffers: DmaBuffer,
+    /// the buffer index of each token
+    slots: Vec<usize>,
+}
+
+pub struct VirtIOInputWrapper {
+    transport: MmioTransport,
+    event_queue: VirtQueue,
+    _status_queue: VirtQueue,
+    inner: UPIntrFreeCell<VirtIOInputInner>,
+    condvar: Condvar,
+}
+
+impl VirtIOInputInner {
+    fn buffer(&mut self, slot: usize) -> &mut [u8] {
+        let size = size_of::<InputEvent>();
+        &mut self.buffers.as_mut_slice()[slot * size..(slot + 1) * size]
+    }
+
+    fn post(&mut self, queue: &VirtQueue, slot: usize) {
+        let token = unsafe { queue.post(&mut [self.buffer(slot)]) };
+        self.slots[token as usize] = slot;
+    }
+}
+
+impl VirtIOInputWrapper {
+    pub fn new(addr: usize) -> Self {
+        let transport =
+            MmioTransport::new(addr, DeviceType::Input).expect("can't find virtio input device");
+        transport.begin_init(0);
+        let event_queue = VirtQueue::new(&transport, QUEUE_EVENT, QUEUE_SIZE);
+        let status_queue = VirtQueue::new(&transport, QUEUE_STATUS, QUEUE_SIZE);
+        let size = event_queue.size() as usize;
+        let mut inner = VirtIOInputInner {
+            events: VecDeque::new(),
+            buffers: DmaBuffer::new(1).unwrap(),
+            slots: vec![0; size],
+        };
+        for slot in 0..size {
+            inner.post(&event_queue, slot);
+        }
+        transport.finish_init();
+        Self {
+            transport,
+            event_queue,
+            _status_queue: status_queue,
+            inner: unsafe { UPIntrFreeCell::new(inner) },
+            condvar: Condvar::new(),
+        }
+    }
+}
+
+impl InputDevice for VirtIOInputWrapper {
+    fn is_empty(&self) -> bool {
+        self.inner.exclusive_access().events.is_empty()
+    }
+
+    fn read_event(&self) -> u64 {
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if let Some(event) = inner.events.pop_front() {
+                return event;
+            } else {
+                let task_cx_ptr = self.condvar.wait_no_sched();
+                drop(inner);
+                schedule(task_cx_ptr);
+            }
+        }
+    }
+
+    fn handle_irq(&self) {
+        let mut count = 0;
+        self.transport.ack_interrupt();
+        self.event_queue.handle_irq(|token, _| {
+            let mut inner = self.inner.exclusive_access();
+            let slot = inner.slots[token as usize];
+            let event = unsafe { *(inner.buffer(slot).as_ptr() as *const InputEvent) };
+            inner.events.push_back(
+                (event.event_type as u64) << 48 | (event.code as u64) << 32 | (event.value) as u64,
+            );
+            // requeue
+            inner.post(&self.event_queue, slot);
+            count += 1;
+        });
+        if count > 0 {
+            self.condvar.signal();
+        };
+    }
+}
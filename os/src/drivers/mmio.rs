@@ -0,0 +1,132 @@
+//! Accesses to device registers.
+//!
+//! With the `mmio-audit` feature, every access is validated against the
+//! device ranges in `MMIO` of the board and recorded in a trace. A bad access
+//! is reported with the recent trace through the SBI console, which does not
+//! depend on any driver, and then the kernel shuts down.
+
+/// Read a register at `addr`.
+#[allow(clippy::let_and_return)]
+pub fn mmio_read<T: Copy + Into<u64>>(addr: usize) -> T {
+    #[cfg(feature = "mmio-audit")]
+    audit::check(addr, core::mem::size_of::<T>(), false);
+    let value = unsafe { (addr as *const T).read_volatile() };
+    #[cfg(feature = "mmio-audit")]
+    audit::record(addr, core::mem::size_of::<T>(), value.into(), false);
+    value
+}
+
+/// Write `value` to a register at `addr`.
+pub fn mmio_write<T: Copy + Into<u64>>(addr: usize, value: T) {
+    #[cfg(feature = "mmio-audit")]
+    {
+        audit::check(addr, core::mem::size_of::<T>(), true);
+        audit::record(addr, core::mem::size_of::<T>(), value.into(), true);
+    }
+    unsafe {
+        (addr as *mut T).write_volatile(value);
+    }
+}
+
+#[cfg(feature = "mmio-audit")]
+mod audit {
+    use crate::config::MMIO;
+    use crate::sbi::shutdown;
+    use crate::sync::UPIntrFreeCell;
+    use core::fmt::{self, Write};
+    use lazy_static::*;
+
+    const TRACE_LEN: usize = 32;
+
+    #[derive(Copy, Clone)]
+    struct Access {
+        addr: usize,
+        width: usize,
+        value: u64,
+        write: bool,
+    }
+
+    struct Trace {
+        /// a ring of the recent accesses
+        accesses: [Option<Access>; TRACE_LEN],
+        next: usize,
+    }
+
+    lazy_static! {
+        static ref TRACE: UPIntrFreeCell<Trace> = unsafe {
+            UPIntrFreeCell::new(Trace {
+                accesses: [None; TRACE_LEN],
+                next: 0,
+            })
+        };
+    }
+
+    struct SbiConsole;
+
+    impl Write for SbiConsole {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            for c in s.bytes() {
+                #[allow(deprecated)]
+                sbi_rt::legacy::console_putchar(c as usize);
+            }
+            Ok(())
+        }
+    }
+
+    impl fmt::Display for Access {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "{} {}B at {:#x}: {:#x}",
+                if self.write { "write" } else { "read" },
+                self.width,
+                self.addr,
+                self.value
+            )
+        }
+    }
+
+    /// Shut down if the access is not within a device or misaligned.
+    pub fn check(addr: usize, width: usize, write: bool) {
+        let in_device = MMIO
+            .iter()
+            .any(|&(base, size)| base <= addr && addr + width <= base + size);
+        let error = if !in_device {
+            "out of devices"
+        } else if addr % width != 0 {
+            "misaligned"
+        } else {
+            return;
+        };
+        let mut console = SbiConsole;
+        writeln!(
+            console,
+            "[mmio] bad {} {}B at {:#x}, {}",
+            if write { "write" } else { "read" },
+            width,
+            addr,
+            error
+        )
+        .unwrap();
+        writeln!(console, "[mmio] recent accesses:").unwrap();
+        let trace = TRACE.exclusive_access();
+        for i in 0..TRACE_LEN {
+            if let Some(access) = trace.accesses[(trace.next + i) % TRACE_LEN] {
+                writeln!(console, "[mmio]   {}", access).unwrap();
+            }
+        }
+        shutdown(true);
+    }
+
+    pub fn record(addr: usize, width: usize, value: u64, write: bool) {
+        let mut trace = TRACE.exclusive_access();
+        let next = trace.next;
+        trace.accesses[next] = Some(Access {
+            addr,
+            width,
+            value,
+            write,
+        });
+        trace.next = (next + 1) % TRACE_LEN;
+    }
+}
@@ -1,12 +1,15 @@
 pub mod block;
 pub mod bus;
 pub mod chardev;
+pub mod clint;
 pub mod gpu;
 pub mod input;
+pub mod mmio;
 pub mod net;
 pub mod plic;
+pub mod rtc;
 
-pub use block::BLOCK_DEVICE;
+pub use block::{BLOCK_DEVICE, MEM_DISK};
 pub use bus::*;
 pub use chardev::UART;
 pub use gpu::*;
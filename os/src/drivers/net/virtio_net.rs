@@ -0,0 +1,82 @@
+use super::NetDevice;
+use crate::drivers::bus::virtio::{as_bytes, as_bytes_mut, DeviceType, MmioTransport, VirtQueue};
+
+const VIRTIO8: usize = 0x10004000;
+
+/// Enough for the header and a packet across a few pages.
+const QUEUE_SIZE: u16 = 8;
+const QUEUE_RECEIVE: u16 = 0;
+const QUEUE_TRANSMIT: u16 = 1;
+
+const FEATURE_MAC: u64 = 1 << 5;
+const FEATURE_STATUS: u64 = 1 << 16;
+
+/// The header before each packet, the legacy interface has no `num_buffers`.
+#[repr(C)]
+#[derive(Default)]
+struct Header {
+    flags: u8,
+    gso_type: u8,
+    hdr_len: u16,
+    gso_size: u16,
+    csum_start: u16,
+    csum_offset: u16,
+    num_buffers: u16,
+}
+
+pub struct VirtIONetWrapper {
+    transport: MmioTransport,
+    recv_queue: VirtQueue,
+    send_queue: VirtQueue,
+    header_len: usize,
+}
+
+impl NetDevice for VirtIONetWrapper {
+    /// A packet too large for the queue is dropped.
+    fn transmit(&self, data: &[u8]) {
+        let header = Header::default();
+        self.send_queue
+            .request(&[&as_bytes(&header)[..self.header_len], data], &mut []);
+    }
+
+    /// Nothing is received if the buffer is too large for the queue, or the
+    /// device writes less than the header.
+    fn receive(&self, data: &mut [u8]) -> usize {
+        let mut header = Header::default();
+        self.recv_queue
+            .request(
+                &[],
+                &mut [&mut as_bytes_mut(&mut header)[..self.header_len], data],
+            )
+            .and_then(|len| (len as usize).checked_sub(self.header_len))
+            .unwrap_or(0)
+    }
+
+    fn handle_irq(&self) {
+        self.transport.ack_interrupt();
+        self.recv_queue.handle_irq(|_, _| {});
+        self.send_queue.handle_irq(|_, _| {});
+    }
+}
+
+impl VirtIONetWrapper {
+    pub fn new() -> Self {
+        let transport = MmioTransport::new(VIRTIO8, DeviceType::Network)
+            .expect("can't create net device by virtio");
+        transport.begin_init(FEATURE_MAC | FEATURE_STATUS);
+        let recv_queue = VirtQueue::new(&transport, QUEUE_RECEIVE, QUEUE_SIZE);
+        let send_queue = VirtQueue::new(&transport, QUEUE_TRANSMIT, QUEUE_SIZE);
+        transport.finish_init();
+        let header_len = if transport.is_legacy() {
+            core::mem::size_of::<Header>() - core::mem::size_of::<u16>()
+        } else {
+            core::mem::size_of::<Header>()
+        };
+        Self {
+            transport,
+            recv_queue,
+            send_queue,
+            header_len,
+        }
+    }
+}
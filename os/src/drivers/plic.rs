@@ -1,6 +1,10 @@
+use super::mmio::{mmio_read, mmio_write};
+
 #[allow(clippy::upper_case_acronyms)]
 pub struct PLIC {
     base_addr: usize,
+    /// hart 0 has only the machine mode context, like the E51 of FU540
+    monitor_core: bool,
 }
 
 #[derive(Copy, Clone)]
@@ -16,55 +20,69 @@ impl IntrTargetPriority {
 }
 
 impl PLIC {
-    fn priority_ptr(&self, intr_source_id: usize) -> *mut u32 {
+    fn priority_addr(&self, intr_source_id: usize) -> usize {
         assert!(intr_source_id > 0 && intr_source_id <= 132);
-        (self.base_addr + intr_source_id * 4) as *mut u32
+        self.base_addr + intr_source_id * 4
     }
-    fn hart_id_with_priority(hart_id: usize, target_priority: IntrTargetPriority) -> usize {
+    fn hart_id_with_priority(&self, hart_id: usize, target_priority: IntrTargetPriority) -> usize {
         let priority_num = IntrTargetPriority::supported_number();
-        hart_id * priority_num + target_priority as usize
+        if self.monitor_core {
+            assert!(hart_id > 0 || matches!(target_priority, IntrTargetPriority::Machine));
+            (hart_id * priority_num + target_priority as usize).saturating_sub(1)
+        } else {
+            hart_id * priority_num + target_priority as usize
+        }
     }
-    fn enable_ptr(
+    fn enable_addr(
         &self,
         hart_id: usize,
         target_priority: IntrTargetPriority,
         intr_source_id: usize,
-    ) -> (*mut u32, usize) {
-        let id = Self::hart_id_with_priority(hart_id, target_priority);
+    ) -> (usize, usize) {
+        let id = self.hart_id_with_priority(hart_id, target_priority);
         let (reg_id, reg_shift) = (intr_source_id / 32, intr_source_id % 32);
         (
-            (self.base_addr + 0x2000 + 0x80 * id + 0x4 * reg_id) as *mut u32,
+            (self.base_addr + 0x2000 + 0x80 * id + 0x4 * reg_id),
             reg_shift,
         )
     }
-    fn threshold_ptr_of_hart_with_priority(
+    fn threshold_addr_of_hart_with_priority(
         &self,
         hart_id: usize,
         target_priority: IntrTargetPriority,
-    ) -> *mut u32 {
-        let id = Self::hart_id_with_priority(hart_id, target_priority);
-        (self.base_addr + 0x20_0000 + 0x1000 * id) as *mut u32
+    ) -> usize {
+        let id = self.hart_id_with_priority(hart_id, target_priority);
+        self.base_addr + 0x20_0000 + 0x1000 * id
     }
-    fn claim_comp_ptr_of_hart_with_priority(
+    fn claim_comp_addr_of_hart_with_priority(
         &self,
         hart_id: usize,
         target_priority: IntrTargetPriority,
-    ) -> *mut u32 {
-        let id = Self::hart_id_with_priority(hart_id, target_priority);
-        (self.base_addr + 0x20_0004 + 0x1000 * id) as *mut u32
+    ) -> usize {
+        let id = self.hart_id_with_priority(hart_id, target_priority);
+        self.base_addr + 0x20_0004 + 0x1000 * id
     }
+    #[allow(unused)]
     pub unsafe fn new(base_addr: usize) -> Self {
-        Self { base_addr }
+        Self {
+            base_addr,
+            monitor_core: false,
+        }
+    }
+    #[allow(unused)]
+    pub unsafe fn with_monitor_core(base_addr: usize) -> Self {
+        Self {
+            base_addr,
+            monitor_core: true,
+        }
     }
     pub fn set_priority(&mut self, intr_source_id: usize, priority: u32) {
         assert!(priority < 8);
-        unsafe {
-            self.priority_ptr(intr_source_id).write_volatile(priority);
-        }
+        mmio_write(self.priority_addr(intr_source_id), priority);
     }
     #[allow(unused)]
     pub fn get_priority(&mut self, intr_source_id: usize) -> u32 {
-        unsafe { self.priority_ptr(intr_source_id).read_volatile() & 7 }
+        mmio_read::<u32>(self.priority_addr(intr_source_id)) & 7
     }
     pub fn enable(
         &mut self,
@@ -72,10 +90,8 @@ impl PLIC {
         target_priority: IntrTargetPriority,
         intr_source_id: usize,
     ) {
-        let (reg_ptr, shift) = self.enable_ptr(hart_id, target_priority, intr_source_id);
-        unsafe {
-            reg_ptr.write_volatile(reg_ptr.read_volatile() | 1 << shift);
-        }
+        let (reg_addr, shift) = self.enable_addr(hart_id, target_priority, intr_source_id);
+        mmio_write(reg_addr, mmio_read::<u32>(reg_addr) | 1 << shift);
     }
     #[allow(unused)]
     pub fn disable(
@@ -84,10 +100,8 @@ impl PLIC {
         target_priority: IntrTargetPriority,
         intr_source_id: usize,
     ) {
-        let (reg_ptr, shift) = self.enable_ptr(hart_id, target_priority, intr_source_id);
-        unsafe {
-            reg_ptr.write_volatile(reg_ptr.read_volatile() & (!(1u32 << shift)));
-        }
+        let (reg_addr, shift) = self.enable_addr(hart_id, target_priority, intr_source_id);
+        mmio_write(reg_addr, mmio_read::<u32>(reg_addr) & (!(1u32 << shift)));
     }
     pub fn set_threshold(
         &mut self,
@@ -96,19 +110,17 @@ impl PLIC {
         threshold: u32,
     ) {
         assert!(threshold < 8);
-        let threshold_ptr = self.threshold_ptr_of_hart_with_priority(hart_id, target_priority);
-        unsafe {
-            threshold_ptr.write_volatile(threshold);
-        }
+        let threshold_addr = self.threshold_addr_of_hart_with_priority(hart_id, target_priority);
+        mmio_write(threshold_addr, threshold);
     }
     #[allow(unused)]
     pub fn get_threshold(&mut self, hart_id: usize, target_priority: IntrTargetPriority) -> u32 {
-        let threshold_ptr = self.threshold_ptr_of_hart_with_priority(hart_id, target_priority);
-        unsafe { threshold_ptr.read_volatile() & 7 }
+        let threshold_addr = self.threshold_addr_of_hart_with_priority(hart_id, target_priority);
+        mmio_read::<u32>(threshold_addr) & 7
     }
     pub fn claim(&mut self, hart_id: usize, target_priority: IntrTargetPriority) -> u32 {
-        let claim_comp_ptr = self.claim_comp_ptr_of_hart_with_priority(hart_id, target_priority);
-        unsafe { claim_comp_ptr.read_volatile() }
+        let claim_comp_addr = self.claim_comp_addr_of_hart_with_priority(hart_id, target_priority);
+        mmio_read(claim_comp_addr)
     }
     pub fn complete(
         &mut self,
@@ -116,9 +128,7 @@ impl PLIC {
         target_priority: IntrTargetPriority,
         completion: u32,
     ) {
-        let claim_comp_ptr = self.claim_comp_ptr_of_hart_with_priority(hart_id, target_priority);
-        unsafe {
-            claim_comp_ptr.write_volatile(completion);
-        }
+        let claim_comp_addr = self.claim_comp_addr_of_hart_with_priority(hart_id, target_priority);
+        mmio_write(claim_comp_addr, completion);
     }
 }
@@ -0,0 +1,27 @@
+//! The Goldfish RTC of the `virt` machine of QEMU, counting nanoseconds
+//! since the Unix epoch.
+//!
+//! Ref: GOLDFISH-VIRTUAL-HARDWARE.TXT of the Android emulator
+
+use super::mmio::mmio_read;
+
+const TIME_LOW_OFFSET: usize = 0x00;
+const TIME_HIGH_OFFSET: usize = 0x04;
+
+pub struct GoldfishRtc {
+    base_addr: usize,
+}
+
+impl GoldfishRtc {
+    pub fn new(base_addr: usize) -> Self {
+        Self { base_addr }
+    }
+
+    /// The wall clock time in nanoseconds. Reading the low half latches the
+    /// high half, so it is read first.
+    pub fn now_ns(&self) -> u64 {
+        let low: u32 = mmio_read(self.base_addr + TIME_LOW_OFFSET);
+        let high: u32 = mmio_read(self.base_addr + TIME_HIGH_OFFSET);
+        ((high as u64) << 32) | low as u64
+    }
+}
@@ -0,0 +1,92 @@
+//! Just enough of the flattened device tree the SBI passes to the boot hart
+//! in `a1` to find the timebase frequency. It is read with paging off, the
+//! tree is not mapped by the kernel space.
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+/// The fields of the header are big-endian u32s.
+struct Fdt {
+    base: usize,
+}
+
+impl Fdt {
+    fn u32_at(&self, offset: usize) -> u32 {
+        u32::from_be(unsafe { ((self.base + offset) as *const u32).read_volatile() })
+    }
+
+    fn str_at(&self, offset: usize) -> &'static [u8] {
+        let start = (self.base + offset) as *const u8;
+        let mut len = 0;
+        while unsafe { *start.add(len) } != 0 {
+            len += 1;
+        }
+        unsafe { core::slice::from_raw_parts(start, len) }
+    }
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+/// The `timebase-frequency` of `/cpus`, or of a cpu node in it, None if
+/// there is no device tree at `dtb`.
+pub fn timebase_frequency(dtb: usize) -> Option<usize> {
+    if dtb == 0 || dtb % 8 != 0 {
+        return None;
+    }
+    let fdt = Fdt { base: dtb };
+    if fdt.u32_at(0) != FDT_MAGIC {
+        return None;
+    }
+    let struct_offset = fdt.u32_at(8) as usize;
+    let strings_offset = fdt.u32_at(12) as usize;
+    let mut offset = struct_offset;
+    // the depth of the node, and the depth of /cpus while in it
+    let mut depth = 0;
+    let mut cpus_depth = None;
+    loop {
+        let token = fdt.u32_at(offset);
+        offset += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                let name = fdt.str_at(offset);
+                offset = align4(offset + name.len() + 1);
+                depth += 1;
+                if depth == 2 && name == b"cpus" {
+                    cpus_depth = Some(depth);
+                }
+            }
+            FDT_END_NODE => {
+                if cpus_depth == Some(depth) {
+                    cpus_depth = None;
+                }
+                depth -= 1;
+            }
+            FDT_PROP => {
+                let len = fdt.u32_at(offset) as usize;
+                let name_offset = fdt.u32_at(offset + 4) as usize;
+                let value = offset + 8;
+                offset = align4(value + len);
+                if cpus_depth.is_some()
+                    && fdt.str_at(strings_offset + name_offset) == b"timebase-frequency"
+                {
+                    return match len {
+                        4 => Some(fdt.u32_at(value) as usize),
+                        8 => Some(
+                            ((fdt.u32_at(value) as usize) << 32) | fdt.u32_at(value + 4) as usize,
+                        ),
+                        _ => None,
+                    };
+                }
+            }
+            FDT_NOP => {}
+            FDT_END => return None,
+            _ => return None,
+        }
+    }
+}
@@ -1,6 +1,8 @@
     .section .text.entry
     .globl _start
 _start:
+    # a0 = hartid, kept in tp, a1 = the device tree
+    mv tp, a0
     la sp, boot_stack_top
     call rust_main
 
@@ -10,3 +12,11 @@ boot_stack_lower_bound:
     .space 4096 * 16
     .globl boot_stack_top
 boot_stack_top:
+
+    .section .text
+    .globl _start_secondary
+_start_secondary:
+    # a0 = hartid, a1 = the top of the stack of the hart
+    mv tp, a0
+    mv sp, a1
+    call secondary_main
@@ -0,0 +1,231 @@
+use super::{File, OpenFlags, SEEK_CUR, SEEK_END, SEEK_SET};
+use crate::drivers::block::Partition;
+use crate::drivers::{BLOCK_DEVICE, MEM_DISK};
+use crate::mm::{UserBuffer, UserSliceRef};
+use crate::sync::UPIntrFreeCell;
+use crate::task::current_user_token;
+use abi::SysError;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use easy_fs::{block_cache_sync_all, BlockDevice, BLOCK_SZ};
+
+/// Get the size in blocks of 512 bytes as an `unsigned long`.
+const BLKGETSIZE: usize = 0x1260;
+/// Get the size in bytes as a `u64`.
+const BLKGETSIZE64: usize = 0x8008_1272;
+
+/// The names of disks, the first one has the root file system.
+const DISKS: [&str; 2] = ["vda", "ram0"];
+
+fn disk(index: usize) -> Arc<dyn BlockDevice> {
+    match index {
+        0 => BLOCK_DEVICE.clone(),
+        _ => MEM_DISK.clone(),
+    }
+}
+
+/// A block device read and written in bytes, blocks written in part are read
+/// first.
+pub struct BlockDevFile {
+    readable: bool,
+    writable: bool,
+    device: Arc<dyn BlockDevice>,
+    offset: UPIntrFreeCell<usize>,
+    /// `OpenFlags::DIRECT`
+    direct: UPIntrFreeCell<bool>,
+}
+
+impl BlockDevFile {
+    fn size(&self) -> usize {
+        self.device.num_blocks() * BLOCK_SZ
+    }
+    /// Whether to transfer `buf` at `offset` straight between the device and
+    /// the user pages, if they are aligned to blocks with `OpenFlags::DIRECT`.
+    fn direct(&self, offset: usize, buf: &UserBuffer) -> bool {
+        *self.direct.exclusive_access() && offset % BLOCK_SZ == 0 && buf.aligned(BLOCK_SZ)
+    }
+}
+
+impl File for BlockDevFile {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        let mut offset = self.offset.exclusive_access();
+        let size = self.size();
+        if self.direct(*offset, &buf) {
+            let mut left = size.saturating_sub(*offset);
+            let mut bufs: Vec<&mut [u8]> = Vec::new();
+            for slice in buf.buffers.iter_mut().take_while(|_| left > 0) {
+                let len = slice.len().min(left);
+                bufs.push(&mut slice[..len]);
+                left -= len;
+            }
+            let len = size.saturating_sub(*offset) - left;
+            self.device.read_blocks(*offset / BLOCK_SZ, &mut bufs);
+            *offset += len;
+            return len;
+        }
+        let mut block = [0u8; BLOCK_SZ];
+        let mut total_read_size = 0usize;
+        for slice in buf.buffers.iter_mut() {
+            let mut start = 0;
+            while start < slice.len() && *offset < size {
+                let block_offset = *offset % BLOCK_SZ;
+                let len = (BLOCK_SZ - block_offset).min(slice.len() - start);
+                self.device.read_block(*offset / BLOCK_SZ, &mut block);
+                slice[start..start + len].copy_from_slice(&block[block_offset..block_offset + len]);
+                start += len;
+                *offset += len;
+                total_read_size += len;
+            }
+        }
+        total_read_size
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        let mut offset = self.offset.exclusive_access();
+        let size = self.size();
+        if self.direct(*offset, &buf) {
+            let mut left = size.saturating_sub(*offset);
+            let mut bufs: Vec<&[u8]> = Vec::new();
+            for slice in buf.buffers.iter().take_while(|_| left > 0) {
+                let len = slice.len().min(left);
+                bufs.push(&slice[..len]);
+                left -= len;
+            }
+            let len = size.saturating_sub(*offset) - left;
+            self.device.write_blocks(*offset / BLOCK_SZ, &bufs);
+            *offset += len;
+            return len;
+        }
+        let mut block = [0u8; BLOCK_SZ];
+        let mut total_write_size = 0usize;
+        for slice in buf.buffers.iter() {
+            let mut start = 0;
+            while start < slice.len() && *offset < size {
+                let block_offset = *offset % BLOCK_SZ;
+                let len = (BLOCK_SZ - block_offset).min(slice.len() - start);
+                if len < BLOCK_SZ {
+                    self.device.read_block(*offset / BLOCK_SZ, &mut block);
+                }
+                block[block_offset..block_offset + len].copy_from_slice(&slice[start..start + len]);
+                self.device.write_block(*offset / BLOCK_SZ, &block);
+                start += len;
+                *offset += len;
+                total_write_size += len;
+            }
+        }
+        total_write_size
+    }
+    fn seek(&self, offset: isize, whence: usize) -> isize {
+        let mut current = self.offset.exclusive_access();
+        let base = match whence {
+            SEEK_SET => 0,
+            SEEK_CUR => *current as isize,
+            SEEK_END => self.size() as isize,
+            _ => return SysError::Invalid.code(),
+        };
+        let new_offset = base + offset;
+        if new_offset < 0 {
+            return SysError::Invalid.code();
+        }
+        *current = new_offset as usize;
+        new_offset
+    }
+    /// The blocks are written through, only the cache of the device is left.
+    fn sync(&self) -> isize {
+        self.device.flush();
+        0
+    }
+    fn status(&self) -> OpenFlags {
+        if *self.direct.exclusive_access() {
+            OpenFlags::DIRECT
+        } else {
+            OpenFlags::empty()
+        }
+    }
+    fn set_status(&self, flags: OpenFlags) -> bool {
+        if !(flags - OpenFlags::DIRECT).is_empty() {
+            return false;
+        }
+        *self.direct.exclusive_access() = flags.contains(OpenFlags::DIRECT);
+        true
+    }
+    fn ioctl(&self, cmd: usize, arg: usize) -> isize {
+        let token = current_user_token();
+        let done = match cmd {
+            BLKGETSIZE => UserSliceRef::one(arg).set(token, 0, self.device.num_blocks()),
+            BLKGETSIZE64 => UserSliceRef::one(arg).set(token, 0, self.size() as u64),
+            _ => return SysError::NotTty.code(),
+        };
+        SysError::encode(done.map(|()| 0).ok_or(SysError::Fault))
+    }
+}
+
+/// Find a disk or a partition of it by the name like Linux, such as `vda1`
+/// for the first partition of `vda` and `ram0p1` for that of `ram0`, return
+/// it with the index of the disk in `DISKS`.
+fn find_dev(name: &str) -> Option<(usize, Arc<dyn BlockDevice>)> {
+    DISKS.iter().enumerate().find_map(|(index, disk_name)| {
+        let number = name.strip_prefix(disk_name)?;
+        if number.is_empty() {
+            return Some((index, disk(index)));
+        }
+        let number = if disk_name.ends_with(|c: char| c.is_ascii_digit()) {
+            number.strip_prefix('p')?
+        } else {
+            number
+        };
+        let partition = Partition::find(&disk(index), number.parse().ok()?)?;
+        Some((index, Arc::new(partition) as Arc<dyn BlockDevice>))
+    })
+}
+
+/// The names of the disks and of the partitions found on them, each with
+/// the index of its disk in `DISKS`, see `find_dev`.
+pub fn block_device_names() -> Vec<(String, usize)> {
+    let mut names = Vec::new();
+    for (index, disk_name) in DISKS.iter().enumerate() {
+        names.push((String::from(*disk_name), index));
+        let separator = if disk_name.ends_with(|c: char| c.is_ascii_digit()) {
+            "p"
+        } else {
+            ""
+        };
+        for number in 1..=4 {
+            if Partition::find(&disk(index), number).is_some() {
+                names.push((format!("{}{}{}", disk_name, separator, number), index));
+            }
+        }
+    }
+    names
+}
+
+/// Find a disk or a partition by its path from the root, see `find_dev`.
+pub fn find_block_device(path: &str) -> Option<(usize, Arc<dyn BlockDevice>)> {
+    find_dev(path.strip_prefix("/dev/")?)
+}
+
+/// Open a disk or a partition by its name, see `find_dev`. The disk of the
+/// root file system and its partitions are read-only as it is mounted.
+pub fn open_block_dev(name: &str, flags: OpenFlags) -> Option<Arc<BlockDevFile>> {
+    let (readable, writable) = flags.read_write();
+    // read what the file system has changed
+    block_cache_sync_all();
+    let (index, device) = find_dev(name)?;
+    if index == 0 && writable {
+        return None;
+    }
+    Some(Arc::new(BlockDevFile {
+        readable,
+        writable,
+        device,
+        offset: unsafe { UPIntrFreeCell::new(0) },
+        direct: unsafe { UPIntrFreeCell::new(flags.contains(OpenFlags::DIRECT)) },
+    }))
+}
@@ -0,0 +1,239 @@
+//! devfs, the devices as files, mounted on /dev at boot. Opening a device
+//! gives its own file instead of the inode: the disks and their partitions,
+//! see `dev.rs`, and the character devices null, zero, random, urandom and
+//! tty, the console.
+//!
+//! The character devices may be read and written by all, the disks only by
+//! root, and the one of the root file system is read-only as it is mounted.
+
+use super::dev::{block_device_names, open_block_dev};
+use super::stdio::Tty;
+use super::vfs::{FileSystemType, Inode, SuperBlock};
+use super::{File, OpenFlags};
+use crate::mm::UserBuffer;
+use crate::random::{fill_random, mix_random};
+use crate::sync::UPIntrFreeCell;
+use crate::timer::get_realtime_ns;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use easy_fs::{BlockDevice, MountFlags};
+
+pub const DEV_FS: FileSystemType = FileSystemType {
+    name: "devfs",
+    requires_dev: false,
+    mount,
+};
+
+fn mount(
+    _device: Option<Arc<dyn BlockDevice>>,
+    dev: usize,
+    flags: MountFlags,
+) -> Option<Arc<dyn SuperBlock>> {
+    Some(Arc::new(DevFs {
+        dev,
+        flags: unsafe { UPIntrFreeCell::new(flags) },
+    }))
+}
+
+pub struct DevFs {
+    dev: usize,
+    flags: UPIntrFreeCell<MountFlags>,
+}
+
+impl SuperBlock for DevFs {
+    fn dev(&self) -> usize {
+        self.dev
+    }
+    fn root(self: Arc<Self>) -> Arc<dyn Inode> {
+        Arc::new(DevInode {
+            fs: self,
+            node: Node::Root,
+        })
+    }
+    fn flags(&self) -> MountFlags {
+        *self.flags.exclusive_access()
+    }
+    fn remount(&self, flags: MountFlags) {
+        *self.flags.exclusive_access() = flags;
+    }
+    /// Nothing is stored.
+    fn sync(&self) {}
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CharDev {
+    /// reads nothing, and takes all written
+    Null,
+    /// reads zeros, and takes all written
+    Zero,
+    /// reads random bytes, and mixes those written in
+    Random,
+    /// the console
+    Tty,
+}
+
+/// The character devices by their names, urandom is the same as random as
+/// the generator never blocks once seeded at boot.
+const CHAR_DEVICES: &[(&str, CharDev)] = &[
+    ("null", CharDev::Null),
+    ("random", CharDev::Random),
+    ("tty", CharDev::Tty),
+    ("urandom", CharDev::Random),
+    ("zero", CharDev::Zero),
+];
+
+#[derive(Clone)]
+enum Node {
+    /// /dev itself
+    Root,
+    /// by the index in `CHAR_DEVICES`
+    Char(usize),
+    /// a disk or a partition by its name, with the index of the disk
+    Block(String, usize),
+}
+
+pub struct DevInode {
+    fs: Arc<DevFs>,
+    node: Node,
+}
+
+impl DevInode {
+    fn child(&self, node: Node) -> Arc<dyn Inode> {
+        Arc::new(DevInode {
+            fs: self.fs.clone(),
+            node,
+        })
+    }
+}
+
+impl Inode for DevInode {
+    /// The disks and the partitions come after the character devices, in
+    /// the order listed.
+    fn id(&self) -> usize {
+        match &self.node {
+            Node::Root => 1,
+            Node::Char(index) => 0x10 + index,
+            Node::Block(name, _) => {
+                0x100
+                    + block_device_names()
+                        .iter()
+                        .position(|(other, _)| other == name)
+                        .unwrap_or(0)
+            }
+        }
+    }
+    fn super_block(&self) -> Arc<dyn SuperBlock> {
+        self.fs.clone()
+    }
+    fn is_dir(&self) -> bool {
+        matches!(self.node, Node::Root)
+    }
+    fn size(&self) -> usize {
+        0
+    }
+    fn times(&self) -> (u32, u32) {
+        let now = (get_realtime_ns() / 1_000_000_000) as u32;
+        (now, now)
+    }
+    /// The devices are read through the files of their own.
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> usize {
+        0
+    }
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> usize {
+        0
+    }
+    fn truncate(&self, _size: usize) -> bool {
+        false
+    }
+    fn find(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        if !matches!(self.node, Node::Root) {
+            return None;
+        }
+        if let Some(index) = CHAR_DEVICES.iter().position(|(other, _)| *other == name) {
+            return Some(self.child(Node::Char(index)));
+        }
+        block_device_names()
+            .into_iter()
+            .find(|(other, _)| other == name)
+            .map(|(name, disk)| self.child(Node::Block(name, disk)))
+    }
+    fn create(&self, _name: &str, _dir: bool) -> Option<Arc<dyn Inode>> {
+        None
+    }
+    fn rename(&self, _old_name: &str, _new_name: &str) -> bool {
+        false
+    }
+    fn unlink(&self, _name: &str, _dir: bool) -> bool {
+        false
+    }
+    fn ls(&self) -> Vec<String> {
+        if !matches!(self.node, Node::Root) {
+            return Vec::new();
+        }
+        CHAR_DEVICES
+            .iter()
+            .map(|(name, _)| String::from(*name))
+            .chain(block_device_names().into_iter().map(|(name, _)| name))
+            .collect()
+    }
+    fn permission(&self) -> Option<(u32, u32)> {
+        Some(match self.node {
+            Node::Root => (0, 0o755),
+            Node::Char(_) => (0, 0o666),
+            Node::Block(_, 0) => (0, 0o440),
+            Node::Block(..) => (0, 0o660),
+        })
+    }
+    fn open_device(&self, flags: OpenFlags) -> Option<Arc<dyn File + Send + Sync>> {
+        let (readable, writable) = flags.read_write();
+        match &self.node {
+            Node::Root => None,
+            Node::Char(index) => Some(match CHAR_DEVICES[*index].1 {
+                CharDev::Tty => Arc::new(Tty::new(readable, writable)),
+                kind => Arc::new(CharDevFile {
+                    kind,
+                    readable,
+                    writable,
+                }),
+            }),
+            Node::Block(name, _) => {
+                open_block_dev(name, flags).map(|file| file as Arc<dyn File + Send + Sync>)
+            }
+        }
+    }
+}
+
+/// null, zero or random opened.
+pub struct CharDevFile {
+    kind: CharDev,
+    readable: bool,
+    writable: bool,
+}
+
+impl File for CharDevFile {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        match self.kind {
+            CharDev::Null => return 0,
+            CharDev::Zero => buf.buffers.iter_mut().for_each(|slice| slice.fill(0)),
+            _ => buf.buffers.iter_mut().for_each(|slice| fill_random(slice)),
+        }
+        buf.len()
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        if self.kind == CharDev::Random {
+            buf.buffers.iter().for_each(|slice| mix_random(slice));
+        }
+        buf.len()
+    }
+    /// There is no offset, so any is taken as on Linux.
+    fn seek(&self, _offset: isize, _whence: usize) -> isize {
+        0
+    }
+}
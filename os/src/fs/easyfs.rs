@@ -0,0 +1,151 @@
+//! easy-fs mounted in the VFS, the root file system and the one made by
+//! `mkfs_easyfs` on other disks.
+
+use super::vfs::{FileSystemType, Inode, SuperBlock};
+use crate::task::current_uid;
+use crate::timer::get_realtime_ns;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use easy_fs::{block_cache_release, block_cache_sync_all, BlockDevice, EasyFileSystem};
+use easy_fs::{MountFlags, Quota};
+
+pub const EASY_FS: FileSystemType = FileSystemType {
+    name: "easyfs",
+    requires_dev: true,
+    mount,
+};
+
+fn mount(
+    device: Option<Arc<dyn BlockDevice>>,
+    dev: usize,
+    flags: MountFlags,
+) -> Option<Arc<dyn SuperBlock>> {
+    let device = device?;
+    if !EasyFileSystem::probe(&device) {
+        return None;
+    }
+    let efs = EasyFileSystem::open(device.clone());
+    {
+        let mut fs = efs.lock();
+        fs.flags = flags;
+        fs.clock = || (get_realtime_ns() / 1_000_000_000) as u32;
+        fs.current_uid = || current_uid() as u16;
+    }
+    Some(Arc::new(EasyFsSuper {
+        root: Arc::new(EasyFileSystem::root_inode(&efs)),
+        device,
+        dev,
+    }))
+}
+
+pub struct EasyFsSuper {
+    root: Arc<easy_fs::Inode>,
+    device: Arc<dyn BlockDevice>,
+    dev: usize,
+}
+
+impl SuperBlock for EasyFsSuper {
+    fn dev(&self) -> usize {
+        self.dev
+    }
+    fn root(self: Arc<Self>) -> Arc<dyn Inode> {
+        Arc::new(EasyFsInode {
+            inode: self.root.clone(),
+            sb: self,
+        })
+    }
+    fn flags(&self) -> MountFlags {
+        self.root.fs().lock().flags
+    }
+    fn remount(&self, flags: MountFlags) {
+        self.root.fs().lock().remount(flags);
+    }
+    /// The block cache is shared by all the file systems, all of it is
+    /// written back. The device is flushed even if none of its blocks is,
+    /// as they may have been written when evicted or by `O_DIRECT`.
+    fn sync(&self) {
+        block_cache_sync_all();
+        self.device.flush();
+    }
+    fn unmount(&self) {
+        block_cache_release(&self.device);
+    }
+    fn quota(&self, uid: u16) -> Option<Quota> {
+        Some(self.root.fs().lock().quota(uid))
+    }
+    fn set_quota_limits(&self, uid: u16, block_limit: u32, inode_limit: u32) -> bool {
+        self.root
+            .fs()
+            .lock()
+            .set_quota_limits(uid, block_limit, inode_limit);
+        true
+    }
+}
+
+pub struct EasyFsInode {
+    inode: Arc<easy_fs::Inode>,
+    sb: Arc<EasyFsSuper>,
+}
+
+impl EasyFsInode {
+    fn wrap(&self, inode: Arc<easy_fs::Inode>) -> Arc<dyn Inode> {
+        Arc::new(Self {
+            inode,
+            sb: self.sb.clone(),
+        })
+    }
+}
+
+impl Inode for EasyFsInode {
+    fn id(&self) -> usize {
+        self.inode.id()
+    }
+    fn super_block(&self) -> Arc<dyn SuperBlock> {
+        self.sb.clone()
+    }
+    fn is_dir(&self) -> bool {
+        self.inode.is_dir()
+    }
+    fn size(&self) -> usize {
+        self.inode.size()
+    }
+    fn times(&self) -> (u32, u32) {
+        self.inode.times()
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        self.inode.read_at(offset, buf)
+    }
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        self.inode.write_at(offset, buf)
+    }
+    fn read_direct(&self, offset: usize, bufs: &mut [&mut [u8]]) -> Option<usize> {
+        Some(self.inode.read_direct(offset, bufs))
+    }
+    fn write_direct(&self, offset: usize, bufs: &[&[u8]]) -> Option<usize> {
+        Some(self.inode.write_direct(offset, bufs))
+    }
+    fn truncate(&self, size: usize) -> bool {
+        self.inode.truncate(size)
+    }
+    fn find(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        self.inode.find(name).map(|inode| self.wrap(inode))
+    }
+    fn create(&self, name: &str, dir: bool) -> Option<Arc<dyn Inode>> {
+        let inode = if dir {
+            self.inode.create_dir(name)
+        } else {
+            self.inode.create(name)
+        };
+        inode.map(|inode| self.wrap(inode))
+    }
+    fn rename(&self, old_name: &str, new_name: &str) -> bool {
+        self.inode.rename(old_name, new_name)
+    }
+    fn unlink(&self, name: &str, dir: bool) -> bool {
+        self.inode.unlink(name, dir)
+    }
+    fn ls(&self) -> Vec<String> {
+        self.inode.ls()
+    }
+}
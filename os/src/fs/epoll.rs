@@ -0,0 +1,312 @@
+//! Event queues like epoll of Linux.
+//!
+//! An `Epoll` keeps an interest list of files by their fds, each with the
+//! events wanted. A ready hook added to each file marks it as changed and
+//! wakes up the tasks waiting in `Epoll::wait`. A level triggered file is
+//! polled on each wait and reported while it is ready, an edge triggered one
+//! only once it is marked, so it is reported again only after it changes.
+//! The files taking no hooks are always polled, and the waiters yield
+//! between the polls like `Poller`.
+
+use super::poll::ReadyHooks;
+use super::{File, PollEvents, ReadyHook};
+use crate::mm::UserBuffer;
+use crate::sync::{UPIntrFreeCell, WaitQueue};
+use crate::task::{current_wake_reason, suspend_current_and_run_next, WakeReason};
+use crate::timer::get_time_ns;
+use abi::{EpollEvent, EpollFlags};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+struct Interest {
+    /// dropped from the list once the file is closed
+    file: Weak<dyn File + Send + Sync>,
+    flags: EpollFlags,
+    data: u64,
+    /// None if the file takes no hooks
+    hook: Option<usize>,
+    /// marked by the hook since the file was polled last
+    changed: bool,
+    /// reported with `EpollFlags::EPOLLONESHOT`
+    disabled: bool,
+}
+
+struct EpollState {
+    /// by the fds
+    interests: BTreeMap<usize, Interest>,
+    /// some file is marked since the last poll
+    notified: bool,
+}
+
+struct EpollShared {
+    state: UPIntrFreeCell<EpollState>,
+    waiters: WaitQueue,
+    /// of the epoll file itself, for ppoll on it
+    hooks: ReadyHooks,
+}
+
+impl EpollShared {
+    /// Called by the hook of the file of `fd`.
+    fn mark(&self, fd: usize) {
+        let mut state = self.state.exclusive_access();
+        match state.interests.get_mut(&fd) {
+            Some(interest) => interest.changed = true,
+            None => return,
+        }
+        state.notified = true;
+        drop(state);
+        self.waiters.wake_up_all();
+        self.hooks.notify(PollEvents::POLLIN);
+    }
+}
+
+/// Why a change of the interest list fails.
+#[derive(Debug, PartialEq, Eq)]
+pub enum EpollCtlError {
+    /// added already
+    Exists,
+    /// not added
+    NotFound,
+    /// an epoll file, which is not added to another one
+    Invalid,
+}
+
+/// An event queue, the file of `sys_epoll_create`.
+pub struct Epoll {
+    shared: Arc<EpollShared>,
+}
+
+impl Epoll {
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(EpollShared {
+                state: unsafe {
+                    UPIntrFreeCell::new(EpollState {
+                        interests: BTreeMap::new(),
+                        notified: false,
+                    })
+                },
+                waiters: WaitQueue::new(),
+                hooks: ReadyHooks::new(),
+            }),
+        }
+    }
+
+    /// Add `file` of `fd` to the interest list with the events and flags of
+    /// `event`.
+    pub fn add(
+        &self,
+        fd: usize,
+        file: &Arc<dyn File + Send + Sync>,
+        event: EpollEvent,
+    ) -> Result<(), EpollCtlError> {
+        if file.as_any().is::<Epoll>() {
+            return Err(EpollCtlError::Invalid);
+        }
+        if self
+            .shared
+            .state
+            .exclusive_access()
+            .interests
+            .contains_key(&fd)
+        {
+            return Err(EpollCtlError::Exists);
+        }
+        // added before it is in the list, the file is not borrowed then
+        let shared = Arc::downgrade(&self.shared);
+        let hook: ReadyHook = Box::new(move |_| {
+            if let Some(shared) = shared.upgrade() {
+                shared.mark(fd);
+            }
+        });
+        let hook = file.add_ready_hook(hook);
+        let mut state = self.shared.state.exclusive_access();
+        if state.interests.contains_key(&fd) {
+            drop(state);
+            if let Some(id) = hook {
+                file.remove_ready_hook(id);
+            }
+            return Err(EpollCtlError::Exists);
+        }
+        state.interests.insert(
+            fd,
+            Interest {
+                file: Arc::downgrade(file),
+                flags: event.flags(),
+                data: event.data,
+                hook,
+                // ready already is reported even if edge triggered
+                changed: true,
+                disabled: false,
+            },
+        );
+        state.notified = true;
+        drop(state);
+        self.shared.waiters.wake_up_all();
+        Ok(())
+    }
+
+    /// Change the events, flags and data of `fd`, which is enabled again if
+    /// it is reported with `EpollFlags::EPOLLONESHOT`.
+    pub fn modify(&self, fd: usize, event: EpollEvent) -> Result<(), EpollCtlError> {
+        let mut state = self.shared.state.exclusive_access();
+        let interest = state
+            .interests
+            .get_mut(&fd)
+            .ok_or(EpollCtlError::NotFound)?;
+        interest.flags = event.flags();
+        interest.data = event.data;
+        interest.changed = true;
+        interest.disabled = false;
+        state.notified = true;
+        drop(state);
+        self.shared.waiters.wake_up_all();
+        Ok(())
+    }
+
+    pub fn remove(&self, fd: usize) -> Result<(), EpollCtlError> {
+        let interest = self
+            .shared
+            .state
+            .exclusive_access()
+            .interests
+            .remove(&fd)
+            .ok_or(EpollCtlError::NotFound)?;
+        if let (Some(file), Some(id)) = (interest.file.upgrade(), interest.hook) {
+            file.remove_ready_hook(id);
+        }
+        Ok(())
+    }
+
+    /// Poll the files to report, at most `max_events` of the ready ones.
+    /// The marks of those polled are taken, and the one-shot ones reported
+    /// are disabled, if `take`.
+    fn poll_interests(&self, max_events: usize, take: bool) -> Vec<EpollEvent> {
+        let mut state = self.shared.state.exclusive_access();
+        state
+            .interests
+            .retain(|_, interest| interest.file.strong_count() > 0);
+        if take {
+            state.notified = false;
+        }
+        let polled: Vec<_> = state
+            .interests
+            .iter()
+            .filter(|(_, interest)| {
+                let edge = interest.flags.contains(EpollFlags::EPOLLET);
+                !interest.disabled && (!edge || interest.hook.is_none() || interest.changed)
+            })
+            .filter_map(|(&fd, interest)| {
+                let file = interest.file.upgrade()?;
+                Some((fd, file, interest.flags, interest.data))
+            })
+            .collect();
+        drop(state);
+        // polled with the list released, as the files call the hooks
+        let mut events = Vec::new();
+        for (fd, file, flags, data) in polled {
+            if events.len() >= max_events {
+                break;
+            }
+            // taken before the poll, so a change after it is not missed
+            if take {
+                if let Some(interest) = self.shared.state.exclusive_access().interests.get_mut(&fd)
+                {
+                    interest.changed = false;
+                }
+            }
+            let wanted = PollEvents::from_bits_truncate(flags.bits() as u16);
+            let revents = file.poll(wanted);
+            if revents.is_empty() {
+                continue;
+            }
+            events.push(EpollEvent::new(
+                EpollFlags::from_bits_truncate(revents.bits() as u32),
+                data,
+            ));
+            if take && flags.contains(EpollFlags::EPOLLONESHOT) {
+                if let Some(interest) = self.shared.state.exclusive_access().interests.get_mut(&fd)
+                {
+                    interest.disabled = true;
+                }
+            }
+        }
+        events
+    }
+
+    /// Wait until some files are ready, the time reaches `expire_ns` if any,
+    /// or the current task is to stop waiting, then report at most
+    /// `max_events` of them. None if it stops waiting for a signal or
+    /// exiting.
+    pub fn wait(&self, max_events: usize, expire_ns: Option<usize>) -> Option<Vec<EpollEvent>> {
+        loop {
+            let events = self.poll_interests(max_events, true);
+            if !events.is_empty() || expire_ns.map_or(false, |expire_ns| get_time_ns() >= expire_ns)
+            {
+                return Some(events);
+            }
+            let state = self.shared.state.exclusive_access();
+            if state.notified {
+                continue;
+            }
+            let unhooked = state
+                .interests
+                .values()
+                .filter(|interest| interest.hook.is_none() && !interest.disabled)
+                .filter_map(|interest| interest.file.upgrade())
+                .collect::<Vec<_>>();
+            let reason = if unhooked.is_empty() {
+                self.shared.waiters.sleep_on_until(state, expire_ns)
+            } else {
+                drop(state);
+                suspend_current_and_run_next();
+                current_wake_reason()
+            };
+            if reason != WakeReason::Woken {
+                return None;
+            }
+        }
+    }
+}
+
+impl Drop for Epoll {
+    fn drop(&mut self) {
+        let interests = core::mem::take(&mut self.shared.state.exclusive_access().interests);
+        for interest in interests.values() {
+            if let (Some(file), Some(id)) = (interest.file.upgrade(), interest.hook) {
+                file.remove_ready_hook(id);
+            }
+        }
+    }
+}
+
+impl File for Epoll {
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    /// Readable while some files would be reported.
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        if self.poll_interests(1, false).is_empty() {
+            PollEvents::empty()
+        } else {
+            events & PollEvents::POLLIN
+        }
+    }
+    fn add_ready_hook(&self, hook: ReadyHook) -> Option<usize> {
+        Some(self.shared.hooks.add(hook))
+    }
+    fn remove_ready_hook(&self, id: usize) {
+        self.shared.hooks.remove(id);
+    }
+}
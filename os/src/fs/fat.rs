@@ -0,0 +1,132 @@
+//! FAT32 mounted in the VFS, of the disks and the SD cards formatted by
+//! other systems or by `mkfs_fat`.
+
+use super::vfs::{FileSystemType, Inode, SuperBlock};
+use crate::timer::get_realtime_ns;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use easy_fs::{block_cache_release, block_cache_sync_all, BlockDevice, MountFlags};
+use fat_fs::FatFileSystem;
+
+pub const FAT_FS: FileSystemType = FileSystemType {
+    name: "vfat",
+    requires_dev: true,
+    mount,
+};
+
+fn mount(
+    device: Option<Arc<dyn BlockDevice>>,
+    dev: usize,
+    flags: MountFlags,
+) -> Option<Arc<dyn SuperBlock>> {
+    let device = device?;
+    if !FatFileSystem::probe(&device) {
+        return None;
+    }
+    let fs = FatFileSystem::open(device.clone());
+    {
+        let mut fs = fs.lock();
+        fs.flags = flags;
+        fs.clock = || (get_realtime_ns() / 1_000_000_000) as u32;
+    }
+    Some(Arc::new(FatSuper {
+        root: Arc::new(FatFileSystem::root_inode(&fs)),
+        device,
+        dev,
+    }))
+}
+
+/// FAT has no owners of files, so no quotas.
+pub struct FatSuper {
+    root: Arc<fat_fs::Inode>,
+    device: Arc<dyn BlockDevice>,
+    dev: usize,
+}
+
+impl SuperBlock for FatSuper {
+    fn dev(&self) -> usize {
+        self.dev
+    }
+    fn root(self: Arc<Self>) -> Arc<dyn Inode> {
+        Arc::new(FatInode {
+            inode: self.root.clone(),
+            sb: self,
+        })
+    }
+    fn flags(&self) -> MountFlags {
+        self.root.fs().lock().flags
+    }
+    fn remount(&self, flags: MountFlags) {
+        self.root.fs().lock().remount(flags);
+    }
+    /// Like easy-fs, the device is flushed in any case.
+    fn sync(&self) {
+        block_cache_sync_all();
+        self.device.flush();
+    }
+    fn unmount(&self) {
+        block_cache_release(&self.device);
+    }
+}
+
+pub struct FatInode {
+    inode: Arc<fat_fs::Inode>,
+    sb: Arc<FatSuper>,
+}
+
+impl FatInode {
+    fn wrap(&self, inode: Arc<fat_fs::Inode>) -> Arc<dyn Inode> {
+        Arc::new(Self {
+            inode,
+            sb: self.sb.clone(),
+        })
+    }
+}
+
+impl Inode for FatInode {
+    fn id(&self) -> usize {
+        self.inode.id()
+    }
+    fn super_block(&self) -> Arc<dyn SuperBlock> {
+        self.sb.clone()
+    }
+    fn is_dir(&self) -> bool {
+        self.inode.is_dir()
+    }
+    fn size(&self) -> usize {
+        self.inode.size()
+    }
+    fn times(&self) -> (u32, u32) {
+        self.inode.times()
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        self.inode.read_at(offset, buf)
+    }
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        self.inode.write_at(offset, buf)
+    }
+    fn truncate(&self, size: usize) -> bool {
+        self.inode.truncate(size)
+    }
+    fn find(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        self.inode.find(name).map(|inode| self.wrap(inode))
+    }
+    fn create(&self, name: &str, dir: bool) -> Option<Arc<dyn Inode>> {
+        let inode = if dir {
+            self.inode.create_dir(name)
+        } else {
+            self.inode.create(name)
+        };
+        inode.map(|inode| self.wrap(inode))
+    }
+    fn rename(&self, old_name: &str, new_name: &str) -> bool {
+        self.inode.rename(old_name, new_name)
+    }
+    fn unlink(&self, name: &str, dir: bool) -> bool {
+        self.inode.unlink(name, dir)
+    }
+    fn ls(&self) -> Vec<String> {
+        self.inode.ls()
+    }
+}
@@ -1,30 +1,61 @@
-use super::File;
-use crate::drivers::BLOCK_DEVICE;
-use crate::mm::UserBuffer;
+use super::lock::release_file_locks;
+use super::page_cache;
+use super::path::{join_path, split_path};
+use super::vfs::{
+    file_id, is_mount_point, lookup, lookup_parent, permitted, Dentry, FileId, Inode,
+};
+use super::{File, SEEK_CUR, SEEK_END, SEEK_SET};
+use crate::mm::{FrameTracker, PageSource, UserBuffer};
 use crate::sync::UPIntrFreeCell;
+use crate::task::current_uid;
+use abi::{SysError, SysResult, DIRENT64_HEAD, DT_DIR, DT_REG};
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 use bitflags::*;
-use easy_fs::{EasyFileSystem, Inode};
-use lazy_static::*;
+use easy_fs::{MountFlags, BLOCK_SZ};
 
+/// An open file description, shared by the descriptors duplicated from it
+/// and the ones inherited by fork, which share the offset and the status
+/// flags but not `FdFlags`.
 pub struct OSInode {
     readable: bool,
     writable: bool,
+    /// the path from the root when opened
+    path: String,
     inner: UPIntrFreeCell<OSInodeInner>,
 }
 
 pub struct OSInodeInner {
     offset: usize,
-    inode: Arc<Inode>,
+    /// only `OpenFlags::STATUS` flags
+    status: OpenFlags,
+    inode: Arc<dyn Inode>,
+}
+
+impl OSInodeInner {
+    /// Whether to bypass the caches for `buf`, see `File::read`.
+    fn direct(&self, buf: &UserBuffer) -> bool {
+        self.status.contains(OpenFlags::DIRECT)
+            && self.offset % BLOCK_SZ == 0
+            && buf.aligned(BLOCK_SZ)
+    }
 }
 
 impl OSInode {
-    pub fn new(readable: bool, writable: bool, inode: Arc<Inode>) -> Self {
+    pub fn new(readable: bool, writable: bool, path: String, inode: Arc<dyn Inode>) -> Self {
         Self {
             readable,
             writable,
-            inner: unsafe { UPIntrFreeCell::new(OSInodeInner { offset: 0, inode }) },
+            path,
+            inner: unsafe {
+                UPIntrFreeCell::new(OSInodeInner {
+                    offset: 0,
+                    status: OpenFlags::empty(),
+                    inode,
+                })
+            },
         }
     }
     pub fn read_all(&self) -> Vec<u8> {
@@ -32,7 +63,7 @@ impl OSInode {
         let mut buffer = [0u8; 512];
         let mut v: Vec<u8> = Vec::new();
         loop {
-            let len = inner.inode.read_at(inner.offset, &mut buffer);
+            let len = page_cache::read(inner.inode.as_ref(), inner.offset, &mut buffer);
             if len == 0 {
                 break;
             }
@@ -41,18 +72,110 @@ impl OSInode {
         }
         v
     }
+    /// Write all the data at the offset, return false if nothing is written
+    /// as the owner would go over its quota.
+    pub fn write_all(&self, data: &[u8]) -> bool {
+        let mut inner = self.inner.exclusive_access();
+        let write_size = page_cache::write(inner.inode.as_ref(), inner.offset, data);
+        inner.offset += write_size;
+        write_size == data.len()
+    }
+    pub fn size(&self) -> usize {
+        self.inner.exclusive_access().inode.size()
+    }
+    pub fn path(&self) -> &str {
+        self.path.as_str()
+    }
+    pub fn is_dir(&self) -> bool {
+        self.inner.exclusive_access().inode.is_dir()
+    }
+    pub(super) fn file_id(&self) -> FileId {
+        file_id(self.inner.exclusive_access().inode.as_ref())
+    }
+    /// The owner and the permission bits of the file, if its file system
+    /// keeps them, see `Inode::permission`.
+    pub fn permission(&self) -> Option<(u32, u32)> {
+        self.inner.exclusive_access().inode.permission()
+    }
+    /// The device file if it is a device, see `Inode::open_device`.
+    pub fn open_device(&self, flags: OpenFlags) -> Option<Arc<dyn File + Send + Sync>> {
+        self.inner.exclusive_access().inode.open_device(flags)
+    }
+    pub fn offset(&self) -> usize {
+        self.inner.exclusive_access().offset
+    }
+    /// Return the access and modification times in seconds since boot.
+    pub fn times(&self) -> (usize, usize) {
+        let (atime, mtime) = self.inner.exclusive_access().inode.times();
+        (atime as usize, mtime as usize)
+    }
+    /// The entries of a directory from the offset on as `linux_dirent64`,
+    /// "." and ".." first, as many as fit in `len` bytes. The offset counts
+    /// the entries. Empty at the end, None if it is not a directory or the
+    /// next entry does not fit.
+    pub fn read_dir(&self, len: usize) -> Option<Vec<u8>> {
+        let mut inner = self.inner.exclusive_access();
+        if !inner.inode.is_dir() {
+            return None;
+        }
+        let mut names = vec![String::from("."), String::from("..")];
+        names.extend(inner.inode.ls());
+        let mut dirents = Vec::new();
+        for (index, name) in names.iter().enumerate().skip(inner.offset) {
+            let reclen = (DIRENT64_HEAD + name.len() + 1 + 7) / 8 * 8;
+            if dirents.len() + reclen > len {
+                if dirents.is_empty() {
+                    return None;
+                }
+                break;
+            }
+            let (ino, dir) = match name.as_str() {
+                "." => (inner.inode.id(), true),
+                ".." => lookup(join_path(self.path.as_str(), "..").as_str())
+                    .map_or((0, true), |dentry| (dentry.inode.id(), true)),
+                _ => inner
+                    .inode
+                    .find(name.as_str())
+                    .map_or((0, false), |inode| (inode.id(), inode.is_dir())),
+            };
+            let start = dirents.len();
+            dirents.extend_from_slice(&(ino as u64).to_le_bytes());
+            dirents.extend_from_slice(&(index as i64 + 1).to_le_bytes());
+            dirents.extend_from_slice(&(reclen as u16).to_le_bytes());
+            dirents.push(if dir { DT_DIR } else { DT_REG });
+            dirents.extend_from_slice(name.as_bytes());
+            dirents.resize(start + reclen, 0);
+            inner.offset = index + 1;
+        }
+        Some(dirents)
+    }
+    /// The frame of the page `index` in the page cache, shared read-only.
+    pub fn cached_page(&self, index: usize) -> Option<FrameTracker> {
+        let inode = self.inner.exclusive_access().inode.clone();
+        page_cache::file_page(inode.as_ref(), index)
+    }
 }
 
-lazy_static! {
-    pub static ref ROOT_INODE: Arc<Inode> = {
-        let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
-        Arc::new(EasyFileSystem::root_inode(&efs))
-    };
+/// The flock of it is released once no descriptor refers to it.
+impl Drop for OSInode {
+    fn drop(&mut self) {
+        release_file_locks(self);
+    }
+}
+
+/// Programs and mapped files are loaded from the page cache on demand.
+impl PageSource for OSInode {
+    fn page(&self, index: usize) -> Option<FrameTracker> {
+        self.cached_page(index)
+    }
+    fn size(&self) -> usize {
+        OSInode::size(self)
+    }
 }
 
 pub fn list_apps() {
     println!("/**** APPS ****");
-    for app in ROOT_INODE.ls() {
+    for app in lookup("/").unwrap().inode.ls() {
         println!("{}", app);
     }
     println!("**************/")
@@ -63,8 +186,20 @@ bitflags! {
         const RDONLY = 0;
         const WRONLY = 1 << 0;
         const RDWR = 1 << 1;
+        const EXCL = 1 << 7;
+        /// each write is at the end of the file
+        const APPEND = 1 << 8;
         const CREATE = 1 << 9;
         const TRUNC = 1 << 10;
+        const NONBLOCK = 1 << 11;
+        /// reads and writes of whole blocks bypass the page cache and the
+        /// block cache
+        const DIRECT = 1 << 14;
+        /// set `FdFlags::CLOEXEC` of the new descriptor
+        const CLOEXEC = 1 << 19;
+        /// the flags of the open file rather than of opening it, which can
+        /// be changed by `F_SETFL`
+        const STATUS = Self::APPEND.bits | Self::NONBLOCK.bits | Self::DIRECT.bits;
     }
 }
 
@@ -72,9 +207,11 @@ impl OpenFlags {
     /// Do not check validity for simplicity
     /// Return (readable, writable)
     pub fn read_write(&self) -> (bool, bool) {
-        if self.is_empty() {
+        // these flags do not change the access mode
+        let flags = *self - (Self::EXCL | Self::STATUS | Self::CLOEXEC);
+        if flags.is_empty() {
             (true, false)
-        } else if self.contains(Self::WRONLY) {
+        } else if flags.contains(Self::WRONLY) {
             (false, true)
         } else {
             (true, true)
@@ -82,26 +219,137 @@ impl OpenFlags {
     }
 }
 
-pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
+/// Open a file by its path from the root directory, which is resolved by the
+/// caller for processes.
+pub fn open_file(path: &str, flags: OpenFlags) -> SysResult<Arc<OSInode>> {
+    let file = open_inode(path, flags)?;
+    file.set_status(flags & OpenFlags::STATUS);
+    Ok(file)
+}
+
+fn open_inode(path: &str, flags: OpenFlags) -> SysResult<Arc<OSInode>> {
     let (readable, writable) = flags.read_write();
-    if flags.contains(OpenFlags::CREATE) {
-        if let Some(inode) = ROOT_INODE.find(name) {
-            // clear size
-            inode.clear();
-            Some(Arc::new(OSInode::new(readable, writable, inode)))
-        } else {
+    let dentry = match lookup(path) {
+        Some(dentry) => {
+            if flags.contains(OpenFlags::CREATE | OpenFlags::EXCL) {
+                return Err(SysError::Exists);
+            }
+            dentry
+        }
+        None if flags.contains(OpenFlags::CREATE) => {
             // create file
-            ROOT_INODE
-                .create(name)
-                .map(|inode| Arc::new(OSInode::new(readable, writable, inode)))
+            let (parent, name) = lookup_parent(path).ok_or(SysError::NoEntry)?;
+            if parent.sb.flags().contains(MountFlags::RDONLY) {
+                return Err(SysError::ReadOnly);
+            }
+            let inode = parent
+                .inode
+                .create(name.as_str(), false)
+                .ok_or(SysError::NoSpace)?;
+            Dentry {
+                path: join_path("/", path),
+                inode,
+                sb: parent.sb,
+            }
         }
+        None => return Err(SysError::NoEntry),
+    };
+    if (writable || flags.intersects(OpenFlags::CREATE | OpenFlags::TRUNC))
+        && dentry.sb.flags().contains(MountFlags::RDONLY)
+    {
+        return Err(SysError::ReadOnly);
+    }
+    if let Some((owner, mode)) = dentry.inode.permission() {
+        if !permitted(owner, mode, current_uid(), readable, writable) {
+            return Err(SysError::Denied);
+        }
+    }
+    if flags.intersects(OpenFlags::CREATE | OpenFlags::TRUNC) {
+        // clear size
+        dentry.inode.truncate(0);
+        page_cache::invalidate(dentry.inode.as_ref());
+    }
+    Ok(Arc::new(OSInode::new(
+        readable,
+        writable,
+        dentry.path,
+        dentry.inode,
+    )))
+}
+
+pub fn make_dir(path: &str) -> SysResult<()> {
+    if lookup(path).is_some() {
+        return Err(SysError::Exists);
+    }
+    let (parent, name) = lookup_parent(path).ok_or(SysError::NoEntry)?;
+    if parent.sb.flags().contains(MountFlags::RDONLY) {
+        return Err(SysError::ReadOnly);
+    }
+    parent
+        .inode
+        .create(name.as_str(), true)
+        .map(drop)
+        .ok_or(SysError::NoSpace)
+}
+
+/// Fail with `NoEntry` if nothing is at the path, or `NotDir` if it is no
+/// directory.
+pub fn check_dir(path: &str) -> SysResult<()> {
+    match lookup(path) {
+        Some(dentry) if dentry.inode.is_dir() => Ok(()),
+        Some(_) => Err(SysError::NotDir),
+        None => Err(SysError::NoEntry),
+    }
+}
+
+/// Only renaming in the same directory is supported, but not the
+/// directories mounted on.
+pub fn rename_file(old_path: &str, new_path: &str) -> SysResult<()> {
+    let (old_path, new_path) = (join_path("/", old_path), join_path("/", new_path));
+    let (old_dir, _) = split_path(old_path.as_str());
+    let (new_dir, new_name) = split_path(new_path.as_str());
+    if old_dir != new_dir || new_name.is_empty() {
+        return Err(SysError::Invalid);
+    }
+    if is_mount_point(old_path.as_str()) || is_mount_point(new_path.as_str()) {
+        return Err(SysError::Busy);
+    }
+    let (parent, old_name) = lookup_parent(old_path.as_str()).ok_or(SysError::NoEntry)?;
+    if parent.inode.find(old_name.as_str()).is_none() {
+        return Err(SysError::NoEntry);
+    }
+    if parent.inode.rename(old_name.as_str(), new_name) {
+        Ok(())
     } else {
-        ROOT_INODE.find(name).map(|inode| {
-            if flags.contains(OpenFlags::TRUNC) {
-                inode.clear();
-            }
-            Arc::new(OSInode::new(readable, writable, inode))
-        })
+        Err(SysError::Exists)
+    }
+}
+
+/// Remove a file, or an empty directory if `dir`, but not one in use or
+/// mounted on.
+pub fn remove_file(path: &str, dir: bool) -> SysResult<()> {
+    let path = join_path("/", path);
+    if is_mount_point(path.as_str()) {
+        return Err(SysError::Busy);
+    }
+    let (parent, name) = lookup_parent(path.as_str()).ok_or(SysError::NoEntry)?;
+    let inode = parent.inode.find(name.as_str()).ok_or(SysError::NoEntry)?;
+    match (dir, inode.is_dir()) {
+        (true, false) => return Err(SysError::NotDir),
+        (false, true) => return Err(SysError::IsDir),
+        (true, true) if !inode.ls().is_empty() => return Err(SysError::NotEmpty),
+        _ => {}
+    }
+    if parent.sb.flags().contains(MountFlags::RDONLY) {
+        return Err(SysError::ReadOnly);
+    }
+    // the inode number may be taken by another file after
+    page_cache::invalidate(inode.as_ref());
+    if parent.inode.unlink(name.as_str(), dir) {
+        Ok(())
+    } else {
+        // still open
+        Err(SysError::Busy)
     }
 }
 
@@ -112,11 +360,21 @@ impl File for OSInode {
     fn writable(&self) -> bool {
         self.writable
     }
+    /// With `OpenFlags::DIRECT`, the blocks are transferred straight between
+    /// the device and the user pages if the offset and the buffer are
+    /// aligned to blocks and the file system supports it. Otherwise it falls
+    /// back to the page cache.
     fn read(&self, mut buf: UserBuffer) -> usize {
         let mut inner = self.inner.exclusive_access();
+        if inner.direct(&buf) {
+            if let Some(read_size) = inner.inode.read_direct(inner.offset, &mut buf.buffers) {
+                inner.offset += read_size;
+                return read_size;
+            }
+        }
         let mut total_read_size = 0usize;
         for slice in buf.buffers.iter_mut() {
-            let read_size = inner.inode.read_at(inner.offset, *slice);
+            let read_size = page_cache::read(inner.inode.as_ref(), inner.offset, *slice);
             if read_size == 0 {
                 break;
             }
@@ -127,13 +385,69 @@ impl File for OSInode {
     }
     fn write(&self, buf: UserBuffer) -> usize {
         let mut inner = self.inner.exclusive_access();
+        if inner.status.contains(OpenFlags::APPEND) {
+            inner.offset = inner.inode.size();
+        }
+        if inner.direct(&buf) {
+            let bufs: Vec<&[u8]> = buf.buffers.iter().map(|slice| &**slice).collect();
+            if let Some(write_size) = inner.inode.write_direct(inner.offset, &bufs) {
+                page_cache::invalidate(inner.inode.as_ref());
+                inner.offset += write_size;
+                return write_size;
+            }
+        }
         let mut total_write_size = 0usize;
         for slice in buf.buffers.iter() {
-            let write_size = inner.inode.write_at(inner.offset, *slice);
-            assert_eq!(write_size, slice.len());
+            // nothing is written if the owner would go over its quota
+            let write_size = page_cache::write(inner.inode.as_ref(), inner.offset, *slice);
+            if write_size == 0 {
+                break;
+            }
             inner.offset += write_size;
             total_write_size += write_size;
         }
         total_write_size
     }
+    fn seek(&self, offset: isize, whence: usize) -> isize {
+        let mut inner = self.inner.exclusive_access();
+        let base = match whence {
+            SEEK_SET => 0,
+            SEEK_CUR => inner.offset as isize,
+            SEEK_END => inner.inode.size() as isize,
+            _ => return SysError::Invalid.code(),
+        };
+        let new_offset = base + offset;
+        if new_offset < 0 {
+            return SysError::Invalid.code();
+        }
+        inner.offset = new_offset as usize;
+        new_offset
+    }
+    fn truncate(&self, len: usize) -> isize {
+        if !self.writable {
+            return SysError::BadFd.code();
+        }
+        let inner = self.inner.exclusive_access();
+        let truncated = inner.inode.truncate(len);
+        page_cache::invalidate(inner.inode.as_ref());
+        if truncated {
+            0
+        } else {
+            SysError::NoSpace.code()
+        }
+    }
+    fn sync(&self) -> isize {
+        self.inner.exclusive_access().inode.super_block().sync();
+        0
+    }
+    fn status(&self) -> OpenFlags {
+        self.inner.exclusive_access().status
+    }
+    fn set_status(&self, flags: OpenFlags) -> bool {
+        self.inner.exclusive_access().status = flags & OpenFlags::STATUS;
+        true
+    }
+    fn page_source(self: Arc<Self>) -> Option<Arc<dyn PageSource>> {
+        Some(self)
+    }
 }
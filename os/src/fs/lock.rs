@@ -0,0 +1,391 @@
+//! Advisory locks of files, which only the processes taking them respect.
+//!
+//! flock locks a whole file for an open file, shared by the descriptors
+//! duplicated from it, until it is unlocked or the open file is dropped.
+//! fcntl locks ranges of bytes for a process, until they are unlocked, the
+//! process closes any descriptor of the file, or it exits. The two kinds do
+//! not conflict with each other, like on Linux.
+//!
+//! A process waiting for a range which is held by processes waiting for
+//! what it holds itself, directly or through others, is told of the
+//! deadlock instead of waiting forever. Waits for flock are not checked.
+
+use super::vfs::FileId;
+use super::{File, OSInode, SEEK_CUR, SEEK_END, SEEK_SET};
+use crate::sync::{UPIntrFreeCell, WaitQueue};
+use crate::task::{current_task, WakeReason};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+pub const LOCK_SH: usize = 1;
+pub const LOCK_EX: usize = 2;
+pub const LOCK_NB: usize = 4;
+pub const LOCK_UN: usize = 8;
+
+pub const F_GETLK: usize = 5;
+pub const F_SETLK: usize = 6;
+pub const F_SETLKW: usize = 7;
+
+pub const F_RDLCK: i16 = 0;
+pub const F_WRLCK: i16 = 1;
+pub const F_UNLCK: i16 = 2;
+
+/// Why a lock is not taken.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LockError {
+    /// not a valid range or operation
+    Invalid,
+    /// held by another owner, and the caller does not wait
+    WouldBlock,
+    /// the holders wait for what the caller holds
+    Deadlock,
+    /// a signal is to be handled, the syscall may be restarted
+    Signalled,
+    /// the caller is exiting
+    Exiting,
+}
+
+/// Same layout as the flock of Linux, a range of bytes for fcntl.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Flock {
+    pub l_type: i16,
+    pub l_whence: i16,
+    pub l_start: i64,
+    /// to the end of the file however it grows if 0, before `l_start` if
+    /// negative
+    pub l_len: i64,
+    /// of the holder of a conflicting lock, by `F_GETLK`
+    pub l_pid: i32,
+}
+
+impl Flock {
+    /// The range from `start` to `end`, exclusive, relative to the offset
+    /// `offset` and the size `size` of the file as `l_whence` says. None if
+    /// it begins before the file.
+    fn range(&self, offset: usize, size: usize) -> Option<(usize, usize)> {
+        let base = match self.l_whence as usize {
+            SEEK_SET => 0,
+            SEEK_CUR => offset as i64,
+            SEEK_END => size as i64,
+            _ => return None,
+        };
+        let start = base.checked_add(self.l_start)?;
+        let (start, end) = match self.l_len {
+            0 => (start, i64::MAX),
+            len if len > 0 => (start, start.saturating_add(len)),
+            len => (start.checked_add(len)?, start),
+        };
+        if start < 0 {
+            return None;
+        }
+        Some((start as usize, end as usize))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Owner {
+    /// by flock, the open file by its address
+    File(usize),
+    /// by fcntl, the process by its pid
+    Process(usize),
+}
+
+#[derive(Clone, Copy)]
+struct Lock {
+    owner: Owner,
+    write: bool,
+    start: usize,
+    /// exclusive, `i64::MAX` to the end of the file
+    end: usize,
+}
+
+impl Lock {
+    /// Whether `self` cannot be held with `other`, by another owner of the
+    /// same kind.
+    fn conflicts(&self, other: &Lock) -> bool {
+        let same_kind = matches!(
+            (self.owner, other.owner),
+            (Owner::File(_), Owner::File(_)) | (Owner::Process(_), Owner::Process(_))
+        );
+        same_kind
+            && self.owner != other.owner
+            && (self.write || other.write)
+            && self.start < other.end
+            && other.start < self.end
+    }
+}
+
+/// What a task waits for, to find deadlocks.
+struct Waiter {
+    pid: usize,
+    file: FileId,
+    request: Lock,
+}
+
+struct FileLocks {
+    files: BTreeMap<FileId, Vec<Lock>>,
+    /// by the address of the task
+    waiters: BTreeMap<usize, Waiter>,
+}
+
+impl FileLocks {
+    fn conflict(&self, file: FileId, request: &Lock) -> Option<Lock> {
+        self.files
+            .get(&file)?
+            .iter()
+            .find(|lock| lock.conflicts(request))
+            .copied()
+    }
+
+    /// Whether the process `pid` would wait for itself, if it waited for
+    /// the holders of the locks conflicting with `request`, and for the
+    /// holders of what they wait for in turn.
+    fn deadlock(&self, pid: usize, file: FileId, request: &Lock) -> bool {
+        let holders = |file: FileId, request: &Lock| -> Vec<usize> {
+            self.files.get(&file).map_or(Vec::new(), |locks| {
+                locks
+                    .iter()
+                    .filter(|lock| lock.conflicts(request))
+                    .filter_map(|lock| match lock.owner {
+                        Owner::Process(pid) => Some(pid),
+                        Owner::File(_) => None,
+                    })
+                    .collect()
+            })
+        };
+        let mut pending = holders(file, request);
+        let mut seen = BTreeSet::new();
+        while let Some(holder) = pending.pop() {
+            if holder == pid {
+                return true;
+            }
+            if !seen.insert(holder) {
+                continue;
+            }
+            for waiter in self.waiters.values().filter(|waiter| waiter.pid == holder) {
+                pending.extend(holders(waiter.file, &waiter.request));
+            }
+        }
+        false
+    }
+
+    /// Release what `owner` holds of the range of `lock`, the parts of its
+    /// locks out of it are kept, then take `lock` if `take`.
+    fn replace(&mut self, file: FileId, lock: Lock, take: bool) {
+        let locks = self.files.entry(file).or_default();
+        let mut kept = Vec::new();
+        for held in locks.drain(..) {
+            if held.owner != lock.owner || held.end <= lock.start || lock.end <= held.start {
+                kept.push(held);
+                continue;
+            }
+            if held.start < lock.start {
+                kept.push(Lock {
+                    end: lock.start,
+                    ..held
+                });
+            }
+            if lock.end < held.end {
+                kept.push(Lock {
+                    start: lock.end,
+                    ..held
+                });
+            }
+        }
+        if take {
+            kept.push(lock);
+        }
+        if kept.is_empty() {
+            self.files.remove(&file);
+        } else {
+            *locks = kept;
+        }
+    }
+
+    fn release_all(&mut self, mut released: impl FnMut(&FileId, &Lock) -> bool) {
+        for (file, locks) in self.files.iter_mut() {
+            locks.retain(|lock| !released(file, lock));
+        }
+        self.files.retain(|_, locks| !locks.is_empty());
+    }
+}
+
+lazy_static! {
+    static ref LOCKS: UPIntrFreeCell<FileLocks> = unsafe {
+        UPIntrFreeCell::new(FileLocks {
+            files: BTreeMap::new(),
+            waiters: BTreeMap::new(),
+        })
+    };
+    /// Woken up all at once whenever something is released.
+    static ref RELEASED: WaitQueue = WaitQueue::new();
+}
+
+/// Take `request` of `file`, waiting for the conflicting locks to be
+/// released if `wait`.
+fn acquire(pid: usize, file: FileId, request: Lock, wait: bool) -> Result<(), LockError> {
+    let task = current_task().map_or(0, |task| Arc::as_ptr(&task) as usize);
+    loop {
+        let mut locks = LOCKS.exclusive_access();
+        locks.waiters.remove(&task);
+        if locks.conflict(file, &request).is_none() {
+            locks.replace(file, request, true);
+            return Ok(());
+        }
+        if !wait {
+            return Err(LockError::WouldBlock);
+        }
+        if matches!(request.owner, Owner::Process(_)) && locks.deadlock(pid, file, &request) {
+            return Err(LockError::Deadlock);
+        }
+        locks.waiters.insert(task, Waiter { pid, file, request });
+        match RELEASED.sleep_on(locks) {
+            WakeReason::Woken => continue,
+            reason => {
+                LOCKS.exclusive_access().waiters.remove(&task);
+                return Err(match reason {
+                    WakeReason::Signalled => LockError::Signalled,
+                    _ => LockError::Exiting,
+                });
+            }
+        }
+    }
+}
+
+fn release(file: FileId, lock: Lock) {
+    LOCKS.exclusive_access().replace(file, lock, false);
+    RELEASED.wake_up_all();
+}
+
+/// Lock the whole of an open file for flock as `op` says, see `LOCK_SH`. A
+/// lock held is released first when it is changed, like on Linux.
+pub fn flock(file: &OSInode, pid: usize, op: usize) -> Result<(), LockError> {
+    let write = match op & !LOCK_NB {
+        LOCK_SH => false,
+        LOCK_EX => true,
+        LOCK_UN => {
+            release(file.file_id(), whole_file(file, false));
+            return Ok(());
+        }
+        _ => return Err(LockError::Invalid),
+    };
+    let lock = whole_file(file, write);
+    let held = LOCKS
+        .exclusive_access()
+        .files
+        .get(&file.file_id())
+        .map_or(false, |locks| {
+            locks
+                .iter()
+                .any(|held| held.owner == lock.owner && held.write != write)
+        });
+    if held {
+        release(file.file_id(), lock);
+    }
+    acquire(pid, file.file_id(), lock, op & LOCK_NB == 0)
+}
+
+fn whole_file(file: &OSInode, write: bool) -> Lock {
+    Lock {
+        owner: Owner::File(file as *const OSInode as usize),
+        write,
+        start: 0,
+        end: i64::MAX as usize,
+    }
+}
+
+/// The range of `flock` as a lock of the process `pid`, None if it is not a
+/// valid one.
+fn range_lock(file: &OSInode, pid: usize, flock: &Flock) -> Option<Lock> {
+    let (start, end) = flock.range(file.offset(), file.size())?;
+    let write = match flock.l_type {
+        F_RDLCK => false,
+        F_WRLCK | F_UNLCK => true,
+        _ => return None,
+    };
+    Some(Lock {
+        owner: Owner::Process(pid),
+        write,
+        start,
+        end,
+    })
+}
+
+/// Set or release a range of a file for the process `pid` as `flock` says,
+/// waiting for it if `wait`.
+pub fn set_range_lock(
+    file: &OSInode,
+    pid: usize,
+    flock: &Flock,
+    wait: bool,
+) -> Result<(), LockError> {
+    let lock = range_lock(file, pid, flock).ok_or(LockError::Invalid)?;
+    if flock.l_type == F_UNLCK {
+        release(file.file_id(), lock);
+        return Ok(());
+    }
+    acquire(pid, file.file_id(), lock, wait)
+}
+
+/// Find a lock conflicting with the range of `flock`, which is changed to
+/// describe it, or has `l_type` set to `F_UNLCK` if there is none.
+pub fn get_range_lock(file: &OSInode, pid: usize, flock: &mut Flock) -> Result<(), LockError> {
+    let request = match range_lock(file, pid, flock) {
+        Some(lock) if flock.l_type != F_UNLCK => lock,
+        _ => return Err(LockError::Invalid),
+    };
+    match LOCKS.exclusive_access().conflict(file.file_id(), &request) {
+        Some(lock) => {
+            flock.l_type = if lock.write { F_WRLCK } else { F_RDLCK };
+            flock.l_whence = SEEK_SET as i16;
+            flock.l_start = lock.start as i64;
+            flock.l_len = if lock.end == i64::MAX as usize {
+                0
+            } else {
+                (lock.end - lock.start) as i64
+            };
+            flock.l_pid = match lock.owner {
+                Owner::Process(pid) => pid as i32,
+                Owner::File(_) => -1,
+            };
+        }
+        None => flock.l_type = F_UNLCK,
+    }
+    Ok(())
+}
+
+/// Release the ranges the process `pid` holds of a file, when it closes a
+/// descriptor of it. Nothing is done for files other than inodes.
+pub fn release_range_locks(file: &dyn File, pid: usize) {
+    let id = match file.as_any().downcast_ref::<OSInode>() {
+        Some(inode) => inode.file_id(),
+        None => return,
+    };
+    LOCKS
+        .exclusive_access()
+        .release_all(|file, lock| *file == id && lock.owner == Owner::Process(pid));
+    RELEASED.wake_up_all();
+}
+
+/// Release all the ranges the process `pid` holds, when it exits.
+pub fn release_process_locks(pid: usize) {
+    LOCKS
+        .exclusive_access()
+        .release_all(|_, lock| lock.owner == Owner::Process(pid));
+    RELEASED.wake_up_all();
+}
+
+/// Release the flock of an open file, when it is dropped.
+pub fn release_file_locks(file: &OSInode) {
+    let owner = Owner::File(file as *const OSInode as usize);
+    let mut locks = LOCKS.exclusive_access();
+    if locks.files.is_empty() {
+        return;
+    }
+    locks.release_all(|_, lock| lock.owner == owner);
+    drop(locks);
+    RELEASED.wake_up_all();
+}
@@ -1,16 +1,164 @@
+mod dev;
+mod devfs;
+mod easyfs;
+mod epoll;
+mod fat;
 mod inode;
+mod lock;
+mod mqueue;
+mod page_cache;
+mod path;
+mod pidfd;
 mod pipe;
+mod poll;
+mod proc;
 mod stdio;
+mod syncd;
+mod tty;
+mod vfs;
 
-use crate::mm::UserBuffer;
+use crate::mm::{PageSource, UserBuffer};
+use abi::{SysError, SysResult};
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use bitflags::*;
+use core::any::Any;
 
-pub trait File: Send + Sync {
+/// Get `&dyn Any` of a file to downcast it to the concrete type.
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+pub trait File: Send + Sync + AsAny {
     fn readable(&self) -> bool;
     fn writable(&self) -> bool;
     fn read(&self, buf: UserBuffer) -> usize;
     fn write(&self, buf: UserBuffer) -> usize;
+    /// Reposition the read/write offset, return the new offset.
+    fn seek(&self, _offset: isize, _whence: usize) -> isize {
+        SysError::Invalid.code()
+    }
+    fn truncate(&self, _len: usize) -> isize {
+        SysError::Invalid.code()
+    }
+    fn ioctl(&self, _cmd: usize, _arg: usize) -> isize {
+        SysError::NotTty.code()
+    }
+    /// Make what has been written to the file persistent on its device,
+    /// `SysError::Invalid` if it has none.
+    fn sync(&self) -> isize {
+        SysError::Invalid.code()
+    }
+    /// Return the events in `events` which are ready now without blocking.
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        let mut revents = PollEvents::empty();
+        if self.readable() {
+            revents |= PollEvents::POLLIN;
+        }
+        if self.writable() {
+            revents |= PollEvents::POLLOUT;
+        }
+        events & revents
+    }
+    /// Whether a read, or a write if `write`, would wait as the file is
+    /// `OpenFlags::NONBLOCK`, then `sys_read` and `sys_write` return
+    /// `SysError::WouldBlock` instead. Only the files supporting it ever would.
+    fn would_block(&self, _write: bool) -> bool {
+        false
+    }
+    /// Whether nothing can be written any more, as no reader of the pipe is
+    /// left, then `sys_write` returns `SysError::BrokenPipe`.
+    fn broken(&self) -> bool {
+        false
+    }
+    /// Call `hook` whenever some events of the file may have become ready,
+    /// until `remove_ready_hook` with the id returned. None if the file
+    /// never calls it, then it is to be polled again and again. The hook
+    /// must not add or remove hooks.
+    fn add_ready_hook(&self, _hook: ReadyHook) -> Option<usize> {
+        None
+    }
+    fn remove_ready_hook(&self, _id: usize) {}
+    /// Whether the file is a socket, each write of which is sent as a packet.
+    fn is_socket(&self) -> bool {
+        false
+    }
+    /// The `OpenFlags::STATUS` flags of the open file, which are shared by
+    /// all descriptors of it.
+    fn status(&self) -> OpenFlags {
+        OpenFlags::empty()
+    }
+    /// Change the status flags, return false if some are not supported.
+    fn set_status(&self, flags: OpenFlags) -> bool {
+        flags.is_empty()
+    }
+    /// Where the pages of the file are mapped from by `sys_mmap`, None if
+    /// it cannot be mapped.
+    fn page_source(self: Arc<Self>) -> Option<Arc<dyn PageSource>> {
+        None
+    }
+}
+
+bitflags! {
+    pub struct PollEvents: u16 {
+        const POLLIN = 0x001;
+        const POLLOUT = 0x004;
+        const POLLERR = 0x008;
+        const POLLHUP = 0x010;
+        const POLLNVAL = 0x020;
+    }
 }
 
-pub use inode::{list_apps, open_file, OSInode, OpenFlags, ROOT_INODE};
-pub use pipe::{make_pipe, Pipe};
-pub use stdio::{Stdin, Stdout};
+/// Called with the events of a file which may have become ready, see
+/// `File::add_ready_hook`.
+pub type ReadyHook = Box<dyn Fn(PollEvents) + Send + Sync>;
+
+/// Same layout as the pollfd of Linux
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: PollEvents,
+    pub revents: PollEvents,
+}
+
+pub const SEEK_SET: usize = 0;
+pub const SEEK_CUR: usize = 1;
+pub const SEEK_END: usize = 2;
+
+pub use dev::BlockDevFile;
+pub use devfs::CharDevFile;
+pub use epoll::{Epoll, EpollCtlError};
+pub use inode::{
+    check_dir, list_apps, make_dir, open_file, remove_file, rename_file, OSInode, OpenFlags,
+};
+pub use lock::{
+    flock, get_range_lock, release_process_locks, release_range_locks, set_range_lock, Flock,
+    LockError, F_GETLK, F_RDLCK, F_SETLK, F_SETLKW, F_WRLCK,
+};
+pub use mqueue::{mq_open, mq_unlink, MqAttr, MqFd, MQ_PRIO_MAX};
+pub use path::join_path;
+pub use pidfd::{ExitStatus, PidFd};
+pub use pipe::{make_pipe, Pipe, PIPE_MAX_SIZE};
+pub use poll::{Poller, ReadyHooks};
+pub use stdio::{Stdin, Stdout, Tty};
+pub use syncd::spawn_syncd;
+pub use tty::CONSOLE_TTY;
+pub use vfs::{mount, remount, root_super_block, sync_all, umount};
+
+/// Open a file by its path from the root directory, or a file of a mounted
+/// file system, procfs on `/proc` and devfs on `/dev` among them. A device
+/// gives its own file.
+pub fn open_path(path: &str, flags: OpenFlags) -> SysResult<Arc<dyn File + Send + Sync>> {
+    let file = open_file(path, flags)?;
+    match file.open_device(flags) {
+        Some(device) => Ok(device),
+        None => Ok(file),
+    }
+}
@@ -0,0 +1,243 @@
+use super::{File, OpenFlags, PollEvents};
+use crate::mm::UserBuffer;
+use crate::sync::{UPIntrFreeCell, WaitQueue};
+use crate::task::WakeReason;
+use abi::{SysError, SysResult};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+pub const MQ_MAXMSG_DEFAULT: usize = 10;
+pub const MQ_MSGSIZE_DEFAULT: usize = 1024;
+pub const MQ_MAXMSG_MAX: usize = 64;
+pub const MQ_MSGSIZE_MAX: usize = 4096;
+pub const MQ_PRIO_MAX: u32 = 32768;
+
+/// Same as mq_attr of Linux without the reserved fields
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct MqAttr {
+    pub flags: usize,
+    pub maxmsg: usize,
+    pub msgsize: usize,
+    pub curmsgs: usize,
+}
+
+pub struct MessageQueue {
+    inner: UPIntrFreeCell<MessageQueueInner>,
+    receivers: WaitQueue,
+    senders: WaitQueue,
+}
+
+pub struct MessageQueueInner {
+    maxmsg: usize,
+    msgsize: usize,
+    curmsgs: usize,
+    /// messages of the same priority are in FIFO order
+    messages: BTreeMap<u32, VecDeque<Vec<u8>>>,
+}
+
+impl MessageQueue {
+    pub fn new(maxmsg: usize, msgsize: usize) -> Self {
+        Self {
+            inner: unsafe {
+                UPIntrFreeCell::new(MessageQueueInner {
+                    maxmsg,
+                    msgsize,
+                    curmsgs: 0,
+                    messages: BTreeMap::new(),
+                })
+            },
+            receivers: WaitQueue::new(),
+            senders: WaitQueue::new(),
+        }
+    }
+
+    /// Fail with `SysError::NotReady` if the queue is full and `nonblock` is
+    /// set, or `SysError::Interrupted` if the task is to stop waiting.
+    /// The message should not be longer than msgsize.
+    pub fn send(&self, msg: Vec<u8>, prio: u32, nonblock: bool) -> SysResult<()> {
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            assert!(msg.len() <= inner.msgsize);
+            if inner.curmsgs < inner.maxmsg {
+                inner.messages.entry(prio).or_default().push_back(msg);
+                inner.curmsgs += 1;
+                self.receivers.wake_up();
+                return Ok(());
+            }
+            if nonblock {
+                return Err(SysError::NotReady);
+            }
+            if self.senders.sleep_on(inner) != WakeReason::Woken {
+                return Err(SysError::Interrupted);
+            }
+        }
+    }
+
+    /// Take the oldest message of the highest priority.
+    /// Fail like `send` if the queue is empty.
+    pub fn receive(&self, nonblock: bool) -> SysResult<(Vec<u8>, u32)> {
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if let Some(mut entry) = inner.messages.last_entry() {
+                let prio = *entry.key();
+                let msg = entry.get_mut().pop_front().unwrap();
+                if entry.get().is_empty() {
+                    entry.remove();
+                }
+                inner.curmsgs -= 1;
+                self.senders.wake_up();
+                return Ok((msg, prio));
+            }
+            if nonblock {
+                return Err(SysError::NotReady);
+            }
+            if self.receivers.sleep_on(inner) != WakeReason::Woken {
+                return Err(SysError::Interrupted);
+            }
+        }
+    }
+
+    pub fn msgsize(&self) -> usize {
+        self.inner.exclusive_access().msgsize
+    }
+
+    pub fn attr(&self) -> MqAttr {
+        let inner = self.inner.exclusive_access();
+        MqAttr {
+            flags: 0,
+            maxmsg: inner.maxmsg,
+            msgsize: inner.msgsize,
+            curmsgs: inner.curmsgs,
+        }
+    }
+}
+
+lazy_static! {
+    static ref MQUEUES: UPIntrFreeCell<BTreeMap<String, Arc<MessageQueue>>> =
+        unsafe { UPIntrFreeCell::new(BTreeMap::new()) };
+}
+
+/// An opened message queue, the queue lives until it is unlinked and closed.
+pub struct MqFd {
+    readable: bool,
+    writable: bool,
+    nonblock: UPIntrFreeCell<bool>,
+    queue: Arc<MessageQueue>,
+}
+
+impl MqFd {
+    pub fn queue(&self) -> &Arc<MessageQueue> {
+        &self.queue
+    }
+    pub fn nonblock(&self) -> bool {
+        *self.nonblock.exclusive_access()
+    }
+    pub fn set_nonblock(&self, nonblock: bool) {
+        *self.nonblock.exclusive_access() = nonblock;
+    }
+}
+
+/// Open or create the queue `name`, `attr` is used only when creating.
+pub fn mq_open(name: &str, flags: OpenFlags, attr: Option<MqAttr>) -> SysResult<Arc<MqFd>> {
+    let mut mqueues = MQUEUES.exclusive_access();
+    let queue = match mqueues.get(name) {
+        Some(_) if flags.contains(OpenFlags::CREATE | OpenFlags::EXCL) => {
+            return Err(SysError::Exists)
+        }
+        Some(queue) => queue.clone(),
+        None if flags.contains(OpenFlags::CREATE) => {
+            let attr = attr.unwrap_or(MqAttr {
+                maxmsg: MQ_MAXMSG_DEFAULT,
+                msgsize: MQ_MSGSIZE_DEFAULT,
+                ..Default::default()
+            });
+            if attr.maxmsg == 0
+                || attr.maxmsg > MQ_MAXMSG_MAX
+                || attr.msgsize == 0
+                || attr.msgsize > MQ_MSGSIZE_MAX
+            {
+                return Err(SysError::Invalid);
+            }
+            let queue = Arc::new(MessageQueue::new(attr.maxmsg, attr.msgsize));
+            mqueues.insert(String::from(name), queue.clone());
+            queue
+        }
+        None => return Err(SysError::NoEntry),
+    };
+    let (readable, writable) = flags.read_write();
+    Ok(Arc::new(MqFd {
+        readable,
+        writable,
+        nonblock: unsafe { UPIntrFreeCell::new(flags.contains(OpenFlags::NONBLOCK)) },
+        queue,
+    }))
+}
+
+/// Remove the name, opened queues are still usable.
+pub fn mq_unlink(name: &str) -> bool {
+    MQUEUES.exclusive_access().remove(name).is_some()
+}
+
+impl File for MqFd {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    /// Receive a message, the part beyond the buffer is dropped.
+    fn read(&self, buf: UserBuffer) -> usize {
+        match self.queue.receive(self.nonblock()) {
+            Some((msg, _)) => {
+                let len = msg.len().min(buf.len());
+                for (byte_ref, &byte) in buf.into_iter().zip(msg.iter()) {
+                    unsafe {
+                        *byte_ref = byte;
+                    }
+                }
+                len
+            }
+            None => 0,
+        }
+    }
+    /// Send a message with priority 0.
+    fn write(&self, buf: UserBuffer) -> usize {
+        let len = buf.len().min(self.queue.msgsize());
+        let msg: Vec<u8> = buf.into_iter().take(len).map(|p| unsafe { *p }).collect();
+        if self.queue.send(msg, 0, self.nonblock()) {
+            len
+        } else {
+            0
+        }
+    }
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        let attr = self.queue.attr();
+        let mut revents = PollEvents::empty();
+        if self.readable && attr.curmsgs > 0 {
+            revents |= PollEvents::POLLIN;
+        }
+        if self.writable && attr.curmsgs < attr.maxmsg {
+            revents |= PollEvents::POLLOUT;
+        }
+        events & revents
+    }
+    fn status(&self) -> OpenFlags {
+        if self.nonblock() {
+            OpenFlags::NONBLOCK
+        } else {
+            OpenFlags::empty()
+        }
+    }
+    /// Only `OpenFlags::NONBLOCK` is supported.
+    fn set_status(&self, flags: OpenFlags) -> bool {
+        if flags.intersects(OpenFlags::APPEND | OpenFlags::DIRECT) {
+            return false;
+        }
+        self.set_nonblock(flags.contains(OpenFlags::NONBLOCK));
+        true
+    }
+}
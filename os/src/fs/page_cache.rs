@@ -0,0 +1,238 @@
+//! The page cache, pages of files shared by the reads and the writes of
+//! them and by the processes executing or mapping them.
+//!
+//! `OSInode` reads and writes files through it. Writes go through to the
+//! file system at once, so the pages are never dirty and are dropped
+//! without writing them back. A page mapped by some process is dropped
+//! when the file is written rather than changed, the process keeps the old
+//! frame.
+//!
+//! exec maps the read-only segments of an ELF to the cached frames of its
+//! file instead of copying them. The pages mapped by some process are kept
+//! when making room, so the next process running the same program shares
+//! them too instead of reading another copy.
+//!
+//! sendfile copies files from the cached frames as well, without a buffer
+//! in the user space.
+
+use super::vfs::{file_id, FileId, Inode};
+use crate::config::PAGE_SIZE;
+use crate::mm::{frame_alloc, frame_refcount, FrameTracker};
+use crate::sync::UPIntrFreeCell;
+use alloc::collections::BTreeMap;
+use lazy_static::*;
+
+/// At most this many pages are cached, the least recently used page not
+/// mapped by any process is dropped to make room.
+const MAX_PAGES: usize = 1024;
+
+struct CachedPage {
+    frame: FrameTracker,
+    /// the clock when it was last used
+    last_used: usize,
+}
+
+impl CachedPage {
+    /// Whether some process maps it, the cache holds one reference.
+    fn in_use(&self) -> bool {
+        frame_refcount(self.frame.ppn) > 1
+    }
+}
+
+/// The counters of the page cache since boot.
+#[derive(Clone, Copy)]
+pub struct PageCacheStats {
+    /// the pages cached now
+    pub pages: usize,
+    pub hits: usize,
+    /// the pages read from the file system
+    pub misses: usize,
+    /// the pages dropped to make room
+    pub evictions: usize,
+}
+
+struct PageCache {
+    /// the pages by the index in each file
+    files: BTreeMap<FileId, BTreeMap<usize, CachedPage>>,
+    pages: usize,
+    clock: usize,
+    /// bumped on every write and invalidation, so a page read meanwhile is
+    /// not cached
+    generation: usize,
+    hits: usize,
+    misses: usize,
+    evictions: usize,
+}
+
+impl PageCache {
+    fn remove(&mut self, id: FileId) {
+        if let Some(pages) = self.files.remove(&id) {
+            self.pages -= pages.len();
+        }
+    }
+
+    fn remove_page(&mut self, id: FileId, index: usize) {
+        let pages = self.files.get_mut(&id).unwrap();
+        pages.remove(&index);
+        if pages.is_empty() {
+            self.files.remove(&id);
+        }
+        self.pages -= 1;
+    }
+
+    /// Drop the least recently used page not in use, return false if all
+    /// of them are in use.
+    fn evict(&mut self) -> bool {
+        let oldest = self
+            .files
+            .iter()
+            .flat_map(|(id, pages)| pages.iter().map(move |(index, page)| (*id, *index, page)))
+            .filter(|(_, _, page)| !page.in_use())
+            .min_by_key(|(_, _, page)| page.last_used)
+            .map(|(id, index, _)| (id, index));
+        match oldest {
+            Some((id, index)) => {
+                self.remove_page(id, index);
+                self.evictions += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The frame of a cached page, marked as used.
+    fn get(&mut self, id: FileId, index: usize) -> Option<&FrameTracker> {
+        self.clock += 1;
+        let clock = self.clock;
+        let page = self.files.get_mut(&id)?.get_mut(&index)?;
+        page.last_used = clock;
+        Some(&page.frame)
+    }
+
+    fn insert(&mut self, id: FileId, index: usize, frame: FrameTracker) {
+        // the pages in use take memory anyway, so go over the limit rather
+        // than losing them
+        while self.pages >= MAX_PAGES && self.evict() {}
+        self.clock += 1;
+        let page = CachedPage {
+            frame,
+            last_used: self.clock,
+        };
+        if self
+            .files
+            .entry(id)
+            .or_default()
+            .insert(index, page)
+            .is_none()
+        {
+            self.pages += 1;
+        }
+    }
+}
+
+lazy_static! {
+    static ref PAGE_CACHE: UPIntrFreeCell<PageCache> = unsafe {
+        UPIntrFreeCell::new(PageCache {
+            files: BTreeMap::new(),
+            pages: 0,
+            clock: 0,
+            generation: 0,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        })
+    };
+}
+
+/// Get the frame of the page `index` of a file, which is read on a miss.
+/// Return None if the page is beyond the end or frames run out.
+pub fn file_page(inode: &dyn Inode, index: usize) -> Option<FrameTracker> {
+    let id = file_id(inode);
+    let generation = {
+        let mut cache = PAGE_CACHE.exclusive_access();
+        if let Some(frame) = cache.get(id, index).cloned() {
+            cache.hits += 1;
+            return Some(frame);
+        }
+        cache.generation
+    };
+    // read without the cache borrowed, it waits for the disk
+    if index * PAGE_SIZE >= inode.size() {
+        return None;
+    }
+    let frame = frame_alloc()?;
+    inode.read_at(index * PAGE_SIZE, frame.ppn.get_bytes_array());
+    let mut cache = PAGE_CACHE.exclusive_access();
+    cache.misses += 1;
+    if cache.generation == generation {
+        cache.insert(id, index, frame.clone());
+    }
+    Some(frame)
+}
+
+/// Read a file from `offset` through the cache like `Inode::read_at`. It
+/// reads the file system straight if it is not `Inode::page_cached` or
+/// frames run out.
+pub fn read(inode: &dyn Inode, offset: usize, buf: &mut [u8]) -> usize {
+    if !inode.page_cached() {
+        return inode.read_at(offset, buf);
+    }
+    let end = (offset + buf.len()).min(inode.size());
+    let mut pos = offset;
+    while pos < end {
+        let dst = &mut buf[pos - offset..end - offset];
+        let page = match file_page(inode, pos / PAGE_SIZE) {
+            Some(page) => page,
+            None => return pos - offset + inode.read_at(pos, dst),
+        };
+        let start = pos % PAGE_SIZE;
+        let len = (PAGE_SIZE - start).min(dst.len());
+        dst[..len].copy_from_slice(&page.ppn.get_bytes_array()[start..start + len]);
+        pos += len;
+    }
+    pos - offset
+}
+
+/// Write a file at `offset` like `Inode::write_at`, and the cached pages
+/// with it.
+pub fn write(inode: &dyn Inode, offset: usize, buf: &[u8]) -> usize {
+    let written = inode.write_at(offset, buf);
+    if !inode.page_cached() {
+        return written;
+    }
+    let id = file_id(inode);
+    let mut cache = PAGE_CACHE.exclusive_access();
+    cache.generation += 1;
+    let mut pos = offset;
+    while pos < offset + written {
+        let (index, start) = (pos / PAGE_SIZE, pos % PAGE_SIZE);
+        let len = (PAGE_SIZE - start).min(offset + written - pos);
+        let page = cache.files.get(&id).and_then(|pages| pages.get(&index));
+        match page {
+            Some(page) if page.in_use() => cache.remove_page(id, index),
+            Some(page) => page.frame.ppn.get_bytes_array()[start..start + len]
+                .copy_from_slice(&buf[pos - offset..pos - offset + len]),
+            None => {}
+        }
+        pos += len;
+    }
+    written
+}
+
+/// Drop the pages of a file which is changed.
+pub fn invalidate(inode: &dyn Inode) {
+    let id = file_id(inode);
+    let mut cache = PAGE_CACHE.exclusive_access();
+    cache.remove(id);
+    cache.generation += 1;
+}
+
+pub fn page_cache_stats() -> PageCacheStats {
+    let cache = PAGE_CACHE.exclusive_access();
+    PageCacheStats {
+        pages: cache.pages,
+        hits: cache.hits,
+        misses: cache.misses,
+        evictions: cache.evictions,
+    }
+}
@@ -0,0 +1,40 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Join `path` to the absolute directory `base` and normalize the result,
+/// ".." never goes above "/".
+pub fn join_path(base: &str, path: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+    let full = if path.starts_with('/') {
+        [path, ""]
+    } else {
+        [base, path]
+    };
+    for component in full.iter().flat_map(|part| part.split('/')) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            name => components.push(name),
+        }
+    }
+    let mut joined = String::new();
+    for component in components {
+        joined.push('/');
+        joined.push_str(component);
+    }
+    if joined.is_empty() {
+        joined.push('/');
+    }
+    joined
+}
+
+/// Split a normalized absolute path into the parent directory and the name.
+pub fn split_path(path: &str) -> (&str, &str) {
+    match path.rfind('/') {
+        Some(0) => ("/", &path[1..]),
+        Some(pos) => (&path[..pos], &path[pos + 1..]),
+        None => ("/", path),
+    }
+}
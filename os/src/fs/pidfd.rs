@@ -0,0 +1,97 @@
+use super::{File, OpenFlags, PollEvents};
+use crate::mm::UserBuffer;
+use crate::sync::UPIntrFreeCell;
+use crate::task::suspend_current_and_run_next;
+use alloc::sync::Arc;
+
+/// The exit code of a process once it exits, shared with its pidfds rather
+/// than the process itself, so the parent still frees it when waiting.
+pub struct ExitStatus {
+    code: UPIntrFreeCell<Option<i32>>,
+}
+
+impl ExitStatus {
+    pub fn new() -> Self {
+        Self {
+            code: unsafe { UPIntrFreeCell::new(None) },
+        }
+    }
+    pub fn set(&self, exit_code: i32) {
+        *self.code.exclusive_access() = Some(exit_code);
+    }
+    pub fn get(&self) -> Option<i32> {
+        *self.code.exclusive_access()
+    }
+}
+
+/// A descriptor of a process, readable once it exits. Reading it gives the
+/// exit code as an i32, the process is still to be waited for by its parent.
+pub struct PidFd {
+    status: Arc<ExitStatus>,
+    nonblock: UPIntrFreeCell<bool>,
+}
+
+impl PidFd {
+    pub fn new(status: Arc<ExitStatus>, flags: OpenFlags) -> Self {
+        Self {
+            status,
+            nonblock: unsafe { UPIntrFreeCell::new(flags.contains(OpenFlags::NONBLOCK)) },
+        }
+    }
+    fn nonblock(&self) -> bool {
+        *self.nonblock.exclusive_access()
+    }
+}
+
+impl File for PidFd {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    /// Wait for the process to exit, unless `OpenFlags::NONBLOCK` is set,
+    /// then return 0 if it is still running.
+    fn read(&self, buf: UserBuffer) -> usize {
+        let exit_code = loop {
+            match self.status.get() {
+                Some(exit_code) => break exit_code,
+                None if self.nonblock() => return 0,
+                None => suspend_current_and_run_next(),
+            }
+        };
+        let bytes = exit_code.to_ne_bytes();
+        let len = bytes.len().min(buf.len());
+        for (byte_ref, &byte) in buf.into_iter().zip(bytes.iter()) {
+            unsafe {
+                *byte_ref = byte;
+            }
+        }
+        len
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        if self.status.get().is_some() {
+            events & PollEvents::POLLIN
+        } else {
+            PollEvents::empty()
+        }
+    }
+    fn status(&self) -> OpenFlags {
+        if self.nonblock() {
+            OpenFlags::NONBLOCK
+        } else {
+            OpenFlags::empty()
+        }
+    }
+    /// Only `OpenFlags::NONBLOCK` is supported.
+    fn set_status(&self, flags: OpenFlags) -> bool {
+        if flags.intersects(OpenFlags::APPEND | OpenFlags::DIRECT) {
+            return false;
+        }
+        *self.nonblock.exclusive_access() = flags.contains(OpenFlags::NONBLOCK);
+        true
+    }
+}
@@ -1,95 +1,145 @@
-use super::File;
+use super::poll::ReadyHooks;
+use super::{File, OpenFlags, PollEvents, ReadyHook};
 use crate::mm::UserBuffer;
-use crate::sync::UPIntrFreeCell;
+use crate::sync::{UPIntrFreeCell, WaitQueue};
+use crate::task::WakeReason;
+use alloc::collections::VecDeque;
 use alloc::sync::{Arc, Weak};
 
-use crate::task::suspend_current_and_run_next;
+/// The capacity of a new pipe in bytes.
+const RING_BUFFER_SIZE: usize = 32;
+/// The most `F_SETPIPE_SZ` sets the capacity to, pipe-max-size of Linux.
+pub const PIPE_MAX_SIZE: usize = 0x10_0000;
 
+/// An end of a pipe, whose status flags are its own.
 pub struct Pipe {
     readable: bool,
     writable: bool,
-    buffer: Arc<UPIntrFreeCell<PipeRingBuffer>>,
+    nonblock: UPIntrFreeCell<bool>,
+    buffer: Arc<PipeShared>,
+}
+
+/// What both ends of a pipe share.
+pub struct PipeShared {
+    ring_buffer: UPIntrFreeCell<PipeRingBuffer>,
+    /// readers waiting for bytes or for the write end to be closed
+    readers: WaitQueue,
+    /// writers waiting for room or for the read end to be closed
+    writers: WaitQueue,
+    hooks: ReadyHooks,
+}
+
+impl PipeShared {
+    fn new() -> Self {
+        unsafe {
+            Self {
+                ring_buffer: UPIntrFreeCell::new(PipeRingBuffer::new()),
+                readers: WaitQueue::new(),
+                writers: WaitQueue::new(),
+                hooks: ReadyHooks::new(),
+            }
+        }
+    }
+    /// Wake up the readers for `POLLIN` or `POLLHUP` and the writers for
+    /// `POLLOUT` or `POLLERR`, then call the ready hooks with `events`.
+    fn notify(&self, events: PollEvents) {
+        if events.intersects(PollEvents::POLLIN | PollEvents::POLLHUP) {
+            self.readers.wake_up_all();
+        }
+        if events.intersects(PollEvents::POLLOUT | PollEvents::POLLERR) {
+            self.writers.wake_up_all();
+        }
+        self.hooks.notify(events);
+    }
 }
 
 impl Pipe {
-    pub fn read_end_with_buffer(buffer: Arc<UPIntrFreeCell<PipeRingBuffer>>) -> Self {
+    pub fn read_end_with_buffer(buffer: Arc<PipeShared>) -> Self {
         Self {
             readable: true,
             writable: false,
+            nonblock: unsafe { UPIntrFreeCell::new(false) },
             buffer,
         }
     }
-    pub fn write_end_with_buffer(buffer: Arc<UPIntrFreeCell<PipeRingBuffer>>) -> Self {
+    pub fn write_end_with_buffer(buffer: Arc<PipeShared>) -> Self {
         Self {
             readable: false,
             writable: true,
+            nonblock: unsafe { UPIntrFreeCell::new(false) },
             buffer,
         }
     }
+    fn nonblock(&self) -> bool {
+        *self.nonblock.exclusive_access()
+    }
+    /// The bytes the pipe holds at most.
+    pub fn capacity(&self) -> usize {
+        self.buffer.ring_buffer.exclusive_access().capacity
+    }
+    /// Change the capacity, return false if it is 0, more than
+    /// `PIPE_MAX_SIZE` or less than the bytes in the pipe now.
+    pub fn set_capacity(&self, capacity: usize) -> bool {
+        let mut ring_buffer = self.buffer.ring_buffer.exclusive_access();
+        if capacity == 0 || capacity > PIPE_MAX_SIZE || capacity < ring_buffer.available_read() {
+            return false;
+        }
+        ring_buffer.capacity = capacity;
+        drop(ring_buffer);
+        self.buffer.notify(PollEvents::POLLOUT);
+        true
+    }
 }
 
-const RING_BUFFER_SIZE: usize = 32;
-
-#[derive(Copy, Clone, PartialEq)]
-enum RingBufferStatus {
-    Full,
-    Empty,
-    Normal,
+/// The readers see the end once the write end is closed, and the writers
+/// the error once the read end is.
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        if self.writable {
+            self.buffer.notify(PollEvents::POLLHUP);
+        }
+        if self.readable {
+            self.buffer.notify(PollEvents::POLLERR);
+        }
+    }
 }
 
 pub struct PipeRingBuffer {
-    arr: [u8; RING_BUFFER_SIZE],
-    head: usize,
-    tail: usize,
-    status: RingBufferStatus,
+    arr: VecDeque<u8>,
+    capacity: usize,
+    read_end: Option<Weak<Pipe>>,
     write_end: Option<Weak<Pipe>>,
 }
 
 impl PipeRingBuffer {
     pub fn new() -> Self {
         Self {
-            arr: [0; RING_BUFFER_SIZE],
-            head: 0,
-            tail: 0,
-            status: RingBufferStatus::Empty,
+            arr: VecDeque::new(),
+            capacity: RING_BUFFER_SIZE,
+            read_end: None,
             write_end: None,
         }
     }
+    pub fn set_read_end(&mut self, read_end: &Arc<Pipe>) {
+        self.read_end = Some(Arc::downgrade(read_end));
+    }
     pub fn set_write_end(&mut self, write_end: &Arc<Pipe>) {
         self.write_end = Some(Arc::downgrade(write_end));
     }
     pub fn write_byte(&mut self, byte: u8) {
-        self.status = RingBufferStatus::Normal;
-        self.arr[self.tail] = byte;
-        self.tail = (self.tail + 1) % RING_BUFFER_SIZE;
-        if self.tail == self.head {
-            self.status = RingBufferStatus::Full;
-        }
+        self.arr.push_back(byte);
     }
     pub fn read_byte(&mut self) -> u8 {
-        self.status = RingBufferStatus::Normal;
-        let c = self.arr[self.head];
-        self.head = (self.head + 1) % RING_BUFFER_SIZE;
-        if self.head == self.tail {
-            self.status = RingBufferStatus::Empty;
-        }
-        c
+        self.arr.pop_front().unwrap()
     }
     pub fn available_read(&self) -> usize {
-        if self.status == RingBufferStatus::Empty {
-            0
-        } else if self.tail > self.head {
-            self.tail - self.head
-        } else {
-            self.tail + RING_BUFFER_SIZE - self.head
-        }
+        self.arr.len()
     }
     pub fn available_write(&self) -> usize {
-        if self.status == RingBufferStatus::Full {
-            0
-        } else {
-            RING_BUFFER_SIZE - self.available_read()
-        }
+        self.capacity.saturating_sub(self.arr.len())
+    }
+    pub fn all_read_ends_closed(&self) -> bool {
+        self.read_end.as_ref().unwrap().upgrade().is_none()
     }
     pub fn all_write_ends_closed(&self) -> bool {
         self.write_end.as_ref().unwrap().upgrade().is_none()
@@ -98,10 +148,13 @@ impl PipeRingBuffer {
 
 /// Return (read_end, write_end)
 pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
-    let buffer = Arc::new(unsafe { UPIntrFreeCell::new(PipeRingBuffer::new()) });
+    let buffer = Arc::new(PipeShared::new());
     let read_end = Arc::new(Pipe::read_end_with_buffer(buffer.clone()));
     let write_end = Arc::new(Pipe::write_end_with_buffer(buffer.clone()));
-    buffer.exclusive_access().set_write_end(&write_end);
+    let mut ring_buffer = buffer.ring_buffer.exclusive_access();
+    ring_buffer.set_read_end(&read_end);
+    ring_buffer.set_write_end(&write_end);
+    drop(ring_buffer);
     (read_end, write_end)
 }
 
@@ -112,62 +165,127 @@ impl File for Pipe {
     fn writable(&self) -> bool {
         self.writable
     }
+    /// Wait until the buffer is filled or the write end is closed, or only
+    /// read what is in the pipe with `OpenFlags::NONBLOCK`.
     fn read(&self, buf: UserBuffer) -> usize {
         assert!(self.readable());
         let want_to_read = buf.len();
         let mut buf_iter = buf.into_iter();
         let mut already_read = 0usize;
-        loop {
-            let mut ring_buffer = self.buffer.exclusive_access();
+        while already_read < want_to_read {
+            let mut ring_buffer = self.buffer.ring_buffer.exclusive_access();
             let loop_read = ring_buffer.available_read();
             if loop_read == 0 {
-                if ring_buffer.all_write_ends_closed() {
-                    return already_read;
+                if ring_buffer.all_write_ends_closed() || self.nonblock() {
+                    break;
+                }
+                if self.buffer.readers.sleep_on(ring_buffer) != WakeReason::Woken {
+                    break;
                 }
-                drop(ring_buffer);
-                suspend_current_and_run_next();
                 continue;
             }
-            for _ in 0..loop_read {
-                if let Some(byte_ref) = buf_iter.next() {
-                    unsafe {
-                        *byte_ref = ring_buffer.read_byte();
-                    }
-                    already_read += 1;
-                    if already_read == want_to_read {
-                        return want_to_read;
-                    }
-                } else {
-                    return already_read;
+            for byte_ref in buf_iter.by_ref().take(loop_read) {
+                unsafe {
+                    *byte_ref = ring_buffer.read_byte();
                 }
+                already_read += 1;
             }
+            drop(ring_buffer);
+            self.buffer.notify(PollEvents::POLLOUT);
         }
+        already_read
     }
+    /// Wait until all is written, or only write what fits with
+    /// `OpenFlags::NONBLOCK`. Stop once the read end is closed.
     fn write(&self, buf: UserBuffer) -> usize {
         assert!(self.writable());
         let want_to_write = buf.len();
         let mut buf_iter = buf.into_iter();
         let mut already_write = 0usize;
-        loop {
-            let mut ring_buffer = self.buffer.exclusive_access();
+        while already_write < want_to_write {
+            let mut ring_buffer = self.buffer.ring_buffer.exclusive_access();
+            if ring_buffer.all_read_ends_closed() {
+                break;
+            }
             let loop_write = ring_buffer.available_write();
             if loop_write == 0 {
-                drop(ring_buffer);
-                suspend_current_and_run_next();
+                if self.nonblock() {
+                    break;
+                }
+                if self.buffer.writers.sleep_on(ring_buffer) != WakeReason::Woken {
+                    break;
+                }
                 continue;
             }
-            // write at most loop_write bytes
-            for _ in 0..loop_write {
-                if let Some(byte_ref) = buf_iter.next() {
-                    ring_buffer.write_byte(unsafe { *byte_ref });
-                    already_write += 1;
-                    if already_write == want_to_write {
-                        return want_to_write;
-                    }
-                } else {
-                    return already_write;
-                }
+            for byte_ref in buf_iter.by_ref().take(loop_write) {
+                ring_buffer.write_byte(unsafe { *byte_ref });
+                already_write += 1;
+            }
+            drop(ring_buffer);
+            self.buffer.notify(PollEvents::POLLIN);
+        }
+        already_write
+    }
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        let ring_buffer = self.buffer.ring_buffer.exclusive_access();
+        let mut revents = PollEvents::empty();
+        if self.readable {
+            if ring_buffer.available_read() > 0 {
+                revents |= PollEvents::POLLIN;
+            }
+            if ring_buffer.all_write_ends_closed() {
+                revents |= PollEvents::POLLHUP;
+            }
+        }
+        if self.writable {
+            if ring_buffer.all_read_ends_closed() {
+                revents |= PollEvents::POLLERR;
+            } else if ring_buffer.available_write() > 0 {
+                revents |= PollEvents::POLLOUT;
             }
         }
+        revents & (events | PollEvents::POLLHUP | PollEvents::POLLERR)
+    }
+    /// The readers would wait for bytes while the write end is open, the
+    /// writers for room.
+    fn would_block(&self, write: bool) -> bool {
+        if !self.nonblock() {
+            return false;
+        }
+        let events = if write {
+            PollEvents::POLLOUT
+        } else {
+            PollEvents::POLLIN
+        };
+        self.poll(events).is_empty()
+    }
+    fn broken(&self) -> bool {
+        self.writable
+            && self
+                .buffer
+                .ring_buffer
+                .exclusive_access()
+                .all_read_ends_closed()
+    }
+    fn add_ready_hook(&self, hook: ReadyHook) -> Option<usize> {
+        Some(self.buffer.hooks.add(hook))
+    }
+    fn remove_ready_hook(&self, id: usize) {
+        self.buffer.hooks.remove(id);
+    }
+    fn status(&self) -> OpenFlags {
+        if self.nonblock() {
+            OpenFlags::NONBLOCK
+        } else {
+            OpenFlags::empty()
+        }
+    }
+    /// Only `OpenFlags::NONBLOCK` is supported.
+    fn set_status(&self, flags: OpenFlags) -> bool {
+        if flags.intersects(OpenFlags::APPEND | OpenFlags::DIRECT) {
+            return false;
+        }
+        *self.nonblock.exclusive_access() = flags.contains(OpenFlags::NONBLOCK);
+        true
     }
 }
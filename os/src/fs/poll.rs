@@ -0,0 +1,119 @@
+//! Waiting for the events of many files at once, for ppoll and pselect.
+//!
+//! A file which knows when its events may have become ready, like a pipe,
+//! the console or a socket, takes a ready hook, see `File::add_ready_hook`. A `Poller`
+//! adds a hook to each file it waits for, which wakes up the task sleeping
+//! in `Poller::wait`. The files without hooks are polled again and again,
+//! the task yields between the polls.
+
+use super::{File, PollEvents, ReadyHook};
+use crate::sync::{UPIntrFreeCell, WaitQueue};
+use crate::task::{current_wake_reason, suspend_current_and_run_next, WakeReason};
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// The ready hooks of a file, called with its lock released.
+pub struct ReadyHooks {
+    /// the next id and the hooks by their ids
+    hooks: UPIntrFreeCell<(usize, Vec<(usize, ReadyHook)>)>,
+}
+
+impl ReadyHooks {
+    pub fn new() -> Self {
+        Self {
+            hooks: unsafe { UPIntrFreeCell::new((0, Vec::new())) },
+        }
+    }
+    pub fn add(&self, hook: ReadyHook) -> usize {
+        let mut hooks = self.hooks.exclusive_access();
+        let id = hooks.0;
+        hooks.0 += 1;
+        hooks.1.push((id, hook));
+        id
+    }
+    pub fn remove(&self, id: usize) {
+        self.hooks
+            .exclusive_access()
+            .1
+            .retain(|(hook_id, _)| *hook_id != id);
+    }
+    /// Call all the hooks with `events`.
+    pub fn notify(&self, events: PollEvents) {
+        for (_, hook) in self.hooks.exclusive_access().1.iter() {
+            hook(events);
+        }
+    }
+}
+
+struct PollerShared {
+    /// set by the hooks since the last wait, so an event between a poll and
+    /// the wait is not lost
+    notified: UPIntrFreeCell<bool>,
+    waiter: WaitQueue,
+}
+
+/// A task waiting for the events of some files.
+pub struct Poller {
+    shared: Arc<PollerShared>,
+    /// the files with the ids of the hooks added to them
+    hooked: Vec<(Arc<dyn File + Send + Sync>, usize)>,
+    /// some files take no hooks
+    unhooked: bool,
+}
+
+impl Poller {
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(PollerShared {
+                notified: unsafe { UPIntrFreeCell::new(false) },
+                waiter: WaitQueue::new(),
+            }),
+            hooked: Vec::new(),
+            unhooked: false,
+        }
+    }
+
+    /// Wait for the events of `file` too. The caller polls the files again
+    /// after adding them, an event before a hook is added is missed.
+    pub fn add(&mut self, file: &Arc<dyn File + Send + Sync>) {
+        let shared = Arc::clone(&self.shared);
+        let hook: ReadyHook = Box::new(move |_| {
+            *shared.notified.exclusive_access() = true;
+            shared.waiter.wake_up_all();
+        });
+        match file.add_ready_hook(hook) {
+            Some(id) => self.hooked.push((Arc::clone(file), id)),
+            None => self.unhooked = true,
+        }
+    }
+
+    /// Wait until a hook is called since the last wait, the time reaches
+    /// `expire_ns` if any, or the current task is to stop waiting. Only
+    /// yield if some files take no hooks. Return false if it is to stop
+    /// waiting for a signal or exiting.
+    pub fn wait(&self, expire_ns: Option<usize>) -> bool {
+        let mut notified = self.shared.notified.exclusive_access();
+        if *notified {
+            *notified = false;
+            return true;
+        }
+        if self.unhooked {
+            drop(notified);
+            suspend_current_and_run_next();
+        } else {
+            let reason = self.shared.waiter.sleep_on_until(notified, expire_ns);
+            *self.shared.notified.exclusive_access() = false;
+            return reason == WakeReason::Woken;
+        }
+        current_wake_reason() == WakeReason::Woken
+    }
+}
+
+impl Drop for Poller {
+    fn drop(&mut self) {
+        for (file, id) in self.hooked.iter() {
+            file.remove_ready_hook(*id);
+        }
+    }
+}
@@ -0,0 +1,582 @@
+//! procfs, the files of processes and of the kernel as text generated when
+//! read, mounted on /proc. Each process has a directory named by its pid.
+//!
+//! Each file is owned by a user with permission bits like those on Linux.
+//! The files of a process are owned by its user, so that processes of
+//! other users cannot read its memory layout or change its state, the
+//! others are owned by root.
+
+use super::page_cache::page_cache_stats;
+use super::syncd::{
+    background_ratio, set_background_ratio, set_writeback_centisecs, writeback_centisecs,
+};
+use super::vfs::{FileSystemType, Inode, SuperBlock};
+use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE};
+use crate::latency::irq_latency_text;
+use crate::mm::{frame_stats, AreaInfo, MapPermission};
+use crate::sync::UPIntrFreeCell;
+use crate::task::{idle_us, kstack_stats, pid2process, pids, TaskStatus, NICE_MAX, NICE_MIN};
+use crate::timer::{get_realtime_ns, get_time_ms};
+use crate::trigger::{kernel_watchpoints_text, store_kernel_watchpoint};
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use easy_fs::{block_cache_count, block_cache_dirty_count, BlockDevice, MountFlags, BLOCK_SZ};
+
+pub const PROC_FS: FileSystemType = FileSystemType {
+    name: "proc",
+    requires_dev: false,
+    mount,
+};
+
+fn mount(
+    _device: Option<Arc<dyn BlockDevice>>,
+    dev: usize,
+    flags: MountFlags,
+) -> Option<Arc<dyn SuperBlock>> {
+    Some(Arc::new(ProcFs {
+        dev,
+        flags: unsafe { UPIntrFreeCell::new(flags) },
+    }))
+}
+
+pub struct ProcFs {
+    dev: usize,
+    flags: UPIntrFreeCell<MountFlags>,
+}
+
+impl SuperBlock for ProcFs {
+    fn dev(&self) -> usize {
+        self.dev
+    }
+    fn root(self: Arc<Self>) -> Arc<dyn Inode> {
+        Arc::new(ProcInode::new(self, Node::Root))
+    }
+    fn flags(&self) -> MountFlags {
+        *self.flags.exclusive_access()
+    }
+    fn remount(&self, flags: MountFlags) {
+        *self.flags.exclusive_access() = flags;
+    }
+    /// Nothing is stored.
+    fn sync(&self) {}
+}
+
+/// A file of the kernel, by its path in /proc.
+struct KernelFile {
+    path: &'static str,
+    text: fn() -> String,
+    /// set the tunable from the text written, false if it is not valid
+    store: Option<fn(&str) -> bool>,
+}
+
+/// A file in the directory of each process, by its pid.
+struct ProcessFile {
+    name: &'static str,
+    mode: u32,
+    /// None once the process is gone
+    text: fn(usize) -> Option<String>,
+    store: Option<fn(usize, &str) -> bool>,
+}
+
+const KERNEL_FILES: &[KernelFile] = &[
+    KernelFile {
+        path: "irq_latency",
+        text: irq_latency_text,
+        store: None,
+    },
+    KernelFile {
+        path: "kstacks",
+        text: kstacks_text,
+        store: None,
+    },
+    KernelFile {
+        path: "meminfo",
+        text: meminfo_text,
+        store: None,
+    },
+    KernelFile {
+        path: "uptime",
+        text: uptime_text,
+        store: None,
+    },
+    KernelFile {
+        path: "vmstat",
+        text: vmstat_text,
+        store: None,
+    },
+    KernelFile {
+        path: "sys/debug/watchpoints",
+        text: kernel_watchpoints_text,
+        store: Some(store_kernel_watchpoint),
+    },
+    KernelFile {
+        path: "sys/vm/dirty_background_ratio",
+        text: || {
+            let mut text = String::new();
+            writeln!(text, "{}", background_ratio()).unwrap();
+            text
+        },
+        store: Some(|text| text.parse().map_or(false, set_background_ratio)),
+    },
+    KernelFile {
+        path: "sys/vm/dirty_writeback_centisecs",
+        text: || {
+            let mut text = String::new();
+            writeln!(text, "{}", writeback_centisecs()).unwrap();
+            text
+        },
+        store: Some(|text| text.parse().map(set_writeback_centisecs).is_ok()),
+    },
+];
+
+const PROCESS_FILES: &[ProcessFile] = &[
+    ProcessFile {
+        name: "maps",
+        mode: 0o400,
+        text: process_maps,
+        store: None,
+    },
+    ProcessFile {
+        name: "nice",
+        mode: 0o644,
+        text: process_nice,
+        store: Some(set_nice),
+    },
+    ProcessFile {
+        name: "stat",
+        mode: 0o444,
+        text: process_stat,
+        store: None,
+    },
+    ProcessFile {
+        name: "status",
+        mode: 0o444,
+        text: process_status,
+        store: None,
+    },
+];
+
+#[derive(Clone, Copy)]
+enum Node {
+    /// /proc itself
+    Root,
+    /// a directory of files of the kernel, by its path in /proc
+    Dir(&'static str),
+    /// by the index in `KERNEL_FILES`
+    Kernel(usize),
+    /// the directory of a process, by its pid
+    Process(usize),
+    /// by the pid and the index in `PROCESS_FILES`
+    ProcessFile(usize, usize),
+}
+
+/// The files and the directories of the kernel in the directory `dir` of
+/// /proc, "" for /proc itself.
+fn kernel_children(dir: &'static str) -> Vec<(&'static str, Node)> {
+    let mut children: Vec<(&'static str, Node)> = Vec::new();
+    for (index, file) in KERNEL_FILES.iter().enumerate() {
+        let rest = match dir {
+            "" => file.path,
+            dir => match file
+                .path
+                .strip_prefix(dir)
+                .and_then(|rest| rest.strip_prefix('/'))
+            {
+                Some(rest) => rest,
+                None => continue,
+            },
+        };
+        let (name, node) = match rest.find('/') {
+            Some(len) => {
+                let end = file.path.len() - rest.len() + len;
+                (&rest[..len], Node::Dir(&file.path[..end]))
+            }
+            None => (rest, Node::Kernel(index)),
+        };
+        if children.iter().all(|(other, _)| *other != name) {
+            children.push((name, node));
+        }
+    }
+    children
+}
+
+fn process_uid(pid: usize) -> Option<u32> {
+    Some(pid2process(pid)?.inner_exclusive_access().uid)
+}
+
+/// A file or a directory of procfs. Another one is made each time it is
+/// looked up, so it is only shared by the duplicates of a descriptor.
+pub struct ProcInode {
+    fs: Arc<ProcFs>,
+    node: Node,
+    /// generated when read from the start, and kept for the reads after so
+    /// that a file read in pieces is consistent
+    text: UPIntrFreeCell<Option<Vec<u8>>>,
+}
+
+impl ProcInode {
+    fn new(fs: Arc<ProcFs>, node: Node) -> Self {
+        Self {
+            fs,
+            node,
+            text: unsafe { UPIntrFreeCell::new(None) },
+        }
+    }
+
+    fn generate(&self) -> Option<String> {
+        match self.node {
+            Node::Kernel(index) => Some((KERNEL_FILES[index].text)()),
+            Node::ProcessFile(pid, index) => (PROCESS_FILES[index].text)(pid),
+            _ => None,
+        }
+    }
+
+    fn child(&self, node: Node) -> Arc<dyn Inode> {
+        Arc::new(ProcInode::new(self.fs.clone(), node))
+    }
+}
+
+impl Inode for ProcInode {
+    fn id(&self) -> usize {
+        match self.node {
+            Node::Root => 1,
+            Node::Dir(path) => {
+                0x100
+                    + KERNEL_FILES
+                        .iter()
+                        .position(|file| file.path.starts_with(path))
+                        .unwrap()
+            }
+            Node::Kernel(index) => 0x200 + index,
+            Node::Process(pid) => (pid + 1) << 16,
+            Node::ProcessFile(pid, index) => ((pid + 1) << 16) + index + 1,
+        }
+    }
+    fn super_block(&self) -> Arc<dyn SuperBlock> {
+        self.fs.clone()
+    }
+    fn is_dir(&self) -> bool {
+        matches!(self.node, Node::Root | Node::Dir(_) | Node::Process(_))
+    }
+    /// 0 as on Linux, the text is not known before it is read.
+    fn size(&self) -> usize {
+        0
+    }
+    fn times(&self) -> (u32, u32) {
+        let now = (get_realtime_ns() / 1_000_000_000) as u32;
+        (now, now)
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let mut text = self.text.exclusive_access();
+        if offset == 0 || text.is_none() {
+            *text = self.generate().map(String::into_bytes);
+        }
+        let text = match text.as_ref() {
+            Some(text) => text,
+            None => return 0,
+        };
+        let remaining = &text[offset.min(text.len())..];
+        let len = buf.len().min(remaining.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        len
+    }
+    /// Set a tunable from the text written at once, nothing is written if
+    /// it is not valid.
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> usize {
+        let text = match core::str::from_utf8(buf) {
+            Ok(text) => text.trim(),
+            Err(_) => return 0,
+        };
+        let stored = match self.node {
+            Node::Kernel(index) => KERNEL_FILES[index].store.map_or(false, |store| store(text)),
+            Node::ProcessFile(pid, index) => PROCESS_FILES[index]
+                .store
+                .map_or(false, |store| store(pid, text)),
+            _ => false,
+        };
+        if stored {
+            buf.len()
+        } else {
+            0
+        }
+    }
+    fn truncate(&self, _size: usize) -> bool {
+        false
+    }
+    fn page_cached(&self) -> bool {
+        false
+    }
+    fn find(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        match self.node {
+            Node::Root => match name.parse() {
+                Ok(pid) => pid2process(pid).map(|_| self.child(Node::Process(pid))),
+                Err(_) => kernel_children("")
+                    .into_iter()
+                    .find(|(other, _)| *other == name)
+                    .map(|(_, node)| self.child(node)),
+            },
+            Node::Dir(dir) => kernel_children(dir)
+                .into_iter()
+                .find(|(other, _)| *other == name)
+                .map(|(_, node)| self.child(node)),
+            Node::Process(pid) => PROCESS_FILES
+                .iter()
+                .position(|file| file.name == name)
+                .map(|index| self.child(Node::ProcessFile(pid, index))),
+            _ => None,
+        }
+    }
+    fn create(&self, _name: &str, _dir: bool) -> Option<Arc<dyn Inode>> {
+        None
+    }
+    fn rename(&self, _old_name: &str, _new_name: &str) -> bool {
+        false
+    }
+    fn unlink(&self, _name: &str, _dir: bool) -> bool {
+        false
+    }
+    /// The directories of the processes come first in the root.
+    fn ls(&self) -> Vec<String> {
+        match self.node {
+            Node::Root => pids()
+                .into_iter()
+                .map(|pid| pid.to_string())
+                .chain(kernel_children("").into_iter().map(|(name, _)| name.into()))
+                .collect(),
+            Node::Dir(dir) => kernel_children(dir)
+                .into_iter()
+                .map(|(name, _)| name.into())
+                .collect(),
+            Node::Process(_) => PROCESS_FILES.iter().map(|file| file.name.into()).collect(),
+            _ => Vec::new(),
+        }
+    }
+    fn permission(&self) -> Option<(u32, u32)> {
+        Some(match self.node {
+            Node::Root | Node::Dir(_) => (0, 0o555),
+            Node::Kernel(index) => match KERNEL_FILES[index].store {
+                Some(_) => (0, 0o644),
+                None => (0, 0o444),
+            },
+            Node::Process(pid) => (process_uid(pid).unwrap_or(0), 0o555),
+            Node::ProcessFile(pid, index) => {
+                (process_uid(pid).unwrap_or(0), PROCESS_FILES[index].mode)
+            }
+        })
+    }
+}
+
+/// A line for an area: the range, permissions and the resident size.
+fn write_area(text: &mut String, info: &AreaInfo) {
+    let flag = |perm: MapPermission, c: char| if info.perm.contains(perm) { c } else { '-' };
+    writeln!(
+        text,
+        "{:016x}-{:016x} {}{}{}{} {} kB",
+        info.start,
+        info.end,
+        flag(MapPermission::R, 'r'),
+        flag(MapPermission::W, 'w'),
+        flag(MapPermission::X, 'x'),
+        flag(MapPermission::U, 'u'),
+        info.resident / 1024,
+    )
+    .unwrap();
+}
+
+/// The state of a process as a letter of Linux, running if any of its
+/// threads is running or ready, sleeping otherwise. Processes exited are
+/// not found any more.
+fn process_state(pid: usize) -> Option<(char, &'static str)> {
+    let process = pid2process(pid)?;
+    let inner = process.inner_exclusive_access();
+    let running = inner
+        .tasks
+        .iter()
+        .flatten()
+        .any(|task| task.inner_exclusive_access().task_status != TaskStatus::Blocked);
+    Some(if running {
+        ('R', "running")
+    } else {
+        ('S', "sleeping")
+    })
+}
+
+/// The memory usage of a process like that of Linux, with the areas listed.
+fn process_status(pid: usize) -> Option<String> {
+    let (state, state_name) = process_state(pid)?;
+    let process = pid2process(pid)?;
+    let inner = process.inner_exclusive_access();
+    let ppid = inner
+        .parent
+        .as_ref()
+        .and_then(|parent| parent.upgrade())
+        .map_or(0, |parent| parent.getpid());
+    let usage = inner.memory_set.usage();
+    let mut status = String::new();
+    writeln!(status, "Name:\t{}", inner.comm).unwrap();
+    writeln!(status, "State:\t{} ({})", state, state_name).unwrap();
+    writeln!(status, "Pid:\t{}", pid).unwrap();
+    writeln!(status, "PPid:\t{}", ppid).unwrap();
+    writeln!(status, "Uid:\t{}", inner.uid).unwrap();
+    writeln!(status, "Threads:\t{}", inner.thread_count()).unwrap();
+    writeln!(status, "VmSize:\t{} kB", usage.virt / 1024).unwrap();
+    writeln!(status, "VmRSS:\t{} kB", usage.resident / 1024).unwrap();
+    writeln!(status, "VmShared:\t{} kB", usage.shared / 1024).unwrap();
+    writeln!(status, "Areas:\t{}", usage.areas).unwrap();
+    for info in inner.memory_set.area_infos() {
+        write_area(&mut status, &info);
+    }
+    Some(status)
+}
+
+/// The fields of a process in a line like the first 24 of Linux, 0 for
+/// those not kept. The time run is that of the threads not waited for yet,
+/// in ticks of 10 ms, all of it counted as in the user mode.
+fn process_stat(pid: usize) -> Option<String> {
+    let (state, _) = process_state(pid)?;
+    let process = pid2process(pid)?;
+    let inner = process.inner_exclusive_access();
+    let ppid = inner
+        .parent
+        .as_ref()
+        .and_then(|parent| parent.upgrade())
+        .map_or(0, |parent| parent.getpid());
+    let ran_us: usize = inner
+        .tasks
+        .iter()
+        .flatten()
+        .map(|task| task.ran_us.load(core::sync::atomic::Ordering::Relaxed))
+        .sum();
+    let usage = inner.memory_set.usage();
+    let faults = inner.memory_set.vm_stats();
+    let mut stat = String::new();
+    writeln!(
+        stat,
+        "{} ({}) {} {} {} {} 0 -1 0 {} 0 {} 0 {} 0 0 0 {} {} {} 0 0 {} {}",
+        pid,
+        inner.comm,
+        state,
+        ppid,
+        inner.pgid,
+        inner.sid,
+        faults.cow_faults + faults.lazy_faults,
+        faults.swap_ins,
+        ran_us / 10_000,
+        20 + inner.nice,
+        inner.nice,
+        inner.thread_count(),
+        usage.virt,
+        usage.resident / PAGE_SIZE,
+    )
+    .unwrap();
+    Some(stat)
+}
+
+/// The areas of a process, one in a line.
+fn process_maps(pid: usize) -> Option<String> {
+    let process = pid2process(pid)?;
+    let inner = process.inner_exclusive_access();
+    let mut maps = String::new();
+    for info in inner.memory_set.area_infos() {
+        write_area(&mut maps, &info);
+    }
+    Some(maps)
+}
+
+fn process_nice(pid: usize) -> Option<String> {
+    let mut text = String::new();
+    writeln!(text, "{}", pid2process(pid)?.inner_exclusive_access().nice).unwrap();
+    Some(text)
+}
+
+/// Set the nice value of all the threads of a process as setpriority does.
+fn set_nice(pid: usize, text: &str) -> bool {
+    let nice: isize = match text.parse() {
+        Ok(nice) => nice,
+        Err(_) => return false,
+    };
+    let process = match pid2process(pid) {
+        Some(process) => process,
+        None => return false,
+    };
+    let nice = nice.clamp(NICE_MIN, NICE_MAX);
+    let mut inner = process.inner_exclusive_access();
+    inner.nice = nice;
+    for task in inner.tasks.iter().flatten() {
+        task.set_nice(nice);
+    }
+    true
+}
+
+/// The kernel stacks, how many bytes of them are used at most tells if the
+/// size is enough.
+fn kstacks_text() -> String {
+    let (in_use, pooled, high_watermark) = kstack_stats();
+    let mut text = String::new();
+    writeln!(text, "Size:\t{} B", KERNEL_STACK_SIZE).unwrap();
+    writeln!(text, "InUse:\t{}", in_use).unwrap();
+    writeln!(text, "Pooled:\t{}", pooled).unwrap();
+    writeln!(text, "HighWatermark:\t{} B", high_watermark).unwrap();
+    text
+}
+
+/// The frames like the memory of Linux, the pages in the page cache, and
+/// the blocks in the block cache, all of them and those modified.
+fn meminfo_text() -> String {
+    let (free, total) = frame_stats();
+    let mut text = String::new();
+    writeln!(text, "MemTotal:\t{} kB", total * PAGE_SIZE / 1024).unwrap();
+    writeln!(text, "MemFree:\t{} kB", free * PAGE_SIZE / 1024).unwrap();
+    writeln!(
+        text,
+        "Buffers:\t{} kB",
+        block_cache_count() * BLOCK_SZ / 1024
+    )
+    .unwrap();
+    writeln!(
+        text,
+        "Cached:\t{} kB",
+        page_cache_stats().pages * PAGE_SIZE / 1024
+    )
+    .unwrap();
+    writeln!(
+        text,
+        "Dirty:\t{} kB",
+        block_cache_dirty_count() * BLOCK_SZ / 1024
+    )
+    .unwrap();
+    text
+}
+
+/// The seconds since boot and those the harts have been idle, summed.
+fn uptime_text() -> String {
+    let up_ms = get_time_ms();
+    let idle_ms = idle_us() / 1000;
+    let mut text = String::new();
+    writeln!(
+        text,
+        "{}.{:02} {}.{:02}",
+        up_ms / 1000,
+        up_ms % 1000 / 10,
+        idle_ms / 1000,
+        idle_ms % 1000 / 10
+    )
+    .unwrap();
+    text
+}
+
+/// The statistics of the memory: the blocks modified in the block cache and
+/// not written back yet, then the pages in the page cache, the reads of
+/// pages found in it and not, and the pages dropped to make room.
+fn vmstat_text() -> String {
+    let stats = page_cache_stats();
+    let mut text = String::new();
+    writeln!(text, "nr_dirty {}", block_cache_dirty_count()).unwrap();
+    writeln!(text, "nr_file_pages {}", stats.pages).unwrap();
+    writeln!(text, "pgcache_hit {}", stats.hits).unwrap();
+    writeln!(text, "pgcache_miss {}", stats.misses).unwrap();
+    writeln!(text, "pgcache_evict {}", stats.evictions).unwrap();
+    text
+}
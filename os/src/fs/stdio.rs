@@ -1,11 +1,78 @@
-use super::File;
-use crate::drivers::chardev::CharDevice;
-use crate::drivers::chardev::UART;
-use crate::mm::UserBuffer;
+use super::tty::CONSOLE_TTY;
+use super::{File, PollEvents, ReadyHook};
+use crate::config::PAGE_SIZE;
+use crate::console::print_user;
+use crate::mm::{UserBuffer, UserSliceRef};
+use crate::task::{current_process, current_user_token};
+use abi::{SysError, Termios, Winsize, TCGETS, TCSETS, TIOCGPGRP, TIOCGWINSZ, TIOCSPGRP};
+use alloc::vec;
 
 pub struct Stdin;
 pub struct Stdout;
 
+/// The console opened as `/dev/tty`, read like stdin and written like
+/// stdout.
+pub struct Tty {
+    readable: bool,
+    writable: bool,
+}
+
+impl Tty {
+    pub fn new(readable: bool, writable: bool) -> Self {
+        Self { readable, writable }
+    }
+}
+
+/// Stdin and stdout are the same terminal.
+fn console_ioctl(cmd: usize, arg: usize) -> isize {
+    let token = current_user_token();
+    let done = match cmd {
+        TCGETS => UserSliceRef::one(arg)
+            .set(token, 0, CONSOLE_TTY.termios())
+            .ok_or(SysError::Fault),
+        // switches between canonical and raw mode
+        TCSETS => UserSliceRef::<Termios>::one(arg)
+            .get(token, 0)
+            .map(|termios| CONSOLE_TTY.set_termios(termios))
+            .ok_or(SysError::Fault),
+        // the size of the serial console is unknown, assume the classic one
+        TIOCGWINSZ => UserSliceRef::one(arg)
+            .set(
+                token,
+                0,
+                Winsize {
+                    row: 24,
+                    col: 80,
+                    xpixel: 0,
+                    ypixel: 0,
+                },
+            )
+            .ok_or(SysError::Fault),
+        TIOCGPGRP => {
+            let pgid = CONSOLE_TTY.foreground().unwrap_or(0);
+            UserSliceRef::one(arg)
+                .set(token, 0, pgid as i32)
+                .ok_or(SysError::Fault)
+        }
+        // only to a group of the session of the caller, which controls the
+        // console from then on if no session does
+        TIOCSPGRP => {
+            let pgid = match UserSliceRef::<i32>::one(arg).get(token, 0) {
+                Some(pgid) if pgid > 0 => pgid as usize,
+                Some(_) => return SysError::Invalid.code(),
+                None => return SysError::Fault.code(),
+            };
+            let sid = current_process().inner_exclusive_access().sid;
+            CONSOLE_TTY
+                .set_foreground(sid, pgid)
+                .then_some(())
+                .ok_or(SysError::NotPermitted)
+        }
+        _ => Err(SysError::NotTty),
+    };
+    SysError::encode(done.map(|()| 0))
+}
+
 impl File for Stdin {
     fn readable(&self) -> bool {
         true
@@ -13,18 +80,41 @@ impl File for Stdin {
     fn writable(&self) -> bool {
         false
     }
-    fn read(&self, mut user_buf: UserBuffer) -> usize {
-        assert_eq!(user_buf.len(), 1);
-        //println!("before UART.read() in Stdin::read()");
-        let ch = UART.read();
-        unsafe {
-            user_buf.buffers[0].as_mut_ptr().write_volatile(ch);
+    fn read(&self, user_buf: UserBuffer) -> usize {
+        // background groups wait to be brought to the foreground, then the
+        // UART hands bytes out in the order the readers wait
+        if !CONSOLE_TTY.wait_foreground() {
+            return 0;
+        }
+        // the rest of a longer line is left for the next read
+        let mut buf = vec![0u8; user_buf.len().min(PAGE_SIZE)];
+        let len = CONSOLE_TTY.read(&mut buf);
+        for (byte_ref, &byte) in user_buf.into_iter().zip(buf[..len].iter()) {
+            unsafe {
+                *byte_ref = byte;
+            }
         }
-        1
+        len
     }
     fn write(&self, _user_buf: UserBuffer) -> usize {
         panic!("Cannot write to stdin!");
     }
+    fn ioctl(&self, cmd: usize, arg: usize) -> isize {
+        console_ioctl(cmd, arg)
+    }
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        if !CONSOLE_TTY.is_foreground() || !CONSOLE_TTY.readable() {
+            PollEvents::empty()
+        } else {
+            events & PollEvents::POLLIN
+        }
+    }
+    fn add_ready_hook(&self, hook: ReadyHook) -> Option<usize> {
+        Some(CONSOLE_TTY.add_ready_hook(hook))
+    }
+    fn remove_ready_hook(&self, id: usize) {
+        CONSOLE_TTY.remove_ready_hook(id);
+    }
 }
 
 impl File for Stdout {
@@ -39,8 +129,46 @@ impl File for Stdout {
     }
     fn write(&self, user_buf: UserBuffer) -> usize {
         for buffer in user_buf.buffers.iter() {
-            print!("{}", core::str::from_utf8(*buffer).unwrap());
+            print_user(core::str::from_utf8(*buffer).unwrap());
         }
         user_buf.len()
     }
+    fn ioctl(&self, cmd: usize, arg: usize) -> isize {
+        console_ioctl(cmd, arg)
+    }
+}
+
+impl File for Tty {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, user_buf: UserBuffer) -> usize {
+        Stdin.read(user_buf)
+    }
+    fn write(&self, user_buf: UserBuffer) -> usize {
+        Stdout.write(user_buf)
+    }
+    fn ioctl(&self, cmd: usize, arg: usize) -> isize {
+        console_ioctl(cmd, arg)
+    }
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        let mut revents = PollEvents::empty();
+        if self.readable {
+            revents |= Stdin.poll(events);
+        }
+        if self.writable {
+            revents |= events & PollEvents::POLLOUT;
+        }
+        revents
+    }
+    /// Called as stdin gets ready, the console is always ready to write.
+    fn add_ready_hook(&self, hook: ReadyHook) -> Option<usize> {
+        Some(CONSOLE_TTY.add_ready_hook(hook))
+    }
+    fn remove_ready_hook(&self, id: usize) {
+        CONSOLE_TTY.remove_ready_hook(id);
+    }
 }
@@ -0,0 +1,84 @@
+//! The sync daemon, a kernel thread writing back the changes of the file
+//! systems mounted without `MountFlags::SYNC` every so often, so that they
+//! are not lost for want of an fsync or a clean shutdown.
+//!
+//! The interval is `/proc/sys/vm/dirty_writeback_centisecs` as on Linux, 0
+//! to turn it off. In between, once more than
+//! `/proc/sys/vm/dirty_background_ratio` percent of the block cache is
+//! modified, it writes back the blocks used least recently down to that, so
+//! that the blocks replaced are seldom written back by the task missing.
+
+use super::vfs::sync_all;
+use crate::config::BLOCK_CACHE_SIZE;
+use crate::sync::block_on_yielding;
+use crate::task::spawn_kernel_thread;
+use crate::timer::{get_time_ns, Sleep};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use easy_fs::{block_cache_dirty_count, block_cache_write_back_oldest};
+use riscv::register::sstatus;
+
+const NSEC_PER_CENTISEC: usize = 10_000_000;
+/// How long to sleep at most, so that a change of the interval is not
+/// waited for long, nor the cache filling with modified blocks.
+const MAX_NAP_NS: usize = 100_000_000;
+
+static WRITEBACK_CENTISECS: AtomicUsize = AtomicUsize::new(500);
+static BACKGROUND_RATIO: AtomicUsize = AtomicUsize::new(10);
+
+pub fn writeback_centisecs() -> usize {
+    WRITEBACK_CENTISECS.load(Ordering::Relaxed)
+}
+
+pub fn set_writeback_centisecs(centisecs: usize) {
+    WRITEBACK_CENTISECS.store(centisecs, Ordering::Relaxed);
+}
+
+pub fn background_ratio() -> usize {
+    BACKGROUND_RATIO.load(Ordering::Relaxed)
+}
+
+/// False if `ratio` is no percentage.
+pub fn set_background_ratio(ratio: usize) -> bool {
+    if ratio > 100 {
+        return false;
+    }
+    BACKGROUND_RATIO.store(ratio, Ordering::Relaxed);
+    true
+}
+
+/// The modified blocks left in the cache by the background writeback.
+fn background_blocks() -> usize {
+    BLOCK_CACHE_SIZE * background_ratio() / 100
+}
+
+fn syncd_main() -> ! {
+    // like a syscall, it waits for the disk with interrupts on
+    unsafe {
+        sstatus::set_sie();
+    }
+    let mut last_ns = get_time_ns();
+    loop {
+        let interval_ns = writeback_centisecs() * NSEC_PER_CENTISEC;
+        let now_ns = get_time_ns();
+        if interval_ns == 0 {
+            last_ns = now_ns;
+        } else if now_ns - last_ns >= interval_ns {
+            sync_all();
+            last_ns = get_time_ns();
+            continue;
+        }
+        if block_cache_dirty_count() > background_blocks() {
+            block_cache_write_back_oldest(background_blocks());
+        }
+        let deadline_ns = match interval_ns {
+            0 => now_ns + MAX_NAP_NS,
+            _ => (last_ns + interval_ns).min(now_ns + MAX_NAP_NS),
+        };
+        block_on_yielding(Sleep::until(deadline_ns));
+    }
+}
+
+/// Start the sync daemon, once there are tasks to schedule.
+pub fn spawn_syncd() {
+    spawn_kernel_thread(syncd_main);
+}
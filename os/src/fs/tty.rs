@@ -0,0 +1,359 @@
+//! The line discipline of the console, between stdin and the UART.
+//!
+//! In canonical mode the bytes received are edited as a line, with the
+//! erase and kill characters of `Termios::cc`, and handed to the readers
+//! once the line ends, at most a line for each read. In raw mode, the
+//! default one, each read takes a byte as soon as it is received. Both modes
+//! echo the bytes with `LocalFlags::ECHO`.
+//!
+//! The mode is switched with TCSETS like on Linux, `LocalFlags::ICANON`. A
+//! read in raw mode with `VMIN` of 0 gives up after `VTIME` tenths of a
+//! second without a byte.
+//!
+//! With `LocalFlags::ISIG`, on by default, the interrupt and suspend
+//! characters are taken by the UART interrupt handler as soon as they are
+//! received, and send SIGINT and SIGTSTP to the foreground process group
+//! instead of going to the readers. SIGTSTP does nothing unless caught, as
+//! processes are never stopped.
+//!
+//! The console is the controlling terminal of the first session which sets
+//! its foreground process group, until the leader of the session exits. Only
+//! the foreground group reads from it, the other processes wait for it.
+
+use super::poll::ReadyHooks;
+use super::{PollEvents, ReadyHook};
+use crate::console::echo;
+use crate::drivers::chardev::CharDevice;
+use crate::drivers::chardev::UART;
+use crate::sync::{UPIntrFreeCell, WaitQueue};
+use crate::task::{current_process, group_in_session, signal_group, SignalFlags, WakeReason};
+use crate::timer::get_time_ns;
+use abi::{InputFlags, LocalFlags, Termios, VEOF, VERASE, VINTR, VKILL, VMIN, VSUSP, VTIME};
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// Raw mode without echo but with Ctrl-C and Ctrl-Z, with the special
+/// characters of Linux for a switch to canonical mode.
+fn default_termios() -> Termios {
+    let mut cc = [0; 19];
+    cc[VINTR] = 0x03;
+    cc[VERASE] = 0x7f;
+    cc[VKILL] = 0x15;
+    cc[VEOF] = 0x04;
+    cc[VMIN] = 1;
+    cc[VSUSP] = 0x1a;
+    Termios {
+        lflag: LocalFlags::ISIG.bits(),
+        cc,
+        ..Default::default()
+    }
+}
+
+struct LineState {
+    termios: Termios,
+    /// the line being edited in canonical mode
+    editing: Vec<u8>,
+    /// the lines ended but not read yet, an empty one is an end of file
+    lines: VecDeque<Vec<u8>>,
+}
+
+/// The session controlling a terminal and its foreground process group.
+#[derive(Clone, Copy)]
+struct Controlling {
+    session: usize,
+    foreground: usize,
+}
+
+pub struct Tty {
+    state: UPIntrFreeCell<LineState>,
+    /// None until a session takes the terminal, when every process reads as
+    /// a foreground one
+    controlling: UPIntrFreeCell<Option<Controlling>>,
+    /// readers of background groups, woken up when the foreground changes
+    background_readers: WaitQueue,
+    /// called when a byte is received or the foreground changes
+    hooks: ReadyHooks,
+}
+
+lazy_static! {
+    pub static ref CONSOLE_TTY: Tty = Tty::new();
+}
+
+impl Tty {
+    fn new() -> Self {
+        Self {
+            state: unsafe {
+                UPIntrFreeCell::new(LineState {
+                    termios: default_termios(),
+                    editing: Vec::new(),
+                    lines: VecDeque::new(),
+                })
+            },
+            controlling: unsafe { UPIntrFreeCell::new(None) },
+            background_readers: WaitQueue::new(),
+            hooks: ReadyHooks::new(),
+        }
+    }
+
+    /// The foreground process group, None if no session controls the terminal.
+    pub fn foreground(&self) -> Option<usize> {
+        self.controlling
+            .exclusive_access()
+            .map(|controlling| controlling.foreground)
+    }
+
+    /// Make `pgid` the foreground process group for a process of the session
+    /// `sid`, which takes the terminal if no session controls it. Return false
+    /// if another session controls it or `pgid` is not a group of `sid`.
+    pub fn set_foreground(&self, sid: usize, pgid: usize) -> bool {
+        if !group_in_session(pgid, sid) {
+            return false;
+        }
+        let mut controlling = self.controlling.exclusive_access();
+        match *controlling {
+            Some(Controlling { session, .. }) if session != sid => return false,
+            _ => {
+                *controlling = Some(Controlling {
+                    session: sid,
+                    foreground: pgid,
+                })
+            }
+        }
+        drop(controlling);
+        self.background_readers.wake_up_all();
+        self.hooks.notify(PollEvents::POLLIN);
+        true
+    }
+
+    /// Free the terminal when the leader of the session `sid` exits.
+    pub fn release(&self, sid: usize) {
+        let mut controlling = self.controlling.exclusive_access();
+        if matches!(*controlling, Some(Controlling { session, .. }) if session == sid) {
+            *controlling = None;
+            drop(controlling);
+            self.background_readers.wake_up_all();
+            self.hooks.notify(PollEvents::POLLIN);
+        }
+    }
+
+    /// Whether the current process is of the foreground process group.
+    pub fn is_foreground(&self) -> bool {
+        Self::in_group(self.foreground())
+    }
+
+    fn in_group(foreground: Option<usize>) -> bool {
+        match foreground {
+            Some(pgid) => current_process().inner_exclusive_access().pgid == pgid,
+            None => true,
+        }
+    }
+
+    /// Wait until the current process is of the foreground process group,
+    /// return false if it stops waiting for a signal or exiting.
+    pub fn wait_foreground(&self) -> bool {
+        loop {
+            let controlling = self.controlling.exclusive_access();
+            if Self::in_group(controlling.map(|controlling| controlling.foreground)) {
+                return true;
+            }
+            if self.background_readers.sleep_on(controlling) != WakeReason::Woken {
+                return false;
+            }
+        }
+    }
+
+    pub fn termios(&self) -> Termios {
+        self.state.exclusive_access().termios
+    }
+
+    /// The line being edited is ended if canonical mode is off.
+    pub fn set_termios(&self, termios: Termios) {
+        let mut state = self.state.exclusive_access();
+        state.termios = termios;
+        if !state.canonical() && !state.editing.is_empty() {
+            let line = core::mem::take(&mut state.editing);
+            state.lines.push_back(line);
+        }
+    }
+
+    /// Read at most a line in canonical mode, or a byte in raw mode, to
+    /// `buf`. Return the length, 0 at the end of file or if the reader stops
+    /// waiting for a signal or exiting.
+    pub fn read(&self, buf: &mut [u8]) -> usize {
+        if buf.is_empty() {
+            return 0;
+        }
+        loop {
+            if let Some(len) = self.take_line(buf) {
+                return len;
+            }
+            let expire_ns = self.state.exclusive_access().read_deadline();
+            let ch = match expire_ns {
+                Some(expire_ns) => UART.read_until(expire_ns),
+                None => UART.read(),
+            };
+            let ch = match ch {
+                Some(ch) => ch,
+                // interrupted or timed out while waiting
+                None => return 0,
+            };
+            let mut state = self.state.exclusive_access();
+            if !state.canonical() {
+                let ch = state.receive_raw(ch);
+                buf[0] = ch;
+                return 1;
+            }
+            state.receive(ch);
+        }
+    }
+
+    /// Check a byte received by the UART interrupt handler, return false if
+    /// it is taken as the interrupt or suspend character. The line being
+    /// edited is discarded with it.
+    pub fn handle_input(&self, ch: u8) -> bool {
+        let mut state = self.state.exclusive_access();
+        if !state.lflag().contains(LocalFlags::ISIG) || ch == 0 {
+            return true;
+        }
+        let (flag, echoed) = if ch == state.termios.cc[VINTR] {
+            (SignalFlags::SIGINT, b"^C\n")
+        } else if ch == state.termios.cc[VSUSP] {
+            (SignalFlags::SIGTSTP, b"^Z\n")
+        } else {
+            return true;
+        };
+        state.editing.clear();
+        if state.lflag().contains(LocalFlags::ECHO) {
+            echo(echoed);
+        }
+        drop(state);
+        self.signal_foreground(flag);
+        false
+    }
+
+    fn signal_foreground(&self, flag: SignalFlags) {
+        match self.foreground() {
+            Some(pgid) if signal_group(pgid, flag) => {}
+            _ => debug!("[kernel] no foreground process group for {:?}", flag),
+        }
+    }
+
+    /// Called by the UART interrupt handler once a byte is received for the
+    /// readers.
+    pub fn notify_received(&self) {
+        self.hooks.notify(PollEvents::POLLIN);
+    }
+
+    /// See `File::add_ready_hook`, for stdin.
+    pub fn add_ready_hook(&self, hook: ReadyHook) -> usize {
+        self.hooks.add(hook)
+    }
+
+    pub fn remove_ready_hook(&self, id: usize) {
+        self.hooks.remove(id);
+    }
+
+    /// Whether a read returns without waiting. The bytes received are edited
+    /// first, the line may end by them.
+    pub fn readable(&self) -> bool {
+        {
+            let state = self.state.exclusive_access();
+            if !state.canonical() {
+                return !state.lines.is_empty() || !UART.read_buffer_is_empty();
+            }
+        }
+        // nobody waits for them, so they are read at once
+        while !UART.read_buffer_is_empty() {
+            match UART.read() {
+                Some(ch) => self.state.exclusive_access().receive(ch),
+                None => break,
+            }
+        }
+        !self.state.exclusive_access().lines.is_empty()
+    }
+
+    /// Take the first line ended, or the part of it left by the last read.
+    fn take_line(&self, buf: &mut [u8]) -> Option<usize> {
+        let mut state = self.state.exclusive_access();
+        let mut line = state.lines.pop_front()?;
+        let len = line.len().min(buf.len());
+        buf[..len].copy_from_slice(&line[..len]);
+        if len < line.len() {
+            state.lines.push_front(line.split_off(len));
+        }
+        Some(len)
+    }
+}
+
+impl LineState {
+    /// When a read in raw mode gives up waiting for a byte, with `VMIN` of 0
+    /// and `VTIME` in tenths of a second. Other values of `VMIN` wait for a
+    /// byte like 1.
+    fn read_deadline(&self) -> Option<usize> {
+        if self.canonical() || self.termios.cc[VMIN] != 0 {
+            return None;
+        }
+        Some(get_time_ns() + self.termios.cc[VTIME] as usize * 100_000_000)
+    }
+
+    fn lflag(&self) -> LocalFlags {
+        LocalFlags::from_bits_truncate(self.termios.lflag)
+    }
+
+    fn canonical(&self) -> bool {
+        self.lflag().contains(LocalFlags::ICANON)
+    }
+
+    fn map_input(&self, ch: u8) -> u8 {
+        let iflag = InputFlags::from_bits_truncate(self.termios.iflag);
+        if ch == b'\r' && iflag.contains(InputFlags::ICRNL) {
+            b'\n'
+        } else {
+            ch
+        }
+    }
+
+    fn echo(&self, ch: u8) {
+        if self.lflag().contains(LocalFlags::ECHO) {
+            echo(&[ch]);
+        }
+    }
+
+    /// A byte received in raw mode, returned after mapped and echoed.
+    fn receive_raw(&self, ch: u8) -> u8 {
+        let ch = self.map_input(ch);
+        self.echo(ch);
+        ch
+    }
+
+    /// Edit the line with a byte received in canonical mode.
+    fn receive(&mut self, ch: u8) {
+        let ch = self.map_input(ch);
+        let cc = self.termios.cc;
+        let erase_echoed = self.lflag().contains(LocalFlags::ECHO | LocalFlags::ECHOE);
+        if ch == cc[VERASE] || (ch == 0x08 && cc[VERASE] != 0) {
+            if self.editing.pop().is_some() && erase_echoed {
+                echo(b"\x08 \x08");
+            }
+        } else if ch == cc[VKILL] && ch != 0 {
+            while self.editing.pop().is_some() {
+                if erase_echoed {
+                    echo(b"\x08 \x08");
+                }
+            }
+        } else if ch == cc[VEOF] && ch != 0 {
+            // the line so far without the character, an end of file if empty
+            let line = core::mem::take(&mut self.editing);
+            self.lines.push_back(line);
+        } else if ch == b'\n' {
+            self.echo(ch);
+            self.editing.push(ch);
+            let line = core::mem::take(&mut self.editing);
+            self.lines.push_back(line);
+        } else {
+            self.echo(ch);
+            self.editing.push(ch);
+        }
+    }
+}
@@ -0,0 +1,360 @@
+//! The virtual file system: file systems mounted on the directories of each
+//! other, and the paths resolved across them.
+//!
+//! A file system implements `SuperBlock` for itself and `Inode` for its
+//! files and directories, and is registered in `FILE_SYSTEMS` by name to be
+//! mounted from a block device, or from nothing for pseudo file systems
+//! like procfs and devfs, which are mounted on /proc and /dev at boot. A path is resolved from
+//! the root of the mount with the longest prefix of it, so what is mounted
+//! on a directory hides what is in it until unmounted.
+
+use super::dev::find_block_device;
+use super::devfs::DEV_FS;
+use super::easyfs::EASY_FS;
+use super::fat::FAT_FS;
+use super::path::{join_path, split_path};
+use super::proc::PROC_FS;
+use super::{File, OpenFlags};
+use crate::config::BLOCK_CACHE_SIZE;
+use crate::drivers::BLOCK_DEVICE;
+use crate::sync::UPIntrFreeCell;
+use abi::{SysError, SysResult};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use easy_fs::{set_block_cache_capacity, BlockDevice, MountFlags, Quota};
+use lazy_static::*;
+
+/// A file or a directory of a mounted file system. It holds the super block
+/// of its file system, which is busy while it is in use.
+pub trait Inode: Send + Sync {
+    /// A number of the inode unique in its file system.
+    fn id(&self) -> usize;
+    fn super_block(&self) -> Arc<dyn SuperBlock>;
+    fn is_dir(&self) -> bool;
+    fn size(&self) -> usize;
+    /// Return the access and modification times in seconds.
+    fn times(&self) -> (u32, u32);
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize;
+    /// Return 0 if nothing can be written.
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize;
+    /// Read whole blocks from `offset` straight from the device into the
+    /// buffers of whole blocks, see `easy_fs::Inode::read_direct`. None if
+    /// the file system always reads through its cache.
+    fn read_direct(&self, _offset: usize, _bufs: &mut [&mut [u8]]) -> Option<usize> {
+        None
+    }
+    /// Write whole blocks like `read_direct`.
+    fn write_direct(&self, _offset: usize, _bufs: &[&[u8]]) -> Option<usize> {
+        None
+    }
+    /// Set the size, filling with zeros when growing. Return false if it
+    /// cannot be changed.
+    fn truncate(&self, size: usize) -> bool;
+    /// Find a name in this directory.
+    fn find(&self, name: &str) -> Option<Arc<dyn Inode>>;
+    /// Create a file, or a directory if `dir`, in this directory. Return
+    /// None if the name exists or nothing can be created.
+    fn create(&self, name: &str, dir: bool) -> Option<Arc<dyn Inode>>;
+    /// Rename a file in this directory, return false if `old_name` does not
+    /// exist or `new_name` does.
+    fn rename(&self, old_name: &str, new_name: &str) -> bool;
+    /// Remove a file, or an empty directory if `dir`, from this directory.
+    /// Return false if it does not exist, is not of the kind asked, or is in
+    /// use.
+    fn unlink(&self, name: &str, dir: bool) -> bool;
+    /// The names in this directory.
+    fn ls(&self) -> Vec<String>;
+    /// The user owning it and its permission bits, checked by `permitted`
+    /// when it is opened. None if anyone may open it, for file systems
+    /// without permissions.
+    fn permission(&self) -> Option<(u32, u32)> {
+        None
+    }
+    /// Whether it is read and written through the page cache, false for
+    /// the files generated when read.
+    fn page_cached(&self) -> bool {
+        true
+    }
+    /// Open the device it stands for instead of it, None if it is no device.
+    fn open_device(&self, _flags: OpenFlags) -> Option<Arc<dyn File + Send + Sync>> {
+        None
+    }
+}
+
+/// A file by the number of its file system and the inode id.
+pub type FileId = (usize, usize);
+
+pub fn file_id(inode: &dyn Inode) -> FileId {
+    (inode.super_block().dev(), inode.id())
+}
+
+/// Whether the user `uid` may open a file of the user `owner` with the
+/// permission bits `mode`, for reading and writing as asked. There are no
+/// groups, the bits of the owner or of others are checked. Root may open
+/// any file, but for writing only one which someone may write.
+pub fn permitted(owner: u32, mode: u32, uid: u32, read: bool, write: bool) -> bool {
+    if uid == 0 {
+        return !write || mode & 0o222 != 0;
+    }
+    let bits = if uid == owner { mode >> 6 } else { mode };
+    (!read || bits & 0o4 != 0) && (!write || bits & 0o2 != 0)
+}
+
+/// A mounted file system.
+pub trait SuperBlock: Send + Sync {
+    /// The number of the file system, unique among all mounted since boot.
+    fn dev(&self) -> usize;
+    fn root(self: Arc<Self>) -> Arc<dyn Inode>;
+    fn flags(&self) -> MountFlags;
+    fn remount(&self, flags: MountFlags);
+    /// Make everything written persistent.
+    fn sync(&self);
+    /// Write back what is changed and release the device, when unmounted.
+    fn unmount(&self) {
+        self.sync();
+    }
+    /// The usage and the limits of `uid`, None if there are no quotas.
+    fn quota(&self, _uid: u16) -> Option<Quota> {
+        None
+    }
+    /// Return false if there are no quotas.
+    fn set_quota_limits(&self, _uid: u16, _block_limit: u32, _inode_limit: u32) -> bool {
+        false
+    }
+}
+
+/// A kind of file system, mounted by its name.
+pub struct FileSystemType {
+    pub name: &'static str,
+    /// Whether it is mounted from a block device, or the source is only a
+    /// name otherwise.
+    pub requires_dev: bool,
+    /// Mount the file system on the device if it requires one, as the
+    /// number `dev`. None if the device does not have it.
+    pub mount: fn(Option<Arc<dyn BlockDevice>>, usize, MountFlags) -> Option<Arc<dyn SuperBlock>>,
+}
+
+/// The file systems which can be mounted.
+const FILE_SYSTEMS: &[FileSystemType] = &[EASY_FS, FAT_FS, PROC_FS, DEV_FS];
+
+/// A path resolved to an inode, with the file system it is in.
+pub struct Dentry {
+    /// the normalized absolute path
+    pub path: String,
+    pub inode: Arc<dyn Inode>,
+    pub sb: Arc<dyn SuperBlock>,
+}
+
+struct Mount {
+    /// the normalized absolute path of the directory mounted on
+    path: String,
+    /// the path of the device, which is mounted once at a time
+    source: String,
+    sb: Arc<dyn SuperBlock>,
+}
+
+static NEXT_DEV: AtomicUsize = AtomicUsize::new(0);
+
+fn alloc_dev() -> usize {
+    NEXT_DEV.fetch_add(1, Ordering::Relaxed)
+}
+
+lazy_static! {
+    /// The mounts in the order mounted, the root file system first, then
+    /// procfs and devfs.
+    static ref MOUNTS: UPIntrFreeCell<Vec<Mount>> = {
+        set_block_cache_capacity(BLOCK_CACHE_SIZE);
+        let sb = (EASY_FS.mount)(Some(BLOCK_DEVICE.clone()), alloc_dev(), MountFlags::SYNC)
+            .expect("no root file system");
+        let root_dir = sb.clone().root();
+        for dir in ["proc", "dev"] {
+            if root_dir.find(dir).is_none() {
+                root_dir.create(dir, true);
+            }
+        }
+        let root = Mount {
+            path: String::from("/"),
+            source: String::from("/dev/vda"),
+            sb,
+        };
+        let proc = Mount {
+            path: String::from("/proc"),
+            source: String::from("proc"),
+            sb: (PROC_FS.mount)(None, alloc_dev(), MountFlags::empty()).unwrap(),
+        };
+        let dev = Mount {
+            path: String::from("/dev"),
+            source: String::from("devfs"),
+            sb: (DEV_FS.mount)(None, alloc_dev(), MountFlags::empty()).unwrap(),
+        };
+        unsafe { UPIntrFreeCell::new(Vec::from([root, proc, dev])) }
+    };
+}
+
+/// Whether `path` is `dir` or in it, both normalized.
+fn is_under(path: &str, dir: &str) -> bool {
+    dir == "/"
+        || path
+            .strip_prefix(dir)
+            .map_or(false, |rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// The file system mounted last with the longest prefix of the normalized
+/// path, and the rest of the path in it.
+fn find_mount(path: &str) -> (Arc<dyn SuperBlock>, String) {
+    let mounts = MOUNTS.exclusive_access();
+    let mount = mounts
+        .iter()
+        .rev()
+        .filter(|mount| is_under(path, mount.path.as_str()))
+        .max_by_key(|mount| mount.path.len())
+        .unwrap();
+    let rest = match mount.path.as_str() {
+        "/" => path,
+        prefix => &path[prefix.len()..],
+    };
+    (mount.sb.clone(), String::from(rest))
+}
+
+/// Resolve a path from the root directory, which is resolved by the caller
+/// for processes.
+pub fn lookup(path: &str) -> Option<Dentry> {
+    let path = join_path("/", path);
+    // the disk is read without the mounts borrowed
+    let (sb, rest) = find_mount(path.as_str());
+    let mut inode = sb.clone().root();
+    for name in rest.split('/').filter(|name| !name.is_empty()) {
+        if !inode.is_dir() {
+            return None;
+        }
+        inode = inode.find(name)?;
+    }
+    Some(Dentry { path, inode, sb })
+}
+
+/// Resolve the parent directory of a path, return it with the name in it.
+pub fn lookup_parent(path: &str) -> Option<(Dentry, String)> {
+    let path = join_path("/", path);
+    let (parent, name) = split_path(path.as_str());
+    if name.is_empty() {
+        return None;
+    }
+    let parent = lookup(parent).filter(|dentry| dentry.inode.is_dir())?;
+    Some((parent, String::from(name)))
+}
+
+/// Whether a file system is mounted on the path.
+pub fn is_mount_point(path: &str) -> bool {
+    let path = join_path("/", path);
+    MOUNTS
+        .exclusive_access()
+        .iter()
+        .any(|mount| mount.path == path)
+}
+
+/// Mount the file system `fstype` on the block device `source` at the
+/// directory `target`, both resolved, or from nothing if it requires no
+/// device. Fail with `NoDevice` if the type or the device is unknown, `Busy`
+/// if the device is in use or the target is mounted on already, or
+/// `Invalid` if the device does not have the file system.
+pub fn mount(source: &str, target: &str, fstype: &str, flags: MountFlags) -> SysResult<()> {
+    let fs_type = FILE_SYSTEMS
+        .iter()
+        .find(|fs_type| fs_type.name == fstype)
+        .ok_or(SysError::NoDevice)?;
+    let target = match lookup(target) {
+        Some(dentry) if dentry.inode.is_dir() => dentry.path,
+        Some(_) => return Err(SysError::NotDir),
+        None => return Err(SysError::NoEntry),
+    };
+    let (source, device) = if fs_type.requires_dev {
+        let source = join_path("/", source);
+        match find_block_device(source.as_str()) {
+            // the disk of the root file system is in use as a whole
+            Some((index, device)) if index > 0 => (source, Some(device)),
+            Some(_) => return Err(SysError::Busy),
+            None => return Err(SysError::NoDevice),
+        }
+    } else {
+        (String::from(source), None)
+    };
+    let in_use = |mounts: &Vec<Mount>| {
+        mounts
+            .iter()
+            .any(|mount| (fs_type.requires_dev && mount.source == source) || mount.path == target)
+    };
+    if in_use(&MOUNTS.exclusive_access()) {
+        return Err(SysError::Busy);
+    }
+    let sb = (fs_type.mount)(device, alloc_dev(), flags).ok_or(SysError::Invalid)?;
+    let mut mounts = MOUNTS.exclusive_access();
+    // mounted meanwhile while the device was read
+    if in_use(&mounts) {
+        drop(mounts);
+        sb.unmount();
+        return Err(SysError::Busy);
+    }
+    mounts.push(Mount {
+        path: target,
+        source,
+        sb,
+    });
+    Ok(())
+}
+
+/// Unmount the file system mounted on `target`. Fail with `Invalid` if none
+/// is mounted there, or `Busy` if it is the root or busy with files in use
+/// or other file systems mounted in it.
+pub fn umount(target: &str) -> SysResult<()> {
+    let target = join_path("/", target);
+    let mut mounts = MOUNTS.exclusive_access();
+    let index = match mounts.iter().position(|mount| mount.path == target) {
+        Some(index) if index > 0 => index,
+        Some(_) => return Err(SysError::Busy),
+        None => return Err(SysError::Invalid),
+    };
+    let busy = Arc::strong_count(&mounts[index].sb) > 1
+        || mounts
+            .iter()
+            .any(|mount| mount.path != target && is_under(mount.path.as_str(), target.as_str()));
+    if busy {
+        return Err(SysError::Busy);
+    }
+    let mount = mounts.remove(index);
+    drop(mounts);
+    mount.sb.unmount();
+    Ok(())
+}
+
+/// Change how the file system mounted on `target` is mounted, fail with
+/// `Invalid` if none is mounted there.
+pub fn remount(target: &str, flags: MountFlags) -> SysResult<()> {
+    let target = join_path("/", target);
+    let sb = MOUNTS
+        .exclusive_access()
+        .iter()
+        .find(|mount| mount.path == target)
+        .map(|mount| mount.sb.clone())
+        .ok_or(SysError::Invalid)?;
+    sb.remount(flags);
+    Ok(())
+}
+
+/// Make everything written to each of the file systems persistent.
+pub fn sync_all() {
+    let sbs: Vec<Arc<dyn SuperBlock>> = MOUNTS
+        .exclusive_access()
+        .iter()
+        .map(|mount| mount.sb.clone())
+        .collect();
+    for sb in sbs {
+        sb.sync();
+    }
+}
+
+/// The root file system.
+pub fn root_super_block() -> Arc<dyn SuperBlock> {
+    MOUNTS.exclusive_access()[0].sb.clone()
+}
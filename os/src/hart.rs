@@ -0,0 +1,234 @@
+//! Harts of the kernel. The boot hart starts the secondary ones with the SBI
+//! HSM extension, at boot or at runtime, and they take tasks from the same
+//! ready queue. Each hart keeps its id in `tp`, see `hart_id`.
+//!
+//! The harts talk to each other with IPIs: an idle hart is asked to look
+//! for tasks again, an online one to stop, and all of them to flush their
+//! TLBs once a mapping is removed. A stopping hart leaves its task in the
+//! ready queue, so there is nothing to migrate.
+
+use crate::config::{BOOT_HART, CLINT, PAGE_SIZE};
+use crate::drivers::clint::Clint;
+use crate::mm::KERNEL_SPACE;
+use crate::sbi::{hart_running, hart_start, hart_stop, send_ipi};
+use crate::task::run_tasks;
+use crate::timer::set_next_trigger;
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use riscv::register::sstatus;
+
+pub const MAX_HARTS: usize = 8;
+/// how many times to poll the hart before giving up
+const TIMEOUT_LOOPS: usize = 10_000_000;
+const HART_STACK_SIZE: usize = PAGE_SIZE * 4;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const OFFLINE: AtomicBool = AtomicBool::new(false);
+static ONLINE: [AtomicBool; MAX_HARTS] = [OFFLINE; MAX_HARTS];
+static STOP_REQUESTED: [AtomicBool; MAX_HARTS] = [OFFLINE; MAX_HARTS];
+/// waiting for an interrupt in the idle loop
+static IDLE: [AtomicBool; MAX_HARTS] = [OFFLINE; MAX_HARTS];
+
+bitflags! {
+    pub struct IpiKind: usize {
+        /// a task is ready, for an idle hart
+        const RESCHEDULE = 1 << 0;
+        /// the hart is asked to stop
+        const STOP = 1 << 1;
+        /// a mapping is removed
+        const TLB_FLUSH = 1 << 2;
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_IPI: AtomicUsize = AtomicUsize::new(0);
+static PENDING_IPIS: [AtomicUsize; MAX_HARTS] = [NO_IPI; MAX_HARTS];
+
+#[repr(align(4096))]
+struct HartStack([u8; HART_STACK_SIZE]);
+
+const HART_STACK: HartStack = HartStack([0; HART_STACK_SIZE]);
+static mut HART_STACKS: [HartStack; MAX_HARTS] = [HART_STACK; MAX_HARTS];
+
+extern "C" {
+    fn _start_secondary();
+}
+
+/// The hart running the code. `tp` is set on entry of every hart, and the
+/// trap context keeps it while the user uses `tp` as its thread pointer.
+#[inline(always)]
+pub fn hart_id() -> usize {
+    let hart_id;
+    unsafe {
+        asm!("mv {}, tp", out(reg) hart_id);
+    }
+    hart_id
+}
+
+/// Entered from `_start_secondary` on the stack of the hart, with paging off.
+#[no_mangle]
+pub fn secondary_main(hart_id: usize) -> ! {
+    KERNEL_SPACE.exclusive_access().activate();
+    crate::trap::init();
+    crate::trap::enable_timer_interrupt();
+    crate::trap::enable_software_interrupt();
+    set_next_trigger();
+    ONLINE[hart_id].store(true, Ordering::SeqCst);
+    // back once the hart is asked to stop
+    run_tasks();
+    ONLINE[hart_id].store(false, Ordering::SeqCst);
+    STOP_REQUESTED[hart_id].store(false, Ordering::SeqCst);
+    hart_stop()
+}
+
+fn is_online(hart_id: usize) -> bool {
+    hart_id == BOOT_HART || ONLINE[hart_id].load(Ordering::SeqCst)
+}
+
+/// The secondary harts online, except the current one.
+pub fn online_harts() -> Vec<usize> {
+    (0..MAX_HARTS)
+        .filter(|&other| other != hart_id() && ONLINE[other].load(Ordering::SeqCst))
+        .collect()
+}
+
+/// Start every secondary hart there is, at boot.
+pub fn boot_secondary_harts() {
+    for hart_id in (0..MAX_HARTS).filter(|&hart_id| hart_id != BOOT_HART) {
+        if cpu_up(hart_id) {
+            info!("KERN: hart {} online", hart_id);
+        }
+    }
+}
+
+fn wait_until(cond: impl Fn() -> bool) -> bool {
+    (0..TIMEOUT_LOOPS).any(|_| {
+        core::hint::spin_loop();
+        cond()
+    })
+}
+
+/// Start a secondary hart, return false if it is the boot hart, online
+/// already or cannot be started by the SBI.
+pub fn cpu_up(hart_id: usize) -> bool {
+    if hart_id == BOOT_HART || hart_id >= MAX_HARTS || ONLINE[hart_id].load(Ordering::SeqCst) {
+        return false;
+    }
+    // it may be on its way to stop by itself
+    if !wait_until(|| !hart_running(hart_id) && !STOP_REQUESTED[hart_id].load(Ordering::SeqCst)) {
+        return false;
+    }
+    let stack_top = unsafe { HART_STACKS[hart_id].0.as_ptr_range().end as usize };
+    if !hart_start(hart_id, _start_secondary as usize, stack_top) {
+        return false;
+    }
+    wait_until(|| ONLINE[hart_id].load(Ordering::SeqCst))
+}
+
+/// Stop an online secondary hart, return false if it is not one. The
+/// current hart stops once it is back from the trap.
+pub fn cpu_down(hart_id: usize) -> bool {
+    if hart_id == BOOT_HART || hart_id >= MAX_HARTS || !ONLINE[hart_id].load(Ordering::SeqCst) {
+        return false;
+    }
+    if STOP_REQUESTED[hart_id].swap(true, Ordering::SeqCst) {
+        return false;
+    }
+    if hart_id == self::hart_id() {
+        return true;
+    }
+    send_ipi_to(hart_id, IpiKind::STOP);
+    wait_until(|| !ONLINE[hart_id].load(Ordering::SeqCst)) && wait_until(|| !hart_running(hart_id))
+}
+
+/// Whether the current hart is asked to stop, then the task running has to
+/// give it up.
+pub fn stop_requested() -> bool {
+    STOP_REQUESTED[hart_id()].load(Ordering::SeqCst)
+}
+
+pub fn send_ipi_to(hart_id: usize, kind: IpiKind) {
+    PENDING_IPIS[hart_id].fetch_or(kind.bits(), Ordering::SeqCst);
+    match CLINT {
+        Some(base_addr) => Clint::new(base_addr).send_soft(hart_id),
+        None => send_ipi(hart_id),
+    }
+}
+
+/// Handle the software interrupt of the current hart, return the IPIs
+/// left for the caller, which are all but the TLB flush.
+pub fn handle_ipi() -> IpiKind {
+    if let Some(base_addr) = CLINT {
+        Clint::new(base_addr).clear_soft(hart_id());
+    }
+    unsafe {
+        // clear sip.SSIP
+        asm!("csrci sip, 2");
+    }
+    let kind = IpiKind::from_bits_truncate(PENDING_IPIS[hart_id()].swap(0, Ordering::SeqCst));
+    if kind.contains(IpiKind::TLB_FLUSH) {
+        unsafe {
+            asm!("sfence.vma");
+        }
+    }
+    kind - IpiKind::TLB_FLUSH
+}
+
+/// Flush the TLB if it is asked to, for a hart spinning with interrupts
+/// masked, which would keep the others waiting in `tlb_shootdown`.
+pub fn poll_tlb_flush() {
+    let pending = &PENDING_IPIS[hart_id()];
+    if pending.load(Ordering::Relaxed) & IpiKind::TLB_FLUSH.bits() != 0 {
+        pending.fetch_and(!IpiKind::TLB_FLUSH.bits(), Ordering::SeqCst);
+        unsafe {
+            asm!("sfence.vma");
+        }
+    }
+}
+
+/// A mapping shared by the harts is removed, flush the TLBs of all of them
+/// before the frames are used again.
+pub fn tlb_shootdown() {
+    unsafe {
+        asm!("sfence.vma");
+    }
+    let others: Vec<usize> = (0..MAX_HARTS)
+        .filter(|&other| other != hart_id() && is_online(other))
+        .collect();
+    for &other in others.iter() {
+        send_ipi_to(other, IpiKind::TLB_FLUSH);
+    }
+    for &other in others.iter() {
+        while PENDING_IPIS[other].load(Ordering::SeqCst) & IpiKind::TLB_FLUSH.bits() != 0 {
+            // the other one may wait for this one as well
+            poll_tlb_flush();
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// A task is ready, ask an idle hart other than the current one to run it.
+pub fn kick_idle_hart() {
+    let current = hart_id();
+    if let Some(idle) = (0..MAX_HARTS)
+        .find(|&other| other != current && is_online(other) && IDLE[other].load(Ordering::SeqCst))
+    {
+        send_ipi_to(idle, IpiKind::RESCHEDULE);
+    }
+}
+
+/// Nothing to run, wait for an interrupt. `has_work` is checked after the
+/// hart is marked idle, so a task added meanwhile is not missed.
+pub fn idle(has_work: impl Fn() -> bool) {
+    let hart_id = hart_id();
+    IDLE[hart_id].store(true, Ordering::SeqCst);
+    if !has_work() && !STOP_REQUESTED[hart_id].load(Ordering::SeqCst) {
+        unsafe {
+            sstatus::set_sie();
+            riscv::asm::wfi();
+            sstatus::clear_sie();
+        }
+    }
+    IDLE[hart_id].store(false, Ordering::SeqCst);
+}
@@ -0,0 +1,142 @@
+//! The kernel log: what the kernel prints is also kept in a ring in memory,
+//! which a process can map read-only with `sys_klog_map` to stream the log
+//! without a syscall per line.
+//!
+//! The ring is a `KlogHeader` followed by the text, and byte `n` of the log
+//! is at `n % size` of the text. Since `head` counts the bytes written since
+//! boot, a reader keeping its own count knows how many it has missed. The
+//! writer moves `reserved` before overwriting, so a reader checks it after
+//! copying in case the bytes copied were overwritten meanwhile.
+//!
+//! The messages of `error!`, `warn!`, `info!` and `debug!` are kept with
+//! their level as a `<n>` prefix, like /proc/kmsg of Linux, and printed only
+//! up to the console level, which is set at boot from `LOG` of the build.
+
+use crate::config::PAGE_SIZE;
+use crate::sync::UPIntrFreeCell;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::*;
+
+pub const KLOG_PAGES: usize = 4;
+const KLOG_SIZE: usize = KLOG_PAGES * PAGE_SIZE - core::mem::size_of::<KlogHeader>();
+
+/// The same layout as the one in the user library.
+#[repr(C)]
+struct KlogHeader {
+    /// the bytes written
+    head: AtomicUsize,
+    /// the bytes written and being written, at least `head`
+    reserved: AtomicUsize,
+    size: usize,
+}
+
+#[repr(C, align(4096))]
+struct KlogRing {
+    header: KlogHeader,
+    text: [u8; KLOG_SIZE],
+}
+
+static mut RING: KlogRing = KlogRing {
+    header: KlogHeader {
+        head: AtomicUsize::new(0),
+        reserved: AtomicUsize::new(0),
+        size: KLOG_SIZE,
+    },
+    text: [0; KLOG_SIZE],
+};
+
+/// The levels of the messages, the numbers of syslog.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 3,
+    Warn = 4,
+    Info = 6,
+    Debug = 7,
+}
+
+impl Level {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "error" | "ERROR" => Some(Self::Error),
+            "warn" | "WARN" => Some(Self::Warn),
+            "info" | "INFO" => Some(Self::Info),
+            "debug" | "DEBUG" => Some(Self::Debug),
+            _ => None,
+        }
+    }
+
+    /// The prefix of a message in the log.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Self::Error => "<3>",
+            Self::Warn => "<4>",
+            Self::Info => "<6>",
+            Self::Debug => "<7>",
+        }
+    }
+}
+
+/// The messages of a level greater than it are only kept in the log.
+static CONSOLE_LEVEL: AtomicUsize = AtomicUsize::new(Level::Info as usize);
+
+/// Set the console level from `LOG`, info if it is not set or unknown.
+pub fn init() {
+    if let Some(level) = option_env!("LOG").and_then(Level::from_name) {
+        CONSOLE_LEVEL.store(level as usize, Ordering::Relaxed);
+    }
+}
+
+/// Whether the messages of `level` are printed to the console.
+pub fn printed(level: Level) -> bool {
+    level as usize <= CONSOLE_LEVEL.load(Ordering::Relaxed)
+}
+
+lazy_static! {
+    static ref KLOG: UPIntrFreeCell<&'static mut KlogRing> =
+        unsafe { UPIntrFreeCell::new(&mut RING) };
+}
+
+/// Append to the log, overwriting the oldest bytes if it is full.
+pub fn record(bytes: &[u8]) {
+    let mut ring = KLOG.exclusive_access();
+    let head = ring.header.head.load(Ordering::Relaxed);
+    let len = bytes.len().min(KLOG_SIZE);
+    let bytes = &bytes[bytes.len() - len..];
+    ring.header.reserved.store(head + len, Ordering::Relaxed);
+    // readers see `reserved` moved before any byte overwritten
+    core::sync::atomic::fence(Ordering::Release);
+    for (i, &byte) in bytes.iter().enumerate() {
+        ring.text[(head + i) % KLOG_SIZE] = byte;
+    }
+    ring.header.head.store(head + len, Ordering::Release);
+}
+
+/// The newest `len` bytes kept in the log at most, for `sys_dmesg`.
+pub fn read_tail(len: usize) -> Vec<u8> {
+    let _ring = KLOG.exclusive_access();
+    let mut text = Vec::new();
+    for_each_text(|bytes| text.extend_from_slice(bytes));
+    text.split_off(text.len() - len.min(text.len()))
+}
+
+/// Call `f` with the text kept in the log from the oldest byte, without the
+/// lock, which the panicked code may hold.
+pub fn for_each_text(mut f: impl FnMut(&[u8])) {
+    let ring = unsafe { &RING };
+    let head = ring.header.head.load(Ordering::Acquire);
+    let start = head.saturating_sub(KLOG_SIZE);
+    let (from, to) = (start % KLOG_SIZE, head % KLOG_SIZE);
+    if from < to || head == start {
+        f(&ring.text[from..to]);
+    } else {
+        f(&ring.text[from..]);
+        f(&ring.text[..to]);
+    }
+}
+
+/// The physical address of the ring, which is `KLOG_PAGES` pages.
+pub fn klog_address() -> usize {
+    let ring = KLOG.exclusive_access();
+    &**ring as *const KlogRing as usize
+}
@@ -0,0 +1,52 @@
+//! The symbol table of the kernel to print code addresses as
+//! `function+offset`.
+//!
+//! It is generated by build.rs from the symbols of a previous link of the
+//! kernel, so `make kernel` links twice. The table is put in .rodata after
+//! the text, whose addresses stay the same in the second link. A kernel
+//! built by cargo alone has an empty one.
+
+use core::arch::global_asm;
+use core::slice;
+use core::str;
+
+global_asm!(include_str!(concat!(env!("OUT_DIR"), "/ksyms.S")));
+
+extern "C" {
+    fn stext();
+    fn etext();
+    static ksyms_num: usize;
+    /// sorted addresses of the functions
+    static ksyms_addrs: usize;
+    /// offsets of their names in `ksyms_names`, one more for the end
+    static ksyms_offsets: u32;
+    static ksyms_names: u8;
+}
+
+fn addrs() -> &'static [usize] {
+    unsafe { slice::from_raw_parts(&ksyms_addrs, ksyms_num) }
+}
+
+fn name(index: usize) -> &'static str {
+    unsafe {
+        let offsets = slice::from_raw_parts(&ksyms_offsets, ksyms_num + 1);
+        let (start, end) = (offsets[index] as usize, offsets[index + 1] as usize);
+        let names = slice::from_raw_parts(&ksyms_names as *const u8, end);
+        str::from_utf8_unchecked(&names[start..end])
+    }
+}
+
+/// Find the function containing the code address `addr`, return its name
+/// and the offset of `addr` in it.
+pub fn lookup(addr: usize) -> Option<(&'static str, usize)> {
+    if !(stext as usize..etext as usize).contains(&addr) {
+        return None;
+    }
+    let addrs = addrs();
+    let index = match addrs.binary_search(&addr) {
+        Ok(index) => index,
+        Err(0) => return None,
+        Err(index) => index - 1,
+    };
+    Some((name(index), addr - addrs[index]))
+}
@@ -1,24 +1,29 @@
+use crate::console::enter_emergency;
+use crate::crashdump;
+use crate::ksyms;
 use crate::sbi::shutdown;
 use crate::task::current_kstack_top;
 use core::arch::asm;
 use core::panic::PanicInfo;
-use log::*;
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    // the console lock may be held by the panicked code
+    enter_emergency();
     if let Some(location) = info.location() {
-        error!(
+        println!(
             "[kernel] Panicked at {}:{} {}",
             location.file(),
             location.line(),
             info.message().unwrap()
         );
     } else {
-        error!("[kernel] Panicked: {}", info.message().unwrap());
+        println!("[kernel] Panicked: {}", info.message().unwrap());
     }
     unsafe {
         backtrace();
     }
+    crashdump::save(info);
     shutdown(true)
 }
 
@@ -31,7 +36,11 @@ unsafe fn backtrace() {
         if fp == stop {
             break;
         }
-        println!("#{}:ra={:#x}", i, *((fp - 8) as *const usize));
+        let ra = *((fp - 8) as *const usize);
+        match ksyms::lookup(ra) {
+            Some((name, offset)) => println!("#{}:ra={:#x} {}+{:#x}", i, ra, name, offset),
+            None => println!("#{}:ra={:#x}", i, ra),
+        }
         fp = *((fp - 16) as *const usize);
     }
     println!("---END   BACKTRACE---");
@@ -0,0 +1,62 @@
+//! Histograms of latencies, for now the one from a byte received by the UART
+//! to the reader woken up taking it, which covers the interrupt handler, the
+//! waker and the scheduler. It is read from `/proc/irq_latency`.
+
+use alloc::string::String;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Bucket `i` counts the latencies in [2^(i-1), 2^i) us, the first one those
+/// under 1us and the last one all from 2^(BUCKETS-2) us.
+const BUCKETS: usize = 24;
+
+pub struct LatencyHistogram {
+    name: &'static str,
+    buckets: [AtomicUsize; BUCKETS],
+    max_us: AtomicUsize,
+}
+
+impl LatencyHistogram {
+    const fn new(name: &'static str) -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicUsize = AtomicUsize::new(0);
+        Self {
+            name,
+            buckets: [ZERO; BUCKETS],
+            max_us: ZERO,
+        }
+    }
+
+    pub fn record(&self, us: usize) {
+        let bucket = (usize::BITS - us.leading_zeros()) as usize;
+        self.buckets[bucket.min(BUCKETS - 1)].fetch_add(1, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+    }
+
+    /// The buckets counted, one in a line like "  16 -   32 us: 5".
+    pub fn render(&self, text: &mut String) {
+        let counts: [usize; BUCKETS] =
+            core::array::from_fn(|i| self.buckets[i].load(Ordering::Relaxed));
+        writeln!(text, "{}", self.name).unwrap();
+        writeln!(text, "count: {}", counts.iter().sum::<usize>()).unwrap();
+        writeln!(text, "max: {} us", self.max_us.load(Ordering::Relaxed)).unwrap();
+        for (i, &count) in counts.iter().enumerate().filter(|(_, &count)| count > 0) {
+            let low = if i == 0 { 0 } else { 1 << (i - 1) };
+            if i == BUCKETS - 1 {
+                writeln!(text, "{:>8} -      inf us: {}", low, count).unwrap();
+            } else {
+                writeln!(text, "{:>8} - {:>8} us: {}", low, 1usize << i, count).unwrap();
+            }
+        }
+    }
+}
+
+pub static UART_WAKEUP_LATENCY: LatencyHistogram =
+    LatencyHistogram::new("uart irq to reader polled");
+
+/// The text of `/proc/irq_latency`.
+pub fn irq_latency_text() -> String {
+    let mut text = String::new();
+    UART_WAKEUP_LATENCY.render(&mut text);
+    text
+}
@@ -3,30 +3,36 @@
 #![feature(panic_info_message)]
 #![feature(alloc_error_handler)]
 
-//use crate::drivers::{GPU_DEVICE, KEYBOARD_DEVICE, MOUSE_DEVICE, INPUT_CONDVAR};
-use crate::drivers::{GPU_DEVICE, KEYBOARD_DEVICE, MOUSE_DEVICE};
 extern crate alloc;
 
 #[macro_use]
 extern crate bitflags;
 
-#[path = "boards/qemu.rs"]
-mod board;
-
 #[macro_use]
 mod console;
+mod boards;
 mod config;
+mod crashdump;
 mod drivers;
+mod dtb;
 mod fs;
+mod hart;
+mod klog;
+mod ksyms;
 mod lang_items;
+mod latency;
 mod mm;
 mod net;
+mod random;
 mod sbi;
+mod suspend;
 mod sync;
 mod syscall;
+mod sysrq;
 mod task;
 mod timer;
 mod trap;
+mod trigger;
 
 use crate::drivers::chardev::CharDevice;
 use crate::drivers::chardev::UART;
@@ -52,25 +58,30 @@ lazy_static! {
         unsafe { UPIntrFreeCell::new(false) };
 }
 
+/// Entered from `_start` with the hart id and the device tree from the SBI.
 #[no_mangle]
-pub fn rust_main() -> ! {
+pub fn rust_main(_hart_id: usize, dtb: usize) -> ! {
     clear_bss();
+    // before paging is on, the device tree is not mapped
+    if let Some(freq) = dtb::timebase_frequency(dtb) {
+        timer::set_timebase_freq(freq);
+    }
+    klog::init();
     mm::init();
     UART.init();
-    println!("KERN: init gpu");
-    let _gpu = GPU_DEVICE.clone();
-    println!("KERN: init keyboard");
-    let _keyboard = KEYBOARD_DEVICE.clone();
-    println!("KERN: init mouse");
-    let _mouse = MOUSE_DEVICE.clone();
-    println!("KERN: init trap");
+    info!("KERN: init trap");
     trap::init();
     trap::enable_timer_interrupt();
+    trap::enable_software_interrupt();
     timer::set_next_trigger();
-    board::device_init();
+    timer::init_realtime();
+    boards::device_init();
     fs::list_apps();
     task::add_initproc();
+    fs::spawn_syncd();
+    console::spawn_consoled();
     *DEV_NON_BLOCKING_ACCESS.exclusive_access() = true;
+    hart::boot_secondary_harts();
     task::run_tasks();
     panic!("Unreachable in rust_main!");
 }
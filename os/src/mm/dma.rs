@@ -0,0 +1,51 @@
+use super::{frame_alloc_more, FrameTracker, PhysAddr};
+use crate::config::PAGE_SIZE;
+use alloc::vec::Vec;
+
+/// Physically contiguous memory for DMA.
+///
+/// The frames are allocated at once, so they are contiguous, and the
+/// physical memory is identically mapped in the kernel, so the virtual
+/// address is the physical one. DMA of the devices on QEMU is coherent with
+/// the caches, nothing has to be flushed.
+pub struct DmaBuffer {
+    /// recycled when dropped
+    frames: Vec<FrameTracker>,
+    paddr: usize,
+}
+
+impl DmaBuffer {
+    /// Allocate zeroed pages, return None if the frames run out.
+    pub fn new(pages: usize) -> Option<Self> {
+        assert!(pages > 0);
+        let frames = frame_alloc_more(pages)?;
+        let ppn = frames.iter().map(|frame| frame.ppn).min().unwrap();
+        Some(Self {
+            frames,
+            paddr: PhysAddr::from(ppn).0,
+        })
+    }
+
+    pub fn vaddr(&self) -> usize {
+        self.paddr
+    }
+
+    pub fn paddr(&self) -> usize {
+        self.paddr
+    }
+
+    pub fn pages(&self) -> usize {
+        self.frames.len()
+    }
+
+    #[allow(unused)]
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.vaddr() as *const u8, self.pages() * PAGE_SIZE) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe {
+            core::slice::from_raw_parts_mut(self.vaddr() as *mut u8, self.pages() * PAGE_SIZE)
+        }
+    }
+}
@@ -1,10 +1,13 @@
 use super::{PhysAddr, PhysPageNum};
 use crate::config::MEMORY_END;
 use crate::sync::UPIntrFreeCell;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::fmt::{self, Debug, Formatter};
 use lazy_static::*;
 
+/// A reference to a physical frame, which is recycled when the last
+/// reference is dropped.
 pub struct FrameTracker {
     pub ppn: PhysPageNum,
 }
@@ -20,6 +23,14 @@ impl FrameTracker {
     }
 }
 
+impl Clone for FrameTracker {
+    /// Share the frame rather than copy it.
+    fn clone(&self) -> Self {
+        frame_get(self.ppn);
+        Self { ppn: self.ppn }
+    }
+}
+
 impl Debug for FrameTracker {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.write_fmt(format_args!("FrameTracker:PPN={:#x}", self.ppn.0))
@@ -28,7 +39,7 @@ impl Debug for FrameTracker {
 
 impl Drop for FrameTracker {
     fn drop(&mut self) {
-        frame_dealloc(self.ppn);
+        frame_put(self.ppn);
     }
 }
 
@@ -93,36 +104,110 @@ impl FrameAllocator for StackFrameAllocator {
 
 type FrameAllocatorImpl = StackFrameAllocator;
 
+/// Reference counts of all the allocatable frames.
+pub struct FrameRefcounts {
+    base: usize,
+    counts: Vec<u16>,
+}
+
+impl FrameRefcounts {
+    pub fn new() -> Self {
+        Self {
+            base: 0,
+            counts: Vec::new(),
+        }
+    }
+    pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
+        self.base = l.0;
+        self.counts = vec![0; r.0 - l.0];
+    }
+    fn count_mut(&mut self, ppn: PhysPageNum) -> &mut u16 {
+        match ppn.0.checked_sub(self.base) {
+            Some(index) if index < self.counts.len() => &mut self.counts[index],
+            _ => panic!("Frame ppn={:#x} is not allocatable!", ppn.0),
+        }
+    }
+}
+
 lazy_static! {
     pub static ref FRAME_ALLOCATOR: UPIntrFreeCell<FrameAllocatorImpl> =
         unsafe { UPIntrFreeCell::new(FrameAllocatorImpl::new()) };
+    pub static ref FRAME_REFCOUNTS: UPIntrFreeCell<FrameRefcounts> =
+        unsafe { UPIntrFreeCell::new(FrameRefcounts::new()) };
 }
 
 pub fn init_frame_allocator() {
     extern "C" {
         fn ekernel();
     }
-    FRAME_ALLOCATOR.exclusive_access().init(
-        PhysAddr::from(ekernel as usize).ceil(),
-        PhysAddr::from(MEMORY_END).floor(),
-    );
+    let l = PhysAddr::from(ekernel as usize).ceil();
+    let r = PhysAddr::from(MEMORY_END).floor();
+    FRAME_ALLOCATOR.exclusive_access().init(l, r);
+    FRAME_REFCOUNTS.exclusive_access().init(l, r);
+}
+
+/// Set the count of a newly allocated frame to 1.
+fn frame_init_refcount(ppn: PhysPageNum) -> PhysPageNum {
+    let mut refcounts = FRAME_REFCOUNTS.exclusive_access();
+    let count = refcounts.count_mut(ppn);
+    debug_assert_eq!(*count, 0, "Frame ppn={:#x} is allocated twice!", ppn.0);
+    *count = 1;
+    ppn
+}
+
+/// The number of free frames and all the allocatable ones.
+pub fn frame_stats() -> (usize, usize) {
+    let allocator = FRAME_ALLOCATOR.exclusive_access();
+    let total = FRAME_REFCOUNTS.exclusive_access().counts.len();
+    (
+        allocator.end - allocator.current + allocator.recycled.len(),
+        total,
+    )
 }
 
 pub fn frame_alloc() -> Option<FrameTracker> {
-    FRAME_ALLOCATOR
-        .exclusive_access()
-        .alloc()
-        .map(FrameTracker::new)
+    let ppn = FRAME_ALLOCATOR.exclusive_access().alloc()?;
+    Some(FrameTracker::new(frame_init_refcount(ppn)))
 }
 
 pub fn frame_alloc_more(num: usize) -> Option<Vec<FrameTracker>> {
-    FRAME_ALLOCATOR
-        .exclusive_access()
-        .alloc_more(num)
-        .map(|x| x.iter().map(|&t| FrameTracker::new(t)).collect())
+    let ppns = FRAME_ALLOCATOR.exclusive_access().alloc_more(num)?;
+    Some(
+        ppns.into_iter()
+            .map(|ppn| FrameTracker::new(frame_init_refcount(ppn)))
+            .collect(),
+    )
+}
+
+/// Take one more reference to an allocated frame.
+pub fn frame_get(ppn: PhysPageNum) {
+    let mut refcounts = FRAME_REFCOUNTS.exclusive_access();
+    let count = refcounts.count_mut(ppn);
+    debug_assert!(*count > 0, "Frame ppn={:#x} has not been allocated!", ppn.0);
+    *count = count.checked_add(1).unwrap();
+}
+
+/// Drop one reference to a frame, which is recycled when it is the last one.
+/// Return whether the frame is recycled.
+pub fn frame_put(ppn: PhysPageNum) -> bool {
+    let mut refcounts = FRAME_REFCOUNTS.exclusive_access();
+    let count = refcounts.count_mut(ppn);
+    debug_assert!(*count > 0, "Frame ppn={:#x} is freed twice!", ppn.0);
+    *count -= 1;
+    if *count > 0 {
+        return false;
+    }
+    drop(refcounts);
+    frame_dealloc(ppn);
+    true
+}
+
+/// The number of references to a frame, 0 if it is free.
+pub fn frame_refcount(ppn: PhysPageNum) -> usize {
+    *FRAME_REFCOUNTS.exclusive_access().count_mut(ppn) as usize
 }
 
-pub fn frame_dealloc(ppn: PhysPageNum) {
+fn frame_dealloc(ppn: PhysPageNum) {
     FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
 }
 
@@ -144,6 +229,20 @@ pub fn frame_allocator_test() {
     println!("frame_allocator_test passed!");
 }
 
+#[allow(unused)]
+pub fn frame_refcount_test() {
+    let frame = frame_alloc().unwrap();
+    let ppn = frame.ppn;
+    assert_eq!(frame_refcount(ppn), 1);
+    let shared = frame.clone();
+    assert_eq!(frame_refcount(ppn), 2);
+    drop(frame);
+    assert_eq!(frame_refcount(ppn), 1);
+    drop(shared);
+    assert_eq!(frame_refcount(ppn), 0);
+    println!("frame_refcount_test passed!");
+}
+
 #[allow(unused)]
 pub fn frame_allocator_alloc_more_test() {
     let mut v: Vec<FrameTracker> = Vec::new();
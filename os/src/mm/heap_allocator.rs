@@ -1,9 +1,17 @@
+#[cfg(feature = "kasan")]
+use super::kasan::KasanHeap;
 use crate::config::KERNEL_HEAP_SIZE;
+#[cfg(not(feature = "kasan"))]
 use buddy_system_allocator::LockedHeap;
 
+#[cfg(not(feature = "kasan"))]
 #[global_allocator]
 static HEAP_ALLOCATOR: LockedHeap = LockedHeap::empty();
 
+#[cfg(feature = "kasan")]
+#[global_allocator]
+static HEAP_ALLOCATOR: KasanHeap = KasanHeap::empty();
+
 #[alloc_error_handler]
 pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
     panic!("Heap allocation error, layout = {:?}", layout);
@@ -19,6 +27,13 @@ pub fn init_heap() {
     }
 }
 
+/// The bytes allocated from the kernel heap and its size, None if the heap
+/// is locked by the code interrupted.
+pub fn heap_stats() -> Option<(usize, usize)> {
+    let heap = HEAP_ALLOCATOR.try_lock()?;
+    Some((heap.stats_alloc_actual(), heap.stats_total_bytes()))
+}
+
 #[allow(unused)]
 pub fn heap_test() {
     use alloc::boxed::Box;
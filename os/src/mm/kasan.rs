@@ -0,0 +1,171 @@
+//! KASAN-lite for the kernel heap, enabled by the `kasan` feature.
+//!
+//! Every object is surrounded by poisoned red zones, which are checked when
+//! it is freed. Freed objects are poisoned and kept in a quarantine for a
+//! while before going back to the heap, so writes after free and double frees
+//! can be caught. Reads of bad memory are not detected without compiler
+//! instrumentation. Errors are reported by a panic with a backtrace.
+
+use buddy_system_allocator::{Heap, LockedHeap};
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::ops::Deref;
+use core::ptr::NonNull;
+
+/// The least size of red zones, the header is not included
+const REDZONE: usize = 16;
+const QUARANTINE_LEN: usize = 256;
+/// Quarantined objects are released earlier if they are too large in total.
+const QUARANTINE_BYTES: usize = 0x10_0000;
+
+const MAGIC_ALLOCATED: usize = 0x6b61_7361_6e61_6c6c;
+const MAGIC_FREED: usize = 0x6b61_7361_6e66_7265;
+const POISON_REDZONE: u8 = 0xfa;
+const POISON_FREED: u8 = 0xfd;
+
+/// At the end of the left red zone, right before the object.
+#[repr(C)]
+struct Header {
+    magic: usize,
+    size: usize,
+}
+
+struct Quarantine {
+    /// a ring of freed objects and their layouts
+    objects: [(usize, Layout); QUARANTINE_LEN],
+    head: usize,
+    len: usize,
+    bytes: usize,
+}
+
+pub struct KasanHeap {
+    heap: LockedHeap,
+    /// protected by the lock of `heap`
+    quarantine: UnsafeCell<Quarantine>,
+}
+
+unsafe impl Sync for KasanHeap {}
+
+/// The layout of an object with its red zones, and the offset of the object.
+fn redzone_layout(layout: Layout) -> (Layout, usize) {
+    let align = layout.align().max(REDZONE);
+    let offset = (core::mem::size_of::<Header>() + REDZONE + align - 1) / align * align;
+    let size = offset + layout.size() + REDZONE;
+    (Layout::from_size_align(size, align).unwrap(), offset)
+}
+
+fn report(kind: &str, ptr: usize, size: usize) -> ! {
+    panic!(
+        "[kasan] {} on the object at {:#x} of size {}",
+        kind, ptr, size
+    );
+}
+
+unsafe fn fill(start: usize, len: usize, byte: u8) {
+    core::slice::from_raw_parts_mut(start as *mut u8, len).fill(byte);
+}
+
+unsafe fn is_poisoned(start: usize, len: usize, byte: u8) -> bool {
+    core::slice::from_raw_parts(start as *const u8, len)
+        .iter()
+        .all(|b| *b == byte)
+}
+
+impl KasanHeap {
+    pub const fn empty() -> Self {
+        Self {
+            heap: LockedHeap::empty(),
+            quarantine: UnsafeCell::new(Quarantine {
+                objects: [(0, Layout::new::<u8>()); QUARANTINE_LEN],
+                head: 0,
+                len: 0,
+                bytes: 0,
+            }),
+        }
+    }
+
+    /// Release the oldest object in quarantine to the heap.
+    unsafe fn release_oldest(&self, heap: &mut Heap) {
+        let quarantine = &mut *self.quarantine.get();
+        let (ptr, layout) = quarantine.objects[quarantine.head];
+        quarantine.head = (quarantine.head + 1) % QUARANTINE_LEN;
+        quarantine.len -= 1;
+        quarantine.bytes -= layout.size();
+        if !is_poisoned(ptr, layout.size(), POISON_FREED) {
+            report("use-after-free write", ptr, layout.size());
+        }
+        let (layout, offset) = redzone_layout(layout);
+        heap.dealloc(NonNull::new_unchecked((ptr - offset) as *mut u8), layout);
+    }
+}
+
+impl Deref for KasanHeap {
+    type Target = LockedHeap;
+
+    fn deref(&self) -> &LockedHeap {
+        &self.heap
+    }
+}
+
+unsafe impl GlobalAlloc for KasanHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let (redzone_layout, offset) = redzone_layout(layout);
+        let mut heap = self.heap.lock();
+        let base = loop {
+            match heap.alloc(redzone_layout) {
+                Ok(base) => break base.as_ptr() as usize,
+                // objects in quarantine may be enough
+                Err(()) if (*self.quarantine.get()).len > 0 => self.release_oldest(&mut heap),
+                Err(()) => return core::ptr::null_mut(),
+            }
+        };
+        drop(heap);
+        let ptr = base + offset;
+        let header_size = core::mem::size_of::<Header>();
+        fill(base, offset - header_size, POISON_REDZONE);
+        *((ptr - header_size) as *mut Header) = Header {
+            magic: MAGIC_ALLOCATED,
+            size: layout.size(),
+        };
+        fill(
+            ptr + layout.size(),
+            redzone_layout.size() - offset - layout.size(),
+            POISON_REDZONE,
+        );
+        ptr as *mut u8
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let ptr = ptr as usize;
+        let (redzone_layout, offset) = redzone_layout(layout);
+        let header_size = core::mem::size_of::<Header>();
+        let header = &mut *((ptr - header_size) as *mut Header);
+        match header.magic {
+            MAGIC_ALLOCATED if header.size == layout.size() => {}
+            MAGIC_FREED => report("double free", ptr, layout.size()),
+            _ => report("invalid free", ptr, layout.size()),
+        }
+        if !is_poisoned(ptr - offset, offset - header_size, POISON_REDZONE) {
+            report("heap buffer underflow", ptr, layout.size());
+        }
+        let right_redzone = redzone_layout.size() - offset - layout.size();
+        if !is_poisoned(ptr + layout.size(), right_redzone, POISON_REDZONE) {
+            report("heap buffer overflow", ptr, layout.size());
+        }
+        header.magic = MAGIC_FREED;
+        fill(ptr, layout.size(), POISON_FREED);
+        let mut heap = self.heap.lock();
+        let quarantine = &mut *self.quarantine.get();
+        if quarantine.len == QUARANTINE_LEN {
+            self.release_oldest(&mut heap);
+        }
+        let quarantine = &mut *self.quarantine.get();
+        let tail = (quarantine.head + quarantine.len) % QUARANTINE_LEN;
+        quarantine.objects[tail] = (ptr, layout);
+        quarantine.len += 1;
+        quarantine.bytes += layout.size();
+        while (*self.quarantine.get()).bytes > QUARANTINE_BYTES {
+            self.release_oldest(&mut heap);
+        }
+    }
+}
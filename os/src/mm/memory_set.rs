@@ -1,8 +1,9 @@
-use super::{frame_alloc, FrameTracker};
+use super::{frame_alloc, frame_refcount, FrameTracker};
 use super::{PTEFlags, PageTable, PageTableEntry};
 use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use super::{StepByOne, VPNRange};
-use crate::config::{MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE};
+use crate::config::{MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE, USER_SPACE_END};
+use crate::hart::tlb_shootdown;
 use crate::sync::UPIntrFreeCell;
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
@@ -36,6 +37,7 @@ pub fn kernel_token() -> usize {
 pub struct MemorySet {
     page_table: PageTable,
     areas: Vec<MapArea>,
+    stats: VmStats,
 }
 
 impl MemorySet {
@@ -43,6 +45,7 @@ impl MemorySet {
         Self {
             page_table: PageTable::new(),
             areas: Vec::new(),
+            stats: VmStats::default(),
         }
     }
     pub fn token(&self) -> usize {
@@ -69,8 +72,95 @@ impl MemorySet {
         {
             area.unmap(&mut self.page_table);
             self.areas.remove(idx);
+            tlb_shootdown();
         }
     }
+    /// Whether no area has a page in [start_vpn, end_vpn).
+    pub fn is_free(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        self.areas.iter().all(|area| {
+            area.vpn_range.get_end() <= start_vpn || end_vpn <= area.vpn_range.get_start()
+        })
+    }
+    /// The lowest free range of `pages` pages in [from, to), None if there
+    /// is none.
+    pub fn find_free(
+        &self,
+        from: VirtPageNum,
+        to: VirtPageNum,
+        pages: usize,
+    ) -> Option<VirtPageNum> {
+        let mut ranges: Vec<(usize, usize)> = self
+            .areas
+            .iter()
+            .map(|area| (area.vpn_range.get_start().0, area.vpn_range.get_end().0))
+            .collect();
+        ranges.sort_unstable();
+        let mut start = from.0;
+        for (area_start, area_end) in ranges {
+            if area_end <= start {
+                continue;
+            }
+            if area_start >= start + pages {
+                break;
+            }
+            start = area_end;
+        }
+        (start + pages <= to.0).then(|| VirtPageNum(start))
+    }
+    /// Add an area created at runtime, like by `sys_mmap`, return false if it
+    /// overlaps another one. The pages of an area backed by a file are loaded
+    /// on their first access.
+    pub fn insert_area(&mut self, map_area: MapArea) -> bool {
+        if !self.is_free(map_area.vpn_range.get_start(), map_area.vpn_range.get_end()) {
+            return false;
+        }
+        if map_area.backing.is_some() {
+            self.areas.push(map_area);
+        } else {
+            self.push(map_area, None);
+        }
+        true
+    }
+    /// Unmap the pages in [start_vpn, end_vpn), splitting the areas partly in
+    /// the range. Return false, with nothing unmapped, if the range has pages
+    /// the user cannot access, like the trap contexts.
+    pub fn unmap_range(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        let overlaps = |area: &MapArea| {
+            area.vpn_range.get_start() < end_vpn && start_vpn < area.vpn_range.get_end()
+        };
+        if self
+            .areas
+            .iter()
+            .any(|area| overlaps(area) && !area.map_perm.contains(MapPermission::U))
+        {
+            return false;
+        }
+        let mut i = 0;
+        while i < self.areas.len() {
+            let area = &mut self.areas[i];
+            if !overlaps(area) {
+                i += 1;
+                continue;
+            }
+            if area.vpn_range.get_end() > end_vpn {
+                let tail = area.split_off(end_vpn);
+                self.areas.insert(i + 1, tail);
+            }
+            let area = &mut self.areas[i];
+            if area.vpn_range.get_start() < start_vpn {
+                // the part in the range is the next one
+                let middle = area.split_off(start_vpn);
+                self.areas.insert(i + 1, middle);
+                i += 1;
+                continue;
+            }
+            let mut area = self.areas.remove(i);
+            area.unmap(&mut self.page_table);
+        }
+        // the frames are freed, other harts may still map them
+        tlb_shootdown();
+        true
+    }
     /// Add a new MapArea into this MemorySet.
     /// Assuming that there are no conflicts in the virtual address
     /// space.
@@ -166,9 +256,9 @@ impl MemorySet {
         }
         memory_set
     }
-    /// Include sections in elf and trampoline,
-    /// also returns user_sp_base and entry point.
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
+    /// Include sections in elf and trampoline, `load_bias` added to their
+    /// addresses, also returns user_sp_base and entry point.
+    pub fn from_elf(elf_data: &[u8], load_bias: usize) -> (Self, usize, usize) {
         let mut memory_set = Self::new_bare();
         // map trampoline
         memory_set.map_trampoline();
@@ -182,19 +272,10 @@ impl MemorySet {
         for i in 0..ph_count {
             let ph = elf.program_header(i).unwrap();
             if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
-                let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
-                let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
-                let mut map_perm = MapPermission::U;
-                let ph_flags = ph.flags();
-                if ph_flags.is_read() {
-                    map_perm |= MapPermission::R;
-                }
-                if ph_flags.is_write() {
-                    map_perm |= MapPermission::W;
-                }
-                if ph_flags.is_execute() {
-                    map_perm |= MapPermission::X;
-                }
+                let start = ph.virtual_addr() as usize + load_bias;
+                let start_va: VirtAddr = start.into();
+                let end_va: VirtAddr = (start + ph.mem_size() as usize).into();
+                let map_perm = elf_map_perm(ph.flags());
                 let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
                 max_end_vpn = map_area.vpn_range.get_end();
                 memory_set.push(
@@ -209,18 +290,102 @@ impl MemorySet {
         (
             memory_set,
             user_stack_base,
-            elf.header.pt2.entry_point() as usize,
+            elf.header.pt2.entry_point() as usize + load_bias,
         )
     }
-    pub fn from_existed_user(user_space: &MemorySet) -> MemorySet {
+    /// Like `from_elf`, but the ELF is read by pages from `source`, and the
+    /// segments are loaded on demand: a page is populated by
+    /// `handle_page_fault` on its first access. Read-only segments are mapped
+    /// to the frames of the file instead of copies of them.
+    ///
+    /// Return None if the program headers are not in the first page. A page
+    /// beyond the end of the file is a fault once accessed.
+    pub fn from_elf_pages(
+        source: Arc<dyn PageSource>,
+        load_bias: usize,
+    ) -> Option<(Self, usize, usize)> {
+        let header = source.page(0)?;
+        let elf = xmas_elf::ElfFile::new(header.ppn.get_bytes_array()).ok()?;
+        let ph_count = elf.header.pt2.ph_count();
+        let ph_end = elf.header.pt2.ph_offset() as usize
+            + elf.header.pt2.ph_entry_size() as usize * ph_count as usize;
+        if ph_end > PAGE_SIZE {
+            return None;
+        }
+        let mut memory_set = Self::new_bare();
+        memory_set.map_trampoline();
+        let mut max_end_vpn = VirtPageNum(0);
+        for i in 0..ph_count {
+            let ph = elf.program_header(i).ok()?;
+            if ph.get_type() != Ok(xmas_elf::program::Type::Load) {
+                continue;
+            }
+            let vaddr = ph.virtual_addr() as usize + load_bias;
+            let offset = ph.offset() as usize;
+            let (file_size, mem_size) = (ph.file_size() as usize, ph.mem_size() as usize);
+            let map_perm = elf_map_perm(ph.flags());
+            let mut map_area = MapArea::new(
+                vaddr.into(),
+                (vaddr + mem_size).into(),
+                MapType::Framed,
+                map_perm,
+            );
+            max_end_vpn = map_area.vpn_range.get_end();
+            map_area.backing = Some(FileBacking {
+                source: Arc::clone(&source),
+                vaddr,
+                offset,
+                file_size,
+                // whole pages of the file without anything to clear
+                share: !map_perm.contains(MapPermission::W)
+                    && vaddr % PAGE_SIZE == offset % PAGE_SIZE
+                    && file_size == mem_size,
+            });
+            memory_set.areas.push(map_area);
+        }
+        let max_end_va: VirtAddr = max_end_vpn.into();
+        let user_stack_base = usize::from(max_end_va) + PAGE_SIZE;
+        Some((
+            memory_set,
+            user_stack_base,
+            elf.header.pt2.entry_point() as usize + load_bias,
+        ))
+    }
+    /// Fork a user space. The frames of the user are shared rather than
+    /// copied, the writable ones are mapped read-only in both spaces and
+    /// copied on the first write, see `handle_page_fault`.
+    pub fn from_existed_user(user_space: &mut MemorySet) -> MemorySet {
         let mut memory_set = Self::new_bare();
         // map trampoline
         memory_set.map_trampoline();
         // copy data sections/trap_context/user_stack
         for area in user_space.areas.iter() {
-            let new_area = MapArea::from_another(area);
+            let mut new_area = MapArea::from_another(area);
+            // nobody writes read-only frames, like the ones of the page cache
+            if area.map_type == MapType::Framed && !area.map_perm.contains(MapPermission::W) {
+                for (vpn, frame) in area.data_frames.iter() {
+                    new_area.map_frame(&mut memory_set.page_table, *vpn, frame.clone());
+                }
+                memory_set.areas.push(new_area);
+                continue;
+            }
+            if area.is_copy_on_write() {
+                let flags = area.cow_flags();
+                for (vpn, frame) in area.data_frames.iter() {
+                    user_space.page_table.remap(*vpn, frame.ppn, flags);
+                    memory_set.page_table.map(*vpn, frame.ppn, flags);
+                    new_area.data_frames.insert(*vpn, frame.clone());
+                }
+                memory_set.areas.push(new_area);
+                continue;
+            }
             memory_set.push(new_area, None);
-            // copy data from another space
+            // the same memory is mapped by linear areas, like the kernel log
+            if area.map_type != MapType::Framed {
+                continue;
+            }
+            // copy data from another space, the trap contexts are written by
+            // the kernel through the frames
             for vpn in area.vpn_range {
                 let src_ppn = user_space.translate(vpn).unwrap().ppn();
                 let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
@@ -229,8 +394,165 @@ impl MemorySet {
                     .copy_from_slice(src_ppn.get_bytes_array());
             }
         }
+        // the parent may still write the shared frames through stale TLBs
+        tlb_shootdown();
         memory_set
     }
+    /// Resolve a page fault at `vpn`, return false if it is a real one. A
+    /// page of a file not loaded yet is loaded. A write to a page of a
+    /// copy-on-write area mapped read-only copies the frame, or just makes it
+    /// writable if nobody else maps it any more.
+    pub fn handle_page_fault(&mut self, vpn: VirtPageNum, write: bool) -> bool {
+        let area = match self.areas.iter_mut().find(|area| area.contains(vpn)) {
+            Some(area) => area,
+            None => return false,
+        };
+        if !area.data_frames.contains_key(&vpn) {
+            if write && !area.map_perm.contains(MapPermission::W) {
+                return false;
+            }
+            if !area.populate(&mut self.page_table, vpn) {
+                return false;
+            }
+            self.stats.lazy_faults += 1;
+            return true;
+        }
+        if !write || !area.is_copy_on_write() {
+            return false;
+        }
+        self.stats.cow_faults += 1;
+        let frame = match area.data_frames.get(&vpn) {
+            Some(frame) => frame,
+            None => return false,
+        };
+        if self.page_table.translate(vpn).unwrap().writable() {
+            // resolved by another thread meanwhile
+            return true;
+        }
+        let flags = PTEFlags::from_bits(area.map_perm.bits).unwrap();
+        if frame_refcount(frame.ppn) == 1 {
+            self.page_table.remap(vpn, frame.ppn, flags);
+            unsafe {
+                asm!("sfence.vma {}", in(reg) usize::from(VirtAddr::from(vpn)));
+            }
+            return true;
+        }
+        let copy = match frame_alloc() {
+            Some(copy) => copy,
+            None => return false,
+        };
+        copy.ppn
+            .get_bytes_array()
+            .copy_from_slice(frame.ppn.get_bytes_array());
+        self.page_table.remap(vpn, copy.ppn, flags);
+        // the old frame is dropped, with the last reference of this space
+        area.data_frames.insert(vpn, copy);
+        // the other threads may read the old frame through stale TLBs
+        tlb_shootdown();
+        true
+    }
+    /// Copy the framed areas accessible in U-mode, which are the program,
+    /// the user stacks and the heap, but not trap contexts. The pages not
+    /// loaded yet are loaded, those beyond the file are zeros.
+    pub fn user_area_images(&mut self) -> Vec<AreaImage> {
+        let page_table = &mut self.page_table;
+        self.areas
+            .iter_mut()
+            .filter(|area| {
+                area.map_type == MapType::Framed && area.map_perm.contains(MapPermission::U)
+            })
+            .map(|area| {
+                let mut data = Vec::new();
+                for vpn in area.vpn_range {
+                    if !area.data_frames.contains_key(&vpn) {
+                        area.populate(page_table, vpn);
+                    }
+                    match area.data_frames.get(&vpn) {
+                        Some(frame) => data.extend_from_slice(frame.ppn.get_bytes_array()),
+                        None => data.resize(data.len() + PAGE_SIZE, 0),
+                    }
+                }
+                AreaImage {
+                    start_vpn: area.vpn_range.get_start(),
+                    end_vpn: area.vpn_range.get_end(),
+                    perm: area.map_perm,
+                    data,
+                }
+            })
+            .collect()
+    }
+    pub fn area_infos(&self) -> Vec<AreaInfo> {
+        self.areas
+            .iter()
+            .map(|area| AreaInfo {
+                start: VirtAddr::from(area.vpn_range.get_start()).into(),
+                end: VirtAddr::from(area.vpn_range.get_end()).into(),
+                perm: area.map_perm,
+                resident: area.data_frames.len() * PAGE_SIZE,
+            })
+            .collect()
+    }
+    pub fn vm_stats(&self) -> VmStats {
+        self.stats
+    }
+    /// Count a page allocated on the first access elsewhere, like the pages
+    /// of a user stack growing.
+    pub fn count_lazy_fault(&mut self) {
+        self.stats.lazy_faults += 1;
+    }
+    pub fn usage(&self) -> MemUsage {
+        let mut usage = MemUsage::default();
+        for info in self.area_infos() {
+            usage.virt += info.end - info.start;
+            usage.resident += info.resident;
+        }
+        for area in self.areas.iter() {
+            let shared = area
+                .data_frames
+                .values()
+                .filter(|frame| frame_refcount(frame.ppn) > 1)
+                .count();
+            usage.shared += shared * PAGE_SIZE;
+        }
+        usage.areas = self.areas.len();
+        usage
+    }
+    /// Rebuild a user space from the images of areas, with the trampoline.
+    /// Return None if an image is not a whole user area with the data of all
+    /// its pages, or they overlap.
+    pub fn from_area_images(images: &[AreaImage]) -> Option<Self> {
+        let user_end = VirtAddr::from(USER_SPACE_END).floor();
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for image in images {
+            let (start, end) = (image.start_vpn.0, image.end_vpn.0);
+            if start >= end
+                || end > user_end.0
+                || !image.perm.contains(MapPermission::U)
+                || image.data.len() != (end - start) * PAGE_SIZE
+            {
+                return None;
+            }
+            ranges.push((start, end));
+        }
+        ranges.sort_unstable();
+        if ranges.windows(2).any(|pair| pair[0].1 > pair[1].0) {
+            return None;
+        }
+        let mut memory_set = Self::new_bare();
+        memory_set.map_trampoline();
+        for image in images {
+            memory_set.push(
+                MapArea::new(
+                    image.start_vpn.into(),
+                    image.end_vpn.into(),
+                    MapType::Framed,
+                    image.perm,
+                ),
+                Some(image.data.as_slice()),
+            );
+        }
+        Some(memory_set)
+    }
     pub fn activate(&self) {
         let satp = self.page_table.token();
         unsafe {
@@ -241,17 +563,113 @@ impl MemorySet {
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.page_table.translate(vpn)
     }
+    /// Print the mappings in the page table within `range`.
+    pub fn dump(&self, range: VPNRange) {
+        self.page_table.dump(range);
+    }
     pub fn recycle_data_pages(&mut self) {
+        // the threads left behind on other harts are out of the user mode
+        // once their TLBs are flushed
+        tlb_shootdown();
         //*self = Self::new_bare();
         self.areas.clear();
     }
 }
 
+/// Memory usage of a space in bytes, the trampoline is not counted.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MemUsage {
+    /// the size of all areas
+    pub virt: usize,
+    /// the size of frames mapped
+    pub resident: usize,
+    /// the size of frames shared with other spaces or the page cache
+    pub shared: usize,
+    pub areas: usize,
+}
+
+/// The page faults resolved in a space since the program started, the
+/// same layout as the one in the user library.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct VmStats {
+    /// writes to frames shared copy-on-write, copied or just made writable
+    pub cow_faults: usize,
+    /// pages allocated or loaded from a file on the first access
+    pub lazy_faults: usize,
+    /// pages read back from swap, always 0 as there is no swap yet
+    pub swap_ins: usize,
+}
+
+pub struct AreaInfo {
+    pub start: usize,
+    pub end: usize,
+    pub perm: MapPermission,
+    pub resident: usize,
+}
+
+/// The range, permission and data of a framed area.
+pub struct AreaImage {
+    pub start_vpn: VirtPageNum,
+    pub end_vpn: VirtPageNum,
+    pub perm: MapPermission,
+    pub data: Vec<u8>,
+}
+
+/// Where the pages of a file come from, like the page cache of an inode.
+pub trait PageSource: Send + Sync {
+    /// The frame of the page `index` of the file, shared read-only.
+    fn page(&self, index: usize) -> Option<FrameTracker>;
+    /// The size of the file in bytes.
+    fn size(&self) -> usize;
+}
+
+/// A segment of a file a framed area is loaded from on demand.
+#[derive(Clone)]
+pub struct FileBacking {
+    source: Arc<dyn PageSource>,
+    /// where the segment starts in the space
+    vaddr: usize,
+    /// where the segment starts in the file
+    offset: usize,
+    file_size: usize,
+    /// map the frames of the file rather than copies of them
+    share: bool,
+}
+
+impl FileBacking {
+    /// Copy the part of the segment in the page `vpn` to `frame`, which is
+    /// cleared. Return false if the file is short.
+    fn copy_page(&self, vpn: VirtPageNum, frame: &FrameTracker) -> bool {
+        let page_start = usize::from(VirtAddr::from(vpn));
+        let start = page_start.max(self.vaddr);
+        let end = (page_start + PAGE_SIZE).min(self.vaddr + self.file_size);
+        let mut va = start;
+        while va < end {
+            let src = self.offset + (va - self.vaddr);
+            let len = (PAGE_SIZE - src % PAGE_SIZE).min(end - va);
+            let page = match self.source.page(src / PAGE_SIZE) {
+                Some(page) => page,
+                None => return false,
+            };
+            let dst = va - page_start;
+            frame.ppn.get_bytes_array()[dst..dst + len].copy_from_slice(
+                &page.ppn.get_bytes_array()[src % PAGE_SIZE..src % PAGE_SIZE + len],
+            );
+            va += len;
+        }
+        true
+    }
+}
+
 pub struct MapArea {
     vpn_range: VPNRange,
     data_frames: BTreeMap<VirtPageNum, FrameTracker>,
     map_type: MapType,
     map_perm: MapPermission,
+    /// Some if the pages are loaded from a file on their first access
+    backing: Option<FileBacking>,
 }
 
 impl MapArea {
@@ -268,16 +686,92 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type,
             map_perm,
+            backing: None,
         }
     }
+    pub fn contains(&self, vpn: VirtPageNum) -> bool {
+        self.vpn_range.get_start() <= vpn && vpn < self.vpn_range.get_end()
+    }
+    /// Writable pages of the user, shared by forked spaces until written.
+    /// The trap contexts are not, the kernel writes them through the frames.
+    fn is_copy_on_write(&self) -> bool {
+        self.map_type == MapType::Framed
+            && self.map_perm.contains(MapPermission::U | MapPermission::W)
+    }
+    /// The flags of a copy-on-write page before it is written.
+    fn cow_flags(&self) -> PTEFlags {
+        PTEFlags::from_bits((self.map_perm - MapPermission::W).bits).unwrap()
+    }
+    /// Load the pages from `source` on their first access, from `offset` of
+    /// the file on. Only `file_size` bytes are read, the rest is zeros.
+    /// Map the frames of the file rather than copies of them if `share`,
+    /// then the file is not short and nobody writes the area.
+    pub fn backed_by(
+        mut self,
+        source: Arc<dyn PageSource>,
+        offset: usize,
+        file_size: usize,
+        share: bool,
+    ) -> Self {
+        self.backing = Some(FileBacking {
+            source,
+            vaddr: VirtAddr::from(self.vpn_range.get_start()).into(),
+            offset,
+            file_size,
+            share,
+        });
+        self
+    }
+    /// Split the area at `vpn`, the pages from `vpn` on are moved to the
+    /// area returned.
+    fn split_off(&mut self, vpn: VirtPageNum) -> Self {
+        let tail = Self {
+            vpn_range: VPNRange::new(vpn, self.vpn_range.get_end()),
+            data_frames: self.data_frames.split_off(&vpn),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
+            backing: self.backing.clone(),
+        };
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), vpn);
+        tail
+    }
     pub fn from_another(another: &MapArea) -> Self {
         Self {
             vpn_range: VPNRange::new(another.vpn_range.get_start(), another.vpn_range.get_end()),
             data_frames: BTreeMap::new(),
             map_type: another.map_type,
             map_perm: another.map_perm,
+            backing: another.backing.clone(),
         }
     }
+    /// Load the page `vpn` of an area backed by a file, return false if it
+    /// is not backed or the file is short.
+    fn populate(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        let backing = match self.backing.as_ref() {
+            Some(backing) => backing,
+            None => return false,
+        };
+        let frame = if backing.share {
+            // the area may be split, count from where the segment starts
+            let index =
+                (backing.offset + usize::from(VirtAddr::from(vpn)) - backing.vaddr) / PAGE_SIZE;
+            match backing.source.page(index) {
+                Some(frame) => frame,
+                None => return false,
+            }
+        } else {
+            let frame = match frame_alloc() {
+                Some(frame) => frame,
+                None => return false,
+            };
+            if !backing.copy_page(vpn, &frame) {
+                return false;
+            }
+            frame
+        };
+        self.map_frame(page_table, vpn, frame);
+        true
+    }
     pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
         let ppn: PhysPageNum;
         match self.map_type {
@@ -298,9 +792,17 @@ impl MapArea {
         let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
         page_table.map(vpn, ppn, pte_flags);
     }
+    /// Map `vpn` to a given frame of a framed area.
+    pub fn map_frame(&mut self, page_table: &mut PageTable, vpn: VirtPageNum, frame: FrameTracker) {
+        assert_eq!(self.map_type, MapType::Framed);
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        page_table.map(vpn, frame.ppn, pte_flags);
+        self.data_frames.insert(vpn, frame);
+    }
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        if self.map_type == MapType::Framed {
-            self.data_frames.remove(&vpn);
+        // the pages of a file may not be loaded yet
+        if self.map_type == MapType::Framed && self.data_frames.remove(&vpn).is_none() {
+            return;
         }
         page_table.unmap(vpn);
     }
@@ -338,6 +840,21 @@ impl MapArea {
     }
 }
 
+/// The permission of a mapping of an ELF segment.
+fn elf_map_perm(flags: xmas_elf::program::Flags) -> MapPermission {
+    let mut map_perm = MapPermission::U;
+    if flags.is_read() {
+        map_perm |= MapPermission::R;
+    }
+    if flags.is_write() {
+        map_perm |= MapPermission::W;
+    }
+    if flags.is_execute() {
+        map_perm |= MapPermission::X;
+    }
+    map_perm
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum MapType {
     Identical,
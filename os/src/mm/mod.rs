@@ -1,19 +1,31 @@
 mod address;
+mod dma;
 mod frame_allocator;
 mod heap_allocator;
+#[cfg(feature = "kasan")]
+mod kasan;
 mod memory_set;
 mod page_table;
+mod user_ptr;
 
 pub use address::VPNRange;
 pub use address::{PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
-pub use frame_allocator::{frame_alloc, frame_alloc_more, frame_dealloc, FrameTracker};
+pub use dma::DmaBuffer;
+pub use frame_allocator::{
+    frame_alloc, frame_alloc_more, frame_get, frame_put, frame_refcount, frame_stats, FrameTracker,
+};
+pub use heap_allocator::heap_stats;
 pub use memory_set::remap_test;
-pub use memory_set::{kernel_token, MapArea, MapPermission, MapType, MemorySet, KERNEL_SPACE};
+pub use memory_set::{
+    kernel_token, AreaImage, AreaInfo, MapArea, MapPermission, MapType, MemUsage, MemorySet,
+    PageSource, VmStats, KERNEL_SPACE,
+};
 use page_table::PTEFlags;
 pub use page_table::{
     translated_byte_buffer, translated_ref, translated_refmut, translated_str, PageTable,
     PageTableEntry, UserBuffer, UserBufferIterator,
 };
+pub use user_ptr::{UserCString, UserSliceRef};
 
 pub fn init() {
     heap_allocator::init_heap();
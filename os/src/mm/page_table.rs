@@ -1,4 +1,8 @@
-use super::{frame_alloc, FrameTracker, PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
+use super::{
+    frame_alloc, FrameTracker, PhysAddr, PhysPageNum, StepByOne, VPNRange, VirtAddr, VirtPageNum,
+};
+use crate::config::PAGE_SIZE;
+use crate::task::handle_user_page_fault;
 use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
@@ -52,6 +56,35 @@ impl PageTableEntry {
     }
 }
 
+/// Pages mapped to contiguous frames with the same flags.
+struct MappingRun {
+    vpn: usize,
+    ppn: usize,
+    pages: usize,
+    flags: PTEFlags,
+}
+
+impl MappingRun {
+    fn print(&self) {
+        let flag = |flag: PTEFlags, c: char| if self.flags.contains(flag) { c } else { '-' };
+        println!(
+            "vpn [{:#x}, {:#x}) -> ppn [{:#x}, {:#x}) {}{}{}{}{}{}{}{}",
+            self.vpn,
+            self.vpn + self.pages,
+            self.ppn,
+            self.ppn + self.pages,
+            flag(PTEFlags::V, 'v'),
+            flag(PTEFlags::R, 'r'),
+            flag(PTEFlags::W, 'w'),
+            flag(PTEFlags::X, 'x'),
+            flag(PTEFlags::U, 'u'),
+            flag(PTEFlags::G, 'g'),
+            flag(PTEFlags::A, 'a'),
+            flag(PTEFlags::D, 'd'),
+        );
+    }
+}
+
 pub struct PageTable {
     root_ppn: PhysPageNum,
     frames: Vec<FrameTracker>,
@@ -115,6 +148,13 @@ impl PageTable {
         assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
     }
+    /// Change the frame and the flags of a mapped page, the TLBs are left
+    /// to the caller.
+    pub fn remap(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
+        let pte = self.find_pte(vpn).unwrap();
+        assert!(pte.is_valid(), "vpn {:?} is invalid before remapping", vpn);
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+    }
     #[allow(unused)]
     pub fn unmap(&mut self, vpn: VirtPageNum) {
         let pte = self.find_pte(vpn).unwrap();
@@ -135,6 +175,104 @@ impl PageTable {
     pub fn token(&self) -> usize {
         8usize << 60 | self.root_ppn.0
     }
+    /// Print the mappings within `range`. Adjacent pages mapped to adjacent
+    /// frames with the same flags are printed in a line.
+    pub fn dump(&self, range: VPNRange) {
+        let mut run = None;
+        Self::dump_level(self.root_ppn, 0, 0, &range, &mut run);
+        match run {
+            Some(run) => run.print(),
+            None => println!("no mappings"),
+        }
+    }
+    fn dump_level(
+        ppn: PhysPageNum,
+        level: usize,
+        base_vpn: usize,
+        range: &VPNRange,
+        run: &mut Option<MappingRun>,
+    ) {
+        let (start, end) = (range.get_start().0, range.get_end().0);
+        // pages covered by an entry of this level
+        let pages = 1usize << (9 * (2 - level));
+        for (i, pte) in ppn.get_pte_array().iter().enumerate() {
+            let vpn = base_vpn + i * pages;
+            if !pte.is_valid() || vpn + pages <= start || vpn >= end {
+                continue;
+            }
+            let is_leaf = pte.readable() || pte.writable() || pte.executable();
+            if !is_leaf {
+                if level < 2 {
+                    Self::dump_level(pte.ppn(), level + 1, vpn, range, run);
+                }
+                continue;
+            }
+            // a huge page is clipped by the range
+            let first = vpn.max(start);
+            let pages = (vpn + pages).min(end) - first;
+            let frame = pte.ppn().0 + first - vpn;
+            match run {
+                Some(last)
+                    if last.flags == pte.flags()
+                        && last.vpn + last.pages == first
+                        && last.ppn + last.pages == frame =>
+                {
+                    last.pages += pages;
+                }
+                _ => {
+                    if let Some(last) = run.take() {
+                        last.print();
+                    }
+                    *run = Some(MappingRun {
+                        vpn: first,
+                        ppn: frame,
+                        pages,
+                        flags: pte.flags(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// The kernel is about to access a page of the user space `token` through
+/// the frame, resolve it first like an access from the user would, so a page
+/// of a program is loaded and a copy-on-write page is copied before written.
+/// A page which stays read-only is left to be read.
+fn prepare_user_page(page_table: &PageTable, token: usize, vpn: VirtPageNum, write: bool) {
+    let pte = page_table.translate(vpn);
+    let valid = pte.map_or(false, |pte| pte.is_valid());
+    let writable = valid && pte.unwrap().writable();
+    let addr = VirtAddr::from(vpn).into();
+    if write && !writable && handle_user_page_fault(token, addr, true) {
+        return;
+    }
+    if !valid {
+        handle_user_page_fault(token, addr, false);
+    }
+}
+
+/// The frame of the page `vpn` of the user space `token`, resolved like
+/// `prepare_user_page`. None unless the user may read it, or write it if
+/// `write`, so the kernel never touches what the user cannot.
+pub(super) fn user_frame(
+    page_table: &PageTable,
+    token: usize,
+    vpn: VirtPageNum,
+    write: bool,
+) -> Option<PhysPageNum> {
+    prepare_user_page(page_table, token, vpn, write);
+    let pte = page_table.translate(vpn)?;
+    let permitted = if write {
+        pte.writable()
+    } else {
+        pte.readable()
+    };
+    if pte.is_valid() && pte.flags().contains(PTEFlags::U) && permitted {
+        Some(pte.ppn())
+    } else {
+        None
+    }
 }
 
 pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
@@ -145,6 +283,7 @@ pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&
     while start < end {
         let start_va = VirtAddr::from(start);
         let mut vpn = start_va.floor();
+        prepare_user_page(&page_table, token, vpn, true);
         let ppn = page_table.translate(vpn).unwrap().ppn();
         vpn.step();
         let mut end_va: VirtAddr = vpn.into();
@@ -165,6 +304,9 @@ pub fn translated_str(token: usize, ptr: *const u8) -> String {
     let mut string = String::new();
     let mut va = ptr as usize;
     loop {
+        if va == ptr as usize || va % PAGE_SIZE == 0 {
+            prepare_user_page(&page_table, token, VirtAddr::from(va).floor(), false);
+        }
         let ch: u8 = *(page_table
             .translate_va(VirtAddr::from(va))
             .unwrap()
@@ -180,6 +322,12 @@ pub fn translated_str(token: usize, ptr: *const u8) -> String {
 
 pub fn translated_ref<T>(token: usize, ptr: *const T) -> &'static T {
     let page_table = PageTable::from_token(token);
+    prepare_user_page(
+        &page_table,
+        token,
+        VirtAddr::from(ptr as usize).floor(),
+        false,
+    );
     page_table
         .translate_va(VirtAddr::from(ptr as usize))
         .unwrap()
@@ -189,6 +337,7 @@ pub fn translated_ref<T>(token: usize, ptr: *const T) -> &'static T {
 pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
     let page_table = PageTable::from_token(token);
     let va = ptr as usize;
+    prepare_user_page(&page_table, token, VirtAddr::from(va).floor(), true);
     page_table
         .translate_va(VirtAddr::from(va))
         .unwrap()
@@ -210,6 +359,12 @@ impl UserBuffer {
         }
         total
     }
+    /// Whether each of the buffers starts and ends at a multiple of `align`.
+    pub fn aligned(&self, align: usize) -> bool {
+        self.buffers
+            .iter()
+            .all(|buf| buf.as_ptr() as usize % align == 0 && buf.len() % align == 0)
+    }
 }
 
 impl IntoIterator for UserBuffer {
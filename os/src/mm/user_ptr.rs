@@ -0,0 +1,212 @@
+//! Pointers from the user in the arguments of syscalls.
+//!
+//! The dispatcher wraps every pointer argument in a `UserCString` or a
+//! `UserSliceRef`, and the syscalls reach the user memory through them only.
+//! Each access is checked against the page table like an access of the user:
+//! a page the user may not read, or write for the results, gives None, which
+//! the syscalls return as `SysError::Fault` instead of the kernel faulting on
+//! it, or as EFAULT where the Linux ABI layer accesses the user memory itself.
+
+use super::page_table::user_frame;
+use super::{PageTable, StepByOne, UserBuffer, VirtAddr};
+use crate::config::{PAGE_SIZE, USER_SPACE_END, USER_STRING_MAX};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of};
+use core::sync::atomic::AtomicU32;
+
+/// The bytes at `[addr, addr + len)` of the user space `token`, a slice for
+/// each page. None if the user may not read them, or write them if `write`.
+fn user_byte_buffer(
+    token: usize,
+    addr: usize,
+    len: usize,
+    write: bool,
+) -> Option<Vec<&'static mut [u8]>> {
+    let end = addr.checked_add(len)?;
+    if end > USER_SPACE_END {
+        return None;
+    }
+    let page_table = PageTable::from_token(token);
+    let mut start = addr;
+    let mut v = Vec::new();
+    while start < end {
+        let start_va = VirtAddr::from(start);
+        let mut vpn = start_va.floor();
+        let ppn = user_frame(&page_table, token, vpn, write)?;
+        vpn.step();
+        let end_va = VirtAddr::from(vpn).min(VirtAddr::from(end));
+        if end_va.page_offset() == 0 {
+            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
+        } else {
+            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
+        }
+        start = end_va.into();
+    }
+    Some(v)
+}
+
+/// A string ended by `\0` in the user space.
+#[derive(Clone, Copy)]
+pub struct UserCString {
+    addr: usize,
+}
+
+impl UserCString {
+    pub fn new(addr: usize) -> Self {
+        Self { addr }
+    }
+
+    /// Copy the string without the `\0`, each byte a char. None if it is not
+    /// readable, or longer than `USER_STRING_MAX` with the `\0`.
+    pub fn read(&self, token: usize) -> Option<String> {
+        let page_table = PageTable::from_token(token);
+        let mut string = String::new();
+        let mut page: &[u8] = &[];
+        for va in self.addr..self.addr.checked_add(USER_STRING_MAX)? {
+            if va >= USER_SPACE_END {
+                return None;
+            }
+            if va == self.addr || va % PAGE_SIZE == 0 {
+                let vpn = VirtAddr::from(va).floor();
+                page = user_frame(&page_table, token, vpn, false)?.get_bytes_array();
+            }
+            let ch = page[va % PAGE_SIZE];
+            if ch == 0 {
+                return Some(string);
+            }
+            string.push(ch as char);
+        }
+        None
+    }
+}
+
+/// `len` values of `T` in the user space, to read or to write. `T` is plain
+/// data, any bytes are a value of it.
+pub struct UserSliceRef<T> {
+    addr: usize,
+    len: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Clone for UserSliceRef<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for UserSliceRef<T> {}
+
+impl<T: Copy> UserSliceRef<T> {
+    pub fn new(addr: usize, len: usize) -> Self {
+        Self {
+            addr,
+            len,
+            _marker: PhantomData,
+        }
+    }
+
+    /// A single value, like a pointer to `T` in C.
+    pub fn one(addr: usize) -> Self {
+        Self::new(addr, 1)
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.addr == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The bytes of `count` values from the one at `index`.
+    fn byte_buffer(
+        &self,
+        token: usize,
+        index: usize,
+        count: usize,
+        write: bool,
+    ) -> Option<Vec<&'static mut [u8]>> {
+        if self.addr % align_of::<T>() != 0 || index.checked_add(count)? > self.len {
+            return None;
+        }
+        let start = self.addr.checked_add(index.checked_mul(size_of::<T>())?)?;
+        user_byte_buffer(token, start, count.checked_mul(size_of::<T>())?, write)
+    }
+
+    /// Copy all the values.
+    pub fn read(&self, token: usize) -> Option<Vec<T>> {
+        let buffers = self.byte_buffer(token, 0, self.len, false)?;
+        let mut values: Vec<T> = Vec::with_capacity(self.len);
+        let mut dst = values.as_mut_ptr() as *mut u8;
+        for buffer in buffers {
+            unsafe {
+                dst.copy_from_nonoverlapping(buffer.as_ptr(), buffer.len());
+                dst = dst.add(buffer.len());
+            }
+        }
+        unsafe {
+            values.set_len(self.len);
+        }
+        Some(values)
+    }
+
+    /// Copy the value at `index`.
+    pub fn get(&self, token: usize, index: usize) -> Option<T> {
+        let buffers = self.byte_buffer(token, index, 1, false)?;
+        let mut value = core::mem::MaybeUninit::<T>::uninit();
+        let mut dst = value.as_mut_ptr() as *mut u8;
+        for buffer in buffers {
+            unsafe {
+                dst.copy_from_nonoverlapping(buffer.as_ptr(), buffer.len());
+                dst = dst.add(buffer.len());
+            }
+        }
+        Some(unsafe { value.assume_init() })
+    }
+
+    /// Write `values` from the first one, at most `len` of them.
+    pub fn write(&self, token: usize, values: &[T]) -> Option<()> {
+        self.write_at(token, 0, values)
+    }
+
+    /// Write `value` at `index`.
+    pub fn set(&self, token: usize, index: usize, value: T) -> Option<()> {
+        self.write_at(token, index, core::slice::from_ref(&value))
+    }
+
+    fn write_at(&self, token: usize, index: usize, values: &[T]) -> Option<()> {
+        let buffers = self.byte_buffer(token, index, values.len(), true)?;
+        let mut src = values.as_ptr() as *const u8;
+        for buffer in buffers {
+            unsafe {
+                buffer
+                    .as_mut_ptr()
+                    .copy_from_nonoverlapping(src, buffer.len());
+                src = src.add(buffer.len());
+            }
+        }
+        Some(())
+    }
+}
+
+impl UserSliceRef<u32> {
+    /// The first value as an atomic shared with the user, like the word of a
+    /// futex, and its physical address. The page is resolved for writing, so
+    /// a copy-on-write page is copied before it is shared. None unless the
+    /// user may write it.
+    pub fn atomic(&self, token: usize) -> Option<(usize, &'static AtomicU32)> {
+        let buffers = self.byte_buffer(token, 0, 1, true)?;
+        let ptr = buffers[0].as_ptr();
+        Some((ptr as usize, unsafe { &*(ptr as *const AtomicU32) }))
+    }
+}
+
+impl UserSliceRef<u8> {
+    /// The bytes as the buffer of a file, which the file fills if `write`
+    /// like for `sys_read`, or takes otherwise.
+    pub fn buffer(&self, token: usize, write: bool) -> Option<UserBuffer> {
+        user_byte_buffer(token, self.addr, self.len, write).map(UserBuffer::new)
+    }
+}
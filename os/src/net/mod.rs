@@ -6,15 +6,22 @@ pub mod udp;
 pub use lose_net_stack::IPv4;
 
 use alloc::{sync::Arc, vec};
+use core::sync::atomic::{AtomicBool, Ordering};
 use lose_net_stack::{results::Packet, LoseStack, MacAddress, TcpFlags};
+use riscv::register::sstatus;
 
 use crate::{
     drivers::NET_DEVICE,
-    net::socket::{get_socket, push_data},
-    sync::UPIntrFreeCell,
+    fs::{PollEvents, ReadyHook, ReadyHooks},
+    net::socket::{get_socket, get_socket_or_bind, get_state, push_data, set_state, SocketState},
+    sync::{UPIntrFreeCell, WaitQueue},
+    task::{spawn_kernel_thread, WakeReason},
 };
 
-use self::{port_table::check_accept, socket::set_s_a_by_index};
+use self::{
+    port_table::{check_accept, queue_syn},
+    socket::set_s_a_by_index,
+};
 
 pub struct NetStack(UPIntrFreeCell<LoseStack>);
 
@@ -31,12 +38,75 @@ impl NetStack {
 
 lazy_static::lazy_static! {
     static ref LOSE_NET_STACK: Arc<NetStack> = Arc::new(NetStack::new());
+    /// the packets handled by the net thread so far
+    static ref HANDLED: UPIntrFreeCell<usize> = unsafe { UPIntrFreeCell::new(0) };
+    /// the tasks blocking on the sockets and the ports
+    static ref NET_WAITERS: WaitQueue = WaitQueue::new();
+    /// the ready hooks of all the sockets and the ports, which are called
+    /// once any packet is handled
+    static ref NET_HOOKS: ReadyHooks = ReadyHooks::new();
+}
+
+static NETD_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Start the net thread with the first socket or port, the net device is
+/// not touched before.
+pub fn start_netd() {
+    if !NETD_STARTED.swap(true, Ordering::AcqRel) {
+        spawn_kernel_thread(netd_main);
+    }
+}
+
+/// The net thread is the only one receiving packets, it sleeps until the
+/// interrupt of the next one and wakes up whoever may wait for it.
+fn netd_main() -> ! {
+    // like a syscall, it waits for the net device with interrupts on
+    unsafe {
+        sstatus::set_sie();
+    }
+    loop {
+        net_interrupt_handler();
+        *HANDLED.exclusive_access() += 1;
+        NET_WAITERS.wake_up_all();
+        NET_HOOKS.notify(PollEvents::POLLIN);
+    }
 }
 
-pub fn net_interrupt_handler() {
+/// Block until `ready` returns true, which is checked again after each
+/// packet handled by the net thread. Return false if the task is to stop
+/// waiting for a signal or exiting, see `current_wake_reason`.
+pub fn wait_for_packets(mut ready: impl FnMut() -> bool) -> bool {
+    loop {
+        let handled = *HANDLED.exclusive_access();
+        if ready() {
+            return true;
+        }
+        let guard = HANDLED.exclusive_access();
+        if *guard != handled {
+            continue;
+        }
+        if NET_WAITERS.sleep_on(guard) != WakeReason::Woken {
+            return false;
+        }
+    }
+}
+
+/// See `File::add_ready_hook`, for the sockets and the ports.
+pub fn add_net_hook(hook: ReadyHook) -> usize {
+    NET_HOOKS.add(hook)
+}
+
+pub fn remove_net_hook(id: usize) {
+    NET_HOOKS.remove(id);
+}
+
+fn net_interrupt_handler() {
     let mut recv_buf = vec![0u8; 1024];
 
     let len = NET_DEVICE.receive(&mut recv_buf);
+    if len == 0 {
+        return;
+    }
 
     let packet = LOSE_NET_STACK
         .0
@@ -61,7 +131,7 @@ pub fn net_interrupt_handler() {
             let lport = udp_packet.dest_port;
             let rport = udp_packet.source_port;
 
-            if let Some(socket_index) = get_socket(target, lport, rport) {
+            if let Some(socket_index) = get_socket_or_bind(target, lport, rport) {
                 push_data(socket_index, udp_packet.data.to_vec());
             }
         }
@@ -72,12 +142,35 @@ pub fn net_interrupt_handler() {
             let rport = tcp_packet.source_port;
             let flags = tcp_packet.flags;
 
-            if flags.contains(TcpFlags::S) {
+            if flags.contains(TcpFlags::R) {
+                if let Some(socket_index) = get_socket(target, lport, rport) {
+                    if get_state(socket_index) == SocketState::Connecting {
+                        set_state(socket_index, SocketState::Refused);
+                        return;
+                    }
+                }
+            }
+
+            if flags.contains(TcpFlags::S | TcpFlags::A) {
+                // the peer answers our connecting request
+                if let Some(socket_index) = get_socket(target, lport, rport) {
+                    if get_state(socket_index) == SocketState::Connecting {
+                        let reply_packet = tcp_packet.ack();
+                        NET_DEVICE.transmit(&reply_packet.build_data());
+                        // following packets carry the same seq and ack as this reply
+                        set_s_a_by_index(socket_index, reply_packet.ack, reply_packet.seq);
+                        set_state(socket_index, SocketState::Established);
+                    }
+                }
+                return;
+            } else if flags.contains(TcpFlags::S) {
                 // if it has a port to accept, then response the request
                 if check_accept(lport, &tcp_packet).is_some() {
                     let mut reply_packet = tcp_packet.ack();
                     reply_packet.flags = TcpFlags::S | TcpFlags::A;
                     NET_DEVICE.transmit(&reply_packet.build_data());
+                } else {
+                    queue_syn(lport, &tcp_packet);
                 }
                 return;
             } else if tcp_packet.flags.contains(TcpFlags::F) {
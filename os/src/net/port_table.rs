@@ -1,17 +1,23 @@
-use alloc::{sync::Arc, vec::Vec};
+use abi::SysError;
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
 use lazy_static::lazy_static;
 use lose_net_stack::packets::tcp::TCPPacket;
+use lose_net_stack::TcpFlags;
 
-use crate::fs::File;
+use crate::drivers::NET_DEVICE;
+use crate::fs::{File, PollEvents, ReadyHook};
 use crate::sync::UPIntrFreeCell;
 use crate::task::TaskControlBlock;
 
 use super::tcp::TCP;
+use super::{add_net_hook, remove_net_hook, start_netd};
 
 pub struct Port {
     pub port: u16,
     pub receivable: bool,
     pub schedule: Option<Arc<TaskControlBlock>>,
+    /// SYN requests arrived while nobody is accepting
+    pub backlog: VecDeque<TCPPacket<'static>>,
 }
 
 lazy_static! {
@@ -20,6 +26,7 @@ lazy_static! {
 }
 
 pub fn listen(port: u16) -> Option<usize> {
+    start_netd();
     let mut listen_table = LISTEN_TABLE.exclusive_access();
     let mut index = usize::MAX;
     for i in 0..listen_table.len() {
@@ -33,6 +40,7 @@ pub fn listen(port: u16) -> Option<usize> {
         port,
         receivable: false,
         schedule: None,
+        backlog: VecDeque::new(),
     };
 
     if index == usize::MAX {
@@ -55,6 +63,19 @@ pub fn accept(listen_index: usize, task: Arc<TaskControlBlock>) {
     listen_port.schedule = Some(task);
 }
 
+/// Give up accepting, return false if a connection is accepted already.
+pub fn cancel_accept(listen_index: usize) -> bool {
+    let mut listen_table = LISTEN_TABLE.exclusive_access();
+    match listen_table[listen_index].as_mut() {
+        Some(listen_port) if listen_port.receivable => {
+            listen_port.receivable = false;
+            listen_port.schedule = None;
+            true
+        }
+        _ => false,
+    }
+}
+
 pub fn port_acceptable(listen_index: usize) -> bool {
     let mut listen_table = LISTEN_TABLE.exclusive_access();
     assert!(listen_index < listen_table.len());
@@ -88,10 +109,56 @@ pub fn check_accept(port: u16, tcp_packet: &TCPPacket) -> Option<()> {
     })
 }
 
+// keep the request until the port is accepted or polled
+pub fn queue_syn(port: u16, tcp_packet: &TCPPacket) {
+    LISTEN_TABLE.exclusive_session(|listen_table| {
+        if let Some(listen_port) = listen_table.iter_mut().flatten().find(|x| x.port == port) {
+            // the peer resends SYN until it is answered
+            if listen_port.backlog.iter().any(|syn| {
+                syn.source_ip == tcp_packet.source_ip && syn.source_port == tcp_packet.source_port
+            }) {
+                return;
+            }
+            listen_port.backlog.push_back(TCPPacket {
+                source_ip: tcp_packet.source_ip,
+                source_mac: tcp_packet.source_mac,
+                source_port: tcp_packet.source_port,
+                dest_ip: tcp_packet.dest_ip,
+                dest_mac: tcp_packet.dest_mac,
+                dest_port: tcp_packet.dest_port,
+                data_len: 0,
+                seq: tcp_packet.seq,
+                ack: tcp_packet.ack,
+                flags: tcp_packet.flags,
+                win: tcp_packet.win,
+                urg: tcp_packet.urg,
+                data: &[],
+            });
+        }
+    })
+}
+
+// accept a queued request, return false if there is none
+pub fn accept_backlog(listen_index: usize, task: Arc<TaskControlBlock>) -> bool {
+    let syn = LISTEN_TABLE.exclusive_session(|listen_table| {
+        listen_table[listen_index]
+            .as_mut()
+            .and_then(|x| x.backlog.pop_front())
+    });
+    match syn {
+        Some(tcp_packet) => {
+            accept_connection(tcp_packet.dest_port, &tcp_packet, task);
+            let mut reply_packet = tcp_packet.ack();
+            reply_packet.flags = TcpFlags::S | TcpFlags::A;
+            NET_DEVICE.transmit(&reply_packet.build_data());
+            true
+        }
+        None => false,
+    }
+}
+
 pub fn accept_connection(_port: u16, tcp_packet: &TCPPacket, task: Arc<TaskControlBlock>) {
     let process = task.process.upgrade().unwrap();
-    let mut inner = process.inner_exclusive_access();
-    let fd = inner.alloc_fd();
 
     let tcp_socket = TCP::new(
         tcp_packet.source_ip,
@@ -101,10 +168,14 @@ pub fn accept_connection(_port: u16, tcp_packet: &TCPPacket, task: Arc<TaskContr
         tcp_packet.ack,
     );
 
-    inner.fd_table[fd] = Some(Arc::new(tcp_socket));
+    // EMFILE if the descriptors run out
+    let fd = match process.fd_table.write().alloc(Arc::new(tcp_socket)) {
+        Some(fd) => fd as isize,
+        None => SysError::TooManyFiles.code(),
+    };
 
     let cx = task.inner_exclusive_access().get_trap_cx();
-    cx.x[10] = fd;
+    cx.x[10] = fd as usize;
 }
 
 // store in the fd_table, delete the listen table when close the application.
@@ -114,6 +185,10 @@ impl PortFd {
     pub fn new(port_index: usize) -> Self {
         PortFd(port_index)
     }
+
+    pub fn port_index(&self) -> usize {
+        self.0
+    }
 }
 
 impl Drop for PortFd {
@@ -138,4 +213,27 @@ impl File for PortFd {
     fn write(&self, _buf: crate::mm::UserBuffer) -> usize {
         0
     }
+
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        let pending = LISTEN_TABLE.exclusive_access()[self.0]
+            .as_ref()
+            .map_or(false, |x| !x.backlog.is_empty());
+        if pending {
+            events & PollEvents::POLLIN
+        } else {
+            PollEvents::empty()
+        }
+    }
+
+    fn add_ready_hook(&self, hook: ReadyHook) -> Option<usize> {
+        Some(add_net_hook(hook))
+    }
+
+    fn remove_ready_hook(&self, id: usize) {
+        remove_net_hook(id);
+    }
+
+    fn is_socket(&self) -> bool {
+        true
+    }
 }
@@ -3,6 +3,7 @@ use alloc::vec::Vec;
 use lazy_static::lazy_static;
 use lose_net_stack::IPv4;
 
+use super::start_netd;
 use crate::sync::UPIntrFreeCell;
 
 // TODO: specify the protocol, TCP or UDP
@@ -13,6 +14,16 @@ pub struct Socket {
     pub buffers: VecDeque<Vec<u8>>, // datas
     pub seq: u32,
     pub ack: u32,
+    pub state: SocketState,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SocketState {
+    /// waiting for the SYN+ACK of an active tcp open
+    Connecting,
+    Established,
+    /// the connecting request is reset by the peer
+    Refused,
 }
 
 lazy_static! {
@@ -60,10 +71,59 @@ pub fn get_socket(raddr: IPv4, lport: u16, rport: u16) -> Option<usize> {
     None
 }
 
+/// Find the socket for the packet, a socket on `lport` waiting for any peer
+/// (remote address 0.0.0.0 and port 0) is bound to the peer of the first packet.
+pub fn get_socket_or_bind(raddr: IPv4, lport: u16, rport: u16) -> Option<usize> {
+    if let Some(index) = get_socket(raddr, lport, rport) {
+        return Some(index);
+    }
+    let any_addr = IPv4::from_u32(0);
+    let mut socket_table = SOCKET_TABLE.exclusive_access();
+    let index = socket_table.iter().position(|sock| {
+        sock.as_ref().map_or(false, |x| {
+            x.raddr == any_addr && x.lport == lport && x.rport == 0
+        })
+    })?;
+    let sock = socket_table[index].as_mut().unwrap();
+    sock.raddr = raddr;
+    sock.rport = rport;
+    Some(index)
+}
+
+/// get the remote address and port by socket index
+pub fn get_peer(index: usize) -> (IPv4, u16) {
+    let socket_table = SOCKET_TABLE.exclusive_access();
+
+    assert!(socket_table.len() > index);
+    assert!(socket_table[index].is_some());
+
+    let sock = socket_table[index].as_ref().unwrap();
+    (sock.raddr, sock.rport)
+}
+
+pub fn get_state(index: usize) -> SocketState {
+    let socket_table = SOCKET_TABLE.exclusive_access();
+
+    assert!(socket_table.len() > index);
+    assert!(socket_table[index].is_some());
+
+    socket_table[index].as_ref().unwrap().state
+}
+
+pub fn set_state(index: usize, state: SocketState) {
+    let mut socket_table = SOCKET_TABLE.exclusive_access();
+
+    assert!(socket_table.len() > index);
+    assert!(socket_table[index].is_some());
+
+    socket_table[index].as_mut().unwrap().state = state;
+}
+
 pub fn add_socket(raddr: IPv4, lport: u16, rport: u16) -> Option<usize> {
     if get_socket(raddr, lport, rport).is_some() {
         return None;
     }
+    start_netd();
 
     let mut socket_table = SOCKET_TABLE.exclusive_access();
     let mut index = usize::MAX;
@@ -81,6 +141,7 @@ pub fn add_socket(raddr: IPv4, lport: u16, rport: u16) -> Option<usize> {
         buffers: VecDeque::new(),
         seq: 0,
         ack: 0,
+        state: SocketState::Established,
     };
 
     if index == usize::MAX {
@@ -121,3 +182,12 @@ pub fn pop_data(index: usize) -> Option<Vec<u8>> {
 
     socket_table[index].as_mut().unwrap().buffers.pop_front()
 }
+
+pub fn has_data(index: usize) -> bool {
+    let socket_table = SOCKET_TABLE.exclusive_access();
+
+    assert!(socket_table.len() > index);
+    assert!(socket_table[index].is_some());
+
+    !socket_table[index].as_ref().unwrap().buffers.is_empty()
+}
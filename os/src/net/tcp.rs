@@ -4,14 +4,18 @@ use lose_net_stack::IPv4;
 use lose_net_stack::MacAddress;
 use lose_net_stack::TcpFlags;
 
-use crate::{drivers::NET_DEVICE, fs::File};
+use crate::{
+    drivers::NET_DEVICE,
+    fs::{File, PollEvents, ReadyHook},
+};
 
-use super::socket::get_s_a_by_index;
+use super::socket::{get_s_a_by_index, get_state, set_s_a_by_index, set_state, SocketState};
 use super::{
-    net_interrupt_handler,
-    socket::{add_socket, pop_data, remove_socket},
-    LOSE_NET_STACK,
+    add_net_hook, remove_net_hook,
+    socket::{add_socket, has_data, pop_data, remove_socket},
+    wait_for_packets, LOSE_NET_STACK,
 };
+use crate::timer::get_time;
 
 // add tcp packet info to this structure
 pub struct TCP {
@@ -36,6 +40,52 @@ impl TCP {
             socket_index: index,
         }
     }
+
+    /// Connect to the target actively, return None if the port is in use,
+    /// the request is refused, or the task stops waiting for the answer for
+    /// a signal.
+    pub fn connect(target: IPv4, sport: u16, dport: u16) -> Option<Self> {
+        let index = add_socket(target, sport, dport)?;
+        let tcp = Self {
+            target,
+            sport,
+            dport,
+            seq: get_time() as u32,
+            ack: 0,
+            socket_index: index,
+        };
+        set_state(index, SocketState::Connecting);
+
+        let syn_data = {
+            let lose_net_stack = LOSE_NET_STACK.0.exclusive_access();
+            TCPPacket {
+                source_ip: lose_net_stack.ip,
+                source_mac: lose_net_stack.mac,
+                source_port: sport,
+                dest_ip: target,
+                dest_mac: MacAddress::new([0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+                dest_port: dport,
+                data_len: 0,
+                seq: tcp.seq,
+                ack: 0,
+                flags: TcpFlags::S,
+                win: 65535,
+                urg: 0,
+                data: &[],
+            }
+            .build_data()
+        };
+        NET_DEVICE.transmit(&syn_data);
+
+        // the socket is removed when tcp is dropped
+        if !wait_for_packets(|| get_state(index) != SocketState::Connecting) {
+            return None;
+        }
+        match get_state(index) {
+            SocketState::Established => Some(tcp),
+            _ => None,
+        }
+    }
 }
 
 impl File for TCP {
@@ -47,27 +97,29 @@ impl File for TCP {
         true
     }
 
+    /// Nothing is read if the task stops waiting for a signal.
     fn read(&self, mut buf: crate::mm::UserBuffer) -> usize {
-        loop {
-            if let Some(data) = pop_data(self.socket_index) {
-                let data_len = data.len();
-                let mut left = 0;
-                for i in 0..buf.buffers.len() {
-                    let buffer_i_len = buf.buffers[i].len().min(data_len - left);
-
-                    buf.buffers[i][..buffer_i_len]
-                        .copy_from_slice(&data[left..(left + buffer_i_len)]);
-
-                    left += buffer_i_len;
-                    if left == data_len {
-                        break;
-                    }
-                }
-                return left;
-            } else {
-                net_interrupt_handler();
+        let mut data = None;
+        if !wait_for_packets(|| {
+            data = pop_data(self.socket_index);
+            data.is_some()
+        }) {
+            return 0;
+        }
+        let data = data.unwrap();
+        let data_len = data.len();
+        let mut left = 0;
+        for i in 0..buf.buffers.len() {
+            let buffer_i_len = buf.buffers[i].len().min(data_len - left);
+
+            buf.buffers[i][..buffer_i_len].copy_from_slice(&data[left..(left + buffer_i_len)]);
+
+            left += buffer_i_len;
+            if left == data_len {
+                break;
             }
         }
+        left
     }
 
     fn write(&self, buf: crate::mm::UserBuffer) -> usize {
@@ -102,8 +154,31 @@ impl File for TCP {
             data: data.as_ref(),
         };
         NET_DEVICE.transmit(&tcp_packet.build_data());
+        // advance the sequence number, so that successive writes are not
+        // taken as retransmissions
+        set_s_a_by_index(self.socket_index, ack, seq.wrapping_add(len as u32));
         len
     }
+
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        let mut revents = PollEvents::POLLOUT;
+        if has_data(self.socket_index) {
+            revents |= PollEvents::POLLIN;
+        }
+        events & revents
+    }
+
+    fn add_ready_hook(&self, hook: ReadyHook) -> Option<usize> {
+        Some(add_net_hook(hook))
+    }
+
+    fn remove_ready_hook(&self, id: usize) {
+        remove_net_hook(id);
+    }
+
+    fn is_socket(&self) -> bool {
+        true
+    }
 }
 
 impl Drop for TCP {
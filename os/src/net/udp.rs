@@ -1,8 +1,8 @@
-use super::net_interrupt_handler;
-use super::socket::{add_socket, pop_data, remove_socket};
+use super::socket::{add_socket, get_peer, has_data, pop_data, remove_socket};
 use super::LOSE_NET_STACK;
 use super::NET_DEVICE;
-use crate::fs::File;
+use super::{add_net_hook, remove_net_hook, wait_for_packets};
+use crate::fs::{File, PollEvents, ReadyHook};
 use alloc::vec;
 use lose_net_stack::packets::udp::UDPPacket;
 use lose_net_stack::IPv4;
@@ -37,27 +37,29 @@ impl File for UDP {
         true
     }
 
+    /// Nothing is read if the task stops waiting for a signal.
     fn read(&self, mut buf: crate::mm::UserBuffer) -> usize {
-        loop {
-            if let Some(data) = pop_data(self.socket_index) {
-                let data_len = data.len();
-                let mut left = 0;
-                for i in 0..buf.buffers.len() {
-                    let buffer_i_len = buf.buffers[i].len().min(data_len - left);
-
-                    buf.buffers[i][..buffer_i_len]
-                        .copy_from_slice(&data[left..(left + buffer_i_len)]);
-
-                    left += buffer_i_len;
-                    if left == data_len {
-                        break;
-                    }
-                }
-                return left;
-            } else {
-                net_interrupt_handler();
+        let mut data = None;
+        if !wait_for_packets(|| {
+            data = pop_data(self.socket_index);
+            data.is_some()
+        }) {
+            return 0;
+        }
+        let data = data.unwrap();
+        let data_len = data.len();
+        let mut left = 0;
+        for i in 0..buf.buffers.len() {
+            let buffer_i_len = buf.buffers[i].len().min(data_len - left);
+
+            buf.buffers[i][..buffer_i_len].copy_from_slice(&data[left..(left + buffer_i_len)]);
+
+            left += buffer_i_len;
+            if left == data_len {
+                break;
             }
         }
+        left
     }
 
     fn write(&self, buf: crate::mm::UserBuffer) -> usize {
@@ -73,19 +75,42 @@ impl File for UDP {
 
         let len = data.len();
 
+        // the peer of a socket waiting for any peer is known after receiving
+        let (target, dport) = get_peer(self.socket_index);
+
         let udp_packet = UDPPacket::new(
             lose_net_stack.ip,
             lose_net_stack.mac,
             self.sport,
-            self.target,
+            target,
             MacAddress::new([0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
-            self.dport,
+            dport,
             len,
             data.as_ref(),
         );
         NET_DEVICE.transmit(&udp_packet.build_data());
         len
     }
+
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        let mut revents = PollEvents::POLLOUT;
+        if has_data(self.socket_index) {
+            revents |= PollEvents::POLLIN;
+        }
+        events & revents
+    }
+
+    fn add_ready_hook(&self, hook: ReadyHook) -> Option<usize> {
+        Some(add_net_hook(hook))
+    }
+
+    fn remove_ready_hook(&self, id: usize) {
+        remove_net_hook(id);
+    }
+
+    fn is_socket(&self) -> bool {
+        true
+    }
 }
 
 impl Drop for UDP {
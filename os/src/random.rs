@@ -0,0 +1,117 @@
+//! The random bytes of `/dev/random`, from a ChaCha20 generator like the one
+//! of Linux. It is seeded with the wall clock and the counter at boot, and
+//! the times of the device interrupts, whose jitter cannot be told from
+//! outside, are mixed in before each request. The key is replaced after
+//! each request, so the bytes handed out cannot be found from the state
+//! afterwards.
+
+use crate::sync::UPIntrFreeCell;
+use crate::timer::{get_realtime_ns, get_time};
+use core::sync::atomic::{AtomicU64, Ordering};
+use lazy_static::*;
+
+/// "expand 32-byte k"
+const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+/// The nonces of the blocks of output and of those mixing samples in, so
+/// that the two never share a block.
+const OUTPUT_NONCE: u64 = 0;
+const MIX_NONCE: u64 = 1;
+
+/// The times of the interrupts since the last request, folded together.
+static INTERRUPT_POOL: AtomicU64 = AtomicU64::new(0);
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// A block of ChaCha20 with the 64-bit counter and nonce of the original.
+fn chacha20_block(key: &[u32; 8], counter: u64, nonce: u64) -> [u32; 16] {
+    let mut input = [0u32; 16];
+    input[..4].copy_from_slice(&CONSTANTS);
+    input[4..12].copy_from_slice(key);
+    input[12] = counter as u32;
+    input[13] = (counter >> 32) as u32;
+    input[14] = nonce as u32;
+    input[15] = (nonce >> 32) as u32;
+    let mut state = input;
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+    for (word, input) in state.iter_mut().zip(input) {
+        *word = word.wrapping_add(input);
+    }
+    state
+}
+
+struct Crng {
+    key: [u32; 8],
+}
+
+impl Crng {
+    /// Mix `sample` into the key, which becomes a block over it keyed by the
+    /// old one.
+    fn mix(&mut self, sample: u64) {
+        let block = chacha20_block(&self.key, sample, MIX_NONCE);
+        self.key.copy_from_slice(&block[..8]);
+    }
+
+    /// The first block is the next key, the bytes are from the blocks after.
+    fn fill(&mut self, buf: &mut [u8]) {
+        for (counter, chunk) in buf.chunks_mut(64).enumerate() {
+            let block = chacha20_block(&self.key, counter as u64 + 1, OUTPUT_NONCE);
+            for (bytes, word) in chunk.chunks_mut(4).zip(block) {
+                bytes.copy_from_slice(&word.to_le_bytes()[..bytes.len()]);
+            }
+        }
+        let block = chacha20_block(&self.key, 0, OUTPUT_NONCE);
+        self.key.copy_from_slice(&block[..8]);
+    }
+}
+
+lazy_static! {
+    static ref CRNG: UPIntrFreeCell<Crng> = {
+        let mut crng = Crng { key: [0; 8] };
+        crng.mix(get_realtime_ns() as u64);
+        crng.mix(get_time() as u64);
+        unsafe { UPIntrFreeCell::new(crng) }
+    };
+}
+
+/// Fold the time of a device interrupt into the pool, cheap enough for each
+/// of them. A sample may be lost when two harts do it at once, which does
+/// no harm.
+pub fn add_interrupt_randomness() {
+    let pool = INTERRUPT_POOL.load(Ordering::Relaxed);
+    INTERRUPT_POOL.store(pool.rotate_left(7) ^ get_time() as u64, Ordering::Relaxed);
+}
+
+/// Fill `buf` with random bytes.
+pub fn fill_random(buf: &mut [u8]) {
+    let mut crng = CRNG.exclusive_access();
+    crng.mix(INTERRUPT_POOL.swap(0, Ordering::Relaxed) ^ get_time() as u64);
+    crng.fill(buf);
+}
+
+/// Mix bytes written to `/dev/random` in, which only adds to what is there.
+pub fn mix_random(bytes: &[u8]) {
+    let mut crng = CRNG.exclusive_access();
+    for chunk in bytes.chunks(8) {
+        let mut sample = [0u8; 8];
+        sample[..chunk.len()].copy_from_slice(chunk);
+        crng.mix(u64::from_le_bytes(sample));
+    }
+}
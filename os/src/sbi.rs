@@ -3,6 +3,12 @@ pub fn set_timer(timer: usize) {
     sbi_rt::set_timer(timer as _);
 }
 
+/// Write a byte with the SBI, which takes no lock of the kernel.
+pub fn console_putchar(c: u8) {
+    #[allow(deprecated)]
+    sbi_rt::legacy::console_putchar(c as usize);
+}
+
 /// use sbi call to shutdown the kernel
 pub fn shutdown(failure: bool) -> ! {
     use sbi_rt::{system_reset, NoReason, Shutdown, SystemFailure};
@@ -13,3 +19,93 @@ pub fn shutdown(failure: bool) -> ! {
     }
     unreachable!()
 }
+
+/// Reboot the machine, which never returns unless the SBI fails.
+pub fn reboot() -> ! {
+    use sbi_rt::{system_reset, ColdReboot, NoReason};
+    system_reset(ColdReboot, NoReason);
+    panic!("the SBI fails to reboot")
+}
+
+/// Start `hart_id` at `start_addr` in the supervisor mode, with `a0` set to
+/// its id and `a1` to `opaque`. Return false if the SBI refuses.
+pub fn hart_start(hart_id: usize, start_addr: usize, opaque: usize) -> bool {
+    sbi_rt::hart_start(hart_id, start_addr, opaque).error == 0
+}
+
+/// Stop the current hart, which never returns unless the SBI fails.
+pub fn hart_stop() -> ! {
+    sbi_rt::hart_stop();
+    panic!("the SBI fails to stop the hart")
+}
+
+/// Whether `hart_id` is started or on its way to be stopped.
+pub fn hart_running(hart_id: usize) -> bool {
+    const HART_STATE_STARTED: usize = 0;
+    const HART_STATE_STOP_PENDING: usize = 3;
+    let ret = sbi_rt::hart_get_status(hart_id);
+    ret.error == 0 && matches!(ret.value, HART_STATE_STARTED | HART_STATE_STOP_PENDING)
+}
+
+/// Raise the supervisor software interrupt of `hart_id`.
+pub fn send_ipi(hart_id: usize) {
+    sbi_rt::send_ipi(1, hart_id);
+}
+
+/// Call the SBI directly for the extensions `sbi_rt` does not cover, return
+/// the error and the value.
+fn sbi_call(eid: usize, fid: usize, args: [usize; 3]) -> (isize, usize) {
+    let (error, value);
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("a0") args[0] => error,
+            inlateout("a1") args[1] => value,
+            in("a2") args[2],
+            in("a6") fid,
+            in("a7") eid,
+        );
+    }
+    (error, value)
+}
+
+const EID_BASE: usize = 0x10;
+const EID_DBTR: usize = 0x4442_5452;
+
+/// Whether the SBI implements the debug triggers extension.
+pub fn debug_triggers_available() -> bool {
+    const PROBE_EXTENSION: usize = 3;
+    let (error, value) = sbi_call(EID_BASE, PROBE_EXTENSION, [EID_DBTR, 0, 0]);
+    error == 0 && value != 0
+}
+
+/// The number of triggers which can be of the type in `tdata1`, 0 for any.
+pub fn debug_num_triggers(tdata1: usize) -> usize {
+    let (error, value) = sbi_call(EID_DBTR, 0, [tdata1, 0, 0]);
+    if error == 0 {
+        value
+    } else {
+        0
+    }
+}
+
+/// Set the memory shared with the SBI for the triggers of the current hart.
+pub fn debug_set_shmem(phys_addr: usize) -> bool {
+    sbi_call(EID_DBTR, 1, [phys_addr, 0, 0]).0 == 0
+}
+
+/// Install `count` triggers described in the shared memory, whose indices
+/// are written back to it.
+pub fn debug_install_triggers(count: usize) -> bool {
+    sbi_call(EID_DBTR, 3, [count, 0, 0]).0 == 0
+}
+
+/// Uninstall the triggers in `mask` from `base`.
+pub fn debug_uninstall_triggers(base: usize, mask: usize) -> bool {
+    sbi_call(EID_DBTR, 5, [base, mask, 0]).0 == 0
+}
+
+/// Disable the triggers in `mask` from `base` without uninstalling them.
+pub fn debug_disable_triggers(base: usize, mask: usize) -> bool {
+    sbi_call(EID_DBTR, 7, [base, mask, 0]).0 == 0
+}
@@ -0,0 +1,85 @@
+//! Suspend to RAM with the SBI system suspend extension.
+//!
+//! The hart resumes at `__resume` with paging off, which restores the context
+//! saved by `__suspend` and returns from it. Devices keep their state on
+//! QEMU, so only the UART and the timer are initialized again.
+
+use crate::config::BOOT_HART;
+use crate::drivers::chardev::{CharDevice, UART};
+use crate::hart::{cpu_down, cpu_up, hart_id, online_harts};
+use crate::timer::set_next_trigger;
+use core::arch::global_asm;
+use riscv::register::{sie, sstatus};
+
+global_asm!(include_str!("suspend.S"));
+
+/// ra, sp, s0~s11 and the CSRs lost on resume.
+#[repr(C)]
+struct SuspendContext {
+    ra: usize,
+    sp: usize,
+    s: [usize; 12],
+    satp: usize,
+    stvec: usize,
+    sscratch: usize,
+    sie: usize,
+}
+
+/// It is in the identically mapped kernel image, so `__resume` can use it
+/// before paging is on.
+static mut SUSPEND_CONTEXT: SuspendContext = SuspendContext {
+    ra: 0,
+    sp: 0,
+    s: [0; 12],
+    satp: 0,
+    stvec: 0,
+    sscratch: 0,
+    sie: 0,
+};
+
+extern "C" {
+    fn __suspend(cx: *mut SuspendContext) -> isize;
+}
+
+/// the SBI error for a request refused
+const SBI_ERR_DENIED: isize = -4;
+
+/// Suspend the system until an interrupt wakes it up, return the SBI error
+/// if it cannot be suspended. Only the boot hart suspends it, since the
+/// others are stopped and started by the boot hart.
+pub fn suspend() -> Result<(), isize> {
+    if hart_id() != BOOT_HART {
+        return Err(SBI_ERR_DENIED);
+    }
+    let sie_enabled = sstatus::read().sie();
+    unsafe {
+        sstatus::clear_sie();
+        // only devices wake the system up
+        sie::clear_stimer();
+    }
+    // the other harts must be stopped
+    let parked = online_harts();
+    for &hart_id in parked.iter() {
+        cpu_down(hart_id);
+    }
+    let ret = unsafe { __suspend(core::ptr::addr_of_mut!(SUSPEND_CONTEXT)) };
+    if ret == 0 {
+        UART.init();
+    }
+    for &hart_id in parked.iter() {
+        cpu_up(hart_id);
+    }
+    unsafe {
+        sie::set_stimer();
+    }
+    set_next_trigger();
+    if sie_enabled {
+        unsafe {
+            sstatus::set_sie();
+        }
+    }
+    match ret {
+        0 => Ok(()),
+        error => Err(error),
+    }
+}
@@ -0,0 +1,115 @@
+use crate::sync::UPIntrFreeCell;
+use crate::task::{
+    block_current_task, clear_wake_hook, current_task, schedule, set_wake_hook, wakeup_task,
+    TaskControlBlock, WakeReason,
+};
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::future::Future;
+use core::pin::{pin, Pin};
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+struct TaskWaker {
+    /// None before tasks are scheduled
+    task: Option<Arc<TaskControlBlock>>,
+    /// whether it is woken up since the last poll
+    woken: UPIntrFreeCell<bool>,
+}
+
+impl TaskWaker {
+    fn wake(&self) {
+        *self.woken.exclusive_access() = true;
+        if let Some(task) = self.task.as_ref() {
+            wakeup_task(Arc::clone(task));
+        }
+    }
+
+    /// The waker is built by hand, as `Wake` needs `Send + Sync` which the
+    /// kernel structures do not promise.
+    fn into_waker(self: Arc<Self>) -> Waker {
+        unsafe { Waker::from_raw(RawWaker::new(Arc::into_raw(self) as *const (), &VTABLE)) }
+    }
+}
+
+static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake_by_ref, drop_waker);
+
+unsafe fn clone(data: *const ()) -> RawWaker {
+    Arc::increment_strong_count(data as *const TaskWaker);
+    RawWaker::new(data, &VTABLE)
+}
+
+unsafe fn wake(data: *const ()) {
+    Arc::from_raw(data as *const TaskWaker).wake();
+}
+
+unsafe fn wake_by_ref(data: *const ()) {
+    (*(data as *const TaskWaker)).wake();
+}
+
+unsafe fn drop_waker(data: *const ()) {
+    drop(Arc::from_raw(data as *const TaskWaker));
+}
+
+/// Run a future in a syscall to completion. The current task is blocked
+/// while the future is pending, other tasks run meanwhile, and it is resumed
+/// once the waker is woken up, usually by the interrupt handler of a driver.
+///
+/// Before tasks are scheduled, the future is polled again and again.
+pub fn block_on_yielding<F: Future>(future: F) -> F::Output {
+    block_on(future, false).unwrap()
+}
+
+/// Like `block_on_yielding`, but give up and return None once the current
+/// task is exiting, killed or left behind by its process, or interrupted by
+/// a signal, see `current_wake_reason`. The future is dropped then, so the
+/// driver forgets its wakers.
+pub fn block_on_cancellable<F: Future>(future: F) -> Option<F::Output> {
+    block_on(future, true)
+}
+
+/// Poll a future once without blocking, for a driver starting a request it
+/// waits for later. Nothing is woken up when it can make progress.
+pub fn poll_once<F: Future + Unpin>(future: &mut F) -> Poll<F::Output> {
+    let task_waker = Arc::new(TaskWaker {
+        task: None,
+        woken: unsafe { UPIntrFreeCell::new(false) },
+    });
+    let waker = task_waker.into_waker();
+    Pin::new(future).poll(&mut Context::from_waker(&waker))
+}
+
+fn block_on<F: Future>(future: F, cancellable: bool) -> Option<F::Output> {
+    let mut future = pin!(future);
+    let task_waker = Arc::new(TaskWaker {
+        task: current_task(),
+        woken: unsafe { UPIntrFreeCell::new(false) },
+    });
+    let waker = Arc::clone(&task_waker).into_waker();
+    let mut cx = Context::from_waker(&waker);
+    let cancellable = cancellable && task_waker.task.is_some();
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return Some(output);
+        }
+        if cancellable {
+            let waker = waker.clone();
+            if !set_wake_hook(Box::new(move || waker.wake())) {
+                return None;
+            }
+        }
+        let mut woken = task_waker.woken.exclusive_access();
+        if !*woken && task_waker.task.is_some() {
+            // a wake up from the interrupt handler is never lost, since it
+            // waits until the task is blocked
+            let task_cx_ptr = block_current_task();
+            drop(woken);
+            schedule(task_cx_ptr);
+            woken = task_waker.woken.exclusive_access();
+        }
+        *woken = false;
+        drop(woken);
+        if cancellable && clear_wake_hook() != WakeReason::Woken {
+            return None;
+        }
+    }
+}
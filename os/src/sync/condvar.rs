@@ -1,7 +1,7 @@
-use crate::sync::{Mutex, UPIntrFreeCell};
+use crate::sync::{Mutex, MutexError, UPIntrFreeCell};
 use crate::task::{
-    block_current_and_run_next, block_current_task, current_task, wakeup_task, TaskContext,
-    TaskControlBlock,
+    block_current_and_release_interruptible, block_current_task, current_task, current_wake_reason,
+    suspend_current_and_run_next, wakeup_task, TaskContext, TaskControlBlock, WakeReason,
 };
 use alloc::{collections::VecDeque, sync::Arc};
 
@@ -47,12 +47,45 @@ impl Condvar {
         block_current_task()
     }
 
-    pub fn wait_with_mutex(&self, mutex: Arc<dyn Mutex>) {
-        mutex.unlock();
-        self.inner.exclusive_session(|inner| {
-            inner.wait_queue.push_back(current_task().unwrap());
+    /// Stop waiting after the time reaches `expire_ms` if it is not None, or
+    /// once the task is to stop waiting, see `current_wake_reason`. The
+    /// mutex is locked again in any case except when it cannot be unlocked
+    /// or the task is exiting.
+    pub fn wait_with_mutex(
+        &self,
+        mutex: Arc<dyn Mutex>,
+        expire_ms: Option<usize>,
+    ) -> Result<(), MutexError> {
+        mutex.unlock()?;
+        let task = current_task().unwrap();
+        let mut inner = self.inner.exclusive_access();
+        inner.wait_queue.push_back(Arc::clone(&task));
+        let expire_ns = expire_ms.map(|expire_ms| expire_ms.saturating_mul(1_000_000));
+        let reason = block_current_and_release_interruptible(inner, expire_ns);
+        // not signaled if still waiting
+        let waiting = self.inner.exclusive_session(|inner| {
+            match inner
+                .wait_queue
+                .iter()
+                .position(|waiting| Arc::ptr_eq(waiting, &task))
+            {
+                Some(pos) => inner.wait_queue.remove(pos).is_some(),
+                None => false,
+            }
         });
-        block_current_and_run_next();
-        mutex.lock();
+        // a signal pending does not stop it, the handler runs with it locked
+        loop {
+            match mutex.lock(None) {
+                Err(MutexError::Interrupted) if current_wake_reason() == WakeReason::Signalled => {
+                    suspend_current_and_run_next()
+                }
+                result => break result,
+            }
+        }?;
+        match (waiting, reason) {
+            (true, WakeReason::Woken) if expire_ms.is_some() => Err(MutexError::TimedOut),
+            (true, WakeReason::Signalled | WakeReason::Exiting) => Err(MutexError::Interrupted),
+            _ => Ok(()),
+        }
     }
 }
@@ -0,0 +1,106 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Bookkeeping of a kind of resources (mutexes or semaphores) of a process for
+/// the banker's algorithm, threads are indexed by tid.
+///
+/// NOTICE: resources released by threads not holding them (like semaphores
+/// used for synchronization) are not predictable, waiting for them may be
+/// reported as a deadlock.
+#[derive(Default)]
+pub struct DeadlockDetector {
+    available: Vec<usize>,
+    allocation: Vec<Vec<usize>>,
+    need: Vec<Vec<usize>>,
+}
+
+impl DeadlockDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register resource `id` with `count` units.
+    pub fn add_resource(&mut self, id: usize, count: usize) {
+        if id >= self.available.len() {
+            self.available.resize(id + 1, 0);
+        }
+        self.available[id] = count;
+        for row in self.allocation.iter_mut().chain(self.need.iter_mut()) {
+            if id < row.len() {
+                row[id] = 0;
+            }
+        }
+    }
+
+    fn entry(table: &mut Vec<Vec<usize>>, tid: usize, id: usize) -> &mut usize {
+        if tid >= table.len() {
+            table.resize(tid + 1, Vec::new());
+        }
+        let row = &mut table[tid];
+        if id >= row.len() {
+            row.resize(id + 1, 0);
+        }
+        &mut row[id]
+    }
+
+    fn get(table: &[Vec<usize>], tid: usize, id: usize) -> usize {
+        table
+            .get(tid)
+            .and_then(|row| row.get(id))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Whether all threads can finish with the available resources.
+    fn is_safe(&self) -> bool {
+        let threads = self.allocation.len().max(self.need.len());
+        let mut work = self.available.clone();
+        let mut finish = vec![false; threads];
+        loop {
+            let next = (0..threads).find(|&tid| {
+                !finish[tid] && (0..work.len()).all(|id| Self::get(&self.need, tid, id) <= work[id])
+            });
+            match next {
+                Some(tid) => {
+                    finish[tid] = true;
+                    for (id, units) in work.iter_mut().enumerate() {
+                        *units += Self::get(&self.allocation, tid, id);
+                    }
+                }
+                None => return finish.iter().all(|&finished| finished),
+            }
+        }
+    }
+
+    /// Record that thread `tid` waits for a unit of resource `id`, return
+    /// false and record nothing if it leads to an unsafe state.
+    pub fn request(&mut self, tid: usize, id: usize, check: bool) -> bool {
+        *Self::entry(&mut self.need, tid, id) += 1;
+        if check && !self.is_safe() {
+            *Self::entry(&mut self.need, tid, id) -= 1;
+            return false;
+        }
+        true
+    }
+
+    /// The request of thread `tid` for resource `id` is satisfied if
+    /// `acquired`, otherwise it has given up.
+    pub fn finish_request(&mut self, tid: usize, id: usize, acquired: bool) {
+        let need = Self::entry(&mut self.need, tid, id);
+        *need = need.saturating_sub(1);
+        if acquired {
+            *Self::entry(&mut self.allocation, tid, id) += 1;
+            self.available[id] = self.available[id].saturating_sub(1);
+        }
+    }
+
+    pub fn holds(&self, tid: usize, id: usize) -> bool {
+        Self::get(&self.allocation, tid, id) > 0
+    }
+
+    pub fn release(&mut self, tid: usize, id: usize) {
+        let allocation = Self::entry(&mut self.allocation, tid, id);
+        *allocation = allocation.saturating_sub(1);
+        self.available[id] += 1;
+    }
+}
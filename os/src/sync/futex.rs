@@ -0,0 +1,98 @@
+//! Futexes, the slow path of locks in the user memory: a thread which finds
+//! a lock taken waits on its word, keyed by the physical address so threads
+//! of other processes sharing the page wait on the same one.
+
+use crate::sync::UPIntrFreeCell;
+use crate::task::{
+    block_current_task_interruptible, clear_wake_hook, current_task, schedule, set_wake_hook,
+    wakeup_task, TaskControlBlock, WakeReason,
+};
+use crate::timer::{add_timer, cancel_timer};
+use alloc::{boxed::Box, collections::VecDeque, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicU32, Ordering};
+use lazy_static::*;
+
+const FUTEX_BUCKETS: usize = 64;
+
+type FutexBucket = UPIntrFreeCell<VecDeque<(usize, Arc<TaskControlBlock>)>>;
+
+lazy_static! {
+    /// The waiters of the words hashed to each bucket, with their addresses.
+    static ref FUTEX_QUEUES: Vec<FutexBucket> = (0..FUTEX_BUCKETS)
+        .map(|_| unsafe { UPIntrFreeCell::new(VecDeque::new()) })
+        .collect();
+}
+
+fn bucket(addr: usize) -> &'static FutexBucket {
+    // the words of a page spread over the buckets
+    &FUTEX_QUEUES[(addr >> 2) % FUTEX_BUCKETS]
+}
+
+/// Why `futex_wait` returns.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FutexWait {
+    /// woken up by `futex_wake`
+    Woken,
+    /// the word is not the value expected already
+    Changed,
+    TimedOut,
+    /// it is to stop waiting, see `WakeReason`
+    Interrupted,
+}
+
+/// Wait on the word at the physical address `addr` until `futex_wake` of
+/// it, the time reaches `expire_ns` if any, or the task is to stop waiting.
+/// The word is checked to be `val` under the lock of the bucket, so a wake
+/// up after the user changes it is never lost.
+pub fn futex_wait(addr: usize, word: &AtomicU32, val: u32, expire_ns: Option<usize>) -> FutexWait {
+    let bucket = bucket(addr);
+    let mut queue = bucket.exclusive_access();
+    if word.load(Ordering::SeqCst) != val {
+        return FutexWait::Changed;
+    }
+    let task = current_task().unwrap();
+    let waiter = Arc::clone(&task);
+    if !set_wake_hook(Box::new(move || wakeup_task(waiter))) {
+        return FutexWait::Interrupted;
+    }
+    queue.push_back((addr, Arc::clone(&task)));
+    let timer = expire_ns.map(|expire_ns| add_timer(expire_ns, Arc::clone(&task)));
+    let task_cx_ptr = block_current_task_interruptible();
+    drop(queue);
+    schedule(task_cx_ptr);
+    if let Some(timer) = timer {
+        cancel_timer(timer);
+    }
+    // still queued unless woken up by `futex_wake`
+    let mut queue = bucket.exclusive_access();
+    let queued = queue
+        .iter()
+        .position(|(_, queued)| Arc::ptr_eq(queued, &task))
+        .map(|pos| queue.remove(pos))
+        .is_some();
+    drop(queue);
+    let reason = clear_wake_hook();
+    if !queued {
+        FutexWait::Woken
+    } else if reason != WakeReason::Woken {
+        FutexWait::Interrupted
+    } else {
+        FutexWait::TimedOut
+    }
+}
+
+/// Wake up at most `count` tasks waiting on the word at the physical
+/// address `addr`, the longest waiting first. Return how many are woken up.
+pub fn futex_wake(addr: usize, count: usize) -> usize {
+    let mut queue = bucket(addr).exclusive_access();
+    let mut woken = 0;
+    queue.retain(|(waiting, task)| {
+        if woken == count || *waiting != addr {
+            return true;
+        }
+        wakeup_task(Arc::clone(task));
+        woken += 1;
+        false
+    });
+    woken
+}
@@ -1,9 +1,22 @@
+mod block_on;
 mod condvar;
+mod deadlock;
+mod futex;
 mod mutex;
+mod rw;
 mod semaphore;
+mod spin;
 mod up;
+mod wait_queue;
 
+pub use block_on::{block_on_cancellable, block_on_yielding, poll_once};
 pub use condvar::Condvar;
-pub use mutex::{Mutex, MutexBlocking, MutexSpin};
-pub use semaphore::Semaphore;
+pub use deadlock::DeadlockDetector;
+pub use futex::{futex_wait, futex_wake, FutexWait};
+pub use mutex::{Mutex, MutexBlocking, MutexError, MutexSpin};
+pub use rw::{RwIntrFreeCell, RwIntrReadGuard, RwIntrWriteGuard};
+pub use semaphore::{Semaphore, SemaphoreError};
+#[allow(unused)]
+pub use spin::{SpinLock, SpinLockGuard, SpinLockIrqSave, SpinLockIrqSaveGuard};
 pub use up::{UPIntrFreeCell, UPIntrRefMut};
+pub use wait_queue::WaitQueue;
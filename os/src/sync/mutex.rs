@@ -1,44 +1,120 @@
 use super::UPIntrFreeCell;
 use crate::task::TaskControlBlock;
-use crate::task::{block_current_and_run_next, suspend_current_and_run_next};
+use crate::task::{
+    block_current_and_release_interruptible, current_wake_reason, suspend_current_and_run_next,
+    WakeReason,
+};
 use crate::task::{current_task, wakeup_task};
+use crate::timer::get_time_ms;
 use alloc::{collections::VecDeque, sync::Arc};
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum MutexError {
+    /// locking an error-checking mutex held by the current task
+    Deadlock,
+    /// unlocking an error-checking mutex not held by the current task
+    NotOwner,
+    TimedOut,
+    /// the task is signalled or exiting while it waits
+    Interrupted,
+    /// the mutex is locked, but its previous owner exited without unlocking
+    /// it, so the data protected may be inconsistent
+    OwnerDied,
+}
+
 pub trait Mutex: Sync + Send {
-    fn lock(&self);
-    fn unlock(&self);
+    /// Give up after the time reaches `expire_ms` if it is not None, or once
+    /// the task is to stop waiting, see `current_wake_reason`.
+    fn lock(&self, expire_ms: Option<usize>) -> Result<(), MutexError>;
+    fn unlock(&self) -> Result<(), MutexError>;
+    /// Release the mutex if it is held by `task` which has exited, return
+    /// whether it is held.
+    fn owner_exited(&self, task: &Arc<TaskControlBlock>) -> bool;
+}
+
+fn is_owner(owner: &Option<Arc<TaskControlBlock>>, task: &Arc<TaskControlBlock>) -> bool {
+    owner
+        .as_ref()
+        .map_or(false, |owner| Arc::ptr_eq(owner, task))
+}
+
+fn is_current(owner: &Option<Arc<TaskControlBlock>>) -> bool {
+    is_owner(owner, &current_task().unwrap())
+}
+
+/// Report the death of the previous owner only once.
+fn locked_result(owner_died: &mut bool) -> Result<(), MutexError> {
+    if core::mem::take(owner_died) {
+        Err(MutexError::OwnerDied)
+    } else {
+        Ok(())
+    }
 }
 
 pub struct MutexSpin {
-    locked: UPIntrFreeCell<bool>,
+    inner: UPIntrFreeCell<MutexSpinInner>,
+    errorcheck: bool,
+}
+
+pub struct MutexSpinInner {
+    /// the task holding the lock
+    owner: Option<Arc<TaskControlBlock>>,
+    owner_died: bool,
 }
 
 impl MutexSpin {
-    pub fn new() -> Self {
+    pub fn new(errorcheck: bool) -> Self {
         Self {
-            locked: unsafe { UPIntrFreeCell::new(false) },
+            inner: unsafe {
+                UPIntrFreeCell::new(MutexSpinInner {
+                    owner: None,
+                    owner_died: false,
+                })
+            },
+            errorcheck,
         }
     }
 }
 
 impl Mutex for MutexSpin {
-    fn lock(&self) {
+    fn lock(&self, expire_ms: Option<usize>) -> Result<(), MutexError> {
         loop {
-            let mut locked = self.locked.exclusive_access();
-            if *locked {
-                drop(locked);
-                suspend_current_and_run_next();
-                continue;
-            } else {
-                *locked = true;
-                return;
+            let mut inner = self.inner.exclusive_access();
+            if inner.owner.is_none() {
+                inner.owner = current_task();
+                return locked_result(&mut inner.owner_died);
+            }
+            if self.errorcheck && is_current(&inner.owner) {
+                return Err(MutexError::Deadlock);
             }
+            drop(inner);
+            if expire_ms.map_or(false, |expire_ms| get_time_ms() >= expire_ms) {
+                return Err(MutexError::TimedOut);
+            }
+            if current_wake_reason() != WakeReason::Woken {
+                return Err(MutexError::Interrupted);
+            }
+            suspend_current_and_run_next();
+        }
+    }
+
+    fn unlock(&self) -> Result<(), MutexError> {
+        let mut inner = self.inner.exclusive_access();
+        if self.errorcheck && !is_current(&inner.owner) {
+            return Err(MutexError::NotOwner);
         }
+        inner.owner = None;
+        Ok(())
     }
 
-    fn unlock(&self) {
-        let mut locked = self.locked.exclusive_access();
-        *locked = false;
+    fn owner_exited(&self, task: &Arc<TaskControlBlock>) -> bool {
+        let mut inner = self.inner.exclusive_access();
+        if !is_owner(&inner.owner, task) {
+            return false;
+        }
+        inner.owner = None;
+        inner.owner_died = true;
+        true
     }
 }
 
@@ -48,41 +124,96 @@ pub struct MutexBlocking {
 
 pub struct MutexBlockingInner {
     locked: bool,
+    /// the task holding the lock
+    owner: Option<Arc<TaskControlBlock>>,
+    owner_died: bool,
+    errorcheck: bool,
     wait_queue: VecDeque<Arc<TaskControlBlock>>,
 }
 
 impl MutexBlocking {
-    pub fn new() -> Self {
+    pub fn new(errorcheck: bool) -> Self {
         Self {
             inner: unsafe {
                 UPIntrFreeCell::new(MutexBlockingInner {
                     locked: false,
+                    owner: None,
+                    owner_died: false,
+                    errorcheck,
                     wait_queue: VecDeque::new(),
                 })
             },
         }
     }
+
+    /// Pass the lock to the first waiting task, or unlock it if none.
+    fn hand_over(mutex_inner: &mut MutexBlockingInner) {
+        if let Some(waking_task) = mutex_inner.wait_queue.pop_front() {
+            mutex_inner.owner = Some(Arc::clone(&waking_task));
+            wakeup_task(waking_task);
+        } else {
+            mutex_inner.locked = false;
+            mutex_inner.owner = None;
+        }
+    }
 }
 
 impl Mutex for MutexBlocking {
-    fn lock(&self) {
+    fn lock(&self, expire_ms: Option<usize>) -> Result<(), MutexError> {
         let mut mutex_inner = self.inner.exclusive_access();
-        if mutex_inner.locked {
-            mutex_inner.wait_queue.push_back(current_task().unwrap());
-            drop(mutex_inner);
-            block_current_and_run_next();
-        } else {
+        if !mutex_inner.locked {
             mutex_inner.locked = true;
+            mutex_inner.owner = current_task();
+            return locked_result(&mut mutex_inner.owner_died);
+        }
+        if mutex_inner.errorcheck && is_current(&mutex_inner.owner) {
+            return Err(MutexError::Deadlock);
+        }
+        let task = current_task().unwrap();
+        mutex_inner.wait_queue.push_back(Arc::clone(&task));
+        let expire_ns = expire_ms.map(|expire_ms| expire_ms.saturating_mul(1_000_000));
+        loop {
+            let reason = block_current_and_release_interruptible(mutex_inner, expire_ns);
+            // the lock is handed over to us if we are no longer waiting
+            mutex_inner = self.inner.exclusive_access();
+            let pos = match mutex_inner
+                .wait_queue
+                .iter()
+                .position(|waiting| Arc::ptr_eq(waiting, &task))
+            {
+                Some(pos) => pos,
+                None => break,
+            };
+            let error = if reason != WakeReason::Woken {
+                MutexError::Interrupted
+            } else if expire_ms.map_or(false, |expire_ms| get_time_ms() >= expire_ms) {
+                MutexError::TimedOut
+            } else {
+                continue;
+            };
+            mutex_inner.wait_queue.remove(pos);
+            return Err(error);
         }
+        locked_result(&mut mutex_inner.owner_died)
     }
 
-    fn unlock(&self) {
+    fn unlock(&self) -> Result<(), MutexError> {
         let mut mutex_inner = self.inner.exclusive_access();
+        if mutex_inner.errorcheck && !is_current(&mutex_inner.owner) {
+            return Err(MutexError::NotOwner);
+        }
         assert!(mutex_inner.locked);
-        if let Some(waking_task) = mutex_inner.wait_queue.pop_front() {
-            wakeup_task(waking_task);
-        } else {
-            mutex_inner.locked = false;
+        Self::hand_over(&mut mutex_inner);
+        Ok(())
+    }
+
+    fn owner_exited(&self, task: &Arc<TaskControlBlock>) -> bool {
+        let mut mutex_inner = self.inner.exclusive_access();
+        if !is_owner(&mutex_inner.owner, task) {
+            return false;
         }
+        mutex_inner.owner_died = true;
+        Self::hand_over(&mut mutex_inner);
+        true
     }
 }
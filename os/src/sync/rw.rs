@@ -0,0 +1,109 @@
+//! A readers-writer spin lock masking the interrupts like `UPIntrFreeCell`,
+//! for data read far more often than it is changed, so the harts reading it
+//! do not wait for each other.
+//!
+//! Readers are preferred: a writer waits until there is no reader at all,
+//! so a hart may read it again while reading it, but a writer may starve
+//! under a steady stream of readers. Writing it again while writing it, or
+//! writing it while reading it on the same hart, spins forever.
+
+use super::up::{pop_intr_off, push_intr_off};
+use crate::hart::poll_tlb_flush;
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Set in the state while a writer holds it, the rest is the reader count.
+const WRITER: usize = 1 << (usize::BITS - 1);
+
+pub struct RwIntrFreeCell<T> {
+    state: AtomicUsize,
+    inner: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for RwIntrFreeCell<T> {}
+
+pub struct RwIntrReadGuard<'a, T>(&'a RwIntrFreeCell<T>);
+
+pub struct RwIntrWriteGuard<'a, T>(&'a RwIntrFreeCell<T>);
+
+impl<T> RwIntrFreeCell<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            inner: UnsafeCell::new(value),
+        }
+    }
+
+    /// Spin while a writer holds it.
+    pub fn read(&self) -> RwIntrReadGuard<'_, T> {
+        push_intr_off();
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            if state & WRITER != 0 {
+                // the writer may wait for the TLB of this hart flushed
+                poll_tlb_flush();
+                core::hint::spin_loop();
+                state = self.state.load(Ordering::Relaxed);
+                continue;
+            }
+            match self.state.compare_exchange_weak(
+                state,
+                state + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return RwIntrReadGuard(self),
+                Err(current) => state = current,
+            }
+        }
+    }
+
+    /// Spin while a writer or any reader holds it.
+    pub fn write(&self) -> RwIntrWriteGuard<'_, T> {
+        push_intr_off();
+        while self
+            .state
+            .compare_exchange_weak(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            poll_tlb_flush();
+            core::hint::spin_loop();
+        }
+        RwIntrWriteGuard(self)
+    }
+}
+
+impl<T> Drop for RwIntrReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.0.state.fetch_sub(1, Ordering::Release);
+        pop_intr_off();
+    }
+}
+
+impl<T> Drop for RwIntrWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.0.state.store(0, Ordering::Release);
+        pop_intr_off();
+    }
+}
+
+impl<T> Deref for RwIntrReadGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.0.inner.get() }
+    }
+}
+
+impl<T> Deref for RwIntrWriteGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.0.inner.get() }
+    }
+}
+
+impl<T> DerefMut for RwIntrWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.0.inner.get() }
+    }
+}
@@ -1,7 +1,18 @@
 use crate::sync::UPIntrFreeCell;
-use crate::task::{block_current_and_run_next, current_task, wakeup_task, TaskControlBlock};
+use crate::task::{
+    block_current_and_release_interruptible, current_task, wakeup_task, TaskControlBlock,
+    WakeReason,
+};
+use crate::timer::get_time_ms;
 use alloc::{collections::VecDeque, sync::Arc};
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum SemaphoreError {
+    TimedOut,
+    /// the task is signalled or exiting while it waits
+    Interrupted,
+}
+
 pub struct Semaphore {
     pub inner: UPIntrFreeCell<SemaphoreInner>,
 }
@@ -33,13 +44,39 @@ impl Semaphore {
         }
     }
 
-    pub fn down(&self) {
+    /// Give up after the time reaches `expire_ms` if it is not None, or once
+    /// the task is to stop waiting, see `current_wake_reason`.
+    pub fn down(&self, expire_ms: Option<usize>) -> Result<(), SemaphoreError> {
         let mut inner = self.inner.exclusive_access();
         inner.count -= 1;
-        if inner.count < 0 {
-            inner.wait_queue.push_back(current_task().unwrap());
-            drop(inner);
-            block_current_and_run_next();
+        if inner.count >= 0 {
+            return Ok(());
+        }
+        let task = current_task().unwrap();
+        inner.wait_queue.push_back(Arc::clone(&task));
+        let expire_ns = expire_ms.map(|expire_ms| expire_ms.saturating_mul(1_000_000));
+        loop {
+            let reason = block_current_and_release_interruptible(inner, expire_ns);
+            // acquired once we are no longer waiting
+            inner = self.inner.exclusive_access();
+            let pos = match inner
+                .wait_queue
+                .iter()
+                .position(|waiting| Arc::ptr_eq(waiting, &task))
+            {
+                Some(pos) => pos,
+                None => return Ok(()),
+            };
+            let error = if reason != WakeReason::Woken {
+                SemaphoreError::Interrupted
+            } else if expire_ms.map_or(false, |expire_ms| get_time_ms() >= expire_ms) {
+                SemaphoreError::TimedOut
+            } else {
+                continue;
+            };
+            inner.wait_queue.remove(pos);
+            inner.count += 1;
+            return Err(error);
         }
     }
 }
@@ -0,0 +1,168 @@
+//! Ticket spin locks, which hand the lock to the harts in the order they
+//! ask for it. `SpinLock` leaves the interrupts alone, so it is for data
+//! never touched by an interrupt handler, `SpinLockIrqSave` masks them while
+//! it is held like `UPIntrFreeCell` does.
+//!
+//! Locking one held by the same hart spins forever, there is no panic like
+//! a `RefCell` borrowed twice.
+
+use super::up::{pop_intr_off, push_intr_off};
+use crate::hart::poll_tlb_flush;
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+struct TicketLock {
+    next_ticket: AtomicUsize,
+    now_serving: AtomicUsize,
+}
+
+impl TicketLock {
+    const fn new() -> Self {
+        Self {
+            next_ticket: AtomicUsize::new(0),
+            now_serving: AtomicUsize::new(0),
+        }
+    }
+
+    fn lock(&self) {
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        while self.now_serving.load(Ordering::Acquire) != ticket {
+            // the holder may wait for the TLB of this hart flushed
+            poll_tlb_flush();
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Take a ticket only if it is served right away.
+    fn try_lock(&self) -> bool {
+        let ticket = self.now_serving.load(Ordering::Acquire);
+        self.next_ticket
+            .compare_exchange(ticket, ticket + 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    fn unlock(&self) {
+        self.now_serving.fetch_add(1, Ordering::Release);
+    }
+
+    fn is_locked(&self) -> bool {
+        self.next_ticket.load(Ordering::Relaxed) != self.now_serving.load(Ordering::Relaxed)
+    }
+}
+
+pub struct SpinLock<T> {
+    lock: TicketLock,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+unsafe impl<T: Send> Send for SpinLock<T> {}
+
+pub struct SpinLockGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+}
+
+impl<T> SpinLock<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            lock: TicketLock::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        self.lock.lock();
+        SpinLockGuard { lock: self }
+    }
+
+    /// Return None instead of spinning if it is held.
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+        self.lock.try_lock().then_some(SpinLockGuard { lock: self })
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.lock.is_locked()
+    }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.lock.unlock();
+    }
+}
+
+impl<T> Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for SpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+pub struct SpinLockIrqSave<T> {
+    inner: SpinLock<T>,
+}
+
+/// The interrupts are masked again once the lock is released.
+pub struct SpinLockIrqSaveGuard<'a, T> {
+    guard: Option<SpinLockGuard<'a, T>>,
+}
+
+impl<T> SpinLockIrqSave<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            inner: SpinLock::new(data),
+        }
+    }
+
+    pub fn lock(&self) -> SpinLockIrqSaveGuard<'_, T> {
+        push_intr_off();
+        SpinLockIrqSaveGuard {
+            guard: Some(self.inner.lock()),
+        }
+    }
+
+    /// Return None instead of spinning if it is held.
+    pub fn try_lock(&self) -> Option<SpinLockIrqSaveGuard<'_, T>> {
+        push_intr_off();
+        match self.inner.try_lock() {
+            Some(guard) => Some(SpinLockIrqSaveGuard { guard: Some(guard) }),
+            None => {
+                pop_intr_off();
+                None
+            }
+        }
+    }
+
+    #[allow(unused)]
+    pub fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+}
+
+impl<T> Drop for SpinLockIrqSaveGuard<'_, T> {
+    fn drop(&mut self) {
+        // unlocked before the interrupts come back
+        self.guard = None;
+        pop_intr_off();
+    }
+}
+
+impl<T> Deref for SpinLockIrqSaveGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.guard.as_ref().unwrap()
+    }
+}
+
+impl<T> DerefMut for SpinLockIrqSaveGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.guard.as_mut().unwrap()
+    }
+}
@@ -1,5 +1,8 @@
-use core::cell::{RefCell, RefMut, UnsafeCell};
+use crate::hart::{hart_id, poll_tlb_flush, MAX_HARTS};
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
 use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
 use lazy_static::*;
 use riscv::register::sstatus;
 
@@ -56,8 +59,24 @@ pub struct IntrMaskingInfo {
 }
 
 lazy_static! {
-    static ref INTR_MASKING_INFO: UPSafeCellRaw<IntrMaskingInfo> =
-        unsafe { UPSafeCellRaw::new(IntrMaskingInfo::new()) };
+    /// Interrupts are masked per hart.
+    static ref INTR_MASKING_INFO: Vec<UPSafeCellRaw<IntrMaskingInfo>> = (0..MAX_HARTS)
+        .map(|_| unsafe { UPSafeCellRaw::new(IntrMaskingInfo::new()) })
+        .collect();
+}
+
+fn intr_masking_info() -> &'static mut IntrMaskingInfo {
+    INTR_MASKING_INFO[hart_id()].get_mut()
+}
+
+/// Mask the interrupts of the current hart, nested with the cells.
+pub(super) fn push_intr_off() {
+    intr_masking_info().enter();
+}
+
+/// Undo a `push_intr_off`.
+pub(super) fn pop_intr_off() {
+    intr_masking_info().exit();
 }
 
 impl IntrMaskingInfo {
@@ -89,26 +108,65 @@ impl IntrMaskingInfo {
     }
 }
 
+/// No hart holds the cell.
+const NO_OWNER: usize = usize::MAX;
+
+/// A spin lock masking the interrupts of the hart holding it, so the data is
+/// shared by the harts and the interrupt handlers alike. The name is kept
+/// from the uniprocessor days.
 pub struct UPIntrFreeCell<T> {
+    /// the hart holding it, or `NO_OWNER`
+    owner: AtomicUsize,
     /// inner data
-    inner: RefCell<T>,
+    inner: UnsafeCell<T>,
 }
 
 unsafe impl<T> Sync for UPIntrFreeCell<T> {}
 
-pub struct UPIntrRefMut<'a, T>(Option<RefMut<'a, T>>);
+pub struct UPIntrRefMut<'a, T>(&'a UPIntrFreeCell<T>);
 
 impl<T> UPIntrFreeCell<T> {
     pub unsafe fn new(value: T) -> Self {
         Self {
-            inner: RefCell::new(value),
+            owner: AtomicUsize::new(NO_OWNER),
+            inner: UnsafeCell::new(value),
         }
     }
 
-    /// Panic if the data has been borrowed.
+    /// Spin while another hart holds it. Panic if the current hart holds it
+    /// already, which would never be released.
     pub fn exclusive_access(&self) -> UPIntrRefMut<'_, T> {
-        INTR_MASKING_INFO.get_mut().enter();
-        UPIntrRefMut(Some(self.inner.borrow_mut()))
+        intr_masking_info().enter();
+        let hart_id = hart_id();
+        while let Err(owner) = self.owner.compare_exchange_weak(
+            NO_OWNER,
+            hart_id,
+            Ordering::Acquire,
+            Ordering::Relaxed,
+        ) {
+            if owner == hart_id {
+                panic!("already borrowed");
+            }
+            // the owner may wait for the TLB of this hart flushed
+            poll_tlb_flush();
+            core::hint::spin_loop();
+        }
+        UPIntrRefMut(self)
+    }
+
+    /// Like `exclusive_access`, but None at once if any hart holds it, for
+    /// the panic handler, whose hart may hold it.
+    pub fn try_exclusive_access(&self) -> Option<UPIntrRefMut<'_, T>> {
+        intr_masking_info().enter();
+        if self
+            .owner
+            .compare_exchange(NO_OWNER, hart_id(), Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            intr_masking_info().exit();
+            return None;
+        }
+        Some(UPIntrRefMut(self))
     }
 
     pub fn exclusive_session<F, V>(&self, f: F) -> V
@@ -122,19 +180,19 @@ impl<T> UPIntrFreeCell<T> {
 
 impl<'a, T> Drop for UPIntrRefMut<'a, T> {
     fn drop(&mut self) {
-        self.0 = None;
-        INTR_MASKING_INFO.get_mut().exit();
+        self.0.owner.store(NO_OWNER, Ordering::Release);
+        intr_masking_info().exit();
     }
 }
 
 impl<'a, T> Deref for UPIntrRefMut<'a, T> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
-        self.0.as_ref().unwrap().deref()
+        unsafe { &*self.0.inner.get() }
     }
 }
 impl<'a, T> DerefMut for UPIntrRefMut<'a, T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        self.0.as_mut().unwrap().deref_mut()
+        unsafe { &mut *self.0.inner.get() }
     }
 }
@@ -0,0 +1,80 @@
+use crate::sync::{UPIntrFreeCell, UPIntrRefMut};
+use crate::task::{
+    block_current_task_interruptible, clear_wake_hook, current_task, current_wake_reason, schedule,
+    set_wake_hook, wakeup_task, TaskControlBlock, WakeReason,
+};
+use crate::timer::{add_timer, cancel_timer};
+use alloc::{boxed::Box, collections::VecDeque, sync::Arc};
+
+/// Tasks sleeping until an event of a driver, usually an interrupt.
+pub struct WaitQueue {
+    tasks: UPIntrFreeCell<VecDeque<Arc<TaskControlBlock>>>,
+}
+
+impl WaitQueue {
+    pub fn new() -> Self {
+        Self {
+            tasks: unsafe { UPIntrFreeCell::new(VecDeque::new()) },
+        }
+    }
+
+    /// Block the current task until it is woken up, or it is to stop waiting
+    /// for a signal or exiting, see `current_wake_reason`. `guard` is the
+    /// borrowed state where the caller finds nothing to do, it is released
+    /// after the task is queued so a wake up from the interrupt handler is
+    /// never lost. Return why the task goes on, the caller checks the state
+    /// again if it is `WakeReason::Woken`.
+    ///
+    /// Before tasks are scheduled, it returns right away and the caller
+    /// polls the state again.
+    pub fn sleep_on<T>(&self, guard: UPIntrRefMut<'_, T>) -> WakeReason {
+        self.sleep_on_until(guard, None)
+    }
+
+    /// Like `sleep_on`, but also wake up once the time reaches `expire_ns`
+    /// if any. The caller should find out if the time is up by itself.
+    pub fn sleep_on_until<T>(
+        &self,
+        guard: UPIntrRefMut<'_, T>,
+        expire_ns: Option<usize>,
+    ) -> WakeReason {
+        let task = match current_task() {
+            Some(task) => task,
+            None => return WakeReason::Woken,
+        };
+        let waiter = Arc::clone(&task);
+        if !set_wake_hook(Box::new(move || wakeup_task(waiter))) {
+            return current_wake_reason();
+        }
+        self.tasks.exclusive_access().push_back(Arc::clone(&task));
+        let timer = expire_ns.map(|expire_ns| add_timer(expire_ns, Arc::clone(&task)));
+        let task_cx_ptr = block_current_task_interruptible();
+        drop(guard);
+        schedule(task_cx_ptr);
+        if let Some(timer) = timer {
+            cancel_timer(timer);
+        }
+        // still queued if woken up by the hook
+        self.tasks
+            .exclusive_access()
+            .retain(|queued| !Arc::ptr_eq(queued, &task));
+        clear_wake_hook()
+    }
+
+    /// Wake up the task sleeping for the longest time, return false if there
+    /// is none.
+    pub fn wake_up(&self) -> bool {
+        let task = self.tasks.exclusive_access().pop_front();
+        match task {
+            Some(task) => {
+                wakeup_task(task);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn wake_up_all(&self) {
+        while self.wake_up() {}
+    }
+}
@@ -0,0 +1,33 @@
+use crate::task::{cpu_group_exists, cpu_group_usage, create_cpu_group, pid2process};
+use abi::{SysError, SysResult};
+
+/// Create a cpu group, `quota_ms` is the cpu time allowed every 100ms, 0 for
+/// unlimited. Return the id of the group.
+pub fn sys_cgroup_create(shares: usize, quota_ms: usize) -> SysResult {
+    let quota_us = if quota_ms == 0 {
+        None
+    } else {
+        Some(quota_ms * 1000)
+    };
+    create_cpu_group(shares, quota_us).ok_or(SysError::Invalid)
+}
+
+/// Move all threads of the process into the group, children forked later
+/// inherit the group.
+pub fn sys_cgroup_attach(pid: usize, group_id: usize) -> SysResult {
+    let process = pid2process(pid).ok_or(SysError::NoProcess)?;
+    if !cpu_group_exists(group_id) {
+        return Err(SysError::Invalid);
+    }
+    let mut inner = process.inner_exclusive_access();
+    inner.cpu_group = group_id;
+    for task in inner.tasks.iter().flatten() {
+        task.set_cpu_group(group_id);
+    }
+    Ok(0)
+}
+
+/// Return the cpu time used by the group in microseconds.
+pub fn sys_cgroup_usage(group_id: usize) -> SysResult {
+    cpu_group_usage(group_id).ok_or(SysError::Invalid)
+}
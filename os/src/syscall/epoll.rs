@@ -0,0 +1,85 @@
+use super::fs::fd_flags;
+use crate::fs::{Epoll, EpollCtlError, File, OpenFlags};
+use crate::mm::UserSliceRef;
+use crate::task::{current_process, current_user_token};
+use crate::timer::get_time_ns;
+use abi::{EpollEvent, SysError, SysResult, EPOLL_CTL_ADD, EPOLL_CTL_DEL, EPOLL_CTL_MOD};
+use alloc::sync::Arc;
+
+fn get_epoll(epfd: usize) -> Option<Arc<dyn File + Send + Sync>> {
+    let process = current_process();
+    let file = process.fd_table.read().get(epfd).cloned()?;
+    file.as_any().downcast_ref::<Epoll>()?;
+    Some(file)
+}
+
+/// Create an event queue, only `OpenFlags::CLOEXEC` is supported.
+pub fn sys_epoll_create(flags: u32) -> SysResult {
+    let flags = match OpenFlags::from_bits(flags) {
+        Some(flags) if OpenFlags::CLOEXEC.contains(flags) => flags,
+        _ => return Err(SysError::Invalid),
+    };
+    let process = current_process();
+    let mut fd_table = process.fd_table.write();
+    let fd = fd_table
+        .alloc(Arc::new(Epoll::new()))
+        .ok_or(SysError::TooManyFiles)?;
+    fd_table.set_flags(fd, fd_flags(flags));
+    Ok(fd)
+}
+
+/// Add `fd` to the interest list of `epfd` with `event`, change it or
+/// remove it. `event` is ignored by `EPOLL_CTL_DEL`.
+pub fn sys_epoll_ctl(
+    epfd: usize,
+    op: usize,
+    fd: usize,
+    event: UserSliceRef<EpollEvent>,
+) -> SysResult {
+    let epoll_file = get_epoll(epfd).ok_or(SysError::BadFd)?;
+    let epoll = epoll_file.as_any().downcast_ref::<Epoll>().unwrap();
+    let event = if op == EPOLL_CTL_DEL {
+        EpollEvent::default()
+    } else {
+        event.get(current_user_token(), 0).ok_or(SysError::Fault)?
+    };
+    let done = match op {
+        EPOLL_CTL_ADD => {
+            let file = current_process().fd_table.read().get(fd).cloned();
+            epoll.add(fd, &file.ok_or(SysError::BadFd)?, event)
+        }
+        EPOLL_CTL_MOD => epoll.modify(fd, event),
+        EPOLL_CTL_DEL => epoll.remove(fd),
+        _ => return Err(SysError::Invalid),
+    };
+    done.map(|()| 0).map_err(|error| match error {
+        EpollCtlError::Exists => SysError::Exists,
+        EpollCtlError::NotFound => SysError::NoEntry,
+        EpollCtlError::Invalid => SysError::Invalid,
+    })
+}
+
+/// Wait for the files of `epfd` to be ready at most `timeout_ms`, or
+/// forever if it is negative, and fill `events` with them. Return the
+/// number of them, or `Interrupted` if a signal comes first.
+pub fn sys_epoll_wait(
+    epfd: usize,
+    events: UserSliceRef<EpollEvent>,
+    timeout_ms: isize,
+) -> SysResult {
+    let epoll_file = get_epoll(epfd).ok_or(SysError::BadFd)?;
+    let epoll = epoll_file.as_any().downcast_ref::<Epoll>().unwrap();
+    if events.len() == 0 {
+        return Err(SysError::Invalid);
+    }
+    let expire_ns = usize::try_from(timeout_ms)
+        .ok()
+        .map(|timeout_ms| get_time_ns().saturating_add(timeout_ms.saturating_mul(1_000_000)));
+    let ready = epoll
+        .wait(events.len(), expire_ns)
+        .ok_or(SysError::Interrupted)?;
+    events
+        .write(current_user_token(), &ready)
+        .ok_or(SysError::Fault)?;
+    Ok(ready.len())
+}
@@ -1,99 +1,825 @@
-use crate::fs::{make_pipe, open_file, OpenFlags};
-use crate::mm::{translated_byte_buffer, translated_refmut, translated_str, UserBuffer};
-use crate::task::{current_process, current_user_token};
+use crate::config::PAGE_SIZE;
+use crate::fs::{
+    check_dir, flock, get_range_lock, join_path, make_dir, make_pipe, mount, open_path,
+    release_range_locks, remount, remove_file, rename_file, root_super_block, set_range_lock,
+    sync_all, umount, File, Flock, LockError, OSInode, OpenFlags, Pipe, PollEvents, PollFd, Poller,
+    F_GETLK, F_RDLCK, F_SETLK, F_SETLKW, F_WRLCK, PIPE_MAX_SIZE, SEEK_SET,
+};
+use crate::mm::{UserBuffer, UserCString, UserSliceRef};
+use crate::task::{current_interrupted, current_process, current_uid, current_user_token, FdFlags};
+use crate::timer::get_time_ns;
+use abi::{FdSet, SysError, SysResult, FD_SETSIZE};
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use easy_fs::{MountFlags, Quota};
 
-pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
+/// The length read or written, or `Restart` if none of the `len` bytes is
+/// since the file stopped waiting for a signal.
+fn done_or_restart(done: usize, len: usize) -> SysResult {
+    if done == 0 && len > 0 && current_interrupted() {
+        Err(SysError::Restart)
+    } else {
+        Ok(done)
+    }
+}
+
+pub fn sys_write(fd: usize, buf: UserSliceRef<u8>) -> SysResult {
     let token = current_user_token();
     let process = current_process();
-    let inner = process.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        return -1;
-    }
-    if let Some(file) = &inner.fd_table[fd] {
+    let fd_table = process.fd_table.read();
+    if let Some(file) = fd_table.get(fd) {
         if !file.writable() {
-            return -1;
+            return Err(SysError::BadFd);
         }
         let file = file.clone();
-        // release current task TCB manually to avoid multi-borrow
-        drop(inner);
-        file.write(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
+        // release the table, the file may block
+        drop(fd_table);
+        match buf.buffer(token, false) {
+            Some(_) if file.broken() => Err(SysError::BrokenPipe),
+            Some(_) if file.would_block(true) => Err(SysError::WouldBlock),
+            Some(user_buf) => match file.write(user_buf) {
+                // the reader left while it waited
+                0 if buf.len() > 0 && file.broken() => Err(SysError::BrokenPipe),
+                written => done_or_restart(written, buf.len()),
+            },
+            None => Err(SysError::Fault),
+        }
     } else {
-        -1
+        Err(SysError::BadFd)
     }
 }
 
-pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
+pub fn sys_read(fd: usize, buf: UserSliceRef<u8>) -> SysResult {
     let token = current_user_token();
     let process = current_process();
-    let inner = process.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        return -1;
-    }
-    if let Some(file) = &inner.fd_table[fd] {
+    let fd_table = process.fd_table.read();
+    if let Some(file) = fd_table.get(fd) {
         let file = file.clone();
         if !file.readable() {
-            return -1;
+            return Err(SysError::BadFd);
+        }
+        // release the table, the file may block
+        drop(fd_table);
+        match buf.buffer(token, true) {
+            Some(_) if file.would_block(false) => Err(SysError::WouldBlock),
+            Some(user_buf) => done_or_restart(file.read(user_buf), buf.len()),
+            None => Err(SysError::Fault),
         }
-        // release current task TCB manually to avoid multi-borrow
-        drop(inner);
-        file.read(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
     } else {
-        -1
+        Err(SysError::BadFd)
     }
 }
 
-pub fn sys_open(path: *const u8, flags: u32) -> isize {
+/// Read like `sys_read`, but wait at most `timeout_ms` for the file to have
+/// something to read, or forever if it is negative. Return `NotReady` on
+/// timeout, or `Interrupted` if a signal comes first.
+pub fn sys_read_timeout(fd: usize, buf: UserSliceRef<u8>, timeout_ms: isize) -> SysResult {
     let process = current_process();
+    let fd_table = process.fd_table.read();
+    let file = match fd_table.get(fd) {
+        Some(file) if file.readable() => file.clone(),
+        _ => return Err(SysError::BadFd),
+    };
+    drop(fd_table);
+    let expire_ns = deadline_ns(timeout_ms);
+    let mut poller: Option<Poller> = None;
+    // readable or at the end, like the write ends of a pipe all closed
+    while file
+        .poll(PollEvents::POLLIN | PollEvents::POLLHUP)
+        .is_empty()
+    {
+        if expire_ns.map_or(false, |expire_ns| get_time_ns() >= expire_ns) {
+            return Err(SysError::NotReady);
+        }
+        match &poller {
+            // polled again once the hook is added
+            None => {
+                let mut new_poller = Poller::new();
+                new_poller.add(&file);
+                poller = Some(new_poller);
+            }
+            Some(poller) => {
+                if !poller.wait(expire_ns) {
+                    return Err(SysError::Interrupted);
+                }
+            }
+        }
+    }
+    sys_read(fd, buf)
+}
+
+/// Keep each write of sendfile to a socket in a single ethernet frame.
+const SOCKET_CHUNK: usize = 1024;
+
+/// Copy at most `count` bytes of a file to `out_fd` from the pages of it in
+/// the page cache, rather than through a buffer in the user space. The file
+/// is read from `*offset`, which is advanced, or from its own offset if
+/// `offset` is null. Return the number of bytes copied.
+pub fn sys_sendfile(
+    out_fd: usize,
+    in_fd: usize,
+    offset: UserSliceRef<usize>,
+    count: usize,
+) -> SysResult {
     let token = current_user_token();
-    let path = translated_str(token, path);
-    if let Some(inode) = open_file(path.as_str(), OpenFlags::from_bits(flags).unwrap()) {
-        let mut inner = process.inner_exclusive_access();
-        let fd = inner.alloc_fd();
-        inner.fd_table[fd] = Some(inode);
-        fd as isize
+    let process = current_process();
+    let fd_table = process.fd_table.read();
+    let (out_file, in_file) = match (fd_table.get(out_fd), fd_table.get(in_fd)) {
+        (Some(out_file), Some(in_file)) => (out_file.clone(), in_file.clone()),
+        _ => return Err(SysError::BadFd),
+    };
+    drop(fd_table);
+    if !in_file.readable() || !out_file.writable() {
+        return Err(SysError::BadFd);
+    }
+    // only files of the page cache are sent
+    let inode = in_file
+        .as_any()
+        .downcast_ref::<OSInode>()
+        .ok_or(SysError::Invalid)?;
+    let start = if offset.is_null() {
+        inode.offset()
+    } else {
+        offset.get(token, 0).ok_or(SysError::Fault)?
+    };
+    let end = start.saturating_add(count).min(inode.size());
+    let chunk = if out_file.is_socket() {
+        SOCKET_CHUNK
+    } else {
+        PAGE_SIZE
+    };
+    let mut pos = start;
+    while pos < end {
+        let page = match inode.cached_page(pos / PAGE_SIZE) {
+            Some(page) => page,
+            None => break,
+        };
+        let page_offset = pos % PAGE_SIZE;
+        let len = (PAGE_SIZE - page_offset).min(end - pos).min(chunk);
+        // only read by the write
+        let data = &mut page.ppn.get_bytes_array()[page_offset..page_offset + len];
+        let written = out_file.write(UserBuffer::new(vec![data]));
+        pos += written;
+        if written < len {
+            break;
+        }
+    }
+    if pos == start && end > start && out_file.broken() {
+        return Err(SysError::BrokenPipe);
+    }
+    if offset.is_null() {
+        in_file.seek(pos as isize, SEEK_SET);
+    } else if offset.set(token, 0, pos).is_none() {
+        return Err(SysError::Fault);
+    }
+    Ok(pos - start)
+}
+
+pub const F_DUPFD: usize = 0;
+pub const F_GETFD: usize = 1;
+pub const F_SETFD: usize = 2;
+pub const F_GETFL: usize = 3;
+pub const F_SETFL: usize = 4;
+pub const F_DUPFD_CLOEXEC: usize = 1030;
+pub const F_SETPIPE_SZ: usize = 1031;
+pub const F_GETPIPE_SZ: usize = 1032;
+/// Set `FdFlags::CLOEXEC` of the descriptors instead of closing them.
+const CLOSE_RANGE_CLOEXEC: u32 = 1 << 2;
+/// Tables are never shared, so it is always unshared.
+const CLOSE_RANGE_UNSHARE: u32 = 1 << 1;
+
+/// The flags of a new descriptor opened with `flags`.
+pub fn fd_flags(flags: OpenFlags) -> FdFlags {
+    if flags.contains(OpenFlags::CLOEXEC) {
+        FdFlags::CLOEXEC
     } else {
-        -1
+        FdFlags::empty()
     }
 }
 
-pub fn sys_close(fd: usize) -> isize {
+/// Open a path from the real root, in the procfs, the devices or the file
+/// system.
+pub fn sys_open(path: UserCString, flags: u32) -> SysResult {
     let process = current_process();
-    let mut inner = process.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        return -1;
+    let token = current_user_token();
+    // before the process is locked, the page of the path may be loaded
+    let path = path.read(token).ok_or(SysError::Fault)?;
+    let path = process.inner_exclusive_access().resolve_path(path.as_str());
+    let flags = OpenFlags::from_bits(flags).ok_or(SysError::Invalid)?;
+    let file = open_path(path.as_str(), flags)?;
+    let mut fd_table = process.fd_table.write();
+    let fd = fd_table.alloc(file).ok_or(SysError::TooManyFiles)?;
+    fd_table.set_flags(fd, fd_flags(flags));
+    Ok(fd)
+}
+
+pub fn sys_close(fd: usize) -> SysResult {
+    let process = current_process();
+    let mut fd_table = process.fd_table.write();
+    let file = fd_table.remove(fd).ok_or(SysError::BadFd)?;
+    drop(fd_table);
+    release_range_locks(file.as_ref(), process.getpid());
+    Ok(0)
+}
+
+/// Create a pipe, only `OpenFlags::CLOEXEC` and `OpenFlags::NONBLOCK` of
+/// `flags` are used.
+pub fn sys_pipe(pipe: UserSliceRef<usize>, flags: u32) -> SysResult {
+    let flags = OpenFlags::from_bits(flags).ok_or(SysError::Invalid)?;
+    let process = current_process();
+    let token = current_user_token();
+    let mut fd_table = process.fd_table.write();
+    let (pipe_read, pipe_write) = make_pipe();
+    pipe_read.set_status(flags & OpenFlags::NONBLOCK);
+    pipe_write.set_status(flags & OpenFlags::NONBLOCK);
+    let read_fd = fd_table.alloc(pipe_read).ok_or(SysError::TooManyFiles)?;
+    let write_fd = match fd_table.alloc(pipe_write) {
+        Some(fd) => fd,
+        None => {
+            fd_table.remove(read_fd);
+            return Err(SysError::TooManyFiles);
+        }
+    };
+    fd_table.set_flags(read_fd, fd_flags(flags));
+    fd_table.set_flags(write_fd, fd_flags(flags));
+    drop(fd_table);
+    if pipe.write(token, &[read_fd, write_fd]).is_none() {
+        let mut fd_table = process.fd_table.write();
+        fd_table.remove(read_fd);
+        fd_table.remove(write_fd);
+        return Err(SysError::Fault);
+    }
+    Ok(0)
+}
+
+pub fn sys_dup(fd: usize) -> SysResult {
+    let process = current_process();
+    let mut fd_table = process.fd_table.write();
+    let file = fd_table.get(fd).ok_or(SysError::BadFd)?.clone();
+    fd_table.alloc(file).ok_or(SysError::TooManyFiles)
+}
+
+/// Duplicate a descriptor to the lowest free one not less than `arg`, get
+/// and set the flags of it, or get and set the access mode and the status
+/// flags of the open file, which are shared by its duplicates and by forked
+/// children. Only `OpenFlags::STATUS` flags can be set.
+///
+/// `F_GETLK`, `F_SETLK` and `F_SETLKW` test, take and wait for a range of
+/// an inode described by the `Flock` at `arg`, see `sys_flock` for errors.
+///
+/// `F_SETPIPE_SZ` sets the capacity of a pipe to `arg` bytes and returns
+/// it, see `Pipe::set_capacity`, `F_GETPIPE_SZ` gets it.
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> SysResult {
+    if matches!(cmd, F_GETLK | F_SETLK | F_SETLKW) {
+        return fcntl_lock(fd, cmd, UserSliceRef::one(arg));
+    }
+    let process = current_process();
+    let mut fd_table = process.fd_table.write();
+    let file = fd_table.get(fd).ok_or(SysError::BadFd)?.clone();
+    match cmd {
+        F_DUPFD | F_DUPFD_CLOEXEC => {
+            let new_fd = fd_table
+                .alloc_from(arg, file)
+                .ok_or(SysError::TooManyFiles)?;
+            if cmd == F_DUPFD_CLOEXEC {
+                fd_table.set_flags(new_fd, FdFlags::CLOEXEC);
+            }
+            Ok(new_fd)
+        }
+        F_GETFD => Ok(fd_table.flags(fd).unwrap().bits() as usize),
+        F_SETFD => {
+            fd_table.set_flags(fd, FdFlags::from_bits_truncate(arg as u32));
+            Ok(0)
+        }
+        F_GETFL => {
+            let access = match (file.readable(), file.writable()) {
+                (true, true) => OpenFlags::RDWR,
+                (false, true) => OpenFlags::WRONLY,
+                _ => OpenFlags::RDONLY,
+            };
+            Ok((access | file.status()).bits() as usize)
+        }
+        F_SETFL => {
+            let flags = OpenFlags::from_bits_truncate(arg as u32) & OpenFlags::STATUS;
+            if file.set_status(flags) {
+                Ok(0)
+            } else {
+                Err(SysError::Invalid)
+            }
+        }
+        F_SETPIPE_SZ | F_GETPIPE_SZ => match file.as_any().downcast_ref::<Pipe>() {
+            Some(pipe) if cmd == F_GETPIPE_SZ => Ok(pipe.capacity()),
+            Some(pipe) if pipe.set_capacity(arg) => Ok(arg),
+            // less than the bytes in the pipe
+            Some(_) if arg > 0 && arg <= PIPE_MAX_SIZE => Err(SysError::Busy),
+            _ => Err(SysError::Invalid),
+        },
+        _ => Err(SysError::Invalid),
+    }
+}
+
+fn fcntl_lock(fd: usize, cmd: usize, flock: UserSliceRef<Flock>) -> SysResult {
+    let process = current_process();
+    let file = process.fd_table.read().get(fd).cloned();
+    let file = file.ok_or(SysError::BadFd)?;
+    let inode = file
+        .as_any()
+        .downcast_ref::<OSInode>()
+        .ok_or(SysError::Invalid)?;
+    let token = current_user_token();
+    let mut request = flock.get(token, 0).ok_or(SysError::Fault)?;
+    let pid = process.getpid();
+    let locked = match cmd {
+        F_GETLK => {
+            lock_result(get_range_lock(inode, pid, &mut request))?;
+            flock.set(token, 0, request).ok_or(SysError::Fault)?;
+            return Ok(0);
+        }
+        _ => {
+            // a range is taken for reading or writing only if the file is
+            // opened so
+            let allowed = match request.l_type {
+                F_RDLCK => file.readable(),
+                F_WRLCK => file.writable(),
+                _ => true,
+            };
+            if !allowed {
+                return Err(SysError::BadFd);
+            }
+            set_range_lock(inode, pid, &request, cmd == F_SETLKW)
+        }
+    };
+    lock_result(locked)
+}
+
+fn lock_result(locked: Result<(), LockError>) -> SysResult {
+    locked.map(|()| 0).map_err(|error| match error {
+        LockError::WouldBlock => SysError::WouldBlock,
+        LockError::Deadlock => SysError::Deadlock,
+        LockError::Signalled => SysError::Restart,
+        LockError::Invalid => SysError::Invalid,
+        // never seen, the caller exits
+        LockError::Exiting => SysError::Interrupted,
+    })
+}
+
+/// Lock the whole of an open file as `op` says, `LOCK_SH`, `LOCK_EX` or
+/// `LOCK_UN`, with `LOCK_NB` not to wait. The lock is shared by the
+/// duplicates of the descriptor and released with the last of them.
+/// Return `WouldBlock` if it would wait, `Deadlock` if a range waited for by
+/// fcntl is held by processes waiting for the caller, or `Restart` if it
+/// stops waiting for a signal.
+pub fn sys_flock(fd: usize, op: usize) -> SysResult {
+    let process = current_process();
+    let file = process.fd_table.read().get(fd).cloned();
+    let file = file.ok_or(SysError::BadFd)?;
+    match file.as_any().downcast_ref::<OSInode>() {
+        Some(inode) => lock_result(flock(inode, process.getpid(), op)),
+        None => Err(SysError::Invalid),
+    }
+}
+
+/// Close the descriptors in [first, last], or mark them close-on-exec with
+/// `CLOSE_RANGE_CLOEXEC`.
+pub fn sys_close_range(first: usize, last: usize, flags: u32) -> SysResult {
+    if first > last || flags & !(CLOSE_RANGE_CLOEXEC | CLOSE_RANGE_UNSHARE) != 0 {
+        return Err(SysError::Invalid);
+    }
+    let process = current_process();
+    let mut fd_table = process.fd_table.write();
+    let fds: Vec<usize> = fd_table
+        .iter()
+        .map(|(fd, _)| fd)
+        .filter(|fd| (first..=last).contains(fd))
+        .collect();
+    let mut closed = Vec::new();
+    for fd in fds {
+        if flags & CLOSE_RANGE_CLOEXEC != 0 {
+            let fd_flags = fd_table.flags(fd).unwrap();
+            fd_table.set_flags(fd, fd_flags | FdFlags::CLOEXEC);
+        } else {
+            closed.extend(fd_table.remove(fd));
+        }
+    }
+    drop(fd_table);
+    for file in closed {
+        release_range_locks(file.as_ref(), process.getpid());
+    }
+    Ok(0)
+}
+
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> SysResult {
+    let process = current_process();
+    let fd_table = process.fd_table.read();
+    if let Some(file) = fd_table.get(fd) {
+        let file = file.clone();
+        drop(fd_table);
+        SysError::check(file.seek(offset, whence))
+    } else {
+        Err(SysError::BadFd)
+    }
+}
+
+pub fn sys_ftruncate(fd: usize, len: usize) -> SysResult {
+    let process = current_process();
+    let fd_table = process.fd_table.read();
+    if let Some(file) = fd_table.get(fd) {
+        let file = file.clone();
+        drop(fd_table);
+        SysError::check(file.truncate(len))
+    } else {
+        Err(SysError::BadFd)
+    }
+}
+
+/// Return once what has been written to each of the file systems is
+/// persistent, like `sys_fdatasync` for all the files at once.
+pub fn sys_sync() -> SysResult {
+    sync_all();
+    Ok(0)
+}
+
+/// Return once what has been written to the file `fd` is persistent, with
+/// what is needed to read it back like the size, which `sys_fsync` also
+/// writes back, as there is nothing more to write of a file.
+pub fn sys_fdatasync(fd: usize) -> SysResult {
+    let process = current_process();
+    let fd_table = process.fd_table.read();
+    if let Some(file) = fd_table.get(fd) {
+        let file = file.clone();
+        drop(fd_table);
+        SysError::check(file.sync())
+    } else {
+        Err(SysError::BadFd)
     }
-    if inner.fd_table[fd].is_none() {
-        return -1;
+}
+
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> SysResult {
+    let process = current_process();
+    let fd_table = process.fd_table.read();
+    if let Some(file) = fd_table.get(fd) {
+        let file = file.clone();
+        drop(fd_table);
+        SysError::check(file.ioctl(cmd, arg))
+    } else {
+        Err(SysError::BadFd)
+    }
+}
+
+pub fn sys_rename(old_path: UserCString, new_path: UserCString) -> SysResult {
+    let token = current_user_token();
+    let process = current_process();
+    let (old_path, new_path) = match (old_path.read(token), new_path.read(token)) {
+        (Some(old_path), Some(new_path)) => (old_path, new_path),
+        _ => return Err(SysError::Fault),
+    };
+    let inner = process.inner_exclusive_access();
+    let old_path = inner.resolve_path(old_path.as_str());
+    let new_path = inner.resolve_path(new_path.as_str());
+    drop(inner);
+    rename_file(old_path.as_str(), new_path.as_str())?;
+    Ok(0)
+}
+
+pub fn sys_mkdir(path: UserCString) -> SysResult {
+    let path = path.read(current_user_token()).ok_or(SysError::Fault)?;
+    let path = current_process()
+        .inner_exclusive_access()
+        .resolve_path(path.as_str());
+    make_dir(path.as_str())?;
+    Ok(0)
+}
+
+pub const AT_REMOVEDIR: u32 = 0x200;
+
+/// Remove a directory with `AT_REMOVEDIR`, which must be empty, or a file
+/// otherwise. Files in use or mounted on are not removed.
+pub fn sys_unlinkat(path: UserCString, flags: u32) -> SysResult {
+    let path = path.read(current_user_token()).ok_or(SysError::Fault)?;
+    if flags & !AT_REMOVEDIR != 0 {
+        return Err(SysError::Invalid);
     }
-    inner.fd_table[fd].take();
-    0
+    let path = current_process()
+        .inner_exclusive_access()
+        .resolve_path(path.as_str());
+    remove_file(path.as_str(), flags & AT_REMOVEDIR != 0)?;
+    Ok(0)
 }
 
-pub fn sys_pipe(pipe: *mut usize) -> isize {
+/// Read the entries of the directory `fd` as `linux_dirent64` into `buf`,
+/// return the length read, 0 at the end, `NotDir` if it is not a directory
+/// or `Invalid` if `buf` is too small for an entry.
+pub fn sys_getdents64(fd: usize, buf: UserSliceRef<u8>) -> SysResult {
     let process = current_process();
+    let fd_table = process.fd_table.read();
+    let file = fd_table.get(fd).ok_or(SysError::BadFd)?.clone();
+    drop(fd_table);
+    let inode = match file.as_any().downcast_ref::<OSInode>() {
+        Some(inode) if file.readable() => inode,
+        Some(_) => return Err(SysError::BadFd),
+        None => return Err(SysError::NotDir),
+    };
+    if !inode.is_dir() {
+        return Err(SysError::NotDir);
+    }
+    let dirents = inode.read_dir(buf.len()).ok_or(SysError::Invalid)?;
+    buf.write(current_user_token(), &dirents)
+        .ok_or(SysError::Fault)?;
+    Ok(dirents.len())
+}
+
+/// Write the working directory with a trailing '\0' into `buf`, return the
+/// length written or `Range` if `buf` is too small.
+pub fn sys_getcwd(buf: UserSliceRef<u8>) -> SysResult {
+    let token = current_user_token();
+    let mut cwd = current_process().inner_exclusive_access().cwd.clone();
+    cwd.push('\0');
+    if cwd.len() > buf.len() {
+        return Err(SysError::Range);
+    }
+    buf.write(token, cwd.as_bytes()).ok_or(SysError::Fault)?;
+    Ok(cwd.len())
+}
+
+pub fn sys_chdir(path: UserCString) -> SysResult {
     let token = current_user_token();
+    let process = current_process();
+    let path = path.read(token).ok_or(SysError::Fault)?;
     let mut inner = process.inner_exclusive_access();
-    let (pipe_read, pipe_write) = make_pipe();
-    let read_fd = inner.alloc_fd();
-    inner.fd_table[read_fd] = Some(pipe_read);
-    let write_fd = inner.alloc_fd();
-    inner.fd_table[write_fd] = Some(pipe_write);
-    *translated_refmut(token, pipe) = read_fd;
-    *translated_refmut(token, unsafe { pipe.add(1) }) = write_fd;
-    0
+    let cwd = join_path(inner.cwd.as_str(), path.as_str());
+    check_dir(inner.resolve_path(cwd.as_str()).as_str())?;
+    inner.cwd = cwd;
+    Ok(0)
 }
 
-pub fn sys_dup(fd: usize) -> isize {
+/// Change the root directory, the working directory is moved to the new root
+/// so that the process cannot escape by relative paths. Only root may.
+pub fn sys_chroot(path: UserCString) -> SysResult {
+    let token = current_user_token();
     let process = current_process();
+    if current_uid() != 0 {
+        return Err(SysError::NotPermitted);
+    }
+    let path = path.read(token).ok_or(SysError::Fault)?;
     let mut inner = process.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        return -1;
+    let root = inner.resolve_path(path.as_str());
+    check_dir(root.as_str())?;
+    inner.root = root;
+    inner.cwd = String::from("/");
+    Ok(0)
+}
+
+const MS_RDONLY: usize = 1;
+const MS_SYNCHRONOUS: usize = 1 << 4;
+const MS_REMOUNT: usize = 1 << 5;
+const MS_NOATIME: usize = 1 << 10;
+
+/// Mount the file system `fstype` on the disk `source` at the directory
+/// `target`, or change how the one at `target` is mounted with `MS_REMOUNT`,
+/// when `source` and `fstype` are ignored. Only the flags of Linux
+/// `MS_RDONLY`, `MS_SYNCHRONOUS` and `MS_NOATIME` are used. Only root may
+/// mount.
+pub fn sys_mount(
+    source: UserCString,
+    target: UserCString,
+    fstype: UserCString,
+    flags: usize,
+) -> SysResult {
+    let token = current_user_token();
+    let process = current_process();
+    if current_uid() != 0 {
+        return Err(SysError::NotPermitted);
+    }
+    let target = target.read(token).ok_or(SysError::Fault)?;
+    let target = process
+        .inner_exclusive_access()
+        .resolve_path(target.as_str());
+    let mut mount_flags = MountFlags::empty();
+    if flags & MS_RDONLY != 0 {
+        mount_flags |= MountFlags::RDONLY;
+    }
+    if flags & MS_SYNCHRONOUS != 0 {
+        mount_flags |= MountFlags::SYNC;
+    }
+    if flags & MS_NOATIME != 0 {
+        mount_flags |= MountFlags::NOATIME;
+    }
+    if flags & MS_REMOUNT != 0 {
+        remount(target.as_str(), mount_flags)
+    } else {
+        let (source, fstype) = match (source.read(token), fstype.read(token)) {
+            (Some(source), Some(fstype)) => (source, fstype),
+            _ => return Err(SysError::Fault),
+        };
+        let source = process
+            .inner_exclusive_access()
+            .resolve_path(source.as_str());
+        mount(
+            source.as_str(),
+            target.as_str(),
+            fstype.as_str(),
+            mount_flags,
+        )
+    }?;
+    Ok(0)
+}
+
+/// Unmount the file system at `target`, which fails if it is busy. No flags
+/// are supported.
+pub fn sys_umount(target: UserCString, flags: usize) -> SysResult {
+    let process = current_process();
+    if current_uid() != 0 {
+        return Err(SysError::NotPermitted);
+    }
+    if flags != 0 {
+        return Err(SysError::Invalid);
+    }
+    let target = target.read(current_user_token()).ok_or(SysError::Fault)?;
+    let target = process
+        .inner_exclusive_access()
+        .resolve_path(target.as_str());
+    umount(target.as_str())?;
+    Ok(0)
+}
+
+const Q_GETQUOTA: usize = 0x800007;
+const Q_SETQUOTA: usize = 0x800008;
+
+/// Get the usage and the limits of the user `uid` in the root file system,
+/// or set the limits from `quota`, 0 for none. Only root may set them, or
+/// get them of others. The limits are lost on reboot.
+pub fn sys_quotactl(cmd: usize, uid: usize, quota: UserSliceRef<Quota>) -> SysResult {
+    let token = current_user_token();
+    let current = current_uid() as usize;
+    if uid > u16::MAX as usize {
+        return Err(SysError::Invalid);
+    }
+    if current != 0 && (cmd != Q_GETQUOTA || uid != current) {
+        return Err(SysError::NotPermitted);
+    }
+    // ENOSYS of Linux if the file system has no quotas
+    let sb = root_super_block();
+    match cmd {
+        Q_GETQUOTA => {
+            let usage = sb.quota(uid as u16).ok_or(SysError::NoSys)?;
+            quota.set(token, 0, usage).ok_or(SysError::Fault)?;
+            Ok(0)
+        }
+        Q_SETQUOTA => {
+            let limits = quota.get(token, 0).ok_or(SysError::Fault)?;
+            if sb.set_quota_limits(uid as u16, limits.block_limit, limits.inode_limit) {
+                Ok(0)
+            } else {
+                Err(SysError::NoSys)
+            }
+        }
+        _ => Err(SysError::Invalid),
+    }
+}
+
+/// When a wait of `timeout_ms` from now times out, None if it is negative.
+fn deadline_ns(timeout_ms: isize) -> Option<usize> {
+    usize::try_from(timeout_ms)
+        .ok()
+        .map(|timeout_ms| get_time_ns().saturating_add(timeout_ms.saturating_mul(1_000_000)))
+}
+
+/// Poll the files of `poll_fds` until some of them have events, at most
+/// `timeout_ms` or forever if it is negative. The task sleeps meanwhile
+/// unless some files take no ready hooks. Return the number of fds with
+/// non-empty revents, or `Interrupted` if a signal comes first.
+fn poll_files(poll_fds: &mut [PollFd], timeout_ms: isize) -> SysResult {
+    let expire_ns = deadline_ns(timeout_ms);
+    let fd_table = current_process().fd_table.read();
+    let files: Vec<Option<Arc<dyn File + Send + Sync>>> = poll_fds
+        .iter()
+        .map(|poll_fd| {
+            usize::try_from(poll_fd.fd)
+                .ok()
+                .and_then(|fd| fd_table.get(fd).cloned())
+        })
+        .collect();
+    drop(fd_table);
+    let mut poller: Option<Poller> = None;
+    loop {
+        let mut ready = 0;
+        for (poll_fd, file) in poll_fds.iter_mut().zip(files.iter()) {
+            poll_fd.revents = match file {
+                Some(file) => file.poll(poll_fd.events),
+                // ignored
+                None if poll_fd.fd < 0 => PollEvents::empty(),
+                None => PollEvents::POLLNVAL,
+            };
+            if !poll_fd.revents.is_empty() {
+                ready += 1;
+            }
+        }
+        if ready > 0 || expire_ns.map_or(false, |expire_ns| get_time_ns() >= expire_ns) {
+            return Ok(ready);
+        }
+        match &poller {
+            // polled again once the hooks are added
+            None => {
+                let mut new_poller = Poller::new();
+                for file in files.iter().flatten() {
+                    new_poller.add(file);
+                }
+                poller = Some(new_poller);
+            }
+            Some(poller) => {
+                if !poller.wait(expire_ns) {
+                    return Err(SysError::Interrupted);
+                }
+            }
+        }
+    }
+}
+
+/// Wait for some events on the fds, a negative `timeout_ms` means forever.
+/// Return the number of fds with non-empty revents, or `Interrupted` if a
+/// signal comes first.
+pub fn sys_ppoll(fds: UserSliceRef<PollFd>, timeout_ms: isize) -> SysResult {
+    let token = current_user_token();
+    let mut poll_fds = fds.read(token).ok_or(SysError::Fault)?;
+    let ready = poll_files(&mut poll_fds, timeout_ms)?;
+    fds.write(token, &poll_fds).ok_or(SysError::Fault)?;
+    Ok(ready)
+}
+
+/// Wait like `sys_ppoll` for the fds below `nfds` in `readfds` to be
+/// readable or those in `writefds` writable, then leave only the ready ones
+/// in the sets. Any set may be null. `exceptfds` is cleared, as no file has
+/// exceptional conditions. Return the number of fds in the sets, `BadFd` if
+/// some fd is not open, or `Interrupted` if a signal comes first.
+pub fn sys_pselect(
+    nfds: usize,
+    readfds: UserSliceRef<FdSet>,
+    writefds: UserSliceRef<FdSet>,
+    exceptfds: UserSliceRef<FdSet>,
+    timeout_ms: isize,
+) -> SysResult {
+    let token = current_user_token();
+    let get_set = |set: &UserSliceRef<FdSet>| {
+        if set.is_null() {
+            Some(FdSet::default())
+        } else {
+            set.get(token, 0)
+        }
+    };
+    let (read_set, write_set) = match (get_set(&readfds), get_set(&writefds)) {
+        (Some(read_set), Some(write_set)) => (read_set, write_set),
+        _ => return Err(SysError::Fault),
+    };
+    let mut poll_fds: Vec<PollFd> = (0..nfds.min(FD_SETSIZE))
+        .filter_map(|fd| {
+            let mut events = PollEvents::empty();
+            events.set(PollEvents::POLLIN, read_set.contains(fd));
+            events.set(PollEvents::POLLOUT, write_set.contains(fd));
+            (!events.is_empty()).then_some(PollFd {
+                fd: fd as i32,
+                events,
+                revents: PollEvents::empty(),
+            })
+        })
+        .collect();
+    poll_files(&mut poll_fds, timeout_ms)?;
+    let mut read_ready = FdSet::default();
+    let mut write_ready = FdSet::default();
+    let mut ready = 0;
+    for poll_fd in poll_fds.iter() {
+        if poll_fd.revents.contains(PollEvents::POLLNVAL) {
+            return Err(SysError::BadFd);
+        }
+        let fd = poll_fd.fd as usize;
+        // at the end or failed, a read or write returns without waiting too
+        if poll_fd.events.contains(PollEvents::POLLIN)
+            && poll_fd
+                .revents
+                .intersects(PollEvents::POLLIN | PollEvents::POLLHUP | PollEvents::POLLERR)
+        {
+            read_ready.insert(fd);
+            ready += 1;
+        }
+        if poll_fd.events.contains(PollEvents::POLLOUT)
+            && poll_fd
+                .revents
+                .intersects(PollEvents::POLLOUT | PollEvents::POLLERR)
+        {
+            write_ready.insert(fd);
+            ready += 1;
+        }
     }
-    if inner.fd_table[fd].is_none() {
-        return -1;
+    let written = [
+        (readfds, read_ready),
+        (writefds, write_ready),
+        (exceptfds, FdSet::default()),
+    ]
+    .iter()
+    .all(|(set, value)| set.is_null() || set.set(token, 0, *value).is_some());
+    if !written {
+        return Err(SysError::Fault);
     }
-    let new_fd = inner.alloc_fd();
-    inner.fd_table[new_fd] = Some(Arc::clone(inner.fd_table[fd].as_ref().unwrap()));
-    new_fd as isize
+    Ok(ready)
 }
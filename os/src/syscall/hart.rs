@@ -0,0 +1,33 @@
+use crate::hart::{cpu_down, cpu_up};
+use crate::suspend::suspend;
+use crate::task::current_uid;
+use abi::{SysError, SysResult};
+
+/// Bring a secondary hart online, only root may.
+pub fn sys_cpu_up(hart_id: usize) -> SysResult {
+    if current_uid() != 0 {
+        return Err(SysError::NotPermitted);
+    }
+    if cpu_up(hart_id) {
+        Ok(0)
+    } else {
+        Err(SysError::Invalid)
+    }
+}
+
+/// Take a secondary hart offline, the boot hart cannot. Only root may.
+pub fn sys_cpu_down(hart_id: usize) -> SysResult {
+    if current_uid() != 0 {
+        return Err(SysError::NotPermitted);
+    }
+    if cpu_down(hart_id) {
+        Ok(0)
+    } else {
+        Err(SysError::Invalid)
+    }
+}
+
+/// Suspend the system to RAM until an interrupt, fail if the SBI cannot.
+pub fn sys_suspend() -> SysResult {
+    suspend().map(|()| 0).map_err(|_| SysError::Failed)
+}
@@ -0,0 +1,750 @@
+//! A compatibility layer for the syscalls of Linux on RISC-V, so that simple
+//! statically linked musl binaries can run unmodified. Syscalls are mapped
+//! onto the implementations of rCore, translating the arguments and structs,
+//! and errors are returned as negative errnos.
+
+use super::epoll::*;
+use super::fs::*;
+use super::process::*;
+use super::sync::{sys_futex, sys_nanosleep, FUTEX_WAIT, FUTEX_WAKE};
+use crate::config::{PAGE_SIZE, USER_ARGS_MAX, USER_SPACE_END};
+use crate::fs::{
+    make_pipe, CharDevFile, File, OSInode, OpenFlags, Pipe, PollFd, Stdin, Stdout, Tty, F_GETLK,
+    F_SETLK, F_SETLKW,
+};
+use crate::mm::{MapArea, MapPermission, MapType, UserCString, UserSliceRef, VirtAddr};
+use crate::task::{
+    current_process, current_task, current_user_token, current_wake_reason, pid2process,
+    suspend_current_and_run_next, WakeReason, ERESTARTSYS, LINUX_MMAP_BASE,
+};
+use crate::timer::{get_realtime_ns, TimeSpec, CLOCK_MONOTONIC, CLOCK_REALTIME};
+use abi::{Stat, SysError, SysResult};
+use alloc::sync::Arc;
+
+const SYSCALL_GETCWD: usize = 17;
+const SYSCALL_EPOLL_CREATE1: usize = 20;
+const SYSCALL_EPOLL_CTL: usize = 21;
+const SYSCALL_EPOLL_PWAIT: usize = 22;
+const SYSCALL_DUP: usize = 23;
+const SYSCALL_DUP3: usize = 24;
+const SYSCALL_FCNTL: usize = 25;
+const SYSCALL_IOCTL: usize = 29;
+const SYSCALL_FLOCK: usize = 32;
+const SYSCALL_MKDIRAT: usize = 34;
+const SYSCALL_UNLINKAT: usize = 35;
+const SYSCALL_UMOUNT2: usize = 39;
+const SYSCALL_MOUNT: usize = 40;
+const SYSCALL_FTRUNCATE: usize = 46;
+const SYSCALL_CHDIR: usize = 49;
+const SYSCALL_CHROOT: usize = 51;
+const SYSCALL_OPENAT: usize = 56;
+const SYSCALL_CLOSE: usize = 57;
+const SYSCALL_PIPE2: usize = 59;
+const SYSCALL_GETDENTS64: usize = 61;
+const SYSCALL_LSEEK: usize = 62;
+const SYSCALL_READ: usize = 63;
+const SYSCALL_WRITE: usize = 64;
+const SYSCALL_READV: usize = 65;
+const SYSCALL_WRITEV: usize = 66;
+const SYSCALL_SENDFILE: usize = 71;
+const SYSCALL_PSELECT6: usize = 72;
+const SYSCALL_PPOLL: usize = 73;
+const SYSCALL_FSTAT: usize = 80;
+const SYSCALL_SYNC: usize = 81;
+const SYSCALL_FSYNC: usize = 82;
+const SYSCALL_FDATASYNC: usize = 83;
+const SYSCALL_PERSONALITY: usize = 92;
+const SYSCALL_EXIT: usize = 93;
+const SYSCALL_EXIT_GROUP: usize = 94;
+const SYSCALL_SET_TID_ADDRESS: usize = 96;
+const SYSCALL_FUTEX: usize = 98;
+const SYSCALL_NANOSLEEP: usize = 101;
+const SYSCALL_CLOCK_GETTIME: usize = 113;
+const SYSCALL_SCHED_YIELD: usize = 124;
+const SYSCALL_KILL: usize = 129;
+const SYSCALL_RT_SIGACTION: usize = 134;
+const SYSCALL_RT_SIGPROCMASK: usize = 135;
+const SYSCALL_SETPRIORITY: usize = 140;
+const SYSCALL_GETPRIORITY: usize = 141;
+const SYSCALL_SETUID: usize = 146;
+const SYSCALL_SETPGID: usize = 154;
+const SYSCALL_GETPGID: usize = 155;
+const SYSCALL_GETSID: usize = 156;
+const SYSCALL_SETSID: usize = 157;
+const SYSCALL_UNAME: usize = 160;
+const SYSCALL_GETTIMEOFDAY: usize = 169;
+const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETPPID: usize = 173;
+const SYSCALL_GETUID: usize = 174;
+const SYSCALL_GETEUID: usize = 175;
+const SYSCALL_GETGID: usize = 176;
+const SYSCALL_GETEGID: usize = 177;
+const SYSCALL_GETTID: usize = 178;
+const SYSCALL_BRK: usize = 214;
+const SYSCALL_MUNMAP: usize = 215;
+const SYSCALL_CLONE: usize = 220;
+const SYSCALL_EXECVE: usize = 221;
+const SYSCALL_MMAP: usize = 222;
+const SYSCALL_MPROTECT: usize = 226;
+const SYSCALL_MADVISE: usize = 233;
+const SYSCALL_WAIT4: usize = 260;
+const SYSCALL_PRLIMIT64: usize = 261;
+const SYSCALL_CLOSE_RANGE: usize = 436;
+
+const ESRCH: isize = 3;
+const EBADF: isize = 9;
+const ECHILD: isize = 10;
+const ENOMEM: isize = 12;
+const EFAULT: isize = 14;
+const EINVAL: isize = 22;
+const EMFILE: isize = 24;
+const ENOSYS: isize = 38;
+
+const AT_FDCWD: isize = -100;
+
+const O_ACCMODE: u32 = 0o3;
+const O_CREAT: u32 = 0o100;
+const O_EXCL: u32 = 0o200;
+const O_TRUNC: u32 = 0o1000;
+const O_APPEND: u32 = 0o2000;
+const O_NONBLOCK: u32 = 0o4000;
+const O_DIRECT: u32 = 0o40000;
+const O_CLOEXEC: u32 = 0o2000000;
+
+const S_IFIFO: u32 = 0o010000;
+const S_IFCHR: u32 = 0o020000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+const PROT_READ: usize = 0x1;
+const PROT_WRITE: usize = 0x2;
+const PROT_EXEC: usize = 0x4;
+const MAP_FIXED: usize = 0x10;
+const MAP_ANONYMOUS: usize = 0x20;
+
+const FUTEX_PRIVATE_FLAG: usize = 128;
+
+const CLONE_VM: usize = 0x100;
+const WNOHANG: usize = 1;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Iovec {
+    base: usize,
+    len: usize,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Timeval {
+    sec: usize,
+    usec: usize,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Utsname {
+    sysname: [u8; 65],
+    nodename: [u8; 65],
+    release: [u8; 65],
+    version: [u8; 65],
+    machine: [u8; 65],
+    domainname: [u8; 65],
+}
+
+pub fn linux_syscall(syscall_id: usize, args: [usize; 6]) -> isize {
+    match syscall_id {
+        SYSCALL_GETCWD => errno(sys_getcwd(UserSliceRef::new(args[0], args[1]))),
+        SYSCALL_EPOLL_CREATE1 => errno(sys_epoll_create(args[0] as u32)),
+        SYSCALL_EPOLL_CTL => errno(sys_epoll_ctl(
+            args[0],
+            args[1],
+            args[2],
+            UserSliceRef::one(args[3]),
+        )),
+        // the signal mask is ignored
+        SYSCALL_EPOLL_PWAIT => errno(sys_epoll_wait(
+            args[0],
+            UserSliceRef::new(args[1], args[2]),
+            args[3] as i32 as isize,
+        )),
+        SYSCALL_DUP => errno(sys_dup(args[0])),
+        SYSCALL_DUP3 => linux_dup3(args[0], args[1], args[2] as u32),
+        SYSCALL_FCNTL => linux_fcntl(args[0], args[1], args[2]),
+        SYSCALL_IOCTL => errno(sys_ioctl(args[0], args[1], args[2])),
+        SYSCALL_FLOCK => match get_file(args[0]) {
+            Some(_) => errno(sys_flock(args[0], args[1])),
+            None => -EBADF,
+        },
+        SYSCALL_MKDIRAT => match at_fdcwd(args[0]) {
+            Ok(()) => errno(sys_mkdir(UserCString::new(args[1]))),
+            Err(err) => err,
+        },
+        SYSCALL_UNLINKAT => match at_fdcwd(args[0]) {
+            Ok(()) => errno(sys_unlinkat(UserCString::new(args[1]), args[2] as u32)),
+            Err(err) => err,
+        },
+        SYSCALL_UMOUNT2 => errno(sys_umount(UserCString::new(args[0]), args[1])),
+        SYSCALL_MOUNT => errno(sys_mount(
+            UserCString::new(args[0]),
+            UserCString::new(args[1]),
+            UserCString::new(args[2]),
+            args[3],
+        )),
+        SYSCALL_FTRUNCATE => errno(sys_ftruncate(args[0], args[1])),
+        SYSCALL_CHDIR => errno(sys_chdir(UserCString::new(args[0]))),
+        SYSCALL_CHROOT => errno(sys_chroot(UserCString::new(args[0]))),
+        SYSCALL_OPENAT => match at_fdcwd(args[0]) {
+            Ok(()) => errno(sys_open(
+                UserCString::new(args[1]),
+                open_flags(args[2] as u32),
+            )),
+            Err(err) => err,
+        },
+        SYSCALL_CLOSE => errno(sys_close(args[0])),
+        SYSCALL_PIPE2 => linux_pipe2(UserSliceRef::new(args[0], 2), args[1] as u32),
+        SYSCALL_GETDENTS64 => errno(sys_getdents64(args[0], UserSliceRef::new(args[1], args[2]))),
+        SYSCALL_LSEEK => errno(sys_lseek(args[0], args[1] as isize, args[2])),
+        SYSCALL_READ => linux_read(args[0], args[1], args[2]),
+        SYSCALL_WRITE => errno(sys_write(args[0], UserSliceRef::new(args[1], args[2]))),
+        SYSCALL_READV => linux_readv(args[0], UserSliceRef::new(args[1], args[2]), false),
+        SYSCALL_WRITEV => linux_readv(args[0], UserSliceRef::new(args[1], args[2]), true),
+        SYSCALL_SENDFILE => errno(sys_sendfile(
+            args[0],
+            args[1],
+            UserSliceRef::one(args[2]),
+            args[3],
+        )),
+        SYSCALL_PSELECT6 => linux_pselect6(args),
+        SYSCALL_PPOLL => linux_ppoll(
+            UserSliceRef::new(args[0], args[1]),
+            UserSliceRef::one(args[2]),
+        ),
+        SYSCALL_FSTAT => linux_fstat(args[0], UserSliceRef::one(args[1])),
+        SYSCALL_SYNC => errno(sys_sync()),
+        SYSCALL_FSYNC | SYSCALL_FDATASYNC => errno(sys_fdatasync(args[0])),
+        SYSCALL_PERSONALITY => errno(sys_personality(args[0])),
+        // NOTICE: other threads are not terminated if a thread exits the group
+        SYSCALL_EXIT | SYSCALL_EXIT_GROUP => sys_exit(args[0] as i32),
+        // the tid is not cleared when the thread exits
+        SYSCALL_SET_TID_ADDRESS | SYSCALL_GETTID => current_tid() as isize,
+        SYSCALL_FUTEX => linux_futex(args[0], args[1], args[2], UserSliceRef::one(args[3])),
+        SYSCALL_NANOSLEEP => {
+            match UserSliceRef::<TimeSpec>::one(args[0]).get(current_user_token(), 0) {
+                Some(req) if req.nsec >= 1_000_000_000 => -EINVAL,
+                Some(req) => errno(sys_nanosleep(
+                    req.sec.saturating_mul(1_000_000_000) + req.nsec,
+                )),
+                None => -EFAULT,
+            }
+        }
+        SYSCALL_CLOCK_GETTIME => match linux_clock(args[0]) {
+            Some(clock_id) => errno(sys_clock_gettime(clock_id, UserSliceRef::one(args[1]))),
+            None => -EINVAL,
+        },
+        SYSCALL_SCHED_YIELD => errno(sys_yield()),
+        SYSCALL_KILL => linux_kill(args[0] as isize, args[1]),
+        // the handlers of Linux return through `sa_restorer` rather than
+        // `sigreturn`, so they are not supported
+        SYSCALL_RT_SIGACTION | SYSCALL_RT_SIGPROCMASK => -ENOSYS,
+        SYSCALL_SETPRIORITY => errno(sys_setpriority(args[0], args[1], args[2] as isize)),
+        SYSCALL_GETPRIORITY => errno(sys_getpriority(args[0], args[1])),
+        SYSCALL_SETUID => errno(sys_setuid(args[0])),
+        SYSCALL_SETPGID => errno(sys_setpgid(args[0], args[1])),
+        SYSCALL_GETPGID => errno(sys_getpgid(args[0])),
+        SYSCALL_GETSID => errno(sys_getsid(args[0])),
+        SYSCALL_SETSID => errno(sys_setsid()),
+        SYSCALL_UNAME => {
+            let uts = Utsname {
+                sysname: uts_field("rCore"),
+                nodename: uts_field("rcore"),
+                release: uts_field("0.1.0"),
+                version: uts_field("rCore-Tutorial-v3"),
+                machine: uts_field("riscv64"),
+                domainname: uts_field("(none)"),
+            };
+            efault(UserSliceRef::one(args[0]).set(current_user_token(), 0, uts))
+        }
+        SYSCALL_GETTIMEOFDAY => {
+            let us = get_realtime_ns() / 1000;
+            let tv = UserSliceRef::one(args[0]);
+            if tv.is_null() {
+                return 0;
+            }
+            let timeval = Timeval {
+                sec: us / 1_000_000,
+                usec: us % 1_000_000,
+            };
+            efault(tv.set(current_user_token(), 0, timeval))
+        }
+        SYSCALL_GETPID => errno(sys_getpid()),
+        SYSCALL_GETPPID => current_process()
+            .inner_exclusive_access()
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade())
+            .map_or(0, |parent| parent.getpid() as isize),
+        // there are no groups, and no effective user other than the real one
+        SYSCALL_GETUID | SYSCALL_GETEUID => errno(sys_getuid()),
+        SYSCALL_GETGID | SYSCALL_GETEGID => 0,
+        SYSCALL_BRK => linux_brk(args[0]),
+        SYSCALL_MUNMAP => linux_munmap(args[0]),
+        SYSCALL_CLONE => {
+            // only fork is supported, threads should be created natively
+            if args[0] & CLONE_VM != 0 {
+                -ENOSYS
+            } else {
+                errno(sys_fork())
+            }
+        }
+        // the environment variables are dropped
+        SYSCALL_EXECVE => errno(sys_exec(
+            UserCString::new(args[0]),
+            UserSliceRef::new(args[1], USER_ARGS_MAX),
+        )),
+        SYSCALL_MMAP => linux_mmap(args[1], args[2], args[3]),
+        // the permissions of mappings are not changed
+        SYSCALL_MPROTECT | SYSCALL_MADVISE => 0,
+        SYSCALL_WAIT4 => linux_wait4(args[0] as isize, UserSliceRef::one(args[1]), args[2]),
+        SYSCALL_PRLIMIT64 => errno(sys_prlimit(
+            args[0],
+            args[1],
+            UserSliceRef::one(args[2]),
+            UserSliceRef::one(args[3]),
+        )),
+        SYSCALL_CLOSE_RANGE => errno(sys_close_range(args[0], args[1], args[2] as u32)),
+        _ => {
+            warn!("[kernel] Unsupported Linux syscall_id: {}", syscall_id);
+            -ENOSYS
+        }
+    }
+}
+
+/// The clock for a clock id of Linux, the coarse and raw ones are the same
+/// as the precise ones.
+fn linux_clock(clock_id: usize) -> Option<usize> {
+    match clock_id {
+        // CLOCK_REALTIME, CLOCK_REALTIME_COARSE
+        0 | 5 => Some(CLOCK_REALTIME),
+        // CLOCK_MONOTONIC, CLOCK_MONOTONIC_RAW, CLOCK_MONOTONIC_COARSE, CLOCK_BOOTTIME
+        1 | 4 | 6 | 7 => Some(CLOCK_MONOTONIC),
+        _ => None,
+    }
+}
+
+/// Map the errors returned by rCore to the errnos of Linux, see
+/// `SysError::linux_errno`. A syscall to restart is left as `ERESTARTSYS`
+/// for the signal handling.
+fn errno(result: SysResult) -> isize {
+    match result {
+        Ok(value) => value as isize,
+        Err(error) => -error.linux_errno(),
+    }
+}
+
+/// Map a bad pointer to EFAULT, see `UserSliceRef`.
+fn efault(done: Option<()>) -> isize {
+    match done {
+        Some(()) => 0,
+        None => -EFAULT,
+    }
+}
+
+/// Paths relative to directory fds are not supported.
+fn at_fdcwd(dirfd: usize) -> Result<(), isize> {
+    if dirfd as isize == AT_FDCWD {
+        Ok(())
+    } else {
+        Err(-EINVAL)
+    }
+}
+
+fn open_flags(flags: u32) -> u32 {
+    let mut open_flags = OpenFlags::from_bits_truncate(flags & O_ACCMODE);
+    if flags & O_CREAT != 0 {
+        open_flags |= OpenFlags::CREATE;
+    }
+    if flags & O_EXCL != 0 {
+        open_flags |= OpenFlags::EXCL;
+    }
+    if flags & O_TRUNC != 0 {
+        open_flags |= OpenFlags::TRUNC;
+    }
+    if flags & O_APPEND != 0 {
+        open_flags |= OpenFlags::APPEND;
+    }
+    if flags & O_NONBLOCK != 0 {
+        open_flags |= OpenFlags::NONBLOCK;
+    }
+    if flags & O_DIRECT != 0 {
+        open_flags |= OpenFlags::DIRECT;
+    }
+    if flags & O_CLOEXEC != 0 {
+        open_flags |= OpenFlags::CLOEXEC;
+    }
+    open_flags.bits()
+}
+
+fn uts_field(value: &str) -> [u8; 65] {
+    let mut field = [0; 65];
+    field[..value.len()].copy_from_slice(value.as_bytes());
+    field
+}
+
+fn current_tid() -> usize {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .unwrap()
+        .tid
+}
+
+fn get_file(fd: usize) -> Option<Arc<dyn File + Send + Sync>> {
+    let process = current_process();
+    let fd_table = process.fd_table.read();
+    fd_table.get(fd).cloned()
+}
+
+fn linux_dup3(old_fd: usize, new_fd: usize, flags: u32) -> isize {
+    let file = match get_file(old_fd) {
+        Some(file) => file,
+        None => return -EBADF,
+    };
+    if old_fd == new_fd || flags & !O_CLOEXEC != 0 {
+        return -EINVAL;
+    }
+    let process = current_process();
+    let mut fd_table = process.fd_table.write();
+    if !fd_table.insert(new_fd, file) {
+        return -EBADF;
+    }
+    let open_flags = OpenFlags::from_bits_truncate(open_flags(flags));
+    fd_table.set_flags(new_fd, fd_flags(open_flags));
+    new_fd as isize
+}
+
+/// Status flags not supported by a file are ignored, so are other commands.
+fn linux_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    if get_file(fd).is_none() {
+        return -EBADF;
+    }
+    match cmd {
+        F_DUPFD | F_DUPFD_CLOEXEC => errno(sys_fcntl(fd, cmd, arg)),
+        F_GETFD | F_SETFD => errno(sys_fcntl(fd, cmd, arg)),
+        F_GETFL => {
+            let flags = sys_fcntl(fd, cmd, arg).unwrap_or_default();
+            let flags = OpenFlags::from_bits_truncate(flags as u32);
+            let mut linux_flags = (flags & (OpenFlags::WRONLY | OpenFlags::RDWR)).bits();
+            if flags.contains(OpenFlags::APPEND) {
+                linux_flags |= O_APPEND;
+            }
+            if flags.contains(OpenFlags::NONBLOCK) {
+                linux_flags |= O_NONBLOCK;
+            }
+            if flags.contains(OpenFlags::DIRECT) {
+                linux_flags |= O_DIRECT;
+            }
+            linux_flags as isize
+        }
+        F_SETFL => errno(sys_fcntl(fd, cmd, open_flags(arg as u32) as usize)),
+        F_GETLK | F_SETLK | F_SETLKW | F_SETPIPE_SZ | F_GETPIPE_SZ => {
+            errno(sys_fcntl(fd, cmd, arg))
+        }
+        _ => -EINVAL,
+    }
+}
+
+fn linux_pipe2(pipe: UserSliceRef<i32>, flags: u32) -> isize {
+    let open_flags = OpenFlags::from_bits_truncate(open_flags(flags));
+    let process = current_process();
+    let token = current_user_token();
+    let mut fd_table = process.fd_table.write();
+    let (pipe_read, pipe_write) = make_pipe();
+    pipe_read.set_status(open_flags & OpenFlags::NONBLOCK);
+    pipe_write.set_status(open_flags & OpenFlags::NONBLOCK);
+    let read_fd = match fd_table.alloc(pipe_read) {
+        Some(fd) => fd,
+        None => return -EMFILE,
+    };
+    let write_fd = match fd_table.alloc(pipe_write) {
+        Some(fd) => fd,
+        None => {
+            fd_table.remove(read_fd);
+            return -EMFILE;
+        }
+    };
+    fd_table.set_flags(read_fd, fd_flags(open_flags));
+    fd_table.set_flags(write_fd, fd_flags(open_flags));
+    drop(fd_table);
+    if pipe
+        .write(token, &[read_fd as i32, write_fd as i32])
+        .is_none()
+    {
+        let mut fd_table = process.fd_table.write();
+        fd_table.remove(read_fd);
+        fd_table.remove(write_fd);
+        return -EFAULT;
+    }
+    0
+}
+
+fn linux_read(fd: usize, buf: usize, len: usize) -> isize {
+    let file = match get_file(fd) {
+        Some(file) => file,
+        None => return -EBADF,
+    };
+    // the console is read byte by byte
+    let len = if file.as_any().is::<Stdin>() || file.as_any().is::<Tty>() {
+        len.min(1)
+    } else {
+        len
+    };
+    if len == 0 {
+        return 0;
+    }
+    errno(sys_read(fd, UserSliceRef::new(buf, len)))
+}
+
+/// Stop at the first short read or write.
+fn linux_readv(fd: usize, iov: UserSliceRef<Iovec>, write: bool) -> isize {
+    let token = current_user_token();
+    let iov = match iov.read(token) {
+        Some(iov) => iov,
+        None => return -EFAULT,
+    };
+    let mut total = 0;
+    for iovec in iov.iter() {
+        let ret = if write {
+            errno(sys_write(fd, UserSliceRef::new(iovec.base, iovec.len)))
+        } else {
+            linux_read(fd, iovec.base, iovec.len)
+        };
+        if ret < 0 {
+            return if total > 0 { total } else { ret };
+        }
+        total += ret;
+        if (ret as usize) < iovec.len {
+            break;
+        }
+    }
+    total
+}
+
+/// The timeout of ppoll and pselect6 in ms, -1 for forever if it is null.
+/// None if it cannot be read.
+fn linux_timeout_ms(timeout: UserSliceRef<TimeSpec>) -> Option<isize> {
+    if timeout.is_null() {
+        return Some(-1);
+    }
+    timeout
+        .get(current_user_token(), 0)
+        .map(|timeout| (timeout.sec * 1000 + timeout.nsec / 1_000_000) as isize)
+}
+
+/// The signal mask is ignored.
+fn linux_ppoll(fds: UserSliceRef<PollFd>, timeout: UserSliceRef<TimeSpec>) -> isize {
+    match linux_timeout_ms(timeout) {
+        Some(timeout_ms) => errno(sys_ppoll(fds, timeout_ms)),
+        None => -EFAULT,
+    }
+}
+
+/// The signal mask is ignored, and the timeout is not updated.
+fn linux_pselect6(args: [usize; 6]) -> isize {
+    match linux_timeout_ms(UserSliceRef::one(args[4])) {
+        Some(timeout_ms) => errno(sys_pselect(
+            args[0],
+            UserSliceRef::one(args[1]),
+            UserSliceRef::one(args[2]),
+            UserSliceRef::one(args[3]),
+            timeout_ms,
+        )),
+        None => -EFAULT,
+    }
+}
+
+/// A private futex is the same as a shared one, keyed by the physical
+/// address either way.
+fn linux_futex(uaddr: usize, op: usize, val: usize, timeout: UserSliceRef<TimeSpec>) -> isize {
+    let op = op & !FUTEX_PRIVATE_FLAG;
+    let timeout_ns = if op != FUTEX_WAIT || timeout.is_null() {
+        -1
+    } else {
+        match timeout.get(current_user_token(), 0) {
+            Some(timeout) if timeout.nsec >= 1_000_000_000 => return -EINVAL,
+            Some(timeout) => timeout
+                .sec
+                .saturating_mul(1_000_000_000)
+                .saturating_add(timeout.nsec)
+                .min(isize::MAX as usize) as isize,
+            None => return -EFAULT,
+        }
+    };
+    if op != FUTEX_WAIT && op != FUTEX_WAKE {
+        return -ENOSYS;
+    }
+    errno(sys_futex(UserSliceRef::one(uaddr), op, val, timeout_ns))
+}
+
+fn linux_fstat(fd: usize, statbuf: UserSliceRef<Stat>) -> isize {
+    let file = match get_file(fd) {
+        Some(file) => file,
+        None => return -EBADF,
+    };
+    let mut stat = Stat {
+        nlink: 1,
+        blksize: 512,
+        ..Stat::default()
+    };
+    if let Some(inode) = file.as_any().downcast_ref::<OSInode>() {
+        let (kind, default_mode) = if inode.is_dir() {
+            (S_IFDIR, 0o755)
+        } else {
+            (S_IFREG, 0o644)
+        };
+        let (uid, mode) = inode.permission().unwrap_or((0, default_mode));
+        stat.mode = kind | mode;
+        stat.uid = uid;
+        stat.size = inode.size() as i64;
+        stat.blocks = (inode.size() as i64 + 511) / 512;
+        let (atime, mtime) = inode.times();
+        stat.atime = [atime as i64, 0];
+        stat.mtime = [mtime as i64, 0];
+    } else if file.as_any().is::<Pipe>() {
+        stat.mode = S_IFIFO | 0o600;
+    } else if file.as_any().is::<Stdin>()
+        || file.as_any().is::<Stdout>()
+        || file.as_any().is::<Tty>()
+    {
+        stat.mode = S_IFCHR | 0o620;
+    } else if file.as_any().is::<CharDevFile>() {
+        stat.mode = S_IFCHR | 0o666;
+    }
+    efault(statbuf.set(current_user_token(), 0, stat))
+}
+
+/// Signals are mapped to `SignalFlags` by their numbers.
+fn linux_kill(pid: isize, signum: usize) -> isize {
+    if pid <= 0 {
+        return -EINVAL;
+    }
+    if pid2process(pid as usize).is_none() {
+        return -ESRCH;
+    }
+    match signum {
+        0 => 0,
+        1..=31 => errno(sys_kill(pid as usize, 1 << signum)),
+        _ => -EINVAL,
+    }
+}
+
+fn linux_wait4(pid: isize, wstatus: UserSliceRef<i32>, options: usize) -> isize {
+    // process groups are not supported, wait for any child instead
+    let pid = if pid <= 0 { -1 } else { pid };
+    loop {
+        match wait_child(pid) {
+            Ok((found_pid, exit_code)) => {
+                let wstatus_value = (exit_code & 0xff) << 8;
+                if !wstatus.is_null()
+                    && wstatus
+                        .set(current_user_token(), 0, wstatus_value)
+                        .is_none()
+                {
+                    return -EFAULT;
+                }
+                return found_pid as isize;
+            }
+            Err(SysError::NotReady) => {
+                if options & WNOHANG != 0 {
+                    return 0;
+                }
+                suspend_current_and_run_next();
+                if current_wake_reason() != WakeReason::Woken {
+                    return ERESTARTSYS;
+                }
+            }
+            Err(_) => return -ECHILD,
+        }
+    }
+}
+
+fn page_ceil(addr: usize) -> usize {
+    (addr + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+}
+
+/// The heap is never shrunk.
+fn linux_brk(brk: usize) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let inner = &mut *inner;
+    let linux = inner.linux.as_mut().unwrap();
+    if brk < linux.brk || brk >= LINUX_MMAP_BASE {
+        return linux.brk as isize;
+    }
+    let brk_mapped = page_ceil(brk);
+    if brk_mapped > linux.brk_mapped {
+        // an area restored from a checkpoint may be in the way
+        if !inner.memory_set.insert_area(MapArea::new(
+            VirtAddr::from(linux.brk_mapped),
+            VirtAddr::from(brk_mapped),
+            MapType::Framed,
+            MapPermission::R | MapPermission::W | MapPermission::U,
+        )) {
+            return linux.brk as isize;
+        }
+        linux.brk_mapped = brk_mapped;
+    }
+    linux.brk = brk;
+    brk as isize
+}
+
+/// Only anonymous mappings are supported, and they are placed one after
+/// another without reusing the unmapped ones.
+fn linux_mmap(len: usize, prot: usize, flags: usize) -> isize {
+    if len == 0 || flags & MAP_ANONYMOUS == 0 || flags & MAP_FIXED != 0 {
+        return -EINVAL;
+    }
+    let mut permission = MapPermission::U;
+    if prot & PROT_READ != 0 {
+        permission |= MapPermission::R;
+    }
+    if prot & PROT_WRITE != 0 {
+        permission |= MapPermission::W;
+    }
+    if prot & PROT_EXEC != 0 {
+        permission |= MapPermission::X;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let inner = &mut *inner;
+    let linux = inner.linux.as_mut().unwrap();
+    let start = linux.mmap_end;
+    let end = match start.checked_add(page_ceil(len)) {
+        Some(end) if end < USER_SPACE_END => end,
+        _ => return -ENOMEM,
+    };
+    if !inner.memory_set.insert_area(MapArea::new(
+        VirtAddr::from(start),
+        VirtAddr::from(end),
+        MapType::Framed,
+        permission,
+    )) {
+        return -ENOMEM;
+    }
+    linux.mmap_end = end;
+    start as isize
+}
+
+/// Only whole mappings can be unmapped.
+fn linux_munmap(addr: usize) -> isize {
+    if addr < LINUX_MMAP_BASE || addr % PAGE_SIZE != 0 {
+        return -EINVAL;
+    }
+    current_process()
+        .inner_exclusive_access()
+        .memory_set
+        .remove_area_with_start_vpn(VirtAddr::from(addr).floor());
+    0
+}
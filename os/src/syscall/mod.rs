@@ -1,90 +1,322 @@
+const SYSCALL_GETCWD: usize = 17;
+// epoll_create1 of Linux
+const SYSCALL_EPOLL_CREATE: usize = 20;
+const SYSCALL_EPOLL_CTL: usize = 21;
+// epoll_pwait of Linux without the signal mask
+const SYSCALL_EPOLL_WAIT: usize = 22;
 const SYSCALL_DUP: usize = 24;
+const SYSCALL_FCNTL: usize = 25;
+// ioctl is 29 on Linux, which is taken by connect here
+const SYSCALL_IOCTL: usize = 28;
 const SYSCALL_CONNECT: usize = 29;
 const SYSCALL_LISTEN: usize = 30;
 const SYSCALL_ACCEPT: usize = 31;
+const SYSCALL_FLOCK: usize = 32;
+// mkdirat of Linux without dirfd
+const SYSCALL_MKDIR: usize = 34;
+// unlinkat of Linux without dirfd
+const SYSCALL_UNLINKAT: usize = 35;
+// umount2 of Linux
+const SYSCALL_UMOUNT: usize = 39;
+// mount of Linux without the data
+const SYSCALL_MOUNT: usize = 40;
+const SYSCALL_FTRUNCATE: usize = 46;
+const SYSCALL_CHDIR: usize = 49;
+const SYSCALL_CHROOT: usize = 51;
 const SYSCALL_OPEN: usize = 56;
 const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_PIPE: usize = 59;
+// quotactl of Linux on the root file system without the device
+const SYSCALL_QUOTACTL: usize = 60;
+const SYSCALL_GETDENTS64: usize = 61;
+const SYSCALL_LSEEK: usize = 62;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_SENDFILE: usize = 71;
+// pselect6 of Linux without the signal mask, the timeout in ms
+const SYSCALL_PSELECT: usize = 72;
+const SYSCALL_PPOLL: usize = 73;
+const SYSCALL_SYNC: usize = 81;
+const SYSCALL_FSYNC: usize = 82;
+const SYSCALL_FDATASYNC: usize = 83;
+const SYSCALL_PERSONALITY: usize = 92;
 const SYSCALL_EXIT: usize = 93;
+// futex of Linux with FUTEX_WAIT and FUTEX_WAKE only, the timeout in ns
+const SYSCALL_FUTEX: usize = 98;
 const SYSCALL_SLEEP: usize = 101;
+const SYSCALL_CLOCK_GETTIME: usize = 113;
+// syslog of Linux with SYSLOG_ACTION_READ_ALL only
+const SYSCALL_DMESG: usize = 116;
+const SYSCALL_PTRACE: usize = 117;
 const SYSCALL_YIELD: usize = 124;
 const SYSCALL_KILL: usize = 129;
+// rt_sigaction, rt_sigprocmask and rt_sigreturn of Linux, with a mask of u32
+const SYSCALL_SIGACTION: usize = 134;
+const SYSCALL_SIGPROCMASK: usize = 135;
+const SYSCALL_SIGRETURN: usize = 139;
+const SYSCALL_SETPRIORITY: usize = 140;
+const SYSCALL_GETPRIORITY: usize = 141;
+const SYSCALL_SETUID: usize = 146;
+const SYSCALL_SETPGID: usize = 154;
+const SYSCALL_GETPGID: usize = 155;
+const SYSCALL_GETSID: usize = 156;
+const SYSCALL_SETSID: usize = 157;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETUID: usize = 174;
+const SYSCALL_MQ_OPEN: usize = 180;
+const SYSCALL_MQ_UNLINK: usize = 181;
+// mq_timedsend and mq_timedreceive on Linux, without the timeout
+const SYSCALL_MQ_SEND: usize = 182;
+const SYSCALL_MQ_RECEIVE: usize = 183;
+const SYSCALL_MQ_GETSETATTR: usize = 185;
+const SYSCALL_MUNMAP: usize = 215;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
+const SYSCALL_MMAP: usize = 222;
 const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_PRLIMIT: usize = 261;
+const SYSCALL_RENAME: usize = 276;
+// the same as rCore labs
+const SYSCALL_SPAWN: usize = 400;
+const SYSCALL_PIDFD_OPEN: usize = 434;
+const SYSCALL_CLOSE_RANGE: usize = 436;
+// the same as rCore labs
+const SYSCALL_ENABLE_DEADLOCK_DETECT: usize = 469;
 const SYSCALL_THREAD_CREATE: usize = 1000;
 const SYSCALL_GETTID: usize = 1001;
 const SYSCALL_WAITTID: usize = 1002;
 const SYSCALL_MUTEX_CREATE: usize = 1010;
 const SYSCALL_MUTEX_LOCK: usize = 1011;
 const SYSCALL_MUTEX_UNLOCK: usize = 1012;
+const SYSCALL_MUTEX_LOCK_TIMEOUT: usize = 1013;
 const SYSCALL_SEMAPHORE_CREATE: usize = 1020;
 const SYSCALL_SEMAPHORE_UP: usize = 1021;
 const SYSCALL_SEMAPHORE_DOWN: usize = 1022;
+const SYSCALL_SEMAPHORE_DOWN_TIMEOUT: usize = 1023;
 const SYSCALL_CONDVAR_CREATE: usize = 1030;
 const SYSCALL_CONDVAR_SIGNAL: usize = 1031;
 const SYSCALL_CONDVAR_WAIT: usize = 1032;
+const SYSCALL_CONDVAR_WAIT_TIMEOUT: usize = 1033;
 const SYSCALL_FRAMEBUFFER: usize = 2000;
 const SYSCALL_FRAMEBUFFER_FLUSH: usize = 2001;
 const SYSCALL_EVENT_GET: usize = 3000;
 const SYSCALL_KEY_PRESSED: usize = 3001;
+const SYSCALL_TCP_CONNECT: usize = 4000;
+const SYSCALL_CGROUP_CREATE: usize = 5000;
+const SYSCALL_CGROUP_ATTACH: usize = 5001;
+const SYSCALL_CGROUP_USAGE: usize = 5002;
+const SYSCALL_CHECKPOINT: usize = 6000;
+const SYSCALL_RESTORE: usize = 6001;
+const SYSCALL_MEMUSAGE: usize = 7000;
+const SYSCALL_VMDUMP: usize = 7001;
+const SYSCALL_KLOG_MAP: usize = 7002;
+const SYSCALL_VM_STATS: usize = 7003;
+const SYSCALL_CPU_UP: usize = 8000;
+const SYSCALL_CPU_DOWN: usize = 8001;
+const SYSCALL_SUSPEND: usize = 8002;
+const SYSCALL_READ_TIMEOUT: usize = 9000;
+const SYSCALL_GET_TIME_NS: usize = 9001;
+const SYSCALL_NANOSLEEP: usize = 9002;
 
+mod cgroup;
+mod epoll;
 mod fs;
 mod gui;
+mod hart;
 mod input;
+mod linux;
+mod mqueue;
 mod net;
 mod process;
 mod sync;
 mod thread;
 
+use cgroup::*;
+use epoll::*;
 use fs::*;
 use gui::*;
+use hart::*;
 use input::*;
+use linux::linux_syscall;
+use mqueue::*;
 use net::*;
 use process::*;
 use sync::*;
 use thread::*;
 
-pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
-    match syscall_id {
+use crate::config::USER_ARGS_MAX;
+use crate::mm::{UserCString, UserSliceRef};
+use crate::task::current_process;
+use abi::SysError;
+
+/// Dispatch a syscall of the native ABI, whose handlers return a `SysResult`
+/// encoded here into a0, or hand it to the Linux layer.
+pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
+    if current_process().inner_exclusive_access().linux.is_some() {
+        return linux_syscall(syscall_id, args);
+    }
+    SysError::encode(match syscall_id {
+        SYSCALL_GETCWD => sys_getcwd(UserSliceRef::new(args[0], args[1])),
+        SYSCALL_EPOLL_CREATE => sys_epoll_create(args[0] as u32),
+        SYSCALL_EPOLL_CTL => sys_epoll_ctl(args[0], args[1], args[2], UserSliceRef::one(args[3])),
+        SYSCALL_EPOLL_WAIT => sys_epoll_wait(
+            args[0],
+            UserSliceRef::new(args[1], args[2]),
+            args[3] as isize,
+        ),
         SYSCALL_DUP => sys_dup(args[0]),
+        SYSCALL_FCNTL => sys_fcntl(args[0], args[1], args[2]),
+        SYSCALL_IOCTL => sys_ioctl(args[0], args[1], args[2]),
         SYSCALL_CONNECT => sys_connect(args[0] as _, args[1] as _, args[2] as _),
         SYSCALL_LISTEN => sys_listen(args[0] as _),
         SYSCALL_ACCEPT => sys_accept(args[0] as _),
-        SYSCALL_OPEN => sys_open(args[0] as *const u8, args[1] as u32),
+        SYSCALL_FLOCK => sys_flock(args[0], args[1]),
+        SYSCALL_MKDIR => sys_mkdir(UserCString::new(args[0])),
+        SYSCALL_UNLINKAT => sys_unlinkat(UserCString::new(args[0]), args[1] as u32),
+        SYSCALL_UMOUNT => sys_umount(UserCString::new(args[0]), args[1]),
+        SYSCALL_MOUNT => sys_mount(
+            UserCString::new(args[0]),
+            UserCString::new(args[1]),
+            UserCString::new(args[2]),
+            args[3],
+        ),
+        SYSCALL_FTRUNCATE => sys_ftruncate(args[0], args[1]),
+        SYSCALL_CHDIR => sys_chdir(UserCString::new(args[0])),
+        SYSCALL_CHROOT => sys_chroot(UserCString::new(args[0])),
+        SYSCALL_OPEN => sys_open(UserCString::new(args[0]), args[1] as u32),
         SYSCALL_CLOSE => sys_close(args[0]),
-        SYSCALL_PIPE => sys_pipe(args[0] as *mut usize),
-        SYSCALL_READ => sys_read(args[0], args[1] as *const u8, args[2]),
-        SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
+        SYSCALL_PIPE => sys_pipe(UserSliceRef::new(args[0], 2), args[1] as u32),
+        SYSCALL_QUOTACTL => sys_quotactl(args[0], args[1], UserSliceRef::one(args[2])),
+        SYSCALL_GETDENTS64 => sys_getdents64(args[0], UserSliceRef::new(args[1], args[2])),
+        SYSCALL_LSEEK => sys_lseek(args[0], args[1] as isize, args[2]),
+        SYSCALL_READ => sys_read(args[0], UserSliceRef::new(args[1], args[2])),
+        SYSCALL_WRITE => sys_write(args[0], UserSliceRef::new(args[1], args[2])),
+        SYSCALL_SENDFILE => sys_sendfile(args[0], args[1], UserSliceRef::one(args[2]), args[3]),
+        SYSCALL_PSELECT => sys_pselect(
+            args[0],
+            UserSliceRef::one(args[1]),
+            UserSliceRef::one(args[2]),
+            UserSliceRef::one(args[3]),
+            args[4] as isize,
+        ),
+        SYSCALL_PPOLL => sys_ppoll(UserSliceRef::new(args[0], args[1]), args[2] as isize),
+        SYSCALL_SYNC => sys_sync(),
+        // fsync writes back nothing more than fdatasync
+        SYSCALL_FSYNC | SYSCALL_FDATASYNC => sys_fdatasync(args[0]),
+        SYSCALL_PERSONALITY => sys_personality(args[0]),
         SYSCALL_EXIT => sys_exit(args[0] as i32),
+        SYSCALL_FUTEX => sys_futex(
+            UserSliceRef::one(args[0]),
+            args[1],
+            args[2],
+            args[3] as isize,
+        ),
         SYSCALL_SLEEP => sys_sleep(args[0]),
+        SYSCALL_CLOCK_GETTIME => sys_clock_gettime(args[0], UserSliceRef::one(args[1])),
+        SYSCALL_DMESG => sys_dmesg(UserSliceRef::new(args[0], args[1])),
+        SYSCALL_PTRACE => sys_ptrace(args[0], args[1], args[2], args[3]),
         SYSCALL_YIELD => sys_yield(),
         SYSCALL_KILL => sys_kill(args[0], args[1] as u32),
+        SYSCALL_SIGACTION => sys_sigaction(
+            args[0],
+            UserSliceRef::one(args[1]),
+            UserSliceRef::one(args[2]),
+        ),
+        SYSCALL_SIGPROCMASK => sys_sigprocmask(args[0], args[1] as u32),
+        SYSCALL_SIGRETURN => sys_sigreturn(),
+        SYSCALL_SETPRIORITY => sys_setpriority(args[0], args[1], args[2] as isize),
+        SYSCALL_GETPRIORITY => sys_getpriority(args[0], args[1]),
+        SYSCALL_SETUID => sys_setuid(args[0]),
+        SYSCALL_SETPGID => sys_setpgid(args[0], args[1]),
+        SYSCALL_GETPGID => sys_getpgid(args[0]),
+        SYSCALL_GETSID => sys_getsid(args[0]),
+        SYSCALL_SETSID => sys_setsid(),
         SYSCALL_GET_TIME => sys_get_time(),
         SYSCALL_GETPID => sys_getpid(),
+        SYSCALL_GETUID => sys_getuid(),
+        SYSCALL_MQ_OPEN => sys_mq_open(
+            UserCString::new(args[0]),
+            args[1] as u32,
+            UserSliceRef::one(args[2]),
+        ),
+        SYSCALL_MQ_UNLINK => sys_mq_unlink(UserCString::new(args[0])),
+        SYSCALL_MQ_SEND => {
+            sys_mq_send(args[0], UserSliceRef::new(args[1], args[2]), args[3] as u32)
+        }
+        SYSCALL_MQ_RECEIVE => sys_mq_receive(
+            args[0],
+            UserSliceRef::new(args[1], args[2]),
+            UserSliceRef::one(args[3]),
+        ),
+        SYSCALL_MQ_GETSETATTR => sys_mq_getsetattr(
+            args[0],
+            UserSliceRef::one(args[1]),
+            UserSliceRef::one(args[2]),
+        ),
+        SYSCALL_MUNMAP => sys_munmap(args[0], args[1]),
         SYSCALL_FORK => sys_fork(),
-        SYSCALL_EXEC => sys_exec(args[0] as *const u8, args[1] as *const usize),
-        SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32),
+        SYSCALL_EXEC => sys_exec(
+            UserCString::new(args[0]),
+            UserSliceRef::new(args[1], USER_ARGS_MAX),
+        ),
+        SYSCALL_MMAP => sys_mmap(args[0], args[1], args[2], args[3], args[4], args[5]),
+        SYSCALL_WAITPID => sys_waitpid(args[0] as isize, UserSliceRef::one(args[1])),
+        SYSCALL_PRLIMIT => sys_prlimit(
+            args[0],
+            args[1],
+            UserSliceRef::one(args[2]),
+            UserSliceRef::one(args[3]),
+        ),
+        SYSCALL_RENAME => sys_rename(UserCString::new(args[0]), UserCString::new(args[1])),
+        SYSCALL_SPAWN => sys_spawn(
+            UserCString::new(args[0]),
+            UserSliceRef::new(args[1], USER_ARGS_MAX),
+            UserSliceRef::new(args[2], USER_ARGS_MAX),
+            UserSliceRef::new(args[3], USER_ARGS_MAX),
+        ),
+        SYSCALL_PIDFD_OPEN => sys_pidfd_open(args[0], args[1] as u32),
+        SYSCALL_CLOSE_RANGE => sys_close_range(args[0], args[1], args[2] as u32),
+        SYSCALL_ENABLE_DEADLOCK_DETECT => sys_enable_deadlock_detect(args[0]),
         SYSCALL_THREAD_CREATE => sys_thread_create(args[0], args[1]),
         SYSCALL_GETTID => sys_gettid(),
-        SYSCALL_WAITTID => sys_waittid(args[0]) as isize,
-        SYSCALL_MUTEX_CREATE => sys_mutex_create(args[0] == 1),
+        SYSCALL_WAITTID => sys_waittid(args[0]),
+        SYSCALL_MUTEX_CREATE => sys_mutex_create(args[0] == 1, args[1] == 1),
         SYSCALL_MUTEX_LOCK => sys_mutex_lock(args[0]),
         SYSCALL_MUTEX_UNLOCK => sys_mutex_unlock(args[0]),
+        SYSCALL_MUTEX_LOCK_TIMEOUT => sys_mutex_lock_timeout(args[0], args[1]),
         SYSCALL_SEMAPHORE_CREATE => sys_semaphore_create(args[0]),
         SYSCALL_SEMAPHORE_UP => sys_semaphore_up(args[0]),
         SYSCALL_SEMAPHORE_DOWN => sys_semaphore_down(args[0]),
+        SYSCALL_SEMAPHORE_DOWN_TIMEOUT => sys_semaphore_down_timeout(args[0], args[1]),
         SYSCALL_CONDVAR_CREATE => sys_condvar_create(),
         SYSCALL_CONDVAR_SIGNAL => sys_condvar_signal(args[0]),
         SYSCALL_CONDVAR_WAIT => sys_condvar_wait(args[0], args[1]),
+        SYSCALL_CONDVAR_WAIT_TIMEOUT => sys_condvar_wait_timeout(args[0], args[1], args[2]),
         SYSCALL_FRAMEBUFFER => sys_framebuffer(),
         SYSCALL_FRAMEBUFFER_FLUSH => sys_framebuffer_flush(),
         SYSCALL_EVENT_GET => sys_event_get(),
         SYSCALL_KEY_PRESSED => sys_key_pressed(),
+        SYSCALL_TCP_CONNECT => sys_tcp_connect(args[0] as _, args[1] as _, args[2] as _),
+        SYSCALL_CGROUP_CREATE => sys_cgroup_create(args[0], args[1]),
+        SYSCALL_CGROUP_ATTACH => sys_cgroup_attach(args[0], args[1]),
+        SYSCALL_CGROUP_USAGE => sys_cgroup_usage(args[0]),
+        SYSCALL_CHECKPOINT => sys_checkpoint(UserCString::new(args[0])),
+        SYSCALL_RESTORE => sys_restore(UserCString::new(args[0])),
+        SYSCALL_MEMUSAGE => sys_memusage(args[0], UserSliceRef::one(args[1])),
+        SYSCALL_VMDUMP => sys_vmdump(args[0], args[1], args[2]),
+        SYSCALL_KLOG_MAP => sys_klog_map(),
+        SYSCALL_VM_STATS => sys_vm_stats(args[0], UserSliceRef::one(args[1])),
+        SYSCALL_CPU_UP => sys_cpu_up(args[0]),
+        SYSCALL_CPU_DOWN => sys_cpu_down(args[0]),
+        SYSCALL_SUSPEND => sys_suspend(),
+        SYSCALL_READ_TIMEOUT => sys_read_timeout(
+            args[0],
+            UserSliceRef::new(args[1], args[2]),
+            args[3] as isize,
+        ),
+        SYSCALL_GET_TIME_NS => sys_get_time_ns(),
+        SYSCALL_NANOSLEEP => sys_nanosleep(args[0]),
         _ => panic!("Unsupported syscall_id: {}", syscall_id),
-    }
+    })
 }
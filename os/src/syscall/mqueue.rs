@@ -0,0 +1,111 @@
+use crate::fs::{mq_open, mq_unlink, File, MqAttr, MqFd, OpenFlags, MQ_PRIO_MAX};
+use crate::mm::{UserCString, UserSliceRef};
+use crate::task::{current_process, current_user_token};
+use abi::{SysError, SysResult};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+fn get_mq_fd(fd: usize) -> Option<Arc<dyn File + Send + Sync>> {
+    let process = current_process();
+    let file = process.fd_table.read().get(fd).cloned()?;
+    file.as_any().downcast_ref::<MqFd>()?;
+    Some(file)
+}
+
+pub fn sys_mq_open(name: UserCString, flags: u32, attr: UserSliceRef<MqAttr>) -> SysResult {
+    let token = current_user_token();
+    let name = name.read(token).ok_or(SysError::Fault)?;
+    let flags = OpenFlags::from_bits(flags).ok_or(SysError::Invalid)?;
+    let attr = if attr.is_null() {
+        None
+    } else {
+        Some(attr.get(token, 0).ok_or(SysError::Fault)?)
+    };
+    let mq_fd = mq_open(name.as_str(), flags, attr)?;
+    let process = current_process();
+    let fd = process.fd_table.write().alloc(mq_fd);
+    fd.ok_or(SysError::TooManyFiles)
+}
+
+pub fn sys_mq_unlink(name: UserCString) -> SysResult {
+    let name = name.read(current_user_token()).ok_or(SysError::Fault)?;
+    if mq_unlink(name.as_str()) {
+        Ok(0)
+    } else {
+        Err(SysError::NoEntry)
+    }
+}
+
+/// Return `NotReady` if the queue is full and the fd is non-blocking, or
+/// `Interrupted` if a signal comes first.
+pub fn sys_mq_send(fd: usize, msg: UserSliceRef<u8>, prio: u32) -> SysResult {
+    let file = match get_mq_fd(fd) {
+        Some(file) if file.writable() => file,
+        _ => return Err(SysError::BadFd),
+    };
+    let mq_fd = file.as_any().downcast_ref::<MqFd>().unwrap();
+    if msg.len() > mq_fd.queue().msgsize() || prio >= MQ_PRIO_MAX {
+        return Err(SysError::Invalid);
+    }
+    let msg: Vec<u8> = msg.read(current_user_token()).ok_or(SysError::Fault)?;
+    mq_fd.queue().send(msg, prio, mq_fd.nonblock()).map(|()| 0)
+}
+
+/// Return the length of the message, or `NotReady` if the queue is empty and
+/// the fd is non-blocking, or `Interrupted` if a signal comes first. The buffer should be
+/// able to hold a message of msgsize.
+pub fn sys_mq_receive(fd: usize, buf: UserSliceRef<u8>, prio: UserSliceRef<u32>) -> SysResult {
+    let file = match get_mq_fd(fd) {
+        Some(file) if file.readable() => file,
+        _ => return Err(SysError::BadFd),
+    };
+    let mq_fd = file.as_any().downcast_ref::<MqFd>().unwrap();
+    if buf.len() < mq_fd.queue().msgsize() {
+        return Err(SysError::Invalid);
+    }
+    // checked before a message is taken, which would be lost otherwise
+    let token = current_user_token();
+    let buffer = buf.buffer(token, true).ok_or(SysError::Fault)?;
+    if !prio.is_null() && prio.get(token, 0).is_none() {
+        return Err(SysError::Fault);
+    }
+    let (msg, msg_prio) = mq_fd.queue().receive(mq_fd.nonblock())?;
+    for (byte_ref, &byte) in buffer.into_iter().zip(msg.iter()) {
+        unsafe {
+            *byte_ref = byte;
+        }
+    }
+    if !prio.is_null() && prio.set(token, 0, msg_prio).is_none() {
+        return Err(SysError::Fault);
+    }
+    Ok(msg.len())
+}
+
+/// Get the attributes into `old_attr` and then set the flags by `new_attr`,
+/// only `OpenFlags::NONBLOCK` can be changed.
+pub fn sys_mq_getsetattr(
+    fd: usize,
+    new_attr: UserSliceRef<MqAttr>,
+    old_attr: UserSliceRef<MqAttr>,
+) -> SysResult {
+    let file = get_mq_fd(fd).ok_or(SysError::BadFd)?;
+    let mq_fd = file.as_any().downcast_ref::<MqFd>().unwrap();
+    let token = current_user_token();
+    if !old_attr.is_null() {
+        let mut attr = mq_fd.queue().attr();
+        if mq_fd.nonblock() {
+            attr.flags = OpenFlags::NONBLOCK.bits() as usize;
+        }
+        if old_attr.set(token, 0, attr).is_none() {
+            return Err(SysError::Fault);
+        }
+    }
+    if !new_attr.is_null() {
+        let flags = match new_attr.get(token, 0) {
+            Some(new_attr) => new_attr.flags as u32,
+            None => return Err(SysError::Fault),
+        };
+        mq_fd.set_nonblock(flags & OpenFlags::NONBLOCK.bits() != 0);
+    }
+    Ok(0)
+}
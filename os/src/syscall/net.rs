@@ -1,53 +1,64 @@
-use crate::net::port_table::{accept, listen, port_acceptable, PortFd};
+use crate::net::port_table::{
+    accept, accept_backlog, cancel_accept, listen, port_acceptable, PortFd,
+};
+use crate::net::tcp::TCP;
 use crate::net::udp::UDP;
-use crate::net::{net_interrupt_handler, IPv4};
+use crate::net::{wait_for_packets, IPv4};
 use crate::task::{current_process, current_task, current_trap_cx};
+use abi::{SysError, SysResult};
 use alloc::sync::Arc;
 
-// just support udp
-pub fn sys_connect(raddr: u32, lport: u16, rport: u16) -> isize {
+// just support udp, connect to 0.0.0.0:0 to wait for any peer
+pub fn sys_connect(raddr: u32, lport: u16, rport: u16) -> SysResult {
     let process = current_process();
-    let mut inner = process.inner_exclusive_access();
-    let fd = inner.alloc_fd();
     let udp_node = UDP::new(IPv4::from_u32(raddr), lport, rport);
-    inner.fd_table[fd] = Some(Arc::new(udp_node));
-    fd as isize
+    let fd = process.fd_table.write().alloc(Arc::new(udp_node));
+    fd.ok_or(SysError::TooManyFiles)
 }
 
-// listen a port
-pub fn sys_listen(port: u16) -> isize {
-    match listen(port) {
-        Some(port_index) => {
-            let process = current_process();
-            let mut inner = process.inner_exclusive_access();
-            let fd = inner.alloc_fd();
-            let port_fd = PortFd::new(port_index);
-            inner.fd_table[fd] = Some(Arc::new(port_fd));
+// connect to a tcp server
+pub fn sys_tcp_connect(raddr: u32, lport: u16, rport: u16) -> SysResult {
+    let tcp_socket = TCP::connect(IPv4::from_u32(raddr), lport, rport).ok_or(SysError::Failed)?;
+    let process = current_process();
+    let fd = process.fd_table.write().alloc(Arc::new(tcp_socket));
+    fd.ok_or(SysError::TooManyFiles)
+}
 
-            // NOTICE: this return the port index, not the fd
-            port_index as isize
-        }
-        None => -1,
-    }
+// listen a port
+pub fn sys_listen(port: u16) -> SysResult {
+    let port_index = listen(port).ok_or(SysError::Failed)?;
+    let process = current_process();
+    let port_fd = PortFd::new(port_index);
+    let fd = process.fd_table.write().alloc(Arc::new(port_fd));
+    fd.ok_or(SysError::TooManyFiles)
 }
 
-// accept a tcp connection
-pub fn sys_accept(port_index: usize) -> isize {
-    println!("accepting port {}", port_index);
+// accept a tcp connection on the listening fd, Interrupted if a signal comes first
+pub fn sys_accept(listen_fd: usize) -> SysResult {
+    let process = current_process();
+    let fd_table = process.fd_table.read();
+    let port_index = match fd_table.get(listen_fd) {
+        Some(file) => file
+            .as_any()
+            .downcast_ref::<PortFd>()
+            .map(|x| x.port_index()),
+        _ => None,
+    };
+    drop(fd_table);
+    let port_index = port_index.ok_or(SysError::Invalid)?;
+    debug!("accepting port {}", port_index);
 
     let task = current_task().unwrap();
+    // a request may have arrived before accepting, e.g. when polling the port
+    if accept_backlog(port_index, task.clone()) {
+        return SysError::check(current_trap_cx().x[10] as isize);
+    }
     accept(port_index, task);
-    // block_current_and_run_next();
-
-    // NOTICE: There does not have interrupt handler, just call it munually.
-    loop {
-        net_interrupt_handler();
-
-        if !port_acceptable(port_index) {
-            break;
-        }
+    // the net thread accepts the next request for us
+    if !wait_for_packets(|| !port_acceptable(port_index)) && cancel_accept(port_index) {
+        return Err(SysError::Interrupted);
     }
 
     let cx = current_trap_cx();
-    cx.x[10] as isize
+    SysError::check(cx.x[10] as isize)
 }
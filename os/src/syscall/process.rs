@@ -1,12 +1,24 @@
-use crate::fs::{open_file, OpenFlags};
-use crate::mm::{translated_ref, translated_refmut, translated_str};
+use super::fs::fd_flags;
+use crate::config::{MMAP_BASE, PAGE_SIZE, USER_SPACE_END};
+use crate::fs::{open_file, open_path, OpenFlags, PidFd};
+use crate::klog::{klog_address, read_tail, KLOG_PAGES};
+use crate::mm::{
+    MapArea, MapPermission, MapType, MemUsage, PhysAddr, UserCString, UserSliceRef, VPNRange,
+    VirtAddr, VmStats,
+};
 use crate::task::{
-    current_process, current_task, current_user_token, exit_current_and_run_next, pid2process,
-    suspend_current_and_run_next, SignalFlags,
+    current_process, current_task, current_uid, current_user_token, current_ustacks_range,
+    exit_current_and_run_next, group_in_session, load_program, may_control, pid2process,
+    processes_of_group, send_signal, suspend_current_and_run_next, FdFlags, FdTable, LinuxAbi,
+    ProcessControlBlock, Program, RLimit, SignalAction, SignalActionFlags, SignalFlags, NICE_MAX,
+    NICE_MIN,
 };
-use crate::timer::get_time_ms;
+use crate::timer::{clock_ns, get_time_ms, get_time_ns, TimeSpec};
+use crate::trigger::{reload_watchpoints, watchpoints_available, Watchpoint, MAX_WATCHPOINTS};
+use abi::{SysError, SysResult};
 use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 
 pub fn sys_exit(exit_code: i32) -> ! {
@@ -14,20 +26,33 @@ pub fn sys_exit(exit_code: i32) -> ! {
     panic!("Unreachable in sys_exit!");
 }
 
-pub fn sys_yield() -> isize {
+pub fn sys_yield() -> SysResult {
     suspend_current_and_run_next();
-    0
+    Ok(0)
+}
+
+pub fn sys_get_time() -> SysResult {
+    Ok(get_time_ms())
 }
 
-pub fn sys_get_time() -> isize {
-    get_time_ms() as isize
+/// The time since boot in ns, as precise as the timebase of the board.
+pub fn sys_get_time_ns() -> SysResult {
+    Ok(get_time_ns())
 }
 
-pub fn sys_getpid() -> isize {
-    current_task().unwrap().process.upgrade().unwrap().getpid() as isize
+/// Get the time of `CLOCK_REALTIME` or `CLOCK_MONOTONIC`.
+pub fn sys_clock_gettime(clock_id: usize, tp: UserSliceRef<TimeSpec>) -> SysResult {
+    let ns = clock_ns(clock_id).ok_or(SysError::Invalid)?;
+    tp.set(current_user_token(), 0, TimeSpec::from_ns(ns))
+        .map(|()| 0)
+        .ok_or(SysError::Fault)
 }
 
-pub fn sys_fork() -> isize {
+pub fn sys_getpid() -> SysResult {
+    Ok(current_task().unwrap().process.upgrade().unwrap().getpid())
+}
+
+pub fn sys_fork() -> SysResult {
     let current_process = current_process();
     let new_process = current_process.fork();
     let new_pid = new_process.getpid();
@@ -38,38 +63,419 @@ pub fn sys_fork() -> isize {
     // we do not have to move to next instruction since we have done it before
     // for child process, fork returns 0
     trap_cx.x[10] = 0;
-    new_pid as isize
+    Ok(new_pid)
 }
 
-pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
-    let token = current_user_token();
-    let path = translated_str(token, path);
+/// The strings of a null-terminated array of pointers, whose length is the
+/// most there may be. None if one is not readable or there is no null.
+fn read_args(token: usize, args: UserSliceRef<usize>) -> Option<Vec<String>> {
     let mut args_vec: Vec<String> = Vec::new();
-    loop {
-        let arg_str_ptr = *translated_ref(token, args);
+    for i in 0..args.len() {
+        let arg_str_ptr = args.get(token, i)?;
         if arg_str_ptr == 0 {
-            break;
+            return Some(args_vec);
+        }
+        args_vec.push(UserCString::new(arg_str_ptr).read(token)?);
+    }
+    None
+}
+
+/// Open the program at `path` of the current process and load it, see
+/// `load_program` for the formats. Fail like `open_file`, or with
+/// `NotExecutable` if it has no format or its interpreter cannot be opened.
+fn load_path(path: &str, args: Vec<String>) -> SysResult<Program> {
+    let process = current_process();
+    let open = |path: &str| {
+        let path = process.inner_exclusive_access().resolve_path(path);
+        open_file(path.as_str(), OpenFlags::RDONLY)
+    };
+    load_program(open(path)?, path, args, |path| open(path).ok()).ok_or(SysError::NotExecutable)
+}
+
+pub fn sys_exec(path: UserCString, args: UserSliceRef<usize>) -> SysResult {
+    let token = current_user_token();
+    let (path, args_vec) = match (path.read(token), read_args(token, args)) {
+        (Some(path), Some(args_vec)) => (path, args_vec),
+        _ => return Err(SysError::Fault),
+    };
+    let program = load_path(path.as_str(), args_vec)?;
+    // a script has its interpreter before the arguments
+    let argc = program.args.len();
+    current_process().exec(program);
+    // return argc because cx.x[10] will be covered with it later
+    Ok(argc)
+}
+
+const SPAWN_END: usize = 0;
+const SPAWN_CLOSE: usize = 1;
+const SPAWN_DUP2: usize = 2;
+const SPAWN_OPEN: usize = 3;
+
+/// A change to the descriptors of a spawned child, the same layout as the
+/// one in the user library. A list of them ends with `SPAWN_END`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SpawnAction {
+    kind: usize,
+    /// closed, or duplicated to `new_fd`
+    fd: usize,
+    new_fd: usize,
+    /// opened at `new_fd` with `flags`
+    path: *const u8,
+    flags: u32,
+}
+
+/// The actions before `SPAWN_END`, with the paths to open. None if one is not
+/// readable or there is no end.
+fn read_spawn_actions(
+    token: usize,
+    fd_actions: UserSliceRef<SpawnAction>,
+) -> Option<(Vec<SpawnAction>, Vec<String>)> {
+    let mut actions = Vec::new();
+    let mut action_paths = Vec::new();
+    for i in 0..fd_actions.len() {
+        let action = fd_actions.get(token, i)?;
+        if action.kind == SPAWN_END {
+            return Some((actions, action_paths));
+        }
+        action_paths.push(if action.kind == SPAWN_OPEN {
+            UserCString::new(action.path as usize).read(token)?
+        } else {
+            String::new()
+        });
+        actions.push(action);
+    }
+    None
+}
+
+/// Apply the actions to the descriptors of a child in order, stop at the
+/// first of them failing.
+fn apply_spawn_actions(
+    fd_table: &mut FdTable,
+    actions: &[SpawnAction],
+    paths: &[String],
+) -> SysResult<()> {
+    for (action, path) in actions.iter().zip(paths.iter()) {
+        let done = match action.kind {
+            SPAWN_CLOSE => fd_table.remove(action.fd).is_some(),
+            SPAWN_DUP2 => match fd_table.get(action.fd).cloned() {
+                // kept open by exec, like dup2 of the same descriptor
+                Some(_) if action.fd == action.new_fd => {
+                    fd_table.set_flags(action.fd, FdFlags::empty())
+                }
+                Some(file) => fd_table.insert(action.new_fd, file),
+                None => false,
+            },
+            SPAWN_OPEN => {
+                let flags = OpenFlags::from_bits(action.flags).ok_or(SysError::Invalid)?;
+                let file = open_path(path.as_str(), flags)?;
+                fd_table.insert(action.new_fd, file)
+                    && fd_table.set_flags(action.new_fd, fd_flags(flags))
+            }
+            _ => return Err(SysError::Invalid),
+        };
+        // a descriptor not open, or beyond the limit
+        if !done {
+            return Err(SysError::BadFd);
         }
-        args_vec.push(translated_str(token, arg_str_ptr as *const u8));
-        unsafe {
-            args = args.add(1);
+    }
+    Ok(())
+}
+
+/// Create a child running the program `path` with the arguments `args`, like
+/// fork and exec in the child but without copying this process. The child
+/// starts with the descriptors of this process changed by `fd_actions`, if
+/// not null, then those with `FdFlags::CLOEXEC` are closed.
+///
+/// There is no environment of processes, so `envp` is dropped like the one
+/// of execve of Linux. Return the pid of the child, or fail like exec if the
+/// program cannot be loaded, or like the action failing.
+pub fn sys_spawn(
+    path: UserCString,
+    args: UserSliceRef<usize>,
+    _envp: UserSliceRef<usize>,
+    fd_actions: UserSliceRef<SpawnAction>,
+) -> SysResult {
+    let token = current_user_token();
+    let (path, args_vec) = match (path.read(token), read_args(token, args)) {
+        (Some(path), Some(args_vec)) => (path, args_vec),
+        _ => return Err(SysError::Fault),
+    };
+    let (actions, mut action_paths) = if fd_actions.is_null() {
+        (Vec::new(), Vec::new())
+    } else {
+        read_spawn_actions(token, fd_actions).ok_or(SysError::Fault)?
+    };
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    for (action, action_path) in actions.iter().zip(action_paths.iter_mut()) {
+        if action.kind == SPAWN_OPEN {
+            *action_path = inner.resolve_path(action_path.as_str());
+        }
+    }
+    drop(inner);
+    let program = load_path(path.as_str(), args_vec)?;
+    let mut fd_table = process.fd_table.read().clone();
+    apply_spawn_actions(&mut fd_table, &actions, &action_paths)?;
+    Ok(process.spawn(program, fd_table).getpid())
+}
+
+/// Save the current process to a file, return 0 after saved, or 1 when the
+/// process is restored from it. Fail with `Invalid` if it has more than one
+/// thread, or `NoSpace` if the image is not written whole.
+pub fn sys_checkpoint(path: UserCString) -> SysResult {
+    let process = current_process();
+    let path = path.read(current_user_token()).ok_or(SysError::Fault)?;
+    let path = process.inner_exclusive_access().resolve_path(path.as_str());
+    let image = process.checkpoint().ok_or(SysError::Invalid)?;
+    let inode = open_file(path.as_str(), OpenFlags::CREATE | OpenFlags::WRONLY)?;
+    if inode.write_all(image.as_slice()) {
+        Ok(0)
+    } else {
+        Err(SysError::NoSpace)
+    }
+}
+
+/// Replace the current process with the one saved in a file, which returns 1
+/// from `sys_checkpoint`. Fail with `Invalid` if the file is no checkpoint
+/// or the current process has more than one thread.
+pub fn sys_restore(path: UserCString) -> SysResult {
+    let process = current_process();
+    let path = path.read(current_user_token()).ok_or(SysError::Fault)?;
+    let path = process.inner_exclusive_access().resolve_path(path.as_str());
+    let inode = open_file(path.as_str(), OpenFlags::RDONLY)?;
+    if process.restore(inode.read_all().as_slice()) {
+        // return 1 because cx.x[10] will be covered with it later
+        Ok(1)
+    } else {
+        Err(SysError::Invalid)
+    }
+}
+
+/// Get the memory usage of a process, or the current one if `pid` is 0.
+pub fn sys_memusage(pid: usize, usage: UserSliceRef<MemUsage>) -> SysResult {
+    let process = if pid == 0 {
+        current_process()
+    } else {
+        pid2process(pid).ok_or(SysError::NoProcess)?
+    };
+    let memusage = process.inner_exclusive_access().memory_set.usage();
+    usage
+        .set(current_user_token(), 0, memusage)
+        .map(|()| 0)
+        .ok_or(SysError::Fault)
+}
+
+/// Get the page faults resolved for a process, or the current one if `pid`
+/// is 0.
+pub fn sys_vm_stats(pid: usize, stats: UserSliceRef<VmStats>) -> SysResult {
+    let process = if pid == 0 {
+        current_process()
+    } else {
+        pid2process(pid).ok_or(SysError::NoProcess)?
+    };
+    let vm_stats = process.inner_exclusive_access().memory_set.vm_stats();
+    stats
+        .set(current_user_token(), 0, vm_stats)
+        .map(|()| 0)
+        .ok_or(SysError::Fault)
+}
+
+/// Print the page table mappings of a process, or the current one if `pid`
+/// is 0, within [start, end) to the console for debugging.
+pub fn sys_vmdump(pid: usize, start: usize, end: usize) -> SysResult {
+    let (start, end) = (VirtAddr::from(start).floor(), VirtAddr::from(end).ceil());
+    if start > end {
+        return Err(SysError::Invalid);
+    }
+    let process = if pid == 0 {
+        current_process()
+    } else {
+        pid2process(pid).ok_or(SysError::NoProcess)?
+    };
+    process
+        .inner_exclusive_access()
+        .memory_set
+        .dump(VPNRange::new(start, end));
+    Ok(0)
+}
+
+/// Copy the newest bytes kept in the kernel log to `buf`, at most its
+/// length, return the number of bytes copied.
+pub fn sys_dmesg(buf: UserSliceRef<u8>) -> SysResult {
+    let text = read_tail(buf.len());
+    buf.write(current_user_token(), &text)
+        .ok_or(SysError::Fault)?;
+    Ok(text.len())
+}
+
+const KLOG_VADDR: usize = 0x18000000;
+
+/// Map the kernel log read-only to the current process, return the address.
+pub fn sys_klog_map() -> SysResult {
+    let start_ppn = PhysAddr::from(klog_address()).floor();
+    let start_vpn = VirtAddr::from(KLOG_VADDR).floor();
+    let pn_offset = start_ppn.0 as isize - start_vpn.0 as isize;
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    // only once for a process
+    let mapped = inner.memory_set.translate(start_vpn);
+    if !mapped.map_or(false, |pte| pte.is_valid()) {
+        inner.memory_set.push(
+            MapArea::new(
+                KLOG_VADDR.into(),
+                (KLOG_VADDR + KLOG_PAGES * PAGE_SIZE).into(),
+                MapType::Linear(pn_offset),
+                MapPermission::R | MapPermission::U,
+            ),
+            None,
+        );
+    }
+    Ok(KLOG_VADDR)
+}
+
+const PROT_READ: usize = 0x1;
+const PROT_WRITE: usize = 0x2;
+const PROT_EXEC: usize = 0x4;
+const MAP_SHARED: usize = 0x1;
+const MAP_PRIVATE: usize = 0x2;
+const MAP_FIXED: usize = 0x10;
+const MAP_ANONYMOUS: usize = 0x20;
+
+/// Map `len` bytes at `addr`, or anywhere from `MMAP_BASE` on if `addr` is
+/// 0 or taken, unless `MAP_FIXED` is set. A mapping of the file `fd` is
+/// loaded from `offset` of it on the first access of each page; the pages
+/// beyond the end of the file are zeros for a private one.
+///
+/// A writable shared mapping would have to be written back, so only a
+/// read-only one of a file is shared, with the page cache. Return the
+/// address, or fail like MAP_FAILED: `Exists` if the range is taken with
+/// `MAP_FIXED`, `NoMemory` if there is no room. The room of the user stacks
+/// is taken in this way, even the pages not mapped yet, see
+/// `TaskUserRes::ustacks_range`.
+pub fn sys_mmap(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> SysResult {
+    if len == 0 || addr % PAGE_SIZE != 0 || offset % PAGE_SIZE != 0 {
+        return Err(SysError::Invalid);
+    }
+    // a page table entry without R, W nor X is not a leaf
+    if prot == 0 || prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+        return Err(SysError::Invalid);
+    }
+    let shared = match flags & (MAP_SHARED | MAP_PRIVATE) {
+        MAP_SHARED => true,
+        MAP_PRIVATE => false,
+        _ => return Err(SysError::Invalid),
+    };
+    let anonymous = flags & MAP_ANONYMOUS != 0;
+    if shared && (anonymous || prot & PROT_WRITE != 0) {
+        return Err(SysError::Invalid);
+    }
+    let len = len.checked_add(PAGE_SIZE - 1).ok_or(SysError::NoMemory)? & !(PAGE_SIZE - 1);
+    let mut permission = MapPermission::U;
+    // W without R is reserved
+    if prot & (PROT_READ | PROT_WRITE) != 0 {
+        permission |= MapPermission::R;
+    }
+    if prot & PROT_WRITE != 0 {
+        permission |= MapPermission::W;
+    }
+    if prot & PROT_EXEC != 0 {
+        permission |= MapPermission::X;
+    }
+    let ustacks = current_ustacks_range();
+    let process = current_process();
+    let source = if anonymous {
+        None
+    } else {
+        let file = match process.fd_table.read().get(fd) {
+            Some(file) if file.readable() => file.clone(),
+            _ => return Err(SysError::BadFd),
+        };
+        // ENODEV of Linux for the files which cannot be mapped
+        Some(file.page_source().ok_or(SysError::NoDevice)?)
+    };
+    let mut inner = process.inner_exclusive_access();
+    let pages = len / PAGE_SIZE;
+    let wanted = match addr.checked_add(len) {
+        Some(end) if addr != 0 && end <= USER_SPACE_END => {
+            (end <= ustacks.start || ustacks.end <= addr)
+                && inner
+                    .memory_set
+                    .is_free(VirtAddr::from(addr).floor(), VirtAddr::from(end).floor())
         }
+        _ => false,
+    };
+    let start = if wanted {
+        addr
+    } else if flags & MAP_FIXED != 0 {
+        return Err(SysError::Exists);
+    } else {
+        let start_vpn = inner
+            .memory_set
+            .find_free(
+                VirtAddr::from(MMAP_BASE).floor(),
+                VirtAddr::from(USER_SPACE_END).floor(),
+                pages,
+            )
+            .ok_or(SysError::NoMemory)?;
+        VirtAddr::from(start_vpn).into()
+    };
+    let mut map_area = MapArea::new(
+        start.into(),
+        (start + len).into(),
+        MapType::Framed,
+        permission,
+    );
+    if let Some(source) = source {
+        let file_size = source.size().saturating_sub(offset).min(len);
+        map_area = map_area.backed_by(source, offset, file_size, shared);
+    }
+    if !inner.memory_set.insert_area(map_area) {
+        return Err(SysError::NoMemory);
     }
-    if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
-        let all_data = app_inode.read_all();
-        let process = current_process();
-        let argc = args_vec.len();
-        process.exec(all_data.as_slice(), args_vec);
-        // return argc because cx.x[10] will be covered with it later
-        argc as isize
+    Ok(start)
+}
+
+/// Unmap the pages in [addr, addr + len), the mappings partly in it are
+/// split. Fail with `Invalid` if the range has pages the user cannot access,
+/// or is in the room of the user stacks, which are unmapped with their
+/// threads.
+pub fn sys_munmap(addr: usize, len: usize) -> SysResult {
+    if len == 0 || addr % PAGE_SIZE != 0 {
+        return Err(SysError::Invalid);
+    }
+    let end = match addr.checked_add(len) {
+        Some(end) if end <= USER_SPACE_END => end,
+        _ => return Err(SysError::Invalid),
+    };
+    let ustacks = current_ustacks_range();
+    if addr < ustacks.end && ustacks.start < end {
+        return Err(SysError::Invalid);
+    }
+    let unmapped = current_process()
+        .inner_exclusive_access()
+        .memory_set
+        .unmap_range(VirtAddr::from(addr).floor(), VirtAddr::from(end).ceil());
+    if unmapped {
+        Ok(0)
     } else {
-        -1
+        Err(SysError::Invalid)
     }
 }
 
-/// If there is not a child process whose pid is same as given, return -1.
-/// Else if there is a child process but it is still running, return -2.
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+/// Reap a zombie child process whose pid is same as given, or any child if
+/// `pid` is -1, return its pid and exit code.
+/// If there is not such a child process, return `SysError::NoChild`.
+/// Else if there is a child process but it is still running, return
+/// `SysError::NotReady`.
+pub fn wait_child(pid: isize) -> SysResult<(usize, i32)> {
     let process = current_process();
     // find a child process
 
@@ -79,7 +485,7 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
         .iter()
         .any(|p| pid == -1 || pid as usize == p.getpid())
     {
-        return -1;
+        return Err(SysError::NoChild);
         // ---- release current PCB
     }
     let pair = inner.children.iter().enumerate().find(|(_, p)| {
@@ -95,23 +501,403 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
         // ++++ temporarily access child PCB exclusively
         let exit_code = child.inner_exclusive_access().exit_code;
         // ++++ release child PCB
-        *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
-        found_pid as isize
+        Ok((found_pid, exit_code))
     } else {
-        -2
+        Err(SysError::NotReady)
     }
     // ---- release current PCB automatically
 }
 
-pub fn sys_kill(pid: usize, signal: u32) -> isize {
-    if let Some(process) = pid2process(pid) {
-        if let Some(flag) = SignalFlags::from_bits(signal) {
-            process.inner_exclusive_access().signals |= flag;
-            0
-        } else {
-            -1
+/// Reap a child like `wait_child` and write its exit code to
+/// `exit_code_ptr` unless it is null.
+pub fn sys_waitpid(pid: isize, exit_code_ptr: UserSliceRef<i32>) -> SysResult {
+    let (found_pid, exit_code) = wait_child(pid)?;
+    // reaped all the same if the pointer is bad, like Linux
+    if !exit_code_ptr.is_null()
+        && exit_code_ptr
+            .set(current_user_token(), 0, exit_code)
+            .is_none()
+    {
+        return Err(SysError::Fault);
+    }
+    Ok(found_pid)
+}
+
+/// Open a descriptor of the process `pid`, which becomes readable once it
+/// exits, so it can be polled along with other descriptors instead of
+/// waiting for the children one at a time. The process may have exited
+/// already if it is a child not waited for. Only `OpenFlags::NONBLOCK` and
+/// `OpenFlags::CLOEXEC` are allowed in `flags`.
+pub fn sys_pidfd_open(pid: usize, flags: u32) -> SysResult {
+    let flags = match OpenFlags::from_bits(flags) {
+        Some(flags) if (OpenFlags::NONBLOCK | OpenFlags::CLOEXEC).contains(flags) => flags,
+        _ => return Err(SysError::Invalid),
+    };
+    let current = current_process();
+    // a zombie is out of the pid map, but still a child of its parent
+    let exit_status = match pid2process(pid) {
+        Some(process) => process.exit_status.clone(),
+        None => {
+            let inner = current.inner_exclusive_access();
+            let child = inner.children.iter().find(|child| child.getpid() == pid);
+            child.ok_or(SysError::NoProcess)?.exit_status.clone()
         }
+    };
+    let mut fd_table = current.fd_table.write();
+    let fd = fd_table
+        .alloc(Arc::new(PidFd::new(exit_status, flags)))
+        .ok_or(SysError::TooManyFiles)?;
+    fd_table.set_flags(fd, fd_flags(flags));
+    Ok(fd)
+}
+
+/// Send the signals in the set `signal` to the process `pid`, taken by one
+/// of its threads not blocking them. Only root may signal the processes of
+/// other users.
+pub fn sys_kill(pid: usize, signal: u32) -> SysResult {
+    let process = pid2process(pid).ok_or(SysError::NoProcess)?;
+    if !may_control(&process) {
+        return Err(SysError::NotPermitted);
+    }
+    let flag = SignalFlags::from_bits(signal).ok_or(SysError::Invalid)?;
+    send_signal(&process, flag);
+    Ok(0)
+}
+
+/// Set the action of the signal `signum` if `action` is not null, and save
+/// the old one to `old_action` if it is not null. The actions of SIGKILL
+/// and SIGSTOP cannot be changed.
+pub fn sys_sigaction(
+    signum: usize,
+    action: UserSliceRef<SignalAction>,
+    old_action: UserSliceRef<SignalAction>,
+) -> SysResult {
+    let signal = SignalFlags::from_signum(signum).ok_or(SysError::Invalid)?;
+    let token = current_user_token();
+    let action = if action.is_null() {
+        None
+    } else if SignalFlags::uncatchable().contains(signal) {
+        return Err(SysError::Invalid);
+    } else {
+        Some(action.get(token, 0).ok_or(SysError::Fault)?)
+    };
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let old = inner.signal_actions.table[signum];
+    if let Some(mut action) = action {
+        action.mask -= SignalFlags::uncatchable();
+        action.flags = SignalActionFlags::from_bits_truncate(action.flags.bits());
+        inner.signal_actions.table[signum] = action;
+    }
+    drop(inner);
+    if !old_action.is_null() && old_action.set(token, 0, old).is_none() {
+        return Err(SysError::Fault);
+    }
+    Ok(0)
+}
+
+const SIG_BLOCK: usize = 0;
+const SIG_UNBLOCK: usize = 1;
+const SIG_SETMASK: usize = 2;
+
+/// Change the signals blocked by the current thread with `how` and `mask`,
+/// return the old mask. SIGKILL and SIGSTOP are never blocked.
+pub fn sys_sigprocmask(how: usize, mask: u32) -> SysResult {
+    let mask = match SignalFlags::from_bits(mask) {
+        Some(mask) => mask - SignalFlags::uncatchable(),
+        None => return Err(SysError::Invalid),
+    };
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let old = task_inner.signal_mask;
+    task_inner.signal_mask = match how {
+        SIG_BLOCK => old | mask,
+        SIG_UNBLOCK => old - mask,
+        SIG_SETMASK => mask,
+        _ => return Err(SysError::Invalid),
+    };
+    Ok(old.bits() as usize)
+}
+
+/// Return from a signal handler to where the thread was and the mask it had,
+/// with a0 as it was too.
+pub fn sys_sigreturn() -> SysResult {
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let (backup, mask) = task_inner.signal_backup.take().ok_or(SysError::Invalid)?;
+    task_inner.signal_mask = mask;
+    // the kernel stack and the hart are of now, which may differ after fork
+    let trap_cx = task_inner.get_trap_cx();
+    trap_cx.x = backup.x;
+    trap_cx.sepc = backup.sepc;
+    trap_cx.sstatus = backup.sstatus;
+    Ok(trap_cx.x[10])
+}
+
+/// Move the process `pid` to the group `pgid`, both 0 for the current one.
+/// Only the current process or its children can be moved, within their
+/// session, to a new group of their own or one of the session, and a session
+/// leader stays in its group.
+pub fn sys_setpgid(pid: usize, pgid: usize) -> SysResult {
+    let current = current_process();
+    let sid = current.inner_exclusive_access().sid;
+    let process = if pid == 0 || pid == current.getpid() {
+        current
+    } else {
+        let inner = current.inner_exclusive_access();
+        let child = inner.children.iter().find(|child| child.getpid() == pid);
+        child.ok_or(SysError::NoProcess)?.clone()
+    };
+    let pid = process.getpid();
+    let pgid = if pgid == 0 { pid } else { pgid };
+    if pgid != pid && !group_in_session(pgid, sid) {
+        return Err(SysError::NotPermitted);
+    }
+    let mut inner = process.inner_exclusive_access();
+    if inner.sid != sid || inner.sid == pid {
+        return Err(SysError::NotPermitted);
+    }
+    inner.pgid = pgid;
+    Ok(0)
+}
+
+/// Get the group of the process `pid`, 0 for the current one.
+pub fn sys_getpgid(pid: usize) -> SysResult {
+    let process = if pid == 0 {
+        current_process()
     } else {
-        -1
+        pid2process(pid).ok_or(SysError::NoProcess)?
+    };
+    let pgid = process.inner_exclusive_access().pgid;
+    Ok(pgid)
+}
+
+/// Start a new session with a new group, both of the id of the current
+/// process, which must not lead a group already. The session controls no
+/// terminal until it sets the foreground group of one.
+pub fn sys_setsid() -> SysResult {
+    let process = current_process();
+    let pid = process.getpid();
+    let sid = process.inner_exclusive_access().sid;
+    // the group of the pid may be left by the process or its children
+    if group_in_session(pid, sid) {
+        return Err(SysError::NotPermitted);
+    }
+    let mut inner = process.inner_exclusive_access();
+    inner.pgid = pid;
+    inner.sid = pid;
+    Ok(pid)
+}
+
+pub fn sys_getsid(pid: usize) -> SysResult {
+    let process = if pid == 0 {
+        current_process()
+    } else {
+        pid2process(pid).ok_or(SysError::NoProcess)?
+    };
+    let sid = process.inner_exclusive_access().sid;
+    Ok(sid)
+}
+
+pub fn sys_getuid() -> SysResult {
+    Ok(current_uid() as usize)
+}
+
+/// Only root may change the user, to any one which file systems record.
+/// Others may only set the one they are.
+pub fn sys_setuid(uid: usize) -> SysResult {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if uid > u16::MAX as usize {
+        return Err(SysError::Invalid);
+    }
+    if inner.uid != 0 && inner.uid as usize != uid {
+        return Err(SysError::NotPermitted);
+    }
+    inner.uid = uid as u32;
+    Ok(0)
+}
+
+const PRIO_PROCESS: usize = 0;
+const PRIO_PGRP: usize = 1;
+
+/// The processes of `who` as `which`, a process or a group, 0 for the
+/// current one. Users are not supported.
+fn priority_targets(which: usize, who: usize) -> Vec<Arc<ProcessControlBlock>> {
+    let current = current_process();
+    match (which, who) {
+        (PRIO_PROCESS, 0) => vec![current],
+        (PRIO_PROCESS, pid) => pid2process(pid).into_iter().collect(),
+        (PRIO_PGRP, 0) => {
+            let pgid = current.inner_exclusive_access().pgid;
+            processes_of_group(pgid)
+        }
+        (PRIO_PGRP, pgid) => processes_of_group(pgid),
+        _ => Vec::new(),
+    }
+}
+
+/// Set the nice value of the processes selected like `sys_getpriority`,
+/// clamped to `NICE_MIN..=NICE_MAX`. The threads of them take it the next
+/// time they are scheduled. Only root may lower it, `Denied` otherwise, or
+/// change it for the processes of other users, `NotPermitted` otherwise.
+pub fn sys_setpriority(which: usize, who: usize, nice: isize) -> SysResult {
+    let processes = priority_targets(which, who);
+    if processes.is_empty() {
+        return Err(SysError::NoProcess);
+    }
+    let nice = nice.clamp(NICE_MIN, NICE_MAX);
+    if current_uid() != 0 {
+        if !processes.iter().all(|process| may_control(process)) {
+            return Err(SysError::NotPermitted);
+        }
+        if processes
+            .iter()
+            .any(|process| nice < process.inner_exclusive_access().nice)
+        {
+            return Err(SysError::Denied);
+        }
+    }
+    for process in processes.iter() {
+        let mut inner = process.inner_exclusive_access();
+        inner.nice = nice;
+        for task in inner.tasks.iter().flatten() {
+            task.set_nice(nice);
+        }
+    }
+    Ok(0)
+}
+
+/// The least nice value of the processes `who` as `which`, a process or a
+/// group, 0 for the current one. Like Linux it is returned as `20 - nice`,
+/// from 1 to 40, so that none is taken as an error.
+pub fn sys_getpriority(which: usize, who: usize) -> SysResult {
+    priority_targets(which, who)
+        .iter()
+        .map(|process| process.inner_exclusive_access().nice)
+        .min()
+        .map(|nice| (20 - nice) as usize)
+        .ok_or(SysError::NoProcess)
+}
+
+/// The resource of the limit of file descriptors, the only one supported.
+const RLIMIT_NOFILE: usize = 7;
+
+/// Get and set a resource limit of the process `pid`, 0 for the current one.
+/// Either pointer may be null. Only root may raise the hard limit, or change
+/// the limits of the processes of other users.
+pub fn sys_prlimit(
+    pid: usize,
+    resource: usize,
+    new_limit: UserSliceRef<RLimit>,
+    old_limit: UserSliceRef<RLimit>,
+) -> SysResult {
+    if resource != RLIMIT_NOFILE {
+        return Err(SysError::Invalid);
+    }
+    let process = if pid == 0 {
+        current_process()
+    } else {
+        pid2process(pid).ok_or(SysError::NoProcess)?
+    };
+    let token = current_user_token();
+    let root = current_uid() == 0;
+    if !new_limit.is_null() && !may_control(&process) {
+        return Err(SysError::NotPermitted);
+    }
+    let new_limit = if new_limit.is_null() {
+        None
+    } else {
+        Some(new_limit.get(token, 0).ok_or(SysError::Fault)?)
+    };
+    let mut fd_table = process.fd_table.write();
+    let limit = fd_table.limit();
+    if let Some(new_limit) = new_limit {
+        if !root && new_limit.max > limit.max {
+            return Err(SysError::NotPermitted);
+        }
+        if !fd_table.set_limit(new_limit) {
+            return Err(SysError::Invalid);
+        }
+    }
+    drop(fd_table);
+    if !old_limit.is_null() && old_limit.set(token, 0, limit).is_none() {
+        return Err(SysError::Fault);
+    }
+    Ok(0)
+}
+
+/// The Linux personality, whose syscalls are dispatched by the compatibility
+/// layer.
+pub const PER_LINUX: usize = 0;
+/// The native personality of rCore, not defined by Linux.
+pub const PER_RCORE: usize = 0x7263;
+const PERSONALITY_QUERY: usize = 0xffff_ffff;
+
+/// Switch the syscall ABI of the current process, which is kept across fork
+/// and exec. Return the previous personality.
+pub fn sys_personality(persona: usize) -> SysResult {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let previous = if inner.linux.is_some() {
+        PER_LINUX
+    } else {
+        PER_RCORE
+    };
+    match persona {
+        PER_LINUX if inner.linux.is_none() => inner.linux = Some(LinuxAbi::new()),
+        PER_RCORE => inner.linux = None,
+        PER_LINUX | PERSONALITY_QUERY => {}
+        _ => return Err(SysError::Invalid),
+    }
+    Ok(previous)
+}
+
+/// The hardware watchpoints, like the requests of Linux on ARM but with a
+/// `Watchpoint` for each slot instead of the registers.
+const PTRACE_GETHBPREGS: usize = 29;
+const PTRACE_SETHBPREGS: usize = 30;
+
+/// Trace the process `pid`, the current one if 0 or else one of its children
+/// of the same user unless the current one is root.
+/// Only watchpoints are supported now: slot `addr` is read into or set from
+/// the `Watchpoint` at `data`, and one of length 0 clears the slot.
+pub fn sys_ptrace(request: usize, pid: usize, addr: usize, data: usize) -> SysResult {
+    if addr >= MAX_WATCHPOINTS {
+        return Err(SysError::Invalid);
+    }
+    let current = current_process();
+    let process = if pid == 0 || pid == current.getpid() {
+        current
+    } else {
+        let inner = current.inner_exclusive_access();
+        let child = inner.children.iter().find(|child| child.getpid() == pid);
+        child.ok_or(SysError::NoProcess)?.clone()
+    };
+    if !may_control(&process) {
+        return Err(SysError::NotPermitted);
+    }
+    let token = current_user_token();
+    let data = UserSliceRef::<Watchpoint>::one(data);
+    match request {
+        PTRACE_GETHBPREGS => {
+            let watchpoint = process.inner_exclusive_access().watchpoints[addr];
+            if data.set(token, 0, watchpoint).is_none() {
+                return Err(SysError::Fault);
+            }
+        }
+        PTRACE_SETHBPREGS => {
+            let watchpoint = data.get(token, 0).ok_or(SysError::Fault)?;
+            if watchpoint.is_set() && (!watchpoint.is_valid() || !watchpoints_available()) {
+                return Err(SysError::Invalid);
+            }
+            let mut inner = process.inner_exclusive_access();
+            inner.watchpoints[addr] = watchpoint;
+            let watchpoints = inner.watchpoints;
+            drop(inner);
+            reload_watchpoints(process.getpid(), &watchpoints);
+        }
+        _ => return Err(SysError::Invalid),
     }
+    Ok(0)
 }
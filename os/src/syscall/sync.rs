@@ -1,22 +1,87 @@
-use crate::sync::{Condvar, Mutex, MutexBlocking, MutexSpin, Semaphore};
-use crate::task::{block_current_and_run_next, current_process, current_task};
-use crate::timer::{add_timer, get_time_ms};
+use crate::mm::UserSliceRef;
+use crate::sync::{
+    futex_wait, futex_wake, Condvar, FutexWait, Mutex, MutexBlocking, MutexError, MutexSpin,
+    Semaphore, SemaphoreError,
+};
+use crate::task::{
+    block_current_and_run_next_interruptible, current_process, current_task, current_user_token,
+    WakeReason,
+};
+use crate::timer::{get_time_ms, get_time_ns};
+use abi::{SysError, SysResult};
 use alloc::sync::Arc;
 
-pub fn sys_sleep(ms: usize) -> isize {
-    let expire_ms = get_time_ms() + ms;
-    let task = current_task().unwrap();
-    add_timer(expire_ms, task);
-    block_current_and_run_next();
-    0
+/// Return `Interrupted` if a signal comes first, it is never restarted as
+/// the time would start over.
+pub fn sys_sleep(ms: usize) -> SysResult {
+    sys_nanosleep(ms.saturating_mul(1_000_000))
 }
 
-pub fn sys_mutex_create(blocking: bool) -> isize {
+/// Block until `ns` nanoseconds pass, woken up by a kernel timer as precise
+/// as the timebase. Return `Interrupted` if a signal comes first, it is
+/// never restarted as the time would start over.
+pub fn sys_nanosleep(ns: usize) -> SysResult {
+    let expire_ns = get_time_ns().saturating_add(ns);
+    while get_time_ns() < expire_ns {
+        if block_current_and_run_next_interruptible(Some(expire_ns)) != WakeReason::Woken {
+            return Err(SysError::Interrupted);
+        }
+    }
+    Ok(0)
+}
+
+pub const FUTEX_WAIT: usize = 0;
+pub const FUTEX_WAKE: usize = 1;
+
+/// `FUTEX_WAIT` sleeps if the word at `uaddr` is `val`, until woken up by
+/// `FUTEX_WAKE` of it or `timeout_ns` passes, forever if it is negative.
+/// Return 0 if woken up, `WouldBlock` if the word is not `val`, `NotReady` on
+/// timeout, or `Interrupted` if a signal comes first.
+///
+/// `FUTEX_WAKE` wakes up at most `val` threads waiting on the word, return
+/// how many.
+///
+/// Fail if the word is not writable by the user or `op` is unknown.
+pub fn sys_futex(uaddr: UserSliceRef<u32>, op: usize, val: usize, timeout_ns: isize) -> SysResult {
+    let (addr, word) = uaddr.atomic(current_user_token()).ok_or(SysError::Fault)?;
+    match op {
+        FUTEX_WAIT => {
+            let expire_ns =
+                (timeout_ns >= 0).then(|| get_time_ns().saturating_add(timeout_ns as usize));
+            match futex_wait(addr, word, val as u32, expire_ns) {
+                FutexWait::Woken => Ok(0),
+                FutexWait::Changed => Err(SysError::WouldBlock),
+                FutexWait::TimedOut => Err(SysError::NotReady),
+                FutexWait::Interrupted => Err(SysError::Interrupted),
+            }
+        }
+        FUTEX_WAKE => Ok(futex_wake(addr, val)),
+        _ => Err(SysError::NoSys),
+    }
+}
+
+fn mutex_result(result: Result<(), MutexError>) -> SysResult {
+    result.map(|()| 0).map_err(|error| match error {
+        MutexError::TimedOut => SysError::NotReady,
+        MutexError::OwnerDied => SysError::OwnerDead,
+        MutexError::Interrupted => SysError::Interrupted,
+        MutexError::Deadlock => SysError::Deadlock,
+        MutexError::NotOwner => SysError::NotPermitted,
+    })
+}
+
+fn mutex_locked(result: &Result<(), MutexError>) -> bool {
+    matches!(result, Ok(()) | Err(MutexError::OwnerDied))
+}
+
+/// An error-checking mutex fails when it is locked again by the owner or
+/// unlocked by others.
+pub fn sys_mutex_create(blocking: bool, errorcheck: bool) -> SysResult {
     let process = current_process();
     let mutex: Option<Arc<dyn Mutex>> = if !blocking {
-        Some(Arc::new(MutexSpin::new()))
+        Some(Arc::new(MutexSpin::new(errorcheck)))
     } else {
-        Some(Arc::new(MutexBlocking::new()))
+        Some(Arc::new(MutexBlocking::new(errorcheck)))
     };
     let mut process_inner = process.inner_exclusive_access();
     if let Some(id) = process_inner
@@ -27,34 +92,71 @@ pub fn sys_mutex_create(blocking: bool) -> isize {
         .map(|(id, _)| id)
     {
         process_inner.mutex_list[id] = mutex;
-        id as isize
+        process_inner.mutex_detector.add_resource(id, 1);
+        Ok(id)
     } else {
         process_inner.mutex_list.push(mutex);
-        process_inner.mutex_list.len() as isize - 1
+        let id = process_inner.mutex_list.len() - 1;
+        process_inner.mutex_detector.add_resource(id, 1);
+        Ok(id)
     }
 }
 
-pub fn sys_mutex_lock(mutex_id: usize) -> isize {
+fn current_tid() -> usize {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .unwrap()
+        .tid
+}
+
+/// Fail with `Deadlock` if the banker's algorithm finds one.
+fn mutex_lock(mutex_id: usize, expire_ms: Option<usize>) -> SysResult {
     let process = current_process();
-    let process_inner = process.inner_exclusive_access();
+    let mut process_inner = process.inner_exclusive_access();
     let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
+    let tid = current_tid();
+    let check = process_inner.deadlock_detect;
+    if !process_inner.mutex_detector.request(tid, mutex_id, check) {
+        return Err(SysError::Deadlock);
+    }
     drop(process_inner);
-    drop(process);
-    mutex.lock();
-    0
+    let result = mutex.lock(expire_ms);
+    process
+        .inner_exclusive_access()
+        .mutex_detector
+        .finish_request(tid, mutex_id, mutex_locked(&result));
+    mutex_result(result)
 }
 
-pub fn sys_mutex_unlock(mutex_id: usize) -> isize {
+/// Return `Interrupted` if a signal comes first, like the other waits below.
+pub fn sys_mutex_lock(mutex_id: usize) -> SysResult {
+    mutex_lock(mutex_id, None)
+}
+
+/// Return `NotReady` if the mutex is not locked in `timeout_ms`.
+pub fn sys_mutex_lock_timeout(mutex_id: usize, timeout_ms: usize) -> SysResult {
+    mutex_lock(mutex_id, Some(get_time_ms() + timeout_ms))
+}
+
+pub fn sys_mutex_unlock(mutex_id: usize) -> SysResult {
     let process = current_process();
     let process_inner = process.inner_exclusive_access();
     let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
     drop(process_inner);
-    drop(process);
-    mutex.unlock();
-    0
+    let result = mutex.unlock();
+    if result.is_ok() {
+        process
+            .inner_exclusive_access()
+            .mutex_detector
+            .release(current_tid(), mutex_id);
+    }
+    mutex_result(result)
 }
 
-pub fn sys_semaphore_create(res_count: usize) -> isize {
+pub fn sys_semaphore_create(res_count: usize) -> SysResult {
     let process = current_process();
     let mut process_inner = process.inner_exclusive_access();
     let id = if let Some(id) = process_inner
@@ -72,28 +174,53 @@ pub fn sys_semaphore_create(res_count: usize) -> isize {
             .push(Some(Arc::new(Semaphore::new(res_count))));
         process_inner.semaphore_list.len() - 1
     };
-    id as isize
+    process_inner.semaphore_detector.add_resource(id, res_count);
+    Ok(id)
 }
 
-pub fn sys_semaphore_up(sem_id: usize) -> isize {
+pub fn sys_semaphore_up(sem_id: usize) -> SysResult {
     let process = current_process();
-    let process_inner = process.inner_exclusive_access();
+    let mut process_inner = process.inner_exclusive_access();
     let sem = Arc::clone(process_inner.semaphore_list[sem_id].as_ref().unwrap());
+    process_inner
+        .semaphore_detector
+        .release(current_tid(), sem_id);
     drop(process_inner);
     sem.up();
-    0
+    Ok(0)
 }
 
-pub fn sys_semaphore_down(sem_id: usize) -> isize {
+fn semaphore_down(sem_id: usize, expire_ms: Option<usize>) -> SysResult {
     let process = current_process();
-    let process_inner = process.inner_exclusive_access();
+    let mut process_inner = process.inner_exclusive_access();
     let sem = Arc::clone(process_inner.semaphore_list[sem_id].as_ref().unwrap());
+    let tid = current_tid();
+    let check = process_inner.deadlock_detect;
+    if !process_inner.semaphore_detector.request(tid, sem_id, check) {
+        return Err(SysError::Deadlock);
+    }
     drop(process_inner);
-    sem.down();
-    0
+    let result = sem.down(expire_ms);
+    process
+        .inner_exclusive_access()
+        .semaphore_detector
+        .finish_request(tid, sem_id, result.is_ok());
+    result.map(|()| 0).map_err(|error| match error {
+        SemaphoreError::TimedOut => SysError::NotReady,
+        SemaphoreError::Interrupted => SysError::Interrupted,
+    })
 }
 
-pub fn sys_condvar_create() -> isize {
+pub fn sys_semaphore_down(sem_id: usize) -> SysResult {
+    semaphore_down(sem_id, None)
+}
+
+/// Return `NotReady` if the semaphore is not acquired in `timeout_ms`.
+pub fn sys_semaphore_down_timeout(sem_id: usize, timeout_ms: usize) -> SysResult {
+    semaphore_down(sem_id, Some(get_time_ms() + timeout_ms))
+}
+
+pub fn sys_condvar_create() -> SysResult {
     let process = current_process();
     let mut process_inner = process.inner_exclusive_access();
     let id = if let Some(id) = process_inner
@@ -111,24 +238,60 @@ pub fn sys_condvar_create() -> isize {
             .push(Some(Arc::new(Condvar::new())));
         process_inner.condvar_list.len() - 1
     };
-    id as isize
+    Ok(id)
 }
 
-pub fn sys_condvar_signal(condvar_id: usize) -> isize {
+pub fn sys_condvar_signal(condvar_id: usize) -> SysResult {
     let process = current_process();
     let process_inner = process.inner_exclusive_access();
     let condvar = Arc::clone(process_inner.condvar_list[condvar_id].as_ref().unwrap());
     drop(process_inner);
     condvar.signal();
-    0
+    Ok(0)
 }
 
-pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
+fn condvar_wait(condvar_id: usize, mutex_id: usize, expire_ms: Option<usize>) -> SysResult {
     let process = current_process();
-    let process_inner = process.inner_exclusive_access();
+    let mut process_inner = process.inner_exclusive_access();
     let condvar = Arc::clone(process_inner.condvar_list[condvar_id].as_ref().unwrap());
     let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
+    let tid = current_tid();
+    // the mutex is released while waiting
+    if process_inner.mutex_detector.holds(tid, mutex_id) {
+        process_inner.mutex_detector.release(tid, mutex_id);
+    }
     drop(process_inner);
-    condvar.wait_with_mutex(mutex);
-    0
+    let result = condvar.wait_with_mutex(mutex, expire_ms);
+    // and locked again unless it cannot be unlocked at first
+    if result != Err(MutexError::NotOwner) {
+        let mut process_inner = process.inner_exclusive_access();
+        process_inner.mutex_detector.request(tid, mutex_id, false);
+        process_inner
+            .mutex_detector
+            .finish_request(tid, mutex_id, true);
+    }
+    mutex_result(result)
+}
+
+pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> SysResult {
+    condvar_wait(condvar_id, mutex_id, None)
+}
+
+/// Return `NotReady` if not signaled in `timeout_ms`, the mutex is locked again anyway.
+pub fn sys_condvar_wait_timeout(
+    condvar_id: usize,
+    mutex_id: usize,
+    timeout_ms: usize,
+) -> SysResult {
+    condvar_wait(condvar_id, mutex_id, Some(get_time_ms() + timeout_ms))
+}
+
+/// Enable deadlock detection of the current process if `enabled` is 1, or
+/// disable it if 0.
+pub fn sys_enable_deadlock_detect(enabled: usize) -> SysResult {
+    if enabled > 1 {
+        return Err(SysError::Invalid);
+    }
+    current_process().inner_exclusive_access().deadlock_detect = enabled == 1;
+    Ok(0)
 }
@@ -3,9 +3,10 @@ use crate::{
     task::{add_task, current_task, TaskControlBlock},
     trap::{trap_handler, TrapContext},
 };
+use abi::{SysError, SysResult};
 use alloc::sync::Arc;
 
-pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
+pub fn sys_thread_create(entry: usize, arg: usize) -> SysResult {
     let task = current_task().unwrap();
     let process = task.process.upgrade().unwrap();
     // create a new thread
@@ -18,18 +19,20 @@ pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
             .ustack_base,
         true,
     ));
-    // add new task to scheduler
-    add_task(Arc::clone(&new_task));
-    let new_task_inner = new_task.inner_exclusive_access();
-    let new_task_res = new_task_inner.res.as_ref().unwrap();
-    let new_task_tid = new_task_res.tid;
+    let signal_mask = task.inner_exclusive_access().signal_mask;
+    let new_task_tid = new_task.inner_exclusive_access().res.as_ref().unwrap().tid;
+    // add new thread to current process, which is locked before the thread
     let mut process_inner = process.inner_exclusive_access();
-    // add new thread to current process
     let tasks = &mut process_inner.tasks;
     while tasks.len() < new_task_tid + 1 {
         tasks.push(None);
     }
     tasks[new_task_tid] = Some(Arc::clone(&new_task));
+    drop(process_inner);
+    let mut new_task_inner = new_task.inner_exclusive_access();
+    // the new thread blocks the signals its creator blocks
+    new_task_inner.signal_mask = signal_mask;
+    let new_task_res = new_task_inner.res.as_ref().unwrap();
     let new_task_trap_cx = new_task_inner.get_trap_cx();
     *new_task_trap_cx = TrapContext::app_init_context(
         entry,
@@ -39,30 +42,36 @@ pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
         trap_handler as usize,
     );
     (*new_task_trap_cx).x[10] = arg;
-    new_task_tid as isize
+    drop(new_task_inner);
+    // add new task to scheduler once it is ready to run on any hart
+    add_task(new_task);
+    Ok(new_task_tid)
 }
 
-pub fn sys_gettid() -> isize {
-    current_task()
+pub fn sys_gettid() -> SysResult {
+    Ok(current_task()
         .unwrap()
         .inner_exclusive_access()
         .res
         .as_ref()
         .unwrap()
-        .tid as isize
+        .tid)
 }
 
-/// thread does not exist, return -1
-/// thread has not exited yet, return -2
+/// thread does not exist, return `NoProcess`
+/// thread is the current one, return `Deadlock`
+/// thread has not exited yet, return `NotReady`
 /// otherwise, return thread's exit code
-pub fn sys_waittid(tid: usize) -> i32 {
+pub fn sys_waittid(tid: usize) -> SysResult {
     let task = current_task().unwrap();
     let process = task.process.upgrade().unwrap();
-    let task_inner = task.inner_exclusive_access();
-    let mut process_inner = process.inner_exclusive_access();
     // a thread cannot wait for itself
-    if task_inner.res.as_ref().unwrap().tid == tid {
-        return -1;
+    if task.inner_exclusive_access().res.as_ref().unwrap().tid == tid {
+        return Err(SysError::Deadlock);
+    }
+    let mut process_inner = process.inner_exclusive_access();
+    if tid >= process_inner.tasks.len() {
+        return Err(SysError::NoProcess);
     }
     let mut exit_code: Option<i32> = None;
     let waited_task = process_inner.tasks[tid].as_ref();
@@ -72,14 +81,14 @@ pub fn sys_waittid(tid: usize) -> i32 {
         }
     } else {
         // waited thread does not exist
-        return -1;
+        return Err(SysError::NoProcess);
     }
     if let Some(exit_code) = exit_code {
         // dealloc the exited thread
         process_inner.tasks[tid] = None;
-        exit_code
+        Ok(exit_code as usize)
     } else {
         // waited thread has not exited
-        -2
+        Err(SysError::NotReady)
     }
 }
@@ -0,0 +1,113 @@
+//! Magic SysRq: debug actions from the serial console, which work even when
+//! the shell is wedged.
+//!
+//! A break on the UART, or `SYSRQ_SEQUENCE` typed in a row, makes the next
+//! key a command instead of input. The bytes of the sequence still go to
+//! the reader. Commands run in the interrupt handler, except syncing the
+//! file system, which waits for the disk and so runs on the next trap from
+//! a user task.
+
+use crate::config::SYSRQ_SEQUENCE;
+use crate::mm::{frame_stats, heap_stats};
+use crate::sbi::reboot;
+use crate::sync::UPIntrFreeCell;
+use crate::task::{dump_tasks, toggle_sched_trace};
+use core::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::*;
+
+struct SysRq {
+    /// whether the next byte is a command
+    armed: bool,
+    /// the length of the prefix of `SYSRQ_SEQUENCE` received
+    matched: usize,
+}
+
+lazy_static! {
+    static ref SYSRQ: UPIntrFreeCell<SysRq> = unsafe {
+        UPIntrFreeCell::new(SysRq {
+            armed: false,
+            matched: 0,
+        })
+    };
+}
+
+static SYNC_PENDING: AtomicBool = AtomicBool::new(false);
+
+fn arm(sysrq: &mut SysRq) {
+    sysrq.armed = true;
+    sysrq.matched = 0;
+    // a break storm arms it again and again
+    log_ratelimited!("\n[sysrq] press a key, h for help");
+}
+
+/// A break is received on the console.
+#[allow(unused)]
+pub fn handle_break() {
+    arm(&mut SYSRQ.exclusive_access());
+}
+
+/// Check a byte received on the console, return false if it is taken as a
+/// command.
+pub fn handle_input(ch: u8) -> bool {
+    let mut sysrq = SYSRQ.exclusive_access();
+    if sysrq.armed {
+        sysrq.armed = false;
+        drop(sysrq);
+        run(ch);
+        return false;
+    }
+    if SYSRQ_SEQUENCE.is_empty() {
+        return true;
+    }
+    if ch == SYSRQ_SEQUENCE[sysrq.matched] {
+        sysrq.matched += 1;
+    } else {
+        sysrq.matched = (ch == SYSRQ_SEQUENCE[0]) as usize;
+    }
+    if sysrq.matched == SYSRQ_SEQUENCE.len() {
+        arm(&mut sysrq);
+    }
+    true
+}
+
+fn run(ch: u8) {
+    match ch {
+        b't' => dump_tasks(),
+        b'm' => {
+            let (free, total) = frame_stats();
+            println!("[sysrq] frames: {} free of {}", free, total);
+            match heap_stats() {
+                Some((used, total)) => println!("[sysrq] heap: {} of {} bytes used", used, total),
+                None => println!("[sysrq] heap: locked"),
+            }
+        }
+        b'z' => {
+            let on = toggle_sched_trace();
+            println!("[sysrq] scheduler trace {}", if on { "on" } else { "off" });
+        }
+        b's' => {
+            SYNC_PENDING.store(true, Ordering::Relaxed);
+            println!("[sysrq] sync on the next trap from a task");
+        }
+        b'b' => {
+            println!("[sysrq] rebooting");
+            reboot();
+        }
+        // like that of Linux, to test crash dumps
+        b'c' => panic!("[sysrq] crash"),
+        _ => println!("[sysrq] t:tasks m:memory z:scheduler-trace s:sync b:reboot c:crash"),
+    }
+}
+
+/// Whether commands are waiting for a task context.
+pub fn has_deferred() -> bool {
+    SYNC_PENDING.load(Ordering::Relaxed)
+}
+
+/// Run the commands waiting for a task context.
+pub fn run_deferred() {
+    if SYNC_PENDING.swap(false, Ordering::Relaxed) {
+        easy_fs::block_cache_sync_all();
+        println!("[sysrq] synced");
+    }
+}
@@ -0,0 +1,187 @@
+//! The formats of programs exec and spawn run, tried in the order of
+//! `FORMATS` with the first page of the file. A format either loads the
+//! program to a new space, or names another program to run instead, like a
+//! script naming its interpreter. A new format is a `BinaryFormat` added to
+//! `FORMATS`, nothing else of exec changes.
+
+use crate::fs::OSInode;
+use crate::mm::{MemorySet, PageSource};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use xmas_elf::header::Type;
+
+/// Where a position-independent executable is loaded, above the klog and
+/// below the heap of the Linux ABI.
+const PIE_BASE: usize = 0x4000_0000;
+/// Scripts whose interpreter is a script, like Linux.
+const MAX_INTERPRETERS: usize = 4;
+
+/// A program loaded to a new space, which is not the one of any process yet.
+pub struct Program {
+    pub memory_set: MemorySet,
+    /// the base of the user stacks
+    pub ustack_base: usize,
+    pub entry_point: usize,
+    /// added to the addresses of the ELF, 0 unless it is PIE
+    pub load_bias: usize,
+    /// the ELF headers at least, for the auxv of Linux
+    pub elf_data: Vec<u8>,
+    /// the arguments, with the interpreters of scripts before them
+    pub args: Vec<String>,
+}
+
+pub enum Loaded {
+    Program(Program),
+    /// run the program at the path with the arguments instead
+    Interpreter(String, Vec<String>),
+}
+
+pub trait BinaryFormat: Sync {
+    /// Load the program `file` at `path`, whose first page is `header`.
+    /// Return None if it is not of this format, or it fails to load.
+    fn load(
+        &self,
+        file: &Arc<OSInode>,
+        header: &[u8],
+        path: &str,
+        args: &[String],
+    ) -> Option<Loaded>;
+}
+
+static FORMATS: &[&dyn BinaryFormat] = &[&StaticElf, &PieElf, &Shebang];
+
+/// Load the program `file` at `path`, with the interpreters opened by
+/// `open`. Return None if there is no format for it.
+pub fn load_program(
+    mut file: Arc<OSInode>,
+    path: &str,
+    mut args: Vec<String>,
+    open: impl Fn(&str) -> Option<Arc<OSInode>>,
+) -> Option<Program> {
+    let mut path = String::from(path);
+    for _ in 0..=MAX_INTERPRETERS {
+        let header = file.cached_page(0)?;
+        let header = header.ppn.get_bytes_array();
+        let loaded = FORMATS
+            .iter()
+            .find_map(|format| format.load(&file, header, path.as_str(), &args))?;
+        match loaded {
+            Loaded::Program(program) => return Some(program),
+            Loaded::Interpreter(interpreter, interpreter_args) => {
+                file = open(interpreter.as_str())?;
+                path = interpreter;
+                args = interpreter_args;
+            }
+        }
+    }
+    None
+}
+
+/// The type of the ELF in `header`, None if it is not an ELF.
+fn elf_type(header: &[u8]) -> Option<Type> {
+    if !header.starts_with(b"\x7fELF") {
+        return None;
+    }
+    let elf = xmas_elf::ElfFile::new(header).ok()?;
+    Some(elf.header.pt2.type_().as_type())
+}
+
+/// The segments are loaded from the page cache on demand, unless the
+/// program headers are beyond the first page, then the whole file is read.
+fn load_elf(file: &Arc<OSInode>, header: &[u8], args: &[String], load_bias: usize) -> Loaded {
+    let (memory_set, ustack_base, entry_point, elf_data) =
+        match MemorySet::from_elf_pages(Arc::clone(file) as Arc<dyn PageSource>, load_bias) {
+            Some((memory_set, ustack_base, entry_point)) => {
+                (memory_set, ustack_base, entry_point, header.to_vec())
+            }
+            None => {
+                let elf_data = file.read_all();
+                let (memory_set, ustack_base, entry_point) =
+                    MemorySet::from_elf(elf_data.as_slice(), load_bias);
+                (memory_set, ustack_base, entry_point, elf_data)
+            }
+        };
+    Loaded::Program(Program {
+        memory_set,
+        ustack_base,
+        entry_point,
+        load_bias,
+        elf_data,
+        args: args.to_vec(),
+    })
+}
+
+/// An ELF linked at fixed addresses.
+struct StaticElf;
+
+impl BinaryFormat for StaticElf {
+    fn load(
+        &self,
+        file: &Arc<OSInode>,
+        header: &[u8],
+        _path: &str,
+        args: &[String],
+    ) -> Option<Loaded> {
+        match elf_type(header)? {
+            Type::Executable => Some(load_elf(file, header, args, 0)),
+            _ => None,
+        }
+    }
+}
+
+/// A position-independent ELF, loaded at `PIE_BASE`. It relocates itself,
+/// there is no dynamic linker.
+struct PieElf;
+
+impl BinaryFormat for PieElf {
+    fn load(
+        &self,
+        file: &Arc<OSInode>,
+        header: &[u8],
+        _path: &str,
+        args: &[String],
+    ) -> Option<Loaded> {
+        match elf_type(header)? {
+            Type::SharedObject => Some(load_elf(file, header, args, PIE_BASE)),
+            _ => None,
+        }
+    }
+}
+
+/// A script starting with `#!interpreter [arg]`, run as the interpreter with
+/// the optional argument, the path of the script and the arguments but the
+/// first one.
+struct Shebang;
+
+impl BinaryFormat for Shebang {
+    fn load(
+        &self,
+        _file: &Arc<OSInode>,
+        header: &[u8],
+        path: &str,
+        args: &[String],
+    ) -> Option<Loaded> {
+        let line = header.strip_prefix(b"#!")?;
+        // the line is in the first page
+        let end = line.iter().position(|&byte| byte == b'\n')?;
+        let line = core::str::from_utf8(&line[..end]).ok()?.trim();
+        let (interpreter, arg) = match line.split_once(|c: char| c == ' ' || c == '\t') {
+            Some((interpreter, arg)) => (interpreter, arg.trim()),
+            None => (line, ""),
+        };
+        if interpreter.is_empty() {
+            return None;
+        }
+        let mut interpreter_args = Vec::from([String::from(interpreter)]);
+        if !arg.is_empty() {
+            interpreter_args.push(String::from(arg));
+        }
+        interpreter_args.push(String::from(path));
+        interpreter_args.extend(args.iter().skip(1).cloned());
+        Some(Loaded::Interpreter(
+            String::from(interpreter),
+            interpreter_args,
+        ))
+    }
+}
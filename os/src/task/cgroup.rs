@@ -0,0 +1,95 @@
+use crate::sync::UPIntrFreeCell;
+use crate::timer::get_time_us;
+use alloc::collections::BTreeMap;
+use lazy_static::*;
+
+/// The group of processes not placed into any group
+pub const ROOT_CPU_GROUP: usize = 0;
+pub const DEFAULT_SHARES: usize = 1024;
+/// quotas are refilled every period
+const PERIOD_US: usize = 100_000;
+
+/// A group of processes sharing cpu time, like the cpu controller of cgroups.
+/// Ready groups get the cpu in proportion to their shares, and a group with a
+/// quota is not scheduled after using up the quota until the next period.
+pub struct CpuGroup {
+    shares: usize,
+    /// cpu time allowed in each period, None for unlimited
+    quota_us: Option<usize>,
+    /// cpu time used scaled by shares, the ready group with the least runs
+    pub vruntime: usize,
+    period_start_us: usize,
+    period_used_us: usize,
+    total_used_us: usize,
+}
+
+impl CpuGroup {
+    fn new(shares: usize, quota_us: Option<usize>) -> Self {
+        Self {
+            shares,
+            quota_us,
+            vruntime: 0,
+            period_start_us: get_time_us(),
+            period_used_us: 0,
+            total_used_us: 0,
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = get_time_us();
+        if now >= self.period_start_us + PERIOD_US {
+            self.period_start_us = now;
+            self.period_used_us = 0;
+        }
+    }
+
+    pub fn throttled(&mut self) -> bool {
+        self.refill();
+        self.quota_us
+            .map_or(false, |quota_us| self.period_used_us >= quota_us)
+    }
+
+    fn charge(&mut self, us: usize) {
+        self.refill();
+        self.period_used_us += us;
+        self.total_used_us += us;
+        self.vruntime += us * DEFAULT_SHARES / self.shares;
+    }
+}
+
+lazy_static! {
+    pub static ref CPU_GROUPS: UPIntrFreeCell<BTreeMap<usize, CpuGroup>> = unsafe {
+        let mut groups = BTreeMap::new();
+        groups.insert(ROOT_CPU_GROUP, CpuGroup::new(DEFAULT_SHARES, None));
+        UPIntrFreeCell::new(groups)
+    };
+}
+
+/// Return the id of the new group, or None if `shares` is 0.
+pub fn create_cpu_group(shares: usize, quota_us: Option<usize>) -> Option<usize> {
+    if shares == 0 {
+        return None;
+    }
+    let mut groups = CPU_GROUPS.exclusive_access();
+    let id = groups.keys().next_back().unwrap() + 1;
+    groups.insert(id, CpuGroup::new(shares, quota_us));
+    Some(id)
+}
+
+pub fn cpu_group_exists(id: usize) -> bool {
+    CPU_GROUPS.exclusive_access().contains_key(&id)
+}
+
+/// Total cpu time used by the group in microseconds.
+pub fn cpu_group_usage(id: usize) -> Option<usize> {
+    CPU_GROUPS
+        .exclusive_access()
+        .get(&id)
+        .map(|group| group.total_used_us)
+}
+
+pub fn charge_cpu_group(id: usize, us: usize) {
+    if let Some(group) = CPU_GROUPS.exclusive_access().get_mut(&id) {
+        group.charge(us);
+    }
+}
@@ -0,0 +1,322 @@
+use super::process::ProcessControlBlock;
+use super::LinuxAbi;
+use crate::config::{PAGE_SIZE, USER_STACKS_END};
+use crate::fs::{open_file, File, OSInode, OpenFlags, Stdin, Stdout, SEEK_SET};
+use crate::mm::{AreaImage, MapPermission, MemorySet, VirtPageNum, KERNEL_SPACE};
+use crate::sync::DeadlockDetector;
+use crate::trap::{trap_handler, TrapContext};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+const CHECKPOINT_MAGIC: &[u8; 8] = b"rCoreCkp";
+
+const FD_STDIN: usize = 0;
+const FD_STDOUT: usize = 1;
+const FD_FILE: usize = 2;
+
+/// An fd kept in the checkpoint, other kinds of files are closed.
+enum FdImage {
+    Stdin,
+    Stdout,
+    /// a regular file reopened by its path
+    File {
+        path: String,
+        readable: bool,
+        writable: bool,
+        offset: usize,
+    },
+}
+
+/// The state of a single-threaded process.
+///
+/// NOTICE: mutexes, semaphores and condvars are not saved, neither are
+/// children and pending signals.
+struct Checkpoint {
+    x: [usize; 32],
+    sepc: usize,
+    ustack_base: usize,
+    root: String,
+    cwd: String,
+    linux: Option<LinuxAbi>,
+    areas: Vec<AreaImage>,
+    fds: Vec<(usize, FdImage)>,
+}
+
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn word(&mut self, word: usize) {
+        self.0.extend_from_slice(&word.to_le_bytes());
+    }
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.word(bytes.len());
+        self.0.extend_from_slice(bytes);
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+    fn word(&mut self) -> Option<usize> {
+        let bytes = self.take(core::mem::size_of::<usize>())?;
+        Some(usize::from_le_bytes(bytes.try_into().unwrap()))
+    }
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.word()?;
+        self.take(len)
+    }
+    fn string(&mut self) -> Option<String> {
+        Some(String::from(core::str::from_utf8(self.bytes()?).ok()?))
+    }
+}
+
+impl Checkpoint {
+    fn serialize(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.0.extend_from_slice(CHECKPOINT_MAGIC);
+        for x in self.x {
+            writer.word(x);
+        }
+        writer.word(self.sepc);
+        writer.word(self.ustack_base);
+        writer.bytes(self.root.as_bytes());
+        writer.bytes(self.cwd.as_bytes());
+        match &self.linux {
+            Some(linux) => {
+                writer.word(1);
+                writer.word(linux.brk);
+                writer.word(linux.brk_mapped);
+                writer.word(linux.mmap_end);
+            }
+            None => writer.word(0),
+        }
+        writer.word(self.areas.len());
+        for area in self.areas.iter() {
+            writer.word(area.start_vpn.0);
+            writer.word(area.end_vpn.0);
+            writer.word(area.perm.bits() as usize);
+            writer.bytes(area.data.as_slice());
+        }
+        writer.word(self.fds.len());
+        for (fd, image) in self.fds.iter() {
+            writer.word(*fd);
+            match image {
+                FdImage::Stdin => writer.word(FD_STDIN),
+                FdImage::Stdout => writer.word(FD_STDOUT),
+                FdImage::File {
+                    path,
+                    readable,
+                    writable,
+                    offset,
+                } => {
+                    writer.word(FD_FILE);
+                    writer.bytes(path.as_bytes());
+                    writer.word(*readable as usize);
+                    writer.word(*writable as usize);
+                    writer.word(*offset);
+                }
+            }
+        }
+        writer.0
+    }
+
+    fn deserialize(data: &[u8]) -> Option<Self> {
+        let mut reader = Reader { data, pos: 0 };
+        if reader.take(CHECKPOINT_MAGIC.len())? != CHECKPOINT_MAGIC {
+            return None;
+        }
+        let mut x = [0; 32];
+        for x in x.iter_mut() {
+            *x = reader.word()?;
+        }
+        let sepc = reader.word()?;
+        let ustack_base = reader.word()?;
+        let root = reader.string()?;
+        let cwd = reader.string()?;
+        let linux = match reader.word()? {
+            0 => None,
+            _ => Some(LinuxAbi {
+                brk: reader.word()?,
+                brk_mapped: reader.word()?,
+                mmap_end: reader.word()?,
+            }),
+        };
+        let mut areas = Vec::new();
+        for _ in 0..reader.word()? {
+            let start_vpn = VirtPageNum(reader.word()?);
+            let end_vpn = VirtPageNum(reader.word()?);
+            let perm = MapPermission::from_bits(reader.word()? as u8)?;
+            let data = Vec::from(reader.bytes()?);
+            areas.push(AreaImage {
+                start_vpn,
+                end_vpn,
+                perm,
+                data,
+            });
+        }
+        let mut fds = Vec::new();
+        for _ in 0..reader.word()? {
+            let fd = reader.word()?;
+            let image = match reader.word()? {
+                FD_STDIN => FdImage::Stdin,
+                FD_STDOUT => FdImage::Stdout,
+                FD_FILE => FdImage::File {
+                    path: reader.string()?,
+                    readable: reader.word()? != 0,
+                    writable: reader.word()? != 0,
+                    offset: reader.word()?,
+                },
+                _ => return None,
+            };
+            fds.push((fd, image));
+        }
+        Some(Self {
+            x,
+            sepc,
+            ustack_base,
+            root,
+            cwd,
+            linux,
+            areas,
+            fds,
+        })
+    }
+}
+
+fn fd_image(file: &Arc<dyn File + Send + Sync>) -> Option<FdImage> {
+    if file.as_any().is::<Stdin>() {
+        Some(FdImage::Stdin)
+    } else if file.as_any().is::<Stdout>() {
+        Some(FdImage::Stdout)
+    } else {
+        let inode = file.as_any().downcast_ref::<OSInode>()?;
+        Some(FdImage::File {
+            path: String::from(inode.path()),
+            readable: file.readable(),
+            writable: file.writable(),
+            offset: inode.offset(),
+        })
+    }
+}
+
+fn reopen(image: &FdImage) -> Option<Arc<dyn File + Send + Sync>> {
+    match image {
+        FdImage::Stdin => Some(Arc::new(Stdin)),
+        FdImage::Stdout => Some(Arc::new(Stdout)),
+        FdImage::File {
+            path,
+            readable,
+            writable,
+            offset,
+        } => {
+            let flags = match (readable, writable) {
+                (true, true) => OpenFlags::RDWR,
+                (false, true) => OpenFlags::WRONLY,
+                _ => OpenFlags::RDONLY,
+            };
+            let inode = open_file(path.as_str(), flags).ok()?;
+            inode.seek(*offset as isize, SEEK_SET);
+            Some(inode)
+        }
+    }
+}
+
+impl ProcessControlBlock {
+    /// Save the process in a syscall, where it returns 1 after restored.
+    /// Only support processes with a single thread.
+    pub fn checkpoint(&self) -> Option<Vec<u8>> {
+        let mut inner = self.inner_exclusive_access();
+        let task = inner.only_task()?;
+        let task_inner = task.inner_exclusive_access();
+        let trap_cx = task_inner.get_trap_cx();
+        let mut x = trap_cx.x;
+        x[10] = 1;
+        let checkpoint = Checkpoint {
+            x,
+            // the ecall has been skipped
+            sepc: trap_cx.sepc,
+            ustack_base: task_inner.res.as_ref().unwrap().ustack_base(),
+            root: inner.root.clone(),
+            cwd: inner.cwd.clone(),
+            linux: inner.linux.clone(),
+            areas: inner.memory_set.user_area_images(),
+            fds: self
+                .fd_table
+                .read()
+                .iter()
+                .filter_map(|(fd, file)| Some((fd, fd_image(file)?)))
+                .collect(),
+        };
+        Some(checkpoint.serialize())
+    }
+
+    /// Replace the process with a checkpoint like exec, files which cannot be
+    /// reopened are closed. Only support processes with a single thread.
+    /// Return false, with the process unchanged, if the checkpoint is not
+    /// valid.
+    pub fn restore(self: &Arc<Self>, image: &[u8]) -> bool {
+        let checkpoint = match Checkpoint::deserialize(image) {
+            Some(checkpoint) => checkpoint,
+            None => return false,
+        };
+        let task = match self.inner_exclusive_access().only_task() {
+            Some(task) => task,
+            None => return false,
+        };
+        let ustack_base = checkpoint.ustack_base;
+        if ustack_base % PAGE_SIZE != 0 || !(PAGE_SIZE..=USER_STACKS_END).contains(&ustack_base) {
+            return false;
+        }
+        let memory_set = match MemorySet::from_area_images(&checkpoint.areas) {
+            Some(memory_set) => memory_set,
+            None => return false,
+        };
+        let files: Vec<(usize, Arc<dyn File + Send + Sync>)> = checkpoint
+            .fds
+            .iter()
+            .filter_map(|(fd, image)| Some((*fd, reopen(image)?)))
+            .collect();
+        let mut inner = self.inner_exclusive_access();
+        inner.memory_set = memory_set;
+        let mut fd_table = self.fd_table.write();
+        fd_table.clear();
+        for (fd, file) in files {
+            fd_table.insert(fd, file);
+        }
+        drop(fd_table);
+        inner.root = checkpoint.root;
+        inner.cwd = checkpoint.cwd;
+        inner.linux = checkpoint.linux;
+        inner.mutex_list.clear();
+        inner.semaphore_list.clear();
+        inner.condvar_list.clear();
+        inner.mutex_detector = DeadlockDetector::new();
+        inner.semaphore_detector = DeadlockDetector::new();
+        drop(inner);
+        // the user stack is restored with other areas
+        let mut task_inner = task.inner_exclusive_access();
+        task_inner.res.as_mut().unwrap().ustack_base = checkpoint.ustack_base;
+        task_inner.res.as_mut().unwrap().alloc_trap_cx();
+        task_inner.trap_cx_ppn = task_inner.res.as_mut().unwrap().trap_cx_ppn();
+        let mut trap_cx = TrapContext::app_init_context(
+            checkpoint.sepc,
+            checkpoint.x[2],
+            KERNEL_SPACE.exclusive_access().token(),
+            task.kstack.get_top(),
+            trap_handler as usize,
+        );
+        trap_cx.x = checkpoint.x;
+        *task_inner.get_trap_cx() = trap_cx;
+        true
+    }
+}
@@ -22,4 +22,12 @@ impl TaskContext {
             s: [0; 12],
         }
     }
+    /// Start a kernel thread at `entry` on its own kernel stack.
+    pub fn goto_kernel_thread(entry: fn() -> !, kstack_ptr: usize) -> Self {
+        Self {
+            ra: entry as usize,
+            sp: kstack_ptr,
+            s: [0; 12],
+        }
+    }
 }
@@ -0,0 +1,198 @@
+//! The file descriptor table of a process.
+//!
+//! The table grows on demand up to the `RLIMIT_NOFILE` of the process. Used
+//! descriptors are tracked in a bitmap with a summary word of the full words
+//! of it, so the lowest free descriptor is found in constant time.
+
+use crate::fs::File;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bitflags::*;
+
+/// The hard limit of descriptors of any process, one summary word covers all
+/// words of the bitmap.
+pub const NOFILE_MAX: usize = 64 * 64;
+/// The soft limit of descriptors of a new process.
+const NOFILE_DEFAULT: usize = 1024;
+
+/// A resource limit, the same as `struct rlimit` of Linux.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct RLimit {
+    pub cur: usize,
+    pub max: usize,
+}
+
+bitflags! {
+    /// Flags of a descriptor rather than the file, which are not shared by
+    /// its duplicates.
+    pub struct FdFlags: u32 {
+        /// closed by a successful exec
+        const CLOEXEC = 1;
+    }
+}
+
+#[derive(Clone)]
+struct FdEntry {
+    file: Arc<dyn File + Send + Sync>,
+    flags: FdFlags,
+}
+
+#[derive(Clone)]
+pub struct FdTable {
+    entries: Vec<Option<FdEntry>>,
+    /// a bit for each descriptor in use
+    used: [u64; NOFILE_MAX / 64],
+    /// a bit for each word of `used` with all bits set
+    full: u64,
+    limit: RLimit,
+}
+
+impl FdTable {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            used: [0; NOFILE_MAX / 64],
+            full: 0,
+            limit: RLimit {
+                cur: NOFILE_DEFAULT,
+                max: NOFILE_MAX,
+            },
+        }
+    }
+
+    pub fn get(&self, fd: usize) -> Option<&Arc<dyn File + Send + Sync>> {
+        Some(&self.entries.get(fd)?.as_ref()?.file)
+    }
+
+    /// Iterate over the descriptors in use and their files.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Arc<dyn File + Send + Sync>)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(fd, entry)| Some((fd, &entry.as_ref()?.file)))
+    }
+
+    /// Put a file at the lowest free descriptor, return None if the ones
+    /// below the limit are all used.
+    pub fn alloc(&mut self, file: Arc<dyn File + Send + Sync>) -> Option<usize> {
+        self.alloc_from(0, file)
+    }
+
+    /// Put a file at the lowest free descriptor not less than `min`.
+    pub fn alloc_from(&mut self, min: usize, file: Arc<dyn File + Send + Sync>) -> Option<usize> {
+        let fd = self.lowest_free(min)?;
+        if fd >= self.limit.cur {
+            return None;
+        }
+        self.set(fd, file);
+        Some(fd)
+    }
+
+    /// Put a file at a given descriptor, which is closed first if used.
+    /// Return false if it is beyond the limit.
+    pub fn insert(&mut self, fd: usize, file: Arc<dyn File + Send + Sync>) -> bool {
+        if fd >= self.limit.cur {
+            return false;
+        }
+        self.set(fd, file);
+        true
+    }
+
+    /// Close a descriptor, return its file if it is used.
+    pub fn remove(&mut self, fd: usize) -> Option<Arc<dyn File + Send + Sync>> {
+        let entry = self.entries.get_mut(fd)?.take()?;
+        self.used[fd / 64] &= !(1 << (fd % 64));
+        self.full &= !(1 << (fd / 64));
+        Some(entry.file)
+    }
+
+    /// Close all descriptors.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.used = [0; NOFILE_MAX / 64];
+        self.full = 0;
+    }
+
+    pub fn flags(&self, fd: usize) -> Option<FdFlags> {
+        Some(self.entries.get(fd)?.as_ref()?.flags)
+    }
+
+    /// Return false if the descriptor is not used.
+    pub fn set_flags(&mut self, fd: usize, flags: FdFlags) -> bool {
+        match self.entries.get_mut(fd).and_then(|entry| entry.as_mut()) {
+            Some(entry) => {
+                entry.flags = flags;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Close the descriptors with `FdFlags::CLOEXEC`, for exec, return the
+    /// files of them.
+    pub fn close_on_exec(&mut self) -> Vec<Arc<dyn File + Send + Sync>> {
+        let fds: Vec<usize> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| {
+                entry
+                    .as_ref()
+                    .map_or(false, |entry| entry.flags.contains(FdFlags::CLOEXEC))
+            })
+            .map(|(fd, _)| fd)
+            .collect();
+        fds.into_iter().filter_map(|fd| self.remove(fd)).collect()
+    }
+
+    pub fn limit(&self) -> RLimit {
+        self.limit
+    }
+
+    /// Change the limit, return false if it is beyond `NOFILE_MAX`.
+    /// Descriptors beyond a lowered limit stay open.
+    pub fn set_limit(&mut self, limit: RLimit) -> bool {
+        if limit.cur > limit.max || limit.max > NOFILE_MAX {
+            return false;
+        }
+        self.limit = limit;
+        true
+    }
+
+    fn lowest_free(&self, min: usize) -> Option<usize> {
+        if min >= NOFILE_MAX {
+            return None;
+        }
+        // skip the full words and the ones below `min`
+        let below = (1u64 << (min / 64)) - 1;
+        let first = (self.full | below).trailing_ones() as usize;
+        for word in first..self.used.len() {
+            // the bits below `min` in its own word are skipped too
+            let mut used = self.used[word];
+            if word == min / 64 {
+                used |= (1u64 << (min % 64)) - 1;
+            }
+            if used != u64::MAX {
+                return Some(word * 64 + used.trailing_ones() as usize);
+            }
+        }
+        None
+    }
+
+    fn set(&mut self, fd: usize, file: Arc<dyn File + Send + Sync>) {
+        if fd >= self.entries.len() {
+            // double the table to grow it rarely
+            let len = (fd + 1).max(self.entries.len() * 2).min(NOFILE_MAX);
+            self.entries.resize(len, None);
+        }
+        self.entries[fd] = Some(FdEntry {
+            file,
+            flags: FdFlags::empty(),
+        });
+        self.used[fd / 64] |= 1 << (fd % 64);
+        if self.used[fd / 64] == u64::MAX {
+            self.full |= 1 << (fd / 64);
+        }
+    }
+}
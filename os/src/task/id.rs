@@ -1,11 +1,18 @@
 use super::ProcessControlBlock;
-use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT_BASE, USER_STACK_SIZE};
-use crate::mm::{MapPermission, PhysPageNum, VirtAddr, KERNEL_SPACE};
+use crate::config::{
+    KERNEL_STACK_SIZE, MAIN_STACK_MAX_SIZE, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT_BASE,
+    USER_STACKS_END, USER_STACK_SIZE,
+};
+use crate::mm::{
+    MapArea, MapPermission, MapType, MemorySet, PhysPageNum, VirtAddr, VirtPageNum, KERNEL_SPACE,
+};
 use crate::sync::UPIntrFreeCell;
 use alloc::{
     sync::{Arc, Weak},
     vec::Vec,
 };
+use core::ops::Range;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use lazy_static::*;
 
 pub struct RecycleAllocator {
@@ -44,6 +51,8 @@ lazy_static! {
         unsafe { UPIntrFreeCell::new(RecycleAllocator::new()) };
     static ref KSTACK_ALLOCATOR: UPIntrFreeCell<RecycleAllocator> =
         unsafe { UPIntrFreeCell::new(RecycleAllocator::new()) };
+    /// Kernel stacks freed but still mapped, for the next tasks.
+    static ref KSTACK_POOL: UPIntrFreeCell<Vec<usize>> = unsafe { UPIntrFreeCell::new(Vec::new()) };
 }
 
 pub const IDLE_PID: usize = 0;
@@ -60,7 +69,19 @@ impl Drop for PidHandle {
     }
 }
 
-/// Return (bottom, top) of a kernel stack in kernel space.
+/// Kernel stacks kept mapped in the pool at most, the others are unmapped
+/// once freed.
+const KSTACK_POOL_SIZE: usize = 16;
+/// Written over a kernel stack when it is handed out, so the bytes still
+/// holding it on free were never used.
+const KSTACK_PAINT: u8 = 0xa5;
+
+/// The most bytes a kernel stack freed has used.
+static KSTACK_HIGH_WATERMARK: AtomicUsize = AtomicUsize::new(0);
+static KSTACKS_IN_USE: AtomicUsize = AtomicUsize::new(0);
+
+/// Return (bottom, top) of a kernel stack in kernel space. There is an
+/// unmapped guard page below each one.
 pub fn kernel_stack_position(kstack_id: usize) -> (usize, usize) {
     let top = TRAMPOLINE - kstack_id * (KERNEL_STACK_SIZE + PAGE_SIZE);
     let bottom = top - KERNEL_STACK_SIZE;
@@ -69,19 +90,41 @@ pub fn kernel_stack_position(kstack_id: usize) -> (usize, usize) {
 
 pub struct KernelStack(pub usize);
 
+/// Take a kernel stack from the pool, or map a new one if it is empty.
 pub fn kstack_alloc() -> KernelStack {
-    let kstack_id = KSTACK_ALLOCATOR.exclusive_access().alloc();
-    let (kstack_bottom, kstack_top) = kernel_stack_position(kstack_id);
-    KERNEL_SPACE.exclusive_access().insert_framed_area(
-        kstack_bottom.into(),
-        kstack_top.into(),
-        MapPermission::R | MapPermission::W,
-    );
+    let pooled = KSTACK_POOL.exclusive_access().pop();
+    let kstack_id = match pooled {
+        Some(kstack_id) => kstack_id,
+        None => {
+            let kstack_id = KSTACK_ALLOCATOR.exclusive_access().alloc();
+            let (kstack_bottom, kstack_top) = kernel_stack_position(kstack_id);
+            KERNEL_SPACE.exclusive_access().insert_framed_area(
+                kstack_bottom.into(),
+                kstack_top.into(),
+                MapPermission::R | MapPermission::W,
+            );
+            kstack_id
+        }
+    };
+    let (kstack_bottom, _) = kernel_stack_position(kstack_id);
+    unsafe {
+        core::slice::from_raw_parts_mut(kstack_bottom as *mut u8, KERNEL_STACK_SIZE)
+            .fill(KSTACK_PAINT);
+    }
+    KSTACKS_IN_USE.fetch_add(1, Ordering::Relaxed);
     KernelStack(kstack_id)
 }
 
 impl Drop for KernelStack {
     fn drop(&mut self) {
+        KSTACK_HIGH_WATERMARK.fetch_max(self.used(), Ordering::Relaxed);
+        KSTACKS_IN_USE.fetch_sub(1, Ordering::Relaxed);
+        let mut pool = KSTACK_POOL.exclusive_access();
+        if pool.len() < KSTACK_POOL_SIZE {
+            pool.push(self.0);
+            return;
+        }
+        drop(pool);
         let (kernel_stack_bottom, _) = kernel_stack_position(self.0);
         let kernel_stack_bottom_va: VirtAddr = kernel_stack_bottom.into();
         KERNEL_SPACE
@@ -91,6 +134,16 @@ impl Drop for KernelStack {
     }
 }
 
+/// The kernel stacks in use and in the pool, and the most bytes used by
+/// one of them, for `/proc/kstacks`.
+pub fn kstack_stats() -> (usize, usize, usize) {
+    (
+        KSTACKS_IN_USE.load(Ordering::Relaxed),
+        KSTACK_POOL.exclusive_access().len(),
+        KSTACK_HIGH_WATERMARK.load(Ordering::Relaxed),
+    )
+}
+
 impl KernelStack {
     #[allow(unused)]
     pub fn push_on_top<T>(&self, value: T) -> *mut T
@@ -108,6 +161,18 @@ impl KernelStack {
         let (_, kernel_stack_top) = kernel_stack_position(self.0);
         kernel_stack_top
     }
+    /// The bytes used since it is handed out, as far as the paint is gone.
+    pub fn used(&self) -> usize {
+        let (kernel_stack_bottom, _) = kernel_stack_position(self.0);
+        let stack = unsafe {
+            core::slice::from_raw_parts(kernel_stack_bottom as *const u8, KERNEL_STACK_SIZE)
+        };
+        KERNEL_STACK_SIZE
+            - stack
+                .iter()
+                .take_while(|&&byte| byte == KSTACK_PAINT)
+                .count()
+    }
 }
 
 pub struct TaskUserRes {
@@ -120,8 +185,22 @@ fn trap_cx_bottom_from_tid(tid: usize) -> usize {
     TRAP_CONTEXT_BASE - tid * PAGE_SIZE
 }
 
+/// Room reserved below the main stack so that it can grow downwards.
+const MAIN_STACK_GROWTH: usize = MAIN_STACK_MAX_SIZE - USER_STACK_SIZE;
+
+/// The user stacks from `ustack_base` upwards: the growth room of the main
+/// stack, the main stack, then a guard page and a stack for each thread.
+/// The guard page of the main stack is the one below `ustack_base`.
 fn ustack_bottom_from_tid(ustack_base: usize, tid: usize) -> usize {
-    ustack_base + tid * (PAGE_SIZE + USER_STACK_SIZE)
+    ustack_base + MAIN_STACK_GROWTH + tid * (PAGE_SIZE + USER_STACK_SIZE)
+}
+
+/// What a page fault near the user stack of a thread means.
+pub enum StackFault {
+    /// the main stack has been grown to cover the address
+    Grown,
+    /// the address is in the guard page below the stack
+    Overflow,
 }
 
 impl TaskUserRes {
@@ -153,7 +232,14 @@ impl TaskUserRes {
             ustack_top.into(),
             MapPermission::R | MapPermission::W | MapPermission::U,
         );
-        // alloc trap_cx
+        drop(process_inner);
+        self.alloc_trap_cx();
+    }
+
+    /// Alloc trap_cx only, when the user stack has been mapped.
+    pub fn alloc_trap_cx(&self) {
+        let process = self.process.upgrade().unwrap();
+        let mut process_inner = process.inner_exclusive_access();
         let trap_cx_bottom = trap_cx_bottom_from_tid(self.tid);
         let trap_cx_top = trap_cx_bottom + PAGE_SIZE;
         process_inner.memory_set.insert_framed_area(
@@ -210,9 +296,62 @@ impl TaskUserRes {
             .ppn()
     }
 
+    /// Check a page fault at `addr` against the user stack of this thread,
+    /// growing the main stack in `memory_set` of the process if `addr` is
+    /// within its growth room.
+    pub fn handle_stack_fault(
+        &self,
+        addr: usize,
+        memory_set: &mut MemorySet,
+    ) -> Option<StackFault> {
+        let ustack_bottom = ustack_bottom_from_tid(self.ustack_base, self.tid);
+        let (limit, guard) = if self.tid == 0 {
+            (self.ustack_base, self.ustack_base - PAGE_SIZE)
+        } else {
+            (ustack_bottom, ustack_bottom - PAGE_SIZE)
+        };
+        if (guard..limit).contains(&addr) {
+            return Some(StackFault::Overflow);
+        }
+        if !(limit..ustack_bottom).contains(&addr) {
+            return None;
+        }
+        // grow from the lowest mapped page of the main stack down to addr
+        let fault_vpn = VirtAddr::from(addr).floor();
+        let mut end_vpn = VirtAddr::from(ustack_bottom).floor();
+        while end_vpn.0 > fault_vpn.0
+            && memory_set
+                .translate(VirtPageNum(end_vpn.0 - 1))
+                .map_or(false, |pte| pte.is_valid())
+        {
+            end_vpn = VirtPageNum(end_vpn.0 - 1);
+        }
+        if end_vpn.0 <= fault_vpn.0 {
+            // mapped already, a fault for another reason
+            return None;
+        }
+        // an area restored from a checkpoint may be in the way
+        if !memory_set.insert_area(MapArea::new(
+            fault_vpn.into(),
+            end_vpn.into(),
+            MapType::Framed,
+            MapPermission::R | MapPermission::W | MapPermission::U,
+        )) {
+            return None;
+        }
+        memory_set.count_lazy_fault();
+        Some(StackFault::Grown)
+    }
+
     pub fn ustack_base(&self) -> usize {
         self.ustack_base
     }
+    /// Where the user stacks of all the threads may be, from the guard page
+    /// of the main stack on, its growth room included. Only the kernel maps
+    /// and unmaps pages there.
+    pub fn ustacks_range(&self) -> Range<usize> {
+        self.ustack_base - PAGE_SIZE..USER_STACKS_END
+    }
     pub fn ustack_top(&self) -> usize {
         ustack_bottom_from_tid(self.ustack_base, self.tid) + USER_STACK_SIZE
     }
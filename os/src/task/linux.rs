@@ -0,0 +1,114 @@
+use crate::config::{PAGE_SIZE, USER_STACKS_END};
+use crate::mm::translated_refmut;
+use crate::random::fill_random;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// The heap of brk and the anonymous mappings are placed far above the
+/// program and the user stacks of threads.
+pub const LINUX_BRK_BASE: usize = USER_STACKS_END;
+pub const LINUX_MMAP_BASE: usize = 0x20_0000_0000;
+
+const AT_NULL: usize = 0;
+const AT_PHDR: usize = 3;
+const AT_PHENT: usize = 4;
+const AT_PHNUM: usize = 5;
+const AT_PAGESZ: usize = 6;
+const AT_ENTRY: usize = 9;
+const AT_RANDOM: usize = 25;
+
+/// State of a process running with the Linux syscall ABI.
+#[derive(Clone)]
+pub struct LinuxAbi {
+    /// the current program break
+    pub brk: usize,
+    /// the end of the mapped heap, page aligned
+    pub brk_mapped: usize,
+    /// where the next anonymous mapping starts
+    pub mmap_end: usize,
+}
+
+impl LinuxAbi {
+    pub fn new() -> Self {
+        Self {
+            brk: LINUX_BRK_BASE,
+            brk_mapped: LINUX_BRK_BASE,
+            mmap_end: LINUX_MMAP_BASE,
+        }
+    }
+}
+
+/// The auxiliary vector needed by the startup code of static musl binaries,
+/// `load_bias` added to the addresses of a PIE one.
+fn auxv(elf_data: &[u8], load_bias: usize) -> Vec<(usize, usize)> {
+    let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
+    let ph_offset = elf.header.pt2.ph_offset() as usize;
+    // the program headers are mapped by the load segment containing them
+    let phdr = (0..elf.header.pt2.ph_count())
+        .map(|i| elf.program_header(i).unwrap())
+        .find(|ph| {
+            ph.get_type() == Ok(xmas_elf::program::Type::Load)
+                && ph.offset() as usize <= ph_offset
+                && ph_offset < (ph.offset() + ph.file_size()) as usize
+        })
+        .map_or(0, |ph| {
+            ph.virtual_addr() as usize + ph_offset - ph.offset() as usize + load_bias
+        });
+    vec![
+        (AT_PHDR, phdr),
+        (AT_PHENT, elf.header.pt2.ph_entry_size() as usize),
+        (AT_PHNUM, elf.header.pt2.ph_count() as usize),
+        (AT_PAGESZ, PAGE_SIZE),
+        (AT_ENTRY, elf.header.pt2.entry_point() as usize + load_bias),
+    ]
+}
+
+/// Push arguments on the user stack as Linux does: argc, argv, an empty envp
+/// and auxv from the stack pointer upwards. Return the new stack pointer.
+pub fn push_linux_args(
+    token: usize,
+    mut user_sp: usize,
+    args: &[String],
+    elf_data: &[u8],
+    load_bias: usize,
+) -> usize {
+    let push_bytes = |user_sp: &mut usize, bytes: &[u8]| {
+        *user_sp -= bytes.len();
+        for (i, byte) in bytes.iter().enumerate() {
+            *translated_refmut(token, (*user_sp + i) as *mut u8) = *byte;
+        }
+    };
+    let mut argv = Vec::new();
+    for arg in args {
+        push_bytes(&mut user_sp, &[0]);
+        push_bytes(&mut user_sp, arg.as_bytes());
+        argv.push(user_sp);
+    }
+    // for stack protectors and hashing
+    let mut bytes = [0u8; 16];
+    fill_random(&mut bytes);
+    push_bytes(&mut user_sp, &bytes);
+    let random = user_sp;
+    let mut auxv = auxv(elf_data, load_bias);
+    auxv.push((AT_RANDOM, random));
+    auxv.push((AT_NULL, 0));
+    let mut words = vec![args.len()];
+    words.extend(argv);
+    words.push(0);
+    // empty envp
+    words.push(0);
+    for (key, value) in auxv {
+        words.push(key);
+        words.push(value);
+    }
+    // the stack pointer is aligned to 16B at the entry
+    user_sp = (user_sp - words.len() * core::mem::size_of::<usize>()) & !0xf;
+    for (i, word) in words.iter().enumerate() {
+        *translated_refmut(
+            token,
+            (user_sp + i * core::mem::size_of::<usize>()) as *mut usize,
+        ) = *word;
+    }
+    user_sp
+}
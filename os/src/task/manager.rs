@@ -1,41 +1,86 @@
-use super::{ProcessControlBlock, TaskControlBlock, TaskStatus};
-use crate::sync::UPIntrFreeCell;
-use alloc::collections::{BTreeMap, VecDeque};
+use super::cgroup::CPU_GROUPS;
+use super::sched::{ReadyQueue, ReadyQueueImpl};
+use super::{send_signal, ProcessControlBlock, SignalFlags, TaskControlBlock, TaskStatus};
+use crate::hart::kick_idle_hart;
+use crate::sync::{RwIntrFreeCell, UPIntrFreeCell};
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::*;
 
 pub struct TaskManager {
-    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+    /// ready tasks of each cpu group
+    ready_queues: BTreeMap<usize, ReadyQueueImpl>,
 }
 
-/// A simple FIFO scheduler.
+/// The tasks of each cpu group in the order of the scheduling policy, and the
+/// groups share the cpu by their shares.
 impl TaskManager {
     pub fn new() -> Self {
         Self {
-            ready_queue: VecDeque::new(),
+            ready_queues: BTreeMap::new(),
         }
     }
     pub fn add(&mut self, task: Arc<TaskControlBlock>) {
-        self.ready_queue.push_back(task);
+        let group_id = task.cpu_group();
+        if self
+            .ready_queues
+            .get(&group_id)
+            .map_or(true, |queue| queue.is_empty())
+        {
+            // a group waking up should not take the cpu for the time it slept
+            let mut groups = CPU_GROUPS.exclusive_access();
+            let min_vruntime = self
+                .ready_queues
+                .iter()
+                .filter(|(_, queue)| !queue.is_empty())
+                .filter_map(|(id, _)| groups.get(id).map(|group| group.vruntime))
+                .min();
+            if let (Some(min_vruntime), Some(group)) = (min_vruntime, groups.get_mut(&group_id)) {
+                group.vruntime = group.vruntime.max(min_vruntime);
+            }
+        }
+        self.ready_queues.entry(group_id).or_default().push(task);
     }
     pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
-        self.ready_queue.pop_front()
+        let mut groups = CPU_GROUPS.exclusive_access();
+        let group_id = self
+            .ready_queues
+            .iter()
+            .filter(|(_, queue)| !queue.is_empty())
+            .filter_map(|(&id, _)| match groups.get_mut(&id) {
+                Some(group) => (!group.throttled()).then_some((group.vruntime, id)),
+                None => Some((0, id)),
+            })
+            .min()?
+            .1;
+        self.ready_queues.get_mut(&group_id).unwrap().pop()
+    }
+    pub fn has_ready(&self) -> bool {
+        self.ready_queues.values().any(|queue| !queue.is_empty())
     }
 }
 
 lazy_static! {
     pub static ref TASK_MANAGER: UPIntrFreeCell<TaskManager> =
         unsafe { UPIntrFreeCell::new(TaskManager::new()) };
-    pub static ref PID2PCB: UPIntrFreeCell<BTreeMap<usize, Arc<ProcessControlBlock>>> =
-        unsafe { UPIntrFreeCell::new(BTreeMap::new()) };
+    /// Looked up far more often than a process comes or goes.
+    pub static ref PID2PCB: RwIntrFreeCell<BTreeMap<usize, Arc<ProcessControlBlock>>> =
+        RwIntrFreeCell::new(BTreeMap::new());
 }
 
 pub fn add_task(task: Arc<TaskControlBlock>) {
     TASK_MANAGER.exclusive_access().add(task);
+    kick_idle_hart();
 }
 
 pub fn wakeup_task(task: Arc<TaskControlBlock>) {
     let mut task_inner = task.inner_exclusive_access();
+    // a task waiting with a timeout may be woken up by both the timer and the
+    // waker, only the first one takes effect
+    if task_inner.task_status != TaskStatus::Blocked {
+        return;
+    }
     task_inner.task_status = TaskStatus::Ready;
     drop(task_inner);
     add_task(task);
@@ -45,18 +90,92 @@ pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
     TASK_MANAGER.exclusive_access().fetch()
 }
 
+pub fn has_ready_task() -> bool {
+    TASK_MANAGER.exclusive_access().has_ready()
+}
+
 pub fn pid2process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
-    let map = PID2PCB.exclusive_access();
+    let map = PID2PCB.read();
     map.get(&pid).map(Arc::clone)
 }
 
 pub fn insert_into_pid2process(pid: usize, process: Arc<ProcessControlBlock>) {
-    PID2PCB.exclusive_access().insert(pid, process);
+    PID2PCB.write().insert(pid, process);
 }
 
 pub fn remove_from_pid2process(pid: usize) {
-    let mut map = PID2PCB.exclusive_access();
+    let mut map = PID2PCB.write();
     if map.remove(&pid).is_none() {
         panic!("cannot find pid {} in pid2task!", pid);
     }
 }
+
+/// The pids of all the processes, in order.
+pub fn pids() -> Vec<usize> {
+    PID2PCB.read().keys().copied().collect()
+}
+
+/// The processes of the group `pgid`.
+pub fn processes_of_group(pgid: usize) -> Vec<Arc<ProcessControlBlock>> {
+    PID2PCB
+        .read()
+        .values()
+        .filter(|process| process.inner_exclusive_access().pgid == pgid)
+        .cloned()
+        .collect()
+}
+
+/// Send the signals in `flag` to every process of the group `pgid`, return
+/// whether there is one.
+pub fn signal_group(pgid: usize, flag: SignalFlags) -> bool {
+    let processes = processes_of_group(pgid);
+    for process in processes.iter() {
+        send_signal(process, flag);
+    }
+    !processes.is_empty()
+}
+
+/// Whether there is a process of the group `pgid` in the session `sid`.
+pub fn group_in_session(pgid: usize, sid: usize) -> bool {
+    PID2PCB.read().values().any(|process| {
+        let inner = process.inner_exclusive_access();
+        inner.pgid == pgid && inner.sid == sid
+    })
+}
+
+/// Print every process with its threads, for the magic SysRq.
+pub fn dump_tasks() {
+    let map = PID2PCB.read();
+    println!(
+        "  PID  PPID  PGID   SID   NI  THREADS (TID:STATUS:{})",
+        ReadyQueueImpl::KEY_NAME
+    );
+    for (pid, process) in map.iter() {
+        let inner = process.inner_exclusive_access();
+        let ppid = inner
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade())
+            .map_or(-1, |parent| parent.getpid() as isize);
+        print!(
+            "{:>5} {:>5} {:>5} {:>5} {:>4} ",
+            pid, ppid, inner.pgid, inner.sid, inner.nice
+        );
+        if inner.is_zombie {
+            print!(" zombie");
+        }
+        for task in inner.tasks.iter().flatten() {
+            let task_inner = task.inner_exclusive_access();
+            let status = match task_inner.task_status {
+                TaskStatus::Ready => "ready",
+                TaskStatus::Running => "running",
+                TaskStatus::Blocked => "blocked",
+            };
+            match task_inner.res.as_ref() {
+                Some(res) => print!(" {}:{}:{}", res.tid, status, ReadyQueueImpl::key(task)),
+                None => print!(" -:{}:{}", status, ReadyQueueImpl::key(task)),
+            }
+        }
+        println!("");
+    }
+}
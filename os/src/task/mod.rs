@@ -1,31 +1,54 @@
+mod binfmt;
+mod cgroup;
+mod checkpoint;
 mod context;
+mod fd_table;
 mod id;
+mod linux;
 mod manager;
 mod process;
 mod processor;
+mod sched;
 mod signal;
 mod switch;
 #[allow(clippy::module_inception)]
 mod task;
 
 use self::id::TaskUserRes;
-use crate::fs::{open_file, OpenFlags};
+use crate::fs::{open_file, release_process_locks, OpenFlags, CONSOLE_TTY};
+use crate::mm::VirtAddr;
 use crate::sbi::shutdown;
-use alloc::{sync::Arc, vec::Vec};
+use crate::sync::UPIntrRefMut;
+use crate::timer::{add_timer, cancel_timer};
+use crate::trap::TrapContext;
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::ops::Range;
 use lazy_static::*;
-use manager::fetch_task;
-use process::ProcessControlBlock;
+use manager::{fetch_task, has_ready_task};
+use processor::{charge_task, current_ran_us};
+use sched::{ReadyQueue, ReadyQueueImpl};
 use switch::__switch;
 
+pub use binfmt::{load_program, Program};
+pub use cgroup::{cpu_group_exists, cpu_group_usage, create_cpu_group};
 pub use context::TaskContext;
-pub use id::{kstack_alloc, pid_alloc, KernelStack, PidHandle, IDLE_PID};
-pub use manager::{add_task, pid2process, remove_from_pid2process, wakeup_task};
+pub use fd_table::{FdFlags, FdTable, RLimit};
+pub use id::{kstack_alloc, kstack_stats, pid_alloc, KernelStack, PidHandle, StackFault, IDLE_PID};
+pub use linux::{LinuxAbi, LINUX_MMAP_BASE};
+pub use manager::{
+    add_task, dump_tasks, group_in_session, pid2process, pids, processes_of_group,
+    remove_from_pid2process, signal_group, wakeup_task,
+};
+pub use process::ProcessControlBlock;
 pub use processor::{
     current_kstack_top, current_process, current_task, current_trap_cx, current_trap_cx_user_va,
-    current_user_token, run_tasks, schedule, take_current_task,
+    current_user_token, idle_us, run_tasks, schedule, take_current_task, toggle_sched_trace,
+};
+pub use signal::{
+    SignalAction, SignalActionFlags, SignalActions, SignalFlags, EINTR, ERESTARTSYS, MAX_SIG,
+    SIG_DFL, SIG_IGN,
 };
-pub use signal::SignalFlags;
-pub use task::{TaskControlBlock, TaskStatus};
+pub use task::{TaskControlBlock, TaskStatus, NICE_MAX, NICE_MIN};
 
 pub fn suspend_current_and_run_next() {
     // There must be an application running.
@@ -39,12 +62,19 @@ pub fn suspend_current_and_run_next() {
     drop(task_inner);
     // ---- release current TCB
 
-    // push back to ready queue.
+    // push back to ready queue, by the time it has run
+    charge_task(&task);
     add_task(task);
     // jump to scheduling cycle
     schedule(task_cx_ptr);
 }
 
+/// Preempt the current task on a timer tick.
+pub fn preempt_current_and_run_next() {
+    ReadyQueueImpl::preempted(&current_task().unwrap(), current_ran_us());
+    suspend_current_and_run_next();
+}
+
 /// This function must be followed by a schedule
 pub fn block_current_task() -> *mut TaskContext {
     let task = take_current_task().unwrap();
@@ -53,23 +83,40 @@ pub fn block_current_task() -> *mut TaskContext {
     &mut task_inner.task_cx as *mut TaskContext
 }
 
-pub fn block_current_and_run_next() {
-    let task_cx_ptr = block_current_task();
-    schedule(task_cx_ptr);
-}
-
 /// Exit the current 'Running' task and run the next task in task list.
 pub fn exit_current_and_run_next(exit_code: i32) {
     let task = take_current_task().unwrap();
     let mut task_inner = task.inner_exclusive_access();
+    let tid = match task_inner.res.as_ref() {
+        Some(res) => res.tid,
+        None => {
+            // left behind by its process, which has recycled everything
+            drop(task_inner);
+            drop(task);
+            let mut _unused = TaskContext::zero_init();
+            schedule(&mut _unused as *mut _);
+            return;
+        }
+    };
     let process = task.process.upgrade().unwrap();
-    let tid = task_inner.res.as_ref().unwrap().tid;
     // record exit code
     task_inner.exit_code = Some(exit_code);
     task_inner.res = None;
     // here we do not remove the thread since we are still using the kstack
     // it will be deallocated when sys_waittid is called
     drop(task_inner);
+    // release the mutexes still held, the next owners will be told
+    let mutex_list = process.inner_exclusive_access().mutex_list.clone();
+    for (mutex_id, mutex) in mutex_list.iter().enumerate() {
+        if let Some(mutex) = mutex {
+            if mutex.owner_exited(&task) {
+                process
+                    .inner_exclusive_access()
+                    .mutex_detector
+                    .release(tid, mutex_id);
+            }
+        }
+    }
     drop(task);
     // however, if this is the main thread of current process
     // the process should terminate at once
@@ -94,6 +141,8 @@ pub fn exit_current_and_run_next(exit_code: i32) {
         process_inner.is_zombie = true;
         // record exit code of main process
         process_inner.exit_code = exit_code;
+        process.exit_status.set(exit_code);
+        let sid = process_inner.sid;
 
         {
             // move all child processes under init process
@@ -108,11 +157,13 @@ pub fn exit_current_and_run_next(exit_code: i32) {
         // it has to be done before we dealloc the whole memory_set
         // otherwise they will be deallocated twice
         let mut recycle_res = Vec::<TaskUserRes>::new();
+        let mut left_behind = Vec::new();
         for task in process_inner.tasks.iter().filter(|t| t.is_some()) {
             let task = task.as_ref().unwrap();
             let mut task_inner = task.inner_exclusive_access();
             if let Some(res) = task_inner.res.take() {
                 recycle_res.push(res);
+                left_behind.push(Arc::clone(task));
             }
         }
         // dealloc_tid and dealloc_user_res require access to PCB inner, so we
@@ -120,13 +171,24 @@ pub fn exit_current_and_run_next(exit_code: i32) {
         // for now to avoid deadlock/double borrow problem.
         drop(process_inner);
         recycle_res.clear();
+        if sid == pid {
+            CONSOLE_TTY.release(sid);
+        }
+        // the other threads waiting in drivers leave them, then exit once
+        // they are back in the trap handler
+        for task in left_behind.iter() {
+            cancel_task(task);
+        }
+        drop(left_behind);
 
         let mut process_inner = process.inner_exclusive_access();
         process_inner.children.clear();
         // deallocate other data in user space i.e. program code/data section
         process_inner.memory_set.recycle_data_pages();
-        // drop file descriptors
-        process_inner.fd_table.clear();
+        drop(process_inner);
+        // drop file descriptors, and the ranges of files locked
+        process.fd_table.write().clear();
+        release_process_locks(pid);
     }
     drop(process);
     // we do not have to save task context
@@ -138,7 +200,7 @@ lazy_static! {
     pub static ref INITPROC: Arc<ProcessControlBlock> = {
         let inode = open_file("initproc", OpenFlags::RDONLY).unwrap();
         let v = inode.read_all();
-        ProcessControlBlock::new(v.as_slice())
+        ProcessControlBlock::new("initproc", v.as_slice())
     };
 }
 
@@ -146,14 +208,335 @@ pub fn add_initproc() {
     let _initproc = INITPROC.clone();
 }
 
-pub fn check_signals_of_current() -> Option<(i32, &'static str)> {
-    let process = current_process();
+/// Run `entry` in a thread of the kernel, scheduled like the others.
+pub fn spawn_kernel_thread(entry: fn() -> !) {
+    add_task(Arc::new(TaskControlBlock::new_kernel(entry)));
+}
+
+/// Why a task waiting in the kernel goes on.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WakeReason {
+    /// what it waits for may have happened, it checks again
+    Woken,
+    /// a signal it acts on is pending, the syscall gives up with EINTR or is
+    /// restarted after the handler
+    Signalled,
+    /// killed or left behind by its process
+    Exiting,
+}
+
+/// Whether `task` is to stop waiting. The caught signals do not count while
+/// a handler runs, like in `handle_signals_of_current`.
+fn wake_reason(task: &Arc<TaskControlBlock>) -> WakeReason {
+    let process = match task.process.upgrade() {
+        Some(process) => process,
+        None => return WakeReason::Exiting,
+    };
+    // the process before the task, like everywhere else
     let process_inner = process.inner_exclusive_access();
-    process_inner.signals.check_error()
+    let task_inner = task.inner_exclusive_access();
+    if task_inner.exiting {
+        return WakeReason::Exiting;
+    }
+    let pending = (task_inner.signals | process_inner.signals) - task_inner.signal_mask;
+    let in_handler = task_inner.signal_backup.is_some();
+    let acted_on = (1..=MAX_SIG).any(|signum| {
+        let signal = SignalFlags::from_signum(signum).unwrap();
+        match process_inner.signal_actions.table[signum].handler {
+            _ if !pending.contains(signal) => false,
+            SIG_DFL => signal.terminates(),
+            SIG_IGN => false,
+            _ => !in_handler,
+        }
+    });
+    if acted_on {
+        WakeReason::Signalled
+    } else {
+        WakeReason::Woken
+    }
 }
 
+/// Whether the current task is to stop waiting, see `wake_reason`. A wait
+/// which gives up for a signal is recorded for `current_interrupted`.
+pub fn current_wake_reason() -> WakeReason {
+    let reason = wake_reason(&current_task().unwrap());
+    if reason == WakeReason::Signalled {
+        current_task().unwrap().inner_exclusive_access().interrupted = true;
+    }
+    reason
+}
+
+/// Run the wake hook of `task` if it waits, so that it finds out it is to
+/// stop waiting.
+fn interrupt_task(task: &Arc<TaskControlBlock>) {
+    let wake_hook = task.inner_exclusive_access().wake_hook.take();
+    if let Some(wake_hook) = wake_hook {
+        wake_hook();
+    }
+}
+
+/// The task is to exit, killed or left behind by its process. If it waits
+/// in a driver future, the wake hook wakes it up to drop the future, so the
+/// driver forgets its wakers instead of keeping them and the next input for
+/// a task that never reads it.
+pub fn cancel_task(task: &Arc<TaskControlBlock>) {
+    task.inner_exclusive_access().exiting = true;
+    interrupt_task(task);
+}
+
+/// Set the hook to run if the current task is to exit or is signalled while
+/// it waits, return false if it is already, see `current_wake_reason`.
+pub fn set_wake_hook(wake_hook: Box<dyn FnOnce()>) -> bool {
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().wake_hook = Some(wake_hook);
+    // checked once the hook is set, so it runs for a signal sent meanwhile
+    if current_wake_reason() != WakeReason::Woken {
+        task.inner_exclusive_access().wake_hook = None;
+        return false;
+    }
+    true
+}
+
+/// The current task stops waiting, remove its wake hook, return why.
+pub fn clear_wake_hook() -> WakeReason {
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().wake_hook = None;
+    current_wake_reason()
+}
+
+/// Whether a wait of the current syscall gave up for a signal, cleared when
+/// the next one starts. A syscall which has done nothing then returns
+/// `ERESTARTSYS`.
+pub fn current_interrupted() -> bool {
+    current_task().unwrap().inner_exclusive_access().interrupted
+}
+
+pub fn clear_current_interrupted() {
+    current_task().unwrap().inner_exclusive_access().interrupted = false;
+}
+
+/// Like `block_current_task`, for the current task with a wake hook set. The
+/// hook may have run before the task is blocked, when it woke nothing up,
+/// so the task is woken up at once if it is to stop waiting already.
+pub fn block_current_task_interruptible() -> *mut TaskContext {
+    let task = current_task().unwrap();
+    let task_cx_ptr = block_current_task();
+    if wake_reason(&task) != WakeReason::Woken {
+        wakeup_task(task);
+    }
+    task_cx_ptr
+}
+
+/// Block the current task until it is woken up, the time reaches
+/// `expire_ns` if any, or it is to stop waiting. The caller should find out
+/// if the time is up by itself.
+pub fn block_current_and_run_next_interruptible(expire_ns: Option<usize>) -> WakeReason {
+    let task = current_task().unwrap();
+    let waiter = Arc::clone(&task);
+    if !set_wake_hook(Box::new(move || wakeup_task(waiter))) {
+        return current_wake_reason();
+    }
+    let timer = expire_ns.map(|expire_ns| add_timer(expire_ns, Arc::clone(&task)));
+    let task_cx_ptr = block_current_task_interruptible();
+    schedule(task_cx_ptr);
+    if let Some(timer) = timer {
+        cancel_timer(timer);
+    }
+    clear_wake_hook()
+}
+
+/// Like `block_current_and_run_next_interruptible`, but `guard` is released
+/// only once the task is blocked, so a wake up by the next one borrowing it
+/// is never lost. The caller queues the task under `guard` and finds out
+/// whether it is still queued afterwards.
+pub fn block_current_and_release_interruptible<T>(
+    guard: UPIntrRefMut<'_, T>,
+    expire_ns: Option<usize>,
+) -> WakeReason {
+    let task = current_task().unwrap();
+    let waiter = Arc::clone(&task);
+    if !set_wake_hook(Box::new(move || wakeup_task(waiter))) {
+        drop(guard);
+        return current_wake_reason();
+    }
+    let timer = expire_ns.map(|expire_ns| add_timer(expire_ns, Arc::clone(&task)));
+    let task_cx_ptr = block_current_task_interruptible();
+    drop(guard);
+    schedule(task_cx_ptr);
+    if let Some(timer) = timer {
+        cancel_timer(timer);
+    }
+    clear_wake_hook()
+}
+
+/// The user of the current process, root before there is any.
+pub fn current_uid() -> u32 {
+    current_task()
+        .and_then(|task| task.process.upgrade())
+        .map_or(0, |process| process.inner_exclusive_access().uid)
+}
+
+/// Whether the current process may signal or change `process`: root may do
+/// so to any, the others only to those of their own user.
+pub fn may_control(process: &ProcessControlBlock) -> bool {
+    let uid = current_uid();
+    uid == 0 || process.inner_exclusive_access().uid == uid
+}
+
+/// Whether the process of the current thread has exited, while it waited or
+/// was ready to run.
+pub fn current_task_left_behind() -> bool {
+    let task = current_task().unwrap();
+    let task_inner = task.inner_exclusive_access();
+    task_inner.res.is_none()
+}
+
+/// Act on the signals pending for the current thread before it returns to
+/// the user mode, the lowest numbered ones not blocked first. A handler is
+/// called by switching the trap context to it, the one before is restored
+/// by sigreturn, and the others wait until it returns. Return the exit code
+/// and the description if the process is to be terminated.
+///
+/// `restart` is the first argument of a syscall which returned
+/// `ERESTARTSYS`, the syscall is restarted unless a handler without
+/// `SignalActionFlags::SA_RESTART` is called, which sees `EINTR` instead.
+pub fn handle_signals_of_current(restart: Option<usize>) -> Option<(i32, &'static str)> {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    // the process before the task, like everywhere else
+    let mut process_inner = process.inner_exclusive_access();
+    let mut task_inner = task.inner_exclusive_access();
+    let mut pending = (task_inner.signals | process_inner.signals) - task_inner.signal_mask;
+    while let Some(signum) = pending.first() {
+        let signal = SignalFlags::from_signum(signum).unwrap();
+        pending.remove(signal);
+        let action = process_inner.signal_actions.table[signum];
+        let caught = action.handler != SIG_DFL && action.handler != SIG_IGN;
+        if caught && task_inner.signal_backup.is_some() {
+            continue;
+        }
+        // taken by this thread
+        if task_inner.signals.contains(signal) {
+            task_inner.signals.remove(signal);
+        } else {
+            process_inner.signals.remove(signal);
+        }
+        if action.handler == SIG_DFL && signal.terminates() {
+            return signal.check_error();
+        }
+        if !caught {
+            continue;
+        }
+        let trap_cx = task_inner.get_trap_cx();
+        if let Some(a0) = restart {
+            if action.flags.contains(SignalActionFlags::SA_RESTART) {
+                restart_syscall(trap_cx, a0);
+            } else {
+                trap_cx.x[10] = EINTR as usize;
+            }
+        }
+        task_inner.signal_backup = Some((trap_cx.clone(), task_inner.signal_mask));
+        task_inner.signal_mask |= (action.mask | signal) - SignalFlags::uncatchable();
+        trap_cx.sepc = action.handler;
+        trap_cx.x[10] = signum;
+        // ra
+        trap_cx.x[1] = action.restorer;
+        // the handler runs on the stack of the thread, aligned to 16B
+        trap_cx.x[2] &= !0xf;
+        return None;
+    }
+    if let Some(a0) = restart {
+        restart_syscall(task_inner.get_trap_cx(), a0);
+    }
+    None
+}
+
+/// Go back to the ecall with its first argument, which the result took.
+fn restart_syscall(trap_cx: &mut TrapContext, a0: usize) {
+    trap_cx.sepc -= 4;
+    trap_cx.x[10] = a0;
+}
+
+/// Raise a signal by a fault of the current thread. If it is blocked or
+/// ignored, or the thread runs a handler already, the default action is
+/// taken instead, since the thread cannot go on.
 pub fn current_add_signal(signal: SignalFlags) {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    // the process before the task, like everywhere else
+    let mut process_inner = process.inner_exclusive_access();
+    let mut task_inner = task.inner_exclusive_access();
+    let action = &mut process_inner.signal_actions.table[signal.first().unwrap()];
+    if task_inner.signal_mask.contains(signal)
+        || action.handler == SIG_IGN
+        || task_inner.signal_backup.is_some()
+    {
+        *action = SignalAction::default();
+        task_inner.signal_mask.remove(signal);
+    }
+    task_inner.signals |= signal;
+}
+
+/// Send the signals in `flag` to `process`, taken by one of its threads not
+/// blocking them. The threads waiting are woken up if they are to be
+/// terminated, or interrupted by a handler otherwise.
+pub fn send_signal(process: &Arc<ProcessControlBlock>, flag: SignalFlags) {
+    let mut inner = process.inner_exclusive_access();
+    inner.signals |= flag;
+    // terminating the process at once, unless they are caught
+    let mut fatal = flag & SignalFlags::SIGKILL;
+    for signum in 1..=MAX_SIG {
+        let signal = SignalFlags::from_signum(signum).unwrap();
+        if flag.contains(signal)
+            && inner.signal_actions.table[signum].handler == SIG_DFL
+            && signal.terminates()
+        {
+            fatal |= signal;
+        }
+    }
+    let tasks: Vec<_> = inner.tasks.iter().flatten().cloned().collect();
+    drop(inner);
+    for task in tasks.iter() {
+        let blocked = task.inner_exclusive_access().signal_mask;
+        if !(fatal - blocked).is_empty() {
+            cancel_task(task);
+        } else if !(flag - blocked).is_empty() {
+            // the ones not taking it check the signals and wait again
+            interrupt_task(task);
+        }
+    }
+}
+
+/// Resolve a page fault at `addr` of the current process, in the space of
+/// `token`, raised by the user or by the kernel touching the user space.
+/// Return false if it is a real fault, or not of the current process.
+pub fn handle_user_page_fault(token: usize, addr: usize, write: bool) -> bool {
     let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner.memory_set.token() != token {
+        return false;
+    }
+    inner
+        .memory_set
+        .handle_page_fault(VirtAddr::from(addr).floor(), write)
+}
+
+/// Check a page fault at `addr` against the user stack of the current thread.
+/// See `TaskUserRes::ustacks_range`.
+pub fn current_ustacks_range() -> Range<usize> {
+    let task = current_task().unwrap();
+    let task_inner = task.inner_exclusive_access();
+    task_inner.res.as_ref().unwrap().ustacks_range()
+}
+
+pub fn current_stack_fault(addr: usize) -> Option<StackFault> {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    // the process before the task, like everywhere else
     let mut process_inner = process.inner_exclusive_access();
-    process_inner.signals |= signal;
+    let task_inner = task.inner_exclusive_access();
+    task_inner
+        .res
+        .as_ref()?
+        .handle_stack_fault(addr, &mut process_inner.memory_set)
 }
@@ -1,21 +1,31 @@
+use super::binfmt::Program;
+use super::cgroup::ROOT_CPU_GROUP;
+use super::fd_table::FdTable;
 use super::id::RecycleAllocator;
+use super::linux::{push_linux_args, LinuxAbi};
 use super::manager::insert_into_pid2process;
 use super::TaskControlBlock;
-use super::{add_task, SignalFlags};
+use super::{add_task, SignalActions, SignalFlags};
 use super::{pid_alloc, PidHandle};
-use crate::fs::{File, Stdin, Stdout};
+use crate::fs::{join_path, release_range_locks, ExitStatus, Stdin, Stdout};
 use crate::mm::{translated_refmut, MemorySet, KERNEL_SPACE};
-use crate::sync::{Condvar, Mutex, Semaphore, UPIntrFreeCell, UPIntrRefMut};
+use crate::sync::{
+    Condvar, DeadlockDetector, Mutex, RwIntrFreeCell, Semaphore, UPIntrFreeCell, UPIntrRefMut,
+};
 use crate::trap::{trap_handler, TrapContext};
+use crate::trigger::{reload_watchpoints, Watchpoint, MAX_WATCHPOINTS};
 use alloc::string::String;
 use alloc::sync::{Arc, Weak};
-use alloc::vec;
 use alloc::vec::Vec;
 
 pub struct ProcessControlBlock {
     // immutable
     pub pid: PidHandle,
     // mutable
+    /// looked up by almost every syscall on files, without the inner locked
+    pub fd_table: RwIntrFreeCell<FdTable>,
+    /// set once the process exits, for the pidfds of it
+    pub exit_status: Arc<ExitStatus>,
     inner: UPIntrFreeCell<ProcessControlBlockInner>,
 }
 
@@ -25,13 +35,37 @@ pub struct ProcessControlBlockInner {
     pub parent: Option<Weak<ProcessControlBlock>>,
     pub children: Vec<Arc<ProcessControlBlock>>,
     pub exit_code: i32,
-    pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+    /// the root directory of this process, as a path from the real root
+    pub root: String,
+    /// the working directory, as a path from `root`
+    pub cwd: String,
+    pub cpu_group: usize,
+    /// the nice value of its threads, from `NICE_MIN` to `NICE_MAX`
+    pub nice: isize,
+    /// the process group, the console only hands input to its foreground one
+    pub pgid: usize,
+    /// the session, of the groups of a shell and the terminal it controls
+    pub sid: usize,
+    /// the user running it, who owns the files it creates, 0 for root
+    pub uid: u32,
+    /// the name of the program, shown by /proc
+    pub comm: String,
+    /// Some if the process runs with the Linux syscall ABI, kept across exec
+    pub linux: Option<LinuxAbi>,
+    /// pending for the process, taken by a thread not blocking them
     pub signals: SignalFlags,
+    pub signal_actions: SignalActions,
     pub tasks: Vec<Option<Arc<TaskControlBlock>>>,
     pub task_res_allocator: RecycleAllocator,
     pub mutex_list: Vec<Option<Arc<dyn Mutex>>>,
     pub semaphore_list: Vec<Option<Arc<Semaphore>>>,
     pub condvar_list: Vec<Option<Arc<Condvar>>>,
+    /// whether locking a mutex or downing a semaphore fails on deadlock
+    pub deadlock_detect: bool,
+    pub mutex_detector: DeadlockDetector,
+    pub semaphore_detector: DeadlockDetector,
+    /// hardware watchpoints set by `sys_ptrace`, not inherited by children
+    pub watchpoints: [Watchpoint; MAX_WATCHPOINTS],
 }
 
 impl ProcessControlBlockInner {
@@ -40,12 +74,42 @@ impl ProcessControlBlockInner {
         self.memory_set.token()
     }
 
-    pub fn alloc_fd(&mut self) -> usize {
-        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
-            fd
-        } else {
-            self.fd_table.push(None);
-            self.fd_table.len() - 1
+    /// Resolve a path of this process to the path from the real root.
+    pub fn resolve_path(&self, path: &str) -> String {
+        let path = join_path(self.cwd.as_str(), path);
+        join_path(self.root.as_str(), &path[1..])
+    }
+
+    /// The state of a new child, which inherits the directories, the cgroup,
+    /// the nice value, the process group, the session, the user and the
+    /// ABI.
+    fn new_child(&self, parent: Weak<ProcessControlBlock>, memory_set: MemorySet) -> Self {
+        Self {
+            is_zombie: false,
+            memory_set,
+            parent: Some(parent),
+            children: Vec::new(),
+            exit_code: 0,
+            root: self.root.clone(),
+            cwd: self.cwd.clone(),
+            cpu_group: self.cpu_group,
+            nice: self.nice,
+            pgid: self.pgid,
+            sid: self.sid,
+            uid: self.uid,
+            comm: self.comm.clone(),
+            linux: self.linux.clone(),
+            signals: SignalFlags::empty(),
+            signal_actions: self.signal_actions,
+            tasks: Vec::new(),
+            task_res_allocator: RecycleAllocator::new(),
+            mutex_list: Vec::new(),
+            semaphore_list: Vec::new(),
+            condvar_list: Vec::new(),
+            deadlock_detect: false,
+            mutex_detector: DeadlockDetector::new(),
+            semaphore_detector: DeadlockDetector::new(),
+            watchpoints: Default::default(),
         }
     }
 
@@ -64,6 +128,15 @@ impl ProcessControlBlockInner {
     pub fn get_task(&self, tid: usize) -> Arc<TaskControlBlock> {
         self.tasks[tid].as_ref().unwrap().clone()
     }
+
+    /// The thread of a process with a single one, None if it has others.
+    pub fn only_task(&self) -> Option<Arc<TaskControlBlock>> {
+        let mut tasks = self.tasks.iter().flatten();
+        match (tasks.next(), tasks.next()) {
+            (Some(task), None) => Some(Arc::clone(task)),
+            _ => None,
+        }
+    }
 }
 
 impl ProcessControlBlock {
@@ -71,13 +144,23 @@ impl ProcessControlBlock {
         self.inner.exclusive_access()
     }
 
-    pub fn new(elf_data: &[u8]) -> Arc<Self> {
+    pub fn new(comm: &str, elf_data: &[u8]) -> Arc<Self> {
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data, 0);
         // allocate a pid
         let pid_handle = pid_alloc();
+        let pgid = pid_handle.0;
+        let mut fd_table = FdTable::new();
+        // 0 -> stdin
+        fd_table.alloc(Arc::new(Stdin));
+        // 1 -> stdout
+        fd_table.alloc(Arc::new(Stdout));
+        // 2 -> stderr
+        fd_table.alloc(Arc::new(Stdout));
         let process = Arc::new(Self {
             pid: pid_handle,
+            fd_table: RwIntrFreeCell::new(fd_table),
+            exit_status: Arc::new(ExitStatus::new()),
             inner: unsafe {
                 UPIntrFreeCell::new(ProcessControlBlockInner {
                     is_zombie: false,
@@ -85,20 +168,26 @@ impl ProcessControlBlock {
                     parent: None,
                     children: Vec::new(),
                     exit_code: 0,
-                    fd_table: vec![
-                        // 0 -> stdin
-                        Some(Arc::new(Stdin)),
-                        // 1 -> stdout
-                        Some(Arc::new(Stdout)),
-                        // 2 -> stderr
-                        Some(Arc::new(Stdout)),
-                    ],
+                    root: String::from("/"),
+                    cwd: String::from("/"),
+                    cpu_group: ROOT_CPU_GROUP,
+                    nice: 0,
+                    pgid,
+                    sid: pgid,
+                    uid: 0,
+                    comm: String::from(comm),
+                    linux: None,
                     signals: SignalFlags::empty(),
+                    signal_actions: SignalActions::default(),
                     tasks: Vec::new(),
                     task_res_allocator: RecycleAllocator::new(),
                     mutex_list: Vec::new(),
                     semaphore_list: Vec::new(),
                     condvar_list: Vec::new(),
+                    deadlock_detect: false,
+                    mutex_detector: DeadlockDetector::new(),
+                    semaphore_detector: DeadlockDetector::new(),
+                    watchpoints: Default::default(),
                 })
             },
         });
@@ -132,22 +221,73 @@ impl ProcessControlBlock {
     }
 
     /// Only support processes with a single thread.
-    pub fn exec(self: &Arc<Self>, elf_data: &[u8], args: Vec<String>) {
+    pub fn exec(self: &Arc<Self>, program: Program) {
         assert_eq!(self.inner_exclusive_access().thread_count(), 1);
-        // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
-        let new_token = memory_set.token();
+        let Program {
+            memory_set,
+            ustack_base,
+            entry_point,
+            load_bias,
+            elf_data,
+            args,
+        } = program;
         // substitute memory_set
-        self.inner_exclusive_access().memory_set = memory_set;
+        let mut inner = self.inner_exclusive_access();
+        inner.memory_set = memory_set;
+        inner.comm = program_name(&args);
+        drop(inner);
+        let closed = self.fd_table.write().close_on_exec();
+        for file in closed {
+            release_range_locks(file.as_ref(), self.getpid());
+        }
+        self.inner_exclusive_access().watchpoints = Default::default();
+        reload_watchpoints(self.getpid(), &[]);
+        self.inner_exclusive_access()
+            .signal_actions
+            .reset_handlers();
         // then we alloc user resource for main thread again
         // since memory_set has been changed
         let task = self.inner_exclusive_access().get_task(0);
         let mut task_inner = task.inner_exclusive_access();
+        task_inner.signal_backup = None;
         task_inner.res.as_mut().unwrap().ustack_base = ustack_base;
         task_inner.res.as_mut().unwrap().alloc_user_res();
         task_inner.trap_cx_ppn = task_inner.res.as_mut().unwrap().trap_cx_ppn();
-        // push arguments on user stack
+        drop(task_inner);
+        if let Some(linux) = self.inner_exclusive_access().linux.as_mut() {
+            *linux = LinuxAbi::new();
+        }
+        self.start_main_thread(&task, entry_point, load_bias, &elf_data, args);
+    }
+
+    /// Push `args` on the user stack of the main thread, whose user resources
+    /// are allocated, and set its trap context to start the program.
+    fn start_main_thread(
+        &self,
+        task: &Arc<TaskControlBlock>,
+        entry_point: usize,
+        load_bias: usize,
+        elf_data: &[u8],
+        args: Vec<String>,
+    ) {
+        let mut task_inner = task.inner_exclusive_access();
         let mut user_sp = task_inner.res.as_mut().unwrap().ustack_top();
+        let process_inner = self.inner_exclusive_access();
+        let new_token = process_inner.memory_set.token();
+        if process_inner.linux.is_some() {
+            drop(process_inner);
+            user_sp = push_linux_args(new_token, user_sp, &args, elf_data, load_bias);
+            *task_inner.get_trap_cx() = TrapContext::app_init_context(
+                entry_point,
+                user_sp,
+                KERNEL_SPACE.exclusive_access().token(),
+                task.kstack.get_top(),
+                trap_handler as usize,
+            );
+            return;
+        }
+        drop(process_inner);
+        // push arguments on user stack
         user_sp -= (args.len() + 1) * core::mem::size_of::<usize>();
         let argv_base = user_sp;
         let mut argv: Vec<_> = (0..=args.len())
@@ -184,41 +324,62 @@ impl ProcessControlBlock {
         *task_inner.get_trap_cx() = trap_cx;
     }
 
+    /// Create a child running `program` with the descriptors in `fd_table`,
+    /// without copying this process first like fork and exec.
+    pub fn spawn(self: &Arc<Self>, program: Program, mut fd_table: FdTable) -> Arc<Self> {
+        let Program {
+            memory_set,
+            ustack_base,
+            entry_point,
+            load_bias,
+            elf_data,
+            args,
+        } = program;
+        fd_table.close_on_exec();
+        let mut parent = self.inner_exclusive_access();
+        let mut child_inner = parent.new_child(Arc::downgrade(self), memory_set);
+        if child_inner.linux.is_some() {
+            child_inner.linux = Some(LinuxAbi::new());
+        }
+        child_inner.signal_actions.reset_handlers();
+        child_inner.comm = program_name(&args);
+        let child = Arc::new(Self {
+            pid: pid_alloc(),
+            fd_table: RwIntrFreeCell::new(fd_table),
+            exit_status: Arc::new(ExitStatus::new()),
+            inner: unsafe { UPIntrFreeCell::new(child_inner) },
+        });
+        parent.children.push(Arc::clone(&child));
+        drop(parent);
+        // the user stack and the trap context are allocated in the new space
+        let task = Arc::new(TaskControlBlock::new(Arc::clone(&child), ustack_base, true));
+        child.start_main_thread(&task, entry_point, load_bias, &elf_data, args);
+        child
+            .inner_exclusive_access()
+            .tasks
+            .push(Some(Arc::clone(&task)));
+        insert_into_pid2process(child.getpid(), Arc::clone(&child));
+        add_task(task);
+        child
+    }
+
     /// Only support processes with a single thread.
     pub fn fork(self: &Arc<Self>) -> Arc<Self> {
         let mut parent = self.inner_exclusive_access();
         assert_eq!(parent.thread_count(), 1);
         // clone parent's memory_set completely including trampoline/ustacks/trap_cxs
-        let memory_set = MemorySet::from_existed_user(&parent.memory_set);
+        let memory_set = MemorySet::from_existed_user(&mut parent.memory_set);
         // alloc a pid
         let pid = pid_alloc();
         // copy fd table
-        let mut new_fd_table: Vec<Option<Arc<dyn File + Send + Sync>>> = Vec::new();
-        for fd in parent.fd_table.iter() {
-            if let Some(file) = fd {
-                new_fd_table.push(Some(file.clone()));
-            } else {
-                new_fd_table.push(None);
-            }
-        }
+        let new_fd_table = self.fd_table.read().clone();
         // create child process pcb
         let child = Arc::new(Self {
             pid,
+            fd_table: RwIntrFreeCell::new(new_fd_table),
+            exit_status: Arc::new(ExitStatus::new()),
             inner: unsafe {
-                UPIntrFreeCell::new(ProcessControlBlockInner {
-                    is_zombie: false,
-                    memory_set,
-                    parent: Some(Arc::downgrade(self)),
-                    children: Vec::new(),
-                    exit_code: 0,
-                    fd_table: new_fd_table,
-                    signals: SignalFlags::empty(),
-                    tasks: Vec::new(),
-                    task_res_allocator: RecycleAllocator::new(),
-                    mutex_list: Vec::new(),
-                    semaphore_list: Vec::new(),
-                    condvar_list: Vec::new(),
-                })
+                UPIntrFreeCell::new(parent.new_child(Arc::downgrade(self), memory_set))
             },
         });
         // add child
@@ -241,8 +402,14 @@ impl ProcessControlBlock {
         let mut child_inner = child.inner_exclusive_access();
         child_inner.tasks.push(Some(Arc::clone(&task)));
         drop(child_inner);
-        // modify kstack_top in trap_cx of this thread
-        let task_inner = task.inner_exclusive_access();
+        // modify kstack_top in trap_cx of this thread, which may run a
+        // signal handler like the parent
+        let parent_task = parent.get_task(0);
+        let parent_task_inner = parent_task.inner_exclusive_access();
+        let mut task_inner = task.inner_exclusive_access();
+        task_inner.signal_mask = parent_task_inner.signal_mask;
+        task_inner.signal_backup = parent_task_inner.signal_backup.clone();
+        drop(parent_task_inner);
         let trap_cx = task_inner.get_trap_cx();
         trap_cx.kernel_sp = task.kstack.get_top();
         drop(task_inner);
@@ -256,3 +423,10 @@ impl ProcessControlBlock {
         self.pid.0
     }
 }
+
+/// The name of a program, the last part of the path it is run by.
+fn program_name(args: &[String]) -> String {
+    args.first()
+        .and_then(|path| path.rsplit('/').next())
+        .map_or(String::new(), String::from)
+}
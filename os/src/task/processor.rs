@@ -1,13 +1,22 @@
 use super::__switch;
-use super::{fetch_task, TaskStatus};
+use super::cgroup::charge_cpu_group;
+use super::sched::{ReadyQueue, ReadyQueueImpl};
+use super::{fetch_task, has_ready_task, TaskStatus};
 use super::{ProcessControlBlock, TaskContext, TaskControlBlock};
+use crate::hart::{hart_id, idle, poll_tlb_flush, stop_requested, MAX_HARTS};
 use crate::sync::UPIntrFreeCell;
+use crate::timer::get_time_us;
 use crate::trap::TrapContext;
+use crate::trigger::switch_watchpoints;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use lazy_static::*;
 
 pub struct Processor {
     current: Option<Arc<TaskControlBlock>>,
+    /// when the current task was switched to, or charged last
+    since_us: usize,
     idle_task_cx: TaskContext,
 }
 
@@ -15,6 +24,7 @@ impl Processor {
     pub fn new() -> Self {
         Self {
             current: None,
+            since_us: 0,
             idle_task_cx: TaskContext::zero_init(),
         }
     }
@@ -30,38 +40,117 @@ impl Processor {
 }
 
 lazy_static! {
-    pub static ref PROCESSOR: UPIntrFreeCell<Processor> =
-        unsafe { UPIntrFreeCell::new(Processor::new()) };
+    /// One for each hart.
+    static ref PROCESSORS: Vec<UPIntrFreeCell<Processor>> = (0..MAX_HARTS)
+        .map(|_| unsafe { UPIntrFreeCell::new(Processor::new()) })
+        .collect();
 }
 
+/// The processor of the current hart.
+fn processor() -> &'static UPIntrFreeCell<Processor> {
+    &PROCESSORS[hart_id()]
+}
+
+/// See `idle_us`.
+static IDLE_US: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether to print every task switched to, toggled by the magic SysRq.
+static SCHED_TRACE: AtomicBool = AtomicBool::new(false);
+
+/// Toggle the trace of the scheduler, return whether it is on now.
+pub fn toggle_sched_trace() -> bool {
+    !SCHED_TRACE.fetch_xor(true, Ordering::Relaxed)
+}
+
+/// The scheduling loop of a hart, which returns only once a secondary hart is
+/// asked to stop.
 pub fn run_tasks() {
     loop {
-        let mut processor = PROCESSOR.exclusive_access();
+        if stop_requested() {
+            return;
+        }
+        let mut processor = processor().exclusive_access();
         if let Some(task) = fetch_task() {
+            // woken up by another hart before it is switched out of the one
+            // it blocked on, wait for its context saved
+            while task.on_cpu.load(Ordering::Acquire) {
+                poll_tlb_flush();
+                core::hint::spin_loop();
+            }
+            task.on_cpu.store(true, Ordering::Relaxed);
             let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
             // access coming task TCB exclusively
             let next_task_cx_ptr = task.inner.exclusive_session(|task_inner| {
                 task_inner.task_status = TaskStatus::Running;
                 &task_inner.task_cx as *const TaskContext
             });
-            processor.current = Some(task);
+            let cpu_group = task.cpu_group();
+            if SCHED_TRACE.load(Ordering::Relaxed) {
+                if let Some(process) = task.process.upgrade() {
+                    let tid = task
+                        .inner_exclusive_access()
+                        .res
+                        .as_ref()
+                        .map(|res| res.tid);
+                    println!("[sched] pid {} tid {:?}", process.getpid(), tid);
+                }
+            }
+            if let Some(process) = task.process.upgrade() {
+                let watchpoints = process.inner_exclusive_access().watchpoints;
+                switch_watchpoints(process.getpid(), &watchpoints);
+            }
+            let start_us = get_time_us();
+            processor.current = Some(Arc::clone(&task));
+            processor.since_us = start_us;
             // release processor manually
             drop(processor);
             unsafe {
                 __switch(idle_task_cx_ptr, next_task_cx_ptr);
             }
+            // back from the task, its context is saved
+            task.on_cpu.store(false, Ordering::Release);
+            // unless it was charged as it was added back
+            charge_task(&task);
+            // charge its group for the time it has run
+            charge_cpu_group(cpu_group, get_time_us() - start_us);
         } else {
-            println!("no tasks available in run_tasks");
+            drop(processor);
+            let start_us = get_time_us();
+            idle(has_ready_task);
+            IDLE_US.fetch_add(get_time_us() - start_us, Ordering::Relaxed);
         }
     }
 }
 
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().take_current()
+    processor().exclusive_access().take_current()
 }
 
 pub fn current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().current()
+    processor().exclusive_access().current()
+}
+
+/// How long the current task has run since it was switched to, or charged
+/// last.
+pub fn current_ran_us() -> usize {
+    get_time_us() - processor().exclusive_session(|processor| processor.since_us)
+}
+
+/// Charge the task taken off the current hart to the scheduling policy for
+/// the time since it was switched to or charged last. It is charged before it
+/// is added back to the ready queue if it is still ready, and after it is
+/// switched out anyway.
+pub fn charge_task(task: &TaskControlBlock) {
+    let now_us = get_time_us();
+    let since_us = processor()
+        .exclusive_session(|processor| core::mem::replace(&mut processor.since_us, now_us));
+    task.ran_us.fetch_add(now_us - since_us, Ordering::Relaxed);
+    ReadyQueueImpl::charge(task, now_us - since_us);
+}
+
+/// How long the harts have been idle in us, all of them summed.
+pub fn idle_us() -> usize {
+    IDLE_US.load(Ordering::Relaxed)
 }
 
 pub fn current_process() -> Arc<ProcessControlBlock> {
@@ -96,7 +185,7 @@ pub fn current_kstack_top() -> usize {
 
 pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
     let idle_task_cx_ptr =
-        PROCESSOR.exclusive_session(|processor| processor.get_idle_task_cx_ptr());
+        processor().exclusive_session(|processor| processor.get_idle_task_cx_ptr());
     unsafe {
         __switch(switched_task_cx_ptr, idle_task_cx_ptr);
     }
@@ -0,0 +1,71 @@
+use super::ReadyQueue;
+use crate::task::{TaskControlBlock, NICE_MIN};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The weight of each nice value from `NICE_MIN`, the same as Linux, each
+/// one about 1.25 times the next.
+const NICE_TO_WEIGHT: [usize; 40] = [
+    88761, 71755, 56483, 46273, 36291, 29154, 23254, 18705, 14949, 11916, 9548, 7620, 6100, 4904,
+    3906, 3121, 2501, 1991, 1586, 1277, 1024, 820, 655, 526, 423, 335, 272, 215, 172, 137, 110, 87,
+    70, 56, 45, 36, 29, 23, 18, 15,
+];
+/// the weight of nice 0
+const NICE_0_WEIGHT: usize = 1024;
+/// A task which has waited is put this much before the least vruntime of
+/// the queue at most, so that it runs soon but cannot take the cpu for all
+/// the time it waited.
+const SLEEPER_CREDIT_US: usize = 10_000;
+
+#[derive(Default)]
+pub struct CfsEntity {
+    /// the time it has run, scaled by `NICE_0_WEIGHT` / its weight
+    vruntime: AtomicUsize,
+}
+
+/// The completely fair scheduler: the task which has run the least virtual
+/// time runs next, a task of a less nice value getting more time for the
+/// same virtual time.
+#[derive(Default)]
+pub struct Cfs {
+    /// by the vruntime, then by the order they are pushed
+    tasks: BTreeMap<(usize, usize), Arc<TaskControlBlock>>,
+    /// the vruntime of the task popped last, never going back
+    min_vruntime: usize,
+    pushed: usize,
+}
+
+impl ReadyQueue for Cfs {
+    type Entity = CfsEntity;
+    const KEY_NAME: &'static str = "VRUNTIME";
+
+    fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+    fn push(&mut self, task: Arc<TaskControlBlock>) {
+        let vruntime = task
+            .sched
+            .vruntime
+            .load(Ordering::Relaxed)
+            .max(self.min_vruntime.saturating_sub(SLEEPER_CREDIT_US));
+        task.sched.vruntime.store(vruntime, Ordering::Relaxed);
+        self.tasks.insert((vruntime, self.pushed), task);
+        self.pushed += 1;
+    }
+    fn pop(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let ((vruntime, _), task) = self.tasks.pop_first()?;
+        self.min_vruntime = self.min_vruntime.max(vruntime);
+        Some(task)
+    }
+    fn preempted(_task: &TaskControlBlock, _ran_us: usize) {}
+    fn charge(task: &TaskControlBlock, us: usize) {
+        let weight = NICE_TO_WEIGHT[(task.nice() - NICE_MIN) as usize];
+        task.sched
+            .vruntime
+            .fetch_add(us * NICE_0_WEIGHT / weight, Ordering::Relaxed);
+    }
+    fn key(task: &TaskControlBlock) -> usize {
+        task.sched.vruntime.load(Ordering::Relaxed)
+    }
+}
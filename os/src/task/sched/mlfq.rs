@@ -0,0 +1,96 @@
+use super::ReadyQueue;
+use crate::task::{TaskControlBlock, NICE_MIN};
+use crate::timer::{get_time_ms, TICK_US};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The levels of the queue, 0 is scheduled first.
+const LEVELS: usize = 8;
+/// nice values of a level a task starts at, -20..-11 start at 0, 0..9 at 2
+const NICE_PER_LEVEL: isize = 10;
+/// All the tasks are boosted back to the level of their nice values this
+/// often, so that no ready task starves.
+const BOOST_INTERVAL_MS: usize = 500;
+
+/// The boosts since boot.
+fn boost_epoch() -> usize {
+    get_time_ms() / BOOST_INTERVAL_MS
+}
+
+#[derive(Default)]
+pub struct MlfqEntity {
+    /// levels it has gone down by using up time slices
+    penalty: AtomicUsize,
+    /// the last boost which has reset the penalty
+    boost_epoch: AtomicUsize,
+}
+
+impl MlfqEntity {
+    /// Back to the level of its nice value, once for each boost `epoch`.
+    fn boost(&self, epoch: usize) {
+        if self.boost_epoch.swap(epoch, Ordering::Relaxed) != epoch {
+            self.penalty.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The level of the task, by its nice value and the time slices it has used
+/// up.
+fn level(task: &TaskControlBlock) -> usize {
+    let base = ((task.nice() - NICE_MIN) / NICE_PER_LEVEL) as usize;
+    (base + task.sched.penalty.load(Ordering::Relaxed)).min(LEVELS - 1)
+}
+
+/// A multilevel feedback queue, FIFO in each level. A task starts at the
+/// level of its nice value and goes down a level whenever it runs through a
+/// time slice, so one which mostly waits, like a shell, runs before those
+/// which compute all the time.
+#[derive(Default)]
+pub struct Mlfq {
+    levels: [VecDeque<Arc<TaskControlBlock>>; LEVELS],
+    /// the last boost of the tasks in the queue
+    boost_epoch: usize,
+}
+
+impl ReadyQueue for Mlfq {
+    type Entity = MlfqEntity;
+    const KEY_NAME: &'static str = "LEVEL";
+
+    fn is_empty(&self) -> bool {
+        self.levels.iter().all(|level| level.is_empty())
+    }
+    fn push(&mut self, task: Arc<TaskControlBlock>) {
+        task.sched.boost(boost_epoch());
+        self.levels[level(&task)].push_back(task);
+    }
+    fn pop(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let epoch = boost_epoch();
+        if self.boost_epoch != epoch {
+            self.boost_epoch = epoch;
+            let tasks: Vec<_> = self
+                .levels
+                .iter_mut()
+                .flat_map(|level| level.drain(..))
+                .collect();
+            for task in tasks {
+                self.push(task);
+            }
+        }
+        self.levels.iter_mut().find_map(|level| level.pop_front())
+    }
+    /// If it has run for most of the tick, it has used up its time slice and
+    /// goes down a level, otherwise it was switched to in the middle of the
+    /// tick and may stay.
+    fn preempted(task: &TaskControlBlock, ran_us: usize) {
+        let penalty = &task.sched.penalty;
+        if ran_us >= TICK_US / 2 && penalty.load(Ordering::Relaxed) < LEVELS - 1 {
+            penalty.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    fn charge(_task: &TaskControlBlock, _us: usize) {}
+    fn key(task: &TaskControlBlock) -> usize {
+        level(task)
+    }
+}
@@ -0,0 +1,40 @@
+//! The policies ordering the ready tasks of a cpu group.
+//!
+//! Each policy implements [`ReadyQueue`] in its own module, and the one being
+//! built is re-exported here as `ReadyQueueImpl`: the multilevel feedback
+//! queue by default, or the completely fair scheduler with the `sched_cfs`
+//! feature. The cpu groups share the cpu by their shares above either.
+
+#[cfg(feature = "sched_cfs")]
+mod cfs;
+#[cfg(not(feature = "sched_cfs"))]
+mod mlfq;
+
+use super::TaskControlBlock;
+use alloc::sync::Arc;
+
+#[cfg(feature = "sched_cfs")]
+pub use cfs::Cfs as ReadyQueueImpl;
+#[cfg(not(feature = "sched_cfs"))]
+pub use mlfq::Mlfq as ReadyQueueImpl;
+
+/// The state of a task kept by the policy being built.
+pub type SchedEntity = <ReadyQueueImpl as ReadyQueue>::Entity;
+
+pub trait ReadyQueue: Default {
+    /// The state of each task, in its `TaskControlBlock`.
+    type Entity: Default;
+    /// The name of what `key` shows, for the magic SysRq.
+    const KEY_NAME: &'static str;
+
+    fn is_empty(&self) -> bool;
+    fn push(&mut self, task: Arc<TaskControlBlock>);
+    /// The task to run next.
+    fn pop(&mut self) -> Option<Arc<TaskControlBlock>>;
+    /// The task has been preempted by a timer tick after running for `ran_us`.
+    fn preempted(task: &TaskControlBlock, ran_us: usize);
+    /// The task has run for `us`, whether it was preempted or not.
+    fn charge(task: &TaskControlBlock, us: usize);
+    /// What the task is ordered by, for the magic SysRq.
+    fn key(task: &TaskControlBlock) -> usize;
+}
@@ -1,29 +1,35 @@
-use bitflags::*;
+use abi::SysError;
+pub use abi::{SignalAction, SignalActionFlags, SignalFlags, MAX_SIG, SIG_DFL, SIG_IGN};
 
-bitflags! {
-    pub struct SignalFlags: u32 {
-        const SIGINT    = 1 << 2;
-        const SIGILL    = 1 << 4;
-        const SIGABRT   = 1 << 6;
-        const SIGFPE    = 1 << 8;
-        const SIGSEGV   = 1 << 11;
+/// Returned by a syscall interrupted by a signal, the errno of Linux.
+pub const EINTR: isize = SysError::Interrupted.code();
+/// Returned by a syscall interrupted before it has done anything, never
+/// seen by the user. It is restarted if no handler is called or the handler
+/// has `SignalActionFlags::SA_RESTART`, and returns `EINTR` otherwise.
+pub const ERESTARTSYS: isize = SysError::Restart.code();
+
+/// The actions of the signals of a process, indexed by their numbers.
+#[derive(Clone, Copy)]
+pub struct SignalActions {
+    pub table: [SignalAction; MAX_SIG + 1],
+}
+
+impl Default for SignalActions {
+    fn default() -> Self {
+        Self {
+            table: [SignalAction::default(); MAX_SIG + 1],
+        }
     }
 }
 
-impl SignalFlags {
-    pub fn check_error(&self) -> Option<(i32, &'static str)> {
-        if self.contains(Self::SIGINT) {
-            Some((-2, "Killed, SIGINT=2"))
-        } else if self.contains(Self::SIGILL) {
-            Some((-4, "Illegal Instruction, SIGILL=4"))
-        } else if self.contains(Self::SIGABRT) {
-            Some((-6, "Aborted, SIGABRT=6"))
-        } else if self.contains(Self::SIGFPE) {
-            Some((-8, "Erroneous Arithmetic Operation, SIGFPE=8"))
-        } else if self.contains(Self::SIGSEGV) {
-            Some((-11, "Segmentation Fault, SIGSEGV=11"))
-        } else {
-            None
+impl SignalActions {
+    /// The actions after exec, where the handlers of the old program are
+    /// gone. The signals ignored stay ignored.
+    pub fn reset_handlers(&mut self) {
+        for action in self.table.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SignalAction::default();
+            }
         }
     }
 }
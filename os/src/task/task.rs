@@ -1,17 +1,35 @@
+use super::cgroup::ROOT_CPU_GROUP;
 use super::id::TaskUserRes;
-use super::{kstack_alloc, KernelStack, ProcessControlBlock, TaskContext};
+use super::sched::SchedEntity;
+use super::{kstack_alloc, KernelStack, ProcessControlBlock, SignalFlags, TaskContext};
 use crate::trap::TrapContext;
 use crate::{
     mm::PhysPageNum,
     sync::{UPIntrFreeCell, UPIntrRefMut},
 };
+use alloc::boxed::Box;
 use alloc::sync::{Arc, Weak};
+use core::sync::atomic::{AtomicBool, AtomicIsize, AtomicUsize, Ordering};
+
+/// The range of nice values, the less the higher the priority.
+pub const NICE_MIN: isize = -20;
+pub const NICE_MAX: isize = 19;
 
 pub struct TaskControlBlock {
     // immutable
     pub process: Weak<ProcessControlBlock>,
     pub kstack: KernelStack,
     // mutable
+    /// copied from the process, so that the scheduler needs not to access it
+    cpu_group: AtomicUsize,
+    /// also copied from the process
+    nice: AtomicIsize,
+    /// kept by the scheduling policy
+    pub sched: SchedEntity,
+    /// running on a hart, or switched out but with the context not saved yet
+    pub on_cpu: AtomicBool,
+    /// how long it has run on the harts in us
+    pub ran_us: AtomicUsize,
     pub inner: UPIntrFreeCell<TaskControlBlockInner>,
 }
 
@@ -20,6 +38,22 @@ impl TaskControlBlock {
         self.inner.exclusive_access()
     }
 
+    pub fn cpu_group(&self) -> usize {
+        self.cpu_group.load(Ordering::Relaxed)
+    }
+
+    pub fn set_cpu_group(&self, group_id: usize) {
+        self.cpu_group.store(group_id, Ordering::Relaxed);
+    }
+
+    pub fn nice(&self) -> isize {
+        self.nice.load(Ordering::Relaxed)
+    }
+
+    pub fn set_nice(&self, nice: isize) {
+        self.nice.store(nice, Ordering::Relaxed);
+    }
+
     pub fn get_user_token(&self) -> usize {
         let process = self.process.upgrade().unwrap();
         let inner = process.inner_exclusive_access();
@@ -33,6 +67,21 @@ pub struct TaskControlBlockInner {
     pub task_cx: TaskContext,
     pub task_status: TaskStatus,
     pub exit_code: Option<i32>,
+    /// killed, or left behind by its process, so it is never to return to
+    /// the user mode
+    pub exiting: bool,
+    /// run once the task is exiting or signalled while it waits, to wake it
+    /// up
+    pub wake_hook: Option<Box<dyn FnOnce()>>,
+    /// a wait of the current syscall gave up for a signal
+    pub interrupted: bool,
+    /// pending for this thread, like the faults it raises
+    pub signals: SignalFlags,
+    /// blocked by this thread
+    pub signal_mask: SignalFlags,
+    /// the context and the mask before a handler runs, restored by
+    /// sigreturn, Some while it runs
+    pub signal_backup: Option<(TrapContext, SignalFlags)>,
 }
 
 impl TaskControlBlockInner {
@@ -56,9 +105,17 @@ impl TaskControlBlock {
         let trap_cx_ppn = res.trap_cx_ppn();
         let kstack = kstack_alloc();
         let kstack_top = kstack.get_top();
+        let process_inner = process.inner_exclusive_access();
+        let (cpu_group, nice) = (process_inner.cpu_group, process_inner.nice);
+        drop(process_inner);
         Self {
             process: Arc::downgrade(&process),
             kstack,
+            cpu_group: AtomicUsize::new(cpu_group),
+            nice: AtomicIsize::new(nice),
+            sched: SchedEntity::default(),
+            on_cpu: AtomicBool::new(false),
+            ran_us: AtomicUsize::new(0),
             inner: unsafe {
                 UPIntrFreeCell::new(TaskControlBlockInner {
                     res: Some(res),
@@ -66,6 +123,43 @@ impl TaskControlBlock {
                     task_cx: TaskContext::goto_trap_return(kstack_top),
                     task_status: TaskStatus::Ready,
                     exit_code: None,
+                    exiting: false,
+                    wake_hook: None,
+                    interrupted: false,
+                    signals: SignalFlags::empty(),
+                    signal_mask: SignalFlags::empty(),
+                    signal_backup: None,
+                })
+            },
+        }
+    }
+
+    /// A thread of the kernel running `entry`, with no process or anything
+    /// of the user, which is never to return to the user mode.
+    pub fn new_kernel(entry: fn() -> !) -> Self {
+        let kstack = kstack_alloc();
+        let kstack_top = kstack.get_top();
+        Self {
+            process: Weak::new(),
+            kstack,
+            cpu_group: AtomicUsize::new(ROOT_CPU_GROUP),
+            nice: AtomicIsize::new(0),
+            sched: SchedEntity::default(),
+            on_cpu: AtomicBool::new(false),
+            ran_us: AtomicUsize::new(0),
+            inner: unsafe {
+                UPIntrFreeCell::new(TaskControlBlockInner {
+                    res: None,
+                    trap_cx_ppn: PhysPageNum(0),
+                    task_cx: TaskContext::goto_kernel_thread(entry, kstack_top),
+                    task_status: TaskStatus::Ready,
+                    exit_code: None,
+                    exiting: false,
+                    wake_hook: None,
+                    interrupted: false,
+                    signals: SignalFlags::empty(),
+                    signal_mask: SignalFlags::empty(),
+                    signal_backup: None,
                 })
             },
         }
@@ -1,74 +1,354 @@
-use core::cmp::Ordering;
-
-use crate::config::CLOCK_FREQ;
+use crate::config::{CLINT, CLOCK_FREQ, RTC};
+use crate::drivers::clint::Clint;
+use crate::drivers::rtc::GoldfishRtc;
+use crate::hart::hart_id;
+use crate::hart::MAX_HARTS;
 use crate::sbi::set_timer;
 use crate::sync::UPIntrFreeCell;
 use crate::task::{wakeup_task, TaskControlBlock};
-use alloc::collections::BinaryHeap;
+pub use abi::{TimeSpec, CLOCK_MONOTONIC, CLOCK_REALTIME};
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use core::task::{Context, Poll, Waker};
 use lazy_static::*;
 use riscv::register::time;
 
 const TICKS_PER_SEC: usize = 100;
 const MSEC_PER_SEC: usize = 1000;
+const USEC_PER_SEC: usize = 1_000_000;
+/// the time slice of a task
+pub const TICK_US: usize = USEC_PER_SEC / TICKS_PER_SEC;
+const NSEC_PER_SEC: u128 = 1_000_000_000;
+
+/// The frequency of `time`, from the device tree if there is one.
+static TIMEBASE_FREQ: AtomicUsize = AtomicUsize::new(CLOCK_FREQ);
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_TICK: AtomicUsize = AtomicUsize::new(0);
+/// The wall clock time at boot in ns, 0 without an RTC.
+static BOOT_REALTIME_NS: AtomicUsize = AtomicUsize::new(0);
+/// When the time slice of each hart ends, in the unit of `time`.
+static NEXT_TICK: [AtomicUsize; MAX_HARTS] = [NO_TICK; MAX_HARTS];
+
+/// Use the timebase frequency found in the device tree, at boot.
+pub fn set_timebase_freq(freq: usize) {
+    if freq != 0 {
+        TIMEBASE_FREQ.store(freq, AtomicOrdering::Relaxed);
+    }
+}
 
+pub fn timebase_freq() -> usize {
+    TIMEBASE_FREQ.load(AtomicOrdering::Relaxed)
+}
+
+/// Read `mtime` directly with the CLINT, `time` may be emulated by the SBI.
 pub fn get_time() -> usize {
-    time::read()
+    match CLINT {
+        Some(base_addr) => Clint::new(base_addr).mtime() as usize,
+        None => time::read(),
+    }
 }
 
 pub fn get_time_ms() -> usize {
-    time::read() / (CLOCK_FREQ / MSEC_PER_SEC)
+    get_time() / (timebase_freq() / MSEC_PER_SEC)
 }
 
-pub fn set_next_trigger() {
-    set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
+pub fn get_time_us() -> usize {
+    get_time() / (timebase_freq() / USEC_PER_SEC)
 }
 
-pub struct TimerCondVar {
-    pub expire_ms: usize,
-    pub task: Arc<TaskControlBlock>,
+/// As precise as the timebase, in 128 bits so it does not overflow.
+pub fn get_time_ns() -> usize {
+    (get_time() as u128 * NSEC_PER_SEC / timebase_freq() as u128) as usize
 }
 
-impl PartialEq for TimerCondVar {
-    fn eq(&self, other: &Self) -> bool {
-        self.expire_ms == other.expire_ms
+/// Read the wall clock time from the RTC of the board if there is one, once
+/// paging is on. It then goes on with `time`, so it never jumps.
+pub fn init_realtime() {
+    if let Some(base_addr) = RTC {
+        let now_ns = GoldfishRtc::new(base_addr).now_ns() as usize;
+        BOOT_REALTIME_NS.store(
+            now_ns.saturating_sub(get_time_ns()),
+            AtomicOrdering::Relaxed,
+        );
     }
 }
-impl Eq for TimerCondVar {}
-impl PartialOrd for TimerCondVar {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        let a = -(self.expire_ms as isize);
-        let b = -(other.expire_ms as isize);
-        Some(a.cmp(&b))
+
+/// The wall clock time in ns since the Unix epoch, or since boot if there
+/// is no real time clock.
+pub fn get_realtime_ns() -> usize {
+    BOOT_REALTIME_NS.load(AtomicOrdering::Relaxed) + get_time_ns()
+}
+
+/// The time of a clock in ns, None if there is no such clock.
+pub fn clock_ns(clock_id: usize) -> Option<usize> {
+    match clock_id {
+        CLOCK_REALTIME => Some(get_realtime_ns()),
+        CLOCK_MONOTONIC => Some(get_time_ns()),
+        _ => None,
     }
 }
 
-impl Ord for TimerCondVar {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.partial_cmp(other).unwrap()
+/// The time `ns` nanoseconds after boot in the unit of `time`, rounded up
+/// so a timer never expires early.
+fn ns_to_time(ns: usize) -> usize {
+    let freq = timebase_freq() as u128;
+    ((ns as u128 * freq + NSEC_PER_SEC - 1) / NSEC_PER_SEC) as usize
+}
+
+/// Interrupt at `time` reaching `next`, through the CLINT of the board if
+/// there is one, or else the SBI.
+fn program_timer(next: usize) {
+    match CLINT {
+        Some(base_addr) => Clint::new(base_addr).set_timer(hart_id(), next as u64),
+        None => set_timer(next),
     }
 }
 
-lazy_static! {
-    static ref TIMERS: UPIntrFreeCell<BinaryHeap<TimerCondVar>> =
-        unsafe { UPIntrFreeCell::new(BinaryHeap::<TimerCondVar>::new()) };
+/// Interrupt at the end of the time slice of the current hart, or when the
+/// first kernel timer expires if it is earlier.
+fn program_next_interrupt() {
+    let tick = NEXT_TICK[hart_id()].load(AtomicOrdering::Relaxed);
+    let first = TIMERS.exclusive_session(|timers| timers.first_expire());
+    program_timer(first.map_or(tick, |first| first.min(tick)));
 }
 
-pub fn add_timer(expire_ms: usize, task: Arc<TaskControlBlock>) {
-    let mut timers = TIMERS.exclusive_access();
-    timers.push(TimerCondVar { expire_ms, task });
+/// Start a new time slice of the current hart.
+pub fn set_next_trigger() {
+    let next = get_time() + timebase_freq() / TICKS_PER_SEC;
+    NEXT_TICK[hart_id()].store(next, AtomicOrdering::Relaxed);
+    program_next_interrupt();
+}
+
+/// A kernel timer, to cancel it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimerId(usize);
+
+/// Run in the timer interrupt with interrupts disabled, it must not block.
+type TimerCallback = Arc<dyn Fn()>;
+
+struct Timer {
+    /// the interval of a periodic timer in the unit of `time`
+    period: Option<usize>,
+    callback: TimerCallback,
 }
 
-pub fn check_timer() {
-    let current_ms = get_time_ms();
-    TIMERS.exclusive_session(|timers| {
-        while let Some(timer) = timers.peek() {
-            if timer.expire_ms <= current_ms {
-                wakeup_task(Arc::clone(&timer.task));
-                timers.pop();
-            } else {
-                break;
+/// The timers by when they expire in the unit of `time`, a min-heap which
+/// cancels a timer by its id in logarithmic time.
+struct TimerQueue {
+    timers: BTreeMap<(usize, TimerId), Timer>,
+    /// when each timer expires
+    expires: BTreeMap<TimerId, usize>,
+    /// the timers whose callbacks are running, by the harts running them
+    running: BTreeMap<TimerId, usize>,
+    next_id: usize,
+}
+
+impl TimerQueue {
+    fn insert(&mut self, id: TimerId, expire: usize, timer: Timer) {
+        self.timers.insert((expire, id), timer);
+        self.expires.insert(id, expire);
+    }
+
+    fn remove(&mut self, id: TimerId) -> Option<Timer> {
+        let expire = self.expires.remove(&id)?;
+        self.timers.remove(&(expire, id))
+    }
+
+    fn first_expire(&self) -> Option<usize> {
+        self.timers
+            .first_key_value()
+            .map(|(&(expire, _), _)| expire)
+    }
+
+    /// Take the first timer if it expires before `now`, a periodic one is
+    /// put back for its next expiry. It is running until `finish`.
+    fn pop_expired(&mut self, now: usize) -> Option<(TimerId, TimerCallback)> {
+        let (&(expire, id), _) = self.timers.first_key_value()?;
+        if expire > now {
+            return None;
+        }
+        let timer = self.remove(id).unwrap();
+        let callback = Arc::clone(&timer.callback);
+        if let Some(period) = timer.period {
+            // the expiries missed are skipped
+            let mut next = expire + period;
+            if next <= now {
+                next = now + period;
             }
+            self.insert(id, next, timer);
         }
+        self.running.insert(id, hart_id());
+        Some((id, callback))
+    }
+
+    fn finish(&mut self, id: TimerId) {
+        self.running.remove(&id);
+    }
+
+    /// Whether the callback of `id` is running on another hart.
+    fn running_elsewhere(&self, id: TimerId) -> bool {
+        self.running
+            .get(&id)
+            .map_or(false, |&hart| hart != hart_id())
+    }
+}
+
+lazy_static! {
+    static ref TIMERS: UPIntrFreeCell<TimerQueue> = unsafe {
+        UPIntrFreeCell::new(TimerQueue {
+            timers: BTreeMap::new(),
+            expires: BTreeMap::new(),
+            running: BTreeMap::new(),
+            next_id: 0,
+        })
+    };
+}
+
+/// Run `callback` once the time reaches `expire_ns` nanoseconds after boot,
+/// then every `period_ns` nanoseconds if it is not None until the timer is
+/// cancelled. The callback runs in the timer interrupt of any hart, so it
+/// must not block.
+pub fn register_timer(
+    expire_ns: usize,
+    period_ns: Option<usize>,
+    callback: impl Fn() + 'static,
+) -> TimerId {
+    let expire = ns_to_time(expire_ns);
+    let timer = Timer {
+        period: period_ns.map(|period_ns| ns_to_time(period_ns).max(1)),
+        callback: Arc::new(callback),
+    };
+    let (id, first) = TIMERS.exclusive_session(|timers| {
+        let id = TimerId(timers.next_id);
+        timers.next_id += 1;
+        timers.insert(id, expire, timer);
+        (id, timers.first_expire() == Some(expire))
     });
+    // the other harts interrupt no earlier than they did
+    if first {
+        program_next_interrupt();
+    }
+    id
+}
+
+/// Cancel a timer, return false if it has expired already and is not
+/// periodic, or is cancelled already. A callback of it running on another
+/// hart is waited for, so none is in flight once it returns.
+pub fn cancel_timer(id: TimerId) -> bool {
+    let cancelled = TIMERS.exclusive_session(|timers| timers.remove(id).is_some());
+    while TIMERS.exclusive_session(|timers| timers.running_elsewhere(id)) {
+        core::hint::spin_loop();
+    }
+    cancelled
+}
+
+/// Wake up `task` once the time reaches `expire_ns`.
+pub fn add_timer(expire_ns: usize, task: Arc<TaskControlBlock>) -> TimerId {
+    register_timer(expire_ns, None, move || wakeup_task(Arc::clone(&task)))
+}
+
+/// Run the callbacks of the timers expired, return true if the time slice
+/// of the current hart is over.
+pub fn handle_timer_interrupt() -> bool {
+    let now = get_time();
+    // one at a time, as a callback may register or cancel timers
+    while let Some((id, callback)) = TIMERS.exclusive_session(|timers| timers.pop_expired(now)) {
+        callback();
+        TIMERS.exclusive_session(|timers| timers.finish(id));
+    }
+    let tick_over = now >= NEXT_TICK[hart_id()].load(AtomicOrdering::Relaxed);
+    if tick_over {
+        set_next_trigger();
+    } else {
+        program_next_interrupt();
+    }
+    tick_over
+}
+
+/// A future ready once the time reaches a deadline, woken up by a kernel
+/// timer, for the drivers and syscalls run by `block_on_yielding`.
+pub struct Sleep {
+    expire_ns: usize,
+    /// the timer waking up the waker of the last poll
+    timer: Option<(TimerId, Waker)>,
+}
+
+impl Sleep {
+    /// Sleep until the time reaches `expire_ns` nanoseconds after boot.
+    pub fn until(expire_ns: usize) -> Self {
+        Self {
+            expire_ns,
+            timer: None,
+        }
+    }
+
+    pub fn after(ns: usize) -> Self {
+        Self::until(get_time_ns().saturating_add(ns))
+    }
+
+    fn cancel(&mut self) {
+        if let Some((timer, _)) = self.timer.take() {
+            cancel_timer(timer);
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        if get_time_ns() >= this.expire_ns {
+            this.cancel();
+            return Poll::Ready(());
+        }
+        match &this.timer {
+            Some((_, waker)) if waker.will_wake(cx.waker()) => {}
+            _ => {
+                this.cancel();
+                let waker = cx.waker().clone();
+                let timer = register_timer(this.expire_ns, None, move || waker.wake_by_ref());
+                this.timer = Some((timer, cx.waker().clone()));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// A future giving up on another one at a deadline, see `with_timeout`.
+pub struct Timeout<F> {
+    future: F,
+    sleep: Sleep,
+}
+
+/// Run `future` until the time reaches `expire_ns` nanoseconds after boot,
+/// None if it is not done by then. It is polled at least once, so one done
+/// already is not lost even if the time is up.
+pub fn with_timeout<F: Future + Unpin>(future: F, expire_ns: usize) -> Timeout<F> {
+    Timeout {
+        future,
+        sleep: Sleep::until(expire_ns),
+    }
+}
+
+impl<F: Future + Unpin> Future for Timeout<F> {
+    type Output = Option<F::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if let Poll::Ready(output) = Pin::new(&mut this.future).poll(cx) {
+            return Poll::Ready(Some(output));
+        }
+        Pin::new(&mut this.sleep).poll(cx).map(|()| None)
+    }
 }
@@ -1,12 +1,17 @@
 mod context;
 
 use crate::config::TRAMPOLINE;
+use crate::hart::{handle_ipi, hart_id, stop_requested};
 use crate::syscall::syscall;
 use crate::task::{
-    check_signals_of_current, current_add_signal, current_trap_cx, current_trap_cx_user_va,
-    current_user_token, exit_current_and_run_next, suspend_current_and_run_next, SignalFlags,
+    clear_current_interrupted, current_add_signal, current_interrupted, current_process,
+    current_stack_fault, current_task, current_task_left_behind, current_trap_cx,
+    current_trap_cx_user_va, current_user_token, exit_current_and_run_next,
+    handle_signals_of_current, handle_user_page_fault, preempt_current_and_run_next,
+    suspend_current_and_run_next, SignalFlags, StackFault, ERESTARTSYS,
 };
-use crate::timer::{check_timer, set_next_trigger};
+use crate::timer::handle_timer_interrupt;
+use crate::trigger::{handle_kernel_hit, user_watchpoint_hit};
 use core::arch::{asm, global_asm};
 use riscv::register::{
     mtvec::TrapMode,
@@ -44,6 +49,13 @@ pub fn enable_timer_interrupt() {
     }
 }
 
+/// IPIs from the other harts.
+pub fn enable_software_interrupt() {
+    unsafe {
+        sie::set_ssoft();
+    }
+}
+
 fn enable_supervisor_interrupt() {
     unsafe {
         sstatus::set_sie();
@@ -62,26 +74,63 @@ pub fn trap_handler() -> ! {
     let scause = scause::read();
     let stval = stval::read();
     // println!("into {:?}", scause.cause());
+    // the first argument of a syscall to restart after the signals
+    let mut restart = None;
     match scause.cause() {
         Trap::Exception(Exception::UserEnvCall) => {
             // jump to next instruction anyway
             let mut cx = current_trap_cx();
             cx.sepc += 4;
+            let a0 = cx.x[10];
+            clear_current_interrupted();
 
             enable_supervisor_interrupt();
 
             // get system call return value
-            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]);
+            let result = syscall(
+                cx.x[17],
+                [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]],
+            );
             // cx is changed during sys_exec, so we have to call it again
             cx = current_trap_cx();
             cx.x[10] = result as usize;
+            // not a value restored by sigreturn
+            if result == ERESTARTSYS && current_interrupted() {
+                restart = Some(a0);
+            }
+        }
+        Trap::Exception(Exception::StorePageFault)
+        | Trap::Exception(Exception::LoadPageFault)
+        | Trap::Exception(Exception::InstructionPageFault) => {
+            let write = matches!(scause.cause(), Trap::Exception(Exception::StorePageFault));
+            // retry the instruction once the page is resolved
+            if !handle_user_page_fault(current_user_token(), stval, write) {
+                match current_stack_fault(stval) {
+                    // retry the instruction on the grown stack
+                    Some(StackFault::Grown) => {}
+                    Some(StackFault::Overflow) => {
+                        warn!(
+                            "[kernel] Stack overflow in pid {} tid {}, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.",
+                            current_process().getpid(),
+                            current_task()
+                                .unwrap()
+                                .inner_exclusive_access()
+                                .res
+                                .as_ref()
+                                .unwrap()
+                                .tid,
+                            stval,
+                            current_trap_cx().sepc,
+                        );
+                        current_add_signal(SignalFlags::SIGSEGV);
+                    }
+                    None => current_add_signal(SignalFlags::SIGSEGV),
+                }
+            }
         }
         Trap::Exception(Exception::StoreFault)
-        | Trap::Exception(Exception::StorePageFault)
         | Trap::Exception(Exception::InstructionFault)
-        | Trap::Exception(Exception::InstructionPageFault)
-        | Trap::Exception(Exception::LoadFault)
-        | Trap::Exception(Exception::LoadPageFault) => {
+        | Trap::Exception(Exception::LoadFault) => {
             /*
             println!(
                 "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.",
@@ -95,13 +144,31 @@ pub fn trap_handler() -> ! {
         Trap::Exception(Exception::IllegalInstruction) => {
             current_add_signal(SignalFlags::SIGILL);
         }
+        Trap::Exception(Exception::Breakpoint) => {
+            // an ebreak, or a watchpoint set by ptrace
+            if let Some(watchpoint) = user_watchpoint_hit(stval) {
+                info!(
+                    "[kernel] Watchpoint [{:#x}, {:#x}) of pid {} hit at {:#x}, bad instruction = {:#x}",
+                    watchpoint.addr,
+                    watchpoint.addr + watchpoint.len,
+                    current_process().getpid(),
+                    stval,
+                    current_trap_cx().sepc,
+                );
+            }
+            current_add_signal(SignalFlags::SIGTRAP);
+        }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
-            set_next_trigger();
-            check_timer();
-            suspend_current_and_run_next();
+            if handle_timer_interrupt() {
+                preempt_current_and_run_next();
+            }
         }
         Trap::Interrupt(Interrupt::SupervisorExternal) => {
-            crate::board::irq_handler();
+            crate::boards::irq_handler();
+        }
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            // a stop is checked below
+            handle_ipi();
         }
         _ => {
             panic!(
@@ -111,9 +178,22 @@ pub fn trap_handler() -> ! {
             );
         }
     }
+    // its process has exited meanwhile, nothing of the user is left
+    if current_task_left_behind() {
+        exit_current_and_run_next(0);
+    }
+    // the hart is going offline, the task runs on another one
+    if stop_requested() {
+        suspend_current_and_run_next();
+    }
+    if crate::sysrq::has_deferred() {
+        // they may wait for the disk
+        enable_supervisor_interrupt();
+        crate::sysrq::run_deferred();
+    }
     // check signals
-    if let Some((errno, msg)) = check_signals_of_current() {
-        println!("[kernel] {}", msg);
+    if let Some((errno, msg)) = handle_signals_of_current(restart) {
+        warn!("[kernel] {}", msg);
         exit_current_and_run_next(errno);
     }
     trap_return();
@@ -123,6 +203,8 @@ pub fn trap_handler() -> ! {
 pub fn trap_return() -> ! {
     disable_supervisor_interrupt();
     set_user_trap_entry();
+    // the task may run on another hart than last time
+    current_trap_cx().hart_id = hart_id();
     let trap_cx_user_va = current_trap_cx_user_va();
     let user_satp = current_user_token();
     extern "C" {
@@ -144,18 +226,23 @@ pub fn trap_return() -> ! {
 }
 
 #[no_mangle]
-pub fn trap_from_kernel(_trap_cx: &TrapContext) {
+pub fn trap_from_kernel(trap_cx: &TrapContext) {
     let scause = scause::read();
     let stval = stval::read();
     match scause.cause() {
         Trap::Interrupt(Interrupt::SupervisorExternal) => {
-            crate::board::irq_handler();
+            crate::boards::irq_handler();
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
-            set_next_trigger();
-            check_timer();
+            handle_timer_interrupt();
             // do not schedule now
         }
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            // a stop waits until the task is back from the kernel
+            handle_ipi();
+        }
+        Trap::Exception(Exception::Breakpoint)
+            if handle_kernel_hit(stval, trap_cx.sepc, trap_cx.x[1]) => {}
         _ => {
             panic!(
                 "Unsupported trap from kernel: {:?}, stval = {:#x}!",
@@ -0,0 +1,377 @@
+//! Hardware watchpoints with the trigger module of RISC-V, which the
+//! supervisor mode programs through the debug triggers extension of the SBI
+//! since the trigger CSRs are for the machine mode only.
+//!
+//! A watchpoint of the kernel matches accesses in the supervisor mode and
+//! stays installed until it is removed. They are set through
+//! /proc/sys/debug/watchpoints by root. Those of a process are set with
+//! `sys_ptrace`, match the user mode, and are installed while its threads
+//! run, so they are switched by the scheduler.
+//!
+//! The triggers belong to a hart, so each hart keeps its own: the kernel
+//! watchpoints are installed on the hart setting them, and on the others as
+//! they switch to a process next, and the watchpoints of the process running
+//! on a hart are installed there.
+//!
+//! A trigger fires before the access with a breakpoint exception. A kernel
+//! watchpoint hit is reported and then disabled so the access goes on, a
+//! process hitting one of its own is killed with SIGTRAP.
+
+use crate::hart::{hart_id, MAX_HARTS};
+use crate::ksyms;
+use crate::sbi::{
+    debug_disable_triggers, debug_install_triggers, debug_num_triggers, debug_set_shmem,
+    debug_triggers_available, debug_uninstall_triggers,
+};
+use crate::sync::UPIntrFreeCell;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use lazy_static::*;
+
+/// The watchpoints a process may set.
+pub const MAX_WATCHPOINTS: usize = 4;
+/// The largest range a watchpoint covers.
+const MAX_WATCH_LEN: usize = 1 << 12;
+
+/// the mcontrol type of tdata1, on RV64
+const MCONTROL_TYPE: usize = 2 << 60;
+/// match a naturally aligned power-of-two range encoded in tdata2
+const MCONTROL_MATCH_NAPOT: usize = 1 << 7;
+const MCONTROL_S: usize = 1 << 4;
+const MCONTROL_U: usize = 1 << 3;
+
+bitflags! {
+    pub struct WatchKind: u32 {
+        const LOAD = 1 << 0;
+        const STORE = 1 << 1;
+        const EXECUTE = 1 << 2;
+    }
+}
+
+/// The same layout as the one in the user library, `len` 0 for none.
+#[repr(C)]
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addr: usize,
+    pub len: usize,
+    pub kind: u32,
+}
+
+impl Watchpoint {
+    pub fn new(addr: usize, len: usize, kind: WatchKind) -> Self {
+        Self {
+            addr,
+            len,
+            kind: kind.bits(),
+        }
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.len != 0
+    }
+
+    /// A naturally aligned power-of-two range up to `MAX_WATCH_LEN`, with
+    /// known kinds of accesses.
+    pub fn is_valid(&self) -> bool {
+        self.len.is_power_of_two()
+            && self.len <= MAX_WATCH_LEN
+            && self.addr % self.len == 0
+            && WatchKind::from_bits(self.kind).map_or(false, |kind| !kind.is_empty())
+    }
+
+    pub fn contains(&self, addr: usize) -> bool {
+        (self.addr..self.addr + self.len).contains(&addr)
+    }
+
+    fn tdata(&self, user: bool) -> (usize, usize) {
+        // the kinds are the execute, store and load bits of mcontrol
+        let mut tdata1 = MCONTROL_TYPE | self.kind as usize;
+        tdata1 |= if user { MCONTROL_U } else { MCONTROL_S };
+        let mut tdata2 = self.addr;
+        if self.len > 1 {
+            tdata1 |= MCONTROL_MATCH_NAPOT;
+            tdata2 |= self.len / 2 - 1;
+        }
+        (tdata1, tdata2)
+    }
+}
+
+/// An entry of the memory shared with the SBI: tstate, tdata1, tdata2 and
+/// tdata3 of a trigger, or the index of a trigger installed in the first.
+#[repr(C, align(8))]
+struct ShmemEntry([usize; 4]);
+
+const SHMEM_ENTRY: ShmemEntry = ShmemEntry([0; 4]);
+/// one for each hart
+static mut SHMEM: [ShmemEntry; MAX_HARTS] = [SHMEM_ENTRY; MAX_HARTS];
+
+/// The kernel watchpoints of all the harts.
+struct KernelWatchpoints {
+    /// changed with the watchpoints, so the harts install them again
+    generation: usize,
+    next_handle: usize,
+    /// the watchpoints by their handles
+    watchpoints: Vec<(usize, Watchpoint)>,
+}
+
+struct Triggers {
+    /// whether the SBI can install triggers, None before probed
+    available: Option<bool>,
+    /// the generation of the kernel watchpoints installed
+    kernel_generation: usize,
+    /// the kernel watchpoints with their trigger indices
+    kernel: Vec<(Watchpoint, usize)>,
+    /// the process whose watchpoints are installed, with trigger indices
+    user_pid: Option<usize>,
+    user: Vec<(Watchpoint, usize)>,
+}
+
+impl Triggers {
+    fn available(&mut self) -> bool {
+        *self.available.get_or_insert_with(|| {
+            debug_triggers_available()
+                && debug_num_triggers(MCONTROL_TYPE) > 0
+                && debug_set_shmem(unsafe { &SHMEM[hart_id()] as *const ShmemEntry as usize })
+        })
+    }
+
+    fn install(&mut self, watchpoint: &Watchpoint, user: bool) -> Option<usize> {
+        if !self.available() {
+            return None;
+        }
+        let (tdata1, tdata2) = watchpoint.tdata(user);
+        unsafe {
+            let shmem = &mut SHMEM[hart_id()];
+            shmem.0 = [0, tdata1, tdata2, 0];
+            debug_install_triggers(1).then(|| shmem.0[0])
+        }
+    }
+
+    /// Install the kernel watchpoints again if they are changed since.
+    fn sync_kernel(&mut self) {
+        let kernel = KERNEL_WATCHPOINTS.exclusive_access();
+        if self.kernel_generation == kernel.generation {
+            return;
+        }
+        for (_, index) in self.kernel.drain(..) {
+            debug_uninstall_triggers(index, 1);
+        }
+        self.kernel_generation = kernel.generation;
+        for (_, watchpoint) in kernel.watchpoints.iter() {
+            if let Some(index) = self.install(watchpoint, false) {
+                self.kernel.push((*watchpoint, index));
+            }
+        }
+    }
+
+    fn uninstall_user(&mut self) {
+        for (_, index) in self.user.drain(..) {
+            debug_uninstall_triggers(index, 1);
+        }
+    }
+
+    fn install_user(&mut self, pid: usize, watchpoints: &[Watchpoint]) {
+        self.uninstall_user();
+        self.user_pid = Some(pid);
+        for watchpoint in watchpoints.iter().filter(|w| w.is_set()) {
+            if let Some(index) = self.install(watchpoint, true) {
+                self.user.push((*watchpoint, index));
+            }
+        }
+    }
+}
+
+lazy_static! {
+    /// One for each hart.
+    static ref TRIGGERS: Vec<UPIntrFreeCell<Triggers>> = (0..MAX_HARTS)
+        .map(|_| unsafe {
+            UPIntrFreeCell::new(Triggers {
+                available: None,
+                kernel_generation: 0,
+                kernel: Vec::new(),
+                user_pid: None,
+                user: Vec::new(),
+            })
+        })
+        .collect();
+    static ref KERNEL_WATCHPOINTS: UPIntrFreeCell<KernelWatchpoints> = unsafe {
+        UPIntrFreeCell::new(KernelWatchpoints {
+            generation: 0,
+            next_handle: 0,
+            watchpoints: Vec::new(),
+        })
+    };
+}
+
+/// The triggers of the current hart.
+fn triggers() -> &'static UPIntrFreeCell<Triggers> {
+    &TRIGGERS[hart_id()]
+}
+
+/// Watch a range of the kernel, return a handle to remove it, or None if
+/// there is no trigger for it on the current hart.
+pub fn watch_kernel(watchpoint: Watchpoint) -> Option<usize> {
+    if !watchpoint.is_valid() {
+        return None;
+    }
+    let mut triggers = triggers().exclusive_access();
+    let handle = KERNEL_WATCHPOINTS.exclusive_session(|kernel| {
+        let handle = kernel.next_handle;
+        kernel.next_handle += 1;
+        kernel.generation += 1;
+        kernel.watchpoints.push((handle, watchpoint));
+        handle
+    });
+    triggers.sync_kernel();
+    if triggers
+        .kernel
+        .iter()
+        .any(|(installed, _)| *installed == watchpoint)
+    {
+        return Some(handle);
+    }
+    drop(triggers);
+    unwatch_kernel(handle);
+    None
+}
+
+/// Remove a kernel watchpoint by the handle from `watch_kernel`.
+pub fn unwatch_kernel(handle: usize) -> bool {
+    let mut triggers = triggers().exclusive_access();
+    let removed = KERNEL_WATCHPOINTS.exclusive_session(|kernel| {
+        let position = kernel
+            .watchpoints
+            .iter()
+            .position(|&(other, _)| other == handle)?;
+        kernel.watchpoints.remove(position);
+        kernel.generation += 1;
+        Some(())
+    });
+    triggers.sync_kernel();
+    removed.is_some()
+}
+
+/// The text of /proc/sys/debug/watchpoints, a line for each kernel
+/// watchpoint: the handle, the address, the length and the kinds.
+pub fn kernel_watchpoints_text() -> String {
+    let mut text = String::new();
+    for (handle, watchpoint) in KERNEL_WATCHPOINTS.exclusive_access().watchpoints.iter() {
+        let kind = WatchKind::from_bits_truncate(watchpoint.kind);
+        let flag = |bit: WatchKind, c: char| if kind.contains(bit) { c } else { '-' };
+        writeln!(
+            text,
+            "{} {:#x} {} {}{}{}",
+            handle,
+            watchpoint.addr,
+            watchpoint.len,
+            flag(WatchKind::LOAD, 'r'),
+            flag(WatchKind::STORE, 'w'),
+            flag(WatchKind::EXECUTE, 'x'),
+        )
+        .unwrap();
+    }
+    text
+}
+
+/// Written to /proc/sys/debug/watchpoints: `addr len kinds` watches the
+/// range for the kinds in `rwx`, the address in hex, and `-handle` removes
+/// a watchpoint. False if it is not valid or fails.
+pub fn store_kernel_watchpoint(text: &str) -> bool {
+    if let Some(handle) = text.strip_prefix('-') {
+        return handle.parse().map_or(false, unwatch_kernel);
+    }
+    let mut fields = text.split_whitespace();
+    let (addr, len, kinds) = match (fields.next(), fields.next(), fields.next(), fields.next()) {
+        (Some(addr), Some(len), Some(kinds), None) => (addr, len, kinds),
+        _ => return false,
+    };
+    let addr = match usize::from_str_radix(addr.trim_start_matches("0x"), 16) {
+        Ok(addr) => addr,
+        Err(_) => return false,
+    };
+    let len = match len.parse() {
+        Ok(len) => len,
+        Err(_) => return false,
+    };
+    let mut kind = WatchKind::empty();
+    for c in kinds.chars() {
+        kind |= match c {
+            'r' => WatchKind::LOAD,
+            'w' => WatchKind::STORE,
+            'x' => WatchKind::EXECUTE,
+            _ => return false,
+        };
+    }
+    watch_kernel(Watchpoint::new(addr, len, kind)).is_some()
+}
+
+/// Whether the triggers can be used at all.
+pub fn watchpoints_available() -> bool {
+    triggers().exclusive_access().available()
+}
+
+/// Install the watchpoints of the process about to run, unless they are
+/// installed already, and the kernel watchpoints changed since.
+pub fn switch_watchpoints(pid: usize, watchpoints: &[Watchpoint]) {
+    let mut triggers = triggers().exclusive_access();
+    triggers.sync_kernel();
+    if triggers.user_pid == Some(pid) {
+        return;
+    }
+    if triggers.user.is_empty() && watchpoints.iter().all(|w| !w.is_set()) {
+        triggers.user_pid = Some(pid);
+        return;
+    }
+    triggers.install_user(pid, watchpoints);
+}
+
+/// The watchpoints of a process are changed, reinstall them if it is the
+/// one running.
+pub fn reload_watchpoints(pid: usize, watchpoints: &[Watchpoint]) {
+    let mut triggers = triggers().exclusive_access();
+    if triggers.user_pid == Some(pid) {
+        triggers.install_user(pid, watchpoints);
+    }
+}
+
+/// A breakpoint exception from the user mode, return the watchpoint of the
+/// running process hit at `addr`, None for an `ebreak`.
+pub fn user_watchpoint_hit(addr: usize) -> Option<Watchpoint> {
+    let triggers = triggers().exclusive_access();
+    triggers
+        .user
+        .iter()
+        .map(|&(watchpoint, _)| watchpoint)
+        .find(|watchpoint| watchpoint.contains(addr))
+}
+
+/// A breakpoint exception from the kernel at `pc`. Report the watchpoint hit
+/// and disable it, return false if it is not of a watchpoint.
+pub fn handle_kernel_hit(addr: usize, pc: usize, ra: usize) -> bool {
+    let triggers = triggers().exclusive_access();
+    let (watchpoint, index) = match triggers
+        .kernel
+        .iter()
+        .find(|(watchpoint, _)| watchpoint.contains(addr))
+    {
+        Some(&hit) => hit,
+        None => return false,
+    };
+    drop(triggers);
+    println!(
+        "[kernel] Watchpoint [{:#x}, {:#x}) hit at {:#x}",
+        watchpoint.addr,
+        watchpoint.addr + watchpoint.len,
+        addr
+    );
+    for (what, addr) in [("pc", pc), ("ra", ra)] {
+        match ksyms::lookup(addr) {
+            Some((name, offset)) => println!("  {}={:#x} {}+{:#x}", what, addr, name, offset),
+            None => println!("  {}={:#x}", what, addr),
+        }
+    }
+    // one shot, or the access would trap again
+    debug_disable_triggers(index, 1);
+    true
+}
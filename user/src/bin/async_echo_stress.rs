@@ -0,0 +1,142 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use user_lib::runtime::{self, AsyncFd};
+use user_lib::{dup, fcntl, get_time_ns, pipe2, OpenFlags, F_SETPIPE_SZ};
+
+const STDOUT: usize = 1;
+/// The user heap is small, each pair takes two futures and four fds.
+const DEFAULT_PAIRS: usize = 32;
+const DEFAULT_MESSAGES: usize = 32;
+/// Smaller than the longest message, so the writers wait for room.
+const PIPE_SIZE: usize = 32;
+/// At most what fits in the two pipes and the buffer of the echo, as a
+/// message is written all before it is read back.
+const MAX_MESSAGE_LEN: usize = 48;
+
+fn async_pipe() -> (AsyncFd, AsyncFd) {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe2(&mut pipe_fd, OpenFlags::NONBLOCK), 0);
+    assert_eq!(
+        fcntl(pipe_fd[1], F_SETPIPE_SZ, PIPE_SIZE),
+        PIPE_SIZE as isize
+    );
+    (AsyncFd::new(pipe_fd[0]), AsyncFd::new(pipe_fd[1]))
+}
+
+/// The `seq`th message of the client `pair`, of various lengths.
+fn message(pair: usize, seq: usize) -> Vec<u8> {
+    let len = (pair * 7 + seq * 13) % MAX_MESSAGE_LEN + 1;
+    (0..len).map(|i| (pair + seq + i) as u8).collect()
+}
+
+/// Write the requests back as the replies until the requests end.
+async fn echo(requests: AsyncFd, replies: AsyncFd) {
+    let mut buf = [0u8; 16];
+    loop {
+        let len = requests.read(&mut buf).await;
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        let len = len as usize;
+        assert_eq!(replies.write_all(&buf[..len]).await, len as isize);
+    }
+}
+
+/// Send the messages and check the replies, then report on the console,
+/// which all the clients share. Writing it never waits, so it puts no load
+/// on the UART.
+async fn client(
+    pair: usize,
+    messages: usize,
+    requests: AsyncFd,
+    replies: AsyncFd,
+    console: Rc<AsyncFd>,
+    echoed: Rc<Cell<usize>>,
+) {
+    let mut buf = [0u8; MAX_MESSAGE_LEN];
+    for seq in 0..messages {
+        let sent = message(pair, seq);
+        assert_eq!(requests.write_all(&sent).await, sent.len() as isize);
+        let mut received = 0;
+        while received < sent.len() {
+            let len = replies.read(&mut buf[received..sent.len()]).await;
+            assert!(len > 0, "the reply of client {} ends early", pair);
+            received += len as usize;
+        }
+        assert_eq!(&buf[..received], &sent[..]);
+        echoed.set(echoed.get() + received);
+    }
+    // the echo sees the end of the requests
+    drop(requests);
+    let line = format!("client {} echoed {} messages\n", pair, messages);
+    assert_eq!(
+        console.write_all(line.as_bytes()).await,
+        line.len() as isize
+    );
+}
+
+/// `async_echo_stress [pairs] [messages]`: run `pairs` of an echo and a
+/// client, sending `messages` through small pipes to it, all of them at
+/// once on `user_lib::runtime`. Only the pipes are under load, the console
+/// just gets a line from each client. It checks that no future is left
+/// waiting forever, and the time taken compares the costs of the waits.
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let mut args = [DEFAULT_PAIRS, DEFAULT_MESSAGES];
+    for (arg, value) in argv[1..argc].iter().zip(args.iter_mut()) {
+        match arg.parse() {
+            Ok(parsed) if parsed > 0 => *value = parsed,
+            _ => {
+                println!("Usage: async_echo_stress [pairs] [messages]");
+                return -1;
+            }
+        }
+    }
+    let [pairs, messages] = args;
+
+    let console = dup(STDOUT);
+    assert!(console >= 0);
+    let console = Rc::new(AsyncFd::new(console as usize));
+    let echoed = Rc::new(Cell::new(0usize));
+    for pair in 0..pairs {
+        let (request_reader, request_writer) = async_pipe();
+        let (reply_reader, reply_writer) = async_pipe();
+        runtime::spawn(echo(request_reader, reply_writer));
+        runtime::spawn(client(
+            pair,
+            messages,
+            request_writer,
+            reply_reader,
+            console.clone(),
+            echoed.clone(),
+        ));
+    }
+    let expected: usize = (0..pairs)
+        .flat_map(|pair| (0..messages).map(move |seq| message(pair, seq).len()))
+        .sum();
+
+    let start = get_time_ns() as usize;
+    runtime::run();
+    let time_us = ((get_time_ns() as usize - start) / 1000).max(1);
+    assert_eq!(echoed.get(), expected);
+    println!(
+        "async_echo_stress: {} futures, {} messages, {} bytes in {} us, {} messages/s",
+        pairs * 2,
+        pairs * messages,
+        expected,
+        time_us,
+        pairs * messages * 1_000_000 / time_us
+    );
+    println!("async_echo_stress passed!");
+    0
+}
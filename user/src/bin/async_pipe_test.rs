@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use user_lib::runtime::{self, AsyncFd};
+use user_lib::{fcntl, pipe2, write, OpenFlags, F_SETPIPE_SZ};
+
+/// The futures passing a token around a ring of pipes.
+const RING: usize = 16;
+const ROUNDS: usize = 20;
+/// Sent through a pipe smaller than it, so the writer waits too.
+const STREAM_LEN: usize = 8192;
+const PIPE_SIZE: usize = 256;
+
+fn async_pipe() -> (AsyncFd, AsyncFd) {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe2(&mut pipe_fd, OpenFlags::NONBLOCK), 0);
+    (AsyncFd::new(pipe_fd[0]), AsyncFd::new(pipe_fd[1]))
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // the token goes from each future to the next one through a pipe
+    let passed = Rc::new(Cell::new(0usize));
+    let (mut readers, mut writers): (Vec<AsyncFd>, Vec<AsyncFd>) =
+        (0..RING).map(|_| async_pipe()).unzip();
+    // the first one reads it first
+    assert_eq!(write(writers[0].fd(), &[0]), 1);
+    writers.rotate_left(1);
+    for (index, (reader, writer)) in readers.drain(..).zip(writers.drain(..)).enumerate() {
+        let passed = passed.clone();
+        runtime::spawn(async move {
+            let mut token = [0u8; 1];
+            for round in 0..ROUNDS {
+                assert_eq!(reader.read(&mut token).await, 1);
+                assert_eq!(token[0] as usize, (round * RING + index) % 256);
+                passed.set(passed.get() + 1);
+                token[0] = token[0].wrapping_add(1);
+                assert_eq!(writer.write_all(&token).await, 1);
+            }
+        });
+    }
+
+    // a stream through a small pipe, the reader spawned first waits
+    let (reader, writer) = async_pipe();
+    assert_eq!(
+        fcntl(writer.fd(), F_SETPIPE_SZ, PIPE_SIZE),
+        PIPE_SIZE as isize
+    );
+    let received = Rc::new(Cell::new(0usize));
+    let counted = received.clone();
+    runtime::spawn(async move {
+        let mut buf = [0u8; 100];
+        loop {
+            let len = reader.read(&mut buf).await;
+            assert!(len >= 0);
+            if len == 0 {
+                break;
+            }
+            for &byte in buf[..len as usize].iter() {
+                assert_eq!(byte as usize, counted.get() % 251);
+                counted.set(counted.get() + 1);
+            }
+        }
+    });
+    runtime::spawn(async move {
+        let data: Vec<u8> = (0..STREAM_LEN).map(|i| (i % 251) as u8).collect();
+        assert_eq!(writer.write_all(&data).await, STREAM_LEN as isize);
+        // the reader sees the end once it is dropped
+    });
+
+    runtime::run();
+    assert_eq!(passed.get(), RING * ROUNDS);
+    assert_eq!(received.get(), STREAM_LEN);
+    println!("async_pipe_test passed!");
+    0
+}
@@ -0,0 +1,64 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, open, pipe2, read, spawn, waitpid, write, OpenFlags, SpawnAction, SysError};
+
+const SCRIPT: &[u8] = b"#!/cat\nrun by cat\n";
+const OUTPUT: &[u8] =
+    b"argc = 2\nargv[0] = /cat\nargv[1] = binfmt_test_script\n#!/cat\nrun by cat\n";
+
+fn create(path: &str, data: &[u8]) {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, data), data.len() as isize);
+    close(fd as usize);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    create("binfmt_test_script\0", SCRIPT);
+    // run as the interpreter with the path of the script
+    let args = [b"binfmt_test_script\0".as_ptr(), core::ptr::null()];
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe2(&mut pipe_fd, OpenFlags::CLOEXEC), 0);
+    let pid = spawn(
+        "binfmt_test_script\0",
+        &args,
+        &[SpawnAction::dup2(pipe_fd[1], 1)],
+    );
+    assert!(pid > 0);
+    close(pipe_fd[1]);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    let mut buf = [0u8; 128];
+    let mut len = 0;
+    loop {
+        let size = read(pipe_fd[0], &mut buf[len..]);
+        if size <= 0 {
+            break;
+        }
+        len += size as usize;
+    }
+    close(pipe_fd[0]);
+    assert_eq!(&buf[..len], OUTPUT);
+
+    // neither a program nor a script
+    create("binfmt_test_data\0", b"plain text\n");
+    let args = [b"binfmt_test_data\0".as_ptr(), core::ptr::null()];
+    assert_eq!(
+        spawn("binfmt_test_data\0", &args, &[]),
+        SysError::NotExecutable.code()
+    );
+    // nor a script without the end of the line
+    create("binfmt_test_data\0", b"#!/cat");
+    assert_eq!(
+        spawn("binfmt_test_data\0", &args, &[]),
+        SysError::NotExecutable.code()
+    );
+    println!("binfmt_test passed!");
+    0
+}
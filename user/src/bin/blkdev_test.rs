@@ -0,0 +1,92 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    blkgetsize, close, ioctl, lseek, open, read, run, write, OpenFlags, SysError, BLKGETSIZE64,
+    SEEK_END, SEEK_SET,
+};
+
+const BLOCK_SZ: usize = 512;
+/// the first block and the number of blocks of the partitions
+const PARTITIONS: [(u32, u32); 2] = [(64, 1024), (2048, 2048)];
+
+/// An MBR with the primary partitions in `PARTITIONS`.
+fn mbr() -> [u8; BLOCK_SZ] {
+    let mut mbr = [0u8; BLOCK_SZ];
+    for (i, (start, blocks)) in PARTITIONS.iter().enumerate() {
+        let entry = &mut mbr[446 + i * 16..][..16];
+        // Linux
+        entry[4] = 0x83;
+        entry[8..12].copy_from_slice(&start.to_le_bytes());
+        entry[12..16].copy_from_slice(&blocks.to_le_bytes());
+    }
+    mbr[510] = 0x55;
+    mbr[511] = 0xaa;
+    mbr
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let disk = open("/dev/ram0\0", OpenFlags::RDWR);
+    assert!(disk > 0);
+    let disk = disk as usize;
+    let blocks = blkgetsize(disk);
+    assert!(blocks >= 4096);
+    let mut size: u64 = 0;
+    assert_eq!(ioctl(disk, BLKGETSIZE64, &mut size as *mut _ as usize), 0);
+    assert_eq!(size, blocks as u64 * BLOCK_SZ as u64);
+    assert_eq!(lseek(disk, 0, SEEK_END), size as isize);
+    // no partitions before the table is written
+    assert_eq!(
+        open("/dev/ram0p1\0", OpenFlags::RDONLY),
+        SysError::NoEntry.code()
+    );
+    lseek(disk, 0, SEEK_SET);
+    assert_eq!(write(disk, &mbr()), BLOCK_SZ as isize);
+
+    let part = open("/dev/ram0p1\0", OpenFlags::RDWR);
+    assert!(part > 0);
+    let part = part as usize;
+    assert_eq!(blkgetsize(part), PARTITIONS[0].1 as isize);
+    assert_eq!(write(part, b"in the partition"), 16);
+    // the partition ends in the middle of the write
+    let end = PARTITIONS[0].1 as isize * BLOCK_SZ as isize;
+    assert_eq!(lseek(part, end - 1, SEEK_SET), end - 1);
+    assert_eq!(write(part, b"xy"), 1);
+    let mut buf = [0u8; 16];
+    assert_eq!(read(part, &mut buf), 0);
+    close(part);
+    // seen through the disk
+    let offset = PARTITIONS[0].0 as isize * BLOCK_SZ as isize;
+    lseek(disk, offset, SEEK_SET);
+    assert_eq!(read(disk, &mut buf), 16);
+    assert_eq!(&buf, b"in the partition");
+    close(disk);
+
+    assert_eq!(
+        open("/dev/ram0p3\0", OpenFlags::RDONLY),
+        SysError::NoEntry.code()
+    );
+    assert_eq!(
+        open("/dev/ram01\0", OpenFlags::RDONLY),
+        SysError::NoEntry.code()
+    );
+    assert_eq!(
+        open("/dev/vda1\0", OpenFlags::RDONLY),
+        SysError::NoEntry.code()
+    );
+    let vda = open("/dev/vda\0", OpenFlags::RDONLY);
+    assert!(vda > 0);
+    assert!(blkgetsize(vda as usize) > 0);
+    close(vda as usize);
+
+    assert_eq!(run(&["mkfs_easyfs\0", "/dev/ram0p2\0"]), 0);
+    assert_eq!(run(&["fsck_easyfs\0", "/dev/ram0p2\0"]), 0);
+    // the first partition has no file system
+    assert_eq!(run(&["fsck_easyfs\0", "/dev/ram0p1\0"]), 1);
+    println!("blkdev_test passed!");
+    0
+}
@@ -0,0 +1,46 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+// cgexec <shares> <quota_ms> <app> [args...]: run the app in a new cpu group,
+// e.g. `cgexec 256 20 matrix` runs matrix with at most 20ms every 100ms.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{cgroup_attach, cgroup_create, exec, getpid};
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc < 4 {
+        println!("Usage: cgexec <shares> <quota_ms> <app> [args...]");
+        return -1;
+    }
+    let (shares, quota_ms) = match (argv[1].parse::<usize>(), argv[2].parse::<usize>()) {
+        (Ok(shares), Ok(quota_ms)) => (shares, quota_ms),
+        _ => {
+            println!("cgexec: invalid shares or quota");
+            return -1;
+        }
+    };
+    let group_id = cgroup_create(shares, quota_ms);
+    if group_id < 0 || cgroup_attach(getpid() as usize, group_id as usize) < 0 {
+        println!("cgexec: cannot create the cpu group");
+        return -1;
+    }
+    let args: Vec<String> = argv[3..argc]
+        .iter()
+        .map(|arg| {
+            let mut arg = String::from(*arg);
+            arg.push('\0');
+            arg
+        })
+        .collect();
+    let mut args_addr: Vec<*const u8> = args.iter().map(|arg| arg.as_ptr()).collect();
+    args_addr.push(core::ptr::null::<u8>());
+    exec(args[0].as_str(), args_addr.as_slice());
+    println!("cgexec: cannot run {}", argv[3]);
+    -1
+}
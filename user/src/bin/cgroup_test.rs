@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{cgroup_attach, cgroup_create, cgroup_usage, exit, fork, get_time, getpid, waitpid};
+
+const BUSY_MS: isize = 300;
+// the quota is checked on each timer tick, so a group may overrun it by a tick
+const QUOTA_MS: usize = 10;
+
+fn busy_loop(ms: isize) {
+    let start = get_time();
+    while get_time() - start < ms {}
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert!(cgroup_create(0, 0) < 0);
+    assert!(cgroup_attach(getpid() as usize, 10000) < 0);
+    let group_id = cgroup_create(1024, QUOTA_MS);
+    assert!(group_id > 0);
+    let group_id = group_id as usize;
+    assert_eq!(cgroup_usage(group_id), 0);
+
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(cgroup_attach(getpid() as usize, group_id), 0);
+        busy_loop(BUSY_MS);
+        exit(0);
+    }
+    // compete with the child for the cpu
+    busy_loop(BUSY_MS);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    let used_ms = cgroup_usage(group_id) / 1000;
+    println!("group used {}ms in {}ms", used_ms, BUSY_MS);
+    assert!(used_ms > 0);
+    assert!(used_ms < BUSY_MS / 2);
+    println!("cgroup_test passed!");
+    0
+}
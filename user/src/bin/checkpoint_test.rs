@@ -0,0 +1,122 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::{
+    checkpoint, close, exit, fork, open, read, restore, waitpid, write, OpenFlags, SysError,
+};
+
+const IMAGE: &str = "checkpoint_test.img\0";
+const DATA: &str = "checkpoint_test.dat\0";
+const CORRUPTED: &str = "checkpoint_test_corrupted.img\0";
+/// After the magic, the registers and sepc.
+const USTACK_BASE_POS: usize = 8 + 33 * 8;
+const RESTORED_CODE: i32 = 17;
+
+static mut COUNTER: usize = 0;
+
+fn read_image() -> Vec<u8> {
+    let fd = open(IMAGE, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut image = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        image.extend_from_slice(&buf[..len as usize]);
+    }
+    close(fd as usize);
+    image
+}
+
+fn word(image: &[u8], pos: usize) -> usize {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&image[pos..pos + 8]);
+    usize::from_le_bytes(bytes)
+}
+
+/// Restore the image with the word at `pos` replaced by `value`.
+fn restore_corrupted(image: &[u8], pos: usize, value: usize) -> isize {
+    let mut corrupted = Vec::from(image);
+    corrupted[pos..pos + 8].copy_from_slice(&value.to_le_bytes());
+    let fd = open(
+        CORRUPTED,
+        OpenFlags::CREATE | OpenFlags::TRUNC | OpenFlags::WRONLY,
+    );
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, &corrupted), corrupted.len() as isize);
+    close(fd as usize);
+    restore(CORRUPTED)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open(DATA, OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, b"hello checkpoint"), 16);
+    let on_stack = [0x5au8; 64];
+    unsafe {
+        COUNTER = 42;
+    }
+    match checkpoint(IMAGE) {
+        0 => {}
+        1 => {
+            // restored in the child: memory and the fd offset are saved
+            let ok = unsafe { COUNTER == 42 } && on_stack.iter().all(|byte| *byte == 0x5a);
+            let mut buf = [0u8; 16];
+            assert_eq!(read(fd, &mut buf), 0);
+            exit(if ok { RESTORED_CODE } else { -1 });
+        }
+        _ => panic!("checkpoint failed"),
+    }
+    unsafe {
+        COUNTER = 0;
+    }
+    let pid = fork();
+    if pid == 0 {
+        restore(IMAGE);
+        panic!("restore failed");
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, RESTORED_CODE);
+    assert_eq!(unsafe { COUNTER }, 0);
+    close(fd);
+    assert_eq!(
+        restore("checkpoint_test_no_image\0"),
+        SysError::NoEntry.code()
+    );
+
+    // a malformed image is refused, and the process goes on unchanged
+    let image = read_image();
+    assert_eq!(
+        restore_corrupted(&image, USTACK_BASE_POS, 0),
+        SysError::Invalid.code()
+    );
+    // then the root, the cwd, no Linux state and the number of areas
+    let root_len = word(&image, USTACK_BASE_POS + 8);
+    let cwd_pos = USTACK_BASE_POS + 16 + root_len;
+    let areas_pos = cwd_pos + 8 + word(&image, cwd_pos) + 8;
+    assert!(word(&image, areas_pos) > 0);
+    let start_vpn = word(&image, areas_pos + 8);
+    // an area beyond the user space, then an empty one
+    assert_eq!(
+        restore_corrupted(&image, areas_pos + 16, 1 << 40),
+        SysError::Invalid.code()
+    );
+    assert_eq!(
+        restore_corrupted(&image, areas_pos + 16, start_vpn),
+        SysError::Invalid.code()
+    );
+    assert_eq!(unsafe { COUNTER }, 0);
+    println!("checkpoint_test passed!");
+    0
+}
@@ -0,0 +1,95 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use user_lib::{
+    chdir, chroot, close, exec, exit, fork, getcwd, mkdir, open, read, setuid, waitpid, write,
+    OpenFlags, SysError,
+};
+
+const CONTENT: &[u8] = b"inside the jail";
+
+fn cwd() -> String {
+    let mut buf = [0u8; 64];
+    let len = getcwd(&mut buf);
+    assert!(len > 0);
+    String::from_utf8(buf[..len as usize - 1].to_vec()).unwrap()
+}
+
+fn read_file(path: &str, buf: &mut [u8]) -> isize {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return fd;
+    }
+    let len = read(fd as usize, buf);
+    close(fd as usize);
+    len
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // left by a previous run otherwise
+    mkdir("chroot_jail\0");
+    mkdir("chroot_jail/sub\0");
+    assert!(mkdir("chroot_jail\0") < 0);
+    let fd = open(
+        "chroot_jail/sub/file\0",
+        OpenFlags::CREATE | OpenFlags::WRONLY,
+    );
+    assert!(fd > 0);
+    write(fd as usize, CONTENT);
+    close(fd as usize);
+    assert!(
+        open(
+            "chroot_jail/missing/file\0",
+            OpenFlags::CREATE | OpenFlags::WRONLY
+        ) < 0
+    );
+
+    assert_eq!(cwd(), "/");
+    assert_eq!(chdir("chroot_jail/./sub\0"), 0);
+    assert_eq!(cwd(), "/chroot_jail/sub");
+    assert!(chdir("file\0") < 0);
+    let mut buf = [0u8; 32];
+    assert_eq!(read_file("file\0", &mut buf), CONTENT.len() as isize);
+    assert_eq!(chdir("../..\0"), 0);
+    assert_eq!(cwd(), "/");
+
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(chroot("chroot_jail\0"), 0);
+        assert_eq!(cwd(), "/");
+        // cannot escape by ".."
+        assert!(read_file("../initproc\0", &mut buf) < 0);
+        assert!(read_file("/initproc\0", &mut buf) < 0);
+        assert_eq!(
+            read_file("/../sub/file\0", &mut buf),
+            CONTENT.len() as isize
+        );
+        assert_eq!(chdir("/sub\0"), 0);
+        assert_eq!(cwd(), "/sub");
+        assert!(exec("initproc\0", &[core::ptr::null::<u8>()]) < 0);
+        return 0;
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    // the parent is not affected
+    assert!(read_file("initproc\0", &mut buf) > 0);
+
+    // only root may
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(setuid(1000), 0);
+        assert_eq!(chroot("chroot_jail\0"), SysError::NotPermitted.code());
+        exit(0);
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("chroot_test passed!");
+    0
+}
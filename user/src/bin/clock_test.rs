@@ -0,0 +1,37 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{clock_gettime, nanosleep, SysError, TimeSpec, CLOCK_MONOTONIC, CLOCK_REALTIME};
+
+fn now(clock_id: usize) -> usize {
+    let mut tp = TimeSpec::default();
+    assert_eq!(clock_gettime(clock_id, &mut tp), 0);
+    assert!(tp.nsec < 1_000_000_000);
+    tp.as_ns()
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut tp = TimeSpec::default();
+    assert_eq!(clock_gettime(2, &mut tp), SysError::Invalid.code());
+
+    let realtime = now(CLOCK_REALTIME);
+    let monotonic = now(CLOCK_MONOTONIC);
+    // the wall clock is at least the time since boot
+    assert!(realtime >= monotonic);
+    println!(
+        "realtime {}s, monotonic {}ms",
+        realtime / 1_000_000_000,
+        monotonic / 1_000_000
+    );
+
+    // both go on with the sleep
+    assert_eq!(nanosleep(5_000_000), 0);
+    assert!(now(CLOCK_MONOTONIC) - monotonic >= 5_000_000);
+    assert!(now(CLOCK_REALTIME) - realtime >= 5_000_000);
+    println!("clock_test passed!");
+    0
+}
@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use user_lib::{
+    close, close_range, dup, exec, exit, fcntl, fork, pipe, pipe2, read, waitpid, write, OpenFlags,
+    SysError, CLOSE_RANGE_CLOEXEC, FD_CLOEXEC, F_DUPFD, F_DUPFD_CLOEXEC, F_GETFD, F_SETFD,
+};
+
+/// Run after exec with the descriptors which should be closed and the write
+/// end of a pipe which should be kept.
+fn after_exec(closed: &str, kept: &str) -> i32 {
+    for fd in closed.split(',') {
+        assert_eq!(
+            fcntl(fd.parse().unwrap(), F_GETFD, 0),
+            SysError::BadFd.code()
+        );
+    }
+    let kept: usize = kept.parse().unwrap();
+    assert_eq!(fcntl(kept, F_GETFD, 0), 0);
+    assert_eq!(write(kept, b"k"), 1);
+    0
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc == 3 {
+        return after_exec(argv[1], argv[2]);
+    }
+    let mut cloexec_pipe = [0usize; 2];
+    assert_eq!(pipe2(&mut cloexec_pipe, OpenFlags::CLOEXEC), 0);
+    let mut kept_pipe = [0usize; 2];
+    assert_eq!(pipe(&mut kept_pipe), 0);
+    assert_eq!(fcntl(cloexec_pipe[0], F_GETFD, 0), FD_CLOEXEC as isize);
+    assert_eq!(fcntl(kept_pipe[1], F_GETFD, 0), 0);
+
+    // duplicates do not share the flags of descriptors
+    let duplicate = dup(cloexec_pipe[0]) as usize;
+    assert_eq!(fcntl(duplicate, F_GETFD, 0), 0);
+    assert_eq!(fcntl(duplicate, F_SETFD, FD_CLOEXEC), 0);
+    assert_eq!(fcntl(duplicate, F_GETFD, 0), FD_CLOEXEC as isize);
+    let high = fcntl(kept_pipe[0], F_DUPFD_CLOEXEC, 20);
+    assert_eq!(high, 20);
+    assert_eq!(fcntl(high as usize, F_GETFD, 0), FD_CLOEXEC as isize);
+    assert_eq!(fcntl(kept_pipe[0], F_DUPFD, 20), 21);
+    // mark the duplicate of the read end as well
+    assert_eq!(close_range(21, usize::MAX, CLOSE_RANGE_CLOEXEC), 0);
+    assert_eq!(fcntl(21, F_GETFD, 0), FD_CLOEXEC as isize);
+
+    let closed = format!(
+        "{},{},{},{},21\0",
+        cloexec_pipe[0], cloexec_pipe[1], duplicate, high
+    );
+    let kept = format!("{}\0", kept_pipe[1]);
+    let pid = fork();
+    if pid == 0 {
+        let args = [
+            "cloexec_test\0".as_ptr(),
+            closed.as_ptr(),
+            kept.as_ptr(),
+            core::ptr::null::<u8>(),
+        ];
+        exec("cloexec_test\0", &args);
+        exit(-1);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    let mut byte = [0u8; 1];
+    assert_eq!(read(kept_pipe[0], &mut byte), 1);
+    assert_eq!(&byte, b"k");
+
+    // all but the standard ones
+    assert_eq!(close_range(3, usize::MAX, 0), 0);
+    assert_eq!(fcntl(kept_pipe[0], F_GETFD, 0), SysError::BadFd.code());
+    assert_eq!(close(21), SysError::BadFd.code());
+    println!("cloexec_test passed!");
+    0
+}
@@ -0,0 +1,54 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, memusage, pipe, read, vm_stats, wait, write, MemUsage, VmStats};
+
+const PAGES: usize = 4;
+const PAGE_SIZE: usize = 4096;
+
+static mut DATA: [u8; PAGES * PAGE_SIZE] = [1; PAGES * PAGE_SIZE];
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut pipe_fd = [0usize; 2];
+    pipe(&mut pipe_fd);
+    let pid = fork();
+    if pid == 0 {
+        // the frames are shared until written
+        let mut usage = MemUsage::default();
+        assert_eq!(memusage(0, &mut usage), 0);
+        assert!(usage.shared >= PAGES * PAGE_SIZE);
+        unsafe {
+            DATA[0] = 2;
+            DATA[PAGE_SIZE] = 2;
+        }
+        let mut stats = VmStats::default();
+        assert_eq!(vm_stats(0, &mut stats), 0);
+        assert!(stats.cow_faults >= 2);
+        // the kernel writes a copy-on-write page as well
+        write(pipe_fd[1], &[3u8]);
+        let data = unsafe { &mut DATA[2 * PAGE_SIZE..2 * PAGE_SIZE + 1] };
+        assert_eq!(read(pipe_fd[0], data), 1);
+        unsafe {
+            assert_eq!(DATA[0], 2);
+            assert_eq!(DATA[PAGE_SIZE], 2);
+            assert_eq!(DATA[2 * PAGE_SIZE], 3);
+            assert_eq!(DATA[3 * PAGE_SIZE], 1);
+        }
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(wait(&mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    // untouched by the child
+    unsafe {
+        assert!(DATA.iter().all(|&byte| byte == 1));
+        DATA[0] = 4;
+        assert_eq!(DATA[0], 4);
+    }
+    println!("cow_test passed!");
+    0
+}
@@ -0,0 +1,90 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use easy_fs::{BlockDevice, EasyFileSystem, BLOCK_SZ};
+use user_lib::{close, open, write, FdBlockDevice, OpenFlags};
+
+/// The disk with the root file system and the crash dump after it.
+const ROOT_DISK: &str = "/dev/vda\0";
+const DUMP_MAGIC: [u8; 8] = *b"rCoreDMP";
+const STDOUT: usize = 1;
+
+/// The same layout as the one of the kernel.
+#[repr(C)]
+struct DumpHeader {
+    magic: [u8; 8],
+    len: u64,
+    time_ms: u64,
+}
+
+/// Write the text of the dump after the header in block `start` to `out`,
+/// return false if there is none.
+fn extract(device: &FdBlockDevice, start: usize, out: usize) -> bool {
+    let mut block = [0u8; BLOCK_SZ];
+    device.read_block(start, &mut block);
+    let header = unsafe { (block.as_ptr() as *const DumpHeader).read_unaligned() };
+    if header.magic != DUMP_MAGIC {
+        return false;
+    }
+    println!(
+        "[crashdump] {} bytes, crashed {} ms after boot",
+        header.len, header.time_ms
+    );
+    let end = device.num_blocks();
+    let mut len = header.len as usize;
+    for block_id in start + 1..end {
+        if len == 0 {
+            break;
+        }
+        device.read_block(block_id, &mut block);
+        let size = len.min(BLOCK_SZ);
+        write(out, &block[..size]);
+        len -= size;
+    }
+    true
+}
+
+/// `crashdump [file]`, print the crash dump saved by the kernel on panic
+/// after the file system of the root disk, or save it to `file`.
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let fd = open(ROOT_DISK, OpenFlags::RDONLY);
+    if fd < 0 {
+        println!("crashdump: cannot open {}", ROOT_DISK);
+        return -1;
+    }
+    let device = FdBlockDevice(fd as usize);
+    let start = match EasyFileSystem::total_blocks(&device) {
+        Some(start) if start < device.num_blocks() => start,
+        _ => {
+            println!("crashdump: no blocks reserved for crash dumps");
+            close(fd as usize);
+            return -1;
+        }
+    };
+    let out = if argc > 1 {
+        match open(argv[1], OpenFlags::CREATE | OpenFlags::WRONLY) {
+            out if out >= 0 => out as usize,
+            _ => {
+                println!("crashdump: cannot open {}", argv[1]);
+                close(fd as usize);
+                return -1;
+            }
+        }
+    } else {
+        STDOUT
+    };
+    let found = extract(&device, start, out);
+    if out != STDOUT {
+        close(out);
+    }
+    close(fd as usize);
+    if !found {
+        println!("crashdump: no crash dump");
+        return 1;
+    }
+    0
+}
@@ -0,0 +1,179 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use alloc::format;
+use user_lib::{close, get_time, lseek, open, read, write, OpenFlags, SEEK_CUR};
+
+/// The largest block size, the buffer is in .bss rather than the small heap.
+const MAX_BS: usize = 1 << 20;
+
+/// Aligned to pages, so that blocks can be transferred straight between it
+/// and the disk with `OpenFlags::DIRECT`.
+#[repr(C, align(4096))]
+struct Buffer([u8; MAX_BS]);
+
+static mut BUFFER: Buffer = Buffer([0; MAX_BS]);
+
+const STDIN: usize = 0;
+const STDOUT: usize = 1;
+const STDERR: usize = 2;
+
+/// A number of bytes with an optional suffix: `b` for 512, `k` or `K` for
+/// 1024 and `M` for 1024 * 1024.
+fn parse_size(arg: &str) -> Option<usize> {
+    let (number, unit) = match arg.as_bytes().last()? {
+        b'b' => (&arg[..arg.len() - 1], 512),
+        b'k' | b'K' => (&arg[..arg.len() - 1], 1 << 10),
+        b'M' => (&arg[..arg.len() - 1], 1 << 20),
+        _ => (arg, 1),
+    };
+    number.parse::<usize>().ok()?.checked_mul(unit)
+}
+
+fn error(message: &str) -> i32 {
+    write(STDERR, format!("dd: {}\n", message).as_bytes());
+    -1
+}
+
+/// Skip `blocks` blocks of `bs` bytes of a file, by reading it if it cannot
+/// be sought like a pipe.
+fn skip(fd: usize, bs: usize, blocks: usize, buffer: &mut [u8]) -> bool {
+    if blocks == 0 || lseek(fd, (bs * blocks) as isize, SEEK_CUR) >= 0 {
+        return true;
+    }
+    for _ in 0..blocks {
+        if read(fd, &mut buffer[..bs]) <= 0 {
+            return false;
+        }
+    }
+    true
+}
+
+/// `dd [if=file] [of=file] [bs=size] [count=n] [skip=n] [seek=n]
+/// [iflag=direct] [oflag=direct]`, copy `count` blocks of `bs` bytes from
+/// `if` to `of`, the standard input and output by default, after skipping
+/// `skip` blocks of the input and `seek` blocks of the output. The output is
+/// truncated unless sought. The files are opened with `OpenFlags::DIRECT` by
+/// the flags. The statistics with the throughput are printed to the
+/// standard error.
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let mut input = None;
+    let mut output = None;
+    let mut bs = 512;
+    let mut count = usize::MAX;
+    let mut skip_blocks = 0;
+    let mut seek_blocks = 0;
+    let mut in_flags = OpenFlags::RDONLY;
+    let mut out_flags = OpenFlags::WRONLY;
+    for arg in &argv[1..argc] {
+        let (key, value) = match arg.split_once('=') {
+            Some(pair) => pair,
+            None => return error(&format!("bad operand {}", arg)),
+        };
+        match (key, parse_size(value)) {
+            ("if", _) => input = Some(value),
+            ("of", _) => output = Some(value),
+            ("bs", Some(n)) => bs = n,
+            ("count", Some(n)) => count = n,
+            ("skip", Some(n)) => skip_blocks = n,
+            ("seek", Some(n)) => seek_blocks = n,
+            ("iflag", _) if value == "direct" => in_flags |= OpenFlags::DIRECT,
+            ("oflag", _) if value == "direct" => out_flags |= OpenFlags::DIRECT,
+            ("iflag" | "oflag", _) => return error(&format!("bad flag {}", value)),
+            ("bs" | "count" | "skip" | "seek", None) => {
+                return error(&format!("bad number {}", value))
+            }
+            _ => return error(&format!("unknown operand {}", key)),
+        }
+    }
+    if bs == 0 || bs > MAX_BS {
+        return error(&format!("bs must be from 1 to {}", MAX_BS));
+    }
+    let in_fd = match input {
+        Some(path) => match open(path, in_flags) {
+            fd if fd >= 0 => fd as usize,
+            _ => return error(&format!("cannot open {}", path)),
+        },
+        None => STDIN,
+    };
+    let out_fd = match output {
+        Some(path) => {
+            // keep what is before the blocks sought over, which must exist
+            let flags = if seek_blocks > 0 {
+                out_flags
+            } else {
+                OpenFlags::CREATE | out_flags
+            };
+            match open(path, flags) {
+                fd if fd >= 0 => fd as usize,
+                _ => return error(&format!("cannot open {}", path)),
+            }
+        }
+        None => STDOUT,
+    };
+    let buffer = unsafe { &mut BUFFER.0[..bs] };
+    if !skip(in_fd, bs, skip_blocks, buffer) {
+        return error("cannot skip the input");
+    }
+    if seek_blocks > 0 && lseek(out_fd, (bs * seek_blocks) as isize, SEEK_CUR) < 0 {
+        return error("cannot seek the output");
+    }
+
+    // whole and partial blocks read and written
+    let (mut full_in, mut partial_in, mut full_out, mut partial_out) = (0, 0, 0, 0);
+    let mut bytes = 0usize;
+    let start = get_time();
+    let mut result = 0;
+    while full_in + partial_in < count {
+        let len = read(in_fd, buffer);
+        if len < 0 {
+            result = error("cannot read the input");
+            break;
+        }
+        let len = len as usize;
+        if len == 0 {
+            break;
+        }
+        if len == bs {
+            full_in += 1;
+        } else {
+            partial_in += 1;
+        }
+        if write(out_fd, &buffer[..len]) != len as isize {
+            result = error("cannot write the output");
+            break;
+        }
+        if len == bs {
+            full_out += 1;
+        } else {
+            partial_out += 1;
+        }
+        bytes += len;
+    }
+    let time_ms = (get_time() - start) as usize;
+    if input.is_some() {
+        close(in_fd);
+    }
+    if output.is_some() {
+        close(out_fd);
+    }
+    write(
+        STDERR,
+        format!(
+            "{}+{} records in\n{}+{} records out\n{} bytes copied, {} ms, {} KiB/s\n",
+            full_in,
+            partial_in,
+            full_out,
+            partial_out,
+            bytes,
+            time_ms,
+            bytes * 1000 / 1024 / time_ms.max(1)
+        )
+        .as_bytes(),
+    );
+    result
+}
@@ -0,0 +1,128 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::{close, fcntl, lseek, open, read, run, write, OpenFlags, F_GETFL, SEEK_SET};
+
+const LEN: usize = 3000;
+
+/// Read `len` bytes of a file from `offset`.
+fn read_file(path: &str, offset: usize, len: usize) -> Vec<u8> {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(lseek(fd, offset as isize, SEEK_SET), offset as isize);
+    let mut data = Vec::new();
+    let mut buf = [0u8; 512];
+    while data.len() < len {
+        let size = read(fd, &mut buf[..(len - data.len()).min(512)]);
+        if size <= 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..size as usize]);
+    }
+    close(fd);
+    data
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let data: Vec<u8> = (0..LEN).map(|i| (i % 251) as u8).collect();
+    let fd = open("dd_test_in\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, &data), LEN as isize);
+    close(fd as usize);
+
+    // two whole blocks and a partial one
+    assert_eq!(
+        run(&["dd\0", "if=dd_test_in\0", "of=dd_test_out\0", "bs=1k\0"]),
+        0
+    );
+    assert_eq!(read_file("dd_test_out\0", 0, LEN + 1), data);
+    let args = [
+        "dd\0",
+        "if=dd_test_in\0",
+        "of=dd_test_out\0",
+        "bs=1000\0",
+        "count=1\0",
+        "skip=2\0",
+    ];
+    assert_eq!(run(&args), 0);
+    assert_eq!(read_file("dd_test_out\0", 0, LEN), &data[2000..]);
+    // the output before the blocks sought over is kept
+    let args = [
+        "dd\0",
+        "if=dd_test_in\0",
+        "of=dd_test_out\0",
+        "bs=500\0",
+        "count=1\0",
+        "seek=1\0",
+    ];
+    assert_eq!(run(&args), 0);
+    assert_eq!(read_file("dd_test_out\0", 0, 500), &data[2000..2500]);
+    assert_eq!(read_file("dd_test_out\0", 500, LEN), &data[..500]);
+
+    // between block devices in large blocks
+    let args = [
+        "dd\0",
+        "if=/dev/vda\0",
+        "of=/dev/ram0\0",
+        "bs=64k\0",
+        "count=4\0",
+    ];
+    assert_eq!(run(&args), 0);
+    for offset in (0..256 * 1024).step_by(16 * 1024) {
+        assert_eq!(
+            read_file("/dev/vda\0", offset, 512),
+            read_file("/dev/ram0\0", offset, 512)
+        );
+    }
+
+    // straight between the disk and the buffer, but the last partial block
+    // is written through the block cache
+    let args = [
+        "dd\0",
+        "if=dd_test_in\0",
+        "of=dd_test_direct\0",
+        "bs=1k\0",
+        "iflag=direct\0",
+        "oflag=direct\0",
+    ];
+    assert_eq!(run(&args), 0);
+    assert_eq!(read_file("dd_test_direct\0", 0, LEN + 1), data);
+    let fd = open("dd_test_direct\0", OpenFlags::RDONLY | OpenFlags::DIRECT);
+    assert!(fd > 0);
+    assert_eq!(
+        fcntl(fd as usize, F_GETFL, 0),
+        OpenFlags::DIRECT.bits() as isize
+    );
+    close(fd as usize);
+    let args = [
+        "dd\0",
+        "if=/dev/vda\0",
+        "of=/dev/ram0\0",
+        "bs=64k\0",
+        "count=4\0",
+        "seek=4\0",
+        "iflag=direct\0",
+        "oflag=direct\0",
+    ];
+    assert_eq!(run(&args), 0);
+    for offset in (0..256 * 1024).step_by(16 * 1024) {
+        assert_eq!(
+            read_file("/dev/vda\0", offset, 512),
+            read_file("/dev/ram0\0", 256 * 1024 + offset, 512)
+        );
+    }
+
+    assert_eq!(run(&["dd\0", "bs=1x\0"]), -1);
+    assert_eq!(run(&["dd\0", "iflag=sync\0"]), -1);
+    assert_eq!(run(&["dd\0", "if=dd_test_none\0"]), -1);
+    assert_eq!(run(&["dd\0", "of=/dev/vda\0"]), -1);
+    println!("dd_test passed!");
+    0
+}
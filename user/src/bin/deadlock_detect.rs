@@ -0,0 +1,80 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::vec;
+use user_lib::{
+    enable_deadlock_detect, exit, mutex_blocking_create, mutex_lock, mutex_unlock,
+    semaphore_create, semaphore_down, semaphore_up, sleep, thread_create, waittid, EDEADLK,
+};
+
+const MUTEX_A: usize = 0;
+const MUTEX_B: usize = 1;
+const SEM_ID: usize = 0;
+
+static mut DEADLOCKS: usize = 0;
+
+fn lock_both(first: usize, second: usize) {
+    assert_eq!(mutex_lock(first), 0);
+    // make sure that both threads hold their first mutex
+    sleep(20);
+    match mutex_lock(second) {
+        0 => assert_eq!(mutex_unlock(second), 0),
+        EDEADLK => unsafe { DEADLOCKS += 1 },
+        _ => panic!("unexpected result"),
+    }
+    assert_eq!(mutex_unlock(first), 0);
+}
+
+fn thread_a() -> ! {
+    lock_both(MUTEX_A, MUTEX_B);
+    exit(0)
+}
+
+fn thread_b() -> ! {
+    lock_both(MUTEX_B, MUTEX_A);
+    exit(0)
+}
+
+fn hold_sem() -> ! {
+    assert_eq!(semaphore_down(SEM_ID), 0);
+    sleep(20);
+    semaphore_up(SEM_ID);
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(enable_deadlock_detect(true), 0);
+    assert_eq!(mutex_blocking_create() as usize, MUTEX_A);
+    assert_eq!(mutex_blocking_create() as usize, MUTEX_B);
+    assert_eq!(semaphore_create(1) as usize, SEM_ID);
+
+    // lock in opposite orders, exactly one of them fails
+    let threads = vec![
+        thread_create(thread_a as usize, 0),
+        thread_create(thread_b as usize, 0),
+    ];
+    for thread in threads.iter() {
+        waittid(*thread as usize);
+    }
+    assert_eq!(unsafe { DEADLOCKS }, 1);
+
+    // waiting for a semaphore which will be released is fine
+    let thread = thread_create(hold_sem as usize, 0);
+    sleep(5);
+    assert_eq!(semaphore_down(SEM_ID), 0);
+    semaphore_up(SEM_ID);
+    waittid(thread as usize);
+
+    // downing a semaphore held by nobody else again never returns
+    assert_eq!(semaphore_down(SEM_ID), 0);
+    assert_eq!(semaphore_down(SEM_ID), EDEADLK);
+    semaphore_up(SEM_ID);
+    println!("deadlock_detect passed!");
+    0
+}
@@ -0,0 +1,109 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    close, dirents, exit, fork, getdents, open, read, setuid, waitpid, write, OpenFlags,
+};
+
+/// The names in a directory.
+fn list(path: &str) -> Vec<String> {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut buf = [0u8; 512];
+    let mut names = Vec::new();
+    loop {
+        let len = getdents(fd as usize, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        for dirent in dirents(&buf[..len as usize]) {
+            names.push(String::from(dirent.name));
+        }
+    }
+    close(fd as usize);
+    names
+}
+
+/// The bytes read from a device at once.
+fn read_device(path: &str, buf: &mut [u8]) -> isize {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let len = read(fd as usize, buf);
+    close(fd as usize);
+    len
+}
+
+fn can_open(path: &str, flags: OpenFlags) -> bool {
+    let fd = open(path, flags);
+    if fd >= 0 {
+        close(fd as usize);
+    }
+    fd >= 0
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let names = list("/dev\0");
+    for name in ["null", "zero", "random", "urandom", "tty", "vda"] {
+        assert!(names.iter().any(|other| other == name));
+    }
+
+    // null reads nothing and takes all written
+    let mut buf = [0xffu8; 64];
+    assert_eq!(read_device("/dev/null\0", &mut buf), 0);
+    let null = open("/dev/null\0", OpenFlags::WRONLY);
+    assert!(null >= 0);
+    assert_eq!(write(null as usize, b"discarded"), 9);
+    close(null as usize);
+
+    // zero reads zeros
+    assert_eq!(read_device("/dev/zero\0", &mut buf), 64);
+    assert!(buf.iter().all(|&byte| byte == 0));
+
+    // random never repeats itself
+    let mut first = [0u8; 64];
+    let mut second = [0u8; 64];
+    assert_eq!(read_device("/dev/random\0", &mut first), 64);
+    assert_eq!(read_device("/dev/urandom\0", &mut second), 64);
+    assert!(first.iter().any(|&byte| byte != 0));
+    assert_ne!(first, second);
+    let random = open("/dev/random\0", OpenFlags::WRONLY);
+    assert!(random >= 0);
+    assert_eq!(write(random as usize, b"more entropy"), 12);
+    close(random as usize);
+
+    // tty is the console
+    let tty = open("/dev/tty\0", OpenFlags::RDWR);
+    assert!(tty >= 0);
+    let message = b"written to /dev/tty\n";
+    assert_eq!(write(tty as usize, message), message.len() as isize);
+    close(tty as usize);
+
+    // nothing is created in it
+    assert_eq!(
+        open("/dev/file\0", OpenFlags::CREATE | OpenFlags::WRONLY),
+        -1
+    );
+
+    // the disks are only for root, the character devices for all
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(setuid(1000), 0);
+        assert!(!can_open("/dev/vda\0", OpenFlags::RDONLY));
+        assert!(can_open("/dev/null\0", OpenFlags::RDWR));
+        assert!(can_open("/dev/urandom\0", OpenFlags::RDONLY));
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("devfs_test passed!");
+    0
+}
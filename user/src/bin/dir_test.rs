@@ -0,0 +1,91 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    chdir, close, dirents, getdents, lseek, mkdir, open, rmdir, unlink, write, OpenFlags, SysError,
+    SEEK_SET,
+};
+
+fn create(path: &str) {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    write(fd as usize, b"to be removed");
+    close(fd as usize);
+}
+
+/// The names and kinds of the entries read with a buffer of `len` bytes.
+fn list(fd: usize, len: usize) -> Vec<(String, bool)> {
+    let mut buf = [0u8; 256];
+    let mut entries = Vec::new();
+    loop {
+        let read = getdents(fd, &mut buf[..len]);
+        assert!(read >= 0);
+        if read == 0 {
+            return entries;
+        }
+        for dirent in dirents(&buf[..read as usize]) {
+            assert!(dirent.ino != 0 || dirent.name == "..");
+            entries.push((String::from(dirent.name), dirent.is_dir));
+        }
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mkdir("dir_test_dir\0"), 0);
+    assert_eq!(mkdir("dir_test_dir/sub\0"), 0);
+    create("dir_test_dir/file\0");
+
+    let fd = open("dir_test_dir\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let expected = [
+        (String::from("."), true),
+        (String::from(".."), true),
+        (String::from("sub"), true),
+        (String::from("file"), false),
+    ];
+    assert_eq!(list(fd, 256), expected);
+    // from the start again, one entry of 24 bytes at a time
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    assert_eq!(list(fd, 24), expected);
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    let mut buf = [0u8; 16];
+    assert_eq!(getdents(fd, &mut buf), SysError::Invalid.code());
+    close(fd);
+    // not of a file
+    let fd = open("dir_test_dir/file\0", OpenFlags::RDONLY);
+    assert_eq!(
+        getdents(fd as usize, &mut [0u8; 64]),
+        SysError::NotDir.code()
+    );
+
+    // removed by the kind, and not while in use
+    assert_eq!(rmdir("dir_test_dir\0"), SysError::NotEmpty.code());
+    assert_eq!(unlink("dir_test_dir/sub\0"), SysError::IsDir.code());
+    assert_eq!(rmdir("dir_test_dir/file\0"), SysError::NotDir.code());
+    assert_eq!(unlink("dir_test_dir/file\0"), SysError::Busy.code());
+    close(fd as usize);
+    assert_eq!(unlink("dir_test_dir/file\0"), 0);
+    assert_eq!(unlink("dir_test_dir/file\0"), SysError::NoEntry.code());
+    assert!(open("dir_test_dir/file\0", OpenFlags::RDONLY) < 0);
+    assert_eq!(rmdir("/\0"), SysError::Busy.code());
+
+    // by paths relative to the working directory
+    assert_eq!(chdir("dir_test_dir/sub\0"), 0);
+    create("file\0");
+    assert_eq!(unlink("./file\0"), 0);
+    assert_eq!(rmdir("../sub\0"), 0);
+    assert_eq!(chdir("/\0"), 0);
+    assert_eq!(rmdir("dir_test_dir\0"), 0);
+    assert!(chdir("dir_test_dir\0") < 0);
+    assert!(open("dir_test_dir\0", OpenFlags::RDONLY) < 0);
+    println!("dir_test passed!");
+    0
+}
@@ -0,0 +1,53 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+#[macro_use]
+extern crate user_lib;
+
+use alloc::vec;
+use user_lib::{dmesg, write};
+
+/// The pages of the kernel log, the most it keeps.
+const KLOG_SIZE: usize = 4 * 4096;
+/// The level of the lines without one, like the output of processes.
+const DEFAULT_LEVEL: u8 = 6;
+
+fn level_of(name: &str) -> Option<u8> {
+    match name {
+        "error" => Some(3),
+        "warn" => Some(4),
+        "info" => Some(6),
+        "debug" => Some(7),
+        _ => None,
+    }
+}
+
+/// `dmesg [level]`, print the kernel log, only the messages up to `level`
+/// among error, warn, info and debug if it is given.
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let max_level = if argc > 1 {
+        match level_of(argv[1]) {
+            Some(level) => level,
+            None => {
+                println!("dmesg: unknown level {}", argv[1]);
+                return -1;
+            }
+        }
+    } else {
+        7
+    };
+    let mut buf = vec![0u8; KLOG_SIZE];
+    let len = dmesg(&mut buf) as usize;
+    for line in buf[..len].split_inclusive(|&byte| byte == b'\n') {
+        let (level, text) = match line {
+            [b'<', level @ b'0'..=b'7', b'>', text @ ..] => (level - b'0', text),
+            _ => (DEFAULT_LEVEL, line),
+        };
+        if level <= max_level {
+            write(1, text);
+        }
+    }
+    0
+}
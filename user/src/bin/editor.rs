@@ -0,0 +1,397 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[macro_use]
+extern crate user_lib;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::console::getchar;
+use user_lib::{
+    close, ftruncate, lseek, open, read, rename, tcgetattr, tcsetattr, write, LocalFlags,
+    OpenFlags, Termios, SEEK_END, SEEK_SET,
+};
+
+const STDIN: usize = 0;
+const STDOUT: usize = 1;
+const ROWS: usize = 24;
+const COLS: usize = 80;
+// the last two rows are taken by status bar and message line
+const TEXT_ROWS: usize = ROWS - 2;
+
+const LF: u8 = 0x0au8;
+const CR: u8 = 0x0du8;
+const DL: u8 = 0x7fu8;
+const BS: u8 = 0x08u8;
+const ESC: u8 = 0x1bu8;
+
+const fn ctrl(key: u8) -> u8 {
+    key & 0x1f
+}
+
+enum Key {
+    Char(u8),
+    Enter,
+    Backspace,
+    Delete,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    Save,
+    Rename,
+    Quit,
+    Unknown,
+}
+
+fn read_key() -> Key {
+    match getchar() {
+        ESC => {
+            if getchar() != b'[' {
+                return Key::Unknown;
+            }
+            match getchar() {
+                b'A' => Key::Up,
+                b'B' => Key::Down,
+                b'C' => Key::Right,
+                b'D' => Key::Left,
+                b'H' => Key::Home,
+                b'F' => Key::End,
+                b'3' if getchar() == b'~' => Key::Delete,
+                _ => Key::Unknown,
+            }
+        }
+        CR | LF => Key::Enter,
+        DL | BS => Key::Backspace,
+        c if c == ctrl(b's') => Key::Save,
+        c if c == ctrl(b'r') => Key::Rename,
+        c if c == ctrl(b'q') => Key::Quit,
+        c if (b' '..DL).contains(&c) || c == b'\t' => Key::Char(c),
+        _ => Key::Unknown,
+    }
+}
+
+fn with_nul(s: &str) -> String {
+    let mut s = String::from(s);
+    s.push('\0');
+    s
+}
+
+/// Read the whole file, None if it cannot be read.
+fn read_all(fd: usize) -> Option<Vec<u8>> {
+    let size = lseek(fd, 0, SEEK_END);
+    if size < 0 || lseek(fd, 0, SEEK_SET) < 0 {
+        return None;
+    }
+    let mut data = Vec::with_capacity(size as usize);
+    let mut buf = [0u8; 256];
+    loop {
+        let len = read(fd, &mut buf);
+        if len < 0 {
+            return None;
+        }
+        if len == 0 {
+            return Some(data);
+        }
+        data.extend_from_slice(&buf[..len as usize]);
+    }
+}
+
+struct Editor {
+    path: String,
+    lines: Vec<Vec<u8>>,
+    /// cursor position in the text
+    cx: usize,
+    cy: usize,
+    /// the first row and column shown on the screen
+    row_off: usize,
+    col_off: usize,
+    dirty: bool,
+    /// whether `path` exists in the file system
+    on_disk: bool,
+    message: String,
+}
+
+impl Editor {
+    fn open(path: &str) -> Self {
+        let mut editor = Self {
+            path: String::from(path),
+            lines: Vec::new(),
+            cx: 0,
+            cy: 0,
+            row_off: 0,
+            col_off: 0,
+            dirty: false,
+            on_disk: false,
+            message: String::from("^S save | ^R rename | ^Q quit"),
+        };
+        let fd = open(with_nul(path).as_str(), OpenFlags::RDONLY);
+        if fd >= 0 {
+            let data = read_all(fd as usize);
+            close(fd as usize);
+            editor.on_disk = true;
+            match data {
+                Some(data) => {
+                    editor.lines = data.split(|&c| c == LF).map(Vec::from).collect();
+                    // drop the empty line after the trailing newline
+                    if data.last() == Some(&LF) {
+                        editor.lines.pop();
+                    }
+                    editor.message = format!("\"{}\" {} bytes", path, data.len());
+                }
+                None => editor.message = format!("Cannot read \"{}\"", path),
+            }
+        }
+        if editor.lines.is_empty() {
+            editor.lines.push(Vec::new());
+        }
+        editor
+    }
+
+    fn save(&mut self) {
+        let path = with_nul(self.path.as_str());
+        // write in place, so that the file keeps its inode
+        let mut fd = open(path.as_str(), OpenFlags::WRONLY);
+        if fd < 0 {
+            fd = open(path.as_str(), OpenFlags::CREATE | OpenFlags::WRONLY);
+        }
+        if fd < 0 {
+            self.message = format!("Cannot open \"{}\"", self.path);
+            return;
+        }
+        let fd = fd as usize;
+        let mut data: Vec<u8> = Vec::new();
+        for line in self.lines.iter() {
+            data.extend_from_slice(line);
+            data.push(LF);
+        }
+        // the file may be longer than the buffer
+        let written = lseek(fd, 0, SEEK_SET) == 0
+            && write(fd, &data) == data.len() as isize
+            && ftruncate(fd, data.len()) == 0;
+        close(fd);
+        self.on_disk = true;
+        if !written {
+            // still dirty, as what is on the disk may be cut short
+            self.message = format!("Cannot write \"{}\"", self.path);
+            return;
+        }
+        self.dirty = false;
+        self.message = format!("\"{}\" {} bytes written", self.path, data.len());
+    }
+
+    fn rename(&mut self) {
+        let new_path = match self.prompt("Rename to: ") {
+            Some(new_path) if !new_path.is_empty() => new_path,
+            _ => {
+                self.message = String::from("Rename aborted");
+                return;
+            }
+        };
+        if self.on_disk
+            && rename(
+                with_nul(self.path.as_str()).as_str(),
+                with_nul(new_path.as_str()).as_str(),
+            ) < 0
+        {
+            self.message = format!("Cannot rename to \"{}\"", new_path);
+            return;
+        }
+        self.message = format!("Renamed to \"{}\"", new_path);
+        self.path = new_path;
+    }
+
+    /// Read a line on the message line, return None if aborted by ESC.
+    fn prompt(&mut self, hint: &str) -> Option<String> {
+        let mut input = String::new();
+        loop {
+            self.message = format!("{}{}", hint, input);
+            self.refresh();
+            match getchar() {
+                CR | LF => return Some(input),
+                ESC => return None,
+                DL | BS => {
+                    input.pop();
+                }
+                c if (b' '..DL).contains(&c) => input.push(c as char),
+                _ => {}
+            }
+        }
+    }
+
+    fn insert_char(&mut self, c: u8) {
+        self.lines[self.cy].insert(self.cx, c);
+        self.cx += 1;
+        self.dirty = true;
+    }
+
+    fn insert_newline(&mut self) {
+        let tail = self.lines[self.cy].split_off(self.cx);
+        self.lines.insert(self.cy + 1, tail);
+        self.cy += 1;
+        self.cx = 0;
+        self.dirty = true;
+    }
+
+    fn delete_char(&mut self) {
+        if self.cx > 0 {
+            self.lines[self.cy].remove(self.cx - 1);
+            self.cx -= 1;
+            self.dirty = true;
+        } else if self.cy > 0 {
+            let line = self.lines.remove(self.cy);
+            self.cy -= 1;
+            self.cx = self.lines[self.cy].len();
+            self.lines[self.cy].extend_from_slice(&line);
+            self.dirty = true;
+        }
+    }
+
+    fn move_cursor(&mut self, key: Key) {
+        match key {
+            Key::Up => self.cy = self.cy.saturating_sub(1),
+            Key::Down => self.cy = (self.cy + 1).min(self.lines.len() - 1),
+            Key::Left => {
+                if self.cx > 0 {
+                    self.cx -= 1;
+                } else if self.cy > 0 {
+                    self.cy -= 1;
+                    self.cx = self.lines[self.cy].len();
+                }
+            }
+            Key::Right => {
+                if self.cx < self.lines[self.cy].len() {
+                    self.cx += 1;
+                } else if self.cy + 1 < self.lines.len() {
+                    self.cy += 1;
+                    self.cx = 0;
+                }
+            }
+            Key::Home => self.cx = 0,
+            Key::End => self.cx = self.lines[self.cy].len(),
+            _ => {}
+        }
+        self.cx = self.cx.min(self.lines[self.cy].len());
+    }
+
+    fn scroll(&mut self) {
+        if self.cy < self.row_off {
+            self.row_off = self.cy;
+        }
+        if self.cy >= self.row_off + TEXT_ROWS {
+            self.row_off = self.cy + 1 - TEXT_ROWS;
+        }
+        if self.cx < self.col_off {
+            self.col_off = self.cx;
+        }
+        if self.cx >= self.col_off + COLS {
+            self.col_off = self.cx + 1 - COLS;
+        }
+    }
+
+    fn refresh(&mut self) {
+        self.scroll();
+        // hide cursor and move it to the top left corner
+        let mut screen = String::from("\x1b[?25l\x1b[H");
+        for row in self.row_off..self.row_off + TEXT_ROWS {
+            if let Some(line) = self.lines.get(row) {
+                let start = self.col_off.min(line.len());
+                let end = (self.col_off + COLS).min(line.len());
+                for &c in &line[start..end] {
+                    screen.push(match c {
+                        b' '..=b'~' => c as char,
+                        b'\t' => ' ',
+                        _ => '?',
+                    });
+                }
+            } else {
+                screen.push('~');
+            }
+            screen.push_str("\x1b[K\r\n");
+        }
+        let status = format!(
+            "{}{} - {} lines",
+            self.path,
+            if self.dirty { " [+]" } else { "" },
+            self.lines.len()
+        );
+        let position = format!("{}:{}", self.cy + 1, self.cx + 1);
+        let padding = COLS.saturating_sub(status.len() + position.len());
+        screen.push_str(&format!(
+            "\x1b[7m{}{:padding$}{}\x1b[m\r\n",
+            status,
+            "",
+            position,
+            padding = padding
+        ));
+        screen.push_str(&self.message);
+        screen.push_str("\x1b[K");
+        screen.push_str(&format!(
+            "\x1b[{};{}H\x1b[?25h",
+            self.cy - self.row_off + 1,
+            self.cx - self.col_off + 1
+        ));
+        write(STDOUT, screen.as_bytes());
+    }
+
+    fn run(&mut self) {
+        let mut quit_confirmed = false;
+        loop {
+            self.refresh();
+            match read_key() {
+                Key::Char(c) => self.insert_char(c),
+                Key::Enter => self.insert_newline(),
+                Key::Backspace => self.delete_char(),
+                Key::Delete => {
+                    let (cx, cy) = (self.cx, self.cy);
+                    self.move_cursor(Key::Right);
+                    if (cx, cy) != (self.cx, self.cy) {
+                        self.delete_char();
+                    }
+                }
+                Key::Save => self.save(),
+                Key::Rename => self.rename(),
+                Key::Quit => {
+                    if !self.dirty || quit_confirmed {
+                        return;
+                    }
+                    quit_confirmed = true;
+                    self.message = String::from("Unsaved changes! Press ^Q again to quit.");
+                    continue;
+                }
+                Key::Unknown => {}
+                key => self.move_cursor(key),
+            }
+            quit_confirmed = false;
+        }
+    }
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc != 2 {
+        println!("Usage: editor <file>");
+        return -1;
+    }
+    let mut orig = Termios::default();
+    if tcgetattr(STDIN, &mut orig) < 0 {
+        println!("editor: stdin is not a terminal");
+        return -1;
+    }
+    let mut raw = orig;
+    raw.lflag &= !(LocalFlags::ECHO | LocalFlags::ICANON | LocalFlags::ISIG).bits();
+    tcsetattr(STDIN, &raw);
+
+    let mut editor = Editor::open(argv[1]);
+    editor.run();
+
+    // clear the screen and restore the terminal
+    print!("\x1b[2J\x1b[H");
+    tcsetattr(STDIN, &orig);
+    0
+}
@@ -0,0 +1,100 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    close, exit, fork, get_time, getpid, kill, pipe, read, sigaction, sleep, waitpid, write,
+    SignalAction, SignalActionFlags, SignalFlags, EINTR,
+};
+
+const SIGUSR1: usize = 10;
+
+static HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn handler(_signum: i32) {
+    HANDLED.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Fork a child which sends SIGUSR1 to the parent once it is blocked, then
+/// writes a byte to `fd` so the parent never waits forever.
+fn signal_later(fd: usize) -> usize {
+    let parent = getpid() as usize;
+    let pid = fork();
+    if pid == 0 {
+        sleep(100);
+        kill(parent, SignalFlags::SIGUSR1.bits());
+        sleep(100);
+        write(fd, b"x");
+        exit(0);
+    }
+    pid as usize
+}
+
+fn wait_child(pid: usize) {
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid as isize);
+    assert_eq!(exit_code, 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [0u8; 1];
+
+    // a read of an empty pipe gives up when a handler runs
+    let action = SignalAction::new(handler, SignalFlags::empty());
+    assert_eq!(sigaction(SIGUSR1, Some(&action), None), 0);
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let pid = signal_later(pipe_fd[1]);
+    assert_eq!(read(pipe_fd[0], &mut buf), EINTR);
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 1);
+    wait_child(pid);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+
+    // and goes on after the handler with SA_RESTART
+    let action = SignalAction {
+        flags: SignalActionFlags::SA_RESTART,
+        ..SignalAction::new(handler, SignalFlags::empty())
+    };
+    assert_eq!(sigaction(SIGUSR1, Some(&action), None), 0);
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let pid = signal_later(pipe_fd[1]);
+    assert_eq!(read(pipe_fd[0], &mut buf), 1);
+    assert_eq!(buf[0], b'x');
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 2);
+    wait_child(pid);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+
+    // a sleep is never restarted
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let start = get_time();
+    let pid = signal_later(pipe_fd[1]);
+    assert_eq!(sleep(5000), EINTR);
+    assert!(get_time() - start < 5000);
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 3);
+    wait_child(pid);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+
+    // a child blocked in a read is terminated at once
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let pid = fork();
+    if pid == 0 {
+        read(pipe_fd[0], &mut buf);
+        exit(0);
+    }
+    sleep(100);
+    assert_eq!(kill(pid as usize, SignalFlags::SIGTERM.bits()), 0);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -15);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    println!("eintr_test passed!");
+    0
+}
@@ -0,0 +1,116 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, epoll_create, epoll_ctl, epoll_wait, exit, fork, pipe2, poll, read, sleep, waitpid,
+    write, EpollEvent, EpollFlags, OpenFlags, PollEvents, PollFd, SysError, EPOLL_CTL_ADD,
+    EPOLL_CTL_DEL, EPOLL_CTL_MOD,
+};
+
+const DATA: u64 = 0x1234_5678_9abc;
+
+fn wait(epfd: usize, timeout_ms: isize) -> Option<EpollEvent> {
+    let mut events = [EpollEvent::default(); 4];
+    match epoll_wait(epfd, &mut events, timeout_ms) {
+        0 => None,
+        1 => Some(events[0]),
+        ret => panic!("epoll_wait returned {}", ret),
+    }
+}
+
+fn set(epfd: usize, op: usize, fd: usize, flags: EpollFlags) -> isize {
+    epoll_ctl(epfd, op, fd, Some(&EpollEvent::new(flags, DATA)))
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let epfd = epoll_create(OpenFlags::CLOEXEC);
+    assert!(epfd >= 0);
+    let epfd = epfd as usize;
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe2(&mut pipe_fd, OpenFlags::NONBLOCK), 0);
+    let (read_fd, write_fd) = (pipe_fd[0], pipe_fd[1]);
+    let mut buf = [0u8; 8];
+
+    // level triggered, reported while there is something to read
+    assert_eq!(set(epfd, EPOLL_CTL_ADD, read_fd, EpollFlags::EPOLLIN), 0);
+    assert_eq!(
+        set(epfd, EPOLL_CTL_ADD, read_fd, EpollFlags::EPOLLIN),
+        SysError::Exists.code()
+    );
+    assert!(wait(epfd, 0).is_none());
+    assert_eq!(write(write_fd, b"a"), 1);
+    for _ in 0..2 {
+        let event = wait(epfd, 0).unwrap();
+        assert_eq!(event.data, DATA);
+        assert_eq!(event.flags(), EpollFlags::EPOLLIN);
+    }
+
+    // edge triggered, reported once until more is written
+    let edge = EpollFlags::EPOLLIN | EpollFlags::EPOLLET;
+    assert_eq!(set(epfd, EPOLL_CTL_MOD, read_fd, edge), 0);
+    assert!(wait(epfd, 0).is_some());
+    assert!(wait(epfd, 0).is_none());
+    assert_eq!(write(write_fd, b"b"), 1);
+    assert!(wait(epfd, 0).is_some());
+    assert!(wait(epfd, 0).is_none());
+
+    // one-shot, disabled until changed again
+    let oneshot = EpollFlags::EPOLLIN | EpollFlags::EPOLLONESHOT;
+    assert_eq!(set(epfd, EPOLL_CTL_MOD, read_fd, oneshot), 0);
+    assert!(wait(epfd, 0).is_some());
+    assert!(wait(epfd, 0).is_none());
+    assert_eq!(set(epfd, EPOLL_CTL_MOD, read_fd, oneshot), 0);
+    assert!(wait(epfd, 0).is_some());
+
+    // the epoll itself is readable while some file is ready
+    assert_eq!(set(epfd, EPOLL_CTL_MOD, read_fd, EpollFlags::EPOLLIN), 0);
+    let mut fds = [PollFd::new(epfd, PollEvents::POLLIN)];
+    assert_eq!(poll(&mut fds, 0), 1);
+    assert_eq!(read(read_fd, &mut buf), 2);
+    assert_eq!(poll(&mut fds, 0), 0);
+    assert!(wait(epfd, 10).is_none());
+    // and never added to another one
+    assert_eq!(
+        set(epfd, EPOLL_CTL_ADD, epfd, EpollFlags::EPOLLIN),
+        SysError::Invalid.code()
+    );
+
+    // sleeps until the child writes, then sees the end once it exits
+    let pid = fork();
+    if pid == 0 {
+        close(read_fd);
+        sleep(50);
+        assert_eq!(write(write_fd, b"late"), 4);
+        exit(0);
+    }
+    close(write_fd);
+    assert_eq!(set(epfd, EPOLL_CTL_MOD, read_fd, edge), 0);
+    assert!(wait(epfd, -1)
+        .unwrap()
+        .flags()
+        .contains(EpollFlags::EPOLLIN));
+    assert_eq!(read(read_fd, &mut buf), 4);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(set(epfd, EPOLL_CTL_MOD, read_fd, EpollFlags::EPOLLIN), 0);
+    assert!(wait(epfd, -1)
+        .unwrap()
+        .flags()
+        .contains(EpollFlags::EPOLLHUP));
+    assert_eq!(read(read_fd, &mut buf), 0);
+
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_DEL, read_fd, None), 0);
+    assert_eq!(
+        epoll_ctl(epfd, EPOLL_CTL_DEL, read_fd, None),
+        SysError::NoEntry.code()
+    );
+    close(read_fd);
+    close(epfd);
+    println!("epoll_test passed!");
+    0
+}
@@ -0,0 +1,81 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, mkdir, mount, open, read, rename, run, umount, write, OpenFlags, SysError,
+    MS_SYNCHRONOUS,
+};
+
+const CONTENT: &[u8] = b"written to FAT32";
+
+/// Read the whole file at `path`, None if it cannot be opened.
+fn read_file(path: &str, buf: &mut [u8]) -> Option<usize> {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let len = read(fd as usize, buf);
+    close(fd as usize);
+    Some(len as usize)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(run(&["mkfs_fat\0", "/dev/ram0\0"]), 0);
+    mkdir("/fat_test_mnt\0");
+    let (disk, dir) = ("/dev/ram0\0", "/fat_test_mnt\0");
+    // not easy-fs any more
+    assert_eq!(
+        mount(disk, dir, "easyfs\0", MS_SYNCHRONOUS),
+        SysError::Invalid.code()
+    );
+    assert_eq!(mount(disk, dir, "vfat\0", MS_SYNCHRONOUS), 0);
+
+    // long names with spaces, in directories
+    assert_eq!(mkdir("/fat_test_mnt/a directory\0"), 0);
+    let path = "/fat_test_mnt/a directory/a file with a long name.txt\0";
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, CONTENT), CONTENT.len() as isize);
+    close(fd as usize);
+    // names are found ignoring the case
+    let mut buf = [0u8; 64];
+    let upper = "/fat_test_mnt/A DIRECTORY/A FILE WITH A LONG NAME.TXT\0";
+    assert_eq!(read_file(upper, &mut buf), Some(CONTENT.len()));
+    assert_eq!(&buf[..CONTENT.len()], CONTENT);
+    assert_eq!(
+        open(
+            "/fat_test_mnt/a directory/A File With A Long Name.txt\0",
+            OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::EXCL
+        ),
+        -1
+    );
+    // not names of FAT
+    assert_eq!(
+        open("/fat_test_mnt/a:b\0", OpenFlags::CREATE | OpenFlags::WRONLY),
+        -1
+    );
+    assert_eq!(rename(path, "/fat_test_mnt/a directory/short.txt\0"), 0);
+    assert_eq!(read_file(path, &mut buf), None);
+
+    // kept on the disk when mounted again
+    assert_eq!(umount(dir), 0);
+    assert_eq!(
+        read_file("/fat_test_mnt/a directory/short.txt\0", &mut buf),
+        None
+    );
+    assert_eq!(mount(disk, dir, "vfat\0", MS_SYNCHRONOUS), 0);
+    let path = "/fat_test_mnt/a directory/short.txt\0";
+    assert_eq!(read_file(path, &mut buf), Some(CONTENT.len()));
+    assert_eq!(&buf[..CONTENT.len()], CONTENT);
+    let fd = open(path, OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd > 0);
+    close(fd as usize);
+    assert_eq!(read_file(path, &mut buf), Some(0));
+    assert_eq!(umount(dir), 0);
+    println!("fat_test passed!");
+    0
+}
@@ -0,0 +1,98 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, dup, exit, fcntl, fork, lseek, open, pipe, read, waitpid, write, OpenFlags, FD_CLOEXEC,
+    F_GETFD, F_GETFL, F_SETFD, F_SETFL, SEEK_CUR, SEEK_SET,
+};
+
+fn wait(pid: isize) {
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+}
+
+fn check_content(expected: &[u8]) {
+    let fd = open("fd_share_test\0", OpenFlags::RDONLY) as usize;
+    let mut buf = [0u8; 64];
+    let len = read(fd, &mut buf);
+    assert_eq!(&buf[..len as usize], expected);
+    close(fd);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("fd_share_test\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+
+    // a forked child writes at the offset of the parent and moves it
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(write(fd, b"child "), 6);
+        exit(0);
+    }
+    wait(pid);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 6);
+    assert_eq!(write(fd, b"parent"), 6);
+    check_content(b"child parent");
+
+    // another open of the file has its own offset
+    let other = open("fd_share_test\0", OpenFlags::RDONLY) as usize;
+    assert_eq!(lseek(other, 0, SEEK_CUR), 0);
+    assert_eq!(fcntl(other, F_GETFL, 0), OpenFlags::RDONLY.bits() as isize);
+    close(other);
+
+    // duplicates share the status flags but not the descriptor flags
+    let duplicate = dup(fd) as usize;
+    assert_eq!(fcntl(fd, F_GETFL, 0), OpenFlags::WRONLY.bits() as isize);
+    assert_eq!(
+        fcntl(duplicate, F_SETFL, OpenFlags::APPEND.bits() as usize),
+        0
+    );
+    assert_eq!(
+        fcntl(fd, F_GETFL, 0),
+        (OpenFlags::WRONLY | OpenFlags::APPEND).bits() as isize
+    );
+    assert_eq!(fcntl(duplicate, F_SETFD, FD_CLOEXEC), 0);
+    assert_eq!(fcntl(fd, F_GETFD, 0), 0);
+    close(duplicate);
+
+    // and so do forked children, whose writes are appended
+    let pid = fork();
+    if pid == 0 {
+        lseek(fd, 0, SEEK_SET);
+        assert_eq!(write(fd, b"!"), 1);
+        exit(0);
+    }
+    wait(pid);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 13);
+    check_content(b"child parent!");
+    close(fd);
+
+    // opened to append
+    let fd = open("fd_share_test\0", OpenFlags::WRONLY | OpenFlags::APPEND) as usize;
+    assert_eq!(write(fd, b"?"), 1);
+    check_content(b"child parent!?");
+    close(fd);
+
+    // pipes have no status flags to set
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(
+        fcntl(pipe_fd[1], F_GETFL, 0),
+        OpenFlags::WRONLY.bits() as isize
+    );
+    assert_eq!(
+        fcntl(pipe_fd[1], F_SETFL, OpenFlags::APPEND.bits() as usize),
+        -1
+    );
+    assert_eq!(fcntl(pipe_fd[1], F_SETFL, 0), 0);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    println!("fd_share_test passed!");
+    0
+}
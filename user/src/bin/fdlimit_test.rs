@@ -0,0 +1,86 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, dup, exit, fork, getrlimit, setrlimit, setuid, waitpid, RLimit, SysError, RLIMIT_NOFILE,
+};
+
+/// More descriptors than the default soft limit allows.
+const MANY: usize = 2000;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut limit = RLimit::default();
+    assert_eq!(getrlimit(RLIMIT_NOFILE, &mut limit), 0);
+    assert!(limit.cur > 3 && limit.cur <= limit.max);
+    let default = limit;
+
+    // the lowest free descriptor is reused first
+    assert_eq!(dup(1), 3);
+    assert_eq!(dup(1), 4);
+    assert_eq!(close(3), 0);
+    assert_eq!(dup(1), 3);
+
+    // the descriptors run out at the soft limit
+    assert_eq!(setrlimit(RLIMIT_NOFILE, &RLimit { cur: 8, max: 8 }), 0);
+    for fd in 5..8 {
+        assert_eq!(dup(1), fd as isize);
+    }
+    assert_eq!(dup(1), SysError::TooManyFiles.code());
+    assert_eq!(close(6), 0);
+    assert_eq!(dup(1), 6);
+    // the soft limit cannot be above the hard one
+    assert_eq!(
+        setrlimit(RLIMIT_NOFILE, &RLimit { cur: 9, max: 8 }),
+        SysError::Invalid.code()
+    );
+
+    // the table grows up to a larger limit
+    let large = RLimit {
+        cur: MANY,
+        max: default.max,
+    };
+    assert_eq!(setrlimit(RLIMIT_NOFILE, &large), 0);
+    for fd in 8..MANY {
+        assert_eq!(dup(1), fd as isize);
+    }
+    assert_eq!(dup(1), SysError::TooManyFiles.code());
+
+    // the limit is inherited by children
+    let pid = fork();
+    if pid == 0 {
+        let mut limit = RLimit::default();
+        getrlimit(RLIMIT_NOFILE, &mut limit);
+        assert_eq!(limit.cur, MANY);
+        assert_eq!(dup(1), SysError::TooManyFiles.code());
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    // only root may raise the hard limit
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(setuid(1000), 0);
+        let lowered = RLimit { cur: 8, max: 8 };
+        assert_eq!(setrlimit(RLIMIT_NOFILE, &lowered), 0);
+        assert_eq!(
+            setrlimit(RLIMIT_NOFILE, &large),
+            SysError::NotPermitted.code()
+        );
+        exit(0);
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    for fd in 3..MANY {
+        assert_eq!(close(fd), 0);
+    }
+    assert_eq!(setrlimit(RLIMIT_NOFILE, &default), 0);
+    println!("fdlimit_test passed!");
+    0
+}
@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, ftruncate, lseek, open, read, rename, write, OpenFlags, SysError, SEEK_CUR, SEEK_END,
+    SEEK_SET,
+};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let src = "filetest_seek_a\0";
+    let dst = "filetest_seek_b\0";
+    // left by a previous run
+    if open(dst, OpenFlags::RDONLY) >= 0 {
+        assert_eq!(rename(dst, src), 0);
+    }
+    let fd = open(src, OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    write(fd, b"Hello, world!");
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 13);
+    assert_eq!(lseek(fd, 7, SEEK_SET), 7);
+    write(fd, b"rCore");
+    assert_eq!(lseek(fd, -3, SEEK_END), 10);
+    assert_eq!(lseek(fd, -20, SEEK_CUR), SysError::Invalid.code());
+    // shrink, then grow again with a hole of zeros
+    assert_eq!(ftruncate(fd, 5), 0);
+    assert_eq!(lseek(fd, 0, SEEK_END), 5);
+    assert_eq!(ftruncate(fd, 8), 0);
+    close(fd);
+
+    assert_eq!(rename(src, dst), 0);
+    assert!(open(src, OpenFlags::RDONLY) < 0);
+    assert!(rename(dst, "initproc\0") < 0);
+
+    let fd = open(dst, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut buffer = [0u8; 100];
+    let read_len = read(fd, &mut buffer) as usize;
+    assert!(ftruncate(fd, 0) < 0);
+    close(fd);
+    assert_eq!(&buffer[..read_len], b"Hello\0\0\0");
+    println!("filetest_seek passed!");
+    0
+}
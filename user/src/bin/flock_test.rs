@@ -0,0 +1,164 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, dup, exit, fcntl_lock, flock, fork, getpid, open, pipe, read, sleep, unlink, waitpid,
+    write, Flock, OpenFlags, EAGAIN, EDEADLK, F_GETLK, F_RDLCK, F_SETLK, F_SETLKW, F_UNLCK,
+    F_WRLCK, LOCK_EX, LOCK_NB, LOCK_SH, LOCK_UN,
+};
+
+const PATH: &str = "flock_test\0";
+
+fn open_rw() -> usize {
+    let fd = open(PATH, OpenFlags::RDWR);
+    assert!(fd > 0);
+    fd as usize
+}
+
+fn wait(pid: isize) {
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+}
+
+fn lock(fd: usize, cmd: usize, l_type: i16, start: usize, len: usize) -> isize {
+    fcntl_lock(fd, cmd, &mut Flock::new(l_type, start, len))
+}
+
+/// Tell the other side through `pipe_fd`, or wait for it.
+fn notify(pipe_fd: &[usize; 2]) {
+    assert_eq!(write(pipe_fd[1], b"!"), 1);
+}
+fn wait_notified(pipe_fd: &[usize; 2]) {
+    let mut buf = [0u8; 1];
+    assert_eq!(read(pipe_fd[0], &mut buf), 1);
+}
+
+fn whole_file_locks() {
+    let fd = open_rw();
+    let other = open_rw();
+    assert_eq!(flock(fd, LOCK_SH), 0);
+    assert_eq!(flock(other, LOCK_SH | LOCK_NB), 0);
+    // the shared lock is released first when converted, even if the
+    // exclusive one is not taken
+    assert_eq!(flock(other, LOCK_EX | LOCK_NB), EAGAIN);
+    assert_eq!(flock(fd, LOCK_EX | LOCK_NB), 0);
+    assert_eq!(flock(other, LOCK_SH | LOCK_NB), EAGAIN);
+
+    // shared by the duplicates, until the last of them is closed
+    let duplicate = dup(fd) as usize;
+    assert_eq!(flock(duplicate, LOCK_EX | LOCK_NB), 0);
+    close(fd);
+    assert_eq!(flock(other, LOCK_EX | LOCK_NB), EAGAIN);
+    close(duplicate);
+    assert_eq!(flock(other, LOCK_EX | LOCK_NB), 0);
+    assert_eq!(flock(other, LOCK_UN), 0);
+    let fd = open_rw();
+    assert_eq!(flock(fd, LOCK_EX | LOCK_NB), 0);
+    close(fd);
+    close(other);
+
+    // waited for until a child holding it exits
+    let mut pipe_fd = [0usize; 2];
+    pipe(&mut pipe_fd);
+    let pid = fork();
+    if pid == 0 {
+        let fd = open_rw();
+        assert_eq!(flock(fd, LOCK_EX), 0);
+        notify(&pipe_fd);
+        sleep(100);
+        exit(0);
+    }
+    wait_notified(&pipe_fd);
+    let fd = open_rw();
+    assert_eq!(flock(fd, LOCK_EX | LOCK_NB), EAGAIN);
+    assert_eq!(flock(fd, LOCK_EX), 0);
+    wait(pid);
+    close(fd);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    println!("whole file locks passed!");
+}
+
+fn range_locks() {
+    let fd = open_rw();
+    assert_eq!(lock(fd, F_SETLK, F_WRLCK, 0, 10), 0);
+    // the ranges of a process do not conflict with each other
+    assert_eq!(lock(fd, F_SETLK, F_RDLCK, 5, 10), 0);
+    let parent = getpid();
+    let pid = fork();
+    if pid == 0 {
+        let fd = open_rw();
+        assert_eq!(lock(fd, F_SETLK, F_RDLCK, 0, 5), EAGAIN);
+        assert_eq!(lock(fd, F_SETLK, F_RDLCK, 5, 10), 0);
+        assert_eq!(lock(fd, F_SETLK, F_WRLCK, 15, 0), 0);
+        let mut request = Flock::new(F_WRLCK, 2, 1);
+        assert_eq!(fcntl_lock(fd, F_GETLK, &mut request), 0);
+        assert_eq!(request.l_type, F_WRLCK);
+        assert_eq!((request.l_start, request.l_len), (0, 5));
+        assert_eq!(request.l_pid as isize, parent);
+        exit(0);
+    }
+    wait(pid);
+    // released when the child exits
+    let mut request = Flock::new(F_WRLCK, 0, 0);
+    assert_eq!(fcntl_lock(fd, F_GETLK, &mut request), 0);
+    assert_eq!(request.l_type, F_UNLCK);
+
+    // released when any descriptor of the file is closed
+    let other = open_rw();
+    close(other);
+    let pid = fork();
+    if pid == 0 {
+        let fd = open_rw();
+        assert_eq!(lock(fd, F_SETLK, F_WRLCK, 0, 0), 0);
+        exit(0);
+    }
+    wait(pid);
+    close(fd);
+    println!("range locks passed!");
+}
+
+fn deadlock() {
+    let fd = open_rw();
+    assert_eq!(lock(fd, F_SETLK, F_WRLCK, 0, 1), 0);
+    let mut pipe_fd = [0usize; 2];
+    pipe(&mut pipe_fd);
+    let pid = fork();
+    if pid == 0 {
+        let fd = open_rw();
+        assert_eq!(lock(fd, F_SETLK, F_WRLCK, 1, 1), 0);
+        notify(&pipe_fd);
+        // waits for the parent, which then would wait for it
+        assert_eq!(lock(fd, F_SETLKW, F_WRLCK, 0, 1), 0);
+        exit(0);
+    }
+    wait_notified(&pipe_fd);
+    sleep(50);
+    assert_eq!(lock(fd, F_SETLKW, F_WRLCK, 1, 1), EDEADLK);
+    assert_eq!(lock(fd, F_SETLK, F_UNLCK, 0, 1), 0);
+    wait(pid);
+    close(fd);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    println!("deadlock detection passed!");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open(PATH, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"0123456789abcdefghij"), 20);
+    close(fd as usize);
+
+    whole_file_locks();
+    range_locks();
+    deadlock();
+
+    assert_eq!(unlink(PATH), 0);
+    println!("flock_test passed!");
+    0
+}
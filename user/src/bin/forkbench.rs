@@ -0,0 +1,75 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time_ns, vm_stats, waitpid, VmStats};
+
+const PAGES: usize = 256;
+const PAGE_SIZE: usize = 4096;
+
+/// In the bss, allocated on the first access.
+static mut DATA: [u8; PAGES * PAGE_SIZE] = [0; PAGES * PAGE_SIZE];
+
+fn stats() -> VmStats {
+    let mut stats = VmStats::default();
+    assert_eq!(vm_stats(0, &mut stats), 0);
+    stats
+}
+
+/// Write a byte of each page, return the time it takes in us and the
+/// faults resolved meanwhile.
+fn write_pages(value: u8) -> (usize, VmStats) {
+    let before = stats();
+    let start = get_time_ns() as usize;
+    for page in 0..PAGES {
+        unsafe {
+            DATA[page * PAGE_SIZE] = value;
+        }
+    }
+    let time = (get_time_ns() as usize - start) / 1000;
+    let after = stats();
+    (
+        time,
+        VmStats {
+            cow_faults: after.cow_faults - before.cow_faults,
+            lazy_faults: after.lazy_faults - before.lazy_faults,
+            swap_ins: after.swap_ins - before.swap_ins,
+        },
+    )
+}
+
+fn report(what: &str, (time, stats): (usize, VmStats)) {
+    println!(
+        "{:<16} {:>8} us {:>6} cow {:>6} lazy {:>6} swap-in",
+        what, time, stats.cow_faults, stats.lazy_faults, stats.swap_ins
+    );
+}
+
+/// `forkbench`, the cost of a fork and of the page faults after it, to
+/// compare the designs of the memory management.
+#[no_mangle]
+pub fn main() -> i32 {
+    println!("forkbench: {} pages", PAGES);
+    report("first writes", write_pages(1));
+
+    let start = get_time_ns() as usize;
+    let pid = fork();
+    if pid == 0 {
+        // every frame is shared with the parent, each write copies one
+        let (time, stats) = write_pages(2);
+        assert!(stats.cow_faults >= PAGES);
+        report("child writes", (time, stats));
+        report("child again", write_pages(3));
+        exit(0);
+    }
+    let fork_time = (get_time_ns() as usize - start) / 1000;
+    println!("{:<16} {:>8} us", "fork", fork_time);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    // the child has exited, the frames are only made writable again
+    report("parent writes", write_pages(4));
+    0
+}
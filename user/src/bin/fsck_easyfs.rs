@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::sync::Arc;
+use easy_fs::EasyFileSystem;
+use user_lib::{close, open, FdBlockDevice, OpenFlags};
+
+/// `fsck_easyfs <device>`, exit with 1 if errors are found.
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc != 2 {
+        println!("usage: fsck_easyfs <device>");
+        return -1;
+    }
+    let fd = open(argv[1], OpenFlags::RDONLY);
+    if fd < 0 {
+        println!("fsck_easyfs: cannot open {}", argv[1]);
+        return -1;
+    }
+    let report = EasyFileSystem::check(Arc::new(FdBlockDevice(fd as usize)));
+    close(fd as usize);
+    for error in report.errors.iter() {
+        println!("{}", error);
+    }
+    if report.error_count > report.errors.len() {
+        println!("... {} more", report.error_count - report.errors.len());
+    }
+    println!(
+        "{}: {} directories, {} files, {} blocks, {} errors",
+        argv[1], report.dirs, report.files, report.blocks, report.error_count
+    );
+    if report.is_clean() {
+        0
+    } else {
+        1
+    }
+}
@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, fdatasync, fsync, open, pipe, read, write, OpenFlags, SysError};
+
+const FILE: &str = "fsync_test_file\0";
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // a file of the root file system, written back and flushed to the disk
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, b"persistent"), 10);
+    assert_eq!(fdatasync(fd), 0);
+    assert_eq!(write(fd, b" data"), 5);
+    assert_eq!(fsync(fd), 0);
+    close(fd);
+    let fd = open(FILE, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut buf = [0u8; 32];
+    assert_eq!(read(fd as usize, &mut buf), 15);
+    assert_eq!(&buf[..15], b"persistent data");
+    // even read-only
+    assert_eq!(fdatasync(fd as usize), 0);
+    close(fd as usize);
+
+    // a disk
+    let disk = open("/dev/ram0\0", OpenFlags::RDWR);
+    assert!(disk > 0);
+    assert_eq!(fdatasync(disk as usize), 0);
+    close(disk as usize);
+
+    // nothing to make persistent for a pipe
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(fdatasync(pipe_fd[0]), SysError::Invalid.code());
+    assert_eq!(fsync(pipe_fd[1]), SysError::Invalid.code());
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    assert_eq!(fdatasync(pipe_fd[0]), SysError::BadFd.code());
+    println!("fsync_test passed!");
+    0
+}
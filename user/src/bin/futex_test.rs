@@ -0,0 +1,57 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use user_lib::{exit, futex_wait, futex_wake, get_time, thread_create, waittid};
+use user_lib::{Semaphore, EAGAIN};
+
+static WORD: AtomicU32 = AtomicU32::new(0);
+static PING: Semaphore = Semaphore::new(0);
+static PONG: Semaphore = Semaphore::new(0);
+const ROUNDS: usize = 100;
+
+fn waker() -> ! {
+    // wait until the main thread is about to sleep on the word
+    while WORD.load(Ordering::SeqCst) == 0 {}
+    WORD.store(2, Ordering::SeqCst);
+    futex_wake(&WORD, 1);
+    exit(0)
+}
+
+fn pong() -> ! {
+    for _ in 0..ROUNDS {
+        PING.down();
+        PONG.up();
+    }
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // nothing to wait for if the word is changed already
+    assert_eq!(futex_wait(&WORD, 1, -1), EAGAIN);
+    assert_eq!(futex_wake(&WORD, 1), 0);
+    let start = get_time();
+    assert_eq!(futex_wait(&WORD, 0, 20_000_000), -2);
+    assert!(get_time() - start >= 20);
+
+    let tid = thread_create(waker as usize, 0) as usize;
+    WORD.store(1, Ordering::SeqCst);
+    while WORD.load(Ordering::SeqCst) == 1 {
+        futex_wait(&WORD, 1, -1);
+    }
+    assert_eq!(waittid(tid), 0);
+
+    let tid = thread_create(pong as usize, 0) as usize;
+    for _ in 0..ROUNDS {
+        PING.up();
+        PONG.down();
+    }
+    assert_eq!(waittid(tid), 0);
+    assert!(!PONG.try_down());
+    println!("futex_test passed!");
+    0
+}
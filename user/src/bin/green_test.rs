@@ -0,0 +1,64 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{close, green, pipe2, OpenFlags};
+
+const THREADS: usize = 20;
+const ROUNDS: usize = 50;
+const WORKERS: usize = 3;
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // green threads taking turns on a few kernel threads
+    for _ in 0..THREADS {
+        green::spawn(|| {
+            for _ in 0..ROUNDS {
+                COUNTER.fetch_add(1, Ordering::SeqCst);
+                green::yield_now();
+            }
+            FINISHED.fetch_add(1, Ordering::SeqCst);
+        });
+    }
+    green::run(WORKERS);
+    assert_eq!(COUNTER.load(Ordering::SeqCst), THREADS * ROUNDS);
+    assert_eq!(FINISHED.load(Ordering::SeqCst), THREADS);
+
+    // ping-pong over pipes, only the green thread waits for a pipe
+    let mut ping = [0usize; 2];
+    let mut pong = [0usize; 2];
+    assert_eq!(pipe2(&mut ping, OpenFlags::NONBLOCK), 0);
+    assert_eq!(pipe2(&mut pong, OpenFlags::NONBLOCK), 0);
+    green::spawn(move || {
+        let mut byte = [0u8; 1];
+        for round in 0..ROUNDS {
+            assert_eq!(green::read(ping[0], &mut byte), 1);
+            assert_eq!(byte[0] as usize, round);
+            assert_eq!(green::write(pong[1], &byte), 1);
+        }
+    });
+    green::spawn(move || {
+        let mut byte = [0u8; 1];
+        for round in 0..ROUNDS {
+            // the other one waits for the pipe meanwhile
+            green::yield_now();
+            assert_eq!(green::write(ping[1], &[round as u8]), 1);
+            assert_eq!(green::read(pong[0], &mut byte), 1);
+            assert_eq!(byte[0] as usize, round);
+        }
+        FINISHED.fetch_add(1, Ordering::SeqCst);
+    });
+    green::run(1);
+    assert_eq!(FINISHED.load(Ordering::SeqCst), THREADS + 1);
+    for fd in ping.iter().chain(pong.iter()) {
+        close(*fd);
+    }
+    println!("green_test passed!");
+    0
+}
@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{cpu_down, cpu_up, exit, fork, setuid, waitpid, SysError};
+
+const BOOT_HART: usize = 0;
+const HART: usize = 1;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(cpu_down(BOOT_HART), SysError::Invalid.code());
+    assert_eq!(cpu_up(BOOT_HART), SysError::Invalid.code());
+    // only root may
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(setuid(1000), 0);
+        assert_eq!(cpu_down(HART), SysError::NotPermitted.code());
+        assert_eq!(cpu_up(HART), SysError::NotPermitted.code());
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    // the secondary harts are started at boot, if there are any
+    let online = cpu_down(HART) == 0;
+    if !online && cpu_up(HART) != 0 {
+        // QEMU runs with a single hart by default
+        println!("hart {} is not available, skipped", HART);
+        return 0;
+    }
+    if !online {
+        assert_eq!(cpu_down(HART), 0);
+    }
+    assert_eq!(cpu_down(HART), SysError::Invalid.code());
+    for _ in 0..3 {
+        assert_eq!(cpu_up(HART), 0);
+        assert_eq!(cpu_up(HART), SysError::Invalid.code());
+        assert_eq!(cpu_down(HART), 0);
+        assert_eq!(cpu_down(HART), SysError::Invalid.code());
+    }
+    if online {
+        assert_eq!(cpu_up(HART), 0);
+    }
+    println!("hotplug_test passed!");
+    0
+}
@@ -0,0 +1,159 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+// serve files of easy-fs, use http://localhost:6201/<file> to access it
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    accept, close, listen, lseek, open, poll, read, sendfile, write, OpenFlags, PollEvents, PollFd,
+    SEEK_END,
+};
+
+const PORT: u16 = 80;
+// larger requests are rejected
+const MAX_REQUEST_LEN: usize = 1024;
+// keep each write in a single ethernet frame
+const CHUNK_SIZE: usize = 1024;
+
+struct Client {
+    fd: usize,
+    request: Vec<u8>,
+}
+
+fn content_type(path: &str) -> &'static str {
+    match path.rsplit_once('.').map(|(_, ext)| ext) {
+        Some("html") | Some("htm") => "text/html",
+        Some("txt") | Some("rs") => "text/plain",
+        Some("css") => "text/css",
+        Some("js") => "text/javascript",
+        _ => "application/octet-stream",
+    }
+}
+
+fn send_header(fd: usize, status: &str, content_type: &str, content_length: usize) {
+    let header = format!(
+        "HTTP/1.0 {}\r\nServer: rCore httpd\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status, content_type, content_length
+    );
+    write(fd, header.as_bytes());
+}
+
+fn send_response(fd: usize, status: &str, content_type: &str, body: &[u8]) {
+    send_header(fd, status, content_type, body.len());
+    for chunk in body.chunks(CHUNK_SIZE) {
+        write(fd, chunk);
+    }
+}
+
+fn send_error(fd: usize, status: &str) {
+    let body = format!("<html><body><h1>{}</h1></body></html>\n", status);
+    send_response(fd, status, "text/html", body.as_bytes());
+}
+
+fn send_file(fd: usize, name: &str) -> bool {
+    let mut path = String::from(name);
+    path.push('\0');
+    let file_fd = open(path.as_str(), OpenFlags::RDONLY);
+    if file_fd < 0 {
+        return false;
+    }
+    let file_fd = file_fd as usize;
+    let size = lseek(file_fd, 0, SEEK_END) as usize;
+    send_header(fd, "200 OK", content_type(name), size);
+    // the kernel copies the file from the page cache in frames
+    let mut offset = 0;
+    while offset < size {
+        if sendfile(fd, file_fd, Some(&mut offset), size) <= 0 {
+            break;
+        }
+    }
+    close(file_fd);
+    true
+}
+
+// handle a complete request, the connection is closed after that
+fn handle_request(fd: usize, request: &[u8]) {
+    let request = String::from_utf8_lossy(request);
+    let mut words = request.lines().next().unwrap_or("").split(' ');
+    let (method, url) = (words.next().unwrap_or(""), words.next().unwrap_or(""));
+    println!("[httpd] {} {}", method, url);
+    if method != "GET" {
+        send_error(fd, "501 Not Implemented");
+        return;
+    }
+    let name = url.trim_start_matches('/');
+    if name.is_empty() {
+        if !send_file(fd, "index.html") {
+            let body = "<html><body><h1>rCore httpd</h1>\
+                <p>Request /&lt;file&gt; to get a file in easy-fs.</p></body></html>\n";
+            send_response(fd, "200 OK", "text/html", body.as_bytes());
+        }
+    } else if name.contains('/') || !send_file(fd, name) {
+        send_error(fd, "404 Not Found");
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let listen_fd = listen(PORT);
+    if listen_fd < 0 {
+        println!("[httpd] Failed to listen on port {}", PORT);
+        return -1;
+    }
+    let listen_fd = listen_fd as usize;
+    println!("[httpd] listening on port {}", PORT);
+    let mut clients: Vec<Client> = Vec::new();
+    loop {
+        let mut fds: Vec<PollFd> = Vec::new();
+        fds.push(PollFd::new(listen_fd, PollEvents::POLLIN));
+        for client in clients.iter() {
+            fds.push(PollFd::new(client.fd, PollEvents::POLLIN));
+        }
+        if poll(&mut fds, -1) < 0 {
+            println!("[httpd] poll failed");
+            return -1;
+        }
+        // serve ready clients before accepting new ones
+        let mut finished: Vec<usize> = Vec::new();
+        for (i, poll_fd) in fds.iter().enumerate().skip(1) {
+            if poll_fd.revents.is_empty() {
+                continue;
+            }
+            let client = &mut clients[i - 1];
+            let mut buf = [0u8; MAX_REQUEST_LEN];
+            let len = read(client.fd, &mut buf);
+            if len <= 0 {
+                finished.push(i - 1);
+                continue;
+            }
+            client.request.extend_from_slice(&buf[..len as usize]);
+            if client.request.windows(4).any(|w| w == b"\r\n\r\n") {
+                handle_request(client.fd, &client.request);
+                finished.push(i - 1);
+            } else if client.request.len() > MAX_REQUEST_LEN {
+                send_error(client.fd, "400 Bad Request");
+                finished.push(i - 1);
+            }
+        }
+        for &i in finished.iter().rev() {
+            close(clients.remove(i).fd);
+        }
+        if fds[0].revents.contains(PollEvents::POLLIN) {
+            let fd = accept(listen_fd);
+            if fd < 0 {
+                println!("[httpd] Failed to accept a client");
+                continue;
+            }
+            clients.push(Client {
+                fd: fd as usize,
+                request: Vec::new(),
+            });
+        }
+    }
+}
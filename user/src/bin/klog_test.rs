@@ -0,0 +1,54 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{dmesg, exit, fork, klog_map, waitpid, KlogReader};
+
+/// Whether `text` contains `pattern`.
+fn contains(text: &[u8], pattern: &[u8]) -> bool {
+    text.windows(pattern.len()).any(|window| window == pattern)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut reader = KlogReader::new();
+    // mapped once
+    assert_eq!(klog_map(), klog_map());
+    // the kernel has printed when booting
+    assert!(reader.head() > 0);
+    reader.skip_to_end();
+    let head = reader.head();
+    // what processes print is not in the kernel log
+    println!("klog_test: not in the kernel log");
+
+    // the mapping is inherited, and read-only
+    let pid = fork();
+    if pid == 0 {
+        assert!(reader.head() >= head);
+        unsafe {
+            (klog_map() as *mut u8).write_volatile(0);
+        }
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -11);
+
+    // and the kernel has logged how the child was killed
+    let mut buf = [0u8; 256];
+    let (len, lost) = reader.read(&mut buf);
+    assert_eq!(lost, 0);
+    assert!(contains(&buf[..len], b"SIGSEGV"));
+    assert!(!contains(&buf[..len], b"klog_test"));
+    assert_eq!(reader.read(&mut buf), (0, 0));
+
+    // the newest bytes of the log, where the message has its level
+    let len = dmesg(&mut buf);
+    assert_eq!(len, buf.len() as isize);
+    assert!(contains(&buf, b"<4>[kernel]"));
+    assert!(contains(&buf, b"SIGSEGV"));
+    println!("klog_test passed!");
+    0
+}
@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+#[macro_use]
+extern crate user_lib;
+
+use alloc::format;
+use user_lib::{open, sleep, write, KlogReader, OpenFlags};
+
+/// How long to wait when there is nothing new in the log.
+const POLL_INTERVAL_MS: usize = 100;
+
+/// `klogd [file]`, append the kernel log to a file, `klog` by default, as
+/// it is written. Run it in the background, it never exits.
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let path = if argc > 1 { argv[1] } else { "klog" };
+    let path = format!("{}\0", path);
+    // CREATE clears the file, so only for a new one
+    let mut fd = open(path.as_str(), OpenFlags::WRONLY | OpenFlags::APPEND);
+    if fd < 0 {
+        fd = open(path.as_str(), OpenFlags::CREATE | OpenFlags::WRONLY);
+    }
+    if fd < 0 {
+        println!("klogd: cannot open {}", path);
+        return -1;
+    }
+    let fd = fd as usize;
+    let mut reader = KlogReader::new();
+    let mut buf = [0u8; 1024];
+    loop {
+        let (len, lost) = reader.read(&mut buf);
+        if lost > 0 {
+            write(fd, format!("\n[klogd] {} bytes lost\n", lost).as_bytes());
+        }
+        if len == 0 {
+            sleep(POLL_INTERVAL_MS);
+            continue;
+        }
+        write(fd, &buf[..len]);
+    }
+}
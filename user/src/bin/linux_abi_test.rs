@@ -0,0 +1,129 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{linux_syscall, personality, PER_LINUX, PER_RCORE};
+
+const SYSCALL_CLOSE: usize = 57;
+const SYSCALL_OPENAT: usize = 56;
+const SYSCALL_PIPE2: usize = 59;
+const SYSCALL_READ: usize = 63;
+const SYSCALL_WRITE: usize = 64;
+const SYSCALL_WRITEV: usize = 66;
+const SYSCALL_EXIT_GROUP: usize = 94;
+const SYSCALL_CLOCK_GETTIME: usize = 113;
+const SYSCALL_UNAME: usize = 160;
+const SYSCALL_GETUID: usize = 174;
+const SYSCALL_BRK: usize = 214;
+const SYSCALL_MUNMAP: usize = 215;
+const SYSCALL_CLONE: usize = 220;
+const SYSCALL_MMAP: usize = 222;
+const SYSCALL_WAIT4: usize = 260;
+
+const ENOENT: isize = 2;
+const AT_FDCWD: isize = -100;
+const CLOCK_MONOTONIC: usize = 1;
+const SIGCHLD: usize = 17;
+const PAGE_SIZE: usize = 4096;
+
+fn syscall(id: usize, args: [usize; 3]) -> isize {
+    linux_syscall(id, [args[0], args[1], args[2], 0, 0, 0])
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(personality(PER_LINUX), PER_RCORE as isize);
+
+    let mut utsname = [0u8; 65 * 6];
+    assert_eq!(
+        syscall(SYSCALL_UNAME, [utsname.as_mut_ptr() as usize, 0, 0]),
+        0
+    );
+    assert_eq!(&utsname[65 * 4..65 * 4 + 8], b"riscv64\0");
+    assert_eq!(syscall(SYSCALL_GETUID, [0; 3]), 0);
+    let mut timespec = [0usize; 2];
+    assert_eq!(
+        syscall(
+            SYSCALL_CLOCK_GETTIME,
+            [CLOCK_MONOTONIC, timespec.as_mut_ptr() as usize, 0]
+        ),
+        0
+    );
+    assert!(timespec[1] < 1_000_000_000);
+
+    // the heap grows with brk
+    let brk = syscall(SYSCALL_BRK, [0; 3]) as usize;
+    let new_brk = brk + 2 * PAGE_SIZE + 1;
+    assert_eq!(syscall(SYSCALL_BRK, [new_brk, 0, 0]) as usize, new_brk);
+    let heap = unsafe { core::slice::from_raw_parts_mut(brk as *mut u8, new_brk - brk) };
+    heap.fill(0x5a);
+    assert!(heap.iter().all(|byte| *byte == 0x5a));
+
+    // anonymous mappings are zeroed
+    let len = 3 * PAGE_SIZE;
+    let addr = linux_syscall(SYSCALL_MMAP, [0, len, 0x3, 0x22, usize::MAX, 0]);
+    assert!(addr > 0);
+    let mapping = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, len) };
+    assert!(mapping.iter().all(|byte| *byte == 0));
+    mapping.fill(0xa5);
+    assert_eq!(syscall(SYSCALL_MUNMAP, [addr as usize, len, 0]), 0);
+
+    // errors are negative errnos
+    let path = "linux_abi_test_no_such_file\0";
+    assert_eq!(
+        linux_syscall(
+            SYSCALL_OPENAT,
+            [AT_FDCWD as usize, path.as_ptr() as usize, 0, 0, 0, 0]
+        ),
+        -ENOENT
+    );
+
+    // fds of pipe2 are ints
+    let mut fds = [-1i32; 2];
+    assert_eq!(syscall(SYSCALL_PIPE2, [fds.as_mut_ptr() as usize, 0, 0]), 0);
+    let msg = b"linux";
+    assert_eq!(
+        syscall(
+            SYSCALL_WRITE,
+            [fds[1] as usize, msg.as_ptr() as usize, msg.len()]
+        ),
+        msg.len() as isize
+    );
+    let mut buf = [0u8; 5];
+    assert_eq!(
+        syscall(
+            SYSCALL_READ,
+            [fds[0] as usize, buf.as_mut_ptr() as usize, buf.len()]
+        ),
+        msg.len() as isize
+    );
+    assert_eq!(&buf, msg);
+    syscall(SYSCALL_CLOSE, [fds[0] as usize, 0, 0]);
+    syscall(SYSCALL_CLOSE, [fds[1] as usize, 0, 0]);
+
+    let parts: [&[u8]; 2] = [b"linux ", b"writev\n"];
+    let iov = parts.map(|part| [part.as_ptr() as usize, part.len()]);
+    assert_eq!(syscall(SYSCALL_WRITEV, [1, iov.as_ptr() as usize, 2]), 13);
+
+    // fork with clone and the exit status of wait4
+    let pid = syscall(SYSCALL_CLONE, [SIGCHLD, 0, 0]);
+    if pid == 0 {
+        syscall(SYSCALL_EXIT_GROUP, [3, 0, 0]);
+        unreachable!();
+    }
+    let mut wstatus = 0i32;
+    assert_eq!(
+        syscall(
+            SYSCALL_WAIT4,
+            [pid as usize, &mut wstatus as *mut i32 as usize, 0]
+        ),
+        pid
+    );
+    assert_eq!(wstatus, 3 << 8);
+
+    assert_eq!(personality(PER_RCORE), PER_LINUX as isize);
+    println!("linux_abi_test passed!");
+    0
+}
@@ -0,0 +1,37 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+// linuxexec <app> [args...]: run a statically linked Linux binary, e.g. one
+// built with riscv64-linux-musl-gcc -static and packed into the file system.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{exec, personality, PER_LINUX, PER_RCORE};
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc < 2 {
+        println!("Usage: linuxexec <app> [args...]");
+        return -1;
+    }
+    let args: Vec<String> = argv[1..argc]
+        .iter()
+        .map(|arg| {
+            let mut arg = String::from(*arg);
+            arg.push('\0');
+            arg
+        })
+        .collect();
+    let mut args_addr: Vec<*const u8> = args.iter().map(|arg| arg.as_ptr()).collect();
+    args_addr.push(core::ptr::null::<u8>());
+    // execve of Linux takes the same arguments, with envp ignored
+    personality(PER_LINUX);
+    exec(args[0].as_str(), args_addr.as_slice());
+    personality(PER_RCORE);
+    println!("linuxexec: cannot run {}", argv[1]);
+    -1
+}
@@ -0,0 +1,94 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use user_lib::{
+    close, exit, fork, getpid, memusage, open, pipe, read, waitpid, write, MemUsage, OpenFlags,
+    SysError,
+};
+
+fn read_status(pid: usize) -> String {
+    let path = format!("/proc/{}/status\0", pid);
+    let fd = open(path.as_str(), OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut status = String::new();
+    let mut buf = [0u8; 64];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        status.push_str(core::str::from_utf8(&buf[..len as usize]).unwrap());
+    }
+    close(fd as usize);
+    status
+}
+
+/// The value of a line like "VmRSS:\t12 kB".
+fn status_field(status: &str, name: &str) -> usize {
+    let line = status.lines().find(|line| line.starts_with(name)).unwrap();
+    line[name.len()..]
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .unwrap()
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut usage = MemUsage::default();
+    assert_eq!(memusage(0, &mut usage), 0);
+    assert!(usage.resident > 0 && usage.resident <= usage.virt);
+    // the program, the user stack and the trap context at least
+    assert!(usage.areas >= 3);
+
+    let pid = getpid() as usize;
+    let status = read_status(pid);
+    assert_eq!(status_field(&status, "Pid:"), pid);
+    assert_eq!(status_field(&status, "VmSize:"), usage.virt / 1024);
+    assert_eq!(status_field(&status, "VmRSS:"), usage.resident / 1024);
+    assert_eq!(status_field(&status, "Areas:"), usage.areas);
+    // the status of proc files cannot be written
+    assert_eq!(
+        open("/proc/1/status\0", OpenFlags::WRONLY),
+        SysError::Denied.code()
+    );
+    assert_eq!(
+        open("/proc/99999/status\0", OpenFlags::RDONLY),
+        SysError::NoEntry.code()
+    );
+
+    // a forked child copies the space but shares the program text
+    let mut pipe_fd = [0usize; 2];
+    pipe(&mut pipe_fd);
+    let child = fork();
+    if child == 0 {
+        // keep alive until measured
+        close(pipe_fd[1]);
+        let mut byte = [0u8; 1];
+        read(pipe_fd[0], &mut byte);
+        exit(0);
+    }
+    close(pipe_fd[0]);
+    let mut child_usage = MemUsage::default();
+    assert_eq!(memusage(child as usize, &mut child_usage), 0);
+    assert_eq!(child_usage.virt, usage.virt);
+    assert!(child_usage.shared > 0);
+    write(pipe_fd[1], b"x");
+    close(pipe_fd[1]);
+    let mut exit_code = 0;
+    waitpid(child as usize, &mut exit_code);
+    assert_eq!(
+        memusage(child as usize, &mut child_usage),
+        SysError::NoProcess.code()
+    );
+    println!("{}", status);
+    println!("memusage_test passed!");
+    0
+}
@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::sync::Arc;
+use easy_fs::{block_cache_sync_all, BlockDevice, EasyFileSystem};
+use user_lib::{close, open, FdBlockDevice, OpenFlags};
+
+/// `mkfs_easyfs <device> [inode bitmap blocks]`, there are 4096 inodes for
+/// each block of the inode bitmap.
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if !(2..=3).contains(&argc) {
+        println!("usage: mkfs_easyfs <device> [inode bitmap blocks]");
+        return -1;
+    }
+    let inode_bitmap_blocks = match argv.get(2).map_or(Ok(1), |arg| arg.parse()) {
+        Ok(blocks) if blocks > 0 => blocks,
+        _ => {
+            println!("mkfs_easyfs: bad number of inode bitmap blocks");
+            return -1;
+        }
+    };
+    let fd = open(argv[1], OpenFlags::RDWR);
+    if fd < 0 {
+        println!("mkfs_easyfs: cannot open {} for writing", argv[1]);
+        return -1;
+    }
+    let device = Arc::new(FdBlockDevice(fd as usize));
+    let total_blocks = device.num_blocks() as u32;
+    // a block of the inode bitmap and 1024 blocks of inodes for it
+    let inode_blocks = inode_bitmap_blocks * 1025;
+    // with the super block, the data bitmap and a data block at least
+    if total_blocks < inode_blocks + 3 {
+        println!("mkfs_easyfs: {} is too small", argv[1]);
+        close(fd as usize);
+        return -1;
+    }
+    EasyFileSystem::create(device, total_blocks, inode_bitmap_blocks);
+    block_cache_sync_all();
+    close(fd as usize);
+    println!(
+        "{}: {} blocks, {} inodes",
+        argv[1],
+        total_blocks,
+        inode_bitmap_blocks * 4096
+    );
+    0
+}
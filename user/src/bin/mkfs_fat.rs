@@ -0,0 +1,46 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::sync::Arc;
+use easy_fs::{block_cache_sync_all, BlockDevice};
+use fat_fs::FatFileSystem;
+use user_lib::{close, open, FdBlockDevice, OpenFlags};
+
+/// The reserved sectors, the FATs and a cluster at least.
+const MIN_SECTORS: usize = 64;
+
+/// `mkfs_fat <device>`, FAT32 on the whole device as `mkfs.fat -F 32` makes
+/// it.
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc != 2 {
+        println!("usage: mkfs_fat <device>");
+        return -1;
+    }
+    let fd = open(argv[1], OpenFlags::RDWR);
+    if fd < 0 {
+        println!("mkfs_fat: cannot open {} for writing", argv[1]);
+        return -1;
+    }
+    let device = Arc::new(FdBlockDevice(fd as usize));
+    let total_sectors = device.num_blocks().min(u32::MAX as usize);
+    if total_sectors < MIN_SECTORS {
+        println!("mkfs_fat: {} is too small", argv[1]);
+        close(fd as usize);
+        return -1;
+    }
+    let fs = FatFileSystem::format(device, total_sectors as u32);
+    block_cache_sync_all();
+    close(fd as usize);
+    println!(
+        "{}: {} sectors, {} clusters free",
+        argv[1],
+        total_sectors,
+        fs.lock().free_clusters()
+    );
+    0
+}
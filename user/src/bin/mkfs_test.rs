@@ -0,0 +1,45 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::sync::Arc;
+use easy_fs::{block_cache_sync_all, EasyFileSystem};
+use user_lib::{close, open, run, FdBlockDevice, OpenFlags, SysError};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // the disk of the root is read-only while mounted
+    assert_eq!(open("/dev/vda\0", OpenFlags::RDWR), SysError::Denied.code());
+    assert_eq!(
+        open("/dev/vdb\0", OpenFlags::RDONLY),
+        SysError::NoEntry.code()
+    );
+    assert_eq!(run(&["fsck_easyfs\0", "/dev/vda\0"]), 0);
+
+    assert_eq!(run(&["mkfs_easyfs\0", "/dev/ram0\0"]), 0);
+    assert_eq!(run(&["fsck_easyfs\0", "/dev/ram0\0"]), 0);
+
+    // use the new file system in this process
+    let fd = open("/dev/ram0\0", OpenFlags::RDWR);
+    assert!(fd > 0);
+    let device = Arc::new(FdBlockDevice(fd as usize));
+    let efs = EasyFileSystem::open(device.clone());
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let dir = root_inode.create_dir("dir").unwrap();
+    let file = dir.create("file").unwrap();
+    assert_eq!(file.write_at(0, b"made by mkfs_test"), 17);
+    let report = EasyFileSystem::check(device);
+    assert!(report.is_clean());
+    assert_eq!((report.dirs, report.files, report.blocks), (2, 1, 3));
+
+    // a block marked used by no inode
+    efs.lock().alloc_data();
+    block_cache_sync_all();
+    close(fd as usize);
+    assert_eq!(run(&["fsck_easyfs\0", "/dev/ram0\0"]), 1);
+    println!("mkfs_test passed!");
+    0
+}
@@ -0,0 +1,154 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::{
+    close, exit, fork, mmap, munmap, open, read, waitpid, write, OpenFlags, SysError,
+    MAP_ANONYMOUS, MAP_FAILED, MAP_FIXED, MAP_PRIVATE, MAP_SHARED, PROT_READ, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 4096;
+/// across pages, the last one partly
+const LEN: usize = 6000;
+
+fn pattern(i: usize) -> u8 {
+    (i % 251) as u8
+}
+
+fn bytes(addr: usize, len: usize) -> &'static mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, len) }
+}
+
+fn anonymous() {
+    let addr = mmap(
+        0,
+        3 * PAGE_SIZE,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        0,
+        0,
+    );
+    assert_ne!(addr, MAP_FAILED);
+    let addr = addr as usize;
+    let data = bytes(addr, 3 * PAGE_SIZE);
+    assert!(data.iter().all(|&byte| byte == 0));
+    data.fill(7);
+    // taken, so mapped elsewhere unless it is fixed
+    let flags = MAP_PRIVATE | MAP_ANONYMOUS;
+    let other = mmap(addr, PAGE_SIZE, PROT_READ, flags, 0, 0);
+    assert!(other != MAP_FAILED && other as usize != addr);
+    assert_eq!(munmap(other as usize, PAGE_SIZE), 0);
+    assert_eq!(
+        mmap(addr, PAGE_SIZE, PROT_READ, flags | MAP_FIXED, 0, 0),
+        MAP_FAILED
+    );
+    // a hole in the middle, then mapped again
+    assert_eq!(munmap(addr + PAGE_SIZE, PAGE_SIZE), 0);
+    let middle = mmap(
+        addr + PAGE_SIZE,
+        PAGE_SIZE,
+        PROT_READ | PROT_WRITE,
+        flags | MAP_FIXED,
+        0,
+        0,
+    );
+    assert_eq!(middle as usize, addr + PAGE_SIZE);
+    assert!(bytes(addr + PAGE_SIZE, PAGE_SIZE)
+        .iter()
+        .all(|&byte| byte == 0));
+    assert_eq!(bytes(addr, 1)[0], 7);
+    assert_eq!(bytes(addr + 2 * PAGE_SIZE, 1)[0], 7);
+    // the child has a copy
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(bytes(addr, 1)[0], 7);
+        bytes(addr, 1)[0] = 8;
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(bytes(addr, 1)[0], 7);
+    assert_eq!(munmap(addr, 3 * PAGE_SIZE), 0);
+}
+
+/// The pages of the user stacks, mapped or not, are left alone.
+fn stacks() {
+    let local = 0u8;
+    let stack = &local as *const u8 as usize & !(PAGE_SIZE - 1);
+    let flags = MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED;
+    // the growth room of the main stack, and the stacks of threads to come
+    for addr in [stack - 4 * PAGE_SIZE, stack + 4 * PAGE_SIZE] {
+        assert_eq!(
+            mmap(addr, PAGE_SIZE, PROT_READ | PROT_WRITE, flags, 0, 0),
+            MAP_FAILED
+        );
+    }
+    let elsewhere = mmap(
+        stack + 4 * PAGE_SIZE,
+        PAGE_SIZE,
+        PROT_READ,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        0,
+        0,
+    );
+    assert!(elsewhere != MAP_FAILED && elsewhere as usize != stack + 4 * PAGE_SIZE);
+    assert_eq!(munmap(elsewhere as usize, PAGE_SIZE), 0);
+    assert_eq!(munmap(stack, PAGE_SIZE), SysError::Invalid.code());
+    assert_eq!(
+        munmap(stack - 4 * PAGE_SIZE, 8 * PAGE_SIZE),
+        SysError::Invalid.code()
+    );
+}
+
+fn file() {
+    let data: Vec<u8> = (0..LEN).map(pattern).collect();
+    let fd = open("mmap_test_file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, &data), LEN as isize);
+    close(fd as usize);
+    let fd = open("mmap_test_file\0", OpenFlags::RDONLY) as usize;
+
+    // a private copy, zeros beyond the end of the file
+    let addr = mmap(0, 2 * PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_PRIVATE, fd, 0);
+    assert_ne!(addr, MAP_FAILED);
+    let mapped = bytes(addr as usize, 2 * PAGE_SIZE);
+    assert_eq!(&mapped[..LEN], data.as_slice());
+    assert!(mapped[LEN..].iter().all(|&byte| byte == 0));
+    mapped[0] = 0xff;
+    let mut byte = [0u8; 1];
+    assert_eq!(read(fd, &mut byte), 1);
+    assert_eq!(byte[0], pattern(0));
+    assert_eq!(munmap(addr as usize, 2 * PAGE_SIZE), 0);
+
+    // from an offset, shared with the page cache
+    let addr = mmap(0, PAGE_SIZE, PROT_READ, MAP_SHARED, fd, PAGE_SIZE);
+    assert_ne!(addr, MAP_FAILED);
+    let mapped = bytes(addr as usize, LEN - PAGE_SIZE);
+    assert_eq!(mapped, &data[PAGE_SIZE..]);
+    assert_eq!(munmap(addr as usize, PAGE_SIZE), 0);
+
+    // the changes of a shared one would not be written back
+    assert_eq!(
+        mmap(0, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0),
+        MAP_FAILED
+    );
+    assert_eq!(
+        mmap(0, PAGE_SIZE, PROT_READ, MAP_PRIVATE, fd, 1),
+        MAP_FAILED
+    );
+    close(fd);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    anonymous();
+    stacks();
+    file();
+    println!("mmap_test passed!");
+    0
+}
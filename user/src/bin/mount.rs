@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use user_lib::{mount, MS_NOATIME, MS_RDONLY, MS_REMOUNT, MS_SYNCHRONOUS};
+
+const USAGE: &str =
+    "usage: mount -t easyfs|vfat [-o options] <device> <dir>\n       mount -o remount[,options] <dir>";
+
+/// `mount -t easyfs|vfat [-o ro|rw,sync|async,noatime|atime] <device>
+/// <dir>`, or `mount -o remount[,...] <dir>` to change how `dir` is mounted.
+/// File systems are mounted `rw,sync,atime` by default, like the root at
+/// boot.
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let mut fstype = None;
+    let mut flags = MS_SYNCHRONOUS;
+    let mut i = 1;
+    while i + 1 < argc && argv[i].starts_with('-') {
+        match argv[i] {
+            "-t" => fstype = Some(argv[i + 1]),
+            "-o" => {
+                for option in argv[i + 1].split(',') {
+                    match option {
+                        "remount" => flags |= MS_REMOUNT,
+                        "ro" => flags |= MS_RDONLY,
+                        "rw" => flags &= !MS_RDONLY,
+                        "sync" => flags |= MS_SYNCHRONOUS,
+                        "async" => flags &= !MS_SYNCHRONOUS,
+                        "noatime" => flags |= MS_NOATIME,
+                        "atime" => flags &= !MS_NOATIME,
+                        _ => {
+                            println!("mount: unknown option {}", option);
+                            return -1;
+                        }
+                    }
+                }
+            }
+            _ => {
+                println!("{}", USAGE);
+                return -1;
+            }
+        }
+        i += 2;
+    }
+    let args = &argv[i..argc];
+    let result = match (args, fstype) {
+        ([target], None) if flags & MS_REMOUNT != 0 => {
+            mount("\0", format!("{}\0", target).as_str(), "\0", flags)
+        }
+        ([source, target], Some(fstype)) if flags & MS_REMOUNT == 0 => mount(
+            format!("{}\0", source).as_str(),
+            format!("{}\0", target).as_str(),
+            format!("{}\0", fstype).as_str(),
+            flags,
+        ),
+        _ => {
+            println!("{}", USAGE);
+            return -1;
+        }
+    };
+    if result != 0 {
+        println!("mount: failed to mount {}", args[args.len() - 1]);
+        return -1;
+    }
+    0
+}
@@ -0,0 +1,141 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, mkdir, mount, open, read, rename, run, setuid, umount, waitpid, write,
+    OpenFlags, SysError, MS_NOATIME, MS_RDONLY, MS_REMOUNT, MS_SYNCHRONOUS,
+};
+
+const CONTENT: &[u8] = b"written before ro";
+const INNER: &[u8] = b"written to the mounted disk";
+
+/// Remount `target` with `flags`.
+fn remount(target: &str, flags: usize) -> isize {
+    mount("\0", target, "\0", MS_REMOUNT | flags)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("mount_test_file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, CONTENT), CONTENT.len() as isize);
+    close(fd as usize);
+
+    // only what is mounted can be remounted
+    assert_eq!(
+        mount("\0", "/\0", "\0", MS_RDONLY),
+        SysError::NoDevice.code()
+    );
+    assert_eq!(
+        remount("/mount_test_file\0", MS_RDONLY),
+        SysError::Invalid.code()
+    );
+
+    assert_eq!(remount("/\0", MS_RDONLY), 0);
+    // nothing can be changed
+    assert_eq!(
+        open("mount_test_file\0", OpenFlags::WRONLY),
+        SysError::ReadOnly.code()
+    );
+    assert_eq!(
+        open("mount_test_file\0", OpenFlags::RDONLY | OpenFlags::TRUNC),
+        SysError::ReadOnly.code()
+    );
+    assert_eq!(
+        open("mount_test_new\0", OpenFlags::CREATE | OpenFlags::WRONLY),
+        SysError::ReadOnly.code()
+    );
+    assert_eq!(mkdir("mount_test_dir\0"), SysError::ReadOnly.code());
+    // but files are still read
+    let fd = open("mount_test_file\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut buf = [0u8; 32];
+    assert_eq!(read(fd as usize, &mut buf), CONTENT.len() as isize);
+    assert_eq!(&buf[..CONTENT.len()], CONTENT);
+    close(fd as usize);
+
+    assert_eq!(remount("/\0", MS_NOATIME), 0);
+    let fd = open("mount_test_file\0", OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd > 0);
+    close(fd as usize);
+
+    // back to the mount at boot
+    assert_eq!(remount("/\0", MS_SYNCHRONOUS), 0);
+
+    // a file system made on the spare disk, mounted on a directory
+    assert_eq!(run(&["mkfs_easyfs\0", "/dev/ram0\0"]), 0);
+    mkdir("/mount_test_mnt\0");
+    let (disk, dir) = ("/dev/ram0\0", "/mount_test_mnt\0");
+    assert_eq!(
+        mount(disk, dir, "fat32\0", MS_SYNCHRONOUS),
+        SysError::NoDevice.code()
+    );
+    assert_eq!(
+        mount("/dev/vda\0", dir, "easyfs\0", MS_SYNCHRONOUS),
+        SysError::Busy.code()
+    );
+    assert_eq!(
+        mount(disk, "/mount_test_file\0", "easyfs\0", 0),
+        SysError::NotDir.code()
+    );
+    let pid = fork();
+    if pid == 0 {
+        setuid(1);
+        exit(mount(disk, dir, "easyfs\0", MS_SYNCHRONOUS) as i32);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, SysError::NotPermitted.code() as i32);
+    assert_eq!(mount(disk, dir, "easyfs\0", MS_SYNCHRONOUS), 0);
+    assert_eq!(
+        mount(disk, "/\0", "easyfs\0", MS_SYNCHRONOUS),
+        SysError::Busy.code()
+    );
+    let fd = open(
+        "/mount_test_mnt/inner\0",
+        OpenFlags::CREATE | OpenFlags::WRONLY,
+    );
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, INNER), INNER.len() as isize);
+    // busy while a file in it is open
+    assert_eq!(umount(dir), SysError::Busy.code());
+    close(fd as usize);
+    assert_eq!(rename(dir, "/mount_test_dir\0"), SysError::Busy.code());
+    // paths cross the mount point back to the root
+    let fd = open("/mount_test_mnt/../mount_test_file\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    // mounted read-only while the root is not
+    assert_eq!(remount(dir, MS_RDONLY), 0);
+    assert_eq!(
+        open(
+            "/mount_test_mnt/new\0",
+            OpenFlags::CREATE | OpenFlags::WRONLY
+        ),
+        SysError::ReadOnly.code()
+    );
+    let fd = open("mount_test_file\0", OpenFlags::WRONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+
+    assert_eq!(umount("/\0"), SysError::Busy.code());
+    assert_eq!(umount(dir), 0);
+    assert_eq!(umount(dir), SysError::Invalid.code());
+    // the directory is seen again, and the file is kept on the disk
+    assert_eq!(
+        open("/mount_test_mnt/inner\0", OpenFlags::RDONLY),
+        SysError::NoEntry.code()
+    );
+    assert_eq!(mount(disk, dir, "easyfs\0", MS_SYNCHRONOUS), 0);
+    let fd = open("/mount_test_mnt/inner\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    assert_eq!(read(fd as usize, &mut buf), INNER.len() as isize);
+    assert_eq!(&buf[..INNER.len()], INNER);
+    close(fd as usize);
+    assert_eq!(umount(dir), 0);
+    println!("mount_test passed!");
+    0
+}
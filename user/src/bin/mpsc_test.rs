@@ -0,0 +1,76 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use user_lib::mpsc::{channel, RecvError, SendError, Sender, TryRecvError};
+use user_lib::{exit, get_time, park, park_timeout, thread_create, unpark, waittid};
+
+const PRODUCERS: usize = 4;
+const PER_PRODUCER: usize = 100;
+
+static WAITING: AtomicBool = AtomicBool::new(false);
+
+fn producer(arg: *mut (usize, Sender<usize>)) -> ! {
+    let (id, sender) = *unsafe { Box::from_raw(arg) };
+    for i in 0..PER_PRODUCER {
+        sender.send(id * PER_PRODUCER + i).unwrap();
+    }
+    drop(sender);
+    exit(0)
+}
+
+fn unparker() -> ! {
+    while !WAITING.load(Ordering::SeqCst) {}
+    unpark(0);
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // the token is kept until the next park
+    unpark(0);
+    park();
+    let start = get_time();
+    assert!(!park_timeout(20_000_000));
+    assert!(get_time() - start >= 20);
+    // woken up by another thread
+    let tid = thread_create(unparker as usize, 0);
+    WAITING.store(true, Ordering::SeqCst);
+    park();
+    assert_eq!(waittid(tid as usize), 0);
+
+    // the values of each sender come in order, all of them once
+    let (sender, receiver) = channel::<usize>();
+    assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+    let mut tids = Vec::new();
+    for id in 0..PRODUCERS {
+        let arg = Box::into_raw(Box::new((id, sender.clone())));
+        tids.push(thread_create(producer as usize, arg as usize));
+    }
+    drop(sender);
+    let mut next = [0usize; PRODUCERS];
+    for value in receiver.iter() {
+        let id = value / PER_PRODUCER;
+        assert_eq!(value % PER_PRODUCER, next[id]);
+        next[id] += 1;
+    }
+    assert!(next.iter().all(|&count| count == PER_PRODUCER));
+    assert_eq!(receiver.recv(), Err(RecvError));
+    assert_eq!(receiver.try_recv(), Err(TryRecvError::Disconnected));
+    for tid in tids {
+        assert_eq!(waittid(tid as usize), 0);
+    }
+
+    // nobody receives once the receiver is dropped
+    let (sender, receiver) = channel();
+    drop(receiver);
+    assert_eq!(sender.send(1), Err(SendError(1)));
+    println!("mpsc_test passed!");
+    0
+}
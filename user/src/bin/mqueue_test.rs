@@ -0,0 +1,98 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fork, mq_getattr, mq_open, mq_receive, mq_send, mq_setattr, mq_unlink, poll, wait,
+    MqAttr, OpenFlags, PollEvents, PollFd,
+};
+
+const NAME: &str = "mqueue_test\0";
+const MAXMSG: usize = 4;
+const MSGSIZE: usize = 32;
+const ROUNDS: usize = 16;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // left by a previous failed run
+    mq_unlink(NAME);
+    let attr = MqAttr::new(MAXMSG, MSGSIZE);
+    let mqd = mq_open(
+        NAME,
+        OpenFlags::CREATE | OpenFlags::EXCL | OpenFlags::RDWR,
+        Some(&attr),
+    );
+    assert!(mqd > 0);
+    let mqd = mqd as usize;
+    assert!(
+        mq_open(
+            NAME,
+            OpenFlags::CREATE | OpenFlags::EXCL | OpenFlags::RDWR,
+            None
+        ) < 0
+    );
+    let mut buf = [0u8; MSGSIZE];
+    let mut prio = 0u32;
+    assert!(mq_send(mqd, &[0u8; MSGSIZE + 1], 0) < 0);
+    assert!(mq_receive(mqd, &mut buf[..MSGSIZE - 1], &mut prio) < 0);
+
+    // higher priority first, fifo within the same priority
+    assert_eq!(mq_send(mqd, b"low", 1), 0);
+    assert_eq!(mq_send(mqd, b"high", 5), 0);
+    assert_eq!(mq_send(mqd, b"low2", 1), 0);
+    assert_eq!(mq_send(mqd, b"mid", 3), 0);
+    let mut fds = [PollFd::new(mqd, PollEvents::POLLIN | PollEvents::POLLOUT)];
+    assert_eq!(poll(&mut fds, 0), 1);
+    assert_eq!(fds[0].revents, PollEvents::POLLIN);
+    for (expected, expected_prio) in [("high", 5), ("mid", 3), ("low", 1), ("low2", 1)] {
+        let len = mq_receive(mqd, &mut buf, &mut prio);
+        assert_eq!(&buf[..len as usize], expected.as_bytes());
+        assert_eq!(prio, expected_prio);
+    }
+
+    // an empty queue does not block in non-blocking mode
+    let mut old_attr = MqAttr::default();
+    let nonblock = MqAttr {
+        flags: OpenFlags::NONBLOCK.bits() as usize,
+        ..Default::default()
+    };
+    assert_eq!(mq_setattr(mqd, &nonblock, &mut old_attr), 0);
+    assert_eq!(old_attr.flags, 0);
+    assert_eq!(old_attr.maxmsg, MAXMSG);
+    assert_eq!(old_attr.msgsize, MSGSIZE);
+    assert_eq!(mq_receive(mqd, &mut buf, &mut prio), -2);
+    assert_eq!(mq_setattr(mqd, &MqAttr::default(), &mut old_attr), 0);
+    assert_eq!(old_attr.flags, OpenFlags::NONBLOCK.bits() as usize);
+
+    // the producer blocks when the queue is full, the consumer when empty
+    if fork() == 0 {
+        let producer = mq_open(NAME, OpenFlags::WRONLY, None);
+        assert!(producer > 0);
+        for i in 0..ROUNDS {
+            assert_eq!(mq_send(producer as usize, &[i as u8], 0), 0);
+        }
+        close(producer as usize);
+        return 0;
+    }
+    for i in 0..ROUNDS {
+        assert_eq!(mq_receive(mqd, &mut buf, &mut prio), 1);
+        assert_eq!(buf[0], i as u8);
+    }
+    let mut exit_code: i32 = 0;
+    wait(&mut exit_code);
+    assert_eq!(exit_code, 0);
+    let mut attr = MqAttr::default();
+    mq_getattr(mqd, &mut attr);
+    assert_eq!(attr.curmsgs, 0);
+
+    // the queue is still usable after unlinking
+    assert_eq!(mq_unlink(NAME), 0);
+    assert!(mq_open(NAME, OpenFlags::RDWR, None) < 0);
+    assert_eq!(mq_send(mqd, b"bye", 0), 0);
+    assert_eq!(mq_receive(mqd, &mut buf, &mut prio), 3);
+    close(mqd);
+    println!("mqueue_test passed!");
+    0
+}
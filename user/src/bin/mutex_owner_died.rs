@@ -0,0 +1,34 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, mutex_blocking_create, mutex_create, mutex_lock, mutex_unlock, sleep, thread_create,
+    waittid, EOWNERDEAD,
+};
+
+// exit while holding the mutex
+fn die_holding(mutex_id: usize) -> ! {
+    assert_eq!(mutex_lock(mutex_id), 0);
+    sleep(20);
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    for mutex_id in [mutex_blocking_create(), mutex_create()] {
+        let mutex_id = mutex_id as usize;
+        let thread = thread_create(die_holding as usize, mutex_id);
+        sleep(5);
+        // woken up when the owner exits, and told only once
+        assert_eq!(mutex_lock(mutex_id), EOWNERDEAD);
+        assert_eq!(mutex_unlock(mutex_id), 0);
+        assert_eq!(mutex_lock(mutex_id), 0);
+        assert_eq!(mutex_unlock(mutex_id), 0);
+        waittid(thread as usize);
+    }
+    println!("mutex_owner_died passed!");
+    0
+}
@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{get_time_ns, nanosleep};
+
+const ROUNDS: usize = 20;
+const SLEEP_NS: usize = 1_000_000;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let start = get_time_ns() as usize;
+    for _ in 0..ROUNDS {
+        let before = get_time_ns() as usize;
+        assert_eq!(nanosleep(SLEEP_NS), 0);
+        assert!(get_time_ns() as usize - before >= SLEEP_NS);
+    }
+    let elapsed = get_time_ns() as usize - start;
+    // far less than a time slice of 10ms for each sleep
+    assert!(
+        elapsed < ROUNDS * SLEEP_NS * 5,
+        "{} sleeps of 1ms took {}us",
+        ROUNDS,
+        elapsed / 1000
+    );
+    println!("nanosleep_test passed!");
+    0
+}
@@ -0,0 +1,155 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+// nc [-u] <ip> <port>: connect to a tcp (or udp) server
+// nc [-u] -l <port>: wait for a tcp (or udp) peer on the port
+//
+// Lines typed on stdin are sent to the peer and data received is printed.
+// e.g. run `nc -l 80` here and `nc localhost 6201` on the host, the host is
+// 10.0.2.2 from here.
+//
+// NOTICE: packets are received synchronously by the kernel, so when waiting
+// for the socket and stdin at the same time, input is noticed only after the
+// next packet arrives.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    accept, close, connect, getpid, listen, poll, read, tcp_connect, write, PollEvents, PollFd,
+};
+
+const STDIN: usize = 0;
+const LF: u8 = 0x0au8;
+const CR: u8 = 0x0du8;
+const DL: u8 = 0x7fu8;
+const BS: u8 = 0x08u8;
+const EOT: u8 = 0x04u8;
+
+fn usage() -> i32 {
+    println!("Usage: nc [-u] <ip> <port>");
+    println!("       nc [-u] -l <port>");
+    -1
+}
+
+fn parse_ip(s: &str) -> Option<u32> {
+    let mut ip = 0u32;
+    let mut count = 0;
+    for part in s.split('.') {
+        ip = ip << 8 | part.parse::<u8>().ok()? as u32;
+        count += 1;
+    }
+    if count == 4 {
+        Some(ip)
+    } else {
+        None
+    }
+}
+
+/// Return the socket fd, or a negative value on failure
+fn open_socket(udp: bool, listening: bool, ip: u32, port: u16) -> isize {
+    // pick a local port for connecting, differs between processes
+    let local_port = 40000 + (getpid() % 10000) as u16;
+    match (udp, listening) {
+        (false, false) => tcp_connect(ip, local_port, port),
+        (true, false) => connect(ip, local_port, port),
+        (false, true) => {
+            let listen_fd = listen(port);
+            if listen_fd < 0 {
+                return listen_fd;
+            }
+            let fd = accept(listen_fd as usize);
+            close(listen_fd as usize);
+            fd
+        }
+        // bound to the first peer sending to the port
+        (true, true) => connect(0, port, 0),
+    }
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let args: Vec<&str> = argv[1..argc].to_vec();
+    let udp = args.contains(&"-u");
+    let listening = args.contains(&"-l");
+    let rest: Vec<&str> = args
+        .iter()
+        .filter(|&&arg| arg != "-u" && arg != "-l")
+        .copied()
+        .collect();
+    let (ip, port) = match (listening, rest.as_slice()) {
+        (true, [port]) => (Some(0), port.parse::<u16>().ok()),
+        (false, [ip, port]) => (parse_ip(ip), port.parse::<u16>().ok()),
+        _ => return usage(),
+    };
+    let (ip, port) = match (ip, port) {
+        (Some(ip), Some(port)) => (ip, port),
+        _ => return usage(),
+    };
+
+    let fd = open_socket(udp, listening, ip, port);
+    if fd < 0 {
+        println!(
+            "nc: cannot open the {} socket",
+            if udp { "udp" } else { "tcp" }
+        );
+        return -1;
+    }
+    let fd = fd as usize;
+    // a udp socket waiting for any peer cannot send before receiving
+    let mut peer_known = !(udp && listening);
+    let mut line = String::new();
+    let mut buf = [0u8; 1024];
+    loop {
+        let mut fds = [
+            PollFd::new(STDIN, PollEvents::POLLIN),
+            PollFd::new(fd, PollEvents::POLLIN),
+        ];
+        if poll(&mut fds, -1) < 0 {
+            break;
+        }
+        if fds[1].revents.contains(PollEvents::POLLIN) {
+            let len = read(fd, &mut buf);
+            if len <= 0 {
+                println!("\nnc: connection closed by peer");
+                break;
+            }
+            peer_known = true;
+            print!("{}", String::from_utf8_lossy(&buf[..len as usize]));
+        }
+        if fds[0].revents.contains(PollEvents::POLLIN) {
+            let mut c = [0u8; 1];
+            read(STDIN, &mut c);
+            match c[0] {
+                CR | LF => {
+                    println!("");
+                    line.push('\n');
+                    if peer_known {
+                        write(fd, line.as_bytes());
+                    } else {
+                        println!("nc: no peer yet, line dropped");
+                    }
+                    line.clear();
+                }
+                BS | DL => {
+                    if line.pop().is_some() {
+                        print!("{}", BS as char);
+                        print!(" ");
+                        print!("{}", BS as char);
+                    }
+                }
+                EOT if line.is_empty() => break,
+                c if (b' '..DL).contains(&c) => {
+                    print!("{}", c as char);
+                    line.push(c as char);
+                }
+                _ => {}
+            }
+        }
+    }
+    close(fd);
+    0
+}
@@ -0,0 +1,45 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+// nice <nice> <app> [args...]: run the app with a nice value from -20 to 19,
+// e.g. `nice 19 matrix` runs matrix after everything else that is ready.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{exec, setpriority, PRIO_PROCESS};
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc < 3 {
+        println!("Usage: nice <nice> <app> [args...]");
+        return -1;
+    }
+    let nice = match argv[1].parse::<isize>() {
+        Ok(nice) => nice,
+        Err(_) => {
+            println!("nice: invalid nice value");
+            return -1;
+        }
+    };
+    if setpriority(PRIO_PROCESS, 0, nice) < 0 {
+        println!("nice: cannot set the nice value");
+        return -1;
+    }
+    let args: Vec<String> = argv[2..argc]
+        .iter()
+        .map(|arg| {
+            let mut arg = String::from(*arg);
+            arg.push('\0');
+            arg
+        })
+        .collect();
+    let mut args_addr: Vec<*const u8> = args.iter().map(|arg| arg.as_ptr()).collect();
+    args_addr.push(core::ptr::null::<u8>());
+    exec(args[0].as_str(), args_addr.as_slice());
+    println!("nice: cannot run {}", argv[2]);
+    -1
+}
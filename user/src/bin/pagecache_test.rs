@@ -0,0 +1,110 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use user_lib::{
+    close, lseek, mmap, munmap, open, read, unlink, write, OpenFlags, MAP_FAILED, MAP_SHARED,
+    PROT_READ, SEEK_SET,
+};
+
+const PAGE_SIZE: usize = 4096;
+const PATH: &str = "pagecache_test_file\0";
+/// three pages and a part of the fourth
+const LEN: usize = 3 * PAGE_SIZE + 100;
+
+fn pattern(i: usize) -> u8 {
+    (i % 251) as u8
+}
+
+/// The number of a line of /proc/vmstat by its name.
+fn vmstat(name: &str) -> usize {
+    let fd = open("/proc/vmstat\0", OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut buf = [0u8; 256];
+    let len = read(fd as usize, &mut buf) as usize;
+    close(fd as usize);
+    let text = core::str::from_utf8(&buf[..len]).unwrap();
+    let line = text
+        .lines()
+        .find(|line| line.split(' ').next() == Some(name))
+        .unwrap();
+    line.rsplit(' ').next().unwrap().parse().unwrap()
+}
+
+/// The whole file, read in pieces not aligned to pages.
+fn read_file() -> Vec<u8> {
+    let fd = open(PATH, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut data = Vec::new();
+    let mut buf = [0u8; 1000];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..len as usize]);
+    }
+    close(fd as usize);
+    data
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut data: Vec<u8> = (0..LEN).map(pattern).collect();
+    let fd = open(PATH, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, &data), LEN as isize);
+    close(fd as usize);
+
+    // read again from the page cache
+    assert_eq!(read_file(), data);
+    let hits = vmstat("pgcache_hit");
+    assert_eq!(read_file(), data);
+    assert!(vmstat("pgcache_hit") >= hits + 4);
+    assert!(vmstat("nr_file_pages") >= 4);
+
+    // a shared mapping keeps the page it mapped when the file is written
+    let fd = open(PATH, OpenFlags::RDWR);
+    assert!(fd >= 0);
+    let addr = mmap(0, PAGE_SIZE, PROT_READ, MAP_SHARED, fd as usize, PAGE_SIZE);
+    assert_ne!(addr, MAP_FAILED);
+    let mapped = unsafe { core::slice::from_raw_parts(addr as *const u8, PAGE_SIZE) };
+    assert_eq!(mapped[0], pattern(PAGE_SIZE));
+
+    // written across pages, the cached ones are written too
+    let changed = vec![0xa5u8; PAGE_SIZE + 10];
+    let start = PAGE_SIZE - 5;
+    assert_eq!(lseek(fd as usize, start as isize, SEEK_SET), start as isize);
+    assert_eq!(write(fd as usize, &changed), changed.len() as isize);
+    data[start..start + changed.len()].copy_from_slice(&changed);
+    // and beyond the end
+    assert_eq!(lseek(fd as usize, LEN as isize, SEEK_SET), LEN as isize);
+    assert_eq!(write(fd as usize, b"appended"), 8);
+    data.extend_from_slice(b"appended");
+    close(fd as usize);
+    assert_eq!(read_file(), data);
+    assert!(mapped
+        .iter()
+        .enumerate()
+        .all(|(i, &byte)| byte == pattern(PAGE_SIZE + i)));
+    assert_eq!(munmap(addr as usize, PAGE_SIZE), 0);
+
+    // truncated, nothing is left cached
+    let fd = open(PATH, OpenFlags::TRUNC | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    close(fd as usize);
+    assert!(read_file().is_empty());
+
+    assert_eq!(unlink(PATH), 0);
+    println!(
+        "pagecache_test passed! {} evicted since boot",
+        vmstat("pgcache_evict")
+    );
+    0
+}
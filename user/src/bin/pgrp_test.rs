@@ -0,0 +1,78 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, getpgid, getpid, getsid, setpgid, setsid, tcgetpgrp, tcsetpgrp, waitpid, SysError,
+};
+
+const NO_PROCESS: usize = 100000;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = getpid() as usize;
+    let old_pgid = getpgid(0) as usize;
+    let sid = getsid(0);
+    assert!(sid >= 0);
+    assert_eq!(getsid(pid), sid);
+    assert_eq!(getsid(NO_PROCESS), SysError::NoProcess.code());
+    let foreground = tcgetpgrp(0);
+    assert!(foreground >= 0);
+    assert_eq!(setpgid(0, 0), 0);
+    assert_eq!(getpgid(0) as usize, pid);
+    assert_eq!(getpgid(pid), pid as isize);
+    assert_eq!(getpgid(NO_PROCESS), SysError::NoProcess.code());
+    assert_eq!(setpgid(NO_PROCESS, pid), SysError::NoProcess.code());
+    assert_eq!(setpgid(0, NO_PROCESS), SysError::NotPermitted.code());
+    // a group leader cannot start a session
+    assert_eq!(setsid(), SysError::NotPermitted.code());
+    assert_eq!(getsid(0), sid);
+
+    let child = fork();
+    if child == 0 {
+        // inherited from the parent until it leads a group of its own
+        assert_eq!(getpgid(0) as usize, pid);
+        assert_eq!(setpgid(0, 0), 0);
+        assert_eq!(getpgid(0), getpid());
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    assert_eq!(exit_code, 0);
+
+    let child = fork();
+    if child == 0 {
+        let child_pid = getpid();
+        assert_eq!(setsid(), child_pid);
+        assert_eq!(getsid(0), child_pid);
+        assert_eq!(getpgid(0), child_pid);
+        // the groups and the console of the old session are out of reach
+        assert_eq!(setpgid(0, pid), SysError::NotPermitted.code());
+        if foreground > 0 {
+            assert_eq!(
+                tcsetpgrp(0, child_pid as usize),
+                SysError::NotPermitted.code()
+            );
+        }
+        exit(0);
+    }
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    assert_eq!(exit_code, 0);
+
+    assert_eq!(tcsetpgrp(0, 0), SysError::Invalid.code());
+    assert_eq!(tcsetpgrp(0, NO_PROCESS), SysError::NotPermitted.code());
+    assert_eq!(tcsetpgrp(0, pid), 0);
+    assert_eq!(tcgetpgrp(0), pid as isize);
+    // give the console back to the group running the test
+    let foreground = if foreground > 0 {
+        foreground as usize
+    } else {
+        old_pgid
+    };
+    assert_eq!(tcsetpgrp(0, foreground), 0);
+    assert_eq!(setpgid(0, old_pgid), 0);
+    println!("pgrp_test passed!");
+    0
+}
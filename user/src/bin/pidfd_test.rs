@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, pidfd_open, poll, read, sleep, waitpid, OpenFlags, PollEvents, PollFd,
+    SysError,
+};
+
+const CHILDREN: usize = 4;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut pids = [0usize; CHILDREN];
+    let mut fds = [PollFd::new(0, PollEvents::POLLIN); CHILDREN];
+    for i in 0..CHILDREN {
+        let pid = fork();
+        if pid == 0 {
+            // exit in the reverse order of forking
+            sleep(20 * (CHILDREN - i));
+            exit(i as i32 + 10);
+        }
+        pids[i] = pid as usize;
+        let fd = pidfd_open(pid as usize, OpenFlags::CLOEXEC);
+        assert!(fd >= 0);
+        fds[i] = PollFd::new(fd as usize, PollEvents::POLLIN);
+    }
+    // still running
+    let nonblock = pidfd_open(pids[0], OpenFlags::NONBLOCK);
+    assert!(nonblock >= 0);
+    let mut exit_code = [0u8; 4];
+    assert_eq!(read(nonblock as usize, &mut exit_code), 0);
+    assert_eq!(
+        pidfd_open(pids[0], OpenFlags::APPEND),
+        SysError::Invalid.code()
+    );
+
+    let mut exited = 0;
+    while exited < CHILDREN {
+        assert!(poll(&mut fds, -1) > 0);
+        for (i, fd) in fds.iter_mut().enumerate() {
+            if fd.fd < 0 || !fd.revents.contains(PollEvents::POLLIN) {
+                continue;
+            }
+            assert_eq!(read(fd.fd as usize, &mut exit_code), 4);
+            assert_eq!(i32::from_ne_bytes(exit_code), i as i32 + 10);
+            // exited, so waiting does not block
+            let mut code = 0;
+            assert_eq!(waitpid(pids[i], &mut code), pids[i] as isize);
+            assert_eq!(code, i as i32 + 10);
+            close(fd.fd as usize);
+            // ignored by poll from now on
+            fd.fd = -1;
+            exited += 1;
+        }
+    }
+    // the process is gone, the descriptor still tells the exit code
+    assert_eq!(read(nonblock as usize, &mut exit_code), 4);
+    assert_eq!(i32::from_ne_bytes(exit_code), 10);
+    close(nonblock as usize);
+    println!("pidfd_test passed!");
+    0
+}
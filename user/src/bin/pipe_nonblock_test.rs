@@ -0,0 +1,84 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fcntl, fork, pipe2, read, sleep, waitpid, write, OpenFlags, SysError, EAGAIN,
+    F_GETFL, F_GETPIPE_SZ, F_SETFL, F_SETPIPE_SZ,
+};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe2(&mut pipe_fd, OpenFlags::NONBLOCK), 0);
+    let (read_fd, write_fd) = (pipe_fd[0], pipe_fd[1]);
+    assert!(
+        OpenFlags::from_bits_truncate(fcntl(read_fd, F_GETFL, 0) as u32)
+            .contains(OpenFlags::NONBLOCK)
+    );
+
+    // nothing to read, no room to write
+    let mut buf = [0u8; 256];
+    assert_eq!(read(read_fd, &mut buf), EAGAIN);
+    let capacity = fcntl(write_fd, F_GETPIPE_SZ, 0) as usize;
+    assert_eq!(write(write_fd, &buf[..capacity + 8]), capacity as isize);
+    assert_eq!(write(write_fd, &buf[..1]), EAGAIN);
+
+    // the capacity holds what is in the pipe at least
+    assert_eq!(
+        fcntl(write_fd, F_SETPIPE_SZ, capacity - 1),
+        SysError::Busy.code()
+    );
+    assert_eq!(fcntl(write_fd, F_SETPIPE_SZ, 0), SysError::Invalid.code());
+    assert_eq!(
+        fcntl(write_fd, F_SETPIPE_SZ, 2 << 20),
+        SysError::Invalid.code()
+    );
+    assert_eq!(fcntl(write_fd, F_SETPIPE_SZ, 4096), 4096);
+    assert_eq!(fcntl(read_fd, F_GETPIPE_SZ, 0), 4096);
+    assert_eq!(write(write_fd, &[7u8; 100]), 100);
+    // only what is there is read
+    assert_eq!(read(read_fd, &mut buf), (capacity + 100) as isize);
+    assert!(buf[capacity..capacity + 100].iter().all(|&byte| byte == 7));
+    assert_eq!(read(read_fd, &mut buf), EAGAIN);
+
+    // blocking again, the reader waits for the writer
+    assert_eq!(fcntl(read_fd, F_SETFL, 0), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(read_fd);
+        sleep(50);
+        assert_eq!(write(write_fd, b"late"), 4);
+        exit(0);
+    }
+    close(write_fd);
+    assert_eq!(read(read_fd, &mut buf[..4]), 4);
+    assert_eq!(&buf[..4], b"late");
+    // at the end once the writer exits
+    assert_eq!(read(read_fd, &mut buf), 0);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    close(read_fd);
+
+    // the writer waiting for room stops once no reader is left
+    assert_eq!(pipe2(&mut pipe_fd, OpenFlags::empty()), 0);
+    let (read_fd, write_fd) = (pipe_fd[0], pipe_fd[1]);
+    let capacity = fcntl(write_fd, F_GETPIPE_SZ, 0) as usize;
+    let pid = fork();
+    if pid == 0 {
+        close(read_fd);
+        assert_eq!(write(write_fd, &buf[..capacity + 8]), capacity as isize);
+        assert_eq!(write(write_fd, &buf[..1]), SysError::BrokenPipe.code());
+        exit(0);
+    }
+    close(write_fd);
+    sleep(50);
+    close(read_fd);
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("pipe_nonblock_test passed!");
+    0
+}
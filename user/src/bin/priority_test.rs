@@ -0,0 +1,100 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, get_time, getpgid, getpid, getpriority, kill, setpriority, setuid, sleep, waitpid,
+    SignalFlags, SysError, NICE_MAX, NICE_MIN, PRIO_PGRP, PRIO_PROCESS,
+};
+
+/// A user other than root.
+const UID: usize = 1000;
+
+const HOGS: usize = 4;
+const HOG_MS: isize = 1000;
+const SLEEPS: isize = 20;
+const SLEEP_MS: isize = 10;
+
+fn busy_loop(ms: isize) {
+    let start = get_time();
+    while get_time() - start < ms {}
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(getpriority(PRIO_PROCESS, 0), Some(0));
+    assert_eq!(setpriority(PRIO_PROCESS, 0, 5), 0);
+    assert_eq!(getpriority(PRIO_PROCESS, getpid() as usize), Some(5));
+    assert_eq!(setpriority(PRIO_PROCESS, 0, 100), 0);
+    assert_eq!(getpriority(PRIO_PROCESS, 0), Some(NICE_MAX));
+    assert_eq!(setpriority(PRIO_PGRP, 0, -100), 0);
+    assert_eq!(getpriority(PRIO_PGRP, getpgid(0) as usize), Some(NICE_MIN));
+    assert_eq!(getpriority(PRIO_PROCESS, 100000), None);
+    assert!(setpriority(PRIO_PROCESS, 100000, 0) < 0);
+    assert!(setpriority(2, 0, 0) < 0);
+    assert_eq!(setpriority(PRIO_PROCESS, 0, 0), 0);
+
+    // a child takes the nice value of its parent
+    assert_eq!(setpriority(PRIO_PROCESS, 0, 3), 0);
+    let pid = fork();
+    if pid == 0 {
+        exit(getpriority(PRIO_PROCESS, 0).unwrap() as i32);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 3);
+    assert_eq!(setpriority(PRIO_PROCESS, 0, 0), 0);
+
+    // only root may lower it, or touch the processes of other users
+    let parent = getpid() as usize;
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(setuid(UID), 0);
+        assert_eq!(setpriority(PRIO_PROCESS, 0, 5), 0);
+        assert_eq!(setpriority(PRIO_PROCESS, 0, 4), SysError::Denied.code());
+        assert_eq!(
+            setpriority(PRIO_PROCESS, parent, 10),
+            SysError::NotPermitted.code()
+        );
+        assert_eq!(
+            kill(parent, SignalFlags::SIGTERM.bits()),
+            SysError::NotPermitted.code()
+        );
+        exit(0);
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(getpriority(PRIO_PROCESS, 0), Some(0));
+
+    // a process which mostly sleeps is not kept waiting by those computing
+    let mut hogs = [0usize; HOGS];
+    for hog in hogs.iter_mut() {
+        let pid = fork();
+        if pid == 0 {
+            busy_loop(HOG_MS);
+            exit(0);
+        }
+        *hog = pid as usize;
+    }
+    // let them use up some time slices
+    sleep(100);
+    let start = get_time();
+    for _ in 0..SLEEPS {
+        sleep(SLEEP_MS as usize);
+    }
+    let average_ms = (get_time() - start) / SLEEPS;
+    println!(
+        "slept {}ms on average for {}ms among {} hogs",
+        average_ms, SLEEP_MS, HOGS
+    );
+    // a sleep ends on a timer tick, or a few later with round-robin
+    assert!(average_ms < SLEEP_MS * 3);
+    for hog in hogs {
+        assert_eq!(waitpid(hog, &mut exit_code), hog as isize);
+        assert_eq!(exit_code, 0);
+    }
+    println!("priority_test passed!");
+    0
+}
@@ -0,0 +1,108 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use user_lib::{
+    close, exit, fork, getpid, getpriority, open, pipe, read, setuid, waitpid, write, OpenFlags,
+    PRIO_PROCESS,
+};
+
+const INTERVAL: &str = "/proc/sys/vm/dirty_writeback_centisecs\0";
+
+fn proc_path(pid: usize, name: &str) -> String {
+    format!("/proc/{}/{}\0", pid, name)
+}
+
+/// Whether the file of `pid` can be opened so.
+fn can_open(pid: usize, name: &str, flags: OpenFlags) -> bool {
+    let fd = open(proc_path(pid, name).as_str(), flags);
+    if fd < 0 {
+        return false;
+    }
+    close(fd as usize);
+    true
+}
+
+/// Write the nice value of `pid` through /proc, return what write returns.
+fn write_nice(pid: usize, nice: &str) -> isize {
+    let fd = open(proc_path(pid, "nice").as_str(), OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    let written = write(fd as usize, nice.as_bytes());
+    close(fd as usize);
+    written
+}
+
+fn wait(pid: isize) {
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let root = getpid() as usize;
+    let mut pipe_fd = [0usize; 2];
+    pipe(&mut pipe_fd);
+
+    // a process of the user 1000, waiting for the others to check its files
+    let owned = fork();
+    if owned == 0 {
+        assert_eq!(setuid(1000), 0);
+        let mut buf = [0u8; 1];
+        assert_eq!(read(pipe_fd[0], &mut buf), 1);
+        // set by root
+        assert_eq!(getpriority(PRIO_PROCESS, 0), Some(3));
+
+        // its own files
+        let pid = getpid() as usize;
+        let fd = open(proc_path(pid, "status").as_str(), OpenFlags::RDONLY);
+        assert!(fd >= 0);
+        let mut status = [0u8; 256];
+        let len = read(fd as usize, &mut status) as usize;
+        close(fd as usize);
+        let status = core::str::from_utf8(&status[..len]).unwrap();
+        assert!(status.lines().any(|line| line == "Uid:\t1000"));
+        assert!(can_open(pid, "maps", OpenFlags::RDONLY));
+        assert!(!can_open(pid, "status", OpenFlags::WRONLY));
+        assert_eq!(write_nice(pid, "5"), 1);
+        assert_eq!(getpriority(PRIO_PROCESS, 0), Some(5));
+
+        // and those of root
+        assert!(can_open(root, "status", OpenFlags::RDONLY));
+        assert!(!can_open(root, "maps", OpenFlags::RDONLY));
+        assert!(can_open(root, "nice", OpenFlags::RDONLY));
+        assert!(!can_open(root, "nice", OpenFlags::WRONLY));
+        assert!(open(INTERVAL, OpenFlags::WRONLY) < 0);
+        exit(0);
+    }
+
+    // root may change any process
+    assert_eq!(write_nice(owned as usize, "3"), 1);
+    assert_eq!(getpriority(PRIO_PROCESS, owned as usize), Some(3));
+
+    // but another user may only read what is public of it
+    let other = fork();
+    if other == 0 {
+        assert_eq!(setuid(1001), 0);
+        let owned = owned as usize;
+        assert!(can_open(owned, "status", OpenFlags::RDONLY));
+        assert!(!can_open(owned, "maps", OpenFlags::RDONLY));
+        assert!(!can_open(owned, "nice", OpenFlags::WRONLY));
+        assert!(!can_open(owned, "nice", OpenFlags::RDWR));
+        exit(0);
+    }
+    wait(other);
+    assert_eq!(getpriority(PRIO_PROCESS, owned as usize), Some(3));
+
+    assert_eq!(write(pipe_fd[1], b"!"), 1);
+    wait(owned);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    println!("proc_access_test passed!");
+    0
+}
@@ -0,0 +1,147 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    close, dirents, exit, fork, getdents, getpid, lseek, mkdir, open, pipe, read, sleep, unlink,
+    waitpid, write, OpenFlags, SEEK_SET,
+};
+
+/// The text of a file read in pieces of `piece` bytes.
+fn read_text(path: &str, piece: usize) -> String {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut text = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let len = read(fd as usize, &mut buf[..piece]);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        text.extend_from_slice(&buf[..len as usize]);
+    }
+    close(fd as usize);
+    String::from_utf8(text).unwrap()
+}
+
+/// The names in a directory and whether each is one.
+fn list(path: &str) -> Vec<(String, bool)> {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut buf = [0u8; 512];
+    let mut entries = Vec::new();
+    loop {
+        let len = getdents(fd as usize, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        for dirent in dirents(&buf[..len as usize]) {
+            entries.push((String::from(dirent.name), dirent.is_dir));
+        }
+    }
+    close(fd as usize);
+    entries
+}
+
+/// The value of the line `key:` of a status file.
+fn status_value<'a>(status: &'a str, key: &str) -> &'a str {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))
+        .unwrap()
+        .trim()
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = getpid() as usize;
+
+    // the directories of the processes, a child sleeping included
+    let mut pipe_fd = [0usize; 2];
+    pipe(&mut pipe_fd);
+    let child = fork();
+    if child == 0 {
+        let mut buf = [0u8; 1];
+        assert_eq!(read(pipe_fd[0], &mut buf), 1);
+        exit(0);
+    }
+    // until it waits on the pipe
+    sleep(50);
+    let root = list("/proc\0");
+    let has_dir = |name: String| root.contains(&(name, true));
+    assert!(has_dir(format!("{}", pid)));
+    assert!(has_dir(format!("{}", child)));
+    assert!(has_dir(String::from("sys")));
+    assert!(root.contains(&(String::from("meminfo"), false)));
+    let child_stat = read_text(format!("/proc/{}/stat\0", child).as_str(), 256);
+    assert!(child_stat.starts_with(format!("{} (procfs_test) S {} ", child, pid).as_str()));
+    assert_eq!(write(pipe_fd[1], b"!"), 1);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    // gone once it exits
+    assert!(
+        open(
+            format!("/proc/{}/stat\0", child).as_str(),
+            OpenFlags::RDONLY
+        ) < 0
+    );
+    let files: Vec<String> = list(format!("/proc/{}\0", pid).as_str())
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    for name in ["maps", "nice", "stat", "status"] {
+        assert!(files.iter().any(|file| file == name));
+    }
+
+    // generated when read, the same in small pieces
+    let status = read_text(format!("/proc/{}/status\0", pid).as_str(), 256);
+    assert_eq!(status_value(&status, "Name"), "procfs_test");
+    assert!(status_value(&status, "State").starts_with('R'));
+    assert_eq!(status_value(&status, "Pid"), format!("{}", pid));
+    let stat = read_text(format!("/proc/{}/stat\0", pid).as_str(), 7);
+    let prefix = format!("{} (procfs_test) R ", pid);
+    assert!(stat.starts_with(prefix.as_str()));
+    assert_eq!(stat.split_whitespace().count(), 24);
+    let maps = read_text(format!("/proc/{}/maps\0", pid).as_str(), 256);
+    assert!(maps.lines().any(|line| line.contains(" r-xu ")));
+
+    let meminfo = read_text("/proc/meminfo\0", 256);
+    let kb = |key: &str| -> usize {
+        status_value(&meminfo, key)
+            .strip_suffix(" kB")
+            .unwrap()
+            .parse()
+            .unwrap()
+    };
+    assert!(kb("MemFree") > 0 && kb("MemFree") < kb("MemTotal"));
+    let uptime = read_text("/proc/uptime\0", 256);
+    let up: Vec<&str> = uptime.split_whitespace().collect();
+    assert_eq!(up.len(), 2);
+    assert!(up.iter().all(|secs| secs.contains('.')));
+
+    // read again from the start after seeking back
+    let fd = open("/proc/uptime\0", OpenFlags::RDONLY);
+    let mut buf = [0u8; 64];
+    assert!(read(fd as usize, &mut buf) > 0);
+    assert_eq!(read(fd as usize, &mut buf), 0);
+    assert_eq!(lseek(fd as usize, 0, SEEK_SET), 0);
+    assert!(read(fd as usize, &mut buf) > 0);
+    close(fd as usize);
+
+    // nothing is created or removed in it
+    assert!(open("/proc/new\0", OpenFlags::CREATE | OpenFlags::WRONLY) < 0);
+    assert!(mkdir("/proc/new\0") < 0);
+    assert!(unlink("/proc/meminfo\0") < 0);
+    println!("procfs_test passed!");
+    0
+}
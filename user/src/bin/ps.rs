@@ -0,0 +1,95 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+#[macro_use]
+extern crate user_lib;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{close, dirents, getdents, open, read, OpenFlags};
+
+/// The text of a file, None if it cannot be read, as a process may have
+/// exited since it was listed.
+fn read_text(path: &str) -> Option<String> {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let mut text = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        text.extend_from_slice(&buf[..len as usize]);
+    }
+    close(fd as usize);
+    String::from_utf8(text).ok()
+}
+
+/// The pids listed in /proc, in order.
+fn pids() -> Vec<usize> {
+    let fd = open("/proc\0", OpenFlags::RDONLY);
+    if fd < 0 {
+        return Vec::new();
+    }
+    let mut pids = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        let len = getdents(fd as usize, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        pids.extend(dirents(&buf[..len as usize]).filter_map(|dirent| dirent.name.parse().ok()));
+    }
+    close(fd as usize);
+    pids
+}
+
+/// A line of the processes shown from `/proc/<pid>/stat`.
+fn process_line(pid: usize) -> Option<String> {
+    let stat = read_text(format!("/proc/{}/stat\0", pid).as_str())?;
+    // the name is in parentheses and may have spaces
+    let comm_start = stat.find('(')?;
+    let comm_end = stat.rfind(')')?;
+    let comm = &stat[comm_start + 1..comm_end];
+    let fields: Vec<&str> = stat[comm_end + 1..].split_whitespace().collect();
+    // from the state, the third field
+    let field = |index: usize| fields.get(index - 3).copied();
+    let ticks: usize = field(14)?.parse().ok()?;
+    let rss_pages: usize = field(24)?.parse().ok()?;
+    Some(format!(
+        "{:>5} {:>5} {} {:>3} {:>3} {:>6} {:>3}:{:02}.{:02} {}",
+        pid,
+        field(4)?,
+        field(3)?,
+        field(19)?,
+        field(20)?,
+        rss_pages * 4,
+        ticks / 6000,
+        ticks / 100 % 60,
+        ticks % 100,
+        comm
+    ))
+}
+
+/// `ps`, list the processes with their parents, states, nice values,
+/// threads, resident memory in kB and the time they have run.
+#[no_mangle]
+pub fn main() -> i32 {
+    let pids = pids();
+    if pids.is_empty() {
+        println!("ps: cannot list /proc");
+        return -1;
+    }
+    println!("  PID  PPID S  NI THR    RSS      TIME CMD");
+    for pid in pids {
+        if let Some(line) = process_line(pid) {
+            println!("{}", line);
+        }
+    }
+    0
+}
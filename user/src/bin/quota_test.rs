@@ -0,0 +1,90 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, getquota, getuid, open, setquota, setuid, waitpid, write, OpenFlags, Quota,
+    SysError,
+};
+
+const UID: usize = 1000;
+const BLOCK: [u8; 512] = [7; 512];
+
+/// Run `f` in a child as the user `UID`, return its exit code.
+fn as_user(f: fn() -> i32) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(setuid(UID), 0);
+        exit(f());
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(getuid(), 0);
+    // the file is owned by the user, made empty if left by a previous run
+    assert_eq!(
+        as_user(|| {
+            let fd = open(
+                "quota_test_file\0",
+                OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+            );
+            assert!(fd > 0);
+            close(fd as usize);
+            // a user cannot become another one or change its limits
+            assert_eq!(setuid(0), SysError::NotPermitted.code());
+            assert_eq!(
+                setquota(UID, &Quota::default()),
+                SysError::NotPermitted.code()
+            );
+            0
+        }),
+        0
+    );
+    let mut before = Quota::default();
+    assert_eq!(getquota(UID, &mut before), 0);
+    let limits = Quota {
+        block_limit: before.blocks + 8,
+        inode_limit: before.inodes,
+        ..Default::default()
+    };
+    assert_eq!(setquota(UID, &limits), 0);
+    assert_eq!(
+        as_user(|| {
+            let fd = open("quota_test_file\0", OpenFlags::WRONLY) as usize;
+            for _ in 0..8 {
+                assert_eq!(write(fd, &BLOCK), BLOCK.len() as isize);
+            }
+            // nothing is written over the limit
+            assert_eq!(write(fd, &BLOCK), 0);
+            close(fd);
+            assert_eq!(
+                open(
+                    "quota_test_new\0",
+                    OpenFlags::CREATE | OpenFlags::EXCL | OpenFlags::WRONLY
+                ),
+                -1
+            );
+            0
+        }),
+        0
+    );
+    let mut after = Quota::default();
+    assert_eq!(getquota(UID, &mut after), 0);
+    assert_eq!(after.blocks, before.blocks + 8);
+    assert_eq!(after.inodes, before.inodes);
+    // the blocks are charged to the owner, whoever writes them
+    let fd = open("quota_test_file\0", OpenFlags::WRONLY | OpenFlags::APPEND);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, &BLOCK), 0);
+    assert_eq!(setquota(UID, &Quota::default()), 0);
+    assert_eq!(write(fd as usize, &BLOCK), BLOCK.len() as isize);
+    close(fd as usize);
+    println!("quota_test passed!");
+    0
+}
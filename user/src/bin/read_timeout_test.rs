@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, connect, exit, fork, get_time, pipe, read_timeout, sleep, waitpid, write, SysError,
+};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let mut buf = [0u8; 8];
+
+    // nothing to read
+    assert_eq!(read_timeout(pipe_fd[0], &mut buf, 0), -2);
+    let start = get_time();
+    assert_eq!(read_timeout(pipe_fd[0], &mut buf, 50), -2);
+    assert!(get_time() - start >= 50);
+
+    // written before the timeout
+    let pid = fork();
+    if pid == 0 {
+        close(pipe_fd[0]);
+        sleep(20);
+        assert_eq!(write(pipe_fd[1], b"x"), 1);
+        exit(0);
+    }
+    close(pipe_fd[1]);
+    let start = get_time();
+    assert_eq!(read_timeout(pipe_fd[0], &mut buf, 5000), 1);
+    assert!(get_time() - start < 5000);
+    assert_eq!(buf[0], b'x');
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    // the write ends are all closed, so it is at the end
+    assert_eq!(read_timeout(pipe_fd[0], &mut buf, 5000), 0);
+    close(pipe_fd[0]);
+    assert_eq!(
+        read_timeout(pipe_fd[0], &mut buf, 0),
+        SysError::BadFd.code()
+    );
+    // not readable
+    assert_eq!(read_timeout(1, &mut buf, 0), SysError::BadFd.code());
+
+    // a socket nobody sends to, the read does not wait for the next packet
+    let socket = connect(0, 2003, 0);
+    assert!(socket >= 0);
+    let start = get_time();
+    assert_eq!(read_timeout(socket as usize, &mut buf, 50), -2);
+    let elapsed = get_time() - start;
+    assert!(elapsed >= 50 && elapsed < 5000);
+    close(socket as usize);
+    println!("read_timeout_test passed!");
+    0
+}
@@ -0,0 +1,25 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+// restore <image>: resume a process saved by checkpoint, e.g. the user shell
+// saved by its builtin `checkpoint <image>` before reboot.
+
+use alloc::string::String;
+use user_lib::restore;
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc != 2 {
+        println!("Usage: restore <image>");
+        return -1;
+    }
+    let mut path = String::from(argv[1]);
+    path.push('\0');
+    restore(path.as_str());
+    println!("restore: cannot restore from {}", argv[1]);
+    -1
+}
@@ -0,0 +1,140 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use user_lib::{
+    close, exit, fork, get_time, pipe, read, setpriority, sleep, waitpid, write, PRIO_PROCESS,
+};
+
+const DEFAULT_MS: isize = 2000;
+const DEFAULT_NICE: [isize; 4] = [0, 0, 5, 10];
+const SLEEP_MS: isize = 10;
+
+/// Estimate pi by sampling points of a square for `ms`, like a cpu hog of
+/// a long computation. Return the samples and those in the inscribed circle.
+fn estimate_pi(ms: isize) -> (u64, u64) {
+    const R: u64 = 1 << 15;
+    let mut seed = get_time() as u64 | 1;
+    let (mut samples, mut inside) = (0u64, 0u64);
+    let start = get_time();
+    while get_time() - start < ms {
+        for _ in 0..1000 {
+            // xorshift
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            let (x, y) = (seed % R, (seed >> 32) % R);
+            if x * x + y * y < R * R {
+                inside += 1;
+            }
+        }
+        samples += 1000;
+    }
+    (samples, inside)
+}
+
+/// `schedbench [ms] [nice...]`: run a pi estimating hog of each nice value
+/// for `ms`, while sleeping for 10ms again and again like an interactive
+/// program, to compare the scheduling policies built with `SCHED` of the
+/// kernel. Each hog gets a share of the cpu by its nice value, and the sleeps
+/// should not take much longer than they ask for.
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let ms = match argv.get(1).map(|arg| arg.parse::<isize>()) {
+        None => DEFAULT_MS,
+        Some(Ok(ms)) if ms > 0 => ms,
+        _ => {
+            println!("Usage: schedbench [ms] [nice...]");
+            return -1;
+        }
+    };
+    let nice: Vec<isize> = if argc > 2 {
+        match argv[2..argc].iter().map(|arg| arg.parse()).collect() {
+            Ok(nice) => nice,
+            Err(_) => {
+                println!("Usage: schedbench [ms] [nice...]");
+                return -1;
+            }
+        }
+    } else {
+        DEFAULT_NICE.to_vec()
+    };
+
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let mut pids = Vec::new();
+    for (i, &nice) in nice.iter().enumerate() {
+        let pid = fork();
+        if pid == 0 {
+            close(pipe_fd[0]);
+            setpriority(PRIO_PROCESS, 0, nice);
+            let (samples, inside) = estimate_pi(ms);
+            let mut record = [0u8; 24];
+            record[..8].copy_from_slice(&(i as u64).to_le_bytes());
+            record[8..16].copy_from_slice(&samples.to_le_bytes());
+            record[16..].copy_from_slice(&inside.to_le_bytes());
+            write(pipe_fd[1], &record);
+            exit(0);
+        }
+        pids.push(pid as usize);
+    }
+    close(pipe_fd[1]);
+
+    // the latency of an interactive program meanwhile
+    let (mut sleeps, mut max_ms) = (0, 0);
+    let start = get_time();
+    while get_time() - start < ms {
+        let before = get_time();
+        sleep(SLEEP_MS as usize);
+        max_ms = max_ms.max(get_time() - before);
+        sleeps += 1;
+    }
+    let average_ms = (get_time() - start) / sleeps;
+
+    let mut records = Vec::new();
+    let mut buf = [0u8; 24];
+    let mut len = 0;
+    loop {
+        let n = read(pipe_fd[0], &mut buf[len..]);
+        if n <= 0 {
+            break;
+        }
+        len += n as usize;
+        if len == buf.len() {
+            let field = |i: usize| u64::from_le_bytes(buf[i * 8..i * 8 + 8].try_into().unwrap());
+            records.push((field(0) as usize, field(1), field(2)));
+            len = 0;
+        }
+    }
+    close(pipe_fd[0]);
+    for pid in pids {
+        let mut exit_code = 0;
+        waitpid(pid, &mut exit_code);
+    }
+
+    records.sort();
+    let total: u64 = records.iter().map(|&(_, samples, _)| samples).sum();
+    println!("hog  nice  samples(k)  share    pi");
+    for (i, samples, inside) in records {
+        let pi = inside * 4 * 10000 / samples.max(1);
+        println!(
+            "{:>3} {:>5} {:>11} {:>5}%  {}.{:04}",
+            i,
+            nice[i],
+            samples / 1000,
+            samples * 100 / total.max(1),
+            pi / 10000,
+            pi % 10000
+        );
+    }
+    println!(
+        "sleeps of {}ms: {}ms on average, {}ms at most",
+        SLEEP_MS, average_ms, max_ms
+    );
+    0
+}
@@ -0,0 +1,76 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, get_time, pipe, poll, read, select, sleep, waitpid, write, FdSet,
+    PollEvents, PollFd, SysError,
+};
+
+const STDIN: usize = 0;
+const DELAY_MS: usize = 50;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let (read_fd, write_fd) = (pipe_fd[0], pipe_fd[1]);
+
+    // the empty pipe times out, the write end has room at once
+    let mut fds = [PollFd::new(read_fd, PollEvents::POLLIN)];
+    assert_eq!(poll(&mut fds, 10), 0);
+    let mut writefds = FdSet::default();
+    writefds.insert(write_fd);
+    assert_eq!(select(write_fd + 1, None, Some(&mut writefds), 0), 1);
+    assert!(writefds.contains(write_fd));
+
+    let pid = fork();
+    if pid == 0 {
+        close(read_fd);
+        sleep(DELAY_MS);
+        assert_eq!(write(write_fd, b"ping"), 4);
+        exit(0);
+    }
+    close(write_fd);
+
+    // sleeps on stdin and the pipe together until the child writes
+    let start = get_time();
+    let mut fds = [
+        PollFd::new(STDIN, PollEvents::POLLIN),
+        PollFd::new(read_fd, PollEvents::POLLIN),
+    ];
+    // nothing is read from stdin if it gets ready too
+    loop {
+        assert!(poll(&mut fds, -1) >= 1);
+        if fds[1].revents.contains(PollEvents::POLLIN) {
+            break;
+        }
+    }
+    assert!(get_time() - start >= DELAY_MS as isize / 2);
+    let mut buf = [0u8; 8];
+    assert_eq!(read(read_fd, &mut buf), 4);
+    assert_eq!(&buf[..4], b"ping");
+
+    // readable at the end once the child exits
+    let mut readfds = FdSet::default();
+    readfds.insert(read_fd);
+    assert_eq!(select(read_fd + 1, Some(&mut readfds), None, -1), 1);
+    assert!(readfds.contains(read_fd));
+    assert_eq!(read(read_fd, &mut buf), 0);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    // fds not open
+    close(read_fd);
+    let mut readfds = FdSet::default();
+    readfds.insert(read_fd);
+    assert_eq!(
+        select(read_fd + 1, Some(&mut readfds), None, 0),
+        SysError::BadFd.code()
+    );
+    println!("select_test passed!");
+    0
+}
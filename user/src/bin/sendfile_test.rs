@@ -0,0 +1,98 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::{
+    close, exit, fork, lseek, open, pipe, read, sendfile, waitpid, write, OpenFlags, SysError,
+    SEEK_CUR, SEEK_SET,
+};
+
+/// across pages
+const LEN: usize = 6000;
+
+fn pattern(i: usize) -> u8 {
+    (i % 251) as u8
+}
+
+/// Read until `len` bytes or the end and check them.
+fn check(fd: usize, from: usize, len: usize) {
+    let mut buf = [0u8; 512];
+    let mut read_len = 0;
+    while read_len < len {
+        let size = read(fd, &mut buf);
+        assert!(size > 0);
+        for (i, byte) in buf[..size as usize].iter().enumerate() {
+            assert_eq!(*byte, pattern(from + read_len + i));
+        }
+        read_len += size as usize;
+    }
+    assert_eq!(read_len, len);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let data: Vec<u8> = (0..LEN).map(pattern).collect();
+    let fd = open("sendfile_test_in\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, &data), LEN as isize);
+    close(fd as usize);
+    drop(data);
+    let in_fd = open("sendfile_test_in\0", OpenFlags::RDONLY) as usize;
+
+    // file to file from the offset of the file
+    let out_fd = open("sendfile_test_out\0", OpenFlags::CREATE | OpenFlags::WRONLY) as usize;
+    assert_eq!(sendfile(out_fd, in_fd, None, 1000), 1000);
+    assert_eq!(
+        sendfile(out_fd, in_fd, None, LEN * 2),
+        (LEN - 1000) as isize
+    );
+    assert_eq!(lseek(in_fd, 0, SEEK_CUR), LEN as isize);
+    assert_eq!(sendfile(out_fd, in_fd, None, LEN), 0);
+    close(out_fd);
+    let out_fd = open("sendfile_test_out\0", OpenFlags::RDONLY) as usize;
+    check(out_fd, 0, LEN);
+    let mut byte = [0u8; 1];
+    assert_eq!(read(out_fd, &mut byte), 0);
+    close(out_fd);
+
+    // file to pipe from a given offset
+    lseek(in_fd, 0, SEEK_SET);
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(pipe_fd[1]);
+        check(pipe_fd[0], 4000, LEN - 4000);
+        exit(0);
+    }
+    close(pipe_fd[0]);
+    let mut offset = 4000;
+    assert_eq!(
+        sendfile(pipe_fd[1], in_fd, Some(&mut offset), LEN),
+        (LEN - 4000) as isize
+    );
+    assert_eq!(offset, LEN);
+    // the offset of the file is kept
+    assert_eq!(lseek(in_fd, 0, SEEK_CUR), 0);
+    close(pipe_fd[1]);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    // only from files to writable descriptors
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(
+        sendfile(pipe_fd[1], pipe_fd[0], None, 1),
+        SysError::Invalid.code()
+    );
+    assert_eq!(sendfile(pipe_fd[0], in_fd, None, 1), SysError::BadFd.code());
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    close(in_fd);
+    println!("sendfile_test passed!");
+    0
+}
@@ -0,0 +1,89 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, getpid, kill, sigaction, sigprocmask, waitpid, yield_, SignalAction, SignalFlags,
+    SysError, SIG_BLOCK, SIG_IGN, SIG_UNBLOCK,
+};
+
+const SIGUSR1: usize = 10;
+const SIGUSR2: usize = 12;
+const SIGKILL: usize = 9;
+
+static HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn handler(signum: i32) {
+    assert_eq!(signum as usize, SIGUSR1);
+    HANDLED.fetch_add(1, Ordering::SeqCst);
+}
+
+fn kill_self(signal: SignalFlags) {
+    assert_eq!(kill(getpid() as usize, signal.bits()), 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // a handler runs once the kill returns
+    let action = SignalAction::new(handler, SignalFlags::empty());
+    assert_eq!(sigaction(SIGUSR1, Some(&action), None), 0);
+    kill_self(SignalFlags::SIGUSR1);
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 1);
+
+    // a signal blocked is pending until unblocked
+    assert_eq!(sigprocmask(SIG_BLOCK, SignalFlags::SIGUSR1), 0);
+    kill_self(SignalFlags::SIGUSR1);
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 1);
+    let old = sigprocmask(SIG_UNBLOCK, SignalFlags::SIGUSR1);
+    assert_eq!(old, SignalFlags::SIGUSR1.bits() as isize);
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 2);
+
+    // the old action is saved
+    let mut old_action = SignalAction::default();
+    assert_eq!(sigaction(SIGUSR1, None, Some(&mut old_action)), 0);
+    assert_eq!(old_action.handler, handler as usize);
+
+    // a signal ignored does nothing, and SIGKILL can't be caught
+    let ignore = SignalAction {
+        handler: SIG_IGN,
+        ..Default::default()
+    };
+    assert_eq!(sigaction(SIGUSR2, Some(&ignore), None), 0);
+    kill_self(SignalFlags::SIGUSR2);
+    assert_eq!(
+        sigaction(SIGKILL, Some(&ignore), None),
+        SysError::Invalid.code()
+    );
+
+    // the default action of SIGTERM terminates the process
+    let pid = fork();
+    if pid == 0 {
+        loop {
+            yield_();
+        }
+    }
+    assert_eq!(kill(pid as usize, SignalFlags::SIGTERM.bits()), 0);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -15);
+
+    // a child ignoring it goes on until killed
+    let pid = fork();
+    if pid == 0 {
+        let ignore = SignalAction {
+            handler: SIG_IGN,
+            ..Default::default()
+        };
+        sigaction(15, Some(&ignore), None);
+        kill_self(SignalFlags::SIGTERM);
+        exit(7);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 7);
+    println!("sig_test passed!");
+    0
+}
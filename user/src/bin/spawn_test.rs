@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, open, pipe2, read, spawn, waitpid, OpenFlags, SpawnAction, SysError};
+
+const HELLO: &[u8] = b"Hello world from user mode program!\n";
+const ARGS: [*const u8; 2] = [b"hello_world\0".as_ptr(), core::ptr::null()];
+
+fn wait_ok(pid: isize) {
+    assert!(pid > 0);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+}
+
+fn read_all(fd: usize, buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    loop {
+        let size = read(fd, &mut buf[len..]);
+        if size <= 0 {
+            return len;
+        }
+        len += size as usize;
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [0u8; 128];
+
+    // the output to a pipe, whose ends are closed by exec otherwise
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe2(&mut pipe_fd, OpenFlags::CLOEXEC), 0);
+    let pid = spawn("hello_world\0", &ARGS, &[SpawnAction::dup2(pipe_fd[1], 1)]);
+    close(pipe_fd[1]);
+    wait_ok(pid);
+    assert_eq!(read_all(pipe_fd[0], &mut buf), HELLO.len());
+    assert_eq!(&buf[..HELLO.len()], HELLO);
+    close(pipe_fd[0]);
+
+    // the output to a file opened in the child
+    let flags = OpenFlags::CREATE | OpenFlags::WRONLY;
+    let pid = spawn(
+        "hello_world\0",
+        &ARGS,
+        &[SpawnAction::open("spawn_test_out\0", flags, 1)],
+    );
+    wait_ok(pid);
+    let fd = open("spawn_test_out\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    assert_eq!(read_all(fd as usize, &mut buf), HELLO.len());
+    assert_eq!(&buf[..HELLO.len()], HELLO);
+    close(fd as usize);
+
+    // no child if the program or an action fails
+    assert_eq!(
+        spawn("spawn_test_missing\0", &ARGS, &[]),
+        SysError::NoEntry.code()
+    );
+    assert_eq!(
+        spawn("hello_world\0", &ARGS, &[SpawnAction::close(1000)]),
+        SysError::BadFd.code()
+    );
+    assert_eq!(
+        spawn("hello_world\0", &ARGS, &[SpawnAction::dup2(1000, 1)]),
+        SysError::BadFd.code()
+    );
+    println!("spawn_test passed!");
+    0
+}
@@ -0,0 +1,28 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::hint::black_box;
+
+/// Use about 1KiB of stack in each frame.
+fn f(depth: usize) -> usize {
+    let mut buf = [0u8; 1024];
+    buf[depth % 1024] = depth as u8;
+    let buf = black_box(buf);
+    if depth == 0 {
+        return buf[0] as usize;
+    }
+    f(depth - 1) + buf[depth % 1024] as usize
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // far beyond the initial 8KiB of the main stack
+    let sum = f(128);
+    let expected: usize = (1..=128usize).map(|depth| depth as u8 as usize).sum();
+    assert_eq!(sum, expected);
+    println!("stack_grow passed!");
+    0
+}
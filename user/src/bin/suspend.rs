@@ -0,0 +1,19 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{get_time, suspend};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    println!("suspending, press a key to resume");
+    let start = get_time();
+    if suspend() != 0 {
+        println!("suspend is not supported by the SBI");
+        return -1;
+    }
+    println!("resumed after {}ms", get_time() - start);
+    0
+}
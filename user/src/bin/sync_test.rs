@@ -0,0 +1,96 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, mkdir, mount, open, read, run, sleep, sync, umount, write, OpenFlags};
+
+const INTERVAL: &str = "/proc/sys/vm/dirty_writeback_centisecs\0";
+const RATIO: &str = "/proc/sys/vm/dirty_background_ratio\0";
+/// The blocks cached by the kernel.
+const BLOCK_CACHE_SIZE: usize = 512;
+
+/// The number at the end of the first line of a file under /proc.
+fn read_number(path: &str) -> usize {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut buf = [0u8; 64];
+    let len = read(fd as usize, &mut buf) as usize;
+    close(fd as usize);
+    let text = core::str::from_utf8(&buf[..len]).unwrap();
+    let line = text.lines().next().unwrap();
+    line.rsplit(' ').next().unwrap().parse().unwrap()
+}
+
+/// Write a file under /proc, return what write returns.
+fn write_text(path: &str, text: &str) -> isize {
+    let fd = open(path, OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    let written = write(fd as usize, text.as_bytes());
+    close(fd as usize);
+    written
+}
+
+fn dirty() -> usize {
+    read_number("/proc/vmstat\0")
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 10 percent by default, as on Linux
+    assert_eq!(read_number(RATIO), 10);
+    assert_eq!(write_text(RATIO, "101"), 0);
+    assert_eq!(read_number(RATIO), 10);
+
+    // the daemon only writes back above the ratio
+    assert_eq!(write_text(INTERVAL, "0"), 1);
+    assert_eq!(run(&["mkfs_easyfs\0", "/dev/ram0\0"]), 0);
+    mkdir("/sync_test_mnt\0");
+    assert_eq!(mount("/dev/ram0\0", "/sync_test_mnt\0", "easyfs\0", 0), 0);
+    let fd = open(
+        "/sync_test_mnt/small\0",
+        OpenFlags::CREATE | OpenFlags::WRONLY,
+    );
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"written back by sync"), 20);
+    close(fd as usize);
+    sleep(300);
+    assert!(dirty() > 0);
+
+    // sync writes back all of them at once
+    assert_eq!(sync(), 0);
+    assert_eq!(dirty(), 0);
+
+    // many more are written back in the background down to the ratio
+    let fd = open(
+        "/sync_test_mnt/large\0",
+        OpenFlags::CREATE | OpenFlags::WRONLY,
+    );
+    assert!(fd > 0);
+    let block = [0x5au8; 512];
+    for _ in 0..BLOCK_CACHE_SIZE / 4 {
+        assert_eq!(write(fd as usize, &block), 512);
+    }
+    close(fd as usize);
+    let background = BLOCK_CACHE_SIZE * 10 / 100;
+    let mut waited_ms = 0;
+    while dirty() > background {
+        assert!(waited_ms < 3000, "the dirty blocks are not written back");
+        sleep(100);
+        waited_ms += 100;
+    }
+    assert!(dirty() > 0);
+    println!(
+        "written back down to {} blocks in {} ms",
+        dirty(),
+        waited_ms
+    );
+
+    assert_eq!(sync(), 0);
+    assert_eq!(dirty(), 0);
+    assert_eq!(write_text(INTERVAL, "500"), 3);
+    assert_eq!(umount("/sync_test_mnt\0"), 0);
+    println!("sync_test passed!");
+    0
+}
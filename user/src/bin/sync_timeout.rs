@@ -0,0 +1,89 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::vec;
+use user_lib::{
+    condvar_create, condvar_signal, condvar_wait_timeout, exit, get_time, mutex_errorcheck_create,
+    mutex_lock, mutex_lock_timeout, mutex_unlock, semaphore_create, semaphore_down_timeout,
+    semaphore_up, sleep, thread_create, waittid, SysError,
+};
+
+const MUTEX_ID: usize = 0;
+const SEM_ID: usize = 0;
+const CONDVAR_ID: usize = 0;
+
+static mut SIGNALED: bool = false;
+
+fn holder() -> ! {
+    assert_eq!(mutex_lock(MUTEX_ID), 0);
+    sleep(100);
+    assert_eq!(mutex_unlock(MUTEX_ID), 0);
+    exit(0)
+}
+
+fn contender() -> ! {
+    sleep(10);
+    // not the owner
+    assert_eq!(mutex_unlock(MUTEX_ID), SysError::NotPermitted.code());
+    let start = get_time();
+    assert_eq!(mutex_lock_timeout(MUTEX_ID, 20), -2);
+    assert!(get_time() - start >= 20);
+    // the holder releases it in time
+    assert_eq!(mutex_lock_timeout(MUTEX_ID, 1000), 0);
+    assert_eq!(mutex_unlock(MUTEX_ID), 0);
+    exit(0)
+}
+
+fn poster() -> ! {
+    sleep(50);
+    semaphore_up(SEM_ID);
+    mutex_lock(MUTEX_ID);
+    unsafe {
+        SIGNALED = true;
+    }
+    condvar_signal(CONDVAR_ID);
+    mutex_unlock(MUTEX_ID);
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mutex_errorcheck_create() as usize, MUTEX_ID);
+    assert_eq!(semaphore_create(0) as usize, SEM_ID);
+    assert_eq!(condvar_create() as usize, CONDVAR_ID);
+
+    // error checking
+    assert_eq!(mutex_lock(MUTEX_ID), 0);
+    assert_eq!(mutex_lock(MUTEX_ID), SysError::Deadlock.code());
+    assert_eq!(mutex_unlock(MUTEX_ID), 0);
+    assert_eq!(mutex_unlock(MUTEX_ID), SysError::NotPermitted.code());
+
+    // timed mutex
+    let threads = vec![
+        thread_create(holder as usize, 0),
+        thread_create(contender as usize, 0),
+    ];
+    for thread in threads.iter() {
+        assert_eq!(waittid(*thread as usize), 0);
+    }
+
+    // timed semaphore and condvar
+    assert_eq!(semaphore_down_timeout(SEM_ID, 10), -2);
+    let thread = thread_create(poster as usize, 0);
+    assert_eq!(semaphore_down_timeout(SEM_ID, 1000), 0);
+    assert_eq!(mutex_lock(MUTEX_ID), 0);
+    while unsafe { !SIGNALED } {
+        assert_eq!(condvar_wait_timeout(CONDVAR_ID, MUTEX_ID, 1000), 0);
+    }
+    // the mutex is held again after timeout
+    assert_eq!(condvar_wait_timeout(CONDVAR_ID, MUTEX_ID, 10), -2);
+    assert_eq!(mutex_unlock(MUTEX_ID), 0);
+    waittid(thread as usize);
+    println!("sync_timeout passed!");
+    0
+}
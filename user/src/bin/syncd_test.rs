@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, mkdir, mount, open, read, run, sleep, umount, write, OpenFlags, SysError};
+
+const INTERVAL: &str = "/proc/sys/vm/dirty_writeback_centisecs\0";
+
+/// The number at the end of the first line of a file under /proc.
+fn read_number(path: &str) -> usize {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut buf = [0u8; 64];
+    let len = read(fd as usize, &mut buf) as usize;
+    close(fd as usize);
+    let text = core::str::from_utf8(&buf[..len]).unwrap();
+    let line = text.lines().next().unwrap();
+    line.rsplit(' ').next().unwrap().parse().unwrap()
+}
+
+/// Write the interval, return what write returns.
+fn set_interval(text: &str) -> isize {
+    let fd = open(INTERVAL, OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    let written = write(fd as usize, text.as_bytes());
+    close(fd as usize);
+    written
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 5 seconds by default, as on Linux
+    assert_eq!(read_number(INTERVAL), 500);
+    assert_eq!(set_interval("soon\n"), 0);
+    assert_eq!(read_number(INTERVAL), 500);
+    // the others are read-only
+    assert_eq!(
+        open("/proc/vmstat\0", OpenFlags::WRONLY),
+        SysError::Denied.code()
+    );
+
+    // turned off, the blocks written stay dirty in the cache
+    assert_eq!(set_interval("0\n"), 2);
+    assert_eq!(read_number(INTERVAL), 0);
+    assert_eq!(run(&["mkfs_easyfs\0", "/dev/ram0\0"]), 0);
+    mkdir("/syncd_test_mnt\0");
+    assert_eq!(mount("/dev/ram0\0", "/syncd_test_mnt\0", "easyfs\0", 0), 0);
+    let fd = open(
+        "/syncd_test_mnt/file\0",
+        OpenFlags::CREATE | OpenFlags::WRONLY,
+    );
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"written back later"), 18);
+    close(fd as usize);
+    assert!(read_number("/proc/vmstat\0") > 0);
+
+    // written back by the daemon once turned on
+    assert_eq!(set_interval("10"), 2);
+    let mut waited_ms = 0;
+    while read_number("/proc/vmstat\0") > 0 {
+        assert!(waited_ms < 3000, "the dirty blocks are not written back");
+        sleep(100);
+        waited_ms += 100;
+    }
+    println!("written back in {} ms", waited_ms);
+
+    assert_eq!(set_interval("500"), 3);
+    assert_eq!(umount("/syncd_test_mnt\0"), 0);
+    println!("syncd_test passed!");
+    0
+}
@@ -0,0 +1,84 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::AtomicU32;
+use user_lib::checked::{
+    clock_gettime, close, exit, flock, futex_wait, mutex_blocking_create, mutex_lock,
+    mutex_lock_timeout, mutex_unlock, open, pipe, read_timeout, thread_create, unlink, waittid,
+    write,
+};
+use user_lib::{OpenFlags, SysError, SysResult, CLOCK_MONOTONIC, LOCK_EX, LOCK_NB, LOCK_UN};
+
+const PATH: &str = "syserror_test_file\0";
+
+/// Write a file, stopping at the first error.
+fn write_file() -> SysResult {
+    let fd = open(PATH, OpenFlags::CREATE | OpenFlags::WRONLY)?;
+    let written = write(fd, b"checked")?;
+    close(fd)?;
+    Ok(written)
+}
+
+fn negative_exit() -> ! {
+    exit(-4)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(write_file(), Ok(7));
+    assert_eq!(
+        open("syserror_test_missing\0", OpenFlags::RDONLY),
+        Err(SysError::NoEntry)
+    );
+    assert_eq!(close(99), Err(SysError::BadFd));
+
+    // the errors callers tell apart
+    let fd = open(PATH, OpenFlags::RDONLY).unwrap();
+    let other = open(PATH, OpenFlags::RDONLY).unwrap();
+    assert_eq!(flock(fd, LOCK_EX | LOCK_NB), Ok(()));
+    assert_eq!(flock(other, LOCK_EX | LOCK_NB), Err(SysError::WouldBlock));
+    assert_eq!(flock(fd, LOCK_UN), Ok(()));
+    close(fd).unwrap();
+    close(other).unwrap();
+
+    static WORD: AtomicU32 = AtomicU32::new(1);
+    assert_eq!(futex_wait(&WORD, 0, -1), Err(SysError::WouldBlock));
+    assert_eq!(futex_wait(&WORD, 1, 10_000_000), Err(SysError::NotReady));
+
+    let [read_end, write_end] = pipe().unwrap();
+    let mut buf = [0u8; 1];
+    assert_eq!(
+        read_timeout(read_end, &mut buf, 10),
+        Err(SysError::NotReady)
+    );
+    close(read_end).unwrap();
+    close(write_end).unwrap();
+
+    // an exit code is no error even if negative
+    let tid = thread_create(negative_exit as usize, 0).unwrap();
+    assert_eq!(waittid(tid), Ok(-4));
+    assert_eq!(waittid(tid), Err(SysError::NoProcess));
+    assert_eq!(waittid(1000), Err(SysError::NoProcess));
+
+    // what is filled in is returned
+    let now = clock_gettime(CLOCK_MONOTONIC).unwrap();
+    assert!(now.sec > 0 || now.nsec > 0);
+
+    // the codes are the same as before
+    let mutex = mutex_blocking_create().unwrap();
+    assert_eq!(mutex_lock(mutex), Ok(()));
+    assert_eq!(mutex_lock_timeout(mutex, 10), Err(SysError::NotReady));
+    assert_eq!(
+        user_lib::mutex_lock_timeout(mutex, 10),
+        SysError::NotReady.code()
+    );
+    assert_eq!(mutex_unlock(mutex), Ok(()));
+    assert_eq!(SysError::Deadlock.code(), -0xdead);
+
+    assert_eq!(unlink(PATH), Ok(()));
+    println!("syserror_test passed!");
+    0
+}
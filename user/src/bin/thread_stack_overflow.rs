@@ -0,0 +1,29 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, thread_create, waittid};
+
+#[allow(unconditional_recursion)]
+fn f(depth: usize) {
+    if depth % 10 == 0 {
+        println!("depth = {}", depth);
+    }
+    f(depth + 1);
+}
+
+pub fn thread_a() -> ! {
+    // the stacks of threads other than the main one never grow
+    f(0);
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    println!("It should trigger segmentation fault!");
+    let tid = thread_create(thread_a as usize, 0);
+    waittid(tid as usize);
+    0
+}
@@ -0,0 +1,23 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use user_lib::umount;
+
+/// `umount <dir>`, unmount the file system mounted at `dir`.
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc != 2 {
+        println!("usage: umount <dir>");
+        return -1;
+    }
+    if umount(format!("{}\0", argv[1]).as_str()) != 0 {
+        println!("umount: {} is busy or not mounted", argv[1]);
+        return -1;
+    }
+    0
+}
@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+#[macro_use]
+extern crate user_lib;
+
+use alloc::string::String;
+use core::slice;
+use user_lib::{close, open, pipe, read, write, OpenFlags, SysError};
+
+const EFAULT: isize = SysError::Fault.code();
+
+/// The trampoline, mapped but not for the user.
+const KERNEL_ADDR: usize = usize::MAX - 4096 + 1;
+/// Not mapped in any process.
+const UNMAPPED_ADDR: usize = 0x10;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(write(pipe_fd[1], b"hello"), 5);
+
+    // the buffers the user cannot access fail instead of faulting the kernel
+    for addr in [KERNEL_ADDR, UNMAPPED_ADDR] {
+        let buf = unsafe { slice::from_raw_parts_mut(addr as *mut u8, 5) };
+        assert_eq!(read(pipe_fd[0], buf), EFAULT);
+        assert_eq!(write(pipe_fd[1], buf), EFAULT);
+    }
+    // the code is readable but not writable
+    let code = unsafe { slice::from_raw_parts_mut(main as usize as *mut u8, 5) };
+    assert_eq!(read(pipe_fd[0], code), EFAULT);
+    // nothing is taken by the reads which failed
+    let mut buf = [0u8; 5];
+    assert_eq!(read(pipe_fd[0], &mut buf), 5);
+    assert_eq!(&buf, b"hello");
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+
+    // a path longer than PATH_MAX
+    let mut path = String::new();
+    for _ in 0..4096 {
+        path.push('a');
+    }
+    path.push('\0');
+    assert_eq!(open(path.as_str(), OpenFlags::RDONLY), EFAULT);
+    println!("user_ptr_test passed!");
+    0
+}
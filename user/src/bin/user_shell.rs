@@ -7,21 +7,21 @@ extern crate alloc;
 #[macro_use]
 extern crate user_lib;
 
-const LF: u8 = 0x0au8;
-const CR: u8 = 0x0du8;
-const DL: u8 = 0x7fu8;
-const BS: u8 = 0x08u8;
 const LINE_START: &str = ">> ";
 
 use alloc::string::String;
 use alloc::vec::Vec;
-use user_lib::console::getchar;
-use user_lib::{close, dup, exec, fork, open, pipe, waitpid, OpenFlags};
+use user_lib::{
+    checkpoint, close, getpgid, getpid, open, pipe2, read, setpgid, sigaction, spawn, tcgetattr,
+    tcsetattr, tcsetpgrp, waitpid, InputFlags, LocalFlags, OpenFlags, SignalAction,
+    SignalActionFlags, SignalFlags, SpawnAction, Termios,
+};
 
 #[derive(Debug)]
 struct ProcessArguments {
     input: String,
     output: String,
+    append: bool,
     args_copy: Vec<String>,
     args_addr: Vec<*const u8>,
 }
@@ -51,13 +51,15 @@ impl ProcessArguments {
             args_copy.drain(idx..=idx + 1);
         }
 
-        // redirect output
+        // redirect output, appended to the file with `>>`
         let mut output = String::new();
+        let mut append = false;
         if let Some((idx, _)) = args_copy
             .iter()
             .enumerate()
-            .find(|(_, arg)| arg.as_str() == ">\0")
+            .find(|(_, arg)| arg.as_str() == ">\0" || arg.as_str() == ">>\0")
         {
+            append = args_copy[idx].as_str() == ">>\0";
             output = args_copy[idx + 1].clone();
             args_copy.drain(idx..=idx + 1);
         }
@@ -68,146 +70,197 @@ impl ProcessArguments {
         Self {
             input,
             output,
+            append,
             args_copy,
             args_addr,
         }
     }
 }
 
+/// Builtin `checkpoint <image>`: save the shell to a file, which can be
+/// resumed by `restore <image>` even after reboot.
+fn checkpoint_shell(image: &str) {
+    let mut path = String::from(image.trim());
+    path.push('\0');
+    match checkpoint(path.as_str()) {
+        0 => println!("Shell saved to {}", image.trim()),
+        1 => println!("Shell restored"),
+        _ => println!("Cannot save the shell to {}", image.trim()),
+    }
+}
+
+/// Read a command line edited by the console in canonical mode, which is
+/// restored to the mode of the commands afterwards.
+fn read_line(line: &mut String) {
+    let mut termios = Termios::default();
+    tcgetattr(0, &mut termios);
+    let mut canonical = termios;
+    canonical.iflag |= InputFlags::ICRNL.bits();
+    canonical.lflag |= (LocalFlags::ICANON | LocalFlags::ECHO | LocalFlags::ECHOE).bits();
+    tcsetattr(0, &canonical);
+    let mut buf = [0u8; 128];
+    loop {
+        let len = read(0, &mut buf);
+        // at the end of file, as if the line ends
+        if len <= 0 {
+            println!("");
+            break;
+        }
+        let bytes = &buf[..len as usize];
+        if let Some((&b'\n', bytes)) = bytes.split_last() {
+            line.extend(bytes.iter().map(|&c| c as char));
+            break;
+        }
+        line.extend(bytes.iter().map(|&c| c as char));
+    }
+    tcsetattr(0, &termios);
+}
+
+const SIGINT: usize = 2;
+
+/// Reset to the default one by spawn, so the jobs are interrupted.
+extern "C" fn on_interrupt(_signum: i32) {}
+
 #[no_mangle]
 pub fn main() -> i32 {
     println!("Rust user shell");
+    // Ctrl-C interrupts the jobs, but not the shell waiting for them in a
+    // group of its own, which must take the console or it would wait to read
+    // forever as a background one
+    if setpgid(0, 0) != 0 || tcsetpgrp(0, getpid() as usize) != 0 {
+        println!("Shell: cannot take the console as the foreground group!");
+        return -1;
+    }
+    // and the line being read goes on
+    let on_interrupt = SignalAction {
+        flags: SignalActionFlags::SA_RESTART,
+        ..SignalAction::new(on_interrupt, SignalFlags::empty())
+    };
+    sigaction(SIGINT, Some(&on_interrupt), None);
     let mut line: String = String::new();
-    print!("{}", LINE_START);
     loop {
-        let c = getchar();
-        match c {
-            LF | CR => {
-                println!("");
-                if !line.is_empty() {
-                    let splited: Vec<_> = line.as_str().split('|').collect();
-                    let process_arguments_list: Vec<_> = splited
-                        .iter()
-                        .map(|&cmd| ProcessArguments::new(cmd))
-                        .collect();
-                    let mut valid = true;
-                    for (i, process_args) in process_arguments_list.iter().enumerate() {
-                        if i == 0 {
-                            if !process_args.output.is_empty() {
-                                valid = false;
-                            }
-                        } else if i == process_arguments_list.len() - 1 {
-                            if !process_args.input.is_empty() {
-                                valid = false;
-                            }
-                        } else if !process_args.output.is_empty() || !process_args.input.is_empty()
-                        {
-                            valid = false;
-                        }
+        print!("{}", LINE_START);
+        line.clear();
+        read_line(&mut line);
+        if let Some(image) = line.strip_prefix("checkpoint ") {
+            checkpoint_shell(image);
+        } else if !line.is_empty() {
+            let splited: Vec<_> = line.as_str().split('|').collect();
+            let process_arguments_list: Vec<_> = splited
+                .iter()
+                .map(|&cmd| ProcessArguments::new(cmd))
+                .collect();
+            let mut valid = true;
+            for (i, process_args) in process_arguments_list.iter().enumerate() {
+                if i == 0 {
+                    if !process_args.output.is_empty() {
+                        valid = false;
                     }
-                    if process_arguments_list.len() == 1 {
-                        valid = true;
+                } else if i == process_arguments_list.len() - 1 {
+                    if !process_args.input.is_empty() {
+                        valid = false;
                     }
-                    if !valid {
-                        println!("Invalid command: Inputs/Outputs cannot be correctly binded!");
-                    } else {
-                        // create pipes
-                        let mut pipes_fd: Vec<[usize; 2]> = Vec::new();
-                        if !process_arguments_list.is_empty() {
-                            for _ in 0..process_arguments_list.len() - 1 {
-                                let mut pipe_fd = [0usize; 2];
-                                pipe(&mut pipe_fd);
-                                pipes_fd.push(pipe_fd);
-                            }
+                } else if !process_args.output.is_empty() || !process_args.input.is_empty() {
+                    valid = false;
+                }
+            }
+            if process_arguments_list.len() == 1 {
+                valid = true;
+            }
+            if !valid {
+                println!("Invalid command: Inputs/Outputs cannot be correctly binded!");
+            } else {
+                // create pipes, closed by exec in the children except
+                // the ends duplicated to their stdin and stdout
+                let mut pipes_fd: Vec<[usize; 2]> = Vec::new();
+                if !process_arguments_list.is_empty() {
+                    for _ in 0..process_arguments_list.len() - 1 {
+                        let mut pipe_fd = [0usize; 2];
+                        pipe2(&mut pipe_fd, OpenFlags::CLOEXEC);
+                        pipes_fd.push(pipe_fd);
+                    }
+                }
+                let mut children: Vec<_> = Vec::new();
+                // the processes of a line form a group led by the first one
+                let mut pgid = 0;
+                for (i, process_argument) in process_arguments_list.iter().enumerate() {
+                    let input = &process_argument.input;
+                    let output = &process_argument.output;
+                    let args_copy = &process_argument.args_copy;
+                    let args_addr = &process_argument.args_addr;
+                    // the files are opened here and duplicated in the child
+                    let mut actions: Vec<SpawnAction> = Vec::new();
+                    let mut opened: Vec<usize> = Vec::new();
+                    // redirect input
+                    if !input.is_empty() {
+                        let input_fd = open(input.as_str(), OpenFlags::RDONLY | OpenFlags::CLOEXEC);
+                        if input_fd < 0 {
+                            println!("Error when opening file {}", input);
+                            continue;
                         }
-                        let mut children: Vec<_> = Vec::new();
-                        for (i, process_argument) in process_arguments_list.iter().enumerate() {
-                            let pid = fork();
-                            if pid == 0 {
-                                let input = &process_argument.input;
-                                let output = &process_argument.output;
-                                let args_copy = &process_argument.args_copy;
-                                let args_addr = &process_argument.args_addr;
-                                // redirect input
-                                if !input.is_empty() {
-                                    let input_fd = open(input.as_str(), OpenFlags::RDONLY);
-                                    if input_fd == -1 {
-                                        println!("Error when opening file {}", input);
-                                        return -4;
-                                    }
-                                    let input_fd = input_fd as usize;
-                                    close(0);
-                                    assert_eq!(dup(input_fd), 0);
-                                    close(input_fd);
-                                }
-                                // redirect output
-                                if !output.is_empty() {
-                                    let output_fd = open(
-                                        output.as_str(),
-                                        OpenFlags::CREATE | OpenFlags::WRONLY,
-                                    );
-                                    if output_fd == -1 {
-                                        println!("Error when opening file {}", output);
-                                        return -4;
-                                    }
-                                    let output_fd = output_fd as usize;
-                                    close(1);
-                                    assert_eq!(dup(output_fd), 1);
-                                    close(output_fd);
-                                }
-                                // receive input from the previous process
-                                if i > 0 {
-                                    close(0);
-                                    let read_end = pipes_fd.get(i - 1).unwrap()[0];
-                                    assert_eq!(dup(read_end), 0);
-                                }
-                                // send output to the next process
-                                if i < process_arguments_list.len() - 1 {
-                                    close(1);
-                                    let write_end = pipes_fd.get(i).unwrap()[1];
-                                    assert_eq!(dup(write_end), 1);
-                                }
-                                // close all pipe ends inherited from the parent process
-                                for pipe_fd in pipes_fd.iter() {
-                                    close(pipe_fd[0]);
-                                    close(pipe_fd[1]);
-                                }
-                                // execute new application
-                                if exec(args_copy[0].as_str(), args_addr.as_slice()) == -1 {
-                                    println!("Error when executing!");
-                                    return -4;
-                                }
-                                unreachable!();
-                            } else {
-                                children.push(pid);
-                            }
+                        actions.push(SpawnAction::dup2(input_fd as usize, 0));
+                        opened.push(input_fd as usize);
+                    }
+                    // redirect output
+                    if !output.is_empty() {
+                        let flags = OpenFlags::WRONLY | OpenFlags::CLOEXEC;
+                        // CREATE clears the file, so only for a new one
+                        let mut output_fd = -1;
+                        if process_argument.append {
+                            output_fd = open(output.as_str(), flags | OpenFlags::APPEND);
                         }
-                        for pipe_fd in pipes_fd.iter() {
-                            close(pipe_fd[0]);
-                            close(pipe_fd[1]);
+                        if output_fd < 0 {
+                            output_fd = open(output.as_str(), flags | OpenFlags::CREATE);
                         }
-                        let mut exit_code: i32 = 0;
-                        for pid in children.into_iter() {
-                            let exit_pid = waitpid(pid as usize, &mut exit_code);
-                            assert_eq!(pid, exit_pid);
-                            //println!("Shell: Process {} exited with code {}", pid, exit_code);
+                        if output_fd < 0 {
+                            println!("Error when opening file {}", output);
+                            for fd in opened.iter() {
+                                close(*fd);
+                            }
+                            continue;
                         }
+                        actions.push(SpawnAction::dup2(output_fd as usize, 1));
+                        opened.push(output_fd as usize);
+                    }
+                    // receive input from the previous process
+                    if i > 0 {
+                        let read_end = pipes_fd.get(i - 1).unwrap()[0];
+                        actions.push(SpawnAction::dup2(read_end, 0));
                     }
-                    line.clear();
+                    // send output to the next process
+                    if i < process_arguments_list.len() - 1 {
+                        let write_end = pipes_fd.get(i).unwrap()[1];
+                        actions.push(SpawnAction::dup2(write_end, 1));
+                    }
+                    let pid = spawn(args_copy[0].as_str(), args_addr.as_slice(), &actions);
+                    for fd in opened.iter() {
+                        close(*fd);
+                    }
+                    if pid < 0 {
+                        println!("Error when executing!");
+                        continue;
+                    }
+                    if pgid == 0 {
+                        pgid = pid as usize;
+                    }
+                    setpgid(pid as usize, pgid);
+                    children.push(pid);
                 }
-                print!("{}", LINE_START);
-            }
-            BS | DL => {
-                if !line.is_empty() {
-                    print!("{}", BS as char);
-                    print!(" ");
-                    print!("{}", BS as char);
-                    line.pop();
+                // the line reads the console until it exits
+                if pgid != 0 {
+                    tcsetpgrp(0, pgid);
                 }
-            }
-            _ => {
-                print!("{}", c as char);
-                line.push(c as char);
+                for pipe_fd in pipes_fd.iter() {
+                    close(pipe_fd[0]);
+                    close(pipe_fd[1]);
+                }
+                let mut exit_code: i32 = 0;
+                for pid in children.into_iter() {
+                    let exit_pid = waitpid(pid as usize, &mut exit_code);
+                    assert_eq!(pid, exit_pid);
+                    //println!("Shell: Process {} exited with code {}", pid, exit_code);
+                }
+                tcsetpgrp(0, getpgid(0) as usize);
             }
         }
     }
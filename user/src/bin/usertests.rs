@@ -5,11 +5,17 @@
 extern crate user_lib;
 
 // not in SUCC_TESTS & FAIL_TESTS
-// count_lines, infloop, user_shell, usertests
+// cgexec, count_lines, crashdump, dd, dmesg, editor, forkbench, fsck_easyfs, infloop, klogd,
+// linuxexec, mkfs_easyfs, mkfs_fat, mount, nice, ps, restore, schedbench, suspend, umount,
+// user_shell, usertests
 
 // item of TESTS : app_name(argv_0), argv_1, argv_2, argv_3, exit_code
 static SUCC_TESTS: &[(&str, &str, &str, &str, i32)] = &[
     ("filetest_simple\0", "\0", "\0", "\0", 0),
+    ("filetest_seek\0", "\0", "\0", "\0", 0),
+    ("chroot_test\0", "\0", "\0", "\0", 0),
+    ("dir_test\0", "\0", "\0", "\0", 0),
+    ("flock_test\0", "\0", "\0", "\0", 0),
     ("cat\0", "filea\0", "\0", "\0", 0),
     ("cmdline_args\0", "1\0", "2\0", "3\0", 0),
     ("eisenberg\0", "\0", "\0", "\0", 0),
@@ -27,26 +33,81 @@ static SUCC_TESTS: &[(&str, &str, &str, &str, i32)] = &[
     ("phil_din_mutex\0", "\0", "\0", "\0", 0),
     ("pipe_large_test\0", "\0", "\0", "\0", 0),
     ("pipetest\0", "\0", "\0", "\0", 0),
+    ("pipe_nonblock_test\0", "\0", "\0", "\0", 0),
+    ("select_test\0", "\0", "\0", "\0", 0),
+    ("epoll_test\0", "\0", "\0", "\0", 0),
+    ("async_pipe_test\0", "\0", "\0", "\0", 0),
+    ("async_echo_stress\0", "\0", "\0", "\0", 0),
+    ("mqueue_test\0", "\0", "\0", "\0", 0),
     ("adder_peterson_spin\0", "\0", "\0", "\0", 0),
     ("adder_peterson_yield\0", "\0", "\0", "\0", 0),
     ("adder_mutex_blocking\0", "\0", "\0", "\0", 0),
     ("adder_mutex_spin\0", "\0", "\0", "\0", 0),
+    ("adder_mutex_futex\0", "\0", "\0", "\0", 0),
     ("run_pipe_test\0", "\0", "\0", "\0", 0),
     ("sleep_simple\0", "\0", "\0", "\0", 0),
     ("sleep\0", "\0", "\0", "\0", 0),
     ("sleep_simple\0", "\0", "\0", "\0", 0),
     ("sync_sem\0", "\0", "\0", "\0", 0),
+    ("sync_timeout\0", "\0", "\0", "\0", 0),
+    ("deadlock_detect\0", "\0", "\0", "\0", 0),
+    ("mutex_owner_died\0", "\0", "\0", "\0", 0),
     ("condsync_sem\0", "\0", "\0", "\0", 0),
     ("condsync_condvar\0", "\0", "\0", "\0", 0),
     ("threads_arg\0", "\0", "\0", "\0", 0),
     ("threads\0", "\0", "\0", "\0", 0),
     ("yield\0", "\0", "\0", "\0", 0),
+    ("cgroup_test\0", "\0", "\0", "\0", 0),
+    ("priority_test\0", "\0", "\0", "\0", 0),
+    ("linux_abi_test\0", "\0", "\0", "\0", 0),
+    ("checkpoint_test\0", "\0", "\0", "\0", 0),
+    ("memusage_test\0", "\0", "\0", "\0", 0),
+    ("cow_test\0", "\0", "\0", "\0", 0),
+    ("mmap_test\0", "\0", "\0", "\0", 0),
+    ("spawn_test\0", "\0", "\0", "\0", 0),
+    ("binfmt_test\0", "\0", "\0", "\0", 0),
+    ("sig_test\0", "\0", "\0", "\0", 0),
+    ("user_ptr_test\0", "\0", "\0", "\0", 0),
+    ("eintr_test\0", "\0", "\0", "\0", 0),
+    ("pidfd_test\0", "\0", "\0", "\0", 0),
+    ("stack_grow\0", "\0", "\0", "\0", 0),
+    ("vmmap\0", "-p\0", "\0", "\0", 0),
+    ("hotplug_test\0", "\0", "\0", "\0", 0),
+    ("pgrp_test\0", "\0", "\0", "\0", 0),
+    ("fdlimit_test\0", "\0", "\0", "\0", 0),
+    ("cloexec_test\0", "\0", "\0", "\0", 0),
+    ("mount_test\0", "\0", "\0", "\0", 0),
+    ("fat_test\0", "\0", "\0", "\0", 0),
+    ("syncd_test\0", "\0", "\0", "\0", 0),
+    ("sync_test\0", "\0", "\0", "\0", 0),
+    ("proc_access_test\0", "\0", "\0", "\0", 0),
+    ("procfs_test\0", "\0", "\0", "\0", 0),
+    ("devfs_test\0", "\0", "\0", "\0", 0),
+    ("mkfs_test\0", "\0", "\0", "\0", 0),
+    ("blkdev_test\0", "\0", "\0", "\0", 0),
+    ("dd_test\0", "\0", "\0", "\0", 0),
+    ("sendfile_test\0", "\0", "\0", "\0", 0),
+    ("pagecache_test\0", "\0", "\0", "\0", 0),
+    ("fsync_test\0", "\0", "\0", "\0", 0),
+    ("fd_share_test\0", "\0", "\0", "\0", 0),
+    ("klog_test\0", "\0", "\0", "\0", 0),
+    ("read_timeout_test\0", "\0", "\0", "\0", 0),
+    ("nanosleep_test\0", "\0", "\0", "\0", 0),
+    ("clock_test\0", "\0", "\0", "\0", 0),
+    ("vtime_test\0", "\0", "\0", "\0", 0),
+    ("futex_test\0", "\0", "\0", "\0", 0),
+    ("syserror_test\0", "\0", "\0", "\0", 0),
+    ("mpsc_test\0", "\0", "\0", "\0", 0),
+    ("green_test\0", "\0", "\0", "\0", 0),
+    ("quota_test\0", "\0", "\0", "\0", 0),
+    ("watchpoint_test\0", "\0", "\0", "\0", 0),
     ("barrier_fail\0", "\0", "\0", "\0", 0),
     ("barrier_condvar\0", "\0", "\0", "\0", 0),
 ];
 
 static FAIL_TESTS: &[(&str, &str, &str, &str, i32)] = &[
     ("stack_overflow\0", "\0", "\0", "\0", -11),
+    ("thread_stack_overflow\0", "\0", "\0", "\0", -11),
     ("race_adder_loop\0", "\0", "\0", "\0", -6),
     ("priv_csr\0", "\0", "\0", "\0", -4),
     ("priv_inst\0", "\0", "\0", "\0", -4),
@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{close, getpid, open, read, vmdump, OpenFlags};
+
+/// Usage: vmmap [pid] [-p]
+/// List the areas of a process, the current one by default. With `-p` the
+/// page table mappings of each area are printed as well.
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let mut pid = getpid() as usize;
+    let mut pages = false;
+    for arg in argv.iter().take(argc).skip(1) {
+        match *arg {
+            "-p" => pages = true,
+            arg => match arg.parse() {
+                Ok(arg) => pid = arg,
+                Err(_) => {
+                    println!("Usage: vmmap [pid] [-p]");
+                    return -1;
+                }
+            },
+        }
+    }
+    let fd = open(format!("/proc/{}/maps\0", pid).as_str(), OpenFlags::RDONLY);
+    if fd < 0 {
+        println!("vmmap: no such process {}", pid);
+        return -1;
+    }
+    let fd = fd as usize;
+    let mut maps = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let size = read(fd, &mut buf);
+        if size <= 0 {
+            break;
+        }
+        maps.extend_from_slice(&buf[..size as usize]);
+    }
+    close(fd);
+    let maps = String::from_utf8(maps).unwrap();
+    for line in maps.lines() {
+        println!("{}", line);
+        if !pages {
+            continue;
+        }
+        // the range is like "0000000000010000-0000000000012000"
+        let range = line.split(' ').next().unwrap();
+        let (start, end) = range.split_once('-').unwrap();
+        let start = usize::from_str_radix(start, 16).unwrap();
+        let end = usize::from_str_radix(end, 16).unwrap();
+        vmdump(pid, start, end);
+    }
+    0
+}
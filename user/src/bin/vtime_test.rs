@@ -0,0 +1,32 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{get_time, read, tcgetattr, tcsetattr, LocalFlags, Termios, VMIN, VTIME};
+
+const STDIN: usize = 0;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut orig = Termios::default();
+    if tcgetattr(STDIN, &mut orig) < 0 {
+        println!("vtime_test: stdin is not a terminal, skipped");
+        return 0;
+    }
+    let mut raw = orig;
+    raw.lflag &= !(LocalFlags::ECHO | LocalFlags::ICANON).bits();
+    raw.cc[VMIN] = 0;
+    raw.cc[VTIME] = 2;
+    assert_eq!(tcsetattr(STDIN, &raw), 0);
+    // nothing is typed, so the read gives up after 200ms
+    let mut buf = [0u8; 1];
+    let start = get_time();
+    let len = read(STDIN, &mut buf);
+    let elapsed = get_time() - start;
+    tcsetattr(STDIN, &orig);
+    assert!(len == 1 || elapsed >= 200, "read {} in {}ms", len, elapsed);
+    println!("vtime_test passed!");
+    0
+}
@@ -0,0 +1,107 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use user_lib::{
+    close, exit, fork, get_watchpoint, open, read, set_watchpoint, vload, vstore, waitpid, write,
+    OpenFlags, WatchKind, Watchpoint,
+};
+
+#[repr(align(8))]
+struct Guarded(usize);
+
+static mut GUARDED: Guarded = Guarded(0);
+
+const KERNEL_WATCHPOINTS: &str = "/proc/sys/debug/watchpoints\0";
+/// The start of the kernel text, which is never written.
+const KERNEL_TEXT: usize = 0x80200000;
+
+/// Write the kernel watchpoints, return what write returns.
+fn store_kernel(text: &str) -> isize {
+    let fd = open(KERNEL_WATCHPOINTS, OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    let written = write(fd as usize, text.as_bytes());
+    close(fd as usize);
+    written
+}
+
+/// The lines of the kernel watchpoints are put in `buf`.
+fn kernel_watchpoints(buf: &mut [u8]) -> &str {
+    let fd = open(KERNEL_WATCHPOINTS, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let len = read(fd as usize, buf);
+    close(fd as usize);
+    assert!(len >= 0);
+    core::str::from_utf8(&buf[..len as usize]).unwrap()
+}
+
+/// Watch the kernel text through procfs, then remove it by its handle.
+fn kernel() {
+    // not a power of two, or an unknown kind
+    assert_eq!(store_kernel(&format!("{:#x} 3 w", KERNEL_TEXT)), 0);
+    assert_eq!(store_kernel(&format!("{:#x} 8 q", KERNEL_TEXT)), 0);
+    let text = format!("{:#x} 8 w", KERNEL_TEXT);
+    assert_eq!(store_kernel(&text), text.len() as isize);
+    let mut buf = [0u8; 256];
+    let line = kernel_watchpoints(&mut buf).lines().next().unwrap();
+    let (handle, watchpoint) = line.split_once(' ').unwrap();
+    assert_eq!(watchpoint, format!("{:#x} 8 -w-", KERNEL_TEXT));
+    let remove = format!("-{}", handle);
+    assert_eq!(store_kernel(&remove), remove.len() as isize);
+    assert_eq!(store_kernel(&remove), 0);
+    assert_eq!(kernel_watchpoints(&mut buf), "");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let addr = unsafe { &GUARDED as *const Guarded as usize };
+    // not a power of two, or not aligned
+    assert_eq!(
+        set_watchpoint(0, 0, &Watchpoint::new(addr, 3, WatchKind::STORE)),
+        -1
+    );
+    assert_eq!(
+        set_watchpoint(0, 0, &Watchpoint::new(addr + 1, 8, WatchKind::STORE)),
+        -1
+    );
+    assert_eq!(
+        set_watchpoint(0, 4, &Watchpoint::new(addr, 8, WatchKind::STORE)),
+        -1
+    );
+    if set_watchpoint(0, 0, &Watchpoint::new(addr, 8, WatchKind::STORE)) != 0 {
+        println!("no hardware triggers, watchpoint_test skipped");
+        return 0;
+    }
+    let mut watchpoint = Watchpoint::default();
+    assert_eq!(get_watchpoint(0, 0, &mut watchpoint), 0);
+    assert_eq!((watchpoint.addr, watchpoint.len), (addr, 8));
+    // reads do not hit a store watchpoint
+    assert_eq!(vload!(unsafe { &GUARDED.0 }), 0usize);
+    assert_eq!(set_watchpoint(0, 0, &Watchpoint::default()), 0);
+
+    // the watchpoints are not inherited, the child sets one by itself
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(get_watchpoint(0, 0, &mut watchpoint), 0);
+        assert_eq!(watchpoint.len, 0);
+        assert_eq!(
+            set_watchpoint(0, 1, &Watchpoint::new(addr, 8, WatchKind::STORE)),
+            0
+        );
+        vstore!(unsafe { &GUARDED.0 }, 1usize);
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    // SIGTRAP
+    assert_eq!(exit_code, -5);
+    // never written here
+    assert_eq!(vload!(unsafe { &GUARDED.0 }), 0usize);
+    kernel();
+    println!("watchpoint_test passed!");
+    0
+}
@@ -0,0 +1,45 @@
+use super::{blkgetsize, fdatasync, lseek, read, write, SEEK_SET};
+use easy_fs::{BlockDevice, BLOCK_SZ};
+
+/// A block device file such as `/dev/ram0` opened as the descriptor, to use
+/// easy-fs on it in user space.
+pub struct FdBlockDevice(pub usize);
+
+impl FdBlockDevice {
+    fn seek_block(&self, block_id: usize) {
+        let offset = (block_id * BLOCK_SZ) as isize;
+        assert_eq!(
+            lseek(self.0, offset, SEEK_SET),
+            offset,
+            "Error when seeking!"
+        );
+    }
+}
+
+impl BlockDevice for FdBlockDevice {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        self.seek_block(block_id);
+        assert_eq!(
+            read(self.0, buf),
+            BLOCK_SZ as isize,
+            "Not a complete block!"
+        );
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.seek_block(block_id);
+        assert_eq!(
+            write(self.0, buf),
+            BLOCK_SZ as isize,
+            "Not a complete block!"
+        );
+    }
+    fn num_blocks(&self) -> usize {
+        blkgetsize(self.0) as usize
+    }
+    fn handle_irq(&self) {
+        unimplemented!();
+    }
+    fn flush(&self) {
+        assert_eq!(fdatasync(self.0), 0, "Error when flushing!");
+    }
+}
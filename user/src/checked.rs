@@ -0,0 +1,470 @@
+//! The syscalls returning `SysResult` rather than their codes, for the
+//! callers handling the errors with `?`, like
+//! `let fd = checked::open(path, flags)?`. What they fill in is returned
+//! instead of passed by reference. `linux_syscall` is left out, its codes
+//! are those of Linux.
+
+use super::*;
+use core::convert::Infallible;
+use core::sync::atomic::AtomicU32;
+
+pub use super::exit;
+
+fn check(ret: isize) -> SysResult {
+    SysError::check(ret)
+}
+
+fn done(ret: isize) -> SysResult<()> {
+    SysError::check(ret).map(drop)
+}
+
+/// For the calls only returning on error.
+fn failed(ret: isize) -> SysResult<Infallible> {
+    Err(SysError::check(ret).err().unwrap_or(SysError::Failed))
+}
+
+pub fn dup(fd: usize) -> SysResult {
+    check(super::dup(fd))
+}
+pub fn open(path: &str, flags: OpenFlags) -> SysResult {
+    check(super::open(path, flags))
+}
+pub fn close(fd: usize) -> SysResult<()> {
+    done(super::close(fd))
+}
+/// Return the read end and the write end.
+pub fn pipe() -> SysResult<[usize; 2]> {
+    let mut pipe_fd = [0usize; 2];
+    done(super::pipe(&mut pipe_fd))?;
+    Ok(pipe_fd)
+}
+pub fn pipe2(flags: OpenFlags) -> SysResult<[usize; 2]> {
+    let mut pipe_fd = [0usize; 2];
+    done(super::pipe2(&mut pipe_fd, flags))?;
+    Ok(pipe_fd)
+}
+pub fn fcntl(fd: usize, cmd: usize, arg: usize) -> SysResult {
+    check(super::fcntl(fd, cmd, arg))
+}
+pub fn fcntl_lock(fd: usize, cmd: usize, flock: &mut Flock) -> SysResult<()> {
+    done(super::fcntl_lock(fd, cmd, flock))
+}
+/// Return `WouldBlock` if `LOCK_NB` is given and the file is locked.
+pub fn flock(fd: usize, op: usize) -> SysResult<()> {
+    done(super::flock(fd, op))
+}
+pub fn close_range(first: usize, last: usize, flags: u32) -> SysResult<()> {
+    done(super::close_range(first, last, flags))
+}
+pub fn read(fd: usize, buf: &mut [u8]) -> SysResult {
+    check(super::read(fd, buf))
+}
+/// Return `NotReady` if nothing is read in `timeout_ms`.
+pub fn read_timeout(fd: usize, buf: &mut [u8], timeout_ms: isize) -> SysResult {
+    check(super::read_timeout(fd, buf, timeout_ms))
+}
+pub fn write(fd: usize, buf: &[u8]) -> SysResult {
+    check(super::write(fd, buf))
+}
+pub fn lseek(fd: usize, offset: isize, whence: usize) -> SysResult {
+    check(super::lseek(fd, offset, whence))
+}
+pub fn ftruncate(fd: usize, len: usize) -> SysResult<()> {
+    done(super::ftruncate(fd, len))
+}
+pub fn sync() -> SysResult<()> {
+    done(super::sync())
+}
+pub fn fsync(fd: usize) -> SysResult<()> {
+    done(super::fsync(fd))
+}
+pub fn fdatasync(fd: usize) -> SysResult<()> {
+    done(super::fdatasync(fd))
+}
+pub fn rename(old_path: &str, new_path: &str) -> SysResult<()> {
+    done(super::rename(old_path, new_path))
+}
+pub fn mkdir(path: &str) -> SysResult<()> {
+    done(super::mkdir(path))
+}
+pub fn unlink(path: &str) -> SysResult<()> {
+    done(super::unlink(path))
+}
+pub fn rmdir(path: &str) -> SysResult<()> {
+    done(super::rmdir(path))
+}
+pub fn getdents(fd: usize, buf: &mut [u8]) -> SysResult {
+    check(super::getdents(fd, buf))
+}
+pub fn getcwd(buf: &mut [u8]) -> SysResult {
+    check(super::getcwd(buf))
+}
+pub fn mount(source: &str, target: &str, fstype: &str, flags: usize) -> SysResult<()> {
+    done(super::mount(source, target, fstype, flags))
+}
+pub fn umount(target: &str) -> SysResult<()> {
+    done(super::umount(target))
+}
+pub fn getquota(uid: usize) -> SysResult<Quota> {
+    let mut quota = Quota::default();
+    done(super::getquota(uid, &mut quota))?;
+    Ok(quota)
+}
+pub fn setquota(uid: usize, quota: &Quota) -> SysResult<()> {
+    done(super::setquota(uid, quota))
+}
+pub fn chdir(path: &str) -> SysResult<()> {
+    done(super::chdir(path))
+}
+pub fn chroot(path: &str) -> SysResult<()> {
+    done(super::chroot(path))
+}
+pub fn ioctl(fd: usize, cmd: usize, arg: usize) -> SysResult {
+    check(super::ioctl(fd, cmd, arg))
+}
+pub fn tcgetattr(fd: usize) -> SysResult<Termios> {
+    let mut termios = Termios::default();
+    done(super::tcgetattr(fd, &mut termios))?;
+    Ok(termios)
+}
+pub fn tcsetattr(fd: usize, termios: &Termios) -> SysResult<()> {
+    done(super::tcsetattr(fd, termios))
+}
+pub fn tcgetpgrp(fd: usize) -> SysResult {
+    check(super::tcgetpgrp(fd))
+}
+pub fn tcsetpgrp(fd: usize, pgid: usize) -> SysResult<()> {
+    done(super::tcsetpgrp(fd, pgid))
+}
+pub fn blkgetsize(fd: usize) -> SysResult {
+    check(super::blkgetsize(fd))
+}
+pub fn sendfile(
+    out_fd: usize,
+    in_fd: usize,
+    offset: Option<&mut usize>,
+    count: usize,
+) -> SysResult {
+    check(super::sendfile(out_fd, in_fd, offset, count))
+}
+pub fn poll(fds: &mut [PollFd], timeout_ms: isize) -> SysResult {
+    check(super::poll(fds, timeout_ms))
+}
+pub fn epoll_create(flags: OpenFlags) -> SysResult {
+    check(super::epoll_create(flags))
+}
+pub fn epoll_ctl(epfd: usize, op: usize, fd: usize, event: Option<&EpollEvent>) -> SysResult<()> {
+    done(super::epoll_ctl(epfd, op, fd, event))
+}
+pub fn epoll_wait(epfd: usize, events: &mut [EpollEvent], timeout_ms: isize) -> SysResult {
+    check(super::epoll_wait(epfd, events, timeout_ms))
+}
+pub fn select(
+    nfds: usize,
+    readfds: Option<&mut FdSet>,
+    writefds: Option<&mut FdSet>,
+    timeout_ms: isize,
+) -> SysResult {
+    check(super::select(nfds, readfds, writefds, timeout_ms))
+}
+
+pub fn yield_() -> SysResult<()> {
+    done(super::yield_())
+}
+pub fn get_time() -> SysResult {
+    check(super::get_time())
+}
+pub fn get_time_ns() -> SysResult {
+    check(super::get_time_ns())
+}
+pub fn clock_gettime(clock_id: usize) -> SysResult<TimeSpec> {
+    let mut tp = TimeSpec::default();
+    done(super::clock_gettime(clock_id, &mut tp))?;
+    Ok(tp)
+}
+pub fn getpid() -> SysResult {
+    check(super::getpid())
+}
+/// Return 0 in the child and its pid in the parent.
+pub fn fork() -> SysResult {
+    check(super::fork())
+}
+pub fn exec(path: &str, args: &[*const u8]) -> SysResult<Infallible> {
+    failed(super::exec(path, args))
+}
+pub fn spawn(path: &str, args: &[*const u8], actions: &[SpawnAction]) -> SysResult {
+    check(super::spawn(path, args, actions))
+}
+/// Return the pid of a child exited and its exit code.
+pub fn wait() -> SysResult<(usize, i32)> {
+    let mut exit_code = 0;
+    let pid = check(super::wait(&mut exit_code))?;
+    Ok((pid, exit_code))
+}
+pub fn waitpid(pid: usize) -> SysResult<(usize, i32)> {
+    let mut exit_code = 0;
+    let pid = check(super::waitpid(pid, &mut exit_code))?;
+    Ok((pid, exit_code))
+}
+/// Return `NotReady` if the child is running.
+pub fn waitpid_nb(pid: usize) -> SysResult<(usize, i32)> {
+    let mut exit_code = 0;
+    let pid = check(super::waitpid_nb(pid, &mut exit_code))?;
+    Ok((pid, exit_code))
+}
+pub fn sigaction(
+    signum: usize,
+    action: Option<&SignalAction>,
+    old_action: Option<&mut SignalAction>,
+) -> SysResult<()> {
+    done(super::sigaction(signum, action, old_action))
+}
+/// Return the old mask.
+pub fn sigprocmask(how: usize, mask: SignalFlags) -> SysResult<SignalFlags> {
+    check(super::sigprocmask(how, mask)).map(|old| SignalFlags::from_bits_truncate(old as u32))
+}
+pub fn pidfd_open(pid: usize, flags: OpenFlags) -> SysResult {
+    check(super::pidfd_open(pid, flags))
+}
+pub fn kill(pid: usize, signal: u32) -> SysResult<()> {
+    done(super::kill(pid, signal))
+}
+pub fn setpgid(pid: usize, pgid: usize) -> SysResult<()> {
+    done(super::setpgid(pid, pgid))
+}
+pub fn getpgid(pid: usize) -> SysResult {
+    check(super::getpgid(pid))
+}
+pub fn setpriority(which: usize, who: usize, nice: isize) -> SysResult<()> {
+    done(super::setpriority(which, who, nice))
+}
+/// Return the least nice value.
+pub fn getpriority(which: usize, who: usize) -> SysResult<isize> {
+    check(sys_getpriority(which, who)).map(|ret| 20 - ret as isize)
+}
+pub fn setsid() -> SysResult {
+    check(super::setsid())
+}
+pub fn getsid(pid: usize) -> SysResult {
+    check(super::getsid(pid))
+}
+pub fn getuid() -> SysResult {
+    check(super::getuid())
+}
+pub fn setuid(uid: usize) -> SysResult<()> {
+    done(super::setuid(uid))
+}
+/// Return the previous personality.
+pub fn personality(persona: usize) -> SysResult {
+    check(super::personality(persona))
+}
+pub fn cgroup_create(shares: usize, quota_ms: usize) -> SysResult {
+    check(super::cgroup_create(shares, quota_ms))
+}
+pub fn cgroup_attach(pid: usize, group_id: usize) -> SysResult<()> {
+    done(super::cgroup_attach(pid, group_id))
+}
+pub fn cgroup_usage(group_id: usize) -> SysResult {
+    check(super::cgroup_usage(group_id))
+}
+/// Return false after saved, true when resumed by `restore`.
+pub fn checkpoint(path: &str) -> SysResult<bool> {
+    check(super::checkpoint(path)).map(|ret| ret == 1)
+}
+pub fn restore(path: &str) -> SysResult<Infallible> {
+    failed(super::restore(path))
+}
+pub fn memusage(pid: usize) -> SysResult<MemUsage> {
+    let mut usage = MemUsage::default();
+    done(super::memusage(pid, &mut usage))?;
+    Ok(usage)
+}
+pub fn vm_stats(pid: usize) -> SysResult<VmStats> {
+    let mut stats = VmStats::default();
+    done(super::vm_stats(pid, &mut stats))?;
+    Ok(stats)
+}
+pub fn vmdump(pid: usize, start: usize, end: usize) -> SysResult<()> {
+    done(super::vmdump(pid, start, end))
+}
+pub fn mmap(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> SysResult {
+    check(super::mmap(addr, len, prot, flags, fd, offset))
+}
+pub fn munmap(addr: usize, len: usize) -> SysResult<()> {
+    done(super::munmap(addr, len))
+}
+pub fn cpu_up(hart_id: usize) -> SysResult<()> {
+    done(super::cpu_up(hart_id))
+}
+pub fn cpu_down(hart_id: usize) -> SysResult<()> {
+    done(super::cpu_down(hart_id))
+}
+pub fn suspend() -> SysResult<()> {
+    done(super::suspend())
+}
+pub fn get_watchpoint(pid: usize, slot: usize) -> SysResult<Watchpoint> {
+    let mut watchpoint = Watchpoint::default();
+    done(super::get_watchpoint(pid, slot, &mut watchpoint))?;
+    Ok(watchpoint)
+}
+pub fn set_watchpoint(pid: usize, slot: usize, watchpoint: &Watchpoint) -> SysResult<()> {
+    done(super::set_watchpoint(pid, slot, watchpoint))
+}
+/// Return `Interrupted` if a signal handler runs first.
+pub fn sleep(sleep_ms: usize) -> SysResult<()> {
+    done(super::sleep(sleep_ms))
+}
+pub fn nanosleep(ns: usize) -> SysResult<()> {
+    done(super::nanosleep(ns))
+}
+pub fn thread_create(entry: usize, arg: usize) -> SysResult {
+    check(super::thread_create(entry, arg))
+}
+pub fn gettid() -> SysResult {
+    check(super::gettid())
+}
+/// Return the exit code of the thread, which may be negative, only the codes
+/// of a thread not to be waited for are errors.
+pub fn waittid(tid: usize) -> SysResult<i32> {
+    let ret = super::waittid(tid);
+    match SysError::from_code(ret) {
+        Some(error @ (SysError::NoProcess | SysError::NotReady | SysError::Deadlock)) => Err(error),
+        _ => Ok(ret as i32),
+    }
+}
+pub fn getrlimit(resource: usize) -> SysResult<RLimit> {
+    let mut limit = RLimit::default();
+    done(super::getrlimit(resource, &mut limit))?;
+    Ok(limit)
+}
+pub fn setrlimit(resource: usize, limit: &RLimit) -> SysResult<()> {
+    done(super::setrlimit(resource, limit))
+}
+
+pub fn enable_deadlock_detect(enabled: bool) -> SysResult<()> {
+    done(super::enable_deadlock_detect(enabled))
+}
+pub fn mutex_create() -> SysResult {
+    check(super::mutex_create())
+}
+pub fn mutex_blocking_create() -> SysResult {
+    check(super::mutex_blocking_create())
+}
+pub fn mutex_errorcheck_create() -> SysResult {
+    check(super::mutex_errorcheck_create())
+}
+/// Return `Deadlock` if detected, or `OwnerDead` if locked but the previous
+/// owner exited without unlocking it.
+pub fn mutex_lock(mutex_id: usize) -> SysResult<()> {
+    done(super::mutex_lock(mutex_id))
+}
+pub fn mutex_lock_timeout(mutex_id: usize, timeout_ms: usize) -> SysResult<()> {
+    done(super::mutex_lock_timeout(mutex_id, timeout_ms))
+}
+pub fn mutex_unlock(mutex_id: usize) -> SysResult<()> {
+    done(super::mutex_unlock(mutex_id))
+}
+pub fn semaphore_create(res_count: usize) -> SysResult {
+    check(super::semaphore_create(res_count))
+}
+pub fn semaphore_up(sem_id: usize) -> SysResult<()> {
+    done(sys_semaphore_up(sem_id))
+}
+pub fn semaphore_down(sem_id: usize) -> SysResult<()> {
+    done(super::semaphore_down(sem_id))
+}
+pub fn semaphore_down_timeout(sem_id: usize, timeout_ms: usize) -> SysResult<()> {
+    done(super::semaphore_down_timeout(sem_id, timeout_ms))
+}
+pub fn condvar_create() -> SysResult {
+    check(super::condvar_create())
+}
+pub fn condvar_signal(condvar_id: usize) -> SysResult<()> {
+    done(sys_condvar_signal(condvar_id))
+}
+pub fn condvar_wait(condvar_id: usize, mutex_id: usize) -> SysResult<()> {
+    done(super::condvar_wait(condvar_id, mutex_id))
+}
+pub fn condvar_wait_timeout(
+    condvar_id: usize,
+    mutex_id: usize,
+    timeout_ms: usize,
+) -> SysResult<()> {
+    done(super::condvar_wait_timeout(
+        condvar_id, mutex_id, timeout_ms,
+    ))
+}
+/// Return `WouldBlock` if the word is not `val`, or `NotReady` on timeout.
+pub fn futex_wait(word: &AtomicU32, val: u32, timeout_ns: isize) -> SysResult<()> {
+    done(super::futex_wait(word, val, timeout_ns))
+}
+pub fn futex_wake(word: &AtomicU32, count: usize) -> SysResult {
+    check(super::futex_wake(word, count))
+}
+
+pub fn mq_open(name: &str, flags: OpenFlags, attr: Option<&MqAttr>) -> SysResult {
+    check(super::mq_open(name, flags, attr))
+}
+pub fn mq_unlink(name: &str) -> SysResult<()> {
+    done(super::mq_unlink(name))
+}
+pub fn mq_send(mqd: usize, msg: &[u8], prio: u32) -> SysResult<()> {
+    done(super::mq_send(mqd, msg, prio))
+}
+/// Return the length of the message and its priority.
+pub fn mq_receive(mqd: usize, buf: &mut [u8]) -> SysResult<(usize, u32)> {
+    let mut prio = 0;
+    let len = check(super::mq_receive(mqd, buf, &mut prio))?;
+    Ok((len, prio))
+}
+pub fn mq_getattr(mqd: usize) -> SysResult<MqAttr> {
+    let mut attr = MqAttr::default();
+    done(super::mq_getattr(mqd, &mut attr))?;
+    Ok(attr)
+}
+/// Return the old attributes.
+pub fn mq_setattr(mqd: usize, attr: &MqAttr) -> SysResult<MqAttr> {
+    let mut old_attr = MqAttr::default();
+    done(super::mq_setattr(mqd, attr, &mut old_attr))?;
+    Ok(old_attr)
+}
+
+pub fn connect(ip: u32, sport: u16, dport: u16) -> SysResult {
+    check(super::connect(ip, sport, dport))
+}
+pub fn tcp_connect(ip: u32, sport: u16, dport: u16) -> SysResult {
+    check(super::tcp_connect(ip, sport, dport))
+}
+pub fn listen(sport: u16) -> SysResult {
+    check(super::listen(sport))
+}
+pub fn accept(listen_fd: usize) -> SysResult {
+    check(super::accept(listen_fd))
+}
+
+pub fn framebuffer() -> SysResult {
+    check(super::framebuffer())
+}
+pub fn framebuffer_flush() -> SysResult<()> {
+    done(super::framebuffer_flush())
+}
+pub fn event_get() -> SysResult<Option<InputEvent>> {
+    check(sys_event_get()).map(|raw_value| (raw_value != 0).then(|| (raw_value as u64).into()))
+}
+pub fn key_pressed() -> SysResult<bool> {
+    check(sys_key_pressed()).map(|pressed| pressed == 1)
+}
+
+pub fn klog_map() -> SysResult {
+    check(super::klog_map())
+}
+pub fn dmesg(buf: &mut [u8]) -> SysResult {
+    check(super::dmesg(buf))
+}
@@ -5,17 +5,44 @@ const STDOUT: usize = 1;
 
 use super::{read, write};
 
-struct Stdout;
+/// The bytes of a print written with one syscall, so the lines printed by
+/// threads do not interleave.
+const LINE_LEN: usize = 256;
+
+struct Stdout {
+    line: [u8; LINE_LEN],
+    len: usize,
+}
+
+impl Stdout {
+    fn flush(&mut self) {
+        if self.len > 0 {
+            write(STDOUT, &self.line[..self.len]);
+            self.len = 0;
+        }
+    }
+}
 
 impl Write for Stdout {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        write(STDOUT, s.as_bytes());
+        for &byte in s.as_bytes() {
+            if self.len == LINE_LEN {
+                self.flush();
+            }
+            self.line[self.len] = byte;
+            self.len += 1;
+        }
         Ok(())
     }
 }
 
 pub fn print(args: fmt::Arguments) {
-    Stdout.write_fmt(args).unwrap();
+    let mut stdout = Stdout {
+        line: [0; LINE_LEN],
+        len: 0,
+    };
+    stdout.write_fmt(args).unwrap();
+    stdout.flush();
 }
 
 #[macro_export]
@@ -0,0 +1,5 @@
+//! The errors of the syscalls as `SysError`, returned by the wrappers of
+//! `checked` for the callers handling them with `?` rather than by their
+//! codes.
+
+pub use abi::{SysError, SysResult};
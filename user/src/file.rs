@@ -1,12 +1,138 @@
 use super::*;
+pub use abi::{
+    EpollEvent, EpollFlags, FdSet, InputFlags, LocalFlags, Termios, Winsize, EPOLL_CTL_ADD,
+    EPOLL_CTL_DEL, EPOLL_CTL_MOD, FD_SETSIZE, VMIN, VTIME,
+};
+use abi::{DIRENT64_HEAD, DT_DIR, TCGETS, TCSETS, TIOCGPGRP, TIOCSPGRP};
 
 bitflags! {
     pub struct OpenFlags: u32 {
         const RDONLY = 0;
         const WRONLY = 1 << 0;
         const RDWR = 1 << 1;
+        const EXCL = 1 << 7;
+        /// each write is at the end of the file
+        const APPEND = 1 << 8;
         const CREATE = 1 << 9;
         const TRUNC = 1 << 10;
+        const NONBLOCK = 1 << 11;
+        /// reads and writes of whole blocks, from and to buffers aligned to
+        /// blocks, bypass the block cache
+        const DIRECT = 1 << 14;
+        /// the descriptor is closed by exec
+        const CLOEXEC = 1 << 19;
+    }
+}
+
+/// Remove a directory rather than a file, see `rmdir`.
+pub const AT_REMOVEDIR: u32 = 0x200;
+
+pub const SEEK_SET: usize = 0;
+pub const SEEK_CUR: usize = 1;
+pub const SEEK_END: usize = 2;
+
+pub const F_DUPFD: usize = 0;
+pub const F_GETFD: usize = 1;
+pub const F_SETFD: usize = 2;
+/// Get the access mode and the status flags of the open file, which are
+/// shared by the duplicates of the descriptor and by forked children.
+pub const F_GETFL: usize = 3;
+/// Set the status flags, `OpenFlags::APPEND`, `OpenFlags::NONBLOCK` and
+/// `OpenFlags::DIRECT`.
+pub const F_SETFL: usize = 4;
+/// Find a lock conflicting with a `Flock`, see `fcntl_lock`.
+pub const F_GETLK: usize = 5;
+/// Take or release a range, `EAGAIN` if it is held by another process.
+pub const F_SETLK: usize = 6;
+/// Take or release a range, waiting for other processes to release it.
+pub const F_SETLKW: usize = 7;
+pub const F_DUPFD_CLOEXEC: usize = 1030;
+/// Set the capacity of a pipe in bytes, up to 1 MiB and not less than the
+/// bytes in it, return the capacity.
+pub const F_SETPIPE_SZ: usize = 1031;
+pub const F_GETPIPE_SZ: usize = 1032;
+/// The only flag of descriptors, closed by exec.
+pub const FD_CLOEXEC: usize = 1;
+/// Mark the descriptors close-on-exec rather than closing them.
+pub const CLOSE_RANGE_CLOEXEC: u32 = 1 << 2;
+
+pub const F_RDLCK: i16 = 0;
+pub const F_WRLCK: i16 = 1;
+pub const F_UNLCK: i16 = 2;
+
+/// A lock shared with other readers, see `flock`.
+pub const LOCK_SH: usize = 1;
+pub const LOCK_EX: usize = 2;
+/// Return `EAGAIN` rather than wait for the lock.
+pub const LOCK_NB: usize = 4;
+pub const LOCK_UN: usize = 8;
+
+/// A range of bytes of a file locked by a process, the same as on Linux.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Flock {
+    /// `F_RDLCK`, `F_WRLCK` or `F_UNLCK`
+    pub l_type: i16,
+    /// `SEEK_SET`, `SEEK_CUR` or `SEEK_END` for `l_start`
+    pub l_whence: i16,
+    pub l_start: i64,
+    /// to the end of the file if 0
+    pub l_len: i64,
+    /// of the holder of the lock found by `F_GETLK`
+    pub l_pid: i32,
+}
+
+impl Flock {
+    /// The range of `len` bytes from `start`.
+    pub fn new(l_type: i16, start: usize, len: usize) -> Self {
+        Self {
+            l_type,
+            l_whence: SEEK_SET as i16,
+            l_start: start as i64,
+            l_len: len as i64,
+            l_pid: 0,
+        }
+    }
+}
+
+/// Nothing can be written to the file system.
+pub const MS_RDONLY: usize = 1;
+/// Changes are written to the disk before returning, the default.
+pub const MS_SYNCHRONOUS: usize = 1 << 4;
+pub const MS_REMOUNT: usize = 1 << 5;
+/// Reads do not update the access times of files.
+pub const MS_NOATIME: usize = 1 << 10;
+
+/// Get the size of a block device in blocks of 512 bytes.
+pub const BLKGETSIZE: usize = 0x1260;
+/// Get the size of a block device in bytes as a `u64`.
+pub const BLKGETSIZE64: usize = 0x8008_1272;
+
+bitflags! {
+    pub struct PollEvents: u16 {
+        const POLLIN = 0x001;
+        const POLLOUT = 0x004;
+        const POLLERR = 0x008;
+        const POLLHUP = 0x010;
+        const POLLNVAL = 0x020;
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: PollEvents,
+    pub revents: PollEvents,
+}
+
+impl PollFd {
+    pub fn new(fd: usize, events: PollEvents) -> Self {
+        Self {
+            fd: fd as i32,
+            events,
+            revents: PollEvents::empty(),
+        }
     }
 }
 
@@ -20,11 +146,228 @@ pub fn close(fd: usize) -> isize {
     sys_close(fd)
 }
 pub fn pipe(pipe_fd: &mut [usize]) -> isize {
-    sys_pipe(pipe_fd)
+    sys_pipe(pipe_fd, 0)
+}
+/// Only `OpenFlags::CLOEXEC` is used.
+pub fn pipe2(pipe_fd: &mut [usize], flags: OpenFlags) -> isize {
+    sys_pipe(pipe_fd, flags.bits)
+}
+pub fn fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    sys_fcntl(fd, cmd, arg)
+}
+/// `F_GETLK`, `F_SETLK` or `F_SETLKW` of `fcntl` with a range. The ranges
+/// a process holds of a file are released when it closes any descriptor of
+/// the file. `EDEADLK` if the holders wait for what the caller holds.
+pub fn fcntl_lock(fd: usize, cmd: usize, flock: &mut Flock) -> isize {
+    sys_fcntl(fd, cmd, flock as *mut Flock as usize)
+}
+/// Lock the whole file for the open file, `LOCK_SH` or `LOCK_EX`, with
+/// `LOCK_NB` not to wait, or unlock it with `LOCK_UN`. The lock is shared
+/// by the duplicates of the descriptor and is released with the last of
+/// them. It does not conflict with the ranges of `fcntl_lock`.
+pub fn flock(fd: usize, op: usize) -> isize {
+    sys_flock(fd, op)
+}
+/// Close the descriptors in [first, last], `last` may be beyond the open
+/// ones.
+pub fn close_range(first: usize, last: usize, flags: u32) -> isize {
+    sys_close_range(first, last, flags)
 }
 pub fn read(fd: usize, buf: &mut [u8]) -> isize {
     sys_read(fd, buf)
 }
+/// Read, but wait at most `timeout_ms` for something to read, or forever if
+/// it is negative. Return -2 on timeout.
+pub fn read_timeout(fd: usize, buf: &mut [u8], timeout_ms: isize) -> isize {
+    sys_read_timeout(fd, buf, timeout_ms)
+}
 pub fn write(fd: usize, buf: &[u8]) -> isize {
     sys_write(fd, buf)
 }
+pub fn lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    sys_lseek(fd, offset, whence)
+}
+pub fn ftruncate(fd: usize, len: usize) -> isize {
+    sys_ftruncate(fd, len)
+}
+/// Return once what has been written to every file system is on the disk.
+pub fn sync() -> isize {
+    sys_sync()
+}
+/// Return once what has been written to `fd` is on the disk, `Invalid` if
+/// it is not a file or a disk.
+pub fn fsync(fd: usize) -> isize {
+    sys_fsync(fd)
+}
+/// The same as `fsync`, the metadata of a file are always written back with
+/// its data.
+pub fn fdatasync(fd: usize) -> isize {
+    sys_fdatasync(fd)
+}
+pub fn rename(old_path: &str, new_path: &str) -> isize {
+    sys_rename(old_path, new_path)
+}
+pub fn mkdir(path: &str) -> isize {
+    sys_mkdir(path)
+}
+/// Remove a file, `IsDir` if it is a directory or `Busy` if in use.
+pub fn unlink(path: &str) -> isize {
+    sys_unlinkat(path, 0)
+}
+/// Remove an empty directory, `NotDir` or `NotEmpty` if it is not one, or
+/// `Busy` if in use.
+pub fn rmdir(path: &str) -> isize {
+    sys_unlinkat(path, AT_REMOVEDIR)
+}
+/// Read the entries of the directory `fd` as `linux_dirent64` into `buf`,
+/// see `dirents`. Return the length read, 0 at the end, `NotDir` if it is
+/// not a directory, or `Invalid` if `buf` is too small for an entry.
+pub fn getdents(fd: usize, buf: &mut [u8]) -> isize {
+    sys_getdents64(fd, buf)
+}
+
+/// An entry of a directory in the bytes read by `getdents`.
+pub struct Dirent<'a> {
+    pub ino: u64,
+    pub is_dir: bool,
+    pub name: &'a str,
+}
+
+/// The entries in the bytes read by `getdents`.
+pub fn dirents(buf: &[u8]) -> impl Iterator<Item = Dirent<'_>> {
+    let mut rest = buf;
+    core::iter::from_fn(move || {
+        if rest.len() < DIRENT64_HEAD {
+            return None;
+        }
+        let reclen = u16::from_le_bytes([rest[16], rest[17]]) as usize;
+        let (dirent, next) = rest.split_at(reclen);
+        rest = next;
+        let name = &dirent[DIRENT64_HEAD..];
+        let len = name
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(name.len());
+        let mut ino = [0u8; 8];
+        ino.copy_from_slice(&dirent[..8]);
+        Some(Dirent {
+            ino: u64::from_le_bytes(ino),
+            is_dir: dirent[18] == DT_DIR,
+            name: core::str::from_utf8(&name[..len]).unwrap_or("?"),
+        })
+    })
+}
+/// Write the working directory ending with '\0' into `buf`, return its
+/// length including '\0', or `Range` if `buf` is too small.
+pub fn getcwd(buf: &mut [u8]) -> isize {
+    sys_getcwd(buf)
+}
+/// Mount the file system `fstype`, only `easyfs`, on the disk `source` at
+/// the directory `target`, or change how the one at `target` is mounted
+/// with `MS_REMOUNT`, when `source` and `fstype` are ignored. Only root may
+/// mount.
+pub fn mount(source: &str, target: &str, fstype: &str, flags: usize) -> isize {
+    sys_mount(source, target, fstype, flags)
+}
+/// Unmount the file system at `target`, `Busy` if files in it are in use.
+pub fn umount(target: &str) -> isize {
+    sys_umount(target, 0)
+}
+pub const Q_GETQUOTA: usize = 0x800007;
+pub const Q_SETQUOTA: usize = 0x800008;
+
+/// The blocks and inodes taken by a user in the root file system, and the
+/// limits of them, 0 for none, same as the kernel.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Quota {
+    pub blocks: u32,
+    pub inodes: u32,
+    pub block_limit: u32,
+    pub inode_limit: u32,
+}
+
+/// Get the quota of `uid`.
+pub fn getquota(uid: usize, quota: &mut Quota) -> isize {
+    sys_quotactl(Q_GETQUOTA, uid, quota)
+}
+/// Set the limits of `uid` from `quota`, which only root may.
+pub fn setquota(uid: usize, quota: &Quota) -> isize {
+    sys_quotactl(Q_SETQUOTA, uid, quota as *const Quota as *mut Quota)
+}
+pub fn chdir(path: &str) -> isize {
+    sys_chdir(path)
+}
+/// The working directory is moved to the new root as well.
+pub fn chroot(path: &str) -> isize {
+    sys_chroot(path)
+}
+pub fn ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    sys_ioctl(fd, cmd, arg)
+}
+pub fn tcgetattr(fd: usize, termios: &mut Termios) -> isize {
+    sys_ioctl(fd, TCGETS, termios as *mut _ as usize)
+}
+pub fn tcsetattr(fd: usize, termios: &Termios) -> isize {
+    sys_ioctl(fd, TCSETS, termios as *const _ as usize)
+}
+/// Get the foreground process group of the terminal, 0 if there is none.
+pub fn tcgetpgrp(fd: usize) -> isize {
+    let mut pgid: i32 = 0;
+    match sys_ioctl(fd, TIOCGPGRP, &mut pgid as *mut _ as usize) {
+        0 => pgid as isize,
+        err => err,
+    }
+}
+/// Only the foreground process group reads the terminal, the others wait.
+pub fn tcsetpgrp(fd: usize, pgid: usize) -> isize {
+    let pgid = pgid as i32;
+    sys_ioctl(fd, TIOCSPGRP, &pgid as *const _ as usize)
+}
+/// Get the number of blocks of 512 bytes of a block device.
+pub fn blkgetsize(fd: usize) -> isize {
+    let mut blocks: usize = 0;
+    match sys_ioctl(fd, BLKGETSIZE, &mut blocks as *mut _ as usize) {
+        0 => blocks as isize,
+        err => err,
+    }
+}
+/// Copy at most `count` bytes of a file to `out_fd` in the kernel, from
+/// `offset` which is advanced, or the offset of `in_fd` if None.
+pub fn sendfile(out_fd: usize, in_fd: usize, offset: Option<&mut usize>, count: usize) -> isize {
+    let offset = offset.map_or(core::ptr::null_mut(), |offset| offset as *mut usize);
+    sys_sendfile(out_fd, in_fd, offset, count)
+}
+/// Wait for events on `fds`, a negative `timeout_ms` means forever.
+pub fn poll(fds: &mut [PollFd], timeout_ms: isize) -> isize {
+    sys_ppoll(fds, timeout_ms)
+}
+/// Create an event queue, only `OpenFlags::CLOEXEC` is supported.
+pub fn epoll_create(flags: OpenFlags) -> isize {
+    sys_epoll_create(flags.bits())
+}
+/// Add `fd` to the interest list of `epfd`, change it or remove it with
+/// `EPOLL_CTL_ADD`, `EPOLL_CTL_MOD` or `EPOLL_CTL_DEL`. `event` may be None
+/// only for `EPOLL_CTL_DEL`.
+pub fn epoll_ctl(epfd: usize, op: usize, fd: usize, event: Option<&EpollEvent>) -> isize {
+    let event = event.map_or(core::ptr::null(), |event| event as *const EpollEvent);
+    sys_epoll_ctl(epfd, op, fd, event)
+}
+/// Wait for the files of `epfd` to be ready and fill `events` with them, a
+/// negative `timeout_ms` means forever. Return the number of them.
+pub fn epoll_wait(epfd: usize, events: &mut [EpollEvent], timeout_ms: isize) -> isize {
+    sys_epoll_wait(epfd, events, timeout_ms)
+}
+/// Wait until some fds below `nfds` in `readfds` are readable or some in
+/// `writefds` writable, a negative `timeout_ms` means forever. Only the
+/// ready ones are left in the sets, return the number of them.
+pub fn select(
+    nfds: usize,
+    readfds: Option<&mut FdSet>,
+    writefds: Option<&mut FdSet>,
+    timeout_ms: isize,
+) -> isize {
+    let readfds = readfds.map_or(core::ptr::null_mut(), |set| set as *mut FdSet);
+    let writefds = writefds.map_or(core::ptr::null_mut(), |set| set as *mut FdSet);
+    sys_pselect(nfds, readfds, writefds, core::ptr::null_mut(), timeout_ms)
+}
@@ -0,0 +1,327 @@
+//! Green threads, stackful coroutines scheduled in the user space on a few
+//! kernel threads, M:N.
+//!
+//! `spawn` queues a green thread and `run` runs them on its kernel threads,
+//! the workers, until all of them finish. A green thread runs until it
+//! calls `yield_now` or waits for a file with `wait`, `read` or `write`,
+//! then its worker switches to the next one ready. The files waited for are
+//! `OpenFlags::NONBLOCK`, an idle worker polls them for all the green
+//! threads waiting. The workers with nothing to do sleep on a futex.
+//!
+//! Each worker keeps a pointer to itself in `tp`, which the kernel keeps
+//! for each thread, so a green thread finds the worker running it.
+
+use super::{
+    futex_wait, futex_wake, mmap, munmap, poll, thread_create, waittid, yield_, Mutex, PollEvents,
+    PollFd, EAGAIN, MAP_ANONYMOUS, MAP_FAILED, MAP_PRIVATE, PROT_READ, PROT_WRITE,
+};
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::arch::{asm, global_asm};
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+global_asm!(include_str!("switch.S"));
+
+extern "C" {
+    fn __green_switch(current_cx_ptr: *mut Context, next_cx_ptr: *const Context);
+}
+
+/// The stack of each green thread, mapped when it is spawned.
+const STACK_SIZE: usize = 4096 * 4;
+/// How long an idle worker polls the files, or sleeps before it checks
+/// whether someone is to poll them.
+const IDLE_WAIT_MS: usize = 10;
+
+/// The registers kept across `__green_switch`, like the `TaskContext` of the
+/// kernel.
+#[repr(C)]
+#[derive(Default)]
+struct Context {
+    ra: usize,
+    sp: usize,
+    s: [usize; 12],
+}
+
+struct GreenThread {
+    context: Context,
+    /// the lowest address of the stack
+    stack: usize,
+    /// taken when it starts
+    entry: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl GreenThread {
+    fn new(entry: Box<dyn FnOnce() + Send>) -> Box<Self> {
+        let stack = mmap(
+            0,
+            STACK_SIZE,
+            PROT_READ | PROT_WRITE,
+            MAP_PRIVATE | MAP_ANONYMOUS,
+            0,
+            0,
+        );
+        assert_ne!(stack, MAP_FAILED, "no memory for a green thread");
+        let stack = stack as usize;
+        Box::new(Self {
+            context: Context {
+                ra: green_entry as usize,
+                sp: stack + STACK_SIZE,
+                ..Default::default()
+            },
+            stack,
+            entry: Some(entry),
+        })
+    }
+}
+
+impl Drop for GreenThread {
+    fn drop(&mut self) {
+        munmap(self.stack, STACK_SIZE);
+    }
+}
+
+/// What the worker does with the green thread which switched back to it.
+#[derive(Clone, Copy)]
+enum Action {
+    Yield,
+    Wait(PollFd),
+    Exit,
+}
+
+/// A kernel thread running green threads.
+struct Worker {
+    /// the context of `work`, which the green threads switch back to
+    context: Context,
+    current: Option<Box<GreenThread>>,
+    action: Action,
+}
+
+struct Scheduler {
+    lock: Mutex,
+    /// only accessed with `lock` locked
+    ready: UnsafeCell<VecDeque<Box<GreenThread>>>,
+    /// the green threads waiting for files, only accessed with `lock` locked
+    waiting: UnsafeCell<Vec<(PollFd, Box<GreenThread>)>>,
+    /// spawned and not finished
+    live: AtomicUsize,
+    /// bumped when a green thread gets ready or all finish, the idle
+    /// workers sleep on it
+    events: AtomicU32,
+    /// taken by the worker polling the files
+    polling: AtomicBool,
+}
+
+unsafe impl Sync for Scheduler {}
+
+static SCHEDULER: Scheduler = Scheduler {
+    lock: Mutex::new(),
+    ready: UnsafeCell::new(VecDeque::new()),
+    waiting: UnsafeCell::new(Vec::new()),
+    live: AtomicUsize::new(0),
+    events: AtomicU32::new(0),
+    polling: AtomicBool::new(false),
+};
+
+impl Scheduler {
+    fn with<T>(
+        &self,
+        f: impl FnOnce(&mut VecDeque<Box<GreenThread>>, &mut Vec<(PollFd, Box<GreenThread>)>) -> T,
+    ) -> T {
+        self.lock.lock();
+        let result = unsafe { f(&mut *self.ready.get(), &mut *self.waiting.get()) };
+        self.lock.unlock();
+        result
+    }
+
+    /// Wake up the idle workers.
+    fn notify(&self, count: usize) {
+        self.events.fetch_add(1, Ordering::SeqCst);
+        futex_wake(&self.events, count);
+    }
+
+    fn push_ready(&self, thread: Box<GreenThread>) {
+        self.with(|ready, _| ready.push_back(thread));
+        self.notify(1);
+    }
+
+    /// Poll the files waited for, at most `IDLE_WAIT_MS`, and make the green
+    /// threads waiting for those with events ready.
+    fn poll_waiting(&self) {
+        let mut fds: Vec<PollFd> =
+            self.with(|_, waiting| waiting.iter().map(|(poll_fd, _)| *poll_fd).collect());
+        if fds.is_empty() || poll(&mut fds, IDLE_WAIT_MS as isize) <= 0 {
+            return;
+        }
+        // only this worker removes waiting ones, the others are appended
+        let woken = self.with(|ready, waiting| {
+            let mut woken = 0;
+            for (index, poll_fd) in fds.iter().enumerate().rev() {
+                if !poll_fd.revents.is_empty() {
+                    ready.push_back(waiting.remove(index).1);
+                    woken += 1;
+                }
+            }
+            woken
+        });
+        self.notify(woken);
+    }
+
+    /// The next green thread to run, waiting for one to get ready. None once
+    /// all finish.
+    fn next(&self) -> Option<Box<GreenThread>> {
+        loop {
+            let seen = self.events.load(Ordering::SeqCst);
+            let (thread, has_waiting) =
+                self.with(|ready, waiting| (ready.pop_front(), !waiting.is_empty()));
+            if thread.is_some() {
+                return thread;
+            }
+            if self.live.load(Ordering::SeqCst) == 0 {
+                return None;
+            }
+            if has_waiting && !self.polling.swap(true, Ordering::SeqCst) {
+                self.poll_waiting();
+                self.polling.store(false, Ordering::SeqCst);
+            } else {
+                futex_wait(&self.events, seen, (IDLE_WAIT_MS * 1_000_000) as isize);
+            }
+        }
+    }
+}
+
+/// The worker of the current kernel thread, None if it runs no green threads.
+fn current_worker() -> Option<&'static mut Worker> {
+    let worker: *mut Worker;
+    unsafe {
+        asm!("mv {}, tp", out(reg) worker);
+        worker.as_mut()
+    }
+}
+
+/// Switch from the current green thread back to its worker, which does
+/// `action` with it.
+fn switch_out(action: Action) {
+    let worker = current_worker().unwrap();
+    worker.action = action;
+    let thread = worker.current.as_mut().unwrap();
+    unsafe {
+        __green_switch(&mut thread.context, &worker.context);
+    }
+}
+
+/// Where a green thread starts, on its own stack.
+extern "C" fn green_entry() -> ! {
+    let worker = current_worker().unwrap();
+    let entry = worker.current.as_mut().unwrap().entry.take().unwrap();
+    entry();
+    switch_out(Action::Exit);
+    unreachable!()
+}
+
+/// Run green threads on the current kernel thread until all finish.
+fn work() {
+    let worker = Box::into_raw(Box::new(Worker {
+        context: Context::default(),
+        current: None,
+        action: Action::Yield,
+    }));
+    let saved_tp: usize;
+    unsafe {
+        asm!("mv {}, tp", out(reg) saved_tp);
+        asm!("mv tp, {}", in(reg) worker);
+    }
+    while let Some(thread) = SCHEDULER.next() {
+        let worker = unsafe { &mut *worker };
+        let next_cx_ptr = &thread.context as *const Context;
+        worker.current = Some(thread);
+        unsafe {
+            __green_switch(&mut worker.context, next_cx_ptr);
+        }
+        let thread = worker.current.take().unwrap();
+        match worker.action {
+            Action::Yield => SCHEDULER.push_ready(thread),
+            Action::Wait(poll_fd) => SCHEDULER.with(|_, waiting| waiting.push((poll_fd, thread))),
+            Action::Exit => {
+                drop(thread);
+                if SCHEDULER.live.fetch_sub(1, Ordering::SeqCst) == 1 {
+                    SCHEDULER.notify(usize::MAX);
+                }
+            }
+        }
+    }
+    unsafe {
+        asm!("mv tp, {}", in(reg) saved_tp);
+        drop(Box::from_raw(worker));
+    }
+}
+
+fn worker_thread() -> ! {
+    work();
+    super::exit(0)
+}
+
+/// Queue a green thread running `f`, it starts once `run` is called.
+pub fn spawn(f: impl FnOnce() + Send + 'static) {
+    SCHEDULER.live.fetch_add(1, Ordering::SeqCst);
+    SCHEDULER.push_ready(GreenThread::new(Box::new(f)));
+}
+
+/// Run the green threads on `workers` kernel threads, the current one
+/// included, until all of them finish, those spawned meanwhile too.
+pub fn run(workers: usize) {
+    let tids: Vec<isize> = (1..workers)
+        .map(|_| thread_create(worker_thread as usize, 0))
+        .collect();
+    work();
+    for tid in tids {
+        waittid(tid as usize);
+    }
+}
+
+/// Let the other green threads run, or other kernel threads if it is not a
+/// green thread.
+pub fn yield_now() {
+    match current_worker() {
+        Some(_) => switch_out(Action::Yield),
+        None => {
+            yield_();
+        }
+    }
+}
+
+/// Wait until `fd` has some of `events`, letting the other green threads
+/// run meanwhile. It only blocks the kernel thread if it is not a green
+/// thread.
+pub fn wait(fd: usize, events: PollEvents) {
+    let poll_fd = PollFd::new(fd, events);
+    match current_worker() {
+        Some(_) => switch_out(Action::Wait(poll_fd)),
+        None => {
+            poll(&mut [poll_fd], -1);
+        }
+    }
+}
+
+/// Read a `OpenFlags::NONBLOCK` file like `read`, waiting with `wait` while
+/// there is nothing to read.
+pub fn read(fd: usize, buf: &mut [u8]) -> isize {
+    loop {
+        match super::read(fd, buf) {
+            EAGAIN => wait(fd, PollEvents::POLLIN),
+            ret => return ret,
+        }
+    }
+}
+
+/// Write a `OpenFlags::NONBLOCK` file like `write`, waiting with `wait`
+/// while there is no room. It may write only a part.
+pub fn write(fd: usize, buf: &[u8]) -> isize {
+    loop {
+        match super::write(fd, buf) {
+            EAGAIN => wait(fd, PollEvents::POLLOUT),
+            ret => return ret,
+        }
+    }
+}
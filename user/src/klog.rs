@@ -0,0 +1,91 @@
+use super::*;
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
+
+/// The same layout as the one in the kernel, followed by the text.
+#[repr(C)]
+struct KlogHeader {
+    /// the bytes written since boot
+    head: AtomicUsize,
+    /// the bytes written and being written, at least `head`
+    reserved: AtomicUsize,
+    size: usize,
+}
+
+/// Map the kernel log read-only, return the address. It is mapped once for
+/// a process and inherited by forked children.
+pub fn klog_map() -> isize {
+    sys_klog_map()
+}
+
+/// Copy the newest bytes kept in the kernel log to `buf`, return the number
+/// of bytes copied. A message with a level starts with `<n>` of syslog.
+pub fn dmesg(buf: &mut [u8]) -> isize {
+    sys_dmesg(buf)
+}
+
+/// A reader of the kernel log, which streams it from the memory mapped by
+/// `klog_map` without syscalls.
+pub struct KlogReader {
+    header: &'static KlogHeader,
+    text: *const u8,
+    /// the bytes of the log read or missed
+    tail: usize,
+}
+
+impl KlogReader {
+    /// Start from the oldest byte kept in the log.
+    pub fn new() -> Self {
+        let base = klog_map() as usize;
+        let header = unsafe { &*(base as *const KlogHeader) };
+        let tail = header
+            .head
+            .load(Ordering::Acquire)
+            .saturating_sub(header.size);
+        Self {
+            header,
+            text: (base + core::mem::size_of::<KlogHeader>()) as *const u8,
+            tail,
+        }
+    }
+
+    /// The number of bytes written to the log since boot.
+    pub fn head(&self) -> usize {
+        self.header.head.load(Ordering::Acquire)
+    }
+
+    /// Skip what is in the log, to read only what comes next.
+    pub fn skip_to_end(&mut self) {
+        self.tail = self.head();
+    }
+
+    /// Copy the bytes not read yet to `buf`. Return the number of bytes
+    /// copied and the number of bytes overwritten by the kernel before they
+    /// could be read.
+    pub fn read(&mut self, buf: &mut [u8]) -> (usize, usize) {
+        let size = self.header.size;
+        let mut lost = 0;
+        loop {
+            let head = self.head();
+            if head - self.tail > size {
+                lost += head - size - self.tail;
+                self.tail = head - size;
+            }
+            let len = buf.len().min(head - self.tail);
+            for (i, byte) in buf[..len].iter_mut().enumerate() {
+                *byte = unsafe { self.text.add((self.tail + i) % size).read_volatile() };
+            }
+            // copy again if the kernel has overwritten some of them meanwhile
+            fence(Ordering::Acquire);
+            if self.header.reserved.load(Ordering::Relaxed) - self.tail <= size {
+                self.tail += len;
+                return (len, lost);
+            }
+        }
+    }
+}
+
+impl Default for KlogReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -6,10 +6,18 @@
 
 #[macro_use]
 pub mod console;
+mod block_dev;
+pub mod checked;
+mod error;
 mod file;
+pub mod green;
 mod io;
+mod klog;
 mod lang_items;
+pub mod mpsc;
+mod mqueue;
 mod net;
+pub mod runtime;
 mod sync;
 mod syscall;
 mod task;
@@ -19,9 +27,13 @@ extern crate alloc;
 extern crate bitflags;
 
 use alloc::vec::Vec;
+pub use block_dev::FdBlockDevice;
 use buddy_system_allocator::LockedHeap;
+pub use error::*;
 pub use file::*;
 pub use io::*;
+pub use klog::*;
+pub use mqueue::*;
 pub use net::*;
 pub use sync::*;
 use syscall::*;
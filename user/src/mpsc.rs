@@ -0,0 +1,146 @@
+//! Channels passing values from the senders in many threads to a receiver
+//! in one, like `std::sync::mpsc`. The receiver parks while the channel is
+//! empty rather than spinning, and the senders unpark it.
+
+use super::{Mutex, Parker};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::cell::{Cell, UnsafeCell};
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+struct Channel<T> {
+    lock: Mutex,
+    /// only accessed with `lock` locked
+    queue: UnsafeCell<VecDeque<T>>,
+    senders: AtomicUsize,
+    receiver_alive: AtomicBool,
+    /// parked by the receiver
+    parker: Parker,
+}
+
+unsafe impl<T: Send> Send for Channel<T> {}
+unsafe impl<T: Send> Sync for Channel<T> {}
+
+impl<T> Channel<T> {
+    fn push(&self, value: T) {
+        self.lock.lock();
+        unsafe { &mut *self.queue.get() }.push_back(value);
+        self.lock.unlock();
+    }
+    fn pop(&self) -> Option<T> {
+        self.lock.lock();
+        let value = unsafe { &mut *self.queue.get() }.pop_front();
+        self.lock.unlock();
+        value
+    }
+}
+
+/// Returned by `Sender::send` with the value if the receiver is dropped.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+/// Returned by `Receiver::recv` if all the senders are dropped and nothing
+/// is left.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RecvError;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    Disconnected,
+}
+
+/// Create a channel without a limit of the values in it.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let channel = Arc::new(Channel {
+        lock: Mutex::new(),
+        queue: UnsafeCell::new(VecDeque::new()),
+        senders: AtomicUsize::new(1),
+        receiver_alive: AtomicBool::new(true),
+        parker: Parker::new(),
+    });
+    (
+        Sender {
+            channel: channel.clone(),
+        },
+        Receiver {
+            channel,
+            _not_sync: PhantomData,
+        },
+    )
+}
+
+/// The sending side, cloned for more senders.
+pub struct Sender<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Never waits.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        if !self.channel.receiver_alive.load(Ordering::SeqCst) {
+            return Err(SendError(value));
+        }
+        self.channel.push(value);
+        self.channel.parker.unpark();
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.channel.senders.fetch_add(1, Ordering::SeqCst);
+        Self {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+/// The receiver sees the channel disconnected once the last one is dropped.
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.channel.senders.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.channel.parker.unpark();
+        }
+    }
+}
+
+/// The receiving side, used by one thread at a time.
+pub struct Receiver<T> {
+    channel: Arc<Channel<T>>,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl<T> Receiver<T> {
+    /// Wait for a value.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        loop {
+            match self.try_recv() {
+                Ok(value) => return Ok(value),
+                Err(TryRecvError::Disconnected) => return Err(RecvError),
+                Err(TryRecvError::Empty) => self.channel.parker.park(),
+            }
+        }
+    }
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        // checked first, so a value sent by the last sender is not missed
+        let disconnected = self.channel.senders.load(Ordering::SeqCst) == 0;
+        match self.channel.pop() {
+            Some(value) => Ok(value),
+            None if disconnected => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+    /// The values until all the senders are dropped.
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        core::iter::from_fn(move || self.recv().ok())
+    }
+}
+
+/// The senders fail from now on, the values left are dropped.
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.channel.receiver_alive.store(false, Ordering::SeqCst);
+    }
+}
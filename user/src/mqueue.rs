@@ -0,0 +1,45 @@
+use super::*;
+
+/// Same as mq_attr of Linux without the reserved fields
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MqAttr {
+    pub flags: usize,
+    pub maxmsg: usize,
+    pub msgsize: usize,
+    pub curmsgs: usize,
+}
+
+impl MqAttr {
+    pub fn new(maxmsg: usize, msgsize: usize) -> Self {
+        Self {
+            maxmsg,
+            msgsize,
+            ..Default::default()
+        }
+    }
+}
+
+/// `attr` is used only when creating the queue, None for the default limits.
+pub fn mq_open(name: &str, flags: OpenFlags, attr: Option<&MqAttr>) -> isize {
+    sys_mq_open(name, flags.bits(), attr)
+}
+pub fn mq_unlink(name: &str) -> isize {
+    sys_mq_unlink(name)
+}
+/// Return -2 if the queue is full and the queue is opened non-blocking.
+pub fn mq_send(mqd: usize, msg: &[u8], prio: u32) -> isize {
+    sys_mq_send(mqd, msg, prio)
+}
+/// Return the length of the message, the buffer should hold at least msgsize
+/// bytes. Return -2 if the queue is empty and the queue is opened non-blocking.
+pub fn mq_receive(mqd: usize, buf: &mut [u8], prio: &mut u32) -> isize {
+    sys_mq_receive(mqd, buf, prio)
+}
+pub fn mq_getattr(mqd: usize, attr: &mut MqAttr) -> isize {
+    sys_mq_getsetattr(mqd, None, attr)
+}
+/// Only `OpenFlags::NONBLOCK` in `attr.flags` takes effect.
+pub fn mq_setattr(mqd: usize, attr: &MqAttr, old_attr: &mut MqAttr) -> isize {
+    sys_mq_getsetattr(mqd, Some(attr), old_attr)
+}
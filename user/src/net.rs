@@ -1,13 +1,18 @@
 use super::*;
 
+/// Create a udp socket, use ip 0 and dport 0 to wait for any peer.
 pub fn connect(ip: u32, sport: u16, dport: u16) -> isize {
     sys_connect(ip, sport, dport)
 }
 
+pub fn tcp_connect(ip: u32, sport: u16, dport: u16) -> isize {
+    sys_tcp_connect(ip, sport, dport)
+}
+
 pub fn listen(sport: u16) -> isize {
     sys_listen(sport)
 }
 
-pub fn accept(socket_fd: usize) -> isize {
-    sys_accept(socket_fd)
+pub fn accept(listen_fd: usize) -> isize {
+    sys_accept(listen_fd)
 }
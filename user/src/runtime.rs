@@ -0,0 +1,251 @@
+//! An async runtime on epoll, running futures on the current thread.
+//!
+//! `spawn` queues a future and `run` polls the futures woken up until all of
+//! them finish. A future waiting for a `OpenFlags::NONBLOCK` file through
+//! `AsyncFd` leaves its waker with the reactor, which adds the file to an
+//! epoll, edge triggered. Once no future is woken up, `run` waits in
+//! `epoll_wait` and wakes up those whose files got ready.
+//!
+//! Only one thread runs the futures and wakes them up.
+
+use super::{
+    close, epoll_create, epoll_ctl, epoll_wait, read, write, EpollEvent, EpollFlags, Mutex,
+    OpenFlags, EAGAIN, EPOLL_CTL_ADD, EPOLL_CTL_DEL,
+};
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use core::cell::UnsafeCell;
+use core::future::{poll_fn, Future};
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+/// The events taken by an `epoll_wait` at most.
+const MAX_EVENTS: usize = 64;
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+/// The ids of the futures woken up.
+struct Woken {
+    lock: Mutex,
+    /// only accessed with `lock` locked
+    ids: UnsafeCell<VecDeque<usize>>,
+}
+
+unsafe impl Sync for Woken {}
+
+impl Woken {
+    fn push(&self, id: usize) {
+        self.lock.lock();
+        unsafe { &mut *self.ids.get() }.push_back(id);
+        self.lock.unlock();
+    }
+    fn pop(&self) -> Option<usize> {
+        self.lock.lock();
+        let id = unsafe { &mut *self.ids.get() }.pop_front();
+        self.lock.unlock();
+        id
+    }
+}
+
+static WOKEN: Woken = Woken {
+    lock: Mutex::new(),
+    ids: UnsafeCell::new(VecDeque::new()),
+};
+
+struct TaskWaker(usize);
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        WOKEN.push(self.0);
+    }
+}
+
+/// The files waited for by the futures, only accessed by the thread
+/// running them.
+struct Reactor {
+    epfd: Option<usize>,
+    /// the wakers of the futures waiting to read and to write by the fds
+    wakers: BTreeMap<usize, (Option<Waker>, Option<Waker>)>,
+}
+
+struct Runtime {
+    /// the futures not finished by their ids, each out while it is polled
+    tasks: BTreeMap<usize, BoxFuture>,
+    next_id: usize,
+    reactor: Reactor,
+}
+
+struct RuntimeCell(UnsafeCell<Runtime>);
+
+unsafe impl Sync for RuntimeCell {}
+
+static RUNTIME: RuntimeCell = RuntimeCell(UnsafeCell::new(Runtime {
+    tasks: BTreeMap::new(),
+    next_id: 0,
+    reactor: Reactor {
+        epfd: None,
+        wakers: BTreeMap::new(),
+    },
+}));
+
+/// Never held across a poll of a future, which may borrow it again.
+fn runtime() -> &'static mut Runtime {
+    unsafe { &mut *RUNTIME.0.get() }
+}
+
+impl Reactor {
+    fn epfd(&mut self) -> usize {
+        *self.epfd.get_or_insert_with(|| {
+            let epfd = epoll_create(OpenFlags::CLOEXEC);
+            assert!(epfd >= 0, "no epoll for the runtime");
+            epfd as usize
+        })
+    }
+
+    fn add(&mut self, fd: usize) {
+        let event = EpollEvent::new(
+            EpollFlags::EPOLLIN | EpollFlags::EPOLLOUT | EpollFlags::EPOLLET,
+            fd as u64,
+        );
+        let epfd = self.epfd();
+        assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, fd, Some(&event)), 0);
+        self.wakers.insert(fd, (None, None));
+    }
+
+    fn remove(&mut self, fd: usize) {
+        let epfd = self.epfd();
+        epoll_ctl(epfd, EPOLL_CTL_DEL, fd, None);
+        self.wakers.remove(&fd);
+    }
+
+    /// Wake up the future with `waker` once `fd` may be readable, or
+    /// writable if `write`.
+    fn wait(&mut self, fd: usize, write: bool, waker: &Waker) {
+        let wakers = self.wakers.get_mut(&fd).unwrap();
+        let slot = if write { &mut wakers.1 } else { &mut wakers.0 };
+        *slot = Some(waker.clone());
+    }
+
+    /// Wait for the files and wake up the futures waiting for them.
+    fn poll(&mut self) {
+        assert!(
+            self.wakers
+                .values()
+                .any(|(reader, writer)| reader.is_some() || writer.is_some()),
+            "the futures wait for nothing"
+        );
+        let epfd = self.epfd();
+        let mut events = [EpollEvent::default(); MAX_EVENTS];
+        let count = epoll_wait(epfd, &mut events, -1);
+        for event in events.iter().take(count.max(0) as usize) {
+            let flags = event.flags();
+            let wakers = match self.wakers.get_mut(&(event.data as usize)) {
+                Some(wakers) => wakers,
+                None => continue,
+            };
+            // at the end or failed, a read or write returns at once too
+            if flags.intersects(EpollFlags::EPOLLIN | EpollFlags::EPOLLHUP | EpollFlags::EPOLLERR) {
+                if let Some(waker) = wakers.0.take() {
+                    waker.wake();
+                }
+            }
+            if flags.intersects(EpollFlags::EPOLLOUT | EpollFlags::EPOLLERR) {
+                if let Some(waker) = wakers.1.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+}
+
+/// Queue `future`, it starts once `run` is called, or at once if it is
+/// spawned by another future.
+pub fn spawn(future: impl Future<Output = ()> + 'static) {
+    let runtime = runtime();
+    let id = runtime.next_id;
+    runtime.next_id += 1;
+    runtime.tasks.insert(id, Box::pin(future));
+    WOKEN.push(id);
+}
+
+/// Run the futures until all of them finish, those spawned meanwhile too.
+pub fn run() {
+    loop {
+        while let Some(id) = WOKEN.pop() {
+            // finished already, woken up more than once
+            let mut future = match runtime().tasks.remove(&id) {
+                Some(future) => future,
+                None => continue,
+            };
+            let waker = Waker::from(Arc::new(TaskWaker(id)));
+            let mut cx = Context::from_waker(&waker);
+            if future.as_mut().poll(&mut cx).is_pending() {
+                runtime().tasks.insert(id, future);
+            }
+        }
+        if runtime().tasks.is_empty() {
+            break;
+        }
+        runtime().reactor.poll();
+    }
+}
+
+/// A `OpenFlags::NONBLOCK` file read and written by the futures, closed
+/// when it is dropped.
+pub struct AsyncFd {
+    fd: usize,
+}
+
+impl AsyncFd {
+    pub fn new(fd: usize) -> Self {
+        runtime().reactor.add(fd);
+        Self { fd }
+    }
+
+    pub fn fd(&self) -> usize {
+        self.fd
+    }
+
+    /// Read like `read`, waiting while there is nothing to read.
+    pub async fn read(&self, buf: &mut [u8]) -> isize {
+        poll_fn(|cx| self.poll_io(cx, false, read(self.fd, buf))).await
+    }
+
+    /// Write like `write`, waiting while there is no room. It may write only
+    /// a part.
+    pub async fn write(&self, buf: &[u8]) -> isize {
+        poll_fn(|cx| self.poll_io(cx, true, write(self.fd, buf))).await
+    }
+
+    /// Write all of `buf`, return the length or the error of a write.
+    pub async fn write_all(&self, buf: &[u8]) -> isize {
+        let mut written = 0;
+        while written < buf.len() {
+            match self.write(&buf[written..]).await {
+                len if len > 0 => written += len as usize,
+                err => return err,
+            }
+        }
+        written as isize
+    }
+
+    /// Wait for the file again if `ret` of a read or write is `EAGAIN`.
+    fn poll_io(&self, cx: &mut Context<'_>, write: bool, ret: isize) -> Poll<isize> {
+        match ret {
+            EAGAIN => {
+                runtime().reactor.wait(self.fd, write, cx.waker());
+                Poll::Pending
+            }
+            ret => Poll::Ready(ret),
+        }
+    }
+}
+
+impl Drop for AsyncFd {
+    fn drop(&mut self) {
+        runtime().reactor.remove(self.fd);
+        close(self.fd);
+    }
+}
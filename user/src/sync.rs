@@ -1,16 +1,37 @@
 use super::*;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Returned instead of waiting when deadlock detection is enabled.
+pub const EDEADLK: isize = SysError::Deadlock.code();
+/// Returned when a mutex is locked, but its previous owner exited without
+/// unlocking it.
+pub const EOWNERDEAD: isize = SysError::OwnerDead.code();
+
+pub fn enable_deadlock_detect(enabled: bool) -> isize {
+    sys_enable_deadlock_detect(enabled as usize)
+}
 
 pub fn mutex_create() -> isize {
-    sys_mutex_create(false)
+    sys_mutex_create(false, false)
 }
 pub fn mutex_blocking_create() -> isize {
-    sys_mutex_create(true)
+    sys_mutex_create(true, false)
+}
+/// Locking it again by the owner returns `Deadlock`, unlocking it by others
+/// `NotPermitted`.
+pub fn mutex_errorcheck_create() -> isize {
+    sys_mutex_create(true, true)
 }
-pub fn mutex_lock(mutex_id: usize) {
-    sys_mutex_lock(mutex_id);
+pub fn mutex_lock(mutex_id: usize) -> isize {
+    sys_mutex_lock(mutex_id)
 }
-pub fn mutex_unlock(mutex_id: usize) {
-    sys_mutex_unlock(mutex_id);
+/// Return -2 if the mutex is not locked in `timeout_ms`.
+pub fn mutex_lock_timeout(mutex_id: usize, timeout_ms: usize) -> isize {
+    sys_mutex_lock_timeout(mutex_id, timeout_ms)
+}
+pub fn mutex_unlock(mutex_id: usize) -> isize {
+    sys_mutex_unlock(mutex_id)
 }
 pub fn semaphore_create(res_count: usize) -> isize {
     sys_semaphore_create(res_count)
@@ -18,8 +39,12 @@ pub fn semaphore_create(res_count: usize) -> isize {
 pub fn semaphore_up(sem_id: usize) {
     sys_semaphore_up(sem_id);
 }
-pub fn semaphore_down(sem_id: usize) {
-    sys_semaphore_down(sem_id);
+pub fn semaphore_down(sem_id: usize) -> isize {
+    sys_semaphore_down(sem_id)
+}
+/// Return -2 if the semaphore is not acquired in `timeout_ms`.
+pub fn semaphore_down_timeout(sem_id: usize, timeout_ms: usize) -> isize {
+    sys_semaphore_down_timeout(sem_id, timeout_ms)
 }
 pub fn condvar_create() -> isize {
     sys_condvar_create()
@@ -27,6 +52,205 @@ pub fn condvar_create() -> isize {
 pub fn condvar_signal(condvar_id: usize) {
     sys_condvar_signal(condvar_id);
 }
-pub fn condvar_wait(condvar_id: usize, mutex_id: usize) {
-    sys_condvar_wait(condvar_id, mutex_id);
+pub fn condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
+    sys_condvar_wait(condvar_id, mutex_id)
+}
+/// Return -2 if not signaled in `timeout_ms`, the mutex is locked again anyway.
+pub fn condvar_wait_timeout(condvar_id: usize, mutex_id: usize, timeout_ms: usize) -> isize {
+    sys_condvar_wait_timeout(condvar_id, mutex_id, timeout_ms)
+}
+
+pub const FUTEX_WAIT: usize = 0;
+pub const FUTEX_WAKE: usize = 1;
+/// Returned by `futex_wait` if the word is not the value expected.
+pub const EAGAIN: isize = SysError::WouldBlock.code();
+
+/// Sleep if `word` is `val`, until `futex_wake` of it or `timeout_ns`
+/// passes, forever if it is negative. Return 0 if woken up, `EAGAIN` if the
+/// word is not `val`, -2 on timeout, or `EINTR` if a signal comes first.
+pub fn futex_wait(word: &AtomicU32, val: u32, timeout_ns: isize) -> isize {
+    sys_futex(
+        word as *const AtomicU32 as *const u32,
+        FUTEX_WAIT,
+        val as usize,
+        timeout_ns,
+    )
+}
+/// Wake up at most `count` threads sleeping on `word`, return how many.
+pub fn futex_wake(word: &AtomicU32, count: usize) -> isize {
+    sys_futex(
+        word as *const AtomicU32 as *const u32,
+        FUTEX_WAKE,
+        count,
+        -1,
+    )
+}
+
+/// How many times a lock is tried before sleeping on its futex, as the owner
+/// on another hart is likely to release it soon.
+const SPIN_LIMIT: usize = 100;
+
+/// A mutex in the user memory, locked and unlocked without a syscall unless
+/// another thread waits. Unlike the ones of `mutex_create`, there is no
+/// deadlock detection, and it is not unlocked if the owner exits.
+pub struct Mutex {
+    /// 0 if unlocked, 1 if locked, 2 if locked and others may wait
+    state: AtomicU32,
+}
+
+impl Mutex {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU32::new(0),
+        }
+    }
+    pub fn try_lock(&self) -> bool {
+        self.state
+            .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+    pub fn lock(&self) {
+        for _ in 0..SPIN_LIMIT {
+            if self.try_lock() {
+                return;
+            }
+            spin_loop();
+        }
+        // marked as waited for, so the owner wakes up a waiter when unlocking
+        while self.state.swap(2, Ordering::Acquire) != 0 {
+            futex_wait(&self.state, 2, -1);
+        }
+    }
+    pub fn unlock(&self) {
+        if self.state.swap(0, Ordering::Release) == 2 {
+            futex_wake(&self.state, 1);
+        }
+    }
+}
+
+/// A semaphore in the user memory, like `Mutex` it makes a syscall only to
+/// wait and to wake up a waiter.
+pub struct Semaphore {
+    count: AtomicU32,
+    /// the threads which are about to wait or waiting
+    waiters: AtomicU32,
+}
+
+impl Semaphore {
+    pub const fn new(count: u32) -> Self {
+        Self {
+            count: AtomicU32::new(count),
+            waiters: AtomicU32::new(0),
+        }
+    }
+    pub fn try_down(&self) -> bool {
+        let mut count = self.count.load(Ordering::Relaxed);
+        while count > 0 {
+            match self.count.compare_exchange_weak(
+                count,
+                count - 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(current) => count = current,
+            }
+        }
+        false
+    }
+    pub fn down(&self) {
+        for _ in 0..SPIN_LIMIT {
+            if self.try_down() {
+                return;
+            }
+            spin_loop();
+        }
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        while !self.try_down() {
+            futex_wait(&self.count, 0, -1);
+        }
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+    }
+    pub fn up(&self) {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            futex_wake(&self.count, 1);
+        }
+    }
+}
+
+const EMPTY: u32 = 0;
+const NOTIFIED: u32 = 1;
+const PARKED: u32 = u32::MAX;
+
+/// A token a thread waits for in `park`, which others give it by `unpark`
+/// like the parking of threads in std. There is one token at most, so an
+/// `unpark` before the `park` makes it return at once.
+pub struct Parker {
+    /// `EMPTY`, `NOTIFIED` when the token is given, or `PARKED` while the
+    /// thread waits for it
+    state: AtomicU32,
+}
+
+impl Parker {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU32::new(EMPTY),
+        }
+    }
+    /// Wait for the token and take it, only by the thread owning it. It
+    /// may also return for a signal, so the caller checks what it waits for
+    /// again.
+    pub fn park(&self) {
+        // NOTIFIED to EMPTY, or EMPTY to PARKED
+        if self.state.fetch_sub(1, Ordering::Acquire) == NOTIFIED {
+            return;
+        }
+        loop {
+            futex_wait(&self.state, PARKED, -1);
+            if self
+                .state
+                .compare_exchange(NOTIFIED, EMPTY, Ordering::Acquire, Ordering::Acquire)
+                .is_ok()
+            {
+                return;
+            }
+        }
+    }
+    /// Like `park`, but wait at most `timeout_ns`. Return whether the token
+    /// is taken.
+    pub fn park_timeout(&self, timeout_ns: usize) -> bool {
+        if self.state.fetch_sub(1, Ordering::Acquire) == NOTIFIED {
+            return true;
+        }
+        futex_wait(&self.state, PARKED, timeout_ns as isize);
+        self.state.swap(EMPTY, Ordering::Acquire) == NOTIFIED
+    }
+    /// Give the token, waking up the thread if it waits.
+    pub fn unpark(&self) {
+        if self.state.swap(NOTIFIED, Ordering::Release) == PARKED {
+            futex_wake(&self.state, 1);
+        }
+    }
+}
+
+/// The threads `park` and `unpark` know, by tid.
+const MAX_PARKED_THREADS: usize = 64;
+
+const THREAD_PARKER: Parker = Parker::new();
+static THREAD_PARKERS: [Parker; MAX_PARKED_THREADS] = [THREAD_PARKER; MAX_PARKED_THREADS];
+
+/// Wait until the current thread is given its token by `unpark`, see
+/// `Parker::park`. The tid is less than `MAX_PARKED_THREADS`.
+pub fn park() {
+    THREAD_PARKERS[gettid() as usize].park();
+}
+/// Like `park`, but wait at most `timeout_ns`. Return whether the token is
+/// taken.
+pub fn park_timeout(timeout_ns: usize) -> bool {
+    THREAD_PARKERS[gettid() as usize].park_timeout(timeout_ns)
+}
+/// Give the thread `tid` of the process its token.
+pub fn unpark(tid: usize) {
+    THREAD_PARKERS[tid].unpark();
 }
@@ -1,37 +1,113 @@
+use super::{
+    EpollEvent, FdSet, MemUsage, MqAttr, PollFd, Quota, RLimit, SignalAction, SpawnAction,
+    TimeSpec, VmStats,
+};
+
+const SYSCALL_GETCWD: usize = 17;
+const SYSCALL_EPOLL_CREATE: usize = 20;
+const SYSCALL_EPOLL_CTL: usize = 21;
+const SYSCALL_EPOLL_WAIT: usize = 22;
 const SYSCALL_DUP: usize = 24;
+const SYSCALL_FCNTL: usize = 25;
+const SYSCALL_IOCTL: usize = 28;
 const SYSCALL_CONNECT: usize = 29;
 const SYSCALL_LISTEN: usize = 30;
 const SYSCALL_ACCEPT: usize = 31;
+const SYSCALL_FLOCK: usize = 32;
+const SYSCALL_MKDIR: usize = 34;
+const SYSCALL_UNLINKAT: usize = 35;
+const SYSCALL_UMOUNT: usize = 39;
+const SYSCALL_MOUNT: usize = 40;
+const SYSCALL_FTRUNCATE: usize = 46;
+const SYSCALL_CHDIR: usize = 49;
+const SYSCALL_CHROOT: usize = 51;
 const SYSCALL_OPEN: usize = 56;
 const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_PIPE: usize = 59;
+const SYSCALL_QUOTACTL: usize = 60;
+const SYSCALL_GETDENTS64: usize = 61;
+const SYSCALL_LSEEK: usize = 62;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_SENDFILE: usize = 71;
+const SYSCALL_PSELECT: usize = 72;
+const SYSCALL_PPOLL: usize = 73;
+const SYSCALL_SYNC: usize = 81;
+const SYSCALL_FSYNC: usize = 82;
+const SYSCALL_FDATASYNC: usize = 83;
+const SYSCALL_PERSONALITY: usize = 92;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_FUTEX: usize = 98;
 const SYSCALL_SLEEP: usize = 101;
+const SYSCALL_CLOCK_GETTIME: usize = 113;
+const SYSCALL_DMESG: usize = 116;
+const SYSCALL_PTRACE: usize = 117;
 const SYSCALL_YIELD: usize = 124;
 const SYSCALL_KILL: usize = 129;
+const SYSCALL_SIGACTION: usize = 134;
+const SYSCALL_SIGPROCMASK: usize = 135;
+const SYSCALL_SIGRETURN: usize = 139;
+const SYSCALL_SETPRIORITY: usize = 140;
+const SYSCALL_GETPRIORITY: usize = 141;
+const SYSCALL_SETUID: usize = 146;
+const SYSCALL_SETPGID: usize = 154;
+const SYSCALL_GETPGID: usize = 155;
+const SYSCALL_GETSID: usize = 156;
+const SYSCALL_SETSID: usize = 157;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETUID: usize = 174;
+const SYSCALL_MQ_OPEN: usize = 180;
+const SYSCALL_MQ_UNLINK: usize = 181;
+const SYSCALL_MQ_SEND: usize = 182;
+const SYSCALL_MQ_RECEIVE: usize = 183;
+const SYSCALL_MQ_GETSETATTR: usize = 185;
+const SYSCALL_MUNMAP: usize = 215;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
+const SYSCALL_MMAP: usize = 222;
 const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_PRLIMIT: usize = 261;
+const SYSCALL_RENAME: usize = 276;
+const SYSCALL_SPAWN: usize = 400;
+const SYSCALL_PIDFD_OPEN: usize = 434;
+const SYSCALL_CLOSE_RANGE: usize = 436;
+const SYSCALL_ENABLE_DEADLOCK_DETECT: usize = 469;
 const SYSCALL_THREAD_CREATE: usize = 1000;
 const SYSCALL_GETTID: usize = 1001;
 const SYSCALL_WAITTID: usize = 1002;
 const SYSCALL_MUTEX_CREATE: usize = 1010;
 const SYSCALL_MUTEX_LOCK: usize = 1011;
 const SYSCALL_MUTEX_UNLOCK: usize = 1012;
+const SYSCALL_MUTEX_LOCK_TIMEOUT: usize = 1013;
 const SYSCALL_SEMAPHORE_CREATE: usize = 1020;
 const SYSCALL_SEMAPHORE_UP: usize = 1021;
 const SYSCALL_SEMAPHORE_DOWN: usize = 1022;
+const SYSCALL_SEMAPHORE_DOWN_TIMEOUT: usize = 1023;
 const SYSCALL_CONDVAR_CREATE: usize = 1030;
 const SYSCALL_CONDVAR_SIGNAL: usize = 1031;
 const SYSCALL_CONDVAR_WAIT: usize = 1032;
+const SYSCALL_CONDVAR_WAIT_TIMEOUT: usize = 1033;
 const SYSCALL_FRAMEBUFFER: usize = 2000;
 const SYSCALL_FRAMEBUFFER_FLUSH: usize = 2001;
 const SYSCALL_EVENT_GET: usize = 3000;
 const SYSCALL_KEY_PRESSED: usize = 3001;
+const SYSCALL_TCP_CONNECT: usize = 4000;
+const SYSCALL_CGROUP_CREATE: usize = 5000;
+const SYSCALL_CGROUP_ATTACH: usize = 5001;
+const SYSCALL_CGROUP_USAGE: usize = 5002;
+const SYSCALL_CHECKPOINT: usize = 6000;
+const SYSCALL_RESTORE: usize = 6001;
+const SYSCALL_MEMUSAGE: usize = 7000;
+const SYSCALL_VMDUMP: usize = 7001;
+const SYSCALL_KLOG_MAP: usize = 7002;
+const SYSCALL_VM_STATS: usize = 7003;
+const SYSCALL_CPU_UP: usize = 8000;
+const SYSCALL_CPU_DOWN: usize = 8001;
+const SYSCALL_SUSPEND: usize = 8002;
+const SYSCALL_READ_TIMEOUT: usize = 9000;
+const SYSCALL_GET_TIME_NS: usize = 9001;
+const SYSCALL_NANOSLEEP: usize = 9002;
 
 fn syscall(id: usize, args: [usize; 3]) -> isize {
     let mut ret: isize;
@@ -47,10 +123,57 @@ fn syscall(id: usize, args: [usize; 3]) -> isize {
     ret
 }
 
+fn syscall6(id: usize, args: [usize; 6]) -> isize {
+    let mut ret: isize;
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("x10") args[0] => ret,
+            in("x11") args[1],
+            in("x12") args[2],
+            in("x13") args[3],
+            in("x14") args[4],
+            in("x15") args[5],
+            in("x17") id
+        );
+    }
+    ret
+}
+
+pub fn sys_getcwd(buf: &mut [u8]) -> isize {
+    syscall(SYSCALL_GETCWD, [buf.as_mut_ptr() as usize, buf.len(), 0])
+}
+
+pub fn sys_epoll_create(flags: u32) -> isize {
+    syscall(SYSCALL_EPOLL_CREATE, [flags as usize, 0, 0])
+}
+
+pub fn sys_epoll_ctl(epfd: usize, op: usize, fd: usize, event: *const EpollEvent) -> isize {
+    syscall6(SYSCALL_EPOLL_CTL, [epfd, op, fd, event as usize, 0, 0])
+}
+
+pub fn sys_epoll_wait(epfd: usize, events: &mut [EpollEvent], timeout_ms: isize) -> isize {
+    syscall6(
+        SYSCALL_EPOLL_WAIT,
+        [
+            epfd,
+            events.as_mut_ptr() as usize,
+            events.len(),
+            timeout_ms as usize,
+            0,
+            0,
+        ],
+    )
+}
+
 pub fn sys_dup(fd: usize) -> isize {
     syscall(SYSCALL_DUP, [fd, 0, 0])
 }
 
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(SYSCALL_IOCTL, [fd, cmd, arg])
+}
+
 pub fn sys_connect(dest: u32, sport: u16, dport: u16) -> isize {
     syscall(
         SYSCALL_CONNECT,
@@ -63,8 +186,24 @@ pub fn sys_listen(sport: u16) -> isize {
     syscall(SYSCALL_LISTEN, [sport as usize, 0, 0])
 }
 
-pub fn sys_accept(socket_fd: usize) -> isize {
-    syscall(SYSCALL_ACCEPT, [socket_fd, 0, 0])
+pub fn sys_accept(listen_fd: usize) -> isize {
+    syscall(SYSCALL_ACCEPT, [listen_fd, 0, 0])
+}
+
+pub fn sys_ftruncate(fd: usize, len: usize) -> isize {
+    syscall(SYSCALL_FTRUNCATE, [fd, len, 0])
+}
+
+pub fn sys_sync() -> isize {
+    syscall(SYSCALL_SYNC, [0, 0, 0])
+}
+
+pub fn sys_fsync(fd: usize) -> isize {
+    syscall(SYSCALL_FSYNC, [fd, 0, 0])
+}
+
+pub fn sys_fdatasync(fd: usize) -> isize {
+    syscall(SYSCALL_FDATASYNC, [fd, 0, 0])
 }
 
 pub fn sys_open(path: &str, flags: u32) -> isize {
@@ -75,8 +214,27 @@ pub fn sys_close(fd: usize) -> isize {
     syscall(SYSCALL_CLOSE, [fd, 0, 0])
 }
 
-pub fn sys_pipe(pipe: &mut [usize]) -> isize {
-    syscall(SYSCALL_PIPE, [pipe.as_mut_ptr() as usize, 0, 0])
+pub fn sys_pipe(pipe: &mut [usize], flags: u32) -> isize {
+    syscall(
+        SYSCALL_PIPE,
+        [pipe.as_mut_ptr() as usize, flags as usize, 0],
+    )
+}
+
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(SYSCALL_FCNTL, [fd, cmd, arg])
+}
+
+pub fn sys_flock(fd: usize, op: usize) -> isize {
+    syscall(SYSCALL_FLOCK, [fd, op, 0])
+}
+
+pub fn sys_close_range(first: usize, last: usize, flags: u32) -> isize {
+    syscall(SYSCALL_CLOSE_RANGE, [first, last, flags as usize])
+}
+
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    syscall(SYSCALL_LSEEK, [fd, offset as usize, whence])
 }
 
 pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize {
@@ -86,10 +244,68 @@ pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize {
     )
 }
 
+pub fn sys_read_timeout(fd: usize, buffer: &mut [u8], timeout_ms: isize) -> isize {
+    syscall6(
+        SYSCALL_READ_TIMEOUT,
+        [
+            fd,
+            buffer.as_mut_ptr() as usize,
+            buffer.len(),
+            timeout_ms as usize,
+            0,
+            0,
+        ],
+    )
+}
+
 pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
     syscall(SYSCALL_WRITE, [fd, buffer.as_ptr() as usize, buffer.len()])
 }
 
+pub fn sys_sendfile(out_fd: usize, in_fd: usize, offset: *mut usize, count: usize) -> isize {
+    syscall6(
+        SYSCALL_SENDFILE,
+        [out_fd, in_fd, offset as usize, count, 0, 0],
+    )
+}
+
+pub fn sys_pselect(
+    nfds: usize,
+    readfds: *mut FdSet,
+    writefds: *mut FdSet,
+    exceptfds: *mut FdSet,
+    timeout_ms: isize,
+) -> isize {
+    syscall6(
+        SYSCALL_PSELECT,
+        [
+            nfds,
+            readfds as usize,
+            writefds as usize,
+            exceptfds as usize,
+            timeout_ms as usize,
+            0,
+        ],
+    )
+}
+
+pub fn sys_ppoll(fds: &mut [PollFd], timeout_ms: isize) -> isize {
+    syscall(
+        SYSCALL_PPOLL,
+        [fds.as_mut_ptr() as usize, fds.len(), timeout_ms as usize],
+    )
+}
+
+pub fn sys_personality(persona: usize) -> isize {
+    syscall(SYSCALL_PERSONALITY, [persona, 0, 0])
+}
+
+/// Issue a syscall of any number, which is dispatched by the Linux
+/// compatibility layer if the personality is Linux.
+pub fn sys_raw(id: usize, args: [usize; 6]) -> isize {
+    syscall6(id, args)
+}
+
 pub fn sys_exit(exit_code: i32) -> ! {
     syscall(SYSCALL_EXIT, [exit_code as usize, 0, 0]);
     panic!("sys_exit never returns!");
@@ -99,22 +315,141 @@ pub fn sys_sleep(sleep_ms: usize) -> isize {
     syscall(SYSCALL_SLEEP, [sleep_ms, 0, 0])
 }
 
+pub fn sys_dmesg(buf: &mut [u8]) -> isize {
+    syscall(SYSCALL_DMESG, [buf.as_mut_ptr() as usize, buf.len(), 0])
+}
+
+pub fn sys_ptrace(request: usize, pid: usize, addr: usize, data: usize) -> isize {
+    syscall6(SYSCALL_PTRACE, [request, pid, addr, data, 0, 0])
+}
+
 pub fn sys_yield() -> isize {
     syscall(SYSCALL_YIELD, [0, 0, 0])
 }
 
-pub fn sys_kill(pid: usize, signal: i32) -> isize {
+pub fn sys_pidfd_open(pid: usize, flags: u32) -> isize {
+    syscall(SYSCALL_PIDFD_OPEN, [pid, flags as usize, 0])
+}
+
+pub fn sys_kill(pid: usize, signal: u32) -> isize {
     syscall(SYSCALL_KILL, [pid, signal as usize, 0])
 }
 
+pub fn sys_sigaction(
+    signum: usize,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    syscall(
+        SYSCALL_SIGACTION,
+        [signum, action as usize, old_action as usize],
+    )
+}
+
+pub fn sys_sigprocmask(how: usize, mask: u32) -> isize {
+    syscall(SYSCALL_SIGPROCMASK, [how, mask as usize, 0])
+}
+
+pub fn sys_sigreturn() -> isize {
+    syscall(SYSCALL_SIGRETURN, [0, 0, 0])
+}
+
+pub fn sys_setpriority(which: usize, who: usize, nice: isize) -> isize {
+    syscall(SYSCALL_SETPRIORITY, [which, who, nice as usize])
+}
+
+pub fn sys_getpriority(which: usize, who: usize) -> isize {
+    syscall(SYSCALL_GETPRIORITY, [which, who, 0])
+}
+
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    syscall(SYSCALL_SETPGID, [pid, pgid, 0])
+}
+
+pub fn sys_getpgid(pid: usize) -> isize {
+    syscall(SYSCALL_GETPGID, [pid, 0, 0])
+}
+
+pub fn sys_getsid(pid: usize) -> isize {
+    syscall(SYSCALL_GETSID, [pid, 0, 0])
+}
+
+pub fn sys_setsid() -> isize {
+    syscall(SYSCALL_SETSID, [0, 0, 0])
+}
+
 pub fn sys_get_time() -> isize {
     syscall(SYSCALL_GET_TIME, [0, 0, 0])
 }
 
+pub fn sys_get_time_ns() -> isize {
+    syscall(SYSCALL_GET_TIME_NS, [0, 0, 0])
+}
+
+pub fn sys_clock_gettime(clock_id: usize, tp: &mut TimeSpec) -> isize {
+    syscall(
+        SYSCALL_CLOCK_GETTIME,
+        [clock_id, tp as *mut TimeSpec as usize, 0],
+    )
+}
+
+pub fn sys_nanosleep(ns: usize) -> isize {
+    syscall(SYSCALL_NANOSLEEP, [ns, 0, 0])
+}
+
 pub fn sys_getpid() -> isize {
     syscall(SYSCALL_GETPID, [0, 0, 0])
 }
 
+pub fn sys_getuid() -> isize {
+    syscall(SYSCALL_GETUID, [0, 0, 0])
+}
+
+pub fn sys_setuid(uid: usize) -> isize {
+    syscall(SYSCALL_SETUID, [uid, 0, 0])
+}
+
+pub fn sys_mq_open(name: &str, flags: u32, attr: Option<&MqAttr>) -> isize {
+    let attr = attr.map_or(core::ptr::null(), |attr| attr as *const _);
+    syscall(
+        SYSCALL_MQ_OPEN,
+        [name.as_ptr() as usize, flags as usize, attr as usize],
+    )
+}
+
+pub fn sys_mq_unlink(name: &str) -> isize {
+    syscall(SYSCALL_MQ_UNLINK, [name.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_mq_send(mqd: usize, msg: &[u8], prio: u32) -> isize {
+    syscall6(
+        SYSCALL_MQ_SEND,
+        [mqd, msg.as_ptr() as usize, msg.len(), prio as usize, 0, 0],
+    )
+}
+
+pub fn sys_mq_receive(mqd: usize, buf: &mut [u8], prio: &mut u32) -> isize {
+    syscall6(
+        SYSCALL_MQ_RECEIVE,
+        [
+            mqd,
+            buf.as_mut_ptr() as usize,
+            buf.len(),
+            prio as *mut u32 as usize,
+            0,
+            0,
+        ],
+    )
+}
+
+pub fn sys_mq_getsetattr(mqd: usize, new_attr: Option<&MqAttr>, old_attr: &mut MqAttr) -> isize {
+    let new_attr = new_attr.map_or(core::ptr::null(), |attr| attr as *const _);
+    syscall(
+        SYSCALL_MQ_GETSETATTR,
+        [mqd, new_attr as usize, old_attr as *mut _ as usize],
+    )
+}
+
 pub fn sys_fork() -> isize {
     syscall(SYSCALL_FORK, [0, 0, 0])
 }
@@ -126,10 +461,100 @@ pub fn sys_exec(path: &str, args: &[*const u8]) -> isize {
     )
 }
 
+pub fn sys_spawn(
+    path: &str,
+    args: &[*const u8],
+    envp: &[*const u8],
+    fd_actions: &[SpawnAction],
+) -> isize {
+    syscall6(
+        SYSCALL_SPAWN,
+        [
+            path.as_ptr() as usize,
+            args.as_ptr() as usize,
+            envp.as_ptr() as usize,
+            fd_actions.as_ptr() as usize,
+            0,
+            0,
+        ],
+    )
+}
+
 pub fn sys_waitpid(pid: isize, exit_code: *mut i32) -> isize {
     syscall(SYSCALL_WAITPID, [pid as usize, exit_code as usize, 0])
 }
 
+pub fn sys_prlimit(
+    pid: usize,
+    resource: usize,
+    new_limit: *const RLimit,
+    old_limit: *mut RLimit,
+) -> isize {
+    syscall6(
+        SYSCALL_PRLIMIT,
+        [pid, resource, new_limit as usize, old_limit as usize, 0, 0],
+    )
+}
+
+pub fn sys_mkdir(path: &str) -> isize {
+    syscall(SYSCALL_MKDIR, [path.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_unlinkat(path: &str, flags: u32) -> isize {
+    syscall(
+        SYSCALL_UNLINKAT,
+        [path.as_ptr() as usize, flags as usize, 0],
+    )
+}
+
+pub fn sys_getdents64(fd: usize, buf: &mut [u8]) -> isize {
+    syscall(
+        SYSCALL_GETDENTS64,
+        [fd, buf.as_mut_ptr() as usize, buf.len()],
+    )
+}
+
+pub fn sys_umount(target: &str, flags: usize) -> isize {
+    syscall(SYSCALL_UMOUNT, [target.as_ptr() as usize, flags, 0])
+}
+
+pub fn sys_mount(source: &str, target: &str, fstype: &str, flags: usize) -> isize {
+    syscall6(
+        SYSCALL_MOUNT,
+        [
+            source.as_ptr() as usize,
+            target.as_ptr() as usize,
+            fstype.as_ptr() as usize,
+            flags,
+            0,
+            0,
+        ],
+    )
+}
+
+pub fn sys_quotactl(cmd: usize, uid: usize, quota: *mut Quota) -> isize {
+    syscall(SYSCALL_QUOTACTL, [cmd, uid, quota as usize])
+}
+
+pub fn sys_chdir(path: &str) -> isize {
+    syscall(SYSCALL_CHDIR, [path.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_chroot(path: &str) -> isize {
+    syscall(SYSCALL_CHROOT, [path.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_rename(old_path: &str, new_path: &str) -> isize {
+    syscall(
+        SYSCALL_RENAME,
+        [old_path.as_ptr() as usize, new_path.as_ptr() as usize, 0],
+    )
+}
+
+pub fn sys_enable_deadlock_detect(enabled: usize) -> isize {
+    syscall(SYSCALL_ENABLE_DEADLOCK_DETECT, [enabled, 0, 0])
+}
+
 pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
     syscall(SYSCALL_THREAD_CREATE, [entry, arg, 0])
 }
@@ -142,8 +567,18 @@ pub fn sys_waittid(tid: usize) -> isize {
     syscall(SYSCALL_WAITTID, [tid, 0, 0])
 }
 
-pub fn sys_mutex_create(blocking: bool) -> isize {
-    syscall(SYSCALL_MUTEX_CREATE, [blocking as usize, 0, 0])
+pub fn sys_futex(uaddr: *const u32, op: usize, val: usize, timeout_ns: isize) -> isize {
+    syscall6(
+        SYSCALL_FUTEX,
+        [uaddr as usize, op, val, timeout_ns as usize, 0, 0],
+    )
+}
+
+pub fn sys_mutex_create(blocking: bool, errorcheck: bool) -> isize {
+    syscall(
+        SYSCALL_MUTEX_CREATE,
+        [blocking as usize, errorcheck as usize, 0],
+    )
 }
 
 pub fn sys_mutex_lock(id: usize) -> isize {
@@ -154,6 +589,10 @@ pub fn sys_mutex_unlock(id: usize) -> isize {
     syscall(SYSCALL_MUTEX_UNLOCK, [id, 0, 0])
 }
 
+pub fn sys_mutex_lock_timeout(id: usize, timeout_ms: usize) -> isize {
+    syscall(SYSCALL_MUTEX_LOCK_TIMEOUT, [id, timeout_ms, 0])
+}
+
 pub fn sys_semaphore_create(res_count: usize) -> isize {
     syscall(SYSCALL_SEMAPHORE_CREATE, [res_count, 0, 0])
 }
@@ -166,6 +605,10 @@ pub fn sys_semaphore_down(sem_id: usize) -> isize {
     syscall(SYSCALL_SEMAPHORE_DOWN, [sem_id, 0, 0])
 }
 
+pub fn sys_semaphore_down_timeout(sem_id: usize, timeout_ms: usize) -> isize {
+    syscall(SYSCALL_SEMAPHORE_DOWN_TIMEOUT, [sem_id, timeout_ms, 0])
+}
+
 pub fn sys_condvar_create() -> isize {
     syscall(SYSCALL_CONDVAR_CREATE, [0, 0, 0])
 }
@@ -178,6 +621,13 @@ pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
     syscall(SYSCALL_CONDVAR_WAIT, [condvar_id, mutex_id, 0])
 }
 
+pub fn sys_condvar_wait_timeout(condvar_id: usize, mutex_id: usize, timeout_ms: usize) -> isize {
+    syscall(
+        SYSCALL_CONDVAR_WAIT_TIMEOUT,
+        [condvar_id, mutex_id, timeout_ms],
+    )
+}
+
 pub fn sys_framebuffer() -> isize {
     syscall(SYSCALL_FRAMEBUFFER, [0, 0, 0])
 }
@@ -193,3 +643,73 @@ pub fn sys_event_get() -> isize {
 pub fn sys_key_pressed() -> isize {
     syscall(SYSCALL_KEY_PRESSED, [0, 0, 0])
 }
+
+pub fn sys_tcp_connect(dest: u32, sport: u16, dport: u16) -> isize {
+    syscall(
+        SYSCALL_TCP_CONNECT,
+        [dest as usize, sport as usize, dport as usize],
+    )
+}
+
+pub fn sys_cgroup_create(shares: usize, quota_ms: usize) -> isize {
+    syscall(SYSCALL_CGROUP_CREATE, [shares, quota_ms, 0])
+}
+
+pub fn sys_cgroup_attach(pid: usize, group_id: usize) -> isize {
+    syscall(SYSCALL_CGROUP_ATTACH, [pid, group_id, 0])
+}
+
+pub fn sys_cgroup_usage(group_id: usize) -> isize {
+    syscall(SYSCALL_CGROUP_USAGE, [group_id, 0, 0])
+}
+
+pub fn sys_checkpoint(path: &str) -> isize {
+    syscall(SYSCALL_CHECKPOINT, [path.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_restore(path: &str) -> isize {
+    syscall(SYSCALL_RESTORE, [path.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_mmap(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    syscall6(SYSCALL_MMAP, [addr, len, prot, flags, fd, offset])
+}
+
+pub fn sys_munmap(addr: usize, len: usize) -> isize {
+    syscall(SYSCALL_MUNMAP, [addr, len, 0])
+}
+
+pub fn sys_memusage(pid: usize, usage: &mut MemUsage) -> isize {
+    syscall(SYSCALL_MEMUSAGE, [pid, usage as *mut MemUsage as usize, 0])
+}
+
+pub fn sys_vmdump(pid: usize, start: usize, end: usize) -> isize {
+    syscall(SYSCALL_VMDUMP, [pid, start, end])
+}
+
+pub fn sys_klog_map() -> isize {
+    syscall(SYSCALL_KLOG_MAP, [0, 0, 0])
+}
+
+pub fn sys_vm_stats(pid: usize, stats: &mut VmStats) -> isize {
+    syscall(SYSCALL_VM_STATS, [pid, stats as *mut VmStats as usize, 0])
+}
+
+pub fn sys_cpu_up(hart_id: usize) -> isize {
+    syscall(SYSCALL_CPU_UP, [hart_id, 0, 0])
+}
+
+pub fn sys_cpu_down(hart_id: usize) -> isize {
+    syscall(SYSCALL_CPU_DOWN, [hart_id, 0, 0])
+}
+
+pub fn sys_suspend() -> isize {
+    syscall(SYSCALL_SUSPEND, [0, 0, 0])
+}
@@ -1,4 +1,8 @@
 use super::*;
+pub use abi::{
+    SignalAction, SignalActionFlags, SignalFlags, TimeSpec, CLOCK_MONOTONIC, CLOCK_REALTIME,
+    SIG_DFL, SIG_IGN,
+};
 
 pub fn exit(exit_code: i32) -> ! {
     sys_exit(exit_code);
@@ -9,6 +13,14 @@ pub fn yield_() -> isize {
 pub fn get_time() -> isize {
     sys_get_time()
 }
+/// The time since boot in ns, for what `get_time` in ms is too coarse.
+pub fn get_time_ns() -> isize {
+    sys_get_time_ns()
+}
+
+pub fn clock_gettime(clock_id: usize, tp: &mut TimeSpec) -> isize {
+    sys_clock_gettime(clock_id, tp)
+}
 pub fn getpid() -> isize {
     sys_getpid()
 }
@@ -19,6 +31,54 @@ pub fn exec(path: &str, args: &[*const u8]) -> isize {
     sys_exec(path, args)
 }
 
+/// A change to the descriptors of a child created by `spawn`, made before
+/// the ones with `OpenFlags::CLOEXEC` are closed.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SpawnAction {
+    kind: usize,
+    fd: usize,
+    new_fd: usize,
+    path: *const u8,
+    flags: u32,
+}
+
+impl SpawnAction {
+    const END: Self = Self::new(0, 0, 0, core::ptr::null(), 0);
+
+    const fn new(kind: usize, fd: usize, new_fd: usize, path: *const u8, flags: u32) -> Self {
+        Self {
+            kind,
+            fd,
+            new_fd,
+            path,
+            flags,
+        }
+    }
+    pub fn close(fd: usize) -> Self {
+        Self::new(1, fd, 0, core::ptr::null(), 0)
+    }
+    /// Make `new_fd` a duplicate of `fd`, which is kept open by exec even if
+    /// they are the same.
+    pub fn dup2(fd: usize, new_fd: usize) -> Self {
+        Self::new(2, fd, new_fd, core::ptr::null(), 0)
+    }
+    /// Open `path`, which ends with '\0', at `new_fd`.
+    pub fn open(path: &str, flags: OpenFlags, new_fd: usize) -> Self {
+        Self::new(3, 0, new_fd, path.as_ptr(), flags.bits())
+    }
+}
+
+/// Create a child running the program `path` with the descriptors of this
+/// process changed by `actions`, like fork and then exec in the child, but
+/// without copying this process. Return the pid of the child, or -1 if the
+/// program is not found or an action fails.
+pub fn spawn(path: &str, args: &[*const u8], actions: &[SpawnAction]) -> isize {
+    let mut fd_actions: Vec<SpawnAction> = actions.to_vec();
+    fd_actions.push(SpawnAction::END);
+    sys_spawn(path, args, &[core::ptr::null()], fd_actions.as_slice())
+}
+
 pub fn wait(exit_code: &mut i32) -> isize {
     loop {
         match sys_waitpid(-1, exit_code as *mut _) {
@@ -47,22 +107,299 @@ pub fn waitpid_nb(pid: usize, exit_code: &mut i32) -> isize {
     sys_waitpid(pid as isize, exit_code as *mut _)
 }
 
-bitflags! {
-    pub struct SignalFlags: i32 {
-        const SIGINT    = 1 << 2;
-        const SIGILL    = 1 << 4;
-        const SIGABRT   = 1 << 6;
-        const SIGFPE    = 1 << 8;
-        const SIGSEGV   = 1 << 11;
+/// Run the program `args[0]` with the arguments `args`, each ending with
+/// `\0`, in a child and wait for it. Return its exit code, -1 if it cannot
+/// be run.
+pub fn run(args: &[&str]) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        let mut argv: Vec<*const u8> = args.iter().map(|arg| arg.as_ptr()).collect();
+        argv.push(core::ptr::null::<u8>());
+        exec(args[0], argv.as_slice());
+        exit(-1);
     }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
 }
 
-pub fn kill(pid: usize, signal: i32) -> isize {
+/// Returned by a blocking call, like `read` or `sleep`, interrupted by a
+/// signal handler.
+pub const EINTR: isize = SysError::Interrupted.code();
+
+/// Where a handler returns to.
+extern "C" fn sigreturn_trampoline() -> ! {
+    sys_sigreturn();
+    panic!("sigreturn without a signal handled");
+}
+
+/// Set the action of the signal `signum` to `action`, and save the old one
+/// to `old_action`. Either can be None.
+pub fn sigaction(
+    signum: usize,
+    action: Option<&SignalAction>,
+    old_action: Option<&mut SignalAction>,
+) -> isize {
+    let action = action.map(|action| SignalAction {
+        restorer: sigreturn_trampoline as usize,
+        ..*action
+    });
+    sys_sigaction(
+        signum,
+        action.as_ref().map_or(core::ptr::null(), |action| action),
+        old_action.map_or(core::ptr::null_mut(), |old_action| old_action),
+    )
+}
+
+/// The values of `how` of `sigprocmask`.
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+/// Block the signals of `mask`, unblock them, or block exactly them for
+/// `how` of `SIG_BLOCK`, `SIG_UNBLOCK` or `SIG_SETMASK`. The signals blocked
+/// stay pending until unblocked. Return the old mask, -1 on error.
+pub fn sigprocmask(how: usize, mask: SignalFlags) -> isize {
+    sys_sigprocmask(how, mask.bits())
+}
+
+/// A descriptor readable once the process `pid` exits, reading it gives
+/// the exit code as an i32. `flags` may have `OpenFlags::NONBLOCK` and
+/// `OpenFlags::CLOEXEC`.
+pub fn pidfd_open(pid: usize, flags: OpenFlags) -> isize {
+    sys_pidfd_open(pid, flags.bits())
+}
+pub fn kill(pid: usize, signal: u32) -> isize {
     sys_kill(pid, signal)
 }
+/// Move the process `pid` to the group `pgid`, 0 for the current process or
+/// a group of the same id as the process. The group must be of the session.
+pub fn setpgid(pid: usize, pgid: usize) -> isize {
+    sys_setpgid(pid, pgid)
+}
+pub fn getpgid(pid: usize) -> isize {
+    sys_getpgid(pid)
+}
+pub const PRIO_PROCESS: usize = 0;
+pub const PRIO_PGRP: usize = 1;
+pub const NICE_MIN: isize = -20;
+pub const NICE_MAX: isize = 19;
+
+/// Set the nice value of the process or the group `who`, 0 for the current
+/// one, clamped to `NICE_MIN..=NICE_MAX`. The less it is, the sooner they
+/// run.
+pub fn setpriority(which: usize, who: usize, nice: isize) -> isize {
+    sys_setpriority(which, who, nice)
+}
+/// The least nice value of the process or the group `who`, None if there is
+/// no such process.
+pub fn getpriority(which: usize, who: usize) -> Option<isize> {
+    let ret = sys_getpriority(which, who);
+    (ret > 0).then(|| 20 - ret)
+}
+/// Start a new session and group led by the current process, which must not
+/// lead a group. Return the id of the session.
+pub fn setsid() -> isize {
+    sys_setsid()
+}
+pub fn getsid(pid: usize) -> isize {
+    sys_getsid(pid)
+}
+/// The user of the process, 0 for root, inherited by children.
+pub fn getuid() -> isize {
+    sys_getuid()
+}
+/// Only root may change the user, to any one up to 65535.
+pub fn setuid(uid: usize) -> isize {
+    sys_setuid(uid)
+}
+
+/// Syscalls are dispatched as those of Linux with this personality.
+pub const PER_LINUX: usize = 0;
+/// The native personality of rCore.
+pub const PER_RCORE: usize = 0x7263;
+
+/// Switch the syscall ABI of the current process, which is kept across fork
+/// and exec. Return the previous personality.
+pub fn personality(persona: usize) -> isize {
+    sys_personality(persona)
+}
+/// Issue a Linux syscall, it only makes sense with the personality of Linux.
+pub fn linux_syscall(id: usize, args: [usize; 6]) -> isize {
+    sys_raw(id, args)
+}
+
+/// The default shares of a cpu group, which the processes not in any group
+/// have as a whole.
+pub const CGROUP_DEFAULT_SHARES: usize = 1024;
+
+/// Create a cpu group getting the cpu in proportion to `shares` and at most
+/// `quota_ms` every 100ms (0 for unlimited), return the group id.
+pub fn cgroup_create(shares: usize, quota_ms: usize) -> isize {
+    sys_cgroup_create(shares, quota_ms)
+}
+/// Move the process into the group, its children forked later are included.
+pub fn cgroup_attach(pid: usize, group_id: usize) -> isize {
+    sys_cgroup_attach(pid, group_id)
+}
+/// Return the cpu time used by the group in microseconds.
+pub fn cgroup_usage(group_id: usize) -> isize {
+    sys_cgroup_usage(group_id)
+}
+
+/// Save the current process to a file, return 0 after saved, 1 when resumed
+/// by `restore` or -1 on error. Only open files and the console are kept.
+pub fn checkpoint(path: &str) -> isize {
+    sys_checkpoint(path)
+}
+/// Replace the current process with a saved one, only return on error.
+pub fn restore(path: &str) -> isize {
+    sys_restore(path)
+}
+
+/// Memory usage of a process in bytes, same as the kernel.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MemUsage {
+    /// the size of all areas
+    pub virt: usize,
+    /// the size of frames mapped
+    pub resident: usize,
+    /// the size of frames shared with other processes or the page cache
+    pub shared: usize,
+    pub areas: usize,
+}
+
+/// Get the memory usage of a process, or the current one if `pid` is 0. It
+/// is also in `/proc/<pid>/status`.
+pub fn memusage(pid: usize, usage: &mut MemUsage) -> isize {
+    sys_memusage(pid, usage)
+}
+
+/// The page faults resolved for a process since its program started, same
+/// as the kernel.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct VmStats {
+    /// writes to frames shared copy-on-write, after a fork
+    pub cow_faults: usize,
+    /// pages allocated or loaded from a file on the first access
+    pub lazy_faults: usize,
+    /// pages read back from swap, always 0 as there is no swap yet
+    pub swap_ins: usize,
+}
+
+/// Get the page faults resolved for a process, or the current one if `pid`
+/// is 0.
+pub fn vm_stats(pid: usize, stats: &mut VmStats) -> isize {
+    sys_vm_stats(pid, stats)
+}
+
+/// Print the page table mappings of a process, or the current one if `pid`
+/// is 0, within [start, end) on the console. The areas are listed in
+/// `/proc/<pid>/maps`.
+pub fn vmdump(pid: usize, start: usize, end: usize) -> isize {
+    sys_vmdump(pid, start, end)
+}
+
+pub const PROT_READ: usize = 0x1;
+pub const PROT_WRITE: usize = 0x2;
+pub const PROT_EXEC: usize = 0x4;
+/// Only a read-only mapping of a file can be shared, with the page cache.
+pub const MAP_SHARED: usize = 0x1;
+pub const MAP_PRIVATE: usize = 0x2;
+/// Fail rather than map elsewhere if the address is taken.
+pub const MAP_FIXED: usize = 0x10;
+pub const MAP_ANONYMOUS: usize = 0x20;
+pub const MAP_FAILED: isize = -1;
 
-pub fn sleep(sleep_ms: usize) {
-    sys_sleep(sleep_ms);
+/// Map `len` bytes of the file `fd` from `offset` on, or zeros with
+/// `MAP_ANONYMOUS`, at `addr` or anywhere if it is 0 or taken. Return the
+/// address or `MAP_FAILED`.
+pub fn mmap(addr: usize, len: usize, prot: usize, flags: usize, fd: usize, offset: usize) -> isize {
+    sys_mmap(addr, len, prot, flags, fd, offset)
+}
+
+/// Unmap the pages in [addr, addr + len), which may be parts of mappings.
+pub fn munmap(addr: usize, len: usize) -> isize {
+    sys_munmap(addr, len)
+}
+
+/// Start a secondary hart, return -1 if it does not exist or is online.
+pub fn cpu_up(hart_id: usize) -> isize {
+    sys_cpu_up(hart_id)
+}
+
+/// Stop a secondary hart, return -1 if it is not online or the boot hart.
+pub fn cpu_down(hart_id: usize) -> isize {
+    sys_cpu_down(hart_id)
+}
+
+/// Suspend the system to RAM until an interrupt like a key press, return -1
+/// if the SBI does not support it.
+pub fn suspend() -> isize {
+    sys_suspend()
+}
+
+bitflags! {
+    pub struct WatchKind: u32 {
+        const LOAD = 1 << 0;
+        const STORE = 1 << 1;
+        const EXECUTE = 1 << 2;
+    }
+}
+
+/// The watchpoints a process may set.
+pub const MAX_WATCHPOINTS: usize = 4;
+
+/// A hardware watchpoint, same as the kernel. The range is a power of two
+/// up to a page and aligned to it, `len` 0 for none.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Watchpoint {
+    pub addr: usize,
+    pub len: usize,
+    pub kind: u32,
+}
+
+impl Watchpoint {
+    pub fn new(addr: usize, len: usize, kind: WatchKind) -> Self {
+        Self {
+            addr,
+            len,
+            kind: kind.bits(),
+        }
+    }
+}
+
+const PTRACE_GETHBPREGS: usize = 29;
+const PTRACE_SETHBPREGS: usize = 30;
+
+/// Get the watchpoint in `slot` of a process, the current one if `pid` is 0
+/// or else a child.
+pub fn get_watchpoint(pid: usize, slot: usize, watchpoint: &mut Watchpoint) -> isize {
+    sys_ptrace(PTRACE_GETHBPREGS, pid, slot, watchpoint as *mut _ as usize)
+}
+/// Set the watchpoint in `slot` of a process like `get_watchpoint`, which is
+/// killed with SIGTRAP once it accesses the range. Return -1 if the hardware
+/// or the SBI has no triggers.
+pub fn set_watchpoint(pid: usize, slot: usize, watchpoint: &Watchpoint) -> isize {
+    sys_ptrace(
+        PTRACE_SETHBPREGS,
+        pid,
+        slot,
+        watchpoint as *const _ as usize,
+    )
+}
+
+/// Return `EINTR` if a signal handler runs first, 0 otherwise.
+pub fn sleep(sleep_ms: usize) -> isize {
+    sys_sleep(sleep_ms)
+}
+
+/// Like `sleep`, not rounded up to the next time slice.
+pub fn nanosleep(ns: usize) -> isize {
+    sys_nanosleep(ns)
 }
 
 pub fn thread_create(entry: usize, arg: usize) -> isize {
@@ -81,3 +418,23 @@ pub fn waittid(tid: usize) -> isize {
         }
     }
 }
+
+/// The limit of file descriptors of a process, the only resource limited.
+pub const RLIMIT_NOFILE: usize = 7;
+
+/// A resource limit, same as the kernel.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RLimit {
+    /// the soft limit
+    pub cur: usize,
+    /// the hard limit, the soft one can be raised up to it
+    pub max: usize,
+}
+
+pub fn getrlimit(resource: usize, limit: &mut RLimit) -> isize {
+    sys_prlimit(0, resource, core::ptr::null(), limit)
+}
+pub fn setrlimit(resource: usize, limit: &RLimit) -> isize {
+    sys_prlimit(0, resource, limit, core::ptr::null_mut())
+}